@@ -1,5 +1,6 @@
 import SwiftUI
 import Combine
+import Security
 
 /// Main application state
 @MainActor
@@ -21,6 +22,10 @@ class AppState: ObservableObject {
     @Published var isInMenuBarMode: Bool = false  // Whether app is minimized to menu bar
     @Published var joiningRoomCode: String? = nil  // Room code we're trying to join (for retries)
     @Published var syncStatus: SyncStatus? = nil  // Current sync status (listeners only)
+    @Published var peerLatencies: [String: UInt64] = [:]  // One-way latency by peer ID (host only)
+    @Published var hostConnection: ConnectionKind? = nil  // How we reach the host (listeners only)
+    @Published var controlRequest: Participant? = nil  // Listener asking to become host (host only)
+    @Published var coreLogs: [LogLine] = []  // Recent core log lines, for the debug console
 
     // MARK: - Persisted State
 
@@ -55,8 +60,22 @@ class AppState: ObservableObject {
     // MARK: - Initialization
 
     init() {
-        session = Session()
+        var config = defaultSessionConfig()
+        config.appVersion = Bundle.main.infoDictionary?["CFBundleShortVersionString"] as? String
+        #if !DEBUG
+        config.syncStatusLevel = .summary  // The live drift readout is only in the debug view
+        #endif
+        session = Session.newWithConfig(config: config)
         session.setCallback(callback: SessionCallbackImpl(appState: self))
+        session.setStorage(storage: KeychainStorage())
+        setLogCallback(callback: LogCallbackImpl(appState: self), minLevel: .info)
+
+        // Stop the network and the core's threads before quitting (the room is remembered)
+        NotificationCenter.default.addObserver(
+            forName: NSApplication.willTerminateNotification, object: nil, queue: .main
+        ) { [session] _ in
+            session.shutdown()
+        }
 
         // Apply saved token
         if !apiToken.isEmpty {
@@ -78,6 +97,22 @@ class AppState: ObservableObject {
         hasAppeared = true
         Task {
             await checkCiderConnection(showError: false)
+            if ciderConnected {
+                await restoreLastSession()
+            }
+        }
+    }
+
+    /// Get back into the room we were in if the app was closed without leaving it
+    private func restoreLastSession() async {
+        guard let saved = try? await session.restoreLastSessionAsync() else { return }
+        if saved.wasHost {
+            viewState = .inRoom
+            isInRoom = true
+            isHost = true
+        } else {
+            viewState = .joining(.searching)
+            joiningRoomCode = saved.roomCode
         }
     }
 
@@ -137,28 +172,26 @@ class AppState: ObservableObject {
         switch error {
         case .CiderNotReachable:
             return "Cider is not running or not reachable"
-        case .CiderApiError(let msg):
-            return msg
-        case .NetworkError(let msg):
-            return "Network error: \(msg)"
+        case .CiderApiError(.ciderUnauthorized, _, _, _):
+            return "Cider rejected the API token - check it in Settings"
+        case .CiderApiError(_, _, _, let detail):
+            return detail
+        case .NetworkError(_, _, _, let detail):
+            return "Network error: \(detail)"
         default:
             return error.localizedDescription
         }
     }
 
     private func fetchNowPlaying() async -> Bool {
-        // Run blocking FFI call off the main thread (single call fetches both concurrently)
-        let result: Result<CurrentPlayback, CoreError> = await withCheckedContinuation { continuation in
-            DispatchQueue.global(qos: .userInitiated).async { [session] in
-                do {
-                    let playback = try session.getPlaybackState()
-                    continuation.resume(returning: .success(playback))
-                } catch let error as CoreError {
-                    continuation.resume(returning: .failure(error))
-                } catch {
-                    continuation.resume(returning: .failure(.CiderNotReachable))
-                }
-            }
+        // Single call fetches both concurrently, without blocking the main actor
+        let result: Result<CurrentPlayback, CoreError>
+        do {
+            result = .success(try await session.getPlaybackStateAsync())
+        } catch let error as CoreError {
+            result = .failure(error)
+        } catch {
+            result = .failure(.CiderNotReachable)
         }
 
         switch result {
@@ -169,6 +202,14 @@ class AppState: ObservableObject {
             if ciderDisconnected {
                 ciderDisconnected = false
             }
+            if isHost {
+                peerLatencies = Dictionary(
+                    session.getPeerLatencies().map { ($0.peerId, $0.latencyMs) },
+                    uniquingKeysWith: { first, _ in first }
+                )
+            } else {
+                hostConnection = session.getNetworkInfo()?.hostConnection
+            }
             // Note: Host broadcast is handled by the Rust core's broadcast loop
             return true
         case .failure:
@@ -226,21 +267,12 @@ class AppState: ObservableObject {
         let name = displayName
 
         Task {
-            let result: Result<String, Error> = await Task.detached { [session] in
-                do {
-                    let code = try session.createRoom(displayName: name)
-                    return .success(code)
-                } catch {
-                    return .failure(error)
-                }
-            }.value
-
-            switch result {
-            case .success:
+            do {
+                _ = try await session.createRoomAsync(displayName: name)
                 viewState = .inRoom
                 isInRoom = true
                 isHost = true
-            case .failure(let error):
+            } catch {
                 errorMessage = "Failed to create room: \(error.localizedDescription)"
                 viewState = .home
             }
@@ -253,16 +285,9 @@ class AppState: ObservableObject {
         let name = displayName
 
         Task {
-            let error: Error? = await Task.detached { [session] in
-                do {
-                    try session.joinRoom(roomCode: code, displayName: name)
-                    return nil
-                } catch {
-                    return error
-                }
-            }.value
-
-            if let error {
+            do {
+                try await session.joinRoomAsync(roomCode: code, displayName: name)
+            } catch {
                 errorMessage = "Failed to join room: \(error.localizedDescription)"
                 viewState = .home
                 joiningRoomCode = nil
@@ -330,29 +355,84 @@ class AppState: ObservableObject {
         }
     }
 
+    /// Ask the host to hand over control (listeners only)
+    func requestHostControl() {
+        Task.detached { [session] in
+            try? session.requestHostControl()
+        }
+    }
+
+    /// Remove a listener from the room (host only); banned ones can't come back
+    func removeParticipant(_ peerId: String, ban: Bool = false) {
+        Task {
+            let error: Error? = await Task.detached { [session] in
+                do {
+                    if ban {
+                        try session.banParticipant(peerId: peerId)
+                    } else {
+                        try session.kickParticipant(peerId: peerId)
+                    }
+                    return nil
+                } catch {
+                    return error
+                }
+            }.value
+
+            if let error {
+                errorMessage = "Failed to remove participant: \(error.localizedDescription)"
+            }
+        }
+    }
+
+    /// Lock or unlock the room to newcomers (host only)
+    func setRoomLocked(_ locked: Bool) {
+        Task.detached { [session] in
+            try? session.setRoomLocked(locked: locked)
+        }
+    }
+
+    /// Warn the host when some listeners' accounts can't play the track (e.g. explicit content restricted)
+    func warnAboutListenersUnableToPlay(_ track: TrackInfo) {
+        Task {
+            let listeners = await Task.detached { [session] in
+                session.getListenersUnableToPlay(track: track)
+            }.value
+            guard !listeners.isEmpty else { return }
+            let names = listeners.map(\.displayName).joined(separator: ", ")
+            errorMessage = "\"\(track.name)\" is explicit - \(names) won't hear it with explicit content restricted"
+        }
+    }
+
     // MARK: - Playback Controls
 
     func play() {
-        Task.detached { [session] in
-            try? session.syncPlay()
+        Task { [session] in
+            try? await session.syncPlayAsync()
         }
     }
 
     func pause() {
-        Task.detached { [session] in
-            try? session.syncPause()
+        Task { [session] in
+            try? await session.syncPauseAsync()
         }
     }
 
     func next() {
-        Task.detached { [session] in
-            try? session.syncNext()
+        Task { [session] in
+            try? await session.syncNextAsync()
         }
     }
 
     func previous() {
-        Task.detached { [session] in
-            try? session.syncPrevious()
+        Task { [session] in
+            try? await session.syncPreviousAsync()
+        }
+    }
+
+    /// Snap back in sync with the host (listeners only)
+    func resync() {
+        Task { [session] in
+            try? await session.forceResyncAsync()
         }
     }
 
@@ -399,6 +479,67 @@ class AppState: ObservableObject {
 
 }
 
+// MARK: - Secure Storage Implementation
+
+/// Core state kept across launches (identity, calibration, last room), in the Keychain
+final class KeychainStorage: SecureStorage, @unchecked Sendable {
+    private let service = "CiderTogether.core"
+
+    func get(key: String) -> String? {
+        var query = baseQuery(key)
+        query[kSecReturnData as String] = true
+        query[kSecMatchLimit as String] = kSecMatchLimitOne
+        var result: AnyObject?
+        guard SecItemCopyMatching(query as CFDictionary, &result) == errSecSuccess,
+              let data = result as? Data else { return nil }
+        return String(data: data, encoding: .utf8)
+    }
+
+    func set(key: String, value: String) {
+        let data = Data(value.utf8)
+        let status = SecItemUpdate(baseQuery(key) as CFDictionary, [kSecValueData as String: data] as CFDictionary)
+        if status == errSecItemNotFound {
+            var item = baseQuery(key)
+            item[kSecValueData as String] = data
+            item[kSecAttrAccessible as String] = kSecAttrAccessibleAfterFirstUnlock
+            SecItemAdd(item as CFDictionary, nil)
+        }
+    }
+
+    func delete(key: String) {
+        SecItemDelete(baseQuery(key) as CFDictionary)
+    }
+
+    private func baseQuery(_ key: String) -> [String: Any] {
+        [
+            kSecClass as String: kSecClassGenericPassword,
+            kSecAttrService as String: service,
+            kSecAttrAccount as String: key,
+        ]
+    }
+}
+
+// MARK: - Log Callback Implementation
+
+final class LogCallbackImpl: LogCallback, @unchecked Sendable {
+    private weak var appState: AppState?
+    private let maxLines = 500
+
+    init(appState: AppState) {
+        self.appState = appState
+    }
+
+    func onLog(line: LogLine) {
+        DispatchQueue.main.async { [weak self] in
+            guard let self, let appState = self.appState else { return }
+            appState.coreLogs.append(line)
+            if appState.coreLogs.count > self.maxLines {
+                appState.coreLogs.removeFirst(appState.coreLogs.count - self.maxLines)
+            }
+        }
+    }
+}
+
 // MARK: - Session Callback Implementation
 
 final class SessionCallbackImpl: SessionCallback, @unchecked Sendable {
@@ -430,6 +571,9 @@ final class SessionCallbackImpl: SessionCallback, @unchecked Sendable {
             if appState.roomState != nil {
                 appState.roomState?.currentTrack = track
             }
+            if appState.isHost, let track, track.explicit {
+                appState.warnAboutListenersUnableToPlay(track)
+            }
         }
     }
 
@@ -464,19 +608,18 @@ final class SessionCallbackImpl: SessionCallback, @unchecked Sendable {
         }
     }
 
-    func onError(message: String) {
+    func onError(code: ErrorCode, messageKey: String, message: String) {
         DispatchQueue.main.async { [weak self] in
             guard let appState = self?.appState else { return }
 
-            // If we were joining and got an error, show timeout state
-            if case .joining = appState.viewState {
-                if message.contains("not found") {
-                    appState.viewState = .joining(.timeout)
-                    return  // Don't show alert, the UI will display the timeout view
-                }
+            // If we were joining and the room wasn't found, show timeout state
+            if case .joining = appState.viewState, code == .roomNotFound {
+                appState.viewState = .joining(.timeout)
+                return  // Don't show alert, the UI will display the timeout view
             }
 
-            appState.errorMessage = message
+            // Falls back to the core's English text until the key is translated
+            appState.errorMessage = NSLocalizedString(messageKey, value: message, comment: "Core error")
         }
     }
 
@@ -510,4 +653,30 @@ final class SessionCallbackImpl: SessionCallback, @unchecked Sendable {
             appState.syncStatus = status
         }
     }
+
+    func onTrackUnavailable(track: TrackInfo) {
+        DispatchQueue.main.async { [weak self] in
+            guard let appState = self?.appState else { return }
+            appState.errorMessage = "\"\(track.name)\" by \(track.artist) isn't available in your Apple Music region"
+        }
+    }
+
+    func onListenerTrackUnavailable(participant: Participant, track: TrackInfo) {
+        DispatchQueue.main.async { [weak self] in
+            guard let appState = self?.appState else { return }
+            let region = participant.storefront.map { " (\($0.uppercased()))" } ?? ""
+            appState.errorMessage = "\(participant.displayName)\(region) can't play \"\(track.name)\" - it may not be available in their region"
+        }
+    }
+
+    func onControlRequested(participant: Participant) {
+        DispatchQueue.main.async { [weak self] in
+            guard let appState = self?.appState else { return }
+            appState.controlRequest = participant
+        }
+    }
+
+    func onFriendStartedRoom(friend: Friend, roomCode: String) {
+        // Friends aren't shown in the app yet
+    }
 }
@@ -36,6 +36,20 @@ struct ContentView: View {
         } message: {
             Text(appState.errorMessage ?? "")
         }
+        .alert(
+            "\(appState.controlRequest?.displayName ?? "A listener") wants to take over",
+            isPresented: .constant(appState.controlRequest != nil)
+        ) {
+            Button("Make Host") {
+                if let peerId = appState.controlRequest?.peerId {
+                    appState.transferHost(to: peerId)
+                }
+                appState.controlRequest = nil
+            }
+            Button("Not Now", role: .cancel) {
+                appState.controlRequest = nil
+            }
+        }
         .task {
             appState.onAppear()
         }
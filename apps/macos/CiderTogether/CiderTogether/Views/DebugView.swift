@@ -46,6 +46,32 @@ struct DebugView: View {
                                         .font(.system(.body, design: .monospaced))
                                 }
 
+                                HStack {
+                                    Text("Jitter")
+                                        .foregroundColor(.secondary)
+                                    Spacer()
+                                    Text("±\(status.jitterMs)ms")
+                                        .font(.system(.body, design: .monospaced))
+                                }
+
+                                HStack {
+                                    Text("Drift Rate")
+                                        .foregroundColor(.secondary)
+                                    Spacer()
+                                    Text(String(format: "%+.1fms/s", status.estimate.rateMsPerSec))
+                                        .font(.system(.body, design: .monospaced))
+                                }
+
+                                if let exceedsIn = status.estimate.exceedsThresholdInMs {
+                                    HStack {
+                                        Text("Next Correction")
+                                            .foregroundColor(.secondary)
+                                        Spacer()
+                                        Text(String(format: "in %.1fs", Double(exceedsIn) / 1000))
+                                            .font(.system(.body, design: .monospaced))
+                                    }
+                                }
+
                                 HStack {
                                     Text("Seek Offset")
                                         .foregroundColor(.secondary)
@@ -15,12 +15,36 @@ struct RoomView: View {
                 RoomCodeView(code: roomState.roomCode)
             }
 
+            // Connection to the host (listeners only)
+            if !appState.isHost, let kind = appState.hostConnection {
+                Label(
+                    kind == .direct ? "Direct connection" : "Relayed connection",
+                    systemImage: kind == .direct ? "bolt.horizontal.fill" : "arrow.triangle.branch"
+                )
+                .font(.caption2)
+                .foregroundColor(kind == .direct ? .secondary : .orange)
+                .help(kind == .direct ? "Connected straight to the host" : "Traffic goes through a relay server, which adds latency")
+            }
+
             // Now Playing
             NowPlayingCard()
 
             // Playback Controls (host only)
             if appState.isHost {
                 PlaybackControlsView()
+            } else if appState.roomState != nil {
+                HStack(spacing: 12) {
+                    Button(action: { appState.resync() }) {
+                        Label("Resync", systemImage: "arrow.triangle.2.circlepath")
+                    }
+                    .help("Jump back in sync with the host")
+
+                    Button(action: { appState.requestHostControl() }) {
+                        Label("Request Control", systemImage: "hand.raised")
+                    }
+                    .help("Ask the host to let you take over playback")
+                }
+                .font(.caption)
             }
 
             // Participants
@@ -156,6 +180,7 @@ struct PlaybackControlsView: View {
 }
 
 struct ParticipantsView: View {
+    @EnvironmentObject var appState: AppState
     let participants: [Participant]
 
     var body: some View {
@@ -167,7 +192,13 @@ struct ParticipantsView: View {
             ScrollView(.horizontal, showsIndicators: false) {
                 HStack(spacing: 8) {
                     ForEach(participants, id: \.peerId) { participant in
-                        ParticipantBadge(participant: participant)
+                        ParticipantBadge(participant: participant, latencyMs: appState.peerLatencies[participant.peerId])
+                            .contextMenu {
+                                if appState.isHost && !participant.isHost {
+                                    Button("Remove from Room") { appState.removeParticipant(participant.peerId) }
+                                    Button("Ban from Room") { appState.removeParticipant(participant.peerId, ban: true) }
+                                }
+                            }
                     }
                 }
             }
@@ -177,6 +208,7 @@ struct ParticipantsView: View {
 
 struct ParticipantBadge: View {
     let participant: Participant
+    var latencyMs: UInt64? = nil
 
     var body: some View {
         HStack(spacing: 5) {
@@ -202,6 +234,13 @@ struct ParticipantBadge: View {
                     .font(.system(size: 8))
                     .foregroundColor(.orange)
             }
+
+            // Latency (shown to the host)
+            if let latencyMs {
+                Text("\(latencyMs) ms")
+                    .font(.caption2)
+                    .foregroundColor(latencyMs > 150 ? .orange : .secondary)
+            }
         }
         .padding(.horizontal, 8)
         .padding(.vertical, 5)
@@ -209,6 +248,10 @@ struct ParticipantBadge: View {
     }
 
     private var initials: String {
+        // An emoji avatar stands in for the initials
+        if let avatar = participant.avatar, avatar.count == 1 {
+            return avatar
+        }
         let words = participant.displayName.split(separator: " ")
         if words.count >= 2 {
             return "\(words[0].prefix(1))\(words[1].prefix(1))".uppercased()
@@ -217,6 +260,14 @@ struct ParticipantBadge: View {
     }
 
     private var avatarColor: Color {
+        if let hex = participant.color, let rgb = UInt32(hex.dropFirst(), radix: 16) {
+            return Color(
+                red: Double((rgb >> 16) & 0xFF) / 255,
+                green: Double((rgb >> 8) & 0xFF) / 255,
+                blue: Double(rgb & 0xFF) / 255
+            )
+        }
+
         // Generate consistent color from name
         let hash = participant.displayName.hashValue
         let hue = Double(abs(hash) % 360) / 360.0
@@ -55,6 +55,7 @@ public partial class AppState : ObservableObject
     [ObservableProperty] private bool _isInRoom;
     [ObservableProperty] private string? _joiningRoomCode;
     [ObservableProperty] private SyncStatus? _syncStatus;
+    [ObservableProperty] private Participant? _controlRequest; // Listener asking to become host (host only)
 
     // Persisted settings
     public string DisplayName
@@ -154,14 +155,16 @@ public partial class AppState : ObservableObject
         {
             CiderConnected = false;
             if (showError)
-                ConnectionError = ex.v1;
+                ConnectionError = ex.code == ErrorCode.CiderUnauthorized
+                    ? "Cider rejected the API token - check it in Settings"
+                    : ex.detail;
             StopPolling();
         }
         catch (CoreException.NetworkException ex)
         {
             CiderConnected = false;
             if (showError)
-                ConnectionError = $"Network error: {ex.v1}";
+                ConnectionError = $"Network error: {ex.detail}";
             StopPolling();
         }
         catch (Exception ex)
@@ -471,9 +474,9 @@ public partial class AppState : ObservableObject
         JoiningRoomCode = null;
     }
 
-    internal void HandleError(string message)
+    internal void HandleError(ErrorCode code, string message)
     {
-        if (ViewState == ViewState.Joining && message.Contains("not found"))
+        if (ViewState == ViewState.Joining && code == ErrorCode.RoomNotFound)
         {
             JoiningProgress = JoiningProgress.Timeout;
             return;
@@ -505,4 +508,20 @@ public partial class AppState : ObservableObject
     {
         SyncStatus = status;
     }
+
+    internal void HandleTrackUnavailable(TrackInfo track)
+    {
+        ErrorMessage = $"\"{track.name}\" by {track.artist} isn't available in your Apple Music region";
+    }
+
+    internal void HandleListenerTrackUnavailable(Participant participant, TrackInfo track)
+    {
+        var region = participant.storefront != null ? $" ({participant.storefront.ToUpperInvariant()})" : "";
+        ErrorMessage = $"{participant.displayName}{region} can't play \"{track.name}\" - it may not be available in their region";
+    }
+
+    internal void HandleControlRequested(Participant participant)
+    {
+        ControlRequest = participant;
+    }
 }
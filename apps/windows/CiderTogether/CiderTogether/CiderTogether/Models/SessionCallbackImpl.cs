@@ -72,13 +72,13 @@ internal class SessionCallbackImpl : SessionCallback
         });
     }
 
-    public void OnError(string message)
+    public void OnError(ErrorCode code, string messageKey, string message)
     {
         _dispatcher.TryEnqueue(() =>
         {
             if (_appStateRef.TryGetTarget(out var appState))
             {
-                appState.HandleError(message);
+                appState.HandleError(code, message);
             }
         });
     }
@@ -115,4 +115,42 @@ internal class SessionCallbackImpl : SessionCallback
             }
         });
     }
+
+    public void OnTrackUnavailable(TrackInfo track)
+    {
+        _dispatcher.TryEnqueue(() =>
+        {
+            if (_appStateRef.TryGetTarget(out var appState))
+            {
+                appState.HandleTrackUnavailable(track);
+            }
+        });
+    }
+
+    public void OnListenerTrackUnavailable(Participant participant, TrackInfo track)
+    {
+        _dispatcher.TryEnqueue(() =>
+        {
+            if (_appStateRef.TryGetTarget(out var appState))
+            {
+                appState.HandleListenerTrackUnavailable(participant, track);
+            }
+        });
+    }
+
+    public void OnControlRequested(Participant participant)
+    {
+        _dispatcher.TryEnqueue(() =>
+        {
+            if (_appStateRef.TryGetTarget(out var appState))
+            {
+                appState.HandleControlRequested(participant);
+            }
+        });
+    }
+
+    public void OnFriendStartedRoom(Friend friend, string roomCode)
+    {
+        // Friends aren't shown in the app yet
+    }
 }
@@ -0,0 +1,189 @@
+//! Sync handler throughput
+//!
+//! How fast a peer gets through the messages it handles most: heartbeats on
+//! a listener (each one checks its drift against Cider), the room state on a
+//! listener, and join requests on the host. Cider is the in-memory mock and
+//! there's no network, so this is the handlers' own cost (the Cider calls
+//! they hand to the player included).
+//!
+//!   cargo bench -p cider-core --bench handlers --features bench-internals
+
+use std::sync::{Arc, RwLock};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tokio::runtime::Runtime;
+
+use cider_core::cider::mock::MockCider;
+use cider_core::ffi::bench::{handle_network_event, room_state_message, Player, RoomBroadcaster, SyncSettings};
+use cider_core::ffi::{current_time_ms, SessionCallback, SyncStatusLevel};
+use cider_core::latency::{self, SharedLatencyTracker};
+use cider_core::network::{NetworkEvent, NetworkHandle};
+use cider_core::seek_calibrator::{self, SharedSeekCalibrator};
+use cider_core::sync::{
+    Capabilities, ClientInfo, Participant, ParticipantStatus, PlaybackInfo, Profile, Room, RoomState, SyncMessage,
+};
+
+/// Room sizes for the room state benchmark
+const ROOM_SIZES: [usize; 3] = [8, 32, 128];
+
+/// A peer's handler state, without a network
+struct Peer {
+    room: Arc<RwLock<Room>>,
+    callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    cider: Arc<RwLock<MockCider>>,
+    network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+    latency_tracker: SharedLatencyTracker,
+    seek_calibrator: SharedSeekCalibrator,
+    local_peer_id: String,
+    settings: Arc<SyncSettings>,
+    player: Player,
+    room_broadcaster: RoomBroadcaster,
+}
+
+impl Peer {
+    /// Needs a runtime entered, for the player and room broadcaster
+    fn new(local_peer_id: &str, room: RoomState, cider: MockCider) -> Self {
+        let room = Arc::new(RwLock::new(Room::Active(room)));
+        let network_handle = Arc::new(RwLock::new(None));
+        Self {
+            room_broadcaster: RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle)),
+            room,
+            callback: Arc::new(RwLock::new(None)),
+            cider: Arc::new(RwLock::new(cider)),
+            network_handle,
+            latency_tracker: latency::new_shared_tracker(),
+            seek_calibrator: seek_calibrator::new_shared_calibrator(),
+            local_peer_id: local_peer_id.to_string(),
+            settings: Arc::new(SyncSettings::new(500, SyncStatusLevel::Off)),
+            player: Player::start(),
+        }
+    }
+
+    /// The host of a room with `participants` in it, host included
+    fn host(participants: usize) -> Self {
+        let mut state = RoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "host".to_string(),
+            "Host".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        for i in 1..participants {
+            state.add_participant(participant(i));
+        }
+        Self::new("host", state, MockCider::new())
+    }
+
+    /// A listener in the host's room, playing along
+    fn listener() -> Self {
+        let mut state = RoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "listener-1".to_string(),
+            "Listener 1".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.host_peer_id = "host".to_string();
+        let cider = MockCider::new().with_playing(MockCider::track("1", 240_000), 60_000);
+        Self::new("listener-1", state, cider)
+    }
+
+    async fn handle(&self, from: &str, message: SyncMessage) {
+        let event = NetworkEvent::Message {
+            from: from.to_string(),
+            message,
+        };
+        handle_network_event(
+            event,
+            &self.room,
+            &self.callback,
+            &self.cider,
+            &self.network_handle,
+            &self.latency_tracker,
+            &self.seek_calibrator,
+            &self.local_peer_id,
+            &self.settings,
+            &self.player,
+            &self.room_broadcaster,
+        )
+        .await;
+        self.player.flush().await;
+    }
+}
+
+fn participant(i: usize) -> Participant {
+    Participant {
+        peer_id: format!("listener-{}", i),
+        display_name: format!("Listener {}", i),
+        is_host: false,
+        storefront: Some("us".to_string()),
+        capabilities: Capabilities::default(),
+        profile: Profile::default(),
+        client: ClientInfo::default(),
+        status: ParticipantStatus::default(),
+    }
+}
+
+fn heartbeat(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let listener = Peer::listener();
+    c.bench_function("handlers/heartbeat", |b| {
+        b.to_async(&runtime).iter(|| async {
+            // In sync with the host, so it only measures the drift (and room hash)
+            let cider = listener.cider.read().unwrap().clone();
+            let room_hash = listener.room.read().unwrap().state().map(RoomState::room_hash);
+            let message = SyncMessage::Heartbeat {
+                track_id: Some("1".to_string()),
+                playback: PlaybackInfo {
+                    is_playing: true,
+                    position_ms: cider.position_ms(),
+                    timestamp_ms: current_time_ms(),
+                },
+                room_hash,
+            };
+            listener.handle("host", message).await
+        })
+    });
+}
+
+fn room_state(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let mut group = c.benchmark_group("handlers/room_state");
+    for participants in ROOM_SIZES {
+        let listener = Peer::listener();
+        let message = match &*Peer::host(participants).room.read().unwrap() {
+            Room::Active(state) => room_state_message(state, 0),
+            _ => unreachable!(),
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(participants), &message, |b, message| {
+            b.to_async(&runtime).iter(|| listener.handle("host", message.clone()))
+        });
+    }
+    group.finish();
+}
+
+fn join_request(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let _runtime = runtime.enter();
+    let host = Peer::host(8);
+    let message = SyncMessage::JoinRequest {
+        display_name: "Listener 1".to_string(),
+        storefront: Some("us".to_string()),
+        capabilities: Capabilities::default(),
+        profile: Profile::default(),
+        client: ClientInfo::default(),
+        status: ParticipantStatus::default(),
+    };
+    c.bench_function("handlers/join_request", |b| {
+        b.to_async(&runtime).iter(|| host.handle("listener-1", message.clone()))
+    });
+}
+
+criterion_group!(benches, heartbeat, room_state, join_request);
+criterion_main!(benches);
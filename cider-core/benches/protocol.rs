@@ -0,0 +1,151 @@
+//! Wire format benchmarks
+//!
+//! Encoding and decoding `SyncMessage`s as JSON, which peers send today, and
+//! as MessagePack, the binary format a protocol change would move to, plus
+//! how the room state sent to joiners grows with the room.
+//!
+//!   cargo bench -p cider-core --bench protocol
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use cider_core::sync::{
+    Capabilities, ClientInfo, Moderation, Participant, ParticipantStatus, PlaybackInfo, Profile, RoomHash, RoomSettings,
+    SyncMessage, TrackInfo, ROOM_HASH_VERSION,
+};
+
+/// Room sizes for the room state benchmarks
+const ROOM_SIZES: [usize; 4] = [1, 8, 32, 128];
+
+fn track() -> TrackInfo {
+    TrackInfo {
+        song_id: "1440857781".to_string(),
+        name: "Midnight City".to_string(),
+        artist: "M83".to_string(),
+        album: "Hurry Up, We're Dreaming".to_string(),
+        artwork_url: "https://is1-ssl.mzstatic.com/image/thumb/Music125/v4/ab/cd/ef/abcdef/source/600x600bb.jpg"
+            .to_string(),
+        duration_ms: 243_960,
+        isrc: Some("FR6V81141061".to_string()),
+        explicit: false,
+    }
+}
+
+fn playback() -> PlaybackInfo {
+    PlaybackInfo {
+        is_playing: true,
+        position_ms: 92_417,
+        timestamp_ms: 1_700_000_000_000,
+    }
+}
+
+/// A peer ID as long as libp2p's
+fn peer_id(i: usize) -> String {
+    format!("12D3KooW{:044}", i)
+}
+
+fn room_state(participants: usize) -> SyncMessage {
+    SyncMessage::RoomState {
+        room_code: "ABCD4679".to_string(),
+        host_peer_id: peer_id(0),
+        participants: (0..participants)
+            .map(|i| Participant {
+                peer_id: peer_id(i),
+                display_name: format!("Listener {}", i),
+                is_host: i == 0,
+                storefront: Some("us".to_string()),
+                capabilities: Capabilities::default(),
+                profile: Profile::default(),
+                client: ClientInfo::default(),
+                status: ParticipantStatus::default(),
+            })
+            .collect(),
+        current_track: Some(track()),
+        playback: playback(),
+        moderation: Moderation::default(),
+        settings: RoomSettings::default(),
+    }
+}
+
+/// The messages peers send most, and the largest
+fn messages() -> Vec<(&'static str, SyncMessage)> {
+    vec![
+        (
+            "heartbeat",
+            SyncMessage::Heartbeat {
+                track_id: Some(track().song_id),
+                playback: playback(),
+                room_hash: Some(RoomHash { version: ROOM_HASH_VERSION, hash: 0x9e37_79b9_7f4a_7c15 }),
+            },
+        ),
+        (
+            "track_change",
+            SyncMessage::TrackChange {
+                track: track(),
+                position_ms: 0,
+                timestamp_ms: 1_700_000_000_000,
+            },
+        ),
+        ("room_state", room_state(8)),
+    ]
+}
+
+fn to_msgpack(message: &SyncMessage) -> Vec<u8> {
+    // Field names kept, so peers can still add fields older ones default
+    rmp_serde::to_vec_named(message).unwrap()
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for (name, message) in messages() {
+        group.bench_with_input(BenchmarkId::new("json", name), &message, |b, m| {
+            b.iter(|| serde_json::to_vec(m).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("msgpack", name), &message, |b, m| b.iter(|| to_msgpack(m)));
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, message) in messages() {
+        let json = serde_json::to_vec(&message).unwrap();
+        let msgpack = to_msgpack(&message);
+        group.bench_with_input(BenchmarkId::new("json", name), &json, |b, data| {
+            b.iter(|| serde_json::from_slice::<SyncMessage>(data).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("msgpack", name), &msgpack, |b, data| {
+            b.iter(|| rmp_serde::from_slice::<SyncMessage>(data).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Room state encoding by participant count; the sizes are printed, the
+/// throughput is per byte
+fn room_state_size(c: &mut Criterion) {
+    let mut group = c.benchmark_group("room_state");
+    for participants in ROOM_SIZES {
+        let message = room_state(participants);
+        let json = serde_json::to_vec(&message).unwrap();
+        let msgpack = to_msgpack(&message);
+        println!(
+            "room_state with {} participants: {} bytes as JSON, {} as MessagePack",
+            participants,
+            json.len(),
+            msgpack.len()
+        );
+
+        group.throughput(Throughput::Bytes(json.len() as u64));
+        group.bench_with_input(BenchmarkId::new("json", participants), &message, |b, m| {
+            b.iter(|| serde_json::to_vec(m).unwrap())
+        });
+        group.throughput(Throughput::Bytes(msgpack.len() as u64));
+        group.bench_with_input(BenchmarkId::new("msgpack", participants), &message, |b, m| {
+            b.iter(|| to_msgpack(m))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode, room_state_size);
+criterion_main!(benches);
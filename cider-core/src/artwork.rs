@@ -0,0 +1,292 @@
+//! Artwork downloads with a shared cache
+//!
+//! Track artwork comes as Apple Music CDN URLs, either templated
+//! (`.../{w}x{h}bb.jpg`) or already sized (`.../600x600bb.jpg`). The apps show
+//! the same few images over and over, so each size is downloaded once and
+//! then served from memory or from disk.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::debug;
+
+/// Number of images kept in memory
+const MEMORY_CACHE_ENTRIES: usize = 32;
+
+/// Timeout for a single download
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Cache directory (under the system temp dir) when none is set
+const DEFAULT_CACHE_DIR: &str = "cider-together-artwork";
+
+/// Errors from fetching artwork
+#[derive(Debug, Error)]
+pub enum ArtworkError {
+    #[error("Artwork download failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Artwork cache error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Not an artwork URL: {0:?}")]
+    InvalidUrl(String),
+}
+
+/// Artwork URL at `size`x`size` pixels
+///
+/// Fills in templated URLs and rewrites the size of sized ones; other URLs are
+/// returned as they are.
+pub fn sized_url(url: &str, size: u32) -> Result<String, ArtworkError> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(ArtworkError::InvalidUrl(url.to_string()));
+    }
+
+    let size = size.to_string();
+    if url.contains("{w}") {
+        return Ok(url.replace("{w}", &size).replace("{h}", &size).replace("{f}", "jpg"));
+    }
+
+    // Sized URLs end in `<w>x<h>...` (e.g. `600x600bb.jpg`)
+    let (base, file) = url.rsplit_once('/').expect("URL has a scheme");
+    let width_len = file.find(|c: char| !c.is_ascii_digit()).unwrap_or(file.len());
+    let Some(rest) = file[width_len..].strip_prefix('x').filter(|_| width_len > 0) else {
+        return Ok(url.to_string());
+    };
+    let height_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    if height_len == 0 {
+        return Ok(url.to_string());
+    }
+    Ok(format!("{}/{}x{}{}", base, size, size, &rest[height_len..]))
+}
+
+/// Most recently used images, by sized URL
+#[derive(Default)]
+struct MemoryCache {
+    images: HashMap<String, Arc<Vec<u8>>>,
+    /// Least recently used first
+    order: VecDeque<String>,
+}
+
+impl MemoryCache {
+    fn get(&mut self, url: &str) -> Option<Arc<Vec<u8>>> {
+        let image = self.images.get(url)?.clone();
+        self.touch(url);
+        Some(image)
+    }
+
+    fn insert(&mut self, url: String, image: Arc<Vec<u8>>) {
+        if self.images.insert(url.clone(), image).is_some() {
+            self.touch(&url);
+            return;
+        }
+        self.order.push_back(url);
+        while self.order.len() > MEMORY_CACHE_ENTRIES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.images.remove(&oldest);
+            }
+        }
+    }
+
+    fn touch(&mut self, url: &str) {
+        if let Some(index) = self.order.iter().position(|u| u == url) {
+            let url = self.order.remove(index).expect("index in bounds");
+            self.order.push_back(url);
+        }
+    }
+}
+
+/// Downloads artwork and caches it in memory and on disk
+pub struct ArtworkCache {
+    http: Client,
+    /// Where files are kept (None = under the system temp dir)
+    dir: RwLock<Option<PathBuf>>,
+    memory: Mutex<MemoryCache>,
+}
+
+impl ArtworkCache {
+    pub fn new() -> Self {
+        Self {
+            http: Client::builder()
+                .timeout(DOWNLOAD_TIMEOUT)
+                .build()
+                .expect("Failed to create HTTP client"),
+            dir: RwLock::new(None),
+            memory: Mutex::new(MemoryCache::default()),
+        }
+    }
+
+    /// Set where files are cached (None = under the system temp dir)
+    pub fn set_dir(&self, dir: Option<PathBuf>) {
+        *self.dir.write().unwrap() = dir;
+    }
+
+    /// Image bytes of `url` at `size`x`size` pixels
+    pub async fn fetch(&self, url: &str, size: u32) -> Result<Arc<Vec<u8>>, ArtworkError> {
+        let url = sized_url(url, size)?;
+        if let Some(image) = self.memory.lock().unwrap().get(&url) {
+            return Ok(image);
+        }
+
+        let path = self.file_path(&url);
+        let image = match tokio::fs::read(&path).await {
+            Ok(image) => image,
+            Err(_) => {
+                let image = self.download(&url).await?;
+                if let Err(e) = write_file(&path, &image).await {
+                    debug!("Couldn't cache artwork in {}: {}", path.display(), e);
+                }
+                image
+            }
+        };
+
+        let image = Arc::new(image);
+        self.memory.lock().unwrap().insert(url, Arc::clone(&image));
+        Ok(image)
+    }
+
+    /// Path of a file holding `url` at `size`x`size` pixels (downloaded if not cached yet)
+    pub async fn path(&self, url: &str, size: u32) -> Result<PathBuf, ArtworkError> {
+        let url = sized_url(url, size)?;
+        let path = self.file_path(&url);
+        if tokio::fs::metadata(&path).await.is_err() {
+            let cached = self.memory.lock().unwrap().get(&url);
+            let image = match cached {
+                Some(image) => image,
+                None => Arc::new(self.download(&url).await?),
+            };
+            write_file(&path, &image).await?;
+            self.memory.lock().unwrap().insert(url, image);
+        }
+        Ok(path)
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, ArtworkError> {
+        debug!("Downloading artwork {}", url);
+        let resp = self.http.get(url).send().await?.error_for_status()?;
+        Ok(resp.bytes().await?.to_vec())
+    }
+
+    /// Cache file for a sized URL
+    fn file_path(&self, url: &str) -> PathBuf {
+        let dir = self
+            .dir
+            .read()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join(DEFAULT_CACHE_DIR));
+        let name: String = Sha256::digest(url.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+        let extension = Path::new(url.split(['?', '#']).next().unwrap_or(url))
+            .extension()
+            .and_then(|e| e.to_str())
+            .filter(|e| matches!(*e, "jpg" | "jpeg" | "png" | "webp"))
+            .unwrap_or("jpg");
+        dir.join(format!("{}.{}", name, extension))
+    }
+}
+
+impl Default for ArtworkCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Write a cache file in one go, so readers never see a partial image
+async fn write_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let partial = path.with_extension("part");
+    tokio::fs::write(&partial, contents).await?;
+    tokio::fs::rename(&partial, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serve a fixed image over HTTP, counting requests
+    async fn stub_cdn() -> (String, Arc<AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                counted.fetch_add(1, Ordering::SeqCst);
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: 4\r\n\r\nJPEG")
+                    .await;
+            }
+        });
+        (base, requests)
+    }
+
+    fn temp_cache() -> (ArtworkCache, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("cider-artwork-test-{}", rand::random::<u64>()));
+        let cache = ArtworkCache::new();
+        cache.set_dir(Some(dir.clone()));
+        (cache, dir)
+    }
+
+    #[test]
+    fn test_sized_url() {
+        assert_eq!(
+            sized_url("https://is1-ssl.mzstatic.com/image/thumb/a/b/{w}x{h}bb.jpg", 300).unwrap(),
+            "https://is1-ssl.mzstatic.com/image/thumb/a/b/300x300bb.jpg"
+        );
+        assert_eq!(
+            sized_url("https://is1-ssl.mzstatic.com/image/thumb/a/b/600x600bb.jpg", 1200).unwrap(),
+            "https://is1-ssl.mzstatic.com/image/thumb/a/b/1200x1200bb.jpg"
+        );
+        assert_eq!(sized_url("https://example.com/cover.png", 300).unwrap(), "https://example.com/cover.png");
+        assert!(matches!(sized_url("", 300), Err(ArtworkError::InvalidUrl(_))));
+    }
+
+    #[test]
+    fn test_memory_eviction() {
+        let mut memory = MemoryCache::default();
+        for i in 0..MEMORY_CACHE_ENTRIES {
+            memory.insert(format!("url{}", i), Arc::new(vec![i as u8]));
+        }
+        // Recently used images survive
+        assert!(memory.get("url0").is_some());
+        memory.insert("new".to_string(), Arc::new(Vec::new()));
+
+        assert!(memory.get("url0").is_some());
+        assert!(memory.get("url1").is_none());
+        assert_eq!(memory.images.len(), MEMORY_CACHE_ENTRIES);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_once() {
+        let (base, requests) = stub_cdn().await;
+        let (cache, dir) = temp_cache();
+        let url = format!("{}/art/{{w}}x{{h}}bb.jpg", base);
+
+        for _ in 0..3 {
+            assert_eq!(cache.fetch(&url, 300).await.unwrap().as_slice(), b"JPEG");
+        }
+        let path = cache.path(&url, 300).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"JPEG");
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // Another size is another image; a fresh cache finds files on disk
+        cache.fetch(&url, 600).await.unwrap();
+        let (fresh, _) = temp_cache();
+        fresh.set_dir(Some(dir.clone()));
+        fresh.fetch(&url, 300).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
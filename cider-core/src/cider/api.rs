@@ -0,0 +1,78 @@
+//! Playback API used by the sync engine
+//!
+//! The handlers and loops that keep peers in sync only need a handful of
+//! Cider calls. They go through this trait so tests can swap in a scripted
+//! mock (`mock::MockCider`) instead of a running Cider.
+
+use std::future::Future;
+
+use super::client::{CiderClient, CiderError};
+use super::types::{NowPlaying, SearchResult};
+
+/// Cider playback control as used by the sync engine
+pub trait CiderApi: Clone + Send + Sync + 'static {
+    /// Currently playing track (None if nothing is playing)
+    fn now_playing(&self) -> impl Future<Output = Result<Option<NowPlaying>, CiderError>> + Send;
+
+    /// Whether music is playing
+    fn is_playing(&self) -> impl Future<Output = Result<bool, CiderError>> + Send;
+
+    /// Resume playback
+    fn play(&self) -> impl Future<Output = Result<(), CiderError>> + Send;
+
+    /// Pause playback
+    fn pause(&self) -> impl Future<Output = Result<(), CiderError>> + Send;
+
+    /// Seek to a position in milliseconds
+    fn seek_ms(&self, position_ms: u64) -> impl Future<Output = Result<(), CiderError>> + Send;
+
+    /// Play an item by type (e.g. "songs") and Apple Music ID
+    fn play_item(&self, item_type: &str, id: &str) -> impl Future<Output = Result<(), CiderError>> + Send;
+
+    /// Search the catalog in the user's storefront
+    fn search(&self, term: &str, types: &[&str]) -> impl Future<Output = Result<Vec<SearchResult>, CiderError>> + Send;
+
+    /// Storefront (country code) of the user's account
+    fn storefront(&self) -> impl Future<Output = Result<String, CiderError>> + Send;
+
+    /// Catalog songs with the given ISRC in the user's storefront
+    fn songs_by_isrc(&self, isrc: &str) -> impl Future<Output = Result<Vec<SearchResult>, CiderError>> + Send;
+}
+
+impl CiderApi for CiderClient {
+    fn now_playing(&self) -> impl Future<Output = Result<Option<NowPlaying>, CiderError>> + Send {
+        CiderClient::now_playing(self)
+    }
+
+    fn is_playing(&self) -> impl Future<Output = Result<bool, CiderError>> + Send {
+        CiderClient::is_playing(self)
+    }
+
+    fn play(&self) -> impl Future<Output = Result<(), CiderError>> + Send {
+        CiderClient::play(self)
+    }
+
+    fn pause(&self) -> impl Future<Output = Result<(), CiderError>> + Send {
+        CiderClient::pause(self)
+    }
+
+    fn seek_ms(&self, position_ms: u64) -> impl Future<Output = Result<(), CiderError>> + Send {
+        CiderClient::seek_ms(self, position_ms)
+    }
+
+    fn play_item(&self, item_type: &str, id: &str) -> impl Future<Output = Result<(), CiderError>> + Send {
+        CiderClient::play_item(self, item_type, id)
+    }
+
+    fn search(&self, term: &str, types: &[&str]) -> impl Future<Output = Result<Vec<SearchResult>, CiderError>> + Send {
+        CiderClient::search(self, term, types)
+    }
+
+    async fn storefront(&self) -> Result<String, CiderError> {
+        CiderClient::storefront(self).await.map(str::to_string)
+    }
+
+    fn songs_by_isrc(&self, isrc: &str) -> impl Future<Output = Result<Vec<SearchResult>, CiderError>> + Send {
+        CiderClient::songs_by_isrc(self, isrc)
+    }
+}
@@ -0,0 +1,176 @@
+//! Short-lived cache of playback reads
+//!
+//! The host loop, the UI's state polling and the sync handlers often ask
+//! Cider for the same now-playing/is-playing data within a few hundred ms.
+//! Reads within the TTL reuse the last response; any command invalidates it.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::types::NowPlaying;
+
+/// A cached response and when it was read
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    read_at: Instant,
+    value: T,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Bumped by every invalidation, so reads that started before a command aren't stored
+    epoch: u64,
+    now_playing: Option<Entry<Option<NowPlaying>>>,
+    is_playing: Option<Entry<bool>>,
+}
+
+/// Recent `now_playing`/`is_playing` responses
+#[derive(Debug)]
+pub struct PlaybackCache {
+    ttl: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl PlaybackCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Current epoch; pass it back when storing a response
+    pub fn epoch(&self) -> u64 {
+        self.inner.lock().unwrap().epoch
+    }
+
+    /// Forget cached responses (playback may have changed)
+    pub fn invalidate(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.epoch += 1;
+        inner.now_playing = None;
+        inner.is_playing = None;
+    }
+
+    /// Cached now-playing response, with the position advanced since it was read if playing
+    pub fn now_playing(&self) -> Option<Option<NowPlaying>> {
+        let inner = self.inner.lock().unwrap();
+        let entry = inner.now_playing.as_ref().filter(|e| e.read_at.elapsed() < self.ttl)?;
+        let playing = inner
+            .is_playing
+            .as_ref()
+            .is_some_and(|e| e.value && e.read_at.elapsed() < self.ttl);
+
+        let mut now_playing = entry.value.clone();
+        if let Some(np) = now_playing.as_mut().filter(|_| playing) {
+            np.current_playback_time += entry.read_at.elapsed().as_secs_f64();
+        }
+        Some(now_playing)
+    }
+
+    /// Cached is-playing response
+    pub fn is_playing(&self) -> Option<bool> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .is_playing
+            .as_ref()
+            .filter(|e| e.read_at.elapsed() < self.ttl)
+            .map(|e| e.value)
+    }
+
+    /// Store a now-playing response read in `epoch`
+    pub fn store_now_playing(&self, epoch: u64, value: Option<NowPlaying>) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.epoch == epoch {
+            inner.now_playing = Some(Entry { read_at: Instant::now(), value });
+        }
+    }
+
+    /// Store an is-playing response read in `epoch`
+    pub fn store_is_playing(&self, epoch: u64, value: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.epoch == epoch {
+            inner.is_playing = Some(Entry { read_at: Instant::now(), value });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cider::Artwork;
+
+    const TTL: Duration = Duration::from_millis(100);
+
+    fn track(position_secs: f64) -> NowPlaying {
+        NowPlaying {
+            play_params: None,
+            name: "Song".to_string(),
+            artist_name: "Artist".to_string(),
+            album_name: "Album".to_string(),
+            artwork: Artwork {
+                width: 600,
+                height: 600,
+                url: String::new(),
+            },
+            duration_in_millis: 200_000,
+            current_playback_time: position_secs,
+            remaining_time: 0.0,
+            genre_names: Vec::new(),
+            track_number: 1,
+            release_date: None,
+            has_lyrics: false,
+            in_favorites: false,
+            in_library: false,
+            shuffle_mode: 0,
+            repeat_mode: 0,
+            url: None,
+            isrc: None,
+            content_rating: None,
+        }
+    }
+
+    #[test]
+    fn test_reuse_within_ttl() {
+        let cache = PlaybackCache::new(TTL);
+        assert!(cache.is_playing().is_none());
+
+        cache.store_is_playing(cache.epoch(), false);
+        cache.store_now_playing(cache.epoch(), Some(track(10.0)));
+        assert_eq!(cache.is_playing(), Some(false));
+        // Paused: the position stays put
+        let np = cache.now_playing().unwrap().unwrap();
+        assert_eq!(np.current_playback_time, 10.0);
+
+        std::thread::sleep(TTL);
+        assert!(cache.is_playing().is_none());
+        assert!(cache.now_playing().is_none());
+    }
+
+    #[test]
+    fn test_position_advances_while_playing() {
+        let cache = PlaybackCache::new(TTL);
+        cache.store_is_playing(cache.epoch(), true);
+        cache.store_now_playing(cache.epoch(), Some(track(10.0)));
+
+        std::thread::sleep(Duration::from_millis(20));
+        let np = cache.now_playing().unwrap().unwrap();
+        assert!(np.current_playback_time >= 10.02);
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let cache = PlaybackCache::new(TTL);
+        let epoch = cache.epoch();
+        cache.store_is_playing(epoch, true);
+
+        cache.invalidate();
+        assert!(cache.is_playing().is_none());
+
+        // A read that started before the command isn't stored
+        cache.store_is_playing(epoch, true);
+        assert!(cache.is_playing().is_none());
+        cache.store_now_playing(cache.epoch(), None);
+        assert!(matches!(cache.now_playing(), Some(None)));
+    }
+}
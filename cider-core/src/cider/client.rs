@@ -1,21 +1,67 @@
 //! Cider API HTTP Client
 
-use std::time::Duration;
-use reqwest::Client;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use reqwest::{Client, RequestBuilder, Response};
+use tokio::sync::OnceCell;
 use thiserror::Error;
 use tracing::{debug, warn, instrument};
 
+use super::cache::PlaybackCache;
+use super::throttle::RateLimiter;
 use super::types::*;
 
 /// Default Cider API port
 pub const DEFAULT_PORT: u16 = 10767;
 
+/// Ports Cider's API is commonly moved to, tried by `discover` (default first)
+pub const CANDIDATE_PORTS: &[u16] = &[DEFAULT_PORT, 10766, 10768, 10769, 10770];
+
+/// Default Cider API host
+// Use 127.0.0.1 explicitly to avoid IPv6 issues
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+
 /// Default connection timeout (short since it's localhost)
 const CONNECTION_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Default request timeout (short since it's localhost)
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
 
+/// Connection timeout for Cider on another machine (Wi-Fi adds latency)
+const REMOTE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Request timeout for Cider on another machine
+const REMOTE_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Attempts per request while Cider can't be reached
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry (doubled for each further one, then jittered)
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// Minimum spacing of calls to the same endpoint
+const ENDPOINT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long now-playing/is-playing responses are reused
+const PLAYBACK_CACHE_TTL: Duration = Duration::from_millis(250);
+
+/// Slowest playback rate `set_playback_rate` sets
+const MIN_PLAYBACK_RATE: f64 = 0.5;
+
+/// Fastest playback rate `set_playback_rate` sets
+const MAX_PLAYBACK_RATE: f64 = 2.0;
+
+/// How long `seek_confirmed` waits for Cider to report the new position
+const SEEK_CONFIRM_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How often `seek_confirmed` reads the position
+const SEEK_CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How far a reported position may be from the seek target and still count as applied
+const SEEK_CONFIRM_TOLERANCE_MS: u64 = 250;
+
 /// Errors that can occur when communicating with Cider
 #[derive(Debug, Error)]
 pub enum CiderError {
@@ -33,14 +79,30 @@ pub enum CiderError {
 
     #[error("API error: {0}")]
     Api(String),
+
+    #[error("Cider didn't apply the seek to {0}ms in time")]
+    SeekNotConfirmed(u64),
+
+    #[error("Unexpected response from Cider: {0}")]
+    UnexpectedSchema(#[from] serde_path_to_error::Error<serde_json::Error>),
 }
 
 /// Client for interacting with Cider's REST API
 #[derive(Debug, Clone)]
 pub struct CiderClient {
     http: Client,
+    host: String,
+    port: u16,
     base_url: String,
     api_token: Option<String>,
+    /// Storefront of the signed-in account (looked up on first search)
+    storefront: Arc<OnceCell<String>>,
+    /// Paces calls per endpoint (shared by clones)
+    limiter: Arc<RateLimiter>,
+    /// Incremented per seek so superseded seeks can be dropped
+    seek_generation: Arc<AtomicU64>,
+    /// Recent playback reads (shared by clones)
+    playback_cache: Arc<PlaybackCache>,
 }
 
 impl CiderClient {
@@ -51,9 +113,19 @@ impl CiderClient {
 
     /// Create a new CiderClient with a custom port
     pub fn with_port(port: u16) -> Self {
+        Self::with_host(DEFAULT_HOST, port)
+    }
+
+    /// Create a new CiderClient for Cider on another machine (e.g. "192.168.1.20" or "desktop.local")
+    pub fn with_host(host: &str, port: u16) -> Self {
+        let (connect_timeout, request_timeout) = if is_local_host(host) {
+            (CONNECTION_TIMEOUT, REQUEST_TIMEOUT)
+        } else {
+            (REMOTE_CONNECTION_TIMEOUT, REMOTE_REQUEST_TIMEOUT)
+        };
         let http = Client::builder()
-            .connect_timeout(CONNECTION_TIMEOUT)
-            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(connect_timeout)
+            .timeout(request_timeout)
             // Limit connection pool to avoid stale connections
             .pool_max_idle_per_host(2)
             .pool_idle_timeout(Duration::from_secs(10))
@@ -62,11 +134,23 @@ impl CiderClient {
             .build()
             .expect("Failed to build HTTP client");
 
+        // IPv6 literals need brackets in URLs
+        let base_url = if host.contains(':') {
+            format!("http://[{}]:{}", host, port)
+        } else {
+            format!("http://{}:{}", host, port)
+        };
+
         Self {
             http,
-            // Use 127.0.0.1 explicitly to avoid IPv6 issues
-            base_url: format!("http://127.0.0.1:{}", port),
+            host: host.to_string(),
+            port,
+            base_url,
             api_token: None,
+            storefront: Arc::new(OnceCell::new()),
+            limiter: Arc::new(RateLimiter::new(ENDPOINT_INTERVAL)),
+            seek_generation: Arc::new(AtomicU64::new(0)),
+            playback_cache: Arc::new(PlaybackCache::new(PLAYBACK_CACHE_TTL)),
         }
     }
 
@@ -76,9 +160,79 @@ impl CiderClient {
         self
     }
 
-    /// Build a request with optional authentication
+    /// Host running Cider's API
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Whether requests carry an API token
+    pub fn has_token(&self) -> bool {
+        self.api_token.is_some()
+    }
+
+    /// Port of Cider's API this client talks to
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Same client (host and token) for another port
+    pub fn at_port(&self, port: u16) -> Self {
+        let client = Self::with_host(&self.host, port);
+        match &self.api_token {
+            Some(token) => client.with_token(token.clone()),
+            None => client,
+        }
+    }
+
+    /// Same client (port and token) for another host
+    pub fn at_host(&self, host: &str) -> Self {
+        let client = Self::with_host(host, self.port);
+        match &self.api_token {
+            Some(token) => client.with_token(token.clone()),
+            None => client,
+        }
+    }
+
+    /// Find Cider's API on one of `ports`
+    ///
+    /// Probes all ports at once and returns a client for the first one (in
+    /// the given order) where Cider answers. A port that rejects the token
+    /// still counts: Cider is there, and the caller can report the token.
+    pub async fn discover(&self, ports: &[u16]) -> Option<Self> {
+        let probes = ports.iter().map(|&port| async move {
+            let client = self.at_port(port);
+            match client.is_active().await {
+                Ok(()) | Err(CiderError::Unauthorized) => Some(client),
+                Err(_) => None,
+            }
+        });
+        futures::future::join_all(probes).await.into_iter().flatten().next()
+    }
+
+    /// Build a playback API request with optional authentication
     fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
-        let url = format!("{}/api/v1/playback{}", self.base_url, path);
+        self.api_request(method, &playback_path(path))
+    }
+
+    /// Build a request for any API path with optional authentication
+    fn api_request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}{}", self.base_url, path);
+        let mut req = self.http.request(method, &url);
+
+        if let Some(token) = &self.api_token {
+            req = req.header("apitoken", token);
+        }
+
+        req
+    }
+
+    /// Build a Socket.IO polling request (`sid` once the session is open)
+    pub(super) fn socket_request(&self, method: reqwest::Method, sid: Option<&str>) -> reqwest::RequestBuilder {
+        let mut url = format!("{}/socket.io/?EIO=4&transport=polling", self.base_url);
+        if let Some(sid) = sid {
+            url.push_str("&sid=");
+            url.push_str(sid);
+        }
         let mut req = self.http.request(method, &url);
 
         if let Some(token) = &self.api_token {
@@ -94,7 +248,7 @@ impl CiderClient {
         debug!("Checking Cider connection");
 
         let resp = self.request(reqwest::Method::GET, "/active")
-            .send()
+            .send_paced(self)
             .await
             .map_err(|e| {
                 warn!("Connection error: {:?}", e);
@@ -116,41 +270,64 @@ impl CiderClient {
         }
     }
 
-    /// Check if music is currently playing
+    /// Check if music is currently playing (reuses a response from the last 250ms)
     pub async fn is_playing(&self) -> Result<bool, CiderError> {
+        if let Some(playing) = self.playback_cache.is_playing() {
+            return Ok(playing);
+        }
+        let epoch = self.playback_cache.epoch();
         let resp: ApiResponse<IsPlayingResponse> = self
             .request(reqwest::Method::GET, "/is-playing")
-            .send()
+            .send_paced(self)
             .await?
             .json()
             .await?;
 
+        self.playback_cache.store_is_playing(epoch, resp.data.is_playing);
         Ok(resp.data.is_playing)
     }
 
+    /// Drop cached playback reads, e.g. after learning from an event that playback changed
+    pub fn invalidate_playback_cache(&self) {
+        self.playback_cache.invalidate();
+    }
+
     /// Get the currently playing track (returns None if nothing is playing)
+    ///
+    /// Reuses a response from the last 250ms, with the position advanced if playing.
     pub async fn now_playing(&self) -> Result<Option<NowPlaying>, CiderError> {
+        if let Some(now_playing) = self.playback_cache.now_playing() {
+            return Ok(now_playing);
+        }
+        self.read_now_playing().await
+    }
+
+    /// Read the current track from Cider, bypassing the cache (but refreshing it)
+    async fn read_now_playing(&self) -> Result<Option<NowPlaying>, CiderError> {
+        let epoch = self.playback_cache.epoch();
         let resp = self
             .request(reqwest::Method::GET, "/now-playing")
-            .send()
+            .send_paced(self)
             .await?;
 
         // Handle case where nothing is playing
         if resp.status() == 404 || resp.status() == 204 {
+            self.playback_cache.store_now_playing(epoch, None);
             return Ok(None);
         }
 
-        // Try to parse the response - if it fails, assume nothing is playing
-        match resp.json::<ApiResponse<NowPlayingResponse>>().await {
-            Ok(data) => Ok(Some(data.data.info)),
-            Err(_) => Ok(None),
-        }
+        let body = resp.text().await?;
+        let now_playing = parse_now_playing(&body).inspect_err(|e| {
+            warn!("Failed to parse now-playing response ({}): {}", e, body);
+        })?;
+        self.playback_cache.store_now_playing(epoch, now_playing.clone());
+        Ok(now_playing)
     }
 
     /// Resume playback
     pub async fn play(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/play")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -159,7 +336,7 @@ impl CiderClient {
     /// Pause playback
     pub async fn pause(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/pause")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -168,7 +345,7 @@ impl CiderClient {
     /// Toggle play/pause
     pub async fn play_pause(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/playpause")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -177,7 +354,7 @@ impl CiderClient {
     /// Stop playback
     pub async fn stop(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/stop")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -186,7 +363,7 @@ impl CiderClient {
     /// Skip to next track
     pub async fn next(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/next")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -195,7 +372,7 @@ impl CiderClient {
     /// Go to previous track
     pub async fn previous(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/previous")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -203,14 +380,24 @@ impl CiderClient {
 
     /// Seek to a position in the current track
     ///
+    /// Seeks issued while an earlier one is still waiting for its slot
+    /// supersede it; only the latest position is sent.
+    ///
     /// # Arguments
     /// * `position_secs` - Position in seconds to seek to
     pub async fn seek(&self, position_secs: f64) -> Result<(), CiderError> {
+        let generation = self.seek_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.limiter.wait_idle(&playback_path("/seek")).await;
+        if self.seek_generation.load(Ordering::SeqCst) != generation {
+            debug!("Dropping seek to {:.2}s, superseded by a newer one", position_secs);
+            return Ok(());
+        }
+
         self.request(reqwest::Method::POST, "/seek")
             .json(&SeekRequest {
                 position: position_secs,
             })
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -221,13 +408,42 @@ impl CiderClient {
         self.seek(position_ms as f64 / 1000.0).await
     }
 
+    /// Seek and wait until Cider reports the new position
+    ///
+    /// Returns the position Cider reported and how long the seek took to show
+    /// up, or `SeekNotConfirmed` if it didn't within a few seconds (e.g.
+    /// because a newer seek superseded it).
+    pub async fn seek_confirmed(&self, position_ms: u64) -> Result<SeekConfirmation, CiderError> {
+        self.seek_ms(position_ms).await?;
+        let sent_at = Instant::now();
+
+        while sent_at.elapsed() < SEEK_CONFIRM_TIMEOUT {
+            if let Some(np) = self.read_now_playing().await? {
+                let reported = np.current_position_ms();
+                // Playback may have moved on from the target since
+                let latest = position_ms + sent_at.elapsed().as_millis() as u64;
+                let applied = position_ms.saturating_sub(SEEK_CONFIRM_TOLERANCE_MS)..=latest + SEEK_CONFIRM_TOLERANCE_MS;
+                if applied.contains(&reported) {
+                    return Ok(SeekConfirmation {
+                        position_ms: reported,
+                        settle_time: sent_at.elapsed(),
+                    });
+                }
+            }
+            tokio::time::sleep(SEEK_CONFIRM_POLL_INTERVAL).await;
+        }
+
+        warn!("Seek to {}ms not reflected after {:?}", position_ms, SEEK_CONFIRM_TIMEOUT);
+        Err(CiderError::SeekNotConfirmed(position_ms))
+    }
+
     /// Play a track by its Apple Music URL
     pub async fn play_url(&self, url: &str) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/play-url")
             .json(&PlayUrlRequest {
                 url: url.to_string(),
             })
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -244,7 +460,7 @@ impl CiderClient {
                 item_type: item_type.to_string(),
                 id: id.to_string(),
             })
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -257,7 +473,7 @@ impl CiderClient {
                 item_type: item_type.to_string(),
                 id: id.to_string(),
             })
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -270,7 +486,7 @@ impl CiderClient {
                 item_type: item_type.to_string(),
                 id: id.to_string(),
             })
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -280,7 +496,7 @@ impl CiderClient {
     pub async fn get_volume(&self) -> Result<f32, CiderError> {
         let resp: ApiResponse<VolumeResponse> = self
             .request(reqwest::Method::GET, "/volume")
-            .send()
+            .send_paced(self)
             .await?
             .json()
             .await?;
@@ -294,7 +510,35 @@ impl CiderClient {
             .json(&VolumeRequest {
                 volume: volume.clamp(0.0, 1.0),
             })
-            .send()
+            .send_paced(self)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Get the playback rate (1.0 = normal speed)
+    pub async fn get_playback_rate(&self) -> Result<f64, CiderError> {
+        let resp: ApiResponse<PlaybackRateResponse> = self
+            .request(reqwest::Method::GET, "/playback-rate")
+            .send_paced(self)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(resp.data.playback_rate)
+    }
+
+    /// Set the playback rate (1.0 = normal speed, clamped to 0.5-2.0)
+    ///
+    /// Small changes (e.g. 1.02 for a few seconds) close a drift gradually,
+    /// without the audible skip and rebuffering of a seek.
+    pub async fn set_playback_rate(&self, rate: f64) -> Result<(), CiderError> {
+        self.request(reqwest::Method::POST, "/playback-rate")
+            .json(&PlaybackRateRequest {
+                playback_rate: rate.clamp(MIN_PLAYBACK_RATE, MAX_PLAYBACK_RATE),
+            })
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -303,19 +547,52 @@ impl CiderClient {
     /// Add current track to library
     pub async fn add_to_library(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/add-to-library")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
     }
 
+    /// Create a playlist in the user's library with catalog songs `song_ids`, in order
+    ///
+    /// Returns the new playlist's library ID. Cider versions without the
+    /// library playlist endpoint answer 404.
+    pub async fn create_library_playlist(
+        &self,
+        name: &str,
+        description: &str,
+        song_ids: &[String],
+    ) -> Result<String, CiderError> {
+        let body = CreatePlaylistRequest {
+            name: name.to_string(),
+            description: description.to_string(),
+            tracks: song_ids
+                .iter()
+                .map(|id| PlaylistTrack {
+                    id: id.clone(),
+                    kind: "songs".to_string(),
+                })
+                .collect(),
+        };
+        let resp = self
+            .request(reqwest::Method::POST, "/library/playlists")
+            .json(&body)
+            .send_paced(self)
+            .await?;
+        if resp.status() == 404 {
+            return Err(CiderError::Api("This version of Cider can't create playlists".to_string()));
+        }
+        let resp: ApiResponse<CreatePlaylistResponse> = resp.error_for_status()?.json().await?;
+        Ok(resp.data.id)
+    }
+
     /// Set rating for current track (-1 = dislike, 0 = unset, 1 = like)
     pub async fn set_rating(&self, rating: i8) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/set-rating")
             .json(&RatingRequest {
                 rating: rating.clamp(-1, 1),
             })
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -325,7 +602,7 @@ impl CiderClient {
     pub async fn get_repeat_mode(&self) -> Result<u8, CiderError> {
         let resp: ApiResponse<RepeatModeResponse> = self
             .request(reqwest::Method::GET, "/repeat-mode")
-            .send()
+            .send_paced(self)
             .await?
             .json()
             .await?;
@@ -336,7 +613,7 @@ impl CiderClient {
     /// Toggle repeat mode
     pub async fn toggle_repeat(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/toggle-repeat")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -346,7 +623,7 @@ impl CiderClient {
     pub async fn get_shuffle_mode(&self) -> Result<u8, CiderError> {
         let resp: ApiResponse<ShuffleModeResponse> = self
             .request(reqwest::Method::GET, "/shuffle-mode")
-            .send()
+            .send_paced(self)
             .await?
             .json()
             .await?;
@@ -357,7 +634,7 @@ impl CiderClient {
     /// Toggle shuffle mode
     pub async fn toggle_shuffle(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/toggle-shuffle")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
@@ -367,7 +644,7 @@ impl CiderClient {
     pub async fn get_autoplay(&self) -> Result<bool, CiderError> {
         let resp: ApiResponse<AutoplayResponse> = self
             .request(reqwest::Method::GET, "/autoplay")
-            .send()
+            .send_paced(self)
             .await?
             .json()
             .await?;
@@ -378,22 +655,224 @@ impl CiderClient {
     /// Toggle autoplay
     pub async fn toggle_autoplay(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/toggle-autoplay")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
     }
 
+    /// Get the playback queue, in play order
+    pub async fn get_queue(&self) -> Result<Vec<QueueItem>, CiderError> {
+        let items: Vec<RawQueueItem> = self
+            .request(reqwest::Method::GET, "/queue")
+            .send_paced(self)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(items.into_iter().map(QueueItem::from).collect())
+    }
+
+    /// Move a queue item to another position
+    ///
+    /// Indices refer to positions in the list returned by `get_queue`.
+    pub async fn move_queue_item(&self, from: usize, to: usize) -> Result<(), CiderError> {
+        self.request(reqwest::Method::POST, "/queue/move-to-position")
+            .json(&QueueMoveRequest {
+                start_index: from,
+                destination_index: to,
+            })
+            .send_paced(self)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Remove the queue item at `index` (a position in `get_queue`)
+    pub async fn remove_queue_item(&self, index: usize) -> Result<(), CiderError> {
+        self.request(reqwest::Method::POST, "/queue/remove-by-index")
+            .json(&QueueRemoveRequest { index })
+            .send_paced(self)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Search the Apple Music catalog in the user's storefront
+    ///
+    /// # Arguments
+    /// * `term` - Search text
+    /// * `types` - Item types to search (e.g., "songs", "albums"); results are grouped in this order
+    pub async fn search(&self, term: &str, types: &[&str]) -> Result<Vec<SearchResult>, CiderError> {
+        let storefront = self.storefront().await?;
+        let mut url = reqwest::Url::parse("http://localhost").expect("static URL");
+        url.set_path(&format!("/v1/catalog/{}/search", storefront));
+        url.query_pairs_mut()
+            .append_pair("term", term)
+            .append_pair("types", &types.join(","));
+        let path = format!("{}?{}", url.path(), url.query().unwrap_or_default());
+
+        let resp: AmApiResponse<SearchResponse> = self.amapi(path).await?;
+        Ok(search_results(resp.data, types))
+    }
+
+    /// Look up catalog songs by ISRC in the user's storefront
+    ///
+    /// The same recording often has a different ID in each storefront; its ISRC stays the same.
+    pub async fn songs_by_isrc(&self, isrc: &str) -> Result<Vec<SearchResult>, CiderError> {
+        let storefront = self.storefront().await?;
+        let mut url = reqwest::Url::parse("http://localhost").expect("static URL");
+        url.set_path(&format!("/v1/catalog/{}/songs", storefront));
+        url.query_pairs_mut().append_pair("filter[isrc]", isrc);
+        let path = format!("{}?{}", url.path(), url.query().unwrap_or_default());
+
+        let resp: AmApiResponse<SearchResultSet> = self.amapi(path).await?;
+        Ok(resp.data.data.into_iter().map(SearchResult::from).collect())
+    }
+
+    /// Apple Music storefront (country code, e.g. "us") of the signed-in account
+    ///
+    /// Looked up once, then cached.
+    pub async fn storefront(&self) -> Result<&str, CiderError> {
+        self.storefront
+            .get_or_try_init(|| async {
+                let resp: AmApiResponse<StorefrontResponse> = self.amapi("/v1/me/storefront".to_string()).await?;
+                resp.data
+                    .data
+                    .into_iter()
+                    .next()
+                    .map(|s| s.id)
+                    .ok_or_else(|| CiderError::Api("No storefront for this account".to_string()))
+            })
+            .await
+            .map(String::as_str)
+    }
+
+    /// Whether the signed-in account has explicit content restricted
+    ///
+    /// Not cached: the restriction can be changed while Cider is running.
+    pub async fn explicit_content_restricted(&self) -> Result<bool, CiderError> {
+        let resp: AmApiResponse<AccountResponse> = self.amapi("/v1/me/account?meta=restrictions".to_string()).await?;
+        Ok(resp.data.meta.restrictions.is_some_and(|r| r.explicit))
+    }
+
+    /// Run an Apple Music API request through Cider (which adds the user's tokens)
+    async fn amapi<T: serde::de::DeserializeOwned>(&self, path: String) -> Result<T, CiderError> {
+        let resp = self
+            .api_request(reqwest::Method::POST, "/api/v1/amapi/run-v3")
+            .json(&AmApiRequest { path })
+            .send_paced(self)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(resp)
+    }
+
     /// Clear the queue
     pub async fn clear_queue(&self) -> Result<(), CiderError> {
         self.request(reqwest::Method::POST, "/queue/clear-queue")
-            .send()
+            .send_paced(self)
             .await?
             .error_for_status()?;
         Ok(())
     }
 }
 
+/// Sending paced per endpoint, with a retry policy for momentary hiccups in Cider's server
+trait SendPaced {
+    /// Send the request once the client's limiter allows, retrying failures to reach Cider with jittered backoff
+    ///
+    /// Connect errors are always retried since nothing reached Cider. Timeouts
+    /// are only retried for GETs: a command may have been carried out anyway.
+    /// Playback commands invalidate the client's cached playback reads.
+    async fn send_paced(self, client: &CiderClient) -> Result<Response, reqwest::Error>;
+}
+
+impl SendPaced for RequestBuilder {
+    async fn send_paced(self, client: &CiderClient) -> Result<Response, reqwest::Error> {
+        let (http, request) = self.build_split();
+        let request = request?;
+        client.limiter.acquire(request.url().path()).await;
+
+        let is_command =
+            request.method() != reqwest::Method::GET && request.url().path().starts_with(&playback_path("/"));
+        if !is_command {
+            return execute_with_retry(&http, request).await;
+        }
+        // Before and after, so reads racing the command aren't cached either
+        client.playback_cache.invalidate();
+        let result = execute_with_retry(&http, request).await;
+        client.playback_cache.invalidate();
+        result
+    }
+}
+
+/// Execute a request, retrying failures to reach Cider with jittered backoff
+async fn execute_with_retry(http: &Client, request: reqwest::Request) -> Result<Response, reqwest::Error> {
+    let mut attempt = 1;
+    loop {
+        // Only streaming bodies can't be cloned, and we never send those
+        let Some(next) = request.try_clone() else {
+            return http.execute(request).await;
+        };
+        let result = http.execute(next).await;
+        let retryable =
+            |e: &reqwest::Error| e.is_connect() || (e.is_timeout() && request.method() == reqwest::Method::GET);
+        match result {
+            Err(e) if attempt < MAX_ATTEMPTS && retryable(&e) => {
+                let delay = retry_delay(attempt);
+                debug!("Cider request {} failed ({}), retrying in {:?}", request.url().path(), e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Full path of a playback API endpoint
+fn playback_path(path: &str) -> String {
+    format!("/api/v1/playback{}", path)
+}
+
+/// Backoff before retry number `attempt` (1-based), jittered by ±50%
+fn retry_delay(attempt: u32) -> Duration {
+    let jitter = rand::thread_rng().gen_range(0.5..1.5);
+    RETRY_BASE_DELAY.mul_f64(2f64.powi(attempt as i32 - 1) * jitter)
+}
+
+/// Whether `host` is this machine
+fn is_local_host(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Flatten search results, grouped in the order the types were requested
+fn search_results(mut response: SearchResponse, types: &[&str]) -> Vec<SearchResult> {
+    types
+        .iter()
+        .filter_map(|kind| response.results.remove(*kind))
+        .flat_map(|set| set.data)
+        .map(SearchResult::from)
+        .collect()
+}
+
+/// Parse a now-playing response (None if nothing is loaded)
+fn parse_now_playing(body: &str) -> Result<Option<NowPlaying>, CiderError> {
+    let mut value: serde_json::Value = serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(body))?;
+
+    // Nothing loaded: Cider leaves `info` out or empty
+    let info = value.get_mut("info").map(serde_json::Value::take).unwrap_or_default();
+    if info.is_null() || info.as_object().is_some_and(|o| o.is_empty()) {
+        return Ok(None);
+    }
+
+    // Paths in errors are relative to `info`
+    Ok(Some(serde_path_to_error::deserialize(info)?))
+}
+
 impl Default for CiderClient {
     fn default() -> Self {
         Self::new()
@@ -407,9 +886,259 @@ mod tests {
     #[tokio::test]
     async fn test_client_creation() {
         let client = CiderClient::new();
-        assert_eq!(client.base_url, "http://localhost:10767");
+        assert_eq!(client.base_url, "http://127.0.0.1:10767");
+
+        let remote = CiderClient::with_host("192.168.1.20", 10767);
+        assert_eq!(remote.base_url, "http://192.168.1.20:10767");
+        let remote_v6 = CiderClient::with_host("fe80::1", 10768);
+        assert_eq!(remote_v6.base_url, "http://[fe80::1]:10768");
+        assert_eq!(remote_v6.at_port(10767).host(), "fe80::1");
+
+        assert!(is_local_host("localhost"));
+        assert!(is_local_host("::1"));
+        assert!(!is_local_host("desktop.local"));
 
         let client_with_token = CiderClient::new().with_token("test-token");
         assert_eq!(client_with_token.api_token, Some("test-token".to_string()));
     }
+
+    /// Local server answering every request with 204, recording the request bodies
+    async fn stub_cider() -> (u16, Arc<std::sync::Mutex<Vec<String>>>) {
+        stub_cider_with(|_| None).await
+    }
+
+    /// Like `stub_cider`, answering requests `respond` has a JSON body for (given the request line)
+    async fn stub_cider_with(
+        respond: impl Fn(&str) -> Option<String> + Send + 'static,
+    ) -> (u16, Arc<std::sync::Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let bodies = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&bodies);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                // Read until the whole body (per Content-Length) is in
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let (head, body) = loop {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => break (String::new(), String::new()),
+                        Ok(len) => request.extend_from_slice(&buf[..len]),
+                    }
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(str::to_string))
+                            .and_then(|v| v.trim().parse().ok())
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break (head.to_string(), body.to_string());
+                        }
+                    }
+                };
+                let response = match respond(head.lines().next().unwrap_or_default()) {
+                    Some(json) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        json.len(),
+                        json
+                    ),
+                    None => "HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n".to_string(),
+                };
+                recorded.lock().unwrap().push(body);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (port, bodies)
+    }
+
+    #[tokio::test]
+    async fn test_discover_port() {
+        let (cider_port, _) = stub_cider().await;
+        let closed_port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+        let client = CiderClient::with_port(closed_port).with_token("token");
+        let found = client.discover(&[closed_port, cider_port]).await.unwrap();
+        assert_eq!(found.port(), cider_port);
+        assert_eq!(found.api_token.as_deref(), Some("token"));
+
+        assert!(client.discover(&[closed_port]).await.is_none());
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        for _ in 0..100 {
+            let first = retry_delay(1);
+            assert!(first >= RETRY_BASE_DELAY / 2 && first < RETRY_BASE_DELAY * 3 / 2);
+            let second = retry_delay(2);
+            assert!(second >= RETRY_BASE_DELAY && second < RETRY_BASE_DELAY * 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_unreachable() {
+        let closed_port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = CiderClient::with_port(closed_port);
+
+        // Every attempt is refused; the retries' backoff shows in the elapsed time
+        let start = std::time::Instant::now();
+        assert!(client.is_active().await.is_err());
+        assert!(start.elapsed() >= RETRY_BASE_DELAY / 2 + RETRY_BASE_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_superseded_seeks_dropped() {
+        let (port, bodies) = stub_cider().await;
+        let client = CiderClient::with_port(port);
+
+        // The first seek takes the slot; of the burst queued behind it only the last is sent
+        client.seek(1.0).await.unwrap();
+        let burst = (2..=5).map(|s| client.seek(s as f64));
+        futures::future::join_all(burst).await;
+
+        let sent = bodies.lock().unwrap().clone();
+        assert_eq!(sent, vec![r#"{"position":1.0}"#, r#"{"position":5.0}"#]);
+    }
+
+    #[tokio::test]
+    async fn test_playback_rate() {
+        let (port, bodies) = stub_cider_with(|request_line| {
+            request_line
+                .starts_with("GET")
+                .then(|| r#"{"status":"ok","playbackRate":1.02}"#.to_string())
+        })
+        .await;
+        let client = CiderClient::with_port(port);
+
+        assert_eq!(client.get_playback_rate().await.unwrap(), 1.02);
+        client.set_playback_rate(1.02).await.unwrap();
+        client.set_playback_rate(10.0).await.unwrap();
+        let sent = bodies.lock().unwrap().clone();
+        assert_eq!(sent[1..], [r#"{"playbackRate":1.02}"#, r#"{"playbackRate":2.0}"#]);
+    }
+
+    #[tokio::test]
+    async fn test_create_library_playlist() {
+        let (port, bodies) = stub_cider_with(|request_line| {
+            request_line
+                .contains("/library/playlists")
+                .then(|| r#"{"status":"ok","id":"p.ZOAXxWZF4EV2GO"}"#.to_string())
+        })
+        .await;
+        let client = CiderClient::with_port(port);
+
+        let ids = vec!["1440818839".to_string(), "1440818840".to_string()];
+        let id = client.create_library_playlist("Friday", "From room ABCD4679", &ids).await.unwrap();
+        assert_eq!(id, "p.ZOAXxWZF4EV2GO");
+        assert_eq!(
+            bodies.lock().unwrap()[0],
+            r#"{"name":"Friday","description":"From room ABCD4679","tracks":[{"id":"1440818839","type":"songs"},{"id":"1440818840","type":"songs"}]}"#
+        );
+    }
+
+    /// Cider reporting the given positions (in seconds) on successive now-playing reads, then the last one
+    async fn stub_positions(positions: &[f64]) -> u16 {
+        let positions = std::sync::Mutex::new(positions.to_vec());
+        let (port, _) = stub_cider_with(move |request_line| {
+            if !request_line.contains("/now-playing") {
+                return None;
+            }
+            let mut positions = positions.lock().unwrap();
+            let position = if positions.len() > 1 { positions.remove(0) } else { positions[0] };
+            Some(format!(
+                r#"{{"status":"ok","info":{{"playParams":{{"id":"1","kind":"song"}},"name":"Song","artistName":"Artist","albumName":"Album","artwork":{{"width":600,"height":600,"url":""}},"durationInMillis":200000,"currentPlaybackTime":{}}}}}"#,
+                position
+            ))
+        })
+        .await;
+        port
+    }
+
+    #[tokio::test]
+    async fn test_seek_confirmed() {
+        // Cider keeps reporting the old position for a few reads
+        let client = CiderClient::with_port(stub_positions(&[10.0, 10.05, 10.1, 60.02]).await);
+
+        let confirmation = client.seek_confirmed(60_000).await.unwrap();
+        assert_eq!(confirmation.position_ms, 60_020);
+        assert!(confirmation.settle_time >= SEEK_CONFIRM_POLL_INTERVAL * 3);
+    }
+
+    #[tokio::test]
+    async fn test_seek_not_confirmed() {
+        let client = CiderClient::with_port(stub_positions(&[10.0]).await);
+
+        let result = client.seek_confirmed(60_000).await;
+        assert!(matches!(result, Err(CiderError::SeekNotConfirmed(60_000))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_queue_items() {
+        let json = r#"[
+            {"id": "1440818839", "type": "songs", "attributes": {"name": "Song", "artistName": "Artist", "durationInMillis": 200000}},
+            {"id": "1440818840", "type": "songs"}
+        ]"#;
+        let items: Vec<QueueItem> = serde_json::from_str::<Vec<RawQueueItem>>(json)
+            .unwrap()
+            .into_iter()
+            .map(QueueItem::from)
+            .collect();
+
+        assert_eq!(
+            items[0],
+            QueueItem {
+                id: "1440818839".to_string(),
+                name: "Song".to_string(),
+                artist: "Artist".to_string(),
+                duration_ms: 200000,
+            }
+        );
+        assert_eq!(items[1].id, "1440818840");
+        assert_eq!(items[1].name, "");
+    }
+
+    #[test]
+    fn test_parse_now_playing() {
+        let body = r#"{"status":"ok","info":{"playParams":{"id":"1440818839","kind":"song"},"name":"Song","artistName":"Artist","albumName":"Album","artwork":{"width":600,"height":600,"url":"https://example.com/{w}x{h}bb.jpg"},"durationInMillis":200000,"currentPlaybackTime":12.5}}"#;
+        let np = parse_now_playing(body).unwrap().unwrap();
+        assert_eq!(np.song_id(), Some("1440818839"));
+        assert_eq!(np.current_position_ms(), 12500);
+
+        assert!(parse_now_playing(r#"{"status":"ok","info":{}}"#).unwrap().is_none());
+        assert!(parse_now_playing(r#"{"status":"ok"}"#).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_now_playing_schema_drift() {
+        // Artwork reshaped, as a Cider update might
+        let body = r#"{"status":"ok","info":{"name":"Song","artistName":"Artist","albumName":"Album","artwork":"https://example.com/art.jpg","durationInMillis":200000}}"#;
+        match parse_now_playing(body) {
+            Err(CiderError::UnexpectedSchema(e)) => assert!(e.path().to_string().contains("artwork"), "path {}", e.path()),
+            other => panic!("expected a schema error, got {:?}", other),
+        }
+
+        assert!(matches!(parse_now_playing("<html>"), Err(CiderError::UnexpectedSchema(_))));
+    }
+
+    #[test]
+    fn test_search_results() {
+        let json = r#"{"data": {"results": {
+            "albums": {"href": "", "data": [{"id": "1440818800", "type": "albums", "attributes": {"name": "Album", "artistName": "Artist"}}]},
+            "songs": {"href": "", "data": [{"id": "1440818839", "type": "songs", "attributes": {
+                "name": "Song", "artistName": "Artist", "albumName": "Album", "durationInMillis": 200000,
+                "isrc": "USUM71900001", "artwork": {"width": 3000, "height": 3000, "url": "https://example.com/{w}x{h}bb.jpg"}
+            }}]}
+        }}}"#;
+        let resp: AmApiResponse<SearchResponse> = serde_json::from_str(json).unwrap();
+        let results = search_results(resp.data, &["songs", "albums"]);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].kind, "songs");
+        assert_eq!(results[0].isrc.as_deref(), Some("USUM71900001"));
+        assert_eq!(results[0].artwork_url.as_deref(), Some("https://example.com/600x600bb.jpg"));
+        assert_eq!(results[1].kind, "albums");
+        assert_eq!(results[1].duration_ms, None);
+    }
 }
@@ -0,0 +1,251 @@
+//! Cider playback events over Socket.IO
+//!
+//! Cider pushes playback changes to Socket.IO clients as `API:Playback`
+//! events. This speaks Engine.IO's long-polling transport through the same
+//! HTTP client as the REST API: Cider holds each poll open until it has
+//! packets for us, so events arrive as they happen.
+
+use std::time::Duration;
+
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use super::client::{CiderClient, CiderError};
+use super::types::NowPlaying;
+
+/// Socket.IO event carrying playback changes
+const PLAYBACK_EVENT: &str = "API:Playback";
+
+/// Separates packets in a polling response
+const RECORD_SEPARATOR: char = '\u{1e}';
+
+/// A playback change reported by Cider
+#[derive(Debug, Clone)]
+pub enum PlaybackEvent {
+    /// Another item started (None if the payload isn't a complete track; fetch it with `now_playing`)
+    ItemChanged(Option<Box<NowPlaying>>),
+    /// Playback was started or paused
+    StateChanged { is_playing: bool },
+    /// Position update (sent several times a second while playing)
+    TimeChanged { position_ms: u64, is_playing: bool },
+}
+
+/// Engine.IO session parameters from the handshake
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Handshake {
+    sid: String,
+    ping_interval: u64,
+    ping_timeout: u64,
+}
+
+/// An Engine.IO packet we act on
+#[derive(Debug)]
+enum Packet {
+    Ping,
+    Close,
+    Playback(PlaybackEvent),
+    Ignored,
+}
+
+/// Live stream of Cider playback events
+///
+/// Ends (`next` returns None) when the session with Cider is lost, e.g.
+/// because Cider was closed.
+pub struct CiderEventStream {
+    events: mpsc::UnboundedReceiver<PlaybackEvent>,
+    task: JoinHandle<()>,
+}
+
+impl CiderEventStream {
+    /// Open a Socket.IO session with Cider and start receiving events
+    pub async fn connect(client: &CiderClient) -> Result<Self, CiderError> {
+        let body = client
+            .socket_request(Method::GET, None)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let handshake: Handshake = body
+            .split(RECORD_SEPARATOR)
+            .next()
+            .and_then(|packet| packet.strip_prefix('0'))
+            .and_then(|json| serde_json::from_str(json).ok())
+            .ok_or_else(|| CiderError::Api(format!("Unexpected Socket.IO handshake: {}", body)))?;
+
+        // Join the default namespace
+        client
+            .socket_request(Method::POST, Some(&handshake.sid))
+            .body("40")
+            .send()
+            .await?
+            .error_for_status()?;
+        debug!("Cider event stream connected (sid {})", handshake.sid);
+
+        let (tx, events) = mpsc::unbounded_channel();
+        let task = tokio::spawn(poll_events(client.clone(), handshake, tx));
+        Ok(Self { events, task })
+    }
+
+    /// Wait for the next playback event
+    pub async fn next(&mut self) -> Option<PlaybackEvent> {
+        self.events.recv().await
+    }
+}
+
+impl Drop for CiderEventStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Long-poll Cider until the session ends or the stream is dropped
+async fn poll_events(client: CiderClient, handshake: Handshake, tx: mpsc::UnboundedSender<PlaybackEvent>) {
+    // Cider answers every poll within a ping interval, if only with a ping
+    let poll_timeout = Duration::from_millis(handshake.ping_interval + handshake.ping_timeout);
+
+    loop {
+        let body = async {
+            client
+                .socket_request(Method::GET, Some(&handshake.sid))
+                .timeout(poll_timeout)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await
+        }
+        .await;
+        let body = match body {
+            Ok(body) => body,
+            Err(e) => {
+                debug!("Cider event stream lost: {}", e);
+                return;
+            }
+        };
+
+        for packet in body.split(RECORD_SEPARATOR) {
+            match parse_packet(packet) {
+                Packet::Ping => {
+                    let pong = client.socket_request(Method::POST, Some(&handshake.sid)).body("3").send().await;
+                    if let Err(e) = pong {
+                        debug!("Failed to answer Cider ping: {}", e);
+                        return;
+                    }
+                }
+                Packet::Close => {
+                    debug!("Cider closed the event stream");
+                    return;
+                }
+                Packet::Playback(event) => {
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+                Packet::Ignored => {}
+            }
+        }
+    }
+}
+
+/// Decode one Engine.IO packet (Socket.IO packets are Engine.IO messages, type 4)
+fn parse_packet(packet: &str) -> Packet {
+    match packet {
+        "2" => return Packet::Ping,
+        "1" => return Packet::Close,
+        _ => {}
+    }
+
+    // Namespace disconnect or refused connect
+    if packet.starts_with("41") || packet.starts_with("44") {
+        warn!("Cider refused the event stream: {}", packet);
+        return Packet::Close;
+    }
+
+    // Events are `42[name, payload]`, with an optional ack ID before the array
+    let Some(args) = packet.strip_prefix("42") else {
+        return Packet::Ignored;
+    };
+    let args = args.trim_start_matches(|c: char| c.is_ascii_digit());
+    let Ok(Value::Array(args)) = serde_json::from_str::<Value>(args) else {
+        return Packet::Ignored;
+    };
+    match args.as_slice() {
+        [Value::String(name), payload, ..] if name == PLAYBACK_EVENT => {
+            playback_event(payload).map_or(Packet::Ignored, Packet::Playback)
+        }
+        _ => Packet::Ignored,
+    }
+}
+
+/// Decode an `API:Playback` payload (`{"type": ..., "data": ...}`)
+fn playback_event(payload: &Value) -> Option<PlaybackEvent> {
+    let data = payload.get("data")?;
+    match payload.get("type")?.as_str()? {
+        "playbackStatus.nowPlayingItemDidChange" => Some(PlaybackEvent::ItemChanged(
+            serde_json::from_value(data.clone()).ok().map(Box::new),
+        )),
+        "playbackStatus.playbackStateDidChange" => Some(PlaybackEvent::StateChanged {
+            is_playing: data.get("state")?.as_str()? == "playing",
+        }),
+        "playbackStatus.playbackTimeDidChange" => Some(PlaybackEvent::TimeChanged {
+            position_ms: (data.get("currentPlaybackTime")?.as_f64()? * 1000.0) as u64,
+            is_playing: data.get("isPlaying")?.as_bool()?,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_packets() {
+        assert!(matches!(parse_packet("2"), Packet::Ping));
+        assert!(matches!(parse_packet("1"), Packet::Close));
+        assert!(matches!(parse_packet("44{\"message\":\"Not authorized\"}"), Packet::Close));
+        assert!(matches!(parse_packet("40{\"sid\":\"abc\"}"), Packet::Ignored));
+        assert!(matches!(parse_packet("6"), Packet::Ignored));
+    }
+
+    #[test]
+    fn test_playback_events() {
+        let time = r#"42["API:Playback",{"type":"playbackStatus.playbackTimeDidChange","data":{"currentPlaybackDuration":200,"currentPlaybackTime":12.5,"currentPlaybackTimeRemaining":187.5,"isPlaying":true}}]"#;
+        assert!(matches!(
+            parse_packet(time),
+            Packet::Playback(PlaybackEvent::TimeChanged { position_ms: 12500, is_playing: true })
+        ));
+
+        let paused = r#"42["API:Playback",{"type":"playbackStatus.playbackStateDidChange","data":{"state":"paused","attributes":{}}}]"#;
+        assert!(matches!(
+            parse_packet(paused),
+            Packet::Playback(PlaybackEvent::StateChanged { is_playing: false })
+        ));
+
+        let item = r#"42["API:Playback",{"type":"playbackStatus.nowPlayingItemDidChange","data":{"playParams":{"id":"1440818839","kind":"song"},"name":"Song","artistName":"Artist","albumName":"Album","artwork":{"width":600,"height":600,"url":"https://example.com/{w}x{h}bb.jpg"},"durationInMillis":200000}}]"#;
+        match parse_packet(item) {
+            Packet::Playback(PlaybackEvent::ItemChanged(Some(np))) => assert_eq!(np.song_id(), Some("1440818839")),
+            other => panic!("unexpected packet {:?}", other),
+        }
+
+        // Partial items still signal the change
+        let partial = r#"42["API:Playback",{"type":"playbackStatus.nowPlayingItemDidChange","data":{"name":"Song"}}]"#;
+        assert!(matches!(parse_packet(partial), Packet::Playback(PlaybackEvent::ItemChanged(None))));
+    }
+
+    #[test]
+    fn test_other_events_ignored() {
+        assert!(matches!(parse_packet(r#"42["API:Lyrics",{"type":"x","data":{}}]"#), Packet::Ignored));
+        assert!(matches!(
+            parse_packet(r#"42["API:Playback",{"type":"playerStatus.volumeDidChange","data":0.5}]"#),
+            Packet::Ignored
+        ));
+        assert!(matches!(parse_packet("42not json"), Packet::Ignored));
+    }
+}
@@ -0,0 +1,339 @@
+//! Scripted stand-in for Cider in tests
+//!
+//! Tracks can be scheduled to start at given times (like the user picking
+//! songs on the host), `play_item` loads tracks from a small catalog, and
+//! seeks can be given a buffering latency during which the position doesn't
+//! advance, like Cider's after a seek. Searches look through the catalog.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::api::CiderApi;
+use super::client::CiderError;
+use super::types::{Artwork, NowPlaying, PlayParams, SearchResult};
+
+/// A call made to the mock
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockCall {
+    PlayItem(String),
+    Play,
+    Pause,
+    Seek(u64),
+}
+
+#[derive(Debug)]
+struct MockState {
+    /// Tracks `play_item` can load
+    catalog: HashMap<String, NowPlaying>,
+    /// Tracks to switch to, by start time
+    timeline: Vec<(Instant, NowPlaying)>,
+    current: Option<NowPlaying>,
+    playing: bool,
+    /// Position at `anchor`
+    position_ms: u64,
+    /// When the position starts advancing from `position_ms` (in the future while a seek buffers)
+    anchor: Instant,
+    seek_latency: Duration,
+    storefront: String,
+    calls: Vec<MockCall>,
+}
+
+impl MockState {
+    fn position_ms(&self) -> u64 {
+        let now = Instant::now();
+        if self.playing && now > self.anchor {
+            self.position_ms + (now - self.anchor).as_millis() as u64
+        } else {
+            self.position_ms
+        }
+    }
+
+    fn set_position(&mut self, position_ms: u64, delay: Duration) {
+        self.position_ms = position_ms;
+        self.anchor = Instant::now() + delay;
+    }
+
+    /// Start scheduled tracks that are due
+    fn advance(&mut self) {
+        let now = Instant::now();
+        while self.timeline.first().is_some_and(|(at, _)| *at <= now) {
+            let (at, track) = self.timeline.remove(0);
+            self.current = Some(track);
+            self.playing = true;
+            self.position_ms = 0;
+            self.anchor = at;
+        }
+    }
+}
+
+/// In-memory Cider for sync tests (clones share state)
+#[derive(Debug, Clone)]
+pub struct MockCider {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockCider {
+    /// Nothing playing, empty catalog, instant seeks, US storefront
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                catalog: HashMap::new(),
+                timeline: Vec::new(),
+                current: None,
+                playing: false,
+                position_ms: 0,
+                anchor: Instant::now(),
+                seek_latency: Duration::ZERO,
+                storefront: "us".to_string(),
+                calls: Vec::new(),
+            })),
+        }
+    }
+
+    /// A song with the given ID
+    pub fn track(id: &str, duration_ms: u64) -> NowPlaying {
+        NowPlaying {
+            play_params: Some(PlayParams {
+                id: id.to_string(),
+                kind: "song".to_string(),
+            }),
+            name: format!("Song {}", id),
+            artist_name: "Artist".to_string(),
+            album_name: "Album".to_string(),
+            artwork: Artwork {
+                width: 600,
+                height: 600,
+                url: String::new(),
+            },
+            duration_in_millis: duration_ms,
+            current_playback_time: 0.0,
+            remaining_time: 0.0,
+            genre_names: Vec::new(),
+            track_number: 1,
+            release_date: None,
+            has_lyrics: false,
+            in_favorites: false,
+            in_library: false,
+            shuffle_mode: 0,
+            repeat_mode: 0,
+            url: None,
+            isrc: None,
+            content_rating: None,
+        }
+    }
+
+    /// Tracks `play_item` can load (others fail, like tracks missing from the storefront)
+    pub fn with_catalog(self, tracks: impl IntoIterator<Item = NowPlaying>) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            for track in tracks {
+                let id = track.song_id().unwrap_or_default().to_string();
+                state.catalog.insert(id, track);
+            }
+        }
+        self
+    }
+
+    /// Be playing `track` at `position_ms`
+    pub fn with_playing(self, track: NowPlaying, position_ms: u64) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.current = Some(track);
+            state.playing = true;
+            state.set_position(position_ms, Duration::ZERO);
+        }
+        self
+    }
+
+    /// Switch to `track` `after` from now, as if the user picked it
+    pub fn with_scheduled(self, after: Duration, track: NowPlaying) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.timeline.push((Instant::now() + after, track));
+            state.timeline.sort_by_key(|(at, _)| *at);
+        }
+        self
+    }
+
+    /// How long the position stays put after a seek (Cider buffering the new position)
+    pub fn with_seek_latency(self, latency: Duration) -> Self {
+        self.state.lock().unwrap().seek_latency = latency;
+        self
+    }
+
+    /// Use another storefront
+    pub fn with_storefront(self, storefront: &str) -> Self {
+        self.state.lock().unwrap().storefront = storefront.to_string();
+        self
+    }
+
+    /// Calls made so far
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Current playback position
+    pub fn position_ms(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.advance();
+        state.position_ms()
+    }
+}
+
+impl Default for MockCider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CiderApi for MockCider {
+    async fn now_playing(&self) -> Result<Option<NowPlaying>, CiderError> {
+        let mut state = self.state.lock().unwrap();
+        state.advance();
+        let position_ms = state.position_ms();
+        Ok(state.current.clone().map(|mut np| {
+            np.current_playback_time = position_ms as f64 / 1000.0;
+            np
+        }))
+    }
+
+    async fn is_playing(&self) -> Result<bool, CiderError> {
+        let mut state = self.state.lock().unwrap();
+        state.advance();
+        Ok(state.playing)
+    }
+
+    async fn play(&self) -> Result<(), CiderError> {
+        let mut state = self.state.lock().unwrap();
+        state.advance();
+        state.calls.push(MockCall::Play);
+        if !state.playing {
+            let position_ms = state.position_ms();
+            state.playing = true;
+            state.set_position(position_ms, Duration::ZERO);
+        }
+        Ok(())
+    }
+
+    async fn pause(&self) -> Result<(), CiderError> {
+        let mut state = self.state.lock().unwrap();
+        state.advance();
+        state.calls.push(MockCall::Pause);
+        let position_ms = state.position_ms();
+        state.playing = false;
+        state.set_position(position_ms, Duration::ZERO);
+        Ok(())
+    }
+
+    async fn seek_ms(&self, position_ms: u64) -> Result<(), CiderError> {
+        let mut state = self.state.lock().unwrap();
+        state.advance();
+        state.calls.push(MockCall::Seek(position_ms));
+        let latency = state.seek_latency;
+        state.set_position(position_ms, latency);
+        Ok(())
+    }
+
+    async fn play_item(&self, _item_type: &str, id: &str) -> Result<(), CiderError> {
+        let mut state = self.state.lock().unwrap();
+        state.advance();
+        state.calls.push(MockCall::PlayItem(id.to_string()));
+        let track = state
+            .catalog
+            .get(id)
+            .cloned()
+            .ok_or_else(|| CiderError::Api(format!("{} is not available", id)))?;
+        state.current = Some(track);
+        state.playing = true;
+        state.set_position(0, Duration::ZERO);
+        Ok(())
+    }
+
+    async fn search(&self, term: &str, _types: &[&str]) -> Result<Vec<SearchResult>, CiderError> {
+        let term = term.to_lowercase();
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .catalog
+            .values()
+            .filter(|np| {
+                let text = format!("{} {}", np.name, np.artist_name).to_lowercase();
+                term.split_whitespace().all(|word| text.contains(word))
+            })
+            .map(search_result)
+            .collect())
+    }
+
+    async fn storefront(&self) -> Result<String, CiderError> {
+        Ok(self.state.lock().unwrap().storefront.clone())
+    }
+
+    async fn songs_by_isrc(&self, isrc: &str) -> Result<Vec<SearchResult>, CiderError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .catalog
+            .values()
+            .filter(|np| np.isrc.as_deref() == Some(isrc))
+            .map(search_result)
+            .collect())
+    }
+}
+
+/// A catalog track as a search result
+fn search_result(np: &NowPlaying) -> SearchResult {
+    SearchResult {
+        id: np.song_id().unwrap_or_default().to_string(),
+        kind: "songs".to_string(),
+        name: np.name.clone(),
+        artist: np.artist_name.clone(),
+        album: Some(np.album_name.clone()),
+        duration_ms: Some(np.duration_in_millis),
+        isrc: np.isrc.clone(),
+        artwork_url: None,
+        explicit: np.is_explicit(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_seek_latency() {
+        let cider = MockCider::new()
+            .with_playing(MockCider::track("1", 200_000), 10_000)
+            .with_seek_latency(Duration::from_millis(100));
+
+        cider.seek_ms(60_000).await.unwrap();
+        // Buffering: the position holds at the target
+        assert_eq!(cider.position_ms(), 60_000);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let position = cider.position_ms();
+        assert!((60_040..60_200).contains(&position), "position {}", position);
+
+        cider.pause().await.unwrap();
+        let paused_at = cider.position_ms();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(cider.position_ms(), paused_at);
+        assert_eq!(cider.calls(), vec![MockCall::Seek(60_000), MockCall::Pause]);
+    }
+
+    #[tokio::test]
+    async fn test_timeline_and_catalog() {
+        let cider = MockCider::new()
+            .with_catalog([MockCider::track("2", 180_000)])
+            .with_scheduled(Duration::from_millis(50), MockCider::track("3", 240_000));
+        assert!(cider.now_playing().await.unwrap().is_none());
+
+        cider.play_item("songs", "2").await.unwrap();
+        assert_eq!(cider.now_playing().await.unwrap().unwrap().song_id(), Some("2"));
+        assert!(cider.play_item("songs", "404").await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let np = cider.now_playing().await.unwrap().unwrap();
+        assert_eq!(np.song_id(), Some("3"));
+        assert!(cider.is_playing().await.unwrap());
+    }
+}
@@ -1,9 +1,18 @@
 //! Cider API Client
 //!
-//! This module provides a client for interacting with Cider's REST API.
+//! This module provides a client for interacting with Cider's REST API
+//! and a stream of its real-time playback events.
 
+mod api;
+mod cache;
 mod client;
+mod events;
+#[cfg(any(test, feature = "bench-internals"))]
+pub mod mock;
+mod throttle;
 mod types;
 
-pub use client::{CiderClient, CiderError};
+pub use api::CiderApi;
+pub use client::{CiderClient, CiderError, CANDIDATE_PORTS, DEFAULT_HOST};
+pub use events::{CiderEventStream, PlaybackEvent};
 pub use types::*;
@@ -0,0 +1,92 @@
+//! Pacing of Cider API calls
+//!
+//! The host loop, the UI's polling and the sync handlers all talk to Cider at
+//! once, and a single heartbeat can fire a seek, two reads and a play/pause
+//! within milliseconds. Spacing out calls to each endpoint keeps these bursts
+//! from stampeding Cider's local server.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Per-endpoint rate limiter: calls to the same endpoint are at least `interval` apart
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    /// Earliest time the next call to each endpoint may go out
+    next_slot: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_slot: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait for the next free slot on `endpoint` and take it
+    pub async fn acquire(&self, endpoint: &str) {
+        let slot = {
+            let mut slots = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = slots.get(endpoint).map_or(now, |&next| next.max(now));
+            slots.insert(endpoint.to_string(), slot + self.interval);
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Wait until `endpoint` has a free slot, without taking it
+    ///
+    /// Lets a caller check whether its call is still wanted (e.g. a newer seek
+    /// arrived meanwhile) before acquiring.
+    pub async fn wait_idle(&self, endpoint: &str) {
+        let slot = self.next_slot.lock().unwrap().get(endpoint).copied();
+        if let Some(slot) = slot {
+            tokio::time::sleep_until(slot).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(50);
+
+    #[tokio::test]
+    async fn test_paces_same_endpoint() {
+        let limiter = RateLimiter::new(INTERVAL);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire("/seek").await;
+        }
+        assert!(start.elapsed() >= INTERVAL * 2);
+    }
+
+    #[tokio::test]
+    async fn test_endpoints_independent() {
+        let limiter = RateLimiter::new(INTERVAL);
+        let start = Instant::now();
+        limiter.acquire("/seek").await;
+        limiter.acquire("/now-playing").await;
+        limiter.acquire("/is-playing").await;
+        assert!(start.elapsed() < INTERVAL);
+    }
+
+    #[tokio::test]
+    async fn test_wait_idle_keeps_slot() {
+        let limiter = RateLimiter::new(INTERVAL);
+        limiter.acquire("/seek").await;
+
+        let start = Instant::now();
+        limiter.wait_idle("/seek").await;
+        assert!(start.elapsed() >= INTERVAL - Duration::from_millis(5));
+        // The slot is still free for the caller
+        let start = Instant::now();
+        limiter.acquire("/seek").await;
+        assert!(start.elapsed() < INTERVAL);
+    }
+}
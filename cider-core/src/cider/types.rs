@@ -1,5 +1,7 @@
 //! Types for Cider API responses
 
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 
 /// Response wrapper for most Cider API endpoints
@@ -19,6 +21,16 @@ pub struct Artwork {
     pub url: String,
 }
 
+impl Artwork {
+    /// Artwork URL at `size`x`size` pixels
+    pub fn url_for_size(&self, size: u32) -> String {
+        self.url
+            .replace("{w}", &size.to_string())
+            .replace("{h}", &size.to_string())
+            .replace("/{w}x{h}", &format!("/{}x{}", size, size))
+    }
+}
+
 /// Currently playing track information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -85,6 +97,14 @@ pub struct NowPlaying {
     /// Apple Music URL
     #[serde(default)]
     pub url: Option<String>,
+
+    /// ISRC (identifies the recording across storefronts)
+    #[serde(default)]
+    pub isrc: Option<String>,
+
+    /// Content rating ("explicit", "clean", or none)
+    #[serde(default)]
+    pub content_rating: Option<String>,
 }
 
 impl NowPlaying {
@@ -100,11 +120,12 @@ impl NowPlaying {
 
     /// Get the full-resolution artwork URL
     pub fn artwork_url(&self, size: u32) -> String {
-        self.artwork
-            .url
-            .replace("{w}", &size.to_string())
-            .replace("{h}", &size.to_string())
-            .replace("/{w}x{h}", &format!("/{}x{}", size, size))
+        self.artwork.url_for_size(size)
+    }
+
+    /// Whether the track is marked explicit
+    pub fn is_explicit(&self) -> bool {
+        self.content_rating.as_deref() == Some("explicit")
     }
 }
 
@@ -123,6 +144,15 @@ pub struct PlaybackState {
     pub timestamp: u64,
 }
 
+/// A seek Cider has applied (see `CiderClient::seek_confirmed`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeekConfirmation {
+    /// Position Cider reported once the seek showed up
+    pub position_ms: u64,
+    /// Time from sending the seek until Cider reported the new position
+    pub settle_time: Duration,
+}
+
 /// Response for is-playing endpoint
 #[derive(Debug, Clone, Deserialize)]
 pub struct IsPlayingResponse {
@@ -141,6 +171,13 @@ pub struct VolumeResponse {
     pub volume: f32,
 }
 
+/// Response for playback-rate endpoint
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackRateResponse {
+    pub playback_rate: f64,
+}
+
 /// Response for repeat-mode endpoint
 #[derive(Debug, Clone, Deserialize)]
 pub struct RepeatModeResponse {
@@ -159,6 +196,166 @@ pub struct AutoplayResponse {
     pub value: bool,
 }
 
+/// An item in Cider's playback queue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueItem {
+    /// Apple Music ID
+    pub id: String,
+    pub name: String,
+    pub artist: String,
+    pub duration_ms: u64,
+}
+
+/// Queue item as returned by the queue endpoint (a MusicKit media item)
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawQueueItem {
+    pub id: String,
+    #[serde(default)]
+    pub attributes: QueueItemAttributes,
+}
+
+/// Attributes of a queue item
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueItemAttributes {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub artist_name: String,
+    #[serde(default)]
+    pub duration_in_millis: u64,
+}
+
+impl From<RawQueueItem> for QueueItem {
+    fn from(item: RawQueueItem) -> Self {
+        Self {
+            id: item.id,
+            name: item.attributes.name,
+            artist: item.attributes.artist_name,
+            duration_ms: item.attributes.duration_in_millis,
+        }
+    }
+}
+
+/// A catalog search result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    /// Apple Music ID
+    pub id: String,
+    /// Item type, e.g. "songs" or "albums" (as accepted by `play_item`)
+    pub kind: String,
+    pub name: String,
+    /// Artist name (empty for artists themselves)
+    pub artist: String,
+    pub album: Option<String>,
+    pub duration_ms: Option<u64>,
+    pub isrc: Option<String>,
+    pub artwork_url: Option<String>,
+    /// Marked explicit in the catalog
+    pub explicit: bool,
+}
+
+/// Apple Music API response, wrapped by Cider's run-v3 proxy
+#[derive(Debug, Clone, Deserialize)]
+pub struct AmApiResponse<T> {
+    pub data: T,
+}
+
+/// Catalog search response
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchResponse {
+    #[serde(default)]
+    pub results: std::collections::HashMap<String, SearchResultSet>,
+}
+
+/// Results of one type
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchResultSet {
+    pub data: Vec<CatalogResource>,
+}
+
+/// An Apple Music catalog resource
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatalogResource {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub attributes: CatalogAttributes,
+}
+
+/// Catalog resource attributes (the subset shared by songs, albums and artists)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogAttributes {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub artist_name: String,
+    pub album_name: Option<String>,
+    pub duration_in_millis: Option<u64>,
+    pub isrc: Option<String>,
+    pub artwork: Option<Artwork>,
+    pub content_rating: Option<String>,
+}
+
+impl From<CatalogResource> for SearchResult {
+    fn from(resource: CatalogResource) -> Self {
+        let attributes = resource.attributes;
+        Self {
+            id: resource.id,
+            kind: resource.kind,
+            name: attributes.name,
+            artist: attributes.artist_name,
+            album: attributes.album_name,
+            duration_ms: attributes.duration_in_millis,
+            isrc: attributes.isrc,
+            artwork_url: attributes.artwork.map(|a| a.url_for_size(600)),
+            explicit: attributes.content_rating.as_deref() == Some("explicit"),
+        }
+    }
+}
+
+/// Storefront list (`/v1/me/storefront`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorefrontResponse {
+    pub data: Vec<Storefront>,
+}
+
+/// A storefront (country) of the user's account
+#[derive(Debug, Clone, Deserialize)]
+pub struct Storefront {
+    pub id: String,
+}
+
+/// Account details (`/v1/me/account?meta=restrictions`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountResponse {
+    #[serde(default)]
+    pub meta: AccountMeta,
+}
+
+/// Account metadata
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AccountMeta {
+    pub restrictions: Option<AccountRestrictions>,
+}
+
+/// Content restrictions set on the account (e.g. by Screen Time)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountRestrictions {
+    /// Explicit content can't be played
+    #[serde(default)]
+    pub explicit: bool,
+}
+
+/// Request body for Cider's Apple Music API proxy
+#[derive(Debug, Clone, Serialize)]
+pub struct AmApiRequest {
+    pub path: String,
+}
+
 /// Request body for play-url endpoint
 #[derive(Debug, Clone, Serialize)]
 pub struct PlayUrlRequest {
@@ -173,6 +370,20 @@ pub struct PlayItemRequest {
     pub id: String,
 }
 
+/// Request body for queue move-to-position endpoint
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueMoveRequest {
+    pub start_index: usize,
+    pub destination_index: usize,
+}
+
+/// Request body for queue remove-by-index endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueRemoveRequest {
+    pub index: usize,
+}
+
 /// Request body for seek endpoint
 #[derive(Debug, Clone, Serialize)]
 pub struct SeekRequest {
@@ -185,8 +396,38 @@ pub struct VolumeRequest {
     pub volume: f32,
 }
 
+/// Request body for playback-rate endpoint
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackRateRequest {
+    pub playback_rate: f64,
+}
+
 /// Request body for rating endpoint
 #[derive(Debug, Clone, Serialize)]
 pub struct RatingRequest {
     pub rating: i8,
 }
+
+/// Request body for creating a library playlist
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatePlaylistRequest {
+    pub name: String,
+    pub description: String,
+    pub tracks: Vec<PlaylistTrack>,
+}
+
+/// A catalog item to add to a playlist
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaylistTrack {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// Response for creating a library playlist
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatePlaylistResponse {
+    /// Library playlist ID, e.g. "p.ZOAXxWZF4EV2GO"
+    pub id: String,
+}
@@ -0,0 +1,85 @@
+//! Wall clock for sync timestamps
+//!
+//! Heartbeats and latency probes carry our wall clock, in milliseconds since
+//! the UNIX epoch. The sync engine reads it through a `Clock` it's given, so
+//! tests can hand a peer a simulated one: one that starts at a fixed time,
+//! advances with tokio's (possibly paused) clock, and is skewed from the
+//! other peers' by a set amount.
+
+/// Milliseconds since the UNIX epoch, on the system clock
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Where a peer reads its wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    #[cfg(test)]
+    Simulated(SimulatedClock),
+}
+
+impl Clock {
+    /// Milliseconds since the UNIX epoch
+    pub fn now_ms(&self) -> u64 {
+        match self {
+            Self::System => now_ms(),
+            #[cfg(test)]
+            Self::Simulated(clock) => clock.now_ms(),
+        }
+    }
+}
+
+/// A peer's wall clock in a simulation
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+pub struct SimulatedClock {
+    /// Wall clock at `started`, before skew
+    epoch_ms: u64,
+    started: tokio::time::Instant,
+    /// How far this clock is ahead of the true time (negative if behind)
+    skew_ms: i64,
+}
+
+#[cfg(test)]
+impl SimulatedClock {
+    /// A clock reading `epoch_ms` plus `skew_ms` now
+    pub fn new(epoch_ms: u64, skew_ms: i64) -> Self {
+        Self {
+            epoch_ms,
+            started: tokio::time::Instant::now(),
+            skew_ms,
+        }
+    }
+
+    /// The same clock, skewed by `skew_ms` instead
+    pub fn skewed(self, skew_ms: i64) -> Self {
+        Self { skew_ms, ..self }
+    }
+
+    pub fn now_ms(&self) -> u64 {
+        let elapsed_ms = self.started.elapsed().as_millis() as i64;
+        (self.epoch_ms as i64 + elapsed_ms + self.skew_ms) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_simulated_clock() {
+        let clock = SimulatedClock::new(1_000_000, 0);
+        let skewed = clock.skewed(-2_000);
+        tokio::time::sleep(Duration::from_millis(1_500)).await;
+
+        assert_eq!(Clock::Simulated(clock).now_ms(), 1_001_500);
+        assert_eq!(Clock::Simulated(skewed).now_ms(), 999_500);
+        assert!(Clock::System.now_ms() > 1_600_000_000_000);
+    }
+}
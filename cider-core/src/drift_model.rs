@@ -0,0 +1,226 @@
+//! Drift model for listener sync
+//!
+//! Checking each heartbeat's drift against the threshold corrects late: a
+//! listener drifting steadily (its clock or Cider running slightly fast or
+//! slow) is only re-synced a heartbeat after it went past the threshold, and
+//! a single noisy measurement can trigger a seek on its own.
+//!
+//! This module tracks drift and how fast it's changing with an alpha-beta
+//! filter (a steady-state Kalman filter for a constant rate), which smooths
+//! out measurement noise and predicts when the threshold will be crossed, so
+//! the listener can re-sync once, just before it is.
+
+use std::time::{Duration, Instant};
+
+/// Weight of a new measurement in the drift estimate (0.0-1.0)
+const ALPHA: f64 = 0.5;
+
+/// Weight of a new measurement in the rate estimate (0.0-1.0)
+/// Lower than `ALPHA` so the rate follows trends rather than noise
+const BETA: f64 = 0.1;
+
+/// EMA smoothing factor for the time between measurements
+const INTERVAL_ALPHA: f64 = 0.3;
+
+/// A measurement this far from the prediction is a jump (a seek, a stall),
+/// not drift: the model starts over from it (ms)
+const JUMP_MS: f64 = 500.0;
+
+/// Measurements this far apart don't follow each other (paused, suspended)
+const MAX_GAP: Duration = Duration::from_secs(10);
+
+/// Measurements closer together than this only refine the drift: over so
+/// short a time, noise would swamp the rate
+const MIN_RATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Measurements needed before the rate is trusted for predictions
+const MIN_SAMPLES_FOR_PREDICTION: u32 = 4;
+
+/// Drift and its rate of change, estimated from measurements
+#[derive(Debug, Clone, Default)]
+pub struct DriftModel {
+    /// Estimated drift (ms, positive = ahead of host)
+    drift_ms: f64,
+    /// Estimated drift rate (ms per second)
+    rate_ms_per_sec: f64,
+    /// Smoothed time between measurements
+    interval: Option<Duration>,
+    /// When the last measurement was taken
+    last_at: Option<Instant>,
+    /// Measurements since the model last started over
+    sample_count: u32,
+}
+
+impl DriftModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start over (after correcting the drift, or when not playing)
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Add the drift measured at `at`; returns the new drift estimate
+    pub fn observe(&mut self, drift_ms: i64, at: Instant) -> i64 {
+        let measured = drift_ms as f64;
+        let elapsed = self.last_at.map(|last| at.saturating_duration_since(last));
+
+        match elapsed {
+            Some(elapsed) if elapsed < MIN_RATE_INTERVAL => {
+                let residual = measured - self.drift_ms;
+                if residual.abs() > JUMP_MS {
+                    self.start_from(measured);
+                } else {
+                    self.drift_ms += ALPHA * residual;
+                }
+                // Still measured from the last measurement that updated the rate
+                return self.drift_ms.round() as i64;
+            }
+            Some(elapsed) if elapsed <= MAX_GAP => {
+                let dt = elapsed.as_secs_f64();
+                let predicted = self.drift_ms + self.rate_ms_per_sec * dt;
+                let residual = measured - predicted;
+                if residual.abs() > JUMP_MS {
+                    self.start_from(measured);
+                } else {
+                    self.drift_ms = predicted + ALPHA * residual;
+                    self.rate_ms_per_sec += BETA * residual / dt;
+                    self.sample_count += 1;
+                }
+                self.interval = Some(match self.interval {
+                    Some(interval) => interval.mul_f64(1.0 - INTERVAL_ALPHA) + elapsed.mul_f64(INTERVAL_ALPHA),
+                    None => elapsed,
+                });
+            }
+            _ => self.start_from(measured),
+        }
+        self.last_at = Some(at);
+        self.drift_ms.round() as i64
+    }
+
+    fn start_from(&mut self, drift_ms: f64) {
+        self.drift_ms = drift_ms;
+        self.rate_ms_per_sec = 0.0;
+        self.sample_count = 1;
+    }
+
+    /// Estimated drift (ms, positive = ahead of host)
+    pub fn drift_ms(&self) -> i64 {
+        self.drift_ms.round() as i64
+    }
+
+    /// Estimated drift rate (ms per second, positive = pulling ahead)
+    pub fn rate_ms_per_sec(&self) -> f64 {
+        self.rate_ms_per_sec
+    }
+
+    /// Measurements since the model last started over
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Time until the drift is expected to exceed `threshold_ms`, from the
+    /// last measurement; `None` if it's not heading there (or the rate isn't
+    /// known well enough yet, or is too slow for it to ever get there). Zero
+    /// once it's past.
+    pub fn time_to_threshold(&self, threshold_ms: u64) -> Option<Duration> {
+        let threshold = threshold_ms as f64;
+        if self.drift_ms.abs() > threshold {
+            return Some(Duration::ZERO);
+        }
+        if self.sample_count < MIN_SAMPLES_FOR_PREDICTION || self.rate_ms_per_sec == 0.0 {
+            return None;
+        }
+        let remaining = threshold.copysign(self.rate_ms_per_sec) - self.drift_ms;
+        Duration::try_from_secs_f64(remaining / self.rate_ms_per_sec).ok()
+    }
+
+    /// Whether the drift will exceed `threshold_ms` before the next measurement
+    /// is expected, so correcting now is better than waiting for it
+    pub fn exceeds_before_next(&self, threshold_ms: u64) -> bool {
+        match (self.time_to_threshold(threshold_ms), self.interval) {
+            (Some(time), _) if time.is_zero() => true,
+            (Some(time), Some(interval)) => time <= interval,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `drifts` one `interval` apart, starting at `start`; returns when the last was taken
+    fn feed(model: &mut DriftModel, start: Instant, interval: Duration, drifts: &[i64]) -> Instant {
+        let mut at = start;
+        for (i, drift) in drifts.iter().enumerate() {
+            at = start + interval * i as u32;
+            model.observe(*drift, at);
+        }
+        at
+    }
+
+    #[test]
+    fn test_smooths_noise() {
+        let mut model = DriftModel::new();
+        feed(&mut model, Instant::now(), Duration::from_millis(1500), &[100, 300, 100, 300, 100, 300]);
+
+        // Holding steady around 200ms, not swinging with each measurement
+        assert!((150..=250).contains(&model.drift_ms()), "drift {}", model.drift_ms());
+        assert!(model.rate_ms_per_sec().abs() < 30.0, "rate {}", model.rate_ms_per_sec());
+        assert!(!model.exceeds_before_next(1_000));
+    }
+
+    #[test]
+    fn test_predicts_steady_drift() {
+        let mut model = DriftModel::new();
+        let interval = Duration::from_secs(1);
+        let drifts: Vec<i64> = (0..20).map(|i| i * 40).collect();
+        feed(&mut model, Instant::now(), interval, &drifts);
+
+        // 40ms/s from 760ms: 1s threshold crossed in ~6s
+        assert!((model.rate_ms_per_sec() - 40.0).abs() < 5.0, "rate {}", model.rate_ms_per_sec());
+        let time = model.time_to_threshold(1_000).unwrap();
+        assert!(time > Duration::from_secs(4) && time < Duration::from_secs(8), "{:?}", time);
+        assert!(!model.exceeds_before_next(1_000));
+
+        // Within one measurement of the threshold
+        assert!(model.exceeds_before_next(780));
+        // Nothing to predict from yet
+        assert!(!DriftModel::new().exceeds_before_next(1_000));
+
+        // Heading there, but too slowly to ever get there
+        model.drift_ms = 0.0;
+        model.rate_ms_per_sec = f64::MIN_POSITIVE;
+        assert_eq!(model.time_to_threshold(1_000), None);
+        assert!(!model.exceeds_before_next(1_000));
+    }
+
+    #[test]
+    fn test_jump_and_gap_start_over() {
+        let mut model = DriftModel::new();
+        let start = Instant::now();
+        let interval = Duration::from_secs(1);
+        let last = feed(&mut model, start, interval, &[0, 20, 40, 60, 80]);
+        assert_eq!(model.sample_count(), 5);
+
+        // A seek elsewhere in the track isn't drift
+        assert_eq!(model.observe(2_000, last + interval), 2_000);
+        assert_eq!(model.sample_count(), 1);
+        assert_eq!(model.rate_ms_per_sec(), 0.0);
+        assert_eq!(model.time_to_threshold(3_000), None);
+        assert_eq!(model.time_to_threshold(1_000), Some(Duration::ZERO));
+
+        // Nor is whatever happened while we weren't measuring
+        let later = last + interval + MAX_GAP * 2;
+        model.observe(2_100, later);
+        assert_eq!(model.sample_count(), 1);
+        assert_eq!(model.drift_ms(), 2_100);
+
+        // A measurement right after another doesn't make a rate
+        model.observe(2_200, later + Duration::from_millis(10));
+        assert_eq!(model.drift_ms(), 2_150);
+        assert_eq!(model.rate_ms_per_sec(), 0.0);
+    }
+}
@@ -0,0 +1,733 @@
+//! Local HTTP/WebSocket bridge to the session
+//!
+//! Lets local integrations (the Cider plugin, scripts, stream overlays)
+//! drive a room without uniffi bindings. Built with the `bridge` feature and
+//! started with `Session::start_bridge`; it only listens on localhost, and
+//! turns away requests from web pages (an `Origin` or `Host` that isn't
+//! localhost). Every request carries the bridge's token (`Session::bridge_token`),
+//! as `Authorization: Bearer <token>` or, where a client can't set headers
+//! (a WebSocket or OBS browser source), a `?token=` query. Routes, JSON in
+//! and out:
+//!   GET  /api/room                 - room state (null when not in a room)
+//!   POST /api/room                 - create a room {"display_name"} -> {"room_code"}
+//!   POST /api/room/join            - join a room {"room_code", "display_name"}
+//!   POST /api/room/leave           - leave the room
+//!   GET  /api/playback             - Cider's track and whether it's playing
+//!   POST /api/playback/play        - play, pause, next, previous: as the
+//!   POST /api/playback/pause         `sync_*` methods (host, or anyone the
+//!   POST /api/playback/next          room's settings allow)
+//!   POST /api/playback/previous
+//!   POST /api/playback/seek        - seek {"position_ms"}
+//!   GET  /api/events               - WebSocket: session events as JSON
+//!                                    messages, tagged by "type"
+//!   GET  /api/overlay              - what the stream overlay shows
+//!   GET  /overlay                  - stream overlay page (HTML), for an OBS
+//!                                    browser source
+//!
+//! Errors come back as {"error": <error code>, "message": <text>}.
+//!
+//! The overlay is covered in `ffi/overlay.rs`.
+
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, info, warn};
+
+use super::overlay::{self, Overlay};
+use super::session::Session;
+use super::types::*;
+
+/// Largest request accepted (headers and body)
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+/// Most headers a request may have
+const MAX_HEADERS: usize = 32;
+
+/// Longest a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest WebSocket frame accepted from a client (we only expect control frames)
+const MAX_CLIENT_FRAME_BYTES: u64 = 64 * 1024;
+
+/// Events kept for a WebSocket client that's slow to read (it misses older ones beyond this)
+const EVENT_BUFFER: usize = 256;
+
+/// Appended to a client's key to accept its WebSocket handshake (RFC 6455)
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// The bridge while it runs
+pub(super) struct RunningBridge {
+    pub port: u16,
+    /// Required of every request
+    pub token: String,
+    events: broadcast::Sender<SessionEvent>,
+    /// Installed as the session's callback, wrapping the app's
+    callback: Arc<BridgeCallback>,
+    /// Stops the server when sent (or dropped)
+    _stop: tokio::sync::oneshot::Sender<()>,
+}
+
+impl RunningBridge {
+    /// Install `app` (the app's callback, if any) behind the bridge: events
+    /// go to both. Returns what to set as the session's callback.
+    pub fn tap(&mut self, app: Option<Arc<dyn SessionCallback>>) -> Arc<dyn SessionCallback> {
+        self.callback = Arc::new(BridgeCallback {
+            app,
+            events: self.events.clone(),
+        });
+        self.callback.clone()
+    }
+
+    /// The app's callback, to put back when the bridge stops
+    pub fn app_callback(&self) -> Option<Arc<dyn SessionCallback>> {
+        self.callback.app.clone()
+    }
+}
+
+/// The bridge serving `session` on `listener`, and the server to run; it
+/// stops when the bridge is dropped. Events only reach it once it's tapped
+/// into the session's callback (see `RunningBridge::tap`).
+pub(super) fn start(
+    listener: TcpListener,
+    session: Weak<Session>,
+    token: String,
+) -> (RunningBridge, impl std::future::Future<Output = ()> + Send + 'static) {
+    let port = listener.local_addr().map(|addr| addr.port()).unwrap_or_default();
+    let (events, _) = broadcast::channel(EVENT_BUFFER);
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+    let callback = Arc::new(BridgeCallback {
+        app: None,
+        events: events.clone(),
+    });
+
+    let server_events = events.clone();
+    let server_token: Arc<str> = token.as_str().into();
+    let server = async move {
+        tokio::select! {
+            result = serve(listener, session, server_events, server_token) => {
+                if let Err(e) = result {
+                    warn!("Bridge stopped: {}", e);
+                }
+            }
+            _ = stop_rx => debug!("Bridge stopped"),
+        }
+    };
+    let running = RunningBridge {
+        port,
+        token,
+        events,
+        callback,
+        _stop: stop_tx,
+    };
+    (running, server)
+}
+
+async fn serve(
+    listener: TcpListener,
+    session: Weak<Session>,
+    events: broadcast::Sender<SessionEvent>,
+    token: Arc<str>,
+) -> std::io::Result<()> {
+    info!("Bridge listening on http://{}", listener.local_addr()?);
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let session = session.clone();
+        let events = events.subscribe();
+        let token = Arc::clone(&token);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, session, events, &token).await {
+                debug!("Bridge request from {} failed: {}", remote, e);
+            }
+        });
+    }
+}
+
+/// Session callback passing events on to the app's callback and the bridge's clients
+struct BridgeCallback {
+    app: Option<Arc<dyn SessionCallback>>,
+    events: broadcast::Sender<SessionEvent>,
+}
+
+impl BridgeCallback {
+    fn emit(&self, event: SessionEvent) {
+        // No one listening is fine
+        let _ = self.events.send(event);
+    }
+}
+
+impl SessionCallback for BridgeCallback {
+    fn on_room_state_changed(&self, state: RoomState) {
+        if let Some(app) = &self.app {
+            app.on_room_state_changed(state.clone());
+        }
+        self.emit(SessionEvent::RoomStateChanged { state });
+    }
+
+    fn on_track_changed(&self, track: Option<TrackInfo>) {
+        if let Some(app) = &self.app {
+            app.on_track_changed(track.clone());
+        }
+        self.emit(SessionEvent::TrackChanged { track });
+    }
+
+    fn on_playback_changed(&self, playback: PlaybackState) {
+        if let Some(app) = &self.app {
+            app.on_playback_changed(playback.clone());
+        }
+        self.emit(SessionEvent::PlaybackChanged { playback });
+    }
+
+    fn on_participant_joined(&self, participant: Participant) {
+        if let Some(app) = &self.app {
+            app.on_participant_joined(participant.clone());
+        }
+        self.emit(SessionEvent::ParticipantJoined { participant });
+    }
+
+    fn on_participant_left(&self, peer_id: String) {
+        if let Some(app) = &self.app {
+            app.on_participant_left(peer_id.clone());
+        }
+        self.emit(SessionEvent::ParticipantLeft { peer_id });
+    }
+
+    fn on_room_ended(&self, reason: String) {
+        if let Some(app) = &self.app {
+            app.on_room_ended(reason.clone());
+        }
+        self.emit(SessionEvent::RoomEnded { reason });
+    }
+
+    fn on_error(&self, code: ErrorCode, message_key: String, message: String) {
+        if let Some(app) = &self.app {
+            app.on_error(code, message_key.clone(), message.clone());
+        }
+        self.emit(SessionEvent::Error { code, message_key, message });
+    }
+
+    fn on_connected(&self) {
+        if let Some(app) = &self.app {
+            app.on_connected();
+        }
+        self.emit(SessionEvent::Connected);
+    }
+
+    fn on_disconnected(&self) {
+        if let Some(app) = &self.app {
+            app.on_disconnected();
+        }
+        self.emit(SessionEvent::Disconnected);
+    }
+
+    fn on_sync_status(&self, status: SyncStatus) {
+        if let Some(app) = &self.app {
+            app.on_sync_status(status.clone());
+        }
+        self.emit(SessionEvent::SyncStatus { status });
+    }
+
+    fn on_track_unavailable(&self, track: TrackInfo) {
+        if let Some(app) = &self.app {
+            app.on_track_unavailable(track.clone());
+        }
+        self.emit(SessionEvent::TrackUnavailable { track });
+    }
+
+    fn on_listener_track_unavailable(&self, participant: Participant, track: TrackInfo) {
+        if let Some(app) = &self.app {
+            app.on_listener_track_unavailable(participant.clone(), track.clone());
+        }
+        self.emit(SessionEvent::ListenerTrackUnavailable { participant, track });
+    }
+
+    fn on_control_requested(&self, participant: Participant) {
+        if let Some(app) = &self.app {
+            app.on_control_requested(participant.clone());
+        }
+        self.emit(SessionEvent::ControlRequested { participant });
+    }
+
+    fn on_friend_started_room(&self, friend: Friend, room_code: String) {
+        if let Some(app) = &self.app {
+            app.on_friend_started_room(friend.clone(), room_code.clone());
+        }
+        self.emit(SessionEvent::FriendStartedRoom { friend, room_code });
+    }
+}
+
+/// A request, as far as the bridge cares
+struct Request {
+    method: String,
+    path: String,
+    /// What follows the `?` in the target, if anything
+    query: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value)
+    }
+}
+
+type Response = (&'static str, String);
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    session: Weak<Session>,
+    events: broadcast::Receiver<SessionEvent>,
+    token: &str,
+) -> std::io::Result<()> {
+    let Ok(request) = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await else {
+        return write_response(&mut stream, ("408 Request Timeout", error_body("bad_request", "Request timed out"))).await;
+    };
+    let response = match request? {
+        None => ("413 Payload Too Large", error_body("bad_request", "Request too large")),
+        Some(request) if !from_local_app(&request) => {
+            ("403 Forbidden", error_body("forbidden", "Only local apps may use the bridge"))
+        }
+        Some(request) if !authorized(&request, token) => {
+            ("401 Unauthorized", error_body("unauthorized", "Missing or wrong bridge token"))
+        }
+        Some(request) => {
+            let Some(session) = session.upgrade() else {
+                return write_response(&mut stream, ("503 Service Unavailable", error_body("shut_down", "Session is gone"))).await;
+            };
+            let upgrade = request.header("upgrade").is_some_and(|u| u.eq_ignore_ascii_case("websocket"));
+            if request.method == "GET" && request.path == "/api/events" && upgrade {
+                let Some(key) = request.header("sec-websocket-key").map(str::to_string) else {
+                    return write_response(&mut stream, ("400 Bad Request", error_body("bad_request", "Missing Sec-WebSocket-Key"))).await;
+                };
+                let snapshot = session.get_room_state();
+                drop(session);
+                return stream_events(stream, &key, snapshot, events).await;
+            }
+            if request.method == "GET" && request.path == "/overlay" {
+                return write_body(&mut stream, "200 OK", "text/html", overlay::PAGE).await;
+            }
+            route(&request, session).await
+        }
+    };
+    write_response(&mut stream, response).await
+}
+
+async fn write_response(stream: &mut TcpStream, (status, body): Response) -> std::io::Result<()> {
+    write_body(stream, status, "application/json", &body).await
+}
+
+async fn write_body(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// The request on `stream`, or None if it's too large
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<Request>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let (method, target, headers, header_end) = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Request::new(&mut headers);
+        match parsed.parse(&buf).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))? {
+            httparse::Status::Complete(header_end) => {
+                let headers: Vec<(String, String)> = parsed
+                    .headers
+                    .iter()
+                    .map(|h| (h.name.to_string(), String::from_utf8_lossy(h.value).trim().to_string()))
+                    .collect();
+                let method = parsed.method.unwrap_or_default().to_string();
+                let target = parsed.path.unwrap_or("/").to_string();
+                break (method, target, headers, header_end);
+            }
+            httparse::Status::Partial if buf.len() > MAX_REQUEST_BYTES => return Ok(None),
+            httparse::Status::Partial => {}
+        }
+    };
+
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let Some(request_end) = header_end.checked_add(content_length).filter(|end| *end <= MAX_REQUEST_BYTES) else {
+        return Ok(None);
+    };
+    while buf.len() < request_end {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body = String::from_utf8_lossy(&buf[header_end..request_end]).to_string();
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    Ok(Some(Request {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        headers,
+        body,
+    }))
+}
+
+/// Whether the request carries the bridge's `token`
+fn authorized(request: &Request, token: &str) -> bool {
+    let given = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| request.query_param("token"));
+    // Compared in full whatever differs, so timing doesn't give it away
+    given.is_some_and(|given| {
+        given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    })
+}
+
+/// Whether the request comes from an app on this machine rather than a web
+/// page: browsers send the page's `Origin` with requests to other sites, and
+/// a page reaching us through DNS rebinding still names its own `Host`
+fn from_local_app(request: &Request) -> bool {
+    let host_ok = request.header("host").is_none_or(is_loopback_host);
+    let origin_ok = request
+        .header("origin")
+        .is_none_or(|origin| origin.split_once("://").is_some_and(|(_, host)| is_loopback_host(host)));
+    host_ok && origin_ok
+}
+
+/// Whether `host` (as in a `Host` header: name, and maybe a port) is this machine
+fn is_loopback_host(host: &str) -> bool {
+    let host = host.trim_end_matches('/');
+    let name = if let Some(rest) = host.strip_prefix('[') {
+        rest.split(']').next().unwrap_or("")
+    } else {
+        host.split(':').next().unwrap_or("")
+    };
+    matches!(name, "localhost" | "127.0.0.1" | "::1")
+}
+
+#[derive(Deserialize)]
+struct CreateRoomRequest {
+    #[serde(default)]
+    display_name: String,
+}
+
+#[derive(Deserialize)]
+struct JoinRoomRequest {
+    room_code: String,
+    #[serde(default)]
+    display_name: String,
+}
+
+#[derive(Deserialize)]
+struct SeekRequest {
+    position_ms: u64,
+}
+
+#[derive(Serialize)]
+struct CreatedRoom {
+    room_code: String,
+}
+
+async fn route(request: &Request, session: Arc<Session>) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/api/room") => ok(&session.get_room_state()),
+        ("GET", "/api/overlay") => ok(&Overlay::now(session.get_room_state().as_ref())),
+        ("POST", "/api/room") => match parse::<CreateRoomRequest>(&request.body) {
+            Ok(req) => {
+                let result = session.off_thread(move |s| s.create_room(req.display_name)).await;
+                respond(result.map(|room_code| CreatedRoom { room_code }))
+            }
+            Err(response) => response,
+        },
+        ("POST", "/api/room/join") => match parse::<JoinRoomRequest>(&request.body) {
+            Ok(req) => respond(session.off_thread(move |s| s.join_room(req.room_code, req.display_name)).await),
+            Err(response) => response,
+        },
+        ("POST", "/api/room/leave") => respond(session.off_thread(|s| s.leave_room()).await),
+        ("GET", "/api/playback") => respond(session.off_thread(|s| s.get_playback_state()).await),
+        ("POST", "/api/playback/play") => respond(session.play_as_host().await),
+        ("POST", "/api/playback/pause") => respond(session.pause_as_host().await),
+        ("POST", "/api/playback/next") => respond(session.next_as_host().await),
+        ("POST", "/api/playback/previous") => respond(session.previous_as_host().await),
+        ("POST", "/api/playback/seek") => match parse::<SeekRequest>(&request.body) {
+            Ok(req) => respond(session.seek_as_host(req.position_ms).await),
+            Err(response) => response,
+        },
+        (_, "/api/room" | "/api/room/join" | "/api/room/leave" | "/api/playback" | "/api/overlay" | "/overlay") => {
+            ("405 Method Not Allowed", error_body("bad_request", "Method not allowed"))
+        }
+        _ => ("404 Not Found", error_body("not_found", "Not found")),
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(body: &str) -> Result<T, Response> {
+    // An empty body is an empty object
+    let body = if body.trim().is_empty() { "{}" } else { body };
+    serde_json::from_str(body).map_err(|e| ("400 Bad Request", error_body("bad_request", &e.to_string())))
+}
+
+fn ok<T: Serialize>(value: &T) -> Response {
+    match serde_json::to_string(value) {
+        Ok(body) => ("200 OK", body),
+        Err(e) => ("500 Internal Server Error", error_body("internal", &e.to_string())),
+    }
+}
+
+fn respond<T: Serialize>(result: Result<T, CoreError>) -> Response {
+    match result {
+        Ok(value) => ok(&value),
+        Err(e) => {
+            let status = match e {
+                CoreError::NotInRoom | CoreError::AlreadyInRoom => "409 Conflict",
+                CoreError::NotHost => "403 Forbidden",
+                CoreError::ShutDown => "503 Service Unavailable",
+                CoreError::NetworkError { code: ErrorCode::InvalidRoomCode, .. } => "400 Bad Request",
+                // Cider or the network let us down
+                _ => "502 Bad Gateway",
+            };
+            let body = serde_json::json!({ "error": e.code(), "message": e.to_string() });
+            (status, body.to_string())
+        }
+    }
+}
+
+fn error_body(error: &str, message: &str) -> String {
+    serde_json::json!({ "error": error, "message": message }).to_string()
+}
+
+/// Accept the WebSocket handshake, then send session events (the room's
+/// state first, if we're in one) until the client or the bridge goes away
+async fn stream_events(
+    mut stream: TcpStream,
+    key: &str,
+    snapshot: Option<RoomState>,
+    mut events: broadcast::Receiver<SessionEvent>,
+) -> std::io::Result<()> {
+    let handshake = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    );
+    stream.write_all(handshake.as_bytes()).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    // Frames are read on their own, so a half-read one isn't lost to the select below
+    let (frames_tx, mut frames) = mpsc::channel(8);
+    let reading = tokio::spawn(async move {
+        let mut reader = reader;
+        while let Ok(frame) = read_frame(&mut reader).await {
+            if frames_tx.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    if let Some(state) = snapshot {
+        send_event(&mut writer, &SessionEvent::RoomStateChanged { state }).await?;
+    }
+    let result = loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => send_event(&mut writer, &event).await?,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Bridge client fell behind, {} events dropped", missed);
+                }
+                // The bridge stopped
+                Err(broadcast::error::RecvError::Closed) => break writer.write_all(&frame(OP_CLOSE, &[])).await,
+            },
+            frame_in = frames.recv() => match frame_in {
+                Some((OP_PING, payload)) => writer.write_all(&frame(OP_PONG, &payload)).await?,
+                Some((OP_CLOSE, _)) | None => break writer.write_all(&frame(OP_CLOSE, &[])).await,
+                // Clients have nothing to say (yet)
+                Some(_) => {}
+            },
+        }
+    };
+    reading.abort();
+    result
+}
+
+async fn send_event(writer: &mut tokio::net::tcp::OwnedWriteHalf, event: &SessionEvent) -> std::io::Result<()> {
+    let json = serde_json::to_string(event).map_err(std::io::Error::other)?;
+    writer.write_all(&frame(OP_TEXT, json.as_bytes())).await
+}
+
+/// `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`
+fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.trim().as_bytes());
+    sha1.update(WEBSOCKET_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(sha1.finalize())
+}
+
+/// An unfragmented server frame (servers don't mask)
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// A client frame: its opcode and (unmasked) payload
+async fn read_frame(reader: &mut OwnedReadHalf) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+    let opcode = head[0] & 0x0f;
+    let masked = head[1] & 0x80 != 0;
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len).await?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len).await?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_CLIENT_FRAME_BYTES {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+    Ok((opcode, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "GET".to_string(),
+            path: "/api/room".to_string(),
+            query: String::new(),
+            headers: headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_local_apps_only() {
+        assert!(from_local_app(&request(&[("Host", "127.0.0.1:7007")])));
+        assert!(from_local_app(&request(&[("Host", "localhost:7007"), ("Origin", "http://localhost:5173")])));
+        assert!(from_local_app(&request(&[("Host", "[::1]:7007")])));
+        assert!(from_local_app(&request(&[])));
+
+        // Web pages, directly or through DNS rebinding
+        assert!(!from_local_app(&request(&[("Host", "127.0.0.1:7007"), ("Origin", "https://example.com")])));
+        assert!(!from_local_app(&request(&[("Host", "evil.example:7007")])));
+        assert!(!from_local_app(&request(&[("Host", "localhost.evil.example")])));
+        assert!(!from_local_app(&request(&[("Origin", "null")])));
+    }
+
+    #[test]
+    fn test_token_required() {
+        let token = "0123456789abcdef";
+        assert!(authorized(&request(&[("Authorization", "Bearer 0123456789abcdef")]), token));
+        let overlay = Request { query: "token=0123456789abcdef".to_string(), ..request(&[]) };
+        assert!(authorized(&overlay, token));
+
+        assert!(!authorized(&request(&[]), token));
+        assert!(!authorized(&request(&[("Authorization", "Bearer 0123456789abcdeg")]), token));
+        assert!(!authorized(&request(&[("Authorization", "Bearer 0123456789")]), token));
+        assert!(!authorized(&request(&[("Authorization", "0123456789abcdef")]), token));
+        let empty = Request { query: "token=".to_string(), ..request(&[]) };
+        assert!(!authorized(&empty, token));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = &listener;
+        let read = |head: String| async move {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(head.as_bytes()).await.unwrap();
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_request(&mut stream).await.unwrap()
+        };
+
+        let post = |length: &str| format!("POST /api/seek HTTP/1.1\r\nContent-Length: {}\r\n\r\n{{}}", length);
+        assert_eq!(read(post("2")).await.unwrap().body, "{}");
+        assert!(read(post(&MAX_REQUEST_BYTES.to_string())).await.is_none());
+        // Past usize once added to the headers' length
+        assert!(read(post(&usize::MAX.to_string())).await.is_none());
+    }
+
+    #[test]
+    fn test_websocket_frames() {
+        // RFC 6455's example handshake
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        assert_eq!(frame(OP_TEXT, b"Hello"), b"\x81\x05Hello");
+        let long = frame(OP_TEXT, &[b'a'; 300]);
+        assert_eq!(&long[..4], &[0x81, 126, 0x01, 0x2c]);
+        assert_eq!(long.len(), 304);
+    }
+
+    #[test]
+    fn test_error_responses() {
+        let (status, body) = respond::<()>(Err(CoreError::NotHost));
+        assert_eq!(status, "403 Forbidden");
+        assert_eq!(body, r#"{"error":"not_host","message":"Not the host"}"#);
+        assert_eq!(respond(Ok(CreatedRoom { room_code: "ABCD-4679".to_string() })).1, r#"{"room_code":"ABCD-4679"}"#);
+        assert!(matches!(parse::<SeekRequest>("{}"), Err(("400 Bad Request", _))));
+    }
+}
@@ -0,0 +1,92 @@
+//! `ciderlt://` links behind `Session::handle_uri`
+//!
+//! The macOS and Windows apps and the Cider plugin all register the scheme;
+//! they hand the link to the core rather than each parsing it. Links:
+//!   ciderlt://join/<room code>                    - join a room
+//!   ciderlt://invite/<room code>?from=<peer ID>   - an invite to a room from
+//!                                                   someone in it
+//!   ciderlt://suggest/<song ID>                   - suggest a catalog song
+//!                                                   for the room's queue
+//!
+//! Room codes are taken as people write them (any case, with or without the
+//! hyphen).
+
+use crate::network::RoomCode;
+
+use super::types::{CoreError, DeepLink, ErrorCode};
+
+pub const SCHEME: &str = "ciderlt";
+
+/// The link `uri` stands for
+pub fn parse(uri: &str) -> Result<DeepLink, CoreError> {
+    let invalid = |detail: &str| CoreError::network(ErrorCode::InvalidLink, format!("{}: {}", detail, uri));
+    let url = reqwest::Url::parse(uri.trim()).map_err(|_| invalid("Not a link"))?;
+    if url.scheme() != SCHEME {
+        return Err(invalid("Not a Listen Together link"));
+    }
+    let action = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    let target = url.path().trim_matches('/');
+    let room_code = || {
+        RoomCode::parse(target)
+            .map(|code| code.as_str().to_string())
+            .ok_or_else(|| CoreError::network(ErrorCode::InvalidRoomCode, "Invalid room code"))
+    };
+
+    match action.as_str() {
+        "join" => Ok(DeepLink::JoinRoom { room_code: room_code()? }),
+        "invite" => Ok(DeepLink::Invite {
+            room_code: room_code()?,
+            from_peer_id: url.query_pairs().find(|(key, _)| key == "from").map(|(_, value)| value.into_owned()),
+        }),
+        "suggest" if !target.is_empty() && target.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'.') => {
+            Ok(DeepLink::Suggest {
+                song_id: target.to_string(),
+            })
+        }
+        "suggest" => Err(invalid("Invalid song ID")),
+        _ => Err(invalid("Unknown link")),
+    }
+}
+
+/// Link inviting others to `room_code`, from `peer_id`
+pub fn invite(room_code: &str, peer_id: &str) -> String {
+    format!("{}://invite/{}?from={}", SCHEME, room_code, peer_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(result: Result<DeepLink, CoreError>) -> ErrorCode {
+        result.unwrap_err().code()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("ciderlt://join/abcd-4679").unwrap(),
+            DeepLink::JoinRoom {
+                room_code: "ABCD4679".to_string()
+            }
+        );
+        assert_eq!(
+            parse(&invite("ABCD4679", "12D3KooWfriend")).unwrap(),
+            DeepLink::Invite {
+                room_code: "ABCD4679".to_string(),
+                from_peer_id: Some("12D3KooWfriend".to_string()),
+            }
+        );
+        assert_eq!(
+            parse("CiderLT://Suggest/1440818839/").unwrap(),
+            DeepLink::Suggest {
+                song_id: "1440818839".to_string()
+            }
+        );
+
+        assert_eq!(code(parse("ciderlt://join/ABC")), ErrorCode::InvalidRoomCode);
+        assert_eq!(code(parse("https://join/ABCD4679")), ErrorCode::InvalidLink);
+        assert_eq!(code(parse("ciderlt://leave/ABCD4679")), ErrorCode::InvalidLink);
+        assert_eq!(code(parse("ciderlt://suggest/..%2F")), ErrorCode::InvalidLink);
+        assert_eq!(code(parse("not a link")), ErrorCode::InvalidLink);
+    }
+}
@@ -0,0 +1,81 @@
+//! Recent errors for diagnostics
+//!
+//! Errors the session hits (Cider unreachable, network failures, rooms not
+//! found) are only logged or passed to the app as they happen. This keeps the
+//! last few so `Session::get_diagnostics` can include them in bug reports.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::types::{current_time_ms, ErrorLogEntry};
+
+/// Maximum number of distinct errors to keep
+const MAX_ERROR_LOG_ENTRIES: usize = 20;
+
+/// Bounded log of recent errors
+///
+/// The same error repeated back to back (e.g. every poll while Cider is
+/// closed) is kept as one entry with a count.
+#[derive(Debug, Default)]
+pub struct ErrorLog {
+    entries: Mutex<VecDeque<ErrorLogEntry>>,
+}
+
+impl ErrorLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an error, evicting the oldest if full
+    pub fn record(&self, message: impl Into<String>) {
+        let message = message.into();
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(last) = entries.back_mut().filter(|e| e.message == message) {
+            last.timestamp_ms = current_time_ms();
+            last.count = last.count.saturating_add(1);
+            return;
+        }
+        if entries.len() >= MAX_ERROR_LOG_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(ErrorLogEntry {
+            timestamp_ms: current_time_ms(),
+            message,
+            count: 1,
+        });
+    }
+
+    /// Get recent errors (oldest first)
+    pub fn entries(&self) -> Vec<ErrorLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeats_collapse() {
+        let log = ErrorLog::new();
+        log.record("Cider is not reachable");
+        log.record("Cider is not reachable");
+        log.record("Room ABC not found");
+        log.record("Cider is not reachable");
+
+        let counts: Vec<u32> = log.entries().iter().map(|e| e.count).collect();
+        assert_eq!(counts, vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let log = ErrorLog::new();
+        for i in 0..MAX_ERROR_LOG_ENTRIES + 5 {
+            log.record(format!("error {}", i));
+        }
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), MAX_ERROR_LOG_ENTRIES);
+        assert_eq!(entries[0].message, "error 5");
+    }
+}
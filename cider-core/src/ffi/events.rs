@@ -0,0 +1,159 @@
+//! Pull-based delivery of session events
+//!
+//! An alternative to `SessionCallback` for bindings (and tests) that can't
+//! keep a callback object alive across the FFI boundary: events are buffered
+//! and the app polls for them with `Session::next_event`.
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use super::types::{
+    ErrorCode, Friend, Participant, PlaybackState, RoomState, SessionCallback, SessionEvent, SyncStatus, TrackInfo,
+};
+
+/// Events kept while the app isn't polling (oldest are dropped beyond this)
+pub const EVENT_QUEUE_CAPACITY: usize = 256;
+
+/// Bounded queue of session events, filled as the session's callback
+pub struct EventQueue {
+    capacity: usize,
+    events: Mutex<VecDeque<SessionEvent>>,
+    available: Condvar,
+}
+
+impl EventQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            events: Mutex::new(VecDeque::new()),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Queue an event, dropping the oldest one if full
+    pub fn push(&self, event: SessionEvent) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() >= self.capacity {
+            if let Some(dropped) = events.pop_front() {
+                warn!("Event queue full, dropping {:?}", dropped);
+            }
+        }
+        events.push_back(event);
+        self.available.notify_one();
+    }
+
+    /// Oldest queued event, waiting up to `timeout` for one
+    pub fn pop(&self, timeout: Duration) -> Option<SessionEvent> {
+        let deadline = Instant::now() + timeout;
+        let mut events = self.events.lock().unwrap();
+        loop {
+            if let Some(event) = events.pop_front() {
+                return Some(event);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            events = self.available.wait_timeout(events, remaining).unwrap().0;
+        }
+    }
+
+    /// Drop all queued events
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
+
+impl SessionCallback for EventQueue {
+    fn on_room_state_changed(&self, state: RoomState) {
+        self.push(SessionEvent::RoomStateChanged { state });
+    }
+
+    fn on_track_changed(&self, track: Option<TrackInfo>) {
+        self.push(SessionEvent::TrackChanged { track });
+    }
+
+    fn on_playback_changed(&self, playback: PlaybackState) {
+        self.push(SessionEvent::PlaybackChanged { playback });
+    }
+
+    fn on_participant_joined(&self, participant: Participant) {
+        self.push(SessionEvent::ParticipantJoined { participant });
+    }
+
+    fn on_participant_left(&self, peer_id: String) {
+        self.push(SessionEvent::ParticipantLeft { peer_id });
+    }
+
+    fn on_room_ended(&self, reason: String) {
+        self.push(SessionEvent::RoomEnded { reason });
+    }
+
+    fn on_error(&self, code: ErrorCode, message_key: String, message: String) {
+        self.push(SessionEvent::Error { code, message_key, message });
+    }
+
+    fn on_connected(&self) {
+        self.push(SessionEvent::Connected);
+    }
+
+    fn on_disconnected(&self) {
+        self.push(SessionEvent::Disconnected);
+    }
+
+    fn on_sync_status(&self, status: SyncStatus) {
+        self.push(SessionEvent::SyncStatus { status });
+    }
+
+    fn on_track_unavailable(&self, track: TrackInfo) {
+        self.push(SessionEvent::TrackUnavailable { track });
+    }
+
+    fn on_listener_track_unavailable(&self, participant: Participant, track: TrackInfo) {
+        self.push(SessionEvent::ListenerTrackUnavailable { participant, track });
+    }
+
+    fn on_control_requested(&self, participant: Participant) {
+        self.push(SessionEvent::ControlRequested { participant });
+    }
+
+    fn on_friend_started_room(&self, friend: Friend, room_code: String) {
+        self.push(SessionEvent::FriendStartedRoom { friend, room_code });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_drops_oldest_when_full() {
+        let queue = EventQueue::new(2);
+        queue.on_participant_left("a".to_string());
+        queue.on_participant_left("b".to_string());
+        queue.on_connected();
+
+        assert!(matches!(queue.pop(Duration::ZERO), Some(SessionEvent::ParticipantLeft { peer_id }) if peer_id == "b"));
+        assert!(matches!(queue.pop(Duration::ZERO), Some(SessionEvent::Connected)));
+        assert!(queue.pop(Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_pop_waits_for_event() {
+        let queue = Arc::new(EventQueue::new(EVENT_QUEUE_CAPACITY));
+        let start = Instant::now();
+        assert!(queue.pop(Duration::from_millis(20)).is_none());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let producer = Arc::clone(&queue);
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            producer.on_disconnected();
+        });
+        assert!(matches!(queue.pop(Duration::from_secs(5)), Some(SessionEvent::Disconnected)));
+    }
+}
@@ -1,27 +1,51 @@
 //! Network event and sync message handlers
+//!
+//! These run on the room actor. What following the host takes from Cider
+//! (loading its track, seeking, re-syncing on heartbeats) is handed to the
+//! `Player` instead, so the actor isn't held up by slow Cider calls.
 
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
+use tokio::time::Instant;
 use tracing::{debug, info, warn};
 
-use crate::cider::CiderClient;
+use crate::cider::{CiderApi, SearchResult};
+use crate::drift_model::DriftModel;
 use crate::latency::SharedLatencyTracker;
-use crate::network::{NetworkEvent, NetworkHandle};
-use crate::seek_calibrator::SharedSeekCalibrator;
-use crate::sync::{Participant as InternalParticipant, Room, SyncMessage};
-
-use super::types::{CalibrationSample, Participant, PlaybackState, RoomState, SessionCallback, SyncStatus, TrackInfo};
+use crate::network::{NetworkEvent, NetworkHandle, RoomCode};
+use crate::seek_calibrator::{SeekKind, SharedSeekCalibrator};
+use crate::sync::{
+    Capabilities, ClientInfo, MessageClass, Moderation, Participant as InternalParticipant, ParticipantStatus, Profile,
+    RemovalReason, Room, RoomHash, RoomHashCheck, RoomSettings as InternalRoomSettings, RoomState as InternalRoomState,
+    SyncMessage, SyncStats,
+};
+
+use super::player::Player;
+use super::room_broadcast::RoomBroadcaster;
+use super::types::{
+    CalibrationSample, DriftEstimate, ErrorCode, Participant, PlaybackState, RoomState, SessionCallback, SyncStatus,
+    SyncStatusLevel, TrackInfo,
+};
+
+/// With `SyncStatusLevel::Summary`, listeners report sync status at most this often
+const SYNC_STATUS_SUMMARY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Deliberate seeks a new listener measures to calibrate its seek offset
+const WARM_UP_SEEKS: u32 = 3;
 
 /// Handle a network event
-pub async fn handle_network_event(
+pub async fn handle_network_event<C: CiderApi>(
     event: NetworkEvent,
     room: &Arc<RwLock<Room>>,
     callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
-    cider: &Arc<RwLock<CiderClient>>,
+    cider: &Arc<RwLock<C>>,
     network_handle: &Arc<RwLock<Option<NetworkHandle>>>,
     latency_tracker: &SharedLatencyTracker,
     seek_calibrator: &SharedSeekCalibrator,
     local_peer_id: &str,
+    sync_settings: &Arc<SyncSettings>,
+    player: &Player,
+    room_broadcaster: &RoomBroadcaster,
 ) {
     match event {
         NetworkEvent::Ready { peer_id } => {
@@ -35,41 +59,41 @@ pub async fn handle_network_event(
             let mut room_guard = room.write().unwrap();
             if let Some(state) = room_guard.state_mut() {
                 if state.is_host() {
+                    if let Some(reason) = state.refusal_for(&peer_id) {
+                        info!("Turning away {}: {:?}", peer_id, reason);
+                        if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                            let _ = handle.broadcast(SyncMessage::Removed { peer_id, reason });
+                        }
+                        // Joiners only believe it once the room state shows we're the host
+                        room_broadcaster.request();
+                        return;
+                    }
+
                     // Add as unknown listener immediately (will be updated if they send JoinRequest)
                     // Skip if it's ourselves or already known
                     if peer_id != state.local_peer_id && !state.participants.contains_key(&peer_id) {
                         info!("Adding unknown listener: {}", peer_id);
-                        state.add_participant(InternalParticipant {
+                        let participant = InternalParticipant {
                             peer_id: peer_id.clone(),
                             display_name: "?".to_string(),
                             is_host: false,
-                        });
+                            storefront: None,
+                            capabilities: Capabilities::default(),
+                            profile: Profile::default(),
+                            client: ClientInfo::default(),
+                            status: ParticipantStatus::default(),
+                        };
+                        let joined = Participant::from(&participant);
+                        state.add_participant(participant);
 
                         // Notify UI about the new participant
                         if let Some(cb) = callback.read().unwrap().as_ref() {
-                            cb.on_participant_joined(Participant {
-                                peer_id: peer_id.clone(),
-                                display_name: "?".to_string(),
-                                is_host: false,
-                            });
+                            cb.on_participant_joined(joined);
                         }
                     }
 
                     // Broadcast room state so new peer can join
-                    if let Some(handle) = network_handle.read().unwrap().as_ref() {
-                        let msg = SyncMessage::RoomState {
-                            room_code: state.room_code.clone(),
-                            host_peer_id: state.host_peer_id.clone(),
-                            participants: state.participant_list().iter().map(|p| InternalParticipant {
-                                peer_id: p.peer_id.clone(),
-                                display_name: p.display_name.clone(),
-                                is_host: p.is_host,
-                            }).collect(),
-                            current_track: state.current_track.clone(),
-                            playback: state.playback.clone(),
-                        };
-                        let _ = handle.broadcast(msg);
-                    }
+                    room_broadcaster.request();
                 }
             }
         }
@@ -111,19 +135,40 @@ pub async fn handle_network_event(
         }
 
         NetworkEvent::Message { from, message } => {
-            handle_sync_message(from, message, room, callback, cider, network_handle, latency_tracker, seek_calibrator, local_peer_id).await;
+            handle_sync_message(
+                from,
+                message,
+                room,
+                callback,
+                cider,
+                network_handle,
+                latency_tracker,
+                seek_calibrator,
+                local_peer_id,
+                sync_settings,
+                player,
+                room_broadcaster,
+            );
+        }
+
+        NetworkEvent::Pong { from, ping_sent_at_ms, received_at_ms } => {
+            handle_pong(&from, ping_sent_at_ms, received_at_ms, room, latency_tracker);
         }
 
         NetworkEvent::Error(e) => {
             warn!("Network error: {}", e);
             if let Some(cb) = callback.read().unwrap().as_ref() {
-                cb.on_error(e);
+                let code = ErrorCode::Network;
+                cb.on_error(code, code.message_key().to_string(), e);
             }
         }
 
         // Handled in session.rs before reaching here
         NetworkEvent::ListeningAddresses { .. } => {}
 
+        // Logged by the network; failures are handled in room_actor.rs before reaching here
+        NetworkEvent::DiscoveryProgress { .. } => {}
+
         // Bootstrap status updates - useful for debugging connectivity
         NetworkEvent::BootstrapStatus {
             connected_bootstrap_nodes,
@@ -139,6 +184,37 @@ pub async fn handle_network_event(
     }
 }
 
+/// The room as sent to joiners and after changes (host only)
+pub fn room_state_message(state: &InternalRoomState, host_delay_ms: u64) -> SyncMessage {
+    SyncMessage::RoomState {
+        room_code: state.room_code.clone(),
+        host_peer_id: state.host_peer_id.clone(),
+        participants: state.participant_list().into_iter().cloned().collect(),
+        current_track: state.current_track.clone(),
+        playback: playback_as_sent(&state.playback, host_delay_ms),
+        moderation: state.moderation.clone(),
+        settings: state.settings.clone(),
+    }
+}
+
+/// Position to broadcast for the host's `position_ms`: ahead by the host's
+/// sync delay while playing (see `SessionConfig::host_sync_delay_ms`)
+pub(super) fn ahead_of_host(position_ms: u64, is_playing: bool, delay_ms: u64) -> u64 {
+    if is_playing {
+        position_ms + delay_ms
+    } else {
+        position_ms
+    }
+}
+
+/// The host's `playback` as sent to listeners, see `ahead_of_host`
+pub(super) fn playback_as_sent(playback: &crate::sync::PlaybackInfo, delay_ms: u64) -> crate::sync::PlaybackInfo {
+    crate::sync::PlaybackInfo {
+        position_ms: ahead_of_host(playback.position_ms, playback.is_playing, delay_ms),
+        ..playback.clone()
+    }
+}
+
 /// Check if a message sender is the current host
 fn is_from_host(from: &str, room: &Arc<RwLock<Room>>) -> bool {
     let room_guard = room.read().unwrap();
@@ -147,21 +223,55 @@ fn is_from_host(from: &str, room: &Arc<RwLock<Room>>) -> bool {
         .unwrap_or(false)
 }
 
+/// What the player needs to follow the host, owned so its jobs can outlive the message
+struct Follower<C> {
+    room: Arc<RwLock<Room>>,
+    callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    cider: Arc<RwLock<C>>,
+    network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+    latency_tracker: SharedLatencyTracker,
+    seek_calibrator: SharedSeekCalibrator,
+    sync_settings: Arc<SyncSettings>,
+}
+
 /// Handle a sync message from another peer
-pub async fn handle_sync_message(
+pub fn handle_sync_message<C: CiderApi>(
     from: String,
     message: SyncMessage,
     room: &Arc<RwLock<Room>>,
     callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
-    cider: &Arc<RwLock<CiderClient>>,
+    cider: &Arc<RwLock<C>>,
     network_handle: &Arc<RwLock<Option<NetworkHandle>>>,
     latency_tracker: &SharedLatencyTracker,
     seek_calibrator: &SharedSeekCalibrator,
     local_peer_id: &str,
+    sync_settings: &Arc<SyncSettings>,
+    player: &Player,
+    room_broadcaster: &RoomBroadcaster,
 ) {
+    let follower = || Follower {
+        room: Arc::clone(room),
+        callback: Arc::clone(callback),
+        cider: Arc::clone(cider),
+        network_handle: Arc::clone(network_handle),
+        latency_tracker: Arc::clone(latency_tracker),
+        seek_calibrator: Arc::clone(seek_calibrator),
+        sync_settings: Arc::clone(sync_settings),
+    };
+
     match message {
-        SyncMessage::JoinRequest { display_name } => {
-            handle_join_request(from, display_name, room, callback, network_handle);
+        SyncMessage::JoinRequest { display_name, storefront, capabilities, profile, client, status } => {
+            let participant = InternalParticipant {
+                peer_id: from,
+                display_name,
+                is_host: false,
+                storefront,
+                capabilities,
+                profile: profile.sanitized(),
+                client: client.sanitized(),
+                status,
+            };
+            handle_join_request(participant, room, callback, network_handle, room_broadcaster);
         }
 
         SyncMessage::RoomState {
@@ -170,27 +280,39 @@ pub async fn handle_sync_message(
             participants,
             current_track,
             playback,
+            moderation,
+            settings,
         } => {
-            // RoomState must come from the claimed host (or we're joining and don't know yet)
-            let is_joining = {
-                let r = room.read().unwrap();
-                matches!(&*r, Room::Joining { .. })
-            };
-            if is_joining || from == host_peer_id {
-                handle_room_state(
+            // RoomState must come from the host it names (joining, that's how we learn who it is)
+            if from == host_peer_id {
+                let refused = match &*room.read().unwrap() {
+                    Room::Joining { refusal: Some((by, reason)), .. } if *by == from => Some(*reason),
+                    _ => None,
+                };
+                if let Some(reason) = refused {
+                    handle_removed(local_peer_id.to_string(), reason, room, callback, cider, network_handle, local_peer_id);
+                    return;
+                }
+                let joined_track = handle_room_state(
                     room_code,
                     host_peer_id,
                     participants,
                     current_track,
                     playback,
+                    moderation,
+                    settings,
                     room,
                     callback,
-                    cider,
                     network_handle,
                     latency_tracker,
-                    seek_calibrator,
                     local_peer_id,
-                ).await;
+                );
+                if let Some((track, playback)) = joined_track {
+                    let f = follower();
+                    player.run(async move {
+                        sync_joined_track(track, playback, &f).await;
+                    });
+                }
             } else {
                 warn!("Ignoring RoomState from non-host: {} (expected {})", from, host_peer_id);
             }
@@ -214,19 +336,73 @@ pub async fn handle_sync_message(
             }
         }
 
-        SyncMessage::TransferHost { new_host_peer_id } => {
+        SyncMessage::ParticipantUpdated { peer_id, display_name, profile } => {
+            // Participants can only update themselves
+            if from == peer_id {
+                handle_participant_updated(peer_id, display_name, profile.sanitized(), room, callback);
+            } else {
+                warn!("Ignoring ParticipantUpdated for {} from {}", peer_id, from);
+            }
+        }
+
+        SyncMessage::StatusUpdated { peer_id, status } => {
+            if from == peer_id {
+                handle_status_updated(peer_id, status, room, callback);
+            } else {
+                warn!("Ignoring StatusUpdated for {} from {}", peer_id, from);
+            }
+        }
+
+        SyncMessage::TransferHost { new_host_peer_id, track, playback } => {
             // Only current host can transfer
             if is_from_host(&from, room) {
-                handle_transfer_host(new_host_peer_id, room, callback);
+                handle_transfer_host(new_host_peer_id, track, playback, room, callback, network_handle);
             } else {
                 warn!("Ignoring TransferHost from non-host: {}", from);
             }
         }
 
+        SyncMessage::RoomCodeChanged { room_code } => {
+            if is_from_host(&from, room) {
+                handle_room_code_changed(room_code, room, callback, network_handle);
+            } else {
+                warn!("Ignoring RoomCodeChanged from non-host: {}", from);
+            }
+        }
+
+        SyncMessage::ControlRequest => {
+            handle_control_request(from, room, callback, std::time::Instant::now());
+        }
+
+        SyncMessage::RoomStateRequest => {
+            if room.read().unwrap().state().is_some_and(|s| s.is_host()) {
+                debug!("{} asked for the room state", from);
+                room_broadcaster.request();
+            }
+        }
+
+        SyncMessage::Removed { peer_id, reason } => {
+            // Only the host removes people. A joiner doesn't know the host yet:
+            // it holds on to being turned away until the room state shows who is
+            if is_from_host(&from, room) {
+                handle_removed(peer_id, reason, room, callback, cider, network_handle, local_peer_id);
+            } else if peer_id == local_peer_id && hold_refusal(&from, reason, room) {
+                info!("Turned away by {} ({:?}), if it's the host", from, reason);
+            } else {
+                warn!("Ignoring Removed from non-host: {}", from);
+            }
+        }
+
         SyncMessage::Play { track, position_ms, .. } => {
             // Only host controls playback
             if is_from_host(&from, room) {
-                handle_play(track, position_ms, room, cider, seek_calibrator).await;
+                let f = follower();
+                player.run(async move {
+                    let song_id = track.song_id.clone();
+                    if !handle_play(track, position_ms, &f.room, &f.callback, &f.cider, &f.seek_calibrator).await {
+                        report_track_unavailable(&song_id, &f.network_handle);
+                    }
+                });
             } else {
                 warn!("Ignoring Play from non-host: {}", from);
             }
@@ -234,7 +410,8 @@ pub async fn handle_sync_message(
 
         SyncMessage::Pause { position_ms, .. } => {
             if is_from_host(&from, room) {
-                handle_pause(position_ms, room, cider).await;
+                let f = follower();
+                player.run(async move { handle_pause(position_ms, &f.room, &f.cider).await });
             } else {
                 warn!("Ignoring Pause from non-host: {}", from);
             }
@@ -242,7 +419,8 @@ pub async fn handle_sync_message(
 
         SyncMessage::Seek { position_ms, .. } => {
             if is_from_host(&from, room) {
-                handle_seek(position_ms, room, cider, seek_calibrator).await;
+                let f = follower();
+                player.run(async move { handle_seek(position_ms, &f.room, &f.cider, &f.seek_calibrator).await });
             } else {
                 warn!("Ignoring Seek from non-host: {}", from);
             }
@@ -250,131 +428,171 @@ pub async fn handle_sync_message(
 
         SyncMessage::TrackChange { track, position_ms, timestamp_ms } => {
             if is_from_host(&from, room) {
-                handle_track_change(track, position_ms, timestamp_ms, room, callback, cider, seek_calibrator).await;
+                record_track(&track, room, callback);
+                let f = follower();
+                player.run(async move {
+                    let song_id = track.song_id.clone();
+                    let playable = handle_track_change(track, position_ms, timestamp_ms, &f).await;
+                    if !playable {
+                        report_track_unavailable(&song_id, &f.network_handle);
+                    }
+                });
             } else {
                 warn!("Ignoring TrackChange from non-host: {}", from);
             }
         }
 
-        SyncMessage::Heartbeat { track_id: _, playback } => {
+        SyncMessage::TrackUnavailable { song_id } => {
+            handle_track_unavailable(from, song_id, room, callback);
+        }
+
+        SyncMessage::Heartbeat { track_id: _, playback, room_hash: _ } => {
+            // The room hash is checked on the actor (see `check_room_hash`)
             if is_from_host(&from, room) {
-                handle_heartbeat(playback, room, callback, cider, latency_tracker, seek_calibrator).await;
+                record_host_playback(&playback, room, callback);
+                let f = follower();
+                player.run_heartbeat(async move {
+                    handle_heartbeat(
+                        playback,
+                        &f.room,
+                        &f.callback,
+                        &f.cider,
+                        &f.latency_tracker,
+                        &f.seek_calibrator,
+                        &f.sync_settings,
+                    )
+                    .await;
+                });
             } else {
                 debug!("Ignoring Heartbeat from non-host: {}", from);
             }
         }
 
-        // Ping/Pong for latency measurement
+        // Recorded for the recap by the room actor
+        SyncMessage::SyncReport(_) => {}
+
+        // Pings on the room topic, from older versions and peers we aren't connected to;
+        // they're meant for the host, and every listener answering would flood the room
         SyncMessage::Ping { sent_at_ms } => {
-            // Respond with Pong containing the original timestamp
+            if !room.read().unwrap().state().is_some_and(|s| s.is_host()) {
+                return;
+            }
             if let Some(handle) = network_handle.read().unwrap().as_ref() {
                 let pong = SyncMessage::Pong {
                     ping_sent_at_ms: sent_at_ms,
-                    received_at_ms: super::types::current_time_ms(),
+                    received_at_ms: latency_tracker.read().unwrap().now_ms(),
                 };
                 let _ = handle.broadcast(pong);
             }
         }
 
-        SyncMessage::Pong { ping_sent_at_ms, .. } => {
-            // Record RTT measurement
-            let mut tracker = latency_tracker.write().unwrap();
-            if let Some(rtt) = tracker.handle_pong(&from, ping_sent_at_ms) {
-                debug!("Measured RTT to {}: {}ms", from, rtt);
-            }
+        SyncMessage::Pong { ping_sent_at_ms, received_at_ms } => {
+            handle_pong(&from, ping_sent_at_ms, received_at_ms, room, latency_tracker);
         }
 
         SyncMessage::JoinResponse { .. } => {}
     }
 }
 
+/// Record the RTT and clock offset measured by a peer's answer to our ping
+fn handle_pong(
+    from: &str,
+    ping_sent_at_ms: u64,
+    received_at_ms: u64,
+    room: &Arc<RwLock<Room>>,
+    latency_tracker: &SharedLatencyTracker,
+) {
+    let rtt = latency_tracker.write().unwrap().handle_pong(from, ping_sent_at_ms, received_at_ms);
+    if let Some(rtt) = rtt {
+        debug!("Measured RTT to {}: {}ms", from, rtt);
+
+        // The host answering our pings keeps the room alive when we've stopped receiving heartbeats
+        if let Some(state) = room.write().unwrap().state_mut() {
+            if state.host_peer_id == from {
+                state.last_heartbeat = std::time::Instant::now();
+            }
+        }
+    }
+}
+
 fn handle_join_request(
-    from: String,
-    display_name: String,
+    participant: InternalParticipant,
     room: &Arc<RwLock<Room>>,
     callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
     network_handle: &Arc<RwLock<Option<NetworkHandle>>>,
+    room_broadcaster: &RoomBroadcaster,
 ) {
     // Only host handles join requests
     let mut room_guard = room.write().unwrap();
     if let Some(state) = room_guard.state_mut() {
         if state.is_host() {
+            if let Some(reason) = state.refusal_for(&participant.peer_id) {
+                info!("Refusing join request from {}: {:?}", participant.peer_id, reason);
+                if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                    let _ = handle.broadcast(SyncMessage::Removed { peer_id: participant.peer_id, reason });
+                }
+                room_broadcaster.request();
+                return;
+            }
+
             // Check if this is a new participant or updating an existing "?" entry
-            let was_unknown = state.participants.get(&from)
+            let from = &participant.peer_id;
+            let was_unknown = state.participants.get(from)
                 .map(|p| p.display_name == "?")
                 .unwrap_or(false);
-            let is_new = !state.participants.contains_key(&from);
+            let is_new = !state.participants.contains_key(from);
 
-            info!("Join request from {} ({}) - new: {}, was_unknown: {}",
-                  display_name, from, is_new, was_unknown);
+            info!("Join request from {} ({}, storefront {:?}, {:?}, {:?}) - new: {}, was_unknown: {}",
+                  participant.display_name, from, participant.storefront, participant.capabilities, participant.client,
+                  is_new, was_unknown);
 
             // Add/update participant
-            state.add_participant(InternalParticipant {
-                peer_id: from.clone(),
-                display_name: display_name.clone(),
-                is_host: false,
-            });
+            let joined = Participant::from(&participant);
+            state.add_participant(participant);
 
             // Notify callback
             if let Some(cb) = callback.read().unwrap().as_ref() {
                 // Only fire on_participant_joined for truly new participants
                 // (not for "?" → real name updates, those come via room_state_changed)
                 if is_new {
-                    cb.on_participant_joined(Participant {
-                        peer_id: from.clone(),
-                        display_name: display_name.clone(),
-                        is_host: false,
-                    });
+                    cb.on_participant_joined(joined);
                 }
                 cb.on_room_state_changed(RoomState::from(&*state));
             }
 
             // Broadcast updated room state
-            if let Some(handle) = network_handle.read().unwrap().as_ref() {
-                let msg = SyncMessage::RoomState {
-                    room_code: state.room_code.clone(),
-                    host_peer_id: state.host_peer_id.clone(),
-                    participants: state.participant_list().iter().map(|p| InternalParticipant {
-                        peer_id: p.peer_id.clone(),
-                        display_name: p.display_name.clone(),
-                        is_host: p.is_host,
-                    }).collect(),
-                    current_track: state.current_track.clone(),
-                    playback: state.playback.clone(),
-                };
-                let _ = handle.broadcast(msg);
-            }
+            room_broadcaster.request();
         }
     }
 }
 
-async fn handle_room_state(
+/// Take the room state the host sent; returns its track and playback if we
+/// just joined, for Cider to catch up with (see `sync_joined_track`)
+fn handle_room_state(
     room_code: String,
     host_peer_id: String,
     participants: Vec<InternalParticipant>,
     current_track: Option<crate::sync::TrackInfo>,
     playback: crate::sync::PlaybackInfo,
+    moderation: Moderation,
+    settings: InternalRoomSettings,
     room: &Arc<RwLock<Room>>,
     callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
-    cider: &Arc<RwLock<CiderClient>>,
     network_handle: &Arc<RwLock<Option<NetworkHandle>>>,
     latency_tracker: &SharedLatencyTracker,
-    seek_calibrator: &SharedSeekCalibrator,
     local_peer_id: &str,
-) {
-    use crate::sync::RoomState as InternalRoomState;
-
+) -> Option<(crate::sync::TrackInfo, crate::sync::PlaybackInfo)> {
     // Set the host in latency tracker for accurate sync
     {
         let mut tracker = latency_tracker.write().unwrap();
         tracker.set_host(host_peer_id.clone());
     }
 
-    // Track info for syncing after we release the lock
-    // (song_id, position_ms, timestamp_ms, is_playing)
-    let track_to_sync: Option<(String, u64, u64, bool)>;
+    // Track to sync to after we release the lock
+    let track_to_sync: Option<(crate::sync::TrackInfo, crate::sync::PlaybackInfo)>;
     let was_joining: bool;
-    let display_name_for_join: String;
+    // Us, as we ask to join
+    let me: InternalParticipant;
 
     {
         let mut room_guard = room.write().unwrap();
@@ -387,39 +605,61 @@ async fn handle_room_state(
         };
 
         if !should_update {
-            return;
+            return None;
         }
 
-        let display_name = match &*room_guard {
-            Room::Joining { display_name, .. } => display_name.clone(),
+        let listener = |display_name: String| InternalParticipant {
+            peer_id: local_peer_id.to_string(),
+            display_name,
+            is_host: false,
+            storefront: None,
+            capabilities: Capabilities::default(),
+            profile: Profile::default(),
+            client: ClientInfo::default(),
+            status: ParticipantStatus::default(),
+        };
+        me = match &*room_guard {
+            Room::Joining { display_name, storefront, capabilities, profile, client, status, .. } => InternalParticipant {
+                storefront: storefront.clone(),
+                capabilities: capabilities.clone(),
+                profile: profile.clone(),
+                client: client.clone(),
+                status: *status,
+                ..listener(display_name.clone())
+            },
             Room::Active(state) => state.participants.get(&state.local_peer_id)
-                .map(|p| p.display_name.clone())
-                .unwrap_or_else(|| "Listener".to_string()),
-            _ => "Listener".to_string(),
+                .cloned()
+                .unwrap_or_else(|| listener("Listener".to_string())),
+            _ => listener("Listener".to_string()),
         };
-        display_name_for_join = display_name.clone();
 
         info!("Received room state from host");
 
         // Capture track info before updating state (including timestamp for accurate sync)
-        track_to_sync = current_track.as_ref().map(|t| {
-            (t.song_id.clone(), playback.position_ms, playback.timestamp_ms, playback.is_playing)
-        });
+        track_to_sync = current_track.as_ref().map(|t| (t.clone(), playback.clone()));
 
         let mut new_state = InternalRoomState::new_as_host(
             room_code.clone(),
             local_peer_id.to_string(),
-            display_name,
+            me.display_name.clone(),
+            me.storefront.clone(),
+            me.capabilities.clone(),
+            me.profile.clone(),
+            me.client.clone(),
         );
         new_state.host_peer_id = host_peer_id;
         new_state.current_track = current_track;
         new_state.playback = playback;
+        new_state.moderation = moderation;
+        new_state.settings = settings;
 
         // Clear default self-participant and add actual participants
         new_state.participants.clear();
         for p in participants {
             new_state.add_participant(p);
         }
+        // Our status is ours to tell; the host's copy may lag behind it
+        new_state.set_participant_status(local_peer_id, me.status);
 
         was_joining = matches!(&*room_guard, Room::Joining { .. });
         *room_guard = Room::Active(new_state);
@@ -438,9 +678,14 @@ async fn handle_room_state(
     // (the initial JoinRequest during Joining state may not have reached the host yet)
     if was_joining {
         if let Some(handle) = network_handle.read().unwrap().as_ref() {
-            info!("Sending JoinRequest after joining: {}", display_name_for_join);
+            info!("Sending JoinRequest after joining: {}", me.display_name);
             let join_msg = SyncMessage::JoinRequest {
-                display_name: display_name_for_join,
+                display_name: me.display_name,
+                storefront: me.storefront,
+                capabilities: me.capabilities,
+                profile: me.profile,
+                client: me.client,
+                status: me.status,
             };
             let _ = handle.broadcast(join_msg);
         }
@@ -448,56 +693,49 @@ async fn handle_room_state(
 
     // Sync Cider to host's track when joining
     if was_joining {
-        if let Some((song_id, position_ms, timestamp_ms, is_playing)) = track_to_sync {
-            info!("Syncing Cider to host's track: {} at {}ms", song_id, position_ms);
-            let cider_client = cider.read().unwrap().clone();
-
-            // Start playing the track
-            let _ = cider_client.play_item("songs", &song_id).await;
-
-            // Poll until track is actually loaded (max 5 seconds)
-            let max_wait = Duration::from_secs(5);
-            let poll_interval = Duration::from_millis(100);
-            let start = std::time::Instant::now();
-
-            loop {
-                if start.elapsed() > max_wait {
-                    warn!("Timeout waiting for track to load, seeking anyway");
-                    break;
-                }
-
-                if let Ok(Some(np)) = cider_client.now_playing().await {
-                    if np.song_id() == Some(&song_id) {
-                        info!("Track loaded after {:?}", start.elapsed());
-                        break;
-                    }
-                }
+        track_to_sync
+    } else {
+        None
+    }
+}
 
-                tokio::time::sleep(poll_interval).await;
-            }
+/// Play the host's track where the host is, having just joined
+async fn sync_joined_track<C: CiderApi>(
+    track: crate::sync::TrackInfo,
+    playback: crate::sync::PlaybackInfo,
+    f: &Follower<C>,
+) {
+    let Follower { room, callback, cider, network_handle, latency_tracker, seek_calibrator, .. } = f;
+    info!("Syncing Cider to host's track: {} at {}ms", track.song_id, playback.position_ms);
+    let cider_client = cider.read().unwrap().clone();
+
+    // Start playing the track (nothing to seek if it can't be played here)
+    let host_storefront = room.read().unwrap().host_storefront().map(str::to_string);
+    if load_track(&cider_client, &track, host_storefront.as_deref(), callback).await.is_none() {
+        report_track_unavailable(&track.song_id, network_handle);
+        return;
+    }
 
-            // Calculate actual position accounting for elapsed time since heartbeat
-            let now = super::types::current_time_ms();
-            let elapsed_since_heartbeat = now.saturating_sub(timestamp_ms);
-            let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms();
-            let actual_position = if is_playing {
-                // Add seek_offset to compensate for Cider's buffering delay
-                position_ms + elapsed_since_heartbeat + seek_offset_ms
-            } else {
-                position_ms
-            };
+    // Calculate actual position accounting for elapsed time since heartbeat
+    let now = latency_tracker.read().unwrap().now_ms();
+    let elapsed_since_heartbeat = now.saturating_sub(playback.timestamp_ms);
+    let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms(SeekKind::TrackStart);
+    let actual_position = if playback.is_playing {
+        // Add seek_offset to compensate for Cider's buffering delay
+        playback.position_ms + elapsed_since_heartbeat + seek_offset_ms
+    } else {
+        playback.position_ms
+    };
 
-            info!("Seeking to adjusted position: {}ms (original: {}ms, elapsed: {}ms, offset: {}ms)",
-                actual_position, position_ms, elapsed_since_heartbeat, seek_offset_ms);
+    info!("Seeking to adjusted position: {}ms (original: {}ms, elapsed: {}ms, offset: {}ms)",
+        actual_position, playback.position_ms, elapsed_since_heartbeat, seek_offset_ms);
 
-            let _ = cider_client.seek_ms(actual_position).await;
+    let _ = cider_client.seek_ms(actual_position).await;
 
-            // Mark that we just seeked - next heartbeat will calibrate
-            {
-                let mut calibrator = seek_calibrator.write().unwrap();
-                calibrator.mark_seek_performed();
-            }
-        }
+    // Mark that we just seeked - next heartbeat will calibrate
+    {
+        let mut calibrator = seek_calibrator.write().unwrap();
+        calibrator.mark_seek_performed(SeekKind::TrackStart);
     }
 }
 
@@ -508,23 +746,63 @@ fn handle_participant_joined(
 ) {
     let mut room_guard = room.write().unwrap();
     if let Some(state) = room_guard.state_mut() {
-        state.add_participant(InternalParticipant {
-            peer_id: participant.peer_id.clone(),
-            display_name: participant.display_name.clone(),
-            is_host: participant.is_host,
-        });
+        let joined = Participant::from(&participant);
+        state.add_participant(participant);
 
         if let Some(cb) = callback.read().unwrap().as_ref() {
-            cb.on_participant_joined(Participant {
-                peer_id: participant.peer_id,
-                display_name: participant.display_name,
-                is_host: participant.is_host,
-            });
+            cb.on_participant_joined(joined);
             cb.on_room_state_changed(RoomState::from(&*state));
         }
     }
 }
 
+fn handle_removed<C: CiderApi>(
+    peer_id: String,
+    reason: RemovalReason,
+    room: &Arc<RwLock<Room>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    cider: &Arc<RwLock<C>>,
+    network_handle: &Arc<RwLock<Option<NetworkHandle>>>,
+    local_peer_id: &str,
+) {
+    if peer_id != local_peer_id {
+        handle_participant_left(peer_id, room, callback);
+        return;
+    }
+
+    info!("Removed from the room by the host: {:?}", reason);
+    *room.write().unwrap() = Room::None;
+    if let Some(handle) = network_handle.read().unwrap().as_ref() {
+        let _ = handle.leave_room();
+    }
+
+    // Don't keep playing the host's music
+    let cider_client = cider.read().unwrap().clone();
+    tokio::spawn(async move {
+        let _ = cider_client.pause().await;
+    });
+
+    if let Some(cb) = callback.read().unwrap().as_ref() {
+        let message = match reason {
+            RemovalReason::Kicked => "Removed from the room by the host",
+            RemovalReason::Banned => "Banned from the room by the host",
+            RemovalReason::RoomLocked => "The room is locked",
+            RemovalReason::RoomFull => "The room is full",
+        };
+        cb.on_room_ended(message.to_string());
+    }
+}
+
+/// Note that `from` turned us away while we're joining; false if we aren't
+fn hold_refusal(from: &str, reason: RemovalReason, room: &Arc<RwLock<Room>>) -> bool {
+    let mut room = room.write().unwrap();
+    let Room::Joining { refusal, .. } = &mut *room else {
+        return false;
+    };
+    *refusal = Some((from.to_string(), reason));
+    true
+}
+
 fn handle_participant_left(
     peer_id: String,
     room: &Arc<RwLock<Room>>,
@@ -541,56 +819,203 @@ fn handle_participant_left(
     }
 }
 
+fn handle_participant_updated(
+    peer_id: String,
+    display_name: String,
+    profile: Profile,
+    room: &Arc<RwLock<Room>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+) {
+    let mut room_guard = room.write().unwrap();
+    if let Some(state) = room_guard.state_mut() {
+        if !state.update_participant(&peer_id, display_name, profile) {
+            debug!("Ignoring ParticipantUpdated for unknown peer {}", peer_id);
+            return;
+        }
+
+        if let Some(cb) = callback.read().unwrap().as_ref() {
+            cb.on_room_state_changed(RoomState::from(&*state));
+        }
+    }
+}
+
+fn handle_status_updated(
+    peer_id: String,
+    status: ParticipantStatus,
+    room: &Arc<RwLock<Room>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+) {
+    let mut room_guard = room.write().unwrap();
+    if let Some(state) = room_guard.state_mut() {
+        if !state.set_participant_status(&peer_id, status) {
+            debug!("Ignoring StatusUpdated for unknown peer {}", peer_id);
+            return;
+        }
+
+        if let Some(cb) = callback.read().unwrap().as_ref() {
+            cb.on_room_state_changed(RoomState::from(&*state));
+        }
+    }
+}
+
 fn handle_transfer_host(
     new_host_peer_id: String,
+    track: Option<crate::sync::TrackInfo>,
+    playback: Option<crate::sync::PlaybackInfo>,
     room: &Arc<RwLock<Room>>,
     callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    network_handle: &Arc<RwLock<Option<NetworkHandle>>>,
 ) {
     let mut room_guard = room.write().unwrap();
     if let Some(state) = room_guard.state_mut() {
         state.transfer_host(&new_host_peer_id);
 
+        // The room carries on from the old host's last playback, which the
+        // new host's first heartbeat goes on from
+        if let Some(playback) = playback {
+            state.update_track(track);
+            state.update_playback(playback);
+        }
+
+        // The host needs every message, whatever we skipped as a listener
+        if state.is_host() {
+            if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                for class in MessageClass::ALL {
+                    let _ = handle.set_receives(class, true);
+                }
+            }
+        }
+
         if let Some(cb) = callback.read().unwrap().as_ref() {
             cb.on_room_state_changed(RoomState::from(&*state));
         }
     }
 }
 
-async fn handle_play(
+/// The host gave the room a new code: follow it to the new topic
+fn handle_room_code_changed(
+    room_code: String,
+    room: &Arc<RwLock<Room>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    network_handle: &Arc<RwLock<Option<NetworkHandle>>>,
+) {
+    let Some(code) = RoomCode::parse(&room_code) else {
+        warn!("Ignoring invalid room code from host: {}", room_code);
+        return;
+    };
+    let mut room_guard = room.write().unwrap();
+    let Some(state) = room_guard.state_mut().filter(|s| s.room_code != code.as_str()) else {
+        return;
+    };
+
+    info!("Room code changed from {} to {}", state.room_code, code);
+    state.room_code = code.as_str().to_string();
+    if let Some(handle) = network_handle.read().unwrap().as_ref() {
+        let _ = handle.change_room_code(code.as_str());
+    }
+    if let Some(cb) = callback.read().unwrap().as_ref() {
+        cb.on_room_state_changed(RoomState::from(&*state));
+    }
+}
+
+/// A listener couldn't play our track (host only)
+fn handle_track_unavailable(
+    from: String,
+    song_id: String,
+    room: &Arc<RwLock<Room>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+) {
+    let room_guard = room.read().unwrap();
+    let Some(state) = room_guard.state().filter(|s| s.is_host()) else {
+        return;
+    };
+    // Reports about an earlier track are stale
+    let Some(track) = state.current_track.as_ref().filter(|t| t.song_id == song_id) else {
+        return;
+    };
+    let Some(participant) = state.participants.get(&from) else {
+        return;
+    };
+
+    warn!(
+        "{} (storefront {:?}) can't play {} - {}",
+        participant.display_name, participant.storefront, track.artist, track.name
+    );
+    if let Some(cb) = callback.read().unwrap().as_ref() {
+        cb.on_listener_track_unavailable(Participant::from(participant), TrackInfo::from(track.clone()));
+    }
+}
+
+/// A listener asked to become host (host only)
+fn handle_control_request(
+    from: String,
+    room: &Arc<RwLock<Room>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    now: std::time::Instant,
+) {
+    let mut room_guard = room.write().unwrap();
+    let Some(state) = room_guard.state_mut().filter(|s| s.is_host()) else {
+        return;
+    };
+    if !state.participants.contains_key(&from) {
+        return;
+    }
+    // Muting someone's suggestions silences their requests too
+    if !state.accepts_suggestions_from(&from) {
+        debug!("Ignoring control request from muted {}", from);
+        return;
+    }
+    if !state.takes_control_request(&from, now) {
+        debug!("Ignoring repeated control request from {}", from);
+        return;
+    }
+    let participant = &state.participants[&from];
+
+    info!("{} asked to become host", participant.display_name);
+    if let Some(cb) = callback.read().unwrap().as_ref() {
+        cb.on_control_requested(Participant::from(participant));
+    }
+}
+
+/// Returns false if the host's track can't be played here
+async fn handle_play<C: CiderApi>(
     track: crate::sync::TrackInfo,
     position_ms: u64,
     room: &Arc<RwLock<Room>>,
-    cider: &Arc<RwLock<CiderClient>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    cider: &Arc<RwLock<C>>,
     seek_calibrator: &SharedSeekCalibrator,
-) {
+) -> bool {
     // Non-host: sync to host's playback
-    let should_sync = {
+    let (should_sync, host_storefront) = {
         let room_guard = room.read().unwrap();
-        room_guard.state().map(|s| !s.is_host()).unwrap_or(false)
+        let should_sync = room_guard.state().map(|s| !s.is_host()).unwrap_or(false);
+        (should_sync, room_guard.host_storefront().map(str::to_string))
     };
 
     if should_sync {
         let cider_client = cider.read().unwrap().clone();
-        let song_id = track.song_id.clone();
-        let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms();
         // Play the same track at the same position + offset to compensate for buffer delay
-        let _ = cider_client.play_item("songs", &song_id).await;
-        tokio::time::sleep(Duration::from_millis(100)).await;
+        if load_track(&cider_client, &track, host_storefront.as_deref(), callback).await.is_none() {
+            return false;
+        }
+        let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms(SeekKind::TrackStart);
         let _ = cider_client.seek_ms(position_ms + seek_offset_ms).await;
         let _ = cider_client.play().await;
 
         // Mark that we just seeked - next heartbeat will calibrate
         {
             let mut calibrator = seek_calibrator.write().unwrap();
-            calibrator.mark_seek_performed();
+            calibrator.mark_seek_performed(SeekKind::TrackStart);
         }
     }
+    true
 }
 
-async fn handle_pause(
+async fn handle_pause<C: CiderApi>(
     position_ms: u64,
     room: &Arc<RwLock<Room>>,
-    cider: &Arc<RwLock<CiderClient>>,
+    cider: &Arc<RwLock<C>>,
 ) {
     let should_sync = {
         let room_guard = room.read().unwrap();
@@ -604,10 +1029,10 @@ async fn handle_pause(
     }
 }
 
-async fn handle_seek(
+async fn handle_seek<C: CiderApi>(
     position_ms: u64,
     room: &Arc<RwLock<Room>>,
-    cider: &Arc<RwLock<CiderClient>>,
+    cider: &Arc<RwLock<C>>,
     seek_calibrator: &SharedSeekCalibrator,
 ) {
     let should_sync = {
@@ -617,61 +1042,38 @@ async fn handle_seek(
 
     if should_sync {
         let cider_client = cider.read().unwrap().clone();
-        let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms();
+        let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms(SeekKind::InTrack);
         let _ = cider_client.seek_ms(position_ms + seek_offset_ms).await;
 
         // Mark that we just seeked - next heartbeat will calibrate
         {
             let mut calibrator = seek_calibrator.write().unwrap();
-            calibrator.mark_seek_performed();
+            calibrator.mark_seek_performed(SeekKind::InTrack);
         }
     }
 }
 
-async fn handle_track_change(
+/// Returns false if the host's track can't be played here
+async fn handle_track_change<C: CiderApi>(
     track: crate::sync::TrackInfo,
     position_ms: u64,
     timestamp_ms: u64,
-    room: &Arc<RwLock<Room>>,
-    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
-    cider: &Arc<RwLock<CiderClient>>,
-    seek_calibrator: &SharedSeekCalibrator,
-) {
-    let is_host = {
+    f: &Follower<C>,
+) -> bool {
+    let Follower { room, callback, cider, latency_tracker, seek_calibrator, .. } = f;
+    let (is_host, host_storefront) = {
         let room_guard = room.read().unwrap();
-        room_guard.state().map(|s| s.is_host()).unwrap_or(false)
+        let is_host = room_guard.state().map(|s| s.is_host()).unwrap_or(false);
+        (is_host, room_guard.host_storefront().map(str::to_string))
     };
 
-    if !is_host {
-        let cider_client = cider.read().unwrap().clone();
-        let song_id = track.song_id.clone();
-        let _ = cider_client.play_item("songs", &song_id).await;
-
-        // Poll until track is actually loaded (max 5 seconds)
-        let max_wait = Duration::from_secs(5);
-        let poll_interval = Duration::from_millis(100);
-        let start = std::time::Instant::now();
-
-        loop {
-            if start.elapsed() > max_wait {
-                warn!("TrackChange: timeout waiting for track to load");
-                break;
-            }
-
-            if let Ok(Some(np)) = cider_client.now_playing().await {
-                if np.song_id() == Some(&song_id) {
-                    info!("TrackChange: track loaded after {:?}", start.elapsed());
-                    break;
-                }
-            }
-
-            tokio::time::sleep(poll_interval).await;
-        }
-
+    let cider_client = cider.read().unwrap().clone();
+    let playable = is_host || load_track(&cider_client, &track, host_storefront.as_deref(), callback).await.is_some();
+    if !is_host && playable {
         // Calculate actual position accounting for elapsed time + seek offset
-        let now = super::types::current_time_ms();
+        let now = latency_tracker.read().unwrap().now_ms();
         let elapsed = now.saturating_sub(timestamp_ms);
-        let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms();
+        let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms(SeekKind::TrackStart);
         let actual_position = position_ms + elapsed + seek_offset_ms;
 
         info!("TrackChange: seeking to {}ms (original: {}ms, elapsed: {}ms, offset: {}ms)",
@@ -682,72 +1084,390 @@ async fn handle_track_change(
         // Mark that we just seeked - next heartbeat will calibrate
         {
             let mut calibrator = seek_calibrator.write().unwrap();
-            calibrator.mark_seek_performed();
+            calibrator.mark_seek_performed(SeekKind::TrackStart);
         }
     }
 
-    // Update local state
+    playable
+}
+
+/// The host's new track, in the room (the actor's side of a TrackChange)
+fn record_track(
+    track: &crate::sync::TrackInfo,
+    room: &Arc<RwLock<Room>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+) {
     let mut room_guard = room.write().unwrap();
     if let Some(state) = room_guard.state_mut() {
         state.update_track(Some(track.clone()));
         if let Some(cb) = callback.read().unwrap().as_ref() {
-            cb.on_track_changed(Some(TrackInfo::from(track)));
+            cb.on_track_changed(Some(TrackInfo::from(track.clone())));
         }
     }
 }
 
-/// Maximum position drift (in ms) before we re-sync the listener
-const DRIFT_THRESHOLD_MS: u64 = 3000;
+/// How long Cider gets to start a track we asked for
+const TRACK_LOAD_TIMEOUT: Duration = Duration::from_secs(5);
 
-async fn handle_heartbeat(
-    playback: crate::sync::PlaybackInfo,
-    room: &Arc<RwLock<Room>>,
+/// Max length difference (in ms) for a name+artist search hit to count as the same recording
+const MATCH_DURATION_TOLERANCE_MS: u64 = 3000;
+
+/// Play the host's track, or the same recording from our storefront if it isn't available here
+///
+/// Returns the ID of the song now playing, or None (after notifying the UI) if neither plays.
+async fn load_track<C: CiderApi>(
+    cider: &C,
+    track: &crate::sync::TrackInfo,
+    host_storefront: Option<&str>,
     callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
-    cider: &Arc<RwLock<CiderClient>>,
-    latency_tracker: &SharedLatencyTracker,
-    seek_calibrator: &SharedSeekCalibrator,
-) {
-    // Check if we're a listener and need to sync
-    let should_sync = {
-        let room_guard = room.read().unwrap();
-        room_guard.state().map(|s| !s.is_host()).unwrap_or(false)
-    };
+) -> Option<String> {
+    if play_and_wait(cider, &track.song_id).await {
+        return Some(track.song_id.clone());
+    }
 
-    if should_sync {
-        // Get estimated one-way latency to host and seek offset
-        let latency_ms = latency_tracker.read().unwrap().host_latency_ms();
-        let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms();
+    warn!("Track {} ({} - {}) didn't play, looking for it in our storefront", track.song_id, track.artist, track.name);
+    if let Some(id) = find_in_catalog(cider, track, host_storefront).await {
+        if play_and_wait(cider, &id).await {
+            info!("Playing catalog match {} for {}", id, track.song_id);
+            return Some(id);
+        }
+    }
 
-        // Get current Cider playback state first
-        let cider_client = cider.read().unwrap().clone();
+    warn!("Track {} is unavailable in our storefront", track.song_id);
+    if let Some(cb) = callback.read().unwrap().as_ref() {
+        cb.on_track_unavailable(TrackInfo::from(track.clone()));
+    }
+    None
+}
 
-        // Check current position from now_playing
-        if let Ok(Some(np)) = cider_client.now_playing().await {
-            // Calculate expected position NOW (after async call completes)
-            // This gives more accurate comparison since current_position is also "now"
-            let now = super::types::current_time_ms();
-            let elapsed_since_heartbeat = now.saturating_sub(playback.timestamp_ms);
-
-            // Expected position for COMPARISON (where host actually is + network latency)
-            // Does NOT include seek_offset - that's only for when we actually seek
-            let expected_position = if playback.is_playing {
-                playback.position_ms + elapsed_since_heartbeat + latency_ms
-            } else {
-                playback.position_ms
-            };
-            let current_position = np.current_position_ms();
+/// Ask Cider to play a song and wait until it's playing
+///
+/// Fails right away if Cider rejects the request, or after a timeout if it
+/// accepts it but keeps playing something else (how missing tracks usually fail).
+async fn play_and_wait<C: CiderApi>(cider: &C, song_id: &str) -> bool {
+    if let Err(e) = cider.play_item("songs", song_id).await {
+        debug!("Cider refused to play {}: {}", song_id, e);
+        return false;
+    }
 
-            // Check if we're drifted too far from expected position
-            let drift_signed = current_position as i64 - expected_position as i64;
-            let drift = drift_signed.unsigned_abs();
+    let poll_interval = Duration::from_millis(100);
+    let start = Instant::now();
+    while start.elapsed() < TRACK_LOAD_TIMEOUT {
+        if let Ok(Some(np)) = cider.now_playing().await {
+            if np.song_id() == Some(song_id) {
+                info!("Track {} loaded after {:?}", song_id, start.elapsed());
+                return true;
+            }
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+    warn!("Timeout waiting for track {} to load", song_id);
+    false
+}
 
-            // Log sync accuracy for diagnostics (positive = ahead, negative = behind)
-            debug!(
-                "Sync: drift {:+}ms (expected: {}ms, actual: {}ms, latency: {}ms, seek_offset: {}ms, elapsed: {}ms)",
-                drift_signed, expected_position, current_position, latency_ms, seek_offset_ms, elapsed_since_heartbeat
-            );
+/// Look up the host's track in our storefront: by ISRC, else by name and artist
+async fn find_in_catalog<C: CiderApi>(
+    cider: &C,
+    track: &crate::sync::TrackInfo,
+    host_storefront: Option<&str>,
+) -> Option<String> {
+    // Same storefront as the host: the track isn't region-locked, so there's no other version to find
+    if let Some(host_storefront) = host_storefront {
+        if cider.storefront().await.is_ok_and(|ours| ours == host_storefront) {
+            debug!("Host shares our storefront ({}), not searching for {}", host_storefront, track.song_id);
+            return None;
+        }
+    }
+
+    if let Some(isrc) = track.isrc.as_deref() {
+        match cider.songs_by_isrc(isrc).await {
+            Ok(results) => {
+                if let Some(result) = results.into_iter().find(|r| r.id != track.song_id) {
+                    return Some(result.id);
+                }
+            }
+            Err(e) => debug!("ISRC lookup for {} failed: {}", isrc, e),
+        }
+    }
+
+    let term = format!("{} {}", track.name, track.artist);
+    match cider.search(&term, &["songs"]).await {
+        Ok(results) => results.into_iter().find(|r| is_same_recording(track, r)).map(|r| r.id),
+        Err(e) => {
+            debug!("Catalog search for {:?} failed: {}", term, e);
+            None
+        }
+    }
+}
+
+/// Tell the host we can't play its track
+fn report_track_unavailable(song_id: &str, network_handle: &Arc<RwLock<Option<NetworkHandle>>>) {
+    if let Some(handle) = network_handle.read().unwrap().as_ref() {
+        let _ = handle.broadcast(SyncMessage::TrackUnavailable {
+            song_id: song_id.to_string(),
+        });
+    }
+}
+
+/// Whether a search hit looks like the host's track: same title and artist, similar length
+fn is_same_recording(track: &crate::sync::TrackInfo, result: &SearchResult) -> bool {
+    result.id != track.song_id
+        && result.name.to_lowercase() == track.name.to_lowercase()
+        && result.artist.to_lowercase() == track.artist.to_lowercase()
+        && result
+            .duration_ms
+            .is_none_or(|d| d.abs_diff(track.duration_ms) <= MATCH_DURATION_TOLERANCE_MS)
+}
+
+/// How a listener follows the host
+#[derive(Debug)]
+pub struct SyncSettings {
+    /// How far we may drift from the host before re-syncing
+    pub drift_threshold_ms: u64,
+    /// How far ahead we may be before pausing for the host to catch up (0 = never)
+    pub catch_up_threshold_ms: u64,
+    /// How often the app gets `on_sync_status`
+    pub status_level: SyncStatusLevel,
+    /// Calibrate the seek offset with a few seeks right after joining
+    pub calibration_warm_up: bool,
+    /// When the last sync status was reported
+    last_status: Mutex<Option<Instant>>,
+    /// Drift measured on heartbeats, to correct before it's past the threshold
+    drift_model: Mutex<DriftModel>,
+    /// Drift and corrections since the room's recap last took them
+    stats: Mutex<SyncStats>,
+}
+
+impl SyncSettings {
+    pub fn new(drift_threshold_ms: u64, status_level: SyncStatusLevel) -> Self {
+        Self {
+            drift_threshold_ms,
+            catch_up_threshold_ms: 0,
+            status_level,
+            calibration_warm_up: false,
+            last_status: Mutex::new(None),
+            drift_model: Mutex::new(DriftModel::new()),
+            stats: Mutex::new(SyncStats::default()),
+        }
+    }
+
+    pub fn with_catch_up_threshold(mut self, catch_up_threshold_ms: u64) -> Self {
+        self.catch_up_threshold_ms = catch_up_threshold_ms;
+        self
+    }
+
+    pub fn with_calibration_warm_up(mut self, calibration_warm_up: bool) -> Self {
+        self.calibration_warm_up = calibration_warm_up;
+        self
+    }
+
+    /// Whether being `drift_ms` ahead of the playing host calls for a catch-up pause
+    fn catches_up(&self, drift_ms: i64) -> bool {
+        self.catch_up_threshold_ms > 0
+            && drift_ms > self.catch_up_threshold_ms as i64
+            && drift_ms.unsigned_abs() <= self.drift_threshold_ms
+    }
+
+    /// Drift and corrections since they were last taken
+    pub fn take_stats(&self) -> SyncStats {
+        std::mem::take(&mut *self.stats.lock().unwrap())
+    }
+
+    /// The drift model's state, for the debug view
+    fn drift_estimate(&self) -> DriftEstimate {
+        let model = self.drift_model.lock().unwrap();
+        DriftEstimate {
+            drift_ms: model.drift_ms(),
+            rate_ms_per_sec: model.rate_ms_per_sec(),
+            exceeds_threshold_in_ms: model
+                .time_to_threshold(self.drift_threshold_ms)
+                .map(|time| time.as_millis() as u64),
+            sample_count: model.sample_count(),
+        }
+    }
+
+    /// Whether a sync status report is due now; if so, it counts as reported
+    fn status_due(&self) -> bool {
+        match self.status_level {
+            SyncStatusLevel::Off => false,
+            SyncStatusLevel::Full => true,
+            SyncStatusLevel::Summary => {
+                let mut last = self.last_status.lock().unwrap();
+                if last.is_some_and(|at| at.elapsed() < SYNC_STATUS_SUMMARY_INTERVAL) {
+                    return false;
+                }
+                *last = Some(Instant::now());
+                true
+            }
+        }
+    }
+}
+
+/// Time since the host's `host_timestamp_ms`, as of our `now_ms`
+///
+/// With the host's clock offset measured, its timestamp is moved onto our
+/// clock, which covers the network delay too. Until then, our clocks are
+/// assumed to agree and the one-way latency is added on top.
+fn elapsed_since_host_time(host_timestamp_ms: u64, now_ms: u64, clock_offset_ms: Option<i64>, latency_ms: u64) -> u64 {
+    match clock_offset_ms {
+        Some(offset_ms) => (now_ms as i64 + offset_ms - host_timestamp_ms as i64).max(0) as u64,
+        None => now_ms.saturating_sub(host_timestamp_ms) + latency_ms,
+    }
+}
+
+/// Where the host's playback is now, if we're a listener and it's playing
+fn host_position_now(room: &Arc<RwLock<Room>>, latency_tracker: &SharedLatencyTracker) -> Option<u64> {
+    let playback = room.read().unwrap().state().filter(|s| !s.is_host())?.playback.clone();
+    if !playback.is_playing {
+        return None;
+    }
+    let (latency_ms, clock_offset_ms, now) = {
+        let tracker = latency_tracker.read().unwrap();
+        (tracker.host_latency_ms(), tracker.host_clock_offset_ms(), tracker.now_ms())
+    };
+    Some(playback.position_ms + elapsed_since_host_time(playback.timestamp_ms, now, clock_offset_ms, latency_ms))
+}
 
-            // Get calibration state for debug display (before we potentially update it)
+/// Where the room's playback is as of our `now_ms`, for progress bars: its
+/// last known position moved on by the time since, capped at the track's end
+///
+/// Listeners count the time since the host sent it as `sync_to_host` does.
+pub(super) fn estimated_position_ms(state: &InternalRoomState, latency_tracker: &SharedLatencyTracker, now_ms: u64) -> u64 {
+    let playback = &state.playback;
+    if !playback.is_playing {
+        return playback.position_ms;
+    }
+    let position_ms = if state.is_host() {
+        playback.position_ms + now_ms.saturating_sub(playback.timestamp_ms)
+    } else {
+        let (latency_ms, clock_offset_ms) = {
+            let tracker = latency_tracker.read().unwrap();
+            (tracker.host_latency_ms(), tracker.host_clock_offset_ms())
+        };
+        playback.position_ms + elapsed_since_host_time(playback.timestamp_ms, now_ms, clock_offset_ms, latency_ms)
+    };
+    match state.current_track.as_ref().filter(|t| t.duration_ms > 0) {
+        Some(track) => position_ms.min(track.duration_ms),
+        None => position_ms,
+    }
+}
+
+/// Calibrate the seek offset with a few measured seeks, right after joining
+///
+/// Left to drift corrections, the calibrator learns over minutes and makes a
+/// few noticeably bad corrections on the way. Seeking to the host in the first
+/// seconds and measuring where each seek landed, `settle` later, converges it
+/// quickly. Stops early if the host isn't playing or we've left the room.
+/// Returns the number of seeks measured.
+pub async fn warm_up_calibration<C: CiderApi>(
+    room: &Arc<RwLock<Room>>,
+    cider: &C,
+    latency_tracker: &SharedLatencyTracker,
+    seek_calibrator: &SharedSeekCalibrator,
+    settle: Duration,
+) -> u32 {
+    let mut measured = 0;
+    for _ in 0..WARM_UP_SEEKS {
+        let Some(target) = host_position_now(room, latency_tracker) else {
+            break;
+        };
+        let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms(SeekKind::InTrack);
+        if cider.seek_ms(target + seek_offset_ms).await.is_err() {
+            break;
+        }
+        seek_calibrator.write().unwrap().mark_seek_performed(SeekKind::InTrack);
+        tokio::time::sleep(settle).await;
+
+        let Ok(Some(np)) = cider.now_playing().await else {
+            break;
+        };
+        let Some(expected) = host_position_now(room, latency_tracker) else {
+            break;
+        };
+        // A heartbeat in between may have taken the measurement already
+        let drift_ms = np.current_position_ms() as i64 - expected as i64;
+        if seek_calibrator.write().unwrap().measure_if_pending(drift_ms) {
+            measured += 1;
+        }
+    }
+    info!(
+        "Calibration warm-up: {} seeks measured, offset now {}ms",
+        measured,
+        seek_calibrator.read().unwrap().offset_ms(SeekKind::InTrack)
+    );
+    measured
+}
+
+/// Bring a listener's playback in line with the host's
+///
+/// Seeks (with the calibrated offset) when we've drifted more than
+/// `settings.drift_threshold_ms` from where the host should be, or while the
+/// host plays, when the drift model expects us to before the next heartbeat.
+/// Also matches the host's play/pause state.
+pub async fn sync_to_host<C: CiderApi>(
+    playback: &crate::sync::PlaybackInfo,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    cider: &Arc<RwLock<C>>,
+    latency_tracker: &SharedLatencyTracker,
+    seek_calibrator: &SharedSeekCalibrator,
+    settings: &SyncSettings,
+) {
+    // Get estimated one-way latency, jitter and clock offset to host, and seek offset
+    let (latency_ms, jitter_ms, clock_offset_ms) = {
+        let tracker = latency_tracker.read().unwrap();
+        (tracker.host_latency_ms(), tracker.host_jitter_ms(), tracker.host_clock_offset_ms())
+    };
+    let seek_offset_ms = seek_calibrator.read().unwrap().offset_ms(SeekKind::InTrack);
+
+    // Get current Cider playback state first
+    let cider_client = cider.read().unwrap().clone();
+
+    // Check current position from now_playing
+    if let Ok(Some(np)) = cider_client.now_playing().await {
+        // Calculate expected position NOW (after async call completes)
+        // This gives more accurate comparison since current_position is also "now"
+        let now = latency_tracker.read().unwrap().now_ms();
+        let elapsed_since_heartbeat = elapsed_since_host_time(playback.timestamp_ms, now, clock_offset_ms, latency_ms);
+
+        // Expected position for COMPARISON (where host actually is)
+        // Does NOT include seek_offset - that's only for when we actually seek
+        let expected_position = if playback.is_playing {
+            playback.position_ms + elapsed_since_heartbeat
+        } else {
+            playback.position_ms
+        };
+        let current_position = np.current_position_ms();
+
+        // Check if we're drifted too far from expected position
+        let drift_signed = current_position as i64 - expected_position as i64;
+        let drift = drift_signed.unsigned_abs();
+
+        // Drift only builds up while playing. A seek since the last heartbeat
+        // moved us, so the drift before it says nothing about the drift now.
+        let seeked = seek_calibrator.read().unwrap().is_awaiting_measurement();
+        let (estimated_drift, correct_now) = {
+            let mut model = settings.drift_model.lock().unwrap();
+            if !playback.is_playing || seeked {
+                model.reset();
+            }
+            if playback.is_playing {
+                settings.stats.lock().unwrap().record_drift(drift_signed);
+                let estimated = model.observe(drift_signed, Instant::now().into_std());
+                let predicted = model.exceeds_before_next(settings.drift_threshold_ms);
+                (estimated, predicted || drift > settings.drift_threshold_ms)
+            } else {
+                (drift_signed, drift > settings.drift_threshold_ms)
+            }
+        };
+
+        // Log sync accuracy for diagnostics (positive = ahead, negative = behind)
+        debug!(
+            "Sync: drift {:+}ms (expected: {}ms, actual: {}ms, latency: {}ms, seek_offset: {}ms, elapsed: {}ms)",
+            drift_signed, expected_position, current_position, latency_ms, seek_offset_ms, elapsed_since_heartbeat
+        );
+
+        // Report sync status to UI for debug display (calibration state from before we update it)
+        if settings.status_due() {
             let (calibration_pending, next_calibration_sample, sample_history) = {
                 let calibrator = seek_calibrator.read().unwrap();
                 let pending = calibrator.is_awaiting_measurement();
@@ -756,19 +1476,20 @@ async fn handle_heartbeat(
                 } else {
                     None
                 };
-                let history: Vec<CalibrationSample> = calibrator
-                    .sample_history()
-                    .iter()
-                    .map(CalibrationSample::from)
-                    .collect();
+                let history: Vec<CalibrationSample> = if settings.status_level == SyncStatusLevel::Full {
+                    calibrator.sample_history().iter().map(CalibrationSample::from).collect()
+                } else {
+                    Vec::new()
+                };
                 (pending, sample, history)
             };
 
-            // Report sync status to UI for debug display
             if let Some(cb) = callback.read().unwrap().as_ref() {
                 cb.on_sync_status(SyncStatus {
                     drift_ms: drift_signed,
                     latency_ms,
+                    jitter_ms,
+                    estimate: settings.drift_estimate(),
                     elapsed_ms: elapsed_since_heartbeat,
                     seek_offset_ms,
                     calibration_pending,
@@ -776,51 +1497,1193 @@ async fn handle_heartbeat(
                     sample_history,
                 });
             }
+        }
 
-            // Try to measure the result of a previous seek operation (only updates if we were awaiting)
-            {
-                let mut calibrator = seek_calibrator.write().unwrap();
-                calibrator.measure_if_pending(drift_signed);
-            }
+        // Try to measure the result of a previous seek operation (only updates if we were awaiting)
+        {
+            let mut calibrator = seek_calibrator.write().unwrap();
+            calibrator.measure_if_pending(drift_signed);
+        }
 
-            if drift > DRIFT_THRESHOLD_MS {
-                // When seeking, ADD seek_offset to compensate for Cider's buffering delay
-                let seek_target = expected_position + seek_offset_ms;
+        if correct_now {
+            // When seeking, ADD seek_offset to compensate for Cider's buffering delay
+            let seek_target = expected_position + seek_offset_ms;
+            if drift > settings.drift_threshold_ms {
                 info!(
                     "Heartbeat: position drift {}ms exceeds threshold, re-syncing (target: {}ms, current: {}ms, offset: {}ms)",
                     drift, seek_target, current_position, seek_offset_ms
                 );
-                let _ = cider_client.seek_ms(seek_target).await;
-
-                // Mark that we just seeked - next heartbeat will measure how accurate it was
-                {
-                    let mut calibrator = seek_calibrator.write().unwrap();
-                    calibrator.mark_seek_performed();
-                }
+            } else {
+                info!(
+                    "Heartbeat: drift {:+}ms will exceed threshold before the next heartbeat, re-syncing (target: {}ms, current: {}ms, offset: {}ms)",
+                    estimated_drift, seek_target, current_position, seek_offset_ms
+                );
             }
-        }
+            let _ = cider_client.seek_ms(seek_target).await;
+            settings.drift_model.lock().unwrap().reset();
+            settings.stats.lock().unwrap().corrections += 1;
 
-        // Also sync play/pause state
-        if let Ok(is_currently_playing) = cider_client.is_playing().await {
-            if playback.is_playing && !is_currently_playing {
-                info!("Heartbeat: host is playing but we're paused, resuming");
+            // Mark that we just seeked - next heartbeat will measure how accurate it was
+            {
+                let mut calibrator = seek_calibrator.write().unwrap();
+                calibrator.mark_seek_performed(SeekKind::InTrack);
+            }
+        } else if playback.is_playing && settings.catches_up(estimated_drift) {
+            // Slightly ahead: a seek back would repeat audio, so wait for the host instead
+            let ahead_ms = estimated_drift.unsigned_abs();
+            info!("Heartbeat: {}ms ahead of the host, pausing for it to catch up", ahead_ms);
+            if cider_client.pause().await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(ahead_ms)).await;
                 let _ = cider_client.play().await;
-            } else if !playback.is_playing && is_currently_playing {
-                info!("Heartbeat: host is paused but we're playing, pausing");
-                let _ = cider_client.pause().await;
             }
+            settings.drift_model.lock().unwrap().reset();
+            settings.stats.lock().unwrap().corrections += 1;
         }
     }
 
-    // Update local state
+    // Also sync play/pause state
+    if let Ok(is_currently_playing) = cider_client.is_playing().await {
+        if playback.is_playing && !is_currently_playing {
+            info!("Heartbeat: host is playing but we're paused, resuming");
+            let _ = cider_client.play().await;
+        } else if !playback.is_playing && is_currently_playing {
+            info!("Heartbeat: host is paused but we're playing, pausing");
+            let _ = cider_client.pause().await;
+        }
+    }
+}
+
+async fn handle_heartbeat<C: CiderApi>(
+    playback: crate::sync::PlaybackInfo,
+    room: &Arc<RwLock<Room>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    cider: &Arc<RwLock<C>>,
+    latency_tracker: &SharedLatencyTracker,
+    seek_calibrator: &SharedSeekCalibrator,
+    sync_settings: &SyncSettings,
+) {
+    // Check if we're a listener and need to sync
+    let should_sync = {
+        let room_guard = room.read().unwrap();
+        room_guard.state().map(|s| !s.is_host()).unwrap_or(false)
+    };
+
+    if should_sync {
+        sync_to_host(&playback, callback, cider, latency_tracker, seek_calibrator, sync_settings).await;
+    }
+}
+
+/// Ask the host for the room state if ours keeps missing what the host's
+/// heartbeats hash (listener only)
+pub(super) fn check_room_hash(
+    host_hash: RoomHash,
+    room: &RwLock<Room>,
+    check: &mut RoomHashCheck,
+    network_handle: &RwLock<Option<NetworkHandle>>,
+    now: std::time::Instant,
+) {
+    let our_hash = room.read().unwrap().state().filter(|state| !state.is_host()).map(|state| state.room_hash());
+    let Some(our_hash) = our_hash else {
+        return;
+    };
+    if check.needs_room_state(host_hash, our_hash, now) {
+        info!("Our copy of the room is out of date, asking the host for it");
+        if let Some(handle) = network_handle.read().unwrap().as_ref() {
+            let _ = handle.broadcast(SyncMessage::RoomStateRequest);
+        }
+    }
+}
+
+/// The host's playback from a heartbeat, in the room (the actor's side of it)
+fn record_host_playback(
+    playback: &crate::sync::PlaybackInfo,
+    room: &Arc<RwLock<Room>>,
+    callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+) {
     let mut room_guard = room.write().unwrap();
     if let Some(state) = room_guard.state_mut() {
         if !state.is_host() {
             state.update_playback(playback.clone());
 
             if let Some(cb) = callback.read().unwrap().as_ref() {
-                cb.on_playback_changed(PlaybackState::from(&playback));
+                cb.on_playback_changed(PlaybackState::from(playback));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cider::mock::{MockCall, MockCider};
+    use crate::latency::new_shared_tracker;
+    use crate::network::NetworkCommand;
+    use crate::seek_calibrator::new_shared_calibrator;
+    use crate::sync::{PlaybackInfo, CONTROL_REQUEST_INTERVAL, ROOM_HASH_VERSION, ROOM_STATE_REQUEST_INTERVAL};
+    use super::super::events::EventQueue;
+    use super::super::types::{current_time_ms, Friend, Platform, SessionEvent, DEFAULT_DRIFT_THRESHOLD_MS};
+
+    /// A room where we're a listener
+    fn listener_room() -> Arc<RwLock<Room>> {
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.host_peer_id = "host".to_string();
+        Arc::new(RwLock::new(Room::Active(state)))
+    }
+
+    /// Host playback as of now
+    fn host_at(position_ms: u64, is_playing: bool) -> PlaybackInfo {
+        PlaybackInfo {
+            is_playing,
+            position_ms,
+            timestamp_ms: current_time_ms(),
+        }
+    }
+
+    /// Records tracks reported unavailable: ours, and listeners' as (peer ID, song ID)
+    #[derive(Default)]
+    struct UnavailableTracks {
+        ours: std::sync::Mutex<Vec<String>>,
+        listeners: std::sync::Mutex<Vec<(String, String)>>,
+    }
+
+    impl SessionCallback for UnavailableTracks {
+        fn on_room_state_changed(&self, _state: RoomState) {}
+        fn on_track_changed(&self, _track: Option<TrackInfo>) {}
+        fn on_playback_changed(&self, _playback: PlaybackState) {}
+        fn on_participant_joined(&self, _participant: Participant) {}
+        fn on_participant_left(&self, _peer_id: String) {}
+        fn on_room_ended(&self, _reason: String) {}
+        fn on_error(&self, _code: ErrorCode, _message_key: String, _message: String) {}
+        fn on_connected(&self) {}
+        fn on_disconnected(&self) {}
+        fn on_sync_status(&self, _status: SyncStatus) {}
+        fn on_track_unavailable(&self, track: TrackInfo) {
+            self.ours.lock().unwrap().push(track.song_id);
+        }
+        fn on_listener_track_unavailable(&self, participant: Participant, track: TrackInfo) {
+            self.listeners.lock().unwrap().push((participant.peer_id, track.song_id));
+        }
+        fn on_control_requested(&self, _participant: Participant) {}
+        fn on_friend_started_room(&self, _friend: Friend, _room_code: String) {}
+    }
+
+    /// The host's track, as sent in a TrackChange
+    fn host_track(id: &str, name: &str, isrc: Option<&str>) -> crate::sync::TrackInfo {
+        crate::sync::TrackInfo {
+            song_id: id.to_string(),
+            name: name.to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            artwork_url: String::new(),
+            duration_ms: 200_000,
+            isrc: isrc.map(str::to_string),
+            explicit: false,
+        }
+    }
+
+    /// A catalog song with the given name and ISRC
+    fn catalog_track(id: &str, name: &str, isrc: Option<&str>) -> crate::cider::NowPlaying {
+        let mut np = MockCider::track(id, 200_500);
+        np.name = name.to_string();
+        np.isrc = isrc.map(str::to_string);
+        np
+    }
+
+    /// Deliver a TrackChange to a listener and return the tracks reported unavailable
+    async fn track_change(track: crate::sync::TrackInfo, cider: &MockCider) -> Vec<String> {
+        track_change_in(&listener_room(), track, cider).await
+    }
+
+    async fn track_change_in(room: &Arc<RwLock<Room>>, track: crate::sync::TrackInfo, cider: &MockCider) -> Vec<String> {
+        let unavailable = Arc::new(UnavailableTracks::default());
+        let callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>> =
+            Arc::new(RwLock::new(Some(unavailable.clone())));
+        let cider = Arc::new(RwLock::new(cider.clone()));
+        let calibrator = new_shared_calibrator();
+        let f = follower(room, &callback, &cider, &calibrator);
+        handle_track_change(track, 0, current_time_ms(), &f).await;
+        let tracks = unavailable.ours.lock().unwrap().clone();
+        tracks
+    }
+
+    fn follower(
+        room: &Arc<RwLock<Room>>,
+        callback: &Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+        cider: &Arc<RwLock<MockCider>>,
+        calibrator: &SharedSeekCalibrator,
+    ) -> Follower<MockCider> {
+        Follower {
+            room: Arc::clone(room),
+            callback: Arc::clone(callback),
+            cider: Arc::clone(cider),
+            network_handle: Arc::new(RwLock::new(None)),
+            latency_tracker: new_shared_tracker(),
+            seek_calibrator: Arc::clone(calibrator),
+            sync_settings: Arc::new(SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full)),
+        }
+    }
+
+    async fn heartbeat(
+        playback: PlaybackInfo,
+        room: &Arc<RwLock<Room>>,
+        cider: &MockCider,
+        calibrator: &SharedSeekCalibrator,
+    ) {
+        let callback = Arc::new(RwLock::new(None));
+        let cider = Arc::new(RwLock::new(cider.clone()));
+        let tracker = new_shared_tracker();
+        let settings = SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full);
+        handle_heartbeat(playback, room, &callback, &cider, &tracker, calibrator, &settings).await;
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_resyncs_drifted_listener() {
+        let room = listener_room();
+        let calibrator = new_shared_calibrator();
+        let cider = MockCider::new().with_playing(MockCider::track("1", 200_000), 0);
+
+        heartbeat(host_at(60_000, true), &room, &cider, &calibrator).await;
+
+        let calls = cider.calls();
+        assert!(
+            matches!(calls.as_slice(), [MockCall::Seek(target)] if *target >= 60_000 + calibrator.read().unwrap().offset_ms(SeekKind::InTrack)),
+            "unexpected calls {:?}",
+            calls
+        );
+        assert!(calibrator.read().unwrap().is_awaiting_measurement());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_drift_threshold() {
+        let room = listener_room();
+        let calibrator = new_shared_calibrator();
+        let callback = Arc::new(RwLock::new(None));
+        let tracker = new_shared_tracker();
+        let mock = MockCider::new().with_playing(MockCider::track("1", 200_000), 0);
+        let cider = Arc::new(RwLock::new(mock.clone()));
+
+        // 2s behind is within the default threshold
+        let behind = host_at(2_000, true);
+        let settings = SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full);
+        handle_heartbeat(behind.clone(), &room, &callback, &cider, &tracker, &calibrator, &settings).await;
+        assert!(mock.calls().is_empty());
+
+        let settings = SyncSettings::new(1_000, SyncStatusLevel::Full);
+        handle_heartbeat(behind, &room, &callback, &cider, &tracker, &calibrator, &settings).await;
+        assert!(matches!(mock.calls().as_slice(), [MockCall::Seek(_)]), "unexpected calls {:?}", mock.calls());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_corrects_host_clock_offset() {
+        let room = listener_room();
+        let calibrator = new_shared_calibrator();
+        let callback = Arc::new(RwLock::new(None));
+        let settings = SyncSettings::new(1_000, SyncStatusLevel::Full);
+        let mock = MockCider::new().with_playing(MockCider::track("1", 200_000), 60_000);
+        let cider = Arc::new(RwLock::new(mock.clone()));
+
+        // In sync with a host whose clock runs 2s behind ours
+        let tracker = new_shared_tracker();
+        {
+            let mut tracker = tracker.write().unwrap();
+            tracker.set_host("host".to_string());
+            let ts = tracker.create_ping();
+            tracker.handle_pong("host", ts, ts - 2_000);
+        }
+        let playback = PlaybackInfo {
+            timestamp_ms: current_time_ms() - 2_000,
+            ..host_at(60_000, true)
+        };
+        handle_heartbeat(playback.clone(), &room, &callback, &cider, &tracker, &calibrator, &settings).await;
+        assert!(mock.calls().is_empty(), "unexpected calls {:?}", mock.calls());
+
+        // Taking its timestamp at face value looks like 2s of drift
+        let unmeasured = new_shared_tracker();
+        handle_heartbeat(playback, &room, &callback, &cider, &unmeasured, &calibrator, &settings).await;
+        assert!(matches!(mock.calls().as_slice(), [MockCall::Seek(_)]), "unexpected calls {:?}", mock.calls());
+    }
+
+    #[test]
+    fn test_estimated_position() {
+        let tracker = new_shared_tracker();
+        let now = current_time_ms();
+        let room = listener_room();
+        let mut room = room.write().unwrap();
+        let state = room.state_mut().unwrap();
+        state.update_track(Some(host_track("1", "Song", None)));
+
+        // A listener moves the host's position on by the time since it was sent, and the latency
+        state.update_playback(PlaybackInfo { timestamp_ms: now - 1_000, ..host_at(60_000, true) });
+        let latency_ms = tracker.read().unwrap().host_latency_ms();
+        assert_eq!(estimated_position_ms(state, &tracker, now), 61_000 + latency_ms);
+        // ...with a host clock 2s behind ours taken into account
+        {
+            let mut tracker = tracker.write().unwrap();
+            tracker.set_host("host".to_string());
+            let ts = tracker.create_ping();
+            tracker.handle_pong("host", ts, ts - 2_000);
+        }
+        state.update_playback(PlaybackInfo { timestamp_ms: now - 3_000, ..host_at(60_000, true) });
+        let position_ms = estimated_position_ms(state, &tracker, now);
+        assert!((60_900..=61_000).contains(&position_ms), "position {}", position_ms);
+
+        // Paused, it stays put; playing, it stops at the end of the track
+        state.update_playback(PlaybackInfo { timestamp_ms: now - 1_000, ..host_at(60_000, false) });
+        assert_eq!(estimated_position_ms(state, &tracker, now), 60_000);
+        state.update_playback(PlaybackInfo { timestamp_ms: now - 10_000, ..host_at(195_000, true) });
+        assert_eq!(estimated_position_ms(state, &tracker, now), 200_000);
+
+        // The host's own playback moves on by the time since it was read
+        state.host_peer_id = "me".to_string();
+        state.update_playback(PlaybackInfo { timestamp_ms: now - 1_000, ..host_at(60_000, true) });
+        assert_eq!(estimated_position_ms(state, &tracker, now), 61_000);
+    }
+
+    #[tokio::test]
+    async fn test_pong_measures_host_latency() {
+        let room = listener_room();
+        let callback = Arc::new(RwLock::new(None));
+        let cider = Arc::new(RwLock::new(MockCider::new()));
+        let network_handle = Arc::new(RwLock::new(None));
+        let calibrator = new_shared_calibrator();
+        let tracker = new_shared_tracker();
+        tracker.write().unwrap().set_host("host".to_string());
+        let ts = tracker.write().unwrap().create_ping();
+
+        // Only answers to pings we sent count
+        for ping_sent_at_ms in [ts + 1, ts] {
+            let pong = NetworkEvent::Pong {
+                from: "host".to_string(),
+                ping_sent_at_ms,
+                received_at_ms: ts - 2_000,
+            };
+            let settings = Arc::new(SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full));
+            let player = Player::start();
+            let room_broadcaster = RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle));
+            handle_network_event(
+                pong,
+                &room,
+                &callback,
+                &cider,
+                &network_handle,
+                &tracker,
+                &calibrator,
+                "me",
+                &settings,
+                &player,
+                &room_broadcaster,
+            )
+            .await;
+            let measured = ping_sent_at_ms == ts;
+            assert_eq!(tracker.read().unwrap().host_clock_offset_ms().is_some(), measured);
+        }
+        assert!(tracker.read().unwrap().host_clock_offset_ms().unwrap() <= -2_000);
+    }
+
+    #[tokio::test]
+    async fn test_topic_pings_still_answered() {
+        let room = listener_room();
+        let callback = Arc::new(RwLock::new(None));
+        let cider = Arc::new(RwLock::new(MockCider::new()));
+        let (handle, mut commands) = NetworkHandle::detached("me");
+        let network_handle = Arc::new(RwLock::new(Some(handle)));
+        let tracker = new_shared_tracker();
+        tracker.write().unwrap().set_host("host".to_string());
+        let ts = tracker.write().unwrap().create_ping();
+        let settings = Arc::new(SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full));
+        let player = Player::start();
+        let room_broadcaster = RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle));
+        let receive = |from: &str, message: SyncMessage| {
+            handle_sync_message(
+                from.to_string(),
+                message,
+                &room,
+                &callback,
+                &cider,
+                &network_handle,
+                &tracker,
+                &new_shared_calibrator(),
+                "me",
+                &settings,
+                &player,
+                &room_broadcaster,
+            );
+        };
+
+        // An older host answers ours there
+        receive("host", SyncMessage::Pong { ping_sent_at_ms: ts, received_at_ms: ts });
+        assert!(tracker.read().unwrap().host_clock_offset_ms().is_some());
+
+        // An older peer pings on the topic, which only the host answers
+        receive("old", SyncMessage::Ping { sent_at_ms: 42 });
+        assert!(commands.try_recv().is_err());
+        assert!(room.write().unwrap().state_mut().unwrap().transfer_host("me"));
+        receive("old", SyncMessage::Ping { sent_at_ms: 42 });
+        let Ok(NetworkCommand::Broadcast { message }) = commands.try_recv() else {
+            panic!("no pong");
+        };
+        assert!(matches!(*message, SyncMessage::Pong { ping_sent_at_ms: 42, .. }));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_catch_up_pause_when_slightly_ahead() {
+        let room = listener_room();
+        let calibrator = new_shared_calibrator();
+        let callback = Arc::new(RwLock::new(None));
+        let tracker = new_shared_tracker();
+        let settings = SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full).with_catch_up_threshold(250);
+        let mock = MockCider::new().with_playing(MockCider::track("1", 200_000), 60_600);
+        let cider = Arc::new(RwLock::new(mock.clone()));
+
+        // 600ms ahead: paused for as long, no seek back
+        let started = Instant::now();
+        handle_heartbeat(host_at(60_000, true), &room, &callback, &cider, &tracker, &calibrator, &settings).await;
+        assert_eq!(mock.calls(), vec![MockCall::Pause, MockCall::Play]);
+        assert!(started.elapsed() >= Duration::from_millis(500));
+        assert!(!calibrator.read().unwrap().is_awaiting_measurement());
+
+        // A lead within the catch-up threshold is left alone
+        let mock = MockCider::new().with_playing(MockCider::track("1", 200_000), 60_100);
+        let cider = Arc::new(RwLock::new(mock.clone()));
+        handle_heartbeat(host_at(60_000, true), &room, &callback, &cider, &tracker, &calibrator, &settings).await;
+        assert!(mock.calls().is_empty(), "unexpected calls {:?}", mock.calls());
+    }
+
+    #[tokio::test]
+    async fn test_steady_drift_corrected_before_threshold() {
+        let room = listener_room();
+        let calibrator = new_shared_calibrator();
+        let callback = Arc::new(RwLock::new(None));
+        let tracker = new_shared_tracker();
+        let settings = SyncSettings::new(950, SyncStatusLevel::Full);
+        let mock = MockCider::new().with_playing(MockCider::track("1", 200_000), 60_000);
+        let cider = Arc::new(RwLock::new(mock.clone()));
+
+        // Pulling ahead of the host by 100ms every 150ms
+        let mut drift = 0;
+        while mock.calls().is_empty() && drift < 2_000 {
+            let host = host_at(mock.position_ms() - drift, true);
+            handle_heartbeat(host, &room, &callback, &cider, &tracker, &calibrator, &settings).await;
+            drift += 100;
+            tokio::time::sleep(Duration::from_millis(150)).await;
+        }
+
+        // Re-synced once, a heartbeat before it would have gone past the threshold
+        assert!(matches!(mock.calls().as_slice(), [MockCall::Seek(_)]), "unexpected calls {:?}", mock.calls());
+        assert!(drift - 100 <= 950, "corrected at {}ms", drift - 100);
+        assert!(drift - 100 > 500, "corrected at {}ms", drift - 100);
+    }
+
+    #[test]
+    fn test_sync_status_levels() {
+        assert!(!SyncSettings::new(0, SyncStatusLevel::Off).status_due());
+        let full = SyncSettings::new(0, SyncStatusLevel::Full);
+        assert!(full.status_due() && full.status_due());
+
+        // Summaries go out once per interval
+        let summary = SyncSettings::new(0, SyncStatusLevel::Summary);
+        assert!(summary.status_due());
+        assert!(!summary.status_due());
+        *summary.last_status.lock().unwrap() = Instant::now().checked_sub(SYNC_STATUS_SUMMARY_INTERVAL);
+        assert!(summary.status_due());
+    }
+
+    #[tokio::test]
+    async fn test_forced_resync_seeks_within_threshold() {
+        let callback = Arc::new(RwLock::new(None));
+        let tracker = new_shared_tracker();
+        let calibrator = new_shared_calibrator();
+        let mock = MockCider::new().with_playing(MockCider::track("1", 200_000), 60_000);
+        let cider = Arc::new(RwLock::new(mock.clone()));
+
+        // Half a second off, as after switching to Bluetooth headphones
+        let playback = host_at(60_500, true);
+        let settings = SyncSettings::new(0, SyncStatusLevel::Full);
+        sync_to_host(&playback, &callback, &cider, &tracker, &calibrator, &settings).await;
+
+        let calls = mock.calls();
+        assert!(matches!(calls.as_slice(), [MockCall::Seek(target)] if *target > 60_500), "unexpected calls {:?}", calls);
+        assert!(calibrator.read().unwrap().is_awaiting_measurement());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_follows_pause() {
+        let room = listener_room();
+        let calibrator = new_shared_calibrator();
+        let cider = MockCider::new().with_playing(MockCider::track("1", 200_000), 30_000);
+
+        heartbeat(host_at(30_000, false), &room, &cider, &calibrator).await;
+
+        assert_eq!(cider.calls(), vec![MockCall::Pause]);
+    }
+
+    #[tokio::test]
+    async fn test_calibration_learns_seek_latency() {
+        let room = listener_room();
+        let calibrator = new_shared_calibrator();
+        let initial_offset = calibrator.read().unwrap().offset_ms(SeekKind::InTrack);
+        let cider = MockCider::new()
+            .with_playing(MockCider::track("1", 200_000), 0)
+            .with_seek_latency(Duration::from_millis(300));
+
+        // Drifted: seek with the default offset, which overshoots a 300ms buffer
+        heartbeat(host_at(60_000, true), &room, &cider, &calibrator).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        heartbeat(host_at(60_500, true), &room, &cider, &calibrator).await;
+
+        let offset = calibrator.read().unwrap().offset_ms(SeekKind::InTrack);
+        assert!(offset < initial_offset && offset >= 300, "offset {}", offset);
+        // Close enough now: no second seek
+        assert_eq!(cider.calls().iter().filter(|c| matches!(c, MockCall::Seek(_))).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_track_change_calibrates_track_start() {
+        let room = listener_room();
+        let calibrator = new_shared_calibrator();
+        let callback = Arc::new(RwLock::new(None));
+        let mock = MockCider::new().with_catalog([MockCider::track("1", 200_000)]);
+        let cider = Arc::new(RwLock::new(mock.clone()));
+
+        let track = host_track("1", "Song 1", None);
+        let f = follower(&room, &callback, &cider, &calibrator);
+        handle_track_change(track, 0, current_time_ms(), &f).await;
+        heartbeat(host_at(0, true), &room, &mock, &calibrator).await;
+
+        // Measured as a seek right after loading the track
+        let calibrator = calibrator.read().unwrap();
+        assert_eq!(calibrator.sample_count(SeekKind::TrackStart), 1);
+        assert_eq!(calibrator.sample_count(SeekKind::InTrack), 0);
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_calibrates_seek_offset() {
+        let room = listener_room();
+        room.write().unwrap().state_mut().unwrap().update_playback(host_at(60_000, true));
+        let tracker = new_shared_tracker();
+        let calibrator = new_shared_calibrator();
+        let initial_offset = calibrator.read().unwrap().offset_ms(SeekKind::InTrack);
+        let cider = MockCider::new()
+            .with_playing(MockCider::track("1", 200_000), 60_000)
+            .with_seek_latency(Duration::from_millis(300));
+
+        let measured =
+            warm_up_calibration(&room, &cider, &tracker, &calibrator, Duration::from_millis(400)).await;
+
+        assert_eq!(measured, WARM_UP_SEEKS);
+        let offset = calibrator.read().unwrap().offset_ms(SeekKind::InTrack);
+        assert!(offset < initial_offset && offset > 250, "offset {}", offset);
+
+        // Nothing to measure against while the host is paused
+        room.write().unwrap().state_mut().unwrap().update_playback(host_at(60_000, false));
+        let measured =
+            warm_up_calibration(&room, &cider, &tracker, &calibrator, Duration::from_millis(400)).await;
+        assert_eq!(measured, 0);
+        assert_eq!(cider.calls().len(), WARM_UP_SEEKS as usize);
+    }
+
+    #[tokio::test]
+    async fn test_track_change_plays_isrc_match() {
+        let cider = MockCider::new().with_catalog([catalog_track("2", "Other Title", Some("USABC2400001"))]);
+
+        let unavailable = track_change(host_track("1", "Song", Some("USABC2400001")), &cider).await;
+
+        assert!(unavailable.is_empty());
+        let calls = cider.calls();
+        assert_eq!(calls[..2], [MockCall::PlayItem("1".to_string()), MockCall::PlayItem("2".to_string())]);
+        assert!(matches!(calls[2..], [MockCall::Seek(_)]), "unexpected calls {:?}", calls);
+    }
+
+    #[tokio::test]
+    async fn test_track_change_plays_name_match() {
+        let cider = MockCider::new().with_catalog([
+            catalog_track("2", "Song (Live)", None),
+            catalog_track("3", "Song", None),
+        ]);
+
+        let unavailable = track_change(host_track("1", "Song", None), &cider).await;
+
+        assert!(unavailable.is_empty());
+        assert!(cider.calls().contains(&MockCall::PlayItem("3".to_string())));
+        assert!(!cider.calls().contains(&MockCall::PlayItem("2".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_track_change_reports_unavailable() {
+        let cider = MockCider::new().with_catalog([catalog_track("2", "Something Else", None)]);
+
+        let unavailable = track_change(host_track("1", "Song", Some("USABC2400001")), &cider).await;
+
+        assert_eq!(unavailable, vec!["1".to_string()]);
+        // Nothing to seek in
+        assert_eq!(cider.calls(), vec![MockCall::PlayItem("1".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_same_storefront_not_searched() {
+        let room = listener_room();
+        {
+            let mut room_guard = room.write().unwrap();
+            let state = room_guard.state_mut().unwrap();
+            state.add_participant(InternalParticipant {
+                peer_id: "host".to_string(),
+                display_name: "Host".to_string(),
+                is_host: true,
+                storefront: Some("us".to_string()),
+                capabilities: Capabilities::default(),
+                profile: Profile::default(),
+                client: ClientInfo::default(),
+                status: ParticipantStatus::default(),
+            });
+        }
+        let track = host_track("1", "Song", Some("USABC2400001"));
+        let catalog = [catalog_track("2", "Song", Some("USABC2400001"))];
+
+        // Sharing the host's storefront, the track isn't region-locked: no other version to look for
+        let cider = MockCider::new().with_catalog(catalog.clone());
+        let unavailable = track_change_in(&room, track.clone(), &cider).await;
+        assert_eq!(unavailable, vec!["1".to_string()]);
+        assert_eq!(cider.calls(), vec![MockCall::PlayItem("1".to_string())]);
+
+        let cider = MockCider::new().with_storefront("jp").with_catalog(catalog);
+        assert!(track_change_in(&room, track, &cider).await.is_empty());
+        assert!(cider.calls().contains(&MockCall::PlayItem("2".to_string())));
+    }
+
+    #[test]
+    fn test_listener_report_reaches_host() {
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.add_participant(InternalParticipant {
+            peer_id: "listener".to_string(),
+            display_name: "Listener".to_string(),
+            is_host: false,
+            storefront: Some("jp".to_string()),
+            capabilities: Capabilities::default(),
+            profile: Profile::default(),
+            client: ClientInfo::default(),
+            status: ParticipantStatus::default(),
+        });
+        state.update_track(Some(host_track("1", "Song", None)));
+        let room = Arc::new(RwLock::new(Room::Active(state)));
+        let unavailable = Arc::new(UnavailableTracks::default());
+        let callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>> =
+            Arc::new(RwLock::new(Some(unavailable.clone())));
+
+        // A report about a previous track is ignored
+        handle_track_unavailable("listener".to_string(), "0".to_string(), &room, &callback);
+        handle_track_unavailable("listener".to_string(), "1".to_string(), &room, &callback);
+
+        let reports = unavailable.listeners.lock().unwrap().clone();
+        assert_eq!(reports, vec![("listener".to_string(), "1".to_string())]);
+    }
+
+    /// A listener asking to join
+    fn listener(peer_id: &str, capabilities: Capabilities) -> InternalParticipant {
+        InternalParticipant {
+            peer_id: peer_id.to_string(),
+            display_name: peer_id.to_uppercase(),
+            is_host: false,
+            storefront: None,
+            capabilities,
+            profile: Profile::default(),
+            client: ClientInfo::default(),
+            status: ParticipantStatus::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_join_request_capabilities() {
+        let state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        let room = Arc::new(RwLock::new(Room::Active(state)));
+        let callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>> = Arc::new(RwLock::new(None));
+        let network_handle = Arc::new(RwLock::new(None));
+        let room_broadcaster = RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle));
+        let restricted = Capabilities { explicit_restricted: Some(true) };
+        handle_join_request(listener("kid", restricted), &room, &callback, &network_handle, &room_broadcaster);
+        // Older peers don't report the setting
+        handle_join_request(listener("old", Capabilities::default()), &room, &callback, &network_handle, &room_broadcaster);
+
+        let room_guard = room.read().unwrap();
+        let state = room_guard.state().unwrap();
+        let mut track = host_track("1", "Song", None);
+        assert!(state.listeners_unable_to_play(&track).is_empty());
+        track.explicit = true;
+        let unable: Vec<_> = state.listeners_unable_to_play(&track).into_iter().map(|p| p.peer_id.as_str()).collect();
+        assert_eq!(unable, vec!["kid"]);
+    }
+
+    #[tokio::test]
+    async fn test_join_request_client_info() {
+        let state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::local(Some("2.1.0".to_string())),
+        );
+        let room = Arc::new(RwLock::new(Room::Active(state)));
+        let network_handle = Arc::new(RwLock::new(None));
+        let join_request = |client: serde_json::Value| {
+            serde_json::from_value::<SyncMessage>(serde_json::json!({
+                "JoinRequest": { "display_name": "Listener", "client": client }
+            }))
+            .unwrap()
+        };
+        let windows = serde_json::json!({
+            "platform": "windows",
+            "app_version": " 2.0.3 ",
+            "core_version": "x".repeat(100),
+        });
+        // Older peers send none, newer ones may run on a platform we don't know of
+        let messages = [
+            ("windows", join_request(windows)),
+            ("old", serde_json::from_str(r#"{"JoinRequest":{"display_name":"Old"}}"#).unwrap()),
+            ("future", join_request(serde_json::json!({ "platform": "visionos", "app_version": "3.0.0\n<b>" }))),
+        ];
+        for (from, message) in messages {
+            handle_sync_message(
+                from.to_string(),
+                message,
+                &room,
+                &Arc::new(RwLock::new(None)),
+                &Arc::new(RwLock::new(MockCider::new())),
+                &network_handle,
+                &new_shared_tracker(),
+                &new_shared_calibrator(),
+                "me",
+                &Arc::new(SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full)),
+                &Player::start(),
+                &RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle)),
+            );
+        }
+
+        let room_state = RoomState::from(room.read().unwrap().state().unwrap());
+        let client = |peer_id: &str| {
+            let p = room_state.participants.iter().find(|p| p.peer_id == peer_id).unwrap();
+            (p.platform, p.app_version.as_deref(), p.core_version.as_deref())
+        };
+        assert_eq!(client("me").0, Platform::from(crate::sync::Platform::current()));
+        assert_eq!(client("me").1, Some("2.1.0"));
+        assert_eq!(client("me").2, Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(client("windows"), (Platform::Windows, Some("2.0.3"), None));
+        assert_eq!(client("old"), (Platform::Unknown, None, None));
+        assert_eq!(client("future"), (Platform::Unknown, None, None));
+    }
+
+    #[tokio::test]
+    async fn test_participant_renames_only_themselves() {
+        let room = listener_room();
+        room.write().unwrap().state_mut().unwrap().add_participant(InternalParticipant {
+            peer_id: "friend".to_string(),
+            display_name: "Frend".to_string(),
+            is_host: false,
+            storefront: None,
+            capabilities: Capabilities::default(),
+            profile: Profile::default(),
+            client: ClientInfo::default(),
+            status: ParticipantStatus::default(),
+        });
+        let callback = Arc::new(RwLock::new(None));
+        let cider = Arc::new(RwLock::new(MockCider::new()));
+        let network_handle = Arc::new(RwLock::new(None));
+        let tracker = new_shared_tracker();
+        let calibrator = new_shared_calibrator();
+
+        for (from, name) in [("friend", "Friend"), ("host", "Impostor")] {
+            let message = SyncMessage::ParticipantUpdated {
+                peer_id: "friend".to_string(),
+                display_name: name.to_string(),
+                profile: Profile {
+                    avatar: Some("🎧".to_string()),
+                    // Not a color the apps can show
+                    color: Some("red; font-size: 99px".to_string()),
+                },
+            };
+            handle_sync_message(
+                from.to_string(),
+                message,
+                &room,
+                &callback,
+                &cider,
+                &network_handle,
+                &tracker,
+                &calibrator,
+                "me",
+                &Arc::new(SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full)),
+                &Player::start(),
+                &RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle)),
+            );
+        }
+
+        let room_guard = room.read().unwrap();
+        let friend = &room_guard.state().unwrap().participants["friend"];
+        assert_eq!(friend.display_name, "Friend");
+        assert_eq!(friend.profile.avatar.as_deref(), Some("🎧"));
+        assert_eq!(friend.profile.color, None);
+    }
+
+    #[tokio::test]
+    async fn test_status_updates_only_from_themselves() {
+        let room = listener_room();
+        room.write().unwrap().state_mut().unwrap().add_participant(InternalParticipant {
+            peer_id: "host".to_string(),
+            display_name: "Host".to_string(),
+            is_host: true,
+            storefront: None,
+            capabilities: Capabilities::default(),
+            profile: Profile::default(),
+            client: ClientInfo::default(),
+            status: ParticipantStatus::default(),
+        });
+        let network_handle = Arc::new(RwLock::new(None));
+        let receive = |message| {
+            handle_sync_message(
+                "host".to_string(),
+                message,
+                &room,
+                &Arc::new(RwLock::new(None)),
+                &Arc::new(RwLock::new(MockCider::new())),
+                &network_handle,
+                &new_shared_tracker(),
+                &new_shared_calibrator(),
+                "me",
+                &Arc::new(SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full)),
+                &Player::start(),
+                &RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle)),
+            )
+        };
+        for peer_id in ["host", "me"] {
+            receive(SyncMessage::StatusUpdated { peer_id: peer_id.to_string(), status: ParticipantStatus::Away });
+        }
+        {
+            let room_guard = room.read().unwrap();
+            let participants = &room_guard.state().unwrap().participants;
+            assert_eq!(participants["host"].status, ParticipantStatus::Away);
+            assert_eq!(participants["me"].status, ParticipantStatus::Active);
+        }
+
+        // The host's room state, from before it heard we went away
+        let host_view = room.read().unwrap().state().unwrap().clone();
+        room.write().unwrap().state_mut().unwrap().set_participant_status("me", ParticipantStatus::Away);
+        receive(room_state_message(&host_view, 0));
+
+        let room_guard = room.read().unwrap();
+        assert_eq!(room_guard.state().unwrap().participants["me"].status, ParticipantStatus::Away);
+    }
+
+    #[tokio::test]
+    async fn test_refuses_banned_and_locked_out() {
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.add_participant(listener("friend", Capabilities::default()));
+        state.banned.insert("troll".to_string());
+        let room = Arc::new(RwLock::new(Room::Active(state)));
+        let callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>> = Arc::new(RwLock::new(None));
+        let network_handle = Arc::new(RwLock::new(None));
+        let room_broadcaster = RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle));
+
+        handle_join_request(listener("troll", Capabilities::default()), &room, &callback, &network_handle, &room_broadcaster);
+        room.write().unwrap().state_mut().unwrap().moderation.locked = true;
+        handle_join_request(listener("new", Capabilities::default()), &room, &callback, &network_handle, &room_broadcaster);
+        // Those already in the room aren't locked out
+        handle_join_request(listener("friend", Capabilities::default()), &room, &callback, &network_handle, &room_broadcaster);
+
+        let room_guard = room.read().unwrap();
+        let mut peers: Vec<_> = room_guard.state().unwrap().participants.keys().cloned().collect();
+        peers.sort();
+        assert_eq!(peers, vec!["friend", "me"]);
+    }
+
+    #[tokio::test]
+    async fn test_only_host_removes_participants() {
+        let room = listener_room();
+        room.write().unwrap().state_mut().unwrap().add_participant(listener("friend", Capabilities::default()));
+        let callback = Arc::new(RwLock::new(None));
+        let cider = MockCider::new().with_playing(MockCider::track("1", 200_000), 0);
+        let cider_lock = Arc::new(RwLock::new(cider.clone()));
+        let network_handle = Arc::new(RwLock::new(None));
+        let tracker = new_shared_tracker();
+        let calibrator = new_shared_calibrator();
+
+        for (from, peer_id) in [("friend", "me"), ("host", "friend"), ("host", "me")] {
+            let message = SyncMessage::Removed {
+                peer_id: peer_id.to_string(),
+                reason: RemovalReason::Kicked,
+            };
+            handle_sync_message(
+                from.to_string(),
+                message,
+                &room,
+                &callback,
+                &cider_lock,
+                &network_handle,
+                &tracker,
+                &calibrator,
+                "me",
+                &Arc::new(SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full)),
+                &Player::start(),
+                &RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle)),
+            );
+
+            if peer_id == "friend" {
+                let room_guard = room.read().unwrap();
+                let state = room_guard.state().expect("still in the room");
+                assert!(!state.participants.contains_key("friend"));
+            }
+        }
+
+        // Kicked by the host: out of the room, and the music stops
+        assert!(!room.read().unwrap().is_busy());
+        tokio::task::yield_now().await;
+        assert!(cider.calls().contains(&MockCall::Pause));
+    }
+
+    #[tokio::test]
+    async fn test_turned_away_only_by_host() {
+        let joining = || {
+            Arc::new(RwLock::new(Room::Joining {
+                room_code: "ABC123".to_string(),
+                display_name: "Me".to_string(),
+                storefront: None,
+                capabilities: Capabilities::default(),
+                profile: Profile::default(),
+                client: ClientInfo::default(),
+                status: ParticipantStatus::default(),
+                refusal: None,
+            }))
+        };
+        let mut host_state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "host".to_string(),
+            "Host".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        host_state.banned.insert("me".to_string());
+        let room_state = room_state_message(&host_state, 0);
+        let removed = SyncMessage::Removed { peer_id: "me".to_string(), reason: RemovalReason::Banned };
+        let callback = Arc::new(RwLock::new(None));
+        let cider = Arc::new(RwLock::new(MockCider::new()));
+        let network_handle = Arc::new(RwLock::new(None));
+        let (tracker, calibrator) = (new_shared_tracker(), new_shared_calibrator());
+        let settings = Arc::new(SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full));
+        let player = Player::start();
+        let receive = |room: &Arc<RwLock<Room>>, from: &str, message: SyncMessage| {
+            handle_sync_message(
+                from.to_string(),
+                message,
+                room,
+                &callback,
+                &cider,
+                &network_handle,
+                &tracker,
+                &calibrator,
+                "me",
+                &settings,
+                &player,
+                &RoomBroadcaster::new(Arc::clone(room), Arc::clone(&network_handle)),
+            );
+        };
+
+        // Someone else in the room can't turn us away, or pass for the host
+        let room = joining();
+        receive(&room, "troll", removed.clone());
+        receive(&room, "troll", room_state.clone());
+        assert!(matches!(&*room.read().unwrap(), Room::Joining { .. }));
+        receive(&room, "host", room_state.clone());
+        assert_eq!(room.read().unwrap().state().unwrap().host_peer_id, "host");
+
+        // The host can, once its room state shows it's the host
+        let room = joining();
+        receive(&room, "host", removed);
+        assert!(room.read().unwrap().is_busy());
+        receive(&room, "host", room_state);
+        assert!(!room.read().unwrap().is_busy());
+    }
+
+    #[test]
+    fn test_control_requests_reach_host() {
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.add_participant(listener("fan", Capabilities::default()));
+        state.add_participant(listener("pest", Capabilities::default()));
+        state.moderation.suggestions_muted.insert("pest".to_string());
+        let room = Arc::new(RwLock::new(Room::Active(state)));
+        let events = Arc::new(EventQueue::new(8));
+        let callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>> =
+            Arc::new(RwLock::new(Some(events.clone() as Arc<dyn SessionCallback>)));
+
+        let start = std::time::Instant::now();
+        let asked_by_fan = |events: &EventQueue| {
+            matches!(
+                events.pop(Duration::ZERO),
+                Some(SessionEvent::ControlRequested { participant }) if participant.peer_id == "fan"
+            )
+        };
+
+        // Strangers and muted listeners are ignored
+        for from in ["fan", "pest", "stranger"] {
+            handle_control_request(from.to_string(), &room, &callback, start);
+        }
+        assert!(asked_by_fan(&events));
+        assert!(events.pop(Duration::ZERO).is_none());
+
+        // Asking again right away doesn't bother the host, once it's been a while does
+        handle_control_request("fan".to_string(), &room, &callback, start);
+        assert!(events.pop(Duration::ZERO).is_none());
+        handle_control_request("fan".to_string(), &room, &callback, start + CONTROL_REQUEST_INTERVAL);
+        assert!(asked_by_fan(&events));
+
+        // Only the host is asked
+        handle_control_request("host".to_string(), &listener_room(), &callback, start);
+        assert!(events.pop(Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_room_code_changed() {
+        let room = listener_room();
+        let events = Arc::new(EventQueue::new(8));
+        let callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>> =
+            Arc::new(RwLock::new(Some(events.clone() as Arc<dyn SessionCallback>)));
+        let network_handle = Arc::new(RwLock::new(None));
+
+        handle_room_code_changed("not a code".to_string(), &room, &callback, &network_handle);
+        assert_eq!(room.read().unwrap().state().unwrap().room_code, "ABC123");
+        assert!(events.pop(Duration::ZERO).is_none());
+
+        handle_room_code_changed("wxyk-4679".to_string(), &room, &callback, &network_handle);
+        assert_eq!(room.read().unwrap().state().unwrap().room_code, "WXYK4679");
+        assert!(matches!(
+            events.pop(Duration::ZERO),
+            Some(SessionEvent::RoomStateChanged { state }) if state.room_code == "WXYK4679"
+        ));
+
+        // Told again (the host sends it on both topics): nothing more to do
+        handle_room_code_changed("WXYK4679".to_string(), &room, &callback, &network_handle);
+        assert!(events.pop(Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_transfer_host_carries_on_playback() {
+        let room = listener_room();
+        let callback = Arc::new(RwLock::new(None));
+        let (handle, _commands) = NetworkHandle::detached("me");
+        let network_handle = Arc::new(RwLock::new(Some(handle)));
+
+        // Handed control with the old host's last playback: we go on from it
+        let playback = host_at(42_000, true);
+        let track = host_track("1440818839", "Song", None);
+        handle_transfer_host(
+            "me".to_string(),
+            Some(track),
+            Some(playback.clone()),
+            &room,
+            &callback,
+            &network_handle,
+        );
+        let room_guard = room.read().unwrap();
+        let state = room_guard.state().unwrap();
+        assert!(state.is_host());
+        assert_eq!(state.current_track.as_ref().unwrap().song_id, "1440818839");
+        assert_eq!(state.playback.position_ms, 42_000);
+        assert_eq!(state.playback.timestamp_ms, playback.timestamp_ms);
+        drop(room_guard);
+
+        // From an older host, without it: what its heartbeats said stands
+        let room = listener_room();
+        room.write().unwrap().state_mut().unwrap().update_playback(playback);
+        handle_transfer_host("me".to_string(), None, None, &room, &callback, &network_handle);
+        assert_eq!(room.read().unwrap().state().unwrap().playback.position_ms, 42_000);
+    }
+
+    #[test]
+    fn test_room_hash_mismatch_asks_for_room_state() {
+        let room = listener_room();
+        let (handle, mut commands) = NetworkHandle::detached("me");
+        let network_handle = Arc::new(RwLock::new(Some(handle)));
+        let mut requests = || {
+            let mut sent = 0;
+            while let Ok(command) = commands.try_recv() {
+                if let NetworkCommand::Broadcast { message } = command {
+                    sent += matches!(*message, SyncMessage::RoomStateRequest) as usize;
+                }
             }
+            sent
+        };
+        let mut check = RoomHashCheck::default();
+        let our_hash = room.read().unwrap().state().unwrap().room_hash();
+        let start = std::time::Instant::now();
+
+        // In sync
+        check_room_hash(our_hash, &room, &mut check, &network_handle, start);
+        check_room_hash(our_hash, &room, &mut check, &network_handle, start);
+        assert_eq!(requests(), 0);
+
+        // A change the host made that we missed: asked for on the second heartbeat, then not for a while
+        room.write().unwrap().state_mut().unwrap().settings.max_participants = Some(4);
+        for _ in 0..4 {
+            check_room_hash(our_hash, &room, &mut check, &network_handle, start);
+        }
+        assert_eq!(requests(), 1);
+        // And again once it's been a while
+        let later = start + ROOM_STATE_REQUEST_INTERVAL;
+        check_room_hash(our_hash, &room, &mut check, &network_handle, later);
+        check_room_hash(our_hash, &room, &mut check, &network_handle, later);
+        assert_eq!(requests(), 1);
+
+        // One mismatch between matches is a change crossing a heartbeat
+        let mut check = RoomHashCheck::default();
+        let state = room.read().unwrap().state().unwrap().clone();
+        assert!(!check.needs_room_state(our_hash, state.room_hash(), start));
+        assert!(!check.needs_room_state(state.room_hash(), state.room_hash(), start));
+        assert!(!check.needs_room_state(our_hash, state.room_hash(), start));
+
+        // Nor do hashes of another version
+        let newer = RoomHash { version: ROOM_HASH_VERSION + 1, ..our_hash };
+        for _ in 0..4 {
+            assert!(!check.needs_room_state(newer, state.room_hash(), start));
         }
     }
 }
@@ -0,0 +1,415 @@
+//! In-process rooms for end-to-end sync tests
+//!
+//! The handler tests feed the sync engine one message at a time, so nothing
+//! checked that listeners stay with the host once the network is in between.
+//! The harness runs a host and listeners in one process, each with its own
+//! `NetworkManager` (no mDNS or DHT: listeners dial the host over loopback
+//! TCP), the session's event handlers and a `MockCider`. The host broadcasts
+//! its Cider's playback the way the session's host loop does, while a test
+//! script plays, pauses, seeks and changes tracks on it, and the harness
+//! samples how far each listener's Cider is from the host's.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::cider::mock::MockCider;
+use crate::cider::CiderApi;
+use crate::clock::Clock;
+use crate::latency::{self, SharedLatencyTracker};
+use crate::network::{NetworkConfig, NetworkEvent, NetworkHandle, NetworkManager};
+use crate::seek_calibrator::{self, SharedSeekCalibrator};
+use crate::sync::{new_shared_recorder, Capabilities, ClientInfo, ParticipantStatus, Profile, Room, RoomState};
+
+use super::handlers::{handle_network_event, SyncSettings};
+use super::player::Player;
+use super::room_broadcast::RoomBroadcaster;
+use super::room_actor::RoomActor;
+use super::runtime::TaskSet;
+use super::session::{broadcast_host_playback, poll_host_playback};
+use super::types::{SessionCallback, SyncStatusLevel};
+
+/// How long to wait for the room to come together
+pub const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the host broadcasts its playback
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How far listeners may drift before re-syncing
+pub const DRIFT_THRESHOLD_MS: u64 = 500;
+
+/// How often drift is sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+pub(super) const ROOM_CODE: &str = "ABCD4679";
+
+/// Something the host does, at its time in the script
+#[derive(Debug, Clone)]
+pub enum HostAction {
+    Play,
+    Pause,
+    Seek(u64),
+    /// Play a track from the host's catalog
+    PlayTrack(String),
+}
+
+/// Where each listener was against the host at one point in the script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriftSample {
+    /// Time since the script started
+    pub at: Duration,
+    /// Time since the host's last action
+    pub since_action: Duration,
+    /// By listener: position minus the host's (ms), None if on another track
+    pub drifts: Vec<Option<i64>>,
+}
+
+/// A peer in the room
+pub struct TestPeer {
+    pub peer_id: String,
+    pub cider: MockCider,
+    pub room: Arc<RwLock<Room>>,
+    network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+}
+
+impl TestPeer {
+    /// Start a peer's network and handle its events the way the session does
+    fn start(cider: MockCider, room: Room, tasks: &TaskSet) -> (Self, NetworkHandle) {
+        let config = NetworkConfig {
+            enable_mdns: false,
+            enable_dht: false,
+            ..NetworkConfig::default()
+        };
+        let (handle, events) = NetworkManager::with_config(config).unwrap().start().unwrap();
+        let peer = Self {
+            peer_id: handle.local_peer_id.clone(),
+            cider,
+            room: Arc::new(RwLock::new(room)),
+            network_handle: Arc::new(RwLock::new(Some(handle.clone()))),
+        };
+        let handler = handle_events(
+            events,
+            Arc::clone(&peer.room),
+            peer.cider.clone(),
+            Arc::clone(&peer.network_handle),
+            latency::new_shared_tracker(),
+            peer.peer_id.clone(),
+        );
+        tasks.spawn(&Handle::current(), handler);
+        (peer, handle)
+    }
+
+    fn is_active(&self) -> bool {
+        self.room.read().unwrap().is_active()
+    }
+
+    fn shutdown(&self) {
+        if let Some(handle) = self.network_handle.write().unwrap().take() {
+            handle.shutdown();
+        }
+    }
+}
+
+/// A host and its listeners
+pub struct TestRoom {
+    pub host: TestPeer,
+    pub listeners: Vec<TestPeer>,
+    tasks: TaskSet,
+}
+
+impl TestRoom {
+    /// Start a room with `host` as the host's Cider and a listener for each
+    /// of `listeners`; returns once every listener is in
+    pub async fn start(host: MockCider, listeners: Vec<MockCider>) -> Self {
+        let tasks = TaskSet::new();
+        let (host, host_handle) = TestPeer::start(host, Room::None, &tasks);
+        *host.room.write().unwrap() = Room::Active(RoomState::new_as_host(
+            ROOM_CODE.to_string(),
+            host.peer_id.clone(),
+            "Host".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        ));
+        host_handle.create_room(ROOM_CODE).unwrap();
+        let host_addr = wait_for("the host to listen", || {
+            host_handle
+                .status()
+                .listening_addresses
+                .into_iter()
+                .find(|a| a.starts_with("/ip4/127.0.0.1/tcp/"))
+        })
+        .await;
+
+        let listeners: Vec<TestPeer> = listeners
+            .into_iter()
+            .enumerate()
+            .map(|(i, cider)| {
+                let joining = Room::Joining {
+                    room_code: ROOM_CODE.to_string(),
+                    display_name: format!("Listener {}", i + 1),
+                    storefront: None,
+                    capabilities: Capabilities::default(),
+                    profile: Profile::default(),
+                    client: ClientInfo::default(),
+                    status: ParticipantStatus::default(),
+                    refusal: None,
+                };
+                let (listener, handle) = TestPeer::start(cider, joining, &tasks);
+                handle.join_room(ROOM_CODE).unwrap();
+                handle.dial_peer(&host_addr).unwrap();
+                listener
+            })
+            .collect();
+        wait_for("the listeners to join", || listeners.iter().all(TestPeer::is_active).then_some(())).await;
+
+        let room = Self { host, listeners, tasks };
+        room.start_host_broadcast();
+        room
+    }
+
+    /// Broadcast the host's playback every `HEARTBEAT_INTERVAL`
+    fn start_host_broadcast(&self) {
+        let room_actor = RoomActor::spawn(
+            Arc::clone(&self.host.room),
+            new_shared_recorder(),
+            &Handle::current(),
+            &self.tasks,
+        );
+        let broadcast = host_broadcast(
+            self.host.cider.clone(),
+            room_actor,
+            Arc::clone(&self.host.network_handle),
+            Clock::System,
+        );
+        self.tasks.spawn(&Handle::current(), broadcast);
+    }
+
+    /// Play `script` (host actions by time since the start) on the host's
+    /// Cider until `duration`, sampling the listeners' drift as it goes
+    pub async fn run(&self, script: Vec<(Duration, HostAction)>, duration: Duration) -> Vec<DriftSample> {
+        let listeners: Vec<_> = self.listeners.iter().map(|l| l.cider.clone()).collect();
+        run_script(&self.host.cider, &listeners, script, duration).await
+    }
+}
+
+impl Drop for TestRoom {
+    fn drop(&mut self) {
+        self.tasks.abort_all();
+        self.host.shutdown();
+        for listener in &self.listeners {
+            listener.shutdown();
+        }
+    }
+}
+
+/// Handle a peer's network events the way the session does
+pub(super) async fn handle_events(
+    mut events: mpsc::UnboundedReceiver<NetworkEvent>,
+    room: Arc<RwLock<Room>>,
+    cider: MockCider,
+    network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+    latency_tracker: SharedLatencyTracker,
+    local_peer_id: String,
+) {
+    let cider = Arc::new(RwLock::new(cider));
+    let callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>> = Arc::new(RwLock::new(None));
+    let seek_calibrator: SharedSeekCalibrator = seek_calibrator::new_shared_calibrator();
+    let settings = Arc::new(SyncSettings::new(DRIFT_THRESHOLD_MS, SyncStatusLevel::Off));
+    let player = Player::start();
+    let room_broadcaster = RoomBroadcaster::new(Arc::clone(&room), Arc::clone(&network_handle));
+    while let Some(event) = events.recv().await {
+        handle_network_event(
+            event,
+            &room,
+            &callback,
+            &cider,
+            &network_handle,
+            &latency_tracker,
+            &seek_calibrator,
+            &local_peer_id,
+            &settings,
+            &player,
+            &room_broadcaster,
+        )
+        .await;
+    }
+}
+
+/// Broadcast the host's playback every `HEARTBEAT_INTERVAL`, as the
+/// session's host loop does when polling Cider
+pub(super) async fn host_broadcast(
+    cider: MockCider,
+    room_actor: RoomActor,
+    network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+    clock: Clock,
+) {
+    let callback: RwLock<Option<Arc<dyn SessionCallback>>> = RwLock::new(None);
+    let last_track_id = RwLock::new(None);
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        heartbeat.tick().await;
+        if let Some(playback) = poll_host_playback(&cider).await {
+            broadcast_host_playback(&playback, &room_actor, &network_handle, &callback, &last_track_id, 0, clock.now_ms());
+        }
+    }
+}
+
+/// Play `script` (host actions by time since the start) on `host` until
+/// `duration`, sampling how far each of `listeners` is from it as it goes
+pub(super) async fn run_script(
+    host: &MockCider,
+    listeners: &[MockCider],
+    script: Vec<(Duration, HostAction)>,
+    duration: Duration,
+) -> Vec<DriftSample> {
+    let start = Instant::now();
+    let mut script = script.into_iter().peekable();
+    let mut last_action = Duration::ZERO;
+    let mut samples = Vec::new();
+    let mut sample = tokio::time::interval(SAMPLE_INTERVAL);
+
+    while start.elapsed() < duration {
+        sample.tick().await;
+        while let Some((_, action)) = script.next_if(|(at, _)| *at <= start.elapsed()) {
+            apply(host, action).await;
+            last_action = start.elapsed();
+        }
+        let at = start.elapsed();
+        samples.push(DriftSample {
+            at,
+            since_action: at - last_action,
+            drifts: drifts(host, listeners).await,
+        });
+    }
+    samples
+}
+
+async fn apply(host: &MockCider, action: HostAction) {
+    let _ = match action {
+        HostAction::Play => host.play().await,
+        HostAction::Pause => host.pause().await,
+        HostAction::Seek(position_ms) => host.seek_ms(position_ms).await,
+        HostAction::PlayTrack(id) => host.play_item("songs", &id).await,
+    };
+}
+
+/// How far each listener is from the host now
+async fn drifts(host: &MockCider, listeners: &[MockCider]) -> Vec<Option<i64>> {
+    let host_track = song_id(host).await;
+    let host_position = host.position_ms() as i64;
+    let mut drifts = Vec::new();
+    for listener in listeners {
+        let same_track = host_track.is_some() && song_id(listener).await == host_track;
+        drifts.push(same_track.then(|| listener.position_ms() as i64 - host_position));
+    }
+    drifts
+}
+
+async fn song_id(cider: &MockCider) -> Option<String> {
+    let np = cider.now_playing().await.ok().flatten()?;
+    np.song_id().map(|id| id.to_string())
+}
+
+/// Poll `ready` until it has a value, panicking after `WAIT_TIMEOUT`
+pub(super) async fn wait_for<T>(what: &str, mut ready: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + WAIT_TIMEOUT;
+    loop {
+        if let Some(value) = ready() {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::simulator::{Link, SimListener, Simulation};
+
+    /// Samples taken long enough after the host's last action for listeners to have followed it
+    const SETTLE: Duration = Duration::from_secs(2);
+
+    /// Listeners only re-sync once the drift they measure is past the
+    /// threshold, and what they measure is off by their latency estimate and
+    /// scheduling, so they can sit a little past it
+    const MAX_DRIFT_MS: u64 = DRIFT_THRESHOLD_MS + 100;
+
+    fn catalog() -> Vec<crate::cider::NowPlaying> {
+        vec![MockCider::track("1", 240_000), MockCider::track("2", 200_000)]
+    }
+
+    fn host() -> MockCider {
+        MockCider::new()
+            .with_catalog(catalog())
+            .with_playing(MockCider::track("1", 240_000), 10_000)
+    }
+
+    /// Cider buffers for a while after each seek, longer on some machines than others
+    fn listeners() -> Vec<MockCider> {
+        [400, 500, 700]
+            .into_iter()
+            .map(|latency_ms| {
+                MockCider::new()
+                    .with_catalog(catalog())
+                    .with_seek_latency(Duration::from_millis(latency_ms))
+            })
+            .collect()
+    }
+
+    fn script() -> Vec<(Duration, HostAction)> {
+        vec![
+            (Duration::from_secs(3), HostAction::Seek(90_000)),
+            (Duration::from_secs(6), HostAction::Pause),
+            (Duration::from_secs(9), HostAction::Play),
+            (Duration::from_secs(12), HostAction::PlayTrack("2".to_string())),
+        ]
+    }
+
+    fn assert_followed(samples: &[DriftSample]) {
+        let settled: Vec<_> = samples.iter().filter(|s| s.since_action >= SETTLE).collect();
+        assert!(settled.len() > 10, "only {} settled samples", settled.len());
+        for sample in settled {
+            for (listener, drift) in sample.drifts.iter().enumerate() {
+                let Some(drift) = drift else {
+                    panic!("listener {} not on the host's track at {:?}", listener + 1, sample.at);
+                };
+                assert!(
+                    drift.unsigned_abs() <= MAX_DRIFT_MS,
+                    "listener {} drifted {}ms at {:?}",
+                    listener + 1,
+                    drift,
+                    sample.at
+                );
+            }
+        }
+    }
+
+    /// The script on tokio's paused clock, over links as quick as loopback
+    #[tokio::test(start_paused = true)]
+    async fn test_listeners_follow_host_timeline() {
+        let listeners = listeners()
+            .into_iter()
+            .map(|cider| SimListener {
+                cider,
+                link: Link::new(Duration::ZERO),
+                clock_skew_ms: 0,
+            })
+            .collect();
+        let sim = Simulation::start(0, host(), listeners).await;
+        assert_followed(&sim.run(script(), Duration::from_secs(15)).await);
+    }
+
+    /// The same script through real network stacks
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "plays a 15s script in real time over loopback TCP"]
+    async fn test_listeners_follow_host_timeline_over_tcp() {
+        let room = TestRoom::start(host(), listeners()).await;
+        assert_followed(&room.run(script(), Duration::from_secs(15)).await);
+    }
+}
@@ -0,0 +1,165 @@
+//! Per-subsystem checks behind `Session::health_check`
+//!
+//! The apps used to stitch a status screen together from several calls
+//! (Cider connection, diagnostics, network info, room state). These turn the
+//! probe results and state snapshots into one status per subsystem.
+
+use crate::cider::CiderError;
+use crate::network::NetworkStatus;
+use crate::sync::Room;
+
+use super::types::{ErrorCode, SubsystemHealth};
+
+/// Whether a probe reached Cider (a rejected token still counts)
+fn answered(probe: &Result<(), CiderError>) -> bool {
+    matches!(probe, Ok(()) | Err(CiderError::Unauthorized))
+}
+
+/// Whether Cider's API answers at `location`
+pub fn cider(probe: &Result<(), CiderError>, location: &str) -> SubsystemHealth {
+    match probe {
+        Err(e) if !answered(probe) => {
+            SubsystemHealth::failed(ErrorCode::CiderNotReachable, format!("Not answering on {}: {}", location, e))
+        }
+        _ => SubsystemHealth::ok(format!("Answering on {}", location)),
+    }
+}
+
+/// Whether Cider accepts our token, from a probe made with it
+pub fn cider_token(reachable: &Result<(), CiderError>, probe: &Result<(), CiderError>, has_token: bool) -> SubsystemHealth {
+    if !answered(reachable) {
+        return SubsystemHealth::skipped("Cider isn't reachable");
+    }
+    match probe {
+        Ok(()) if has_token => SubsystemHealth::ok("Accepted"),
+        Ok(()) => SubsystemHealth::ok("Cider doesn't require a token"),
+        Err(CiderError::Unauthorized) if has_token => {
+            SubsystemHealth::failed(ErrorCode::CiderUnauthorized, "Cider rejected the API token")
+        }
+        Err(CiderError::Unauthorized) => {
+            SubsystemHealth::failed(ErrorCode::CiderUnauthorized, "Cider requires an API token")
+        }
+        Err(e) => SubsystemHealth::failed(ErrorCode::CiderApi, e.to_string()),
+    }
+}
+
+/// Whether the P2P network is running and connected to the wider network
+pub fn network(status: Option<&NetworkStatus>, lan_only: bool) -> SubsystemHealth {
+    let Some(status) = status else {
+        return SubsystemHealth::skipped("Not started (starts with a room)");
+    };
+    if lan_only {
+        return SubsystemHealth::ok(format!("LAN only, {} peers connected", status.connected_peers));
+    }
+    if status.dht_ready || status.connected_bootstrap_nodes > 0 {
+        SubsystemHealth::ok(format!("{} peers connected", status.connected_peers))
+    } else if status.connected_peers > 0 {
+        SubsystemHealth::degraded("No bootstrap nodes reachable: only rooms on this network can be found")
+    } else {
+        SubsystemHealth::failed(ErrorCode::NetworkUnavailable, "No peers reachable")
+    }
+}
+
+/// Whether peers outside our network can reach us (directly, or through a relay reservation)
+pub fn relay(status: Option<&NetworkStatus>, lan_only: bool) -> SubsystemHealth {
+    let Some(status) = status else {
+        return SubsystemHealth::skipped("Network not started");
+    };
+    if lan_only {
+        return SubsystemHealth::skipped("Relays aren't used in LAN-only sessions");
+    }
+    if status.publicly_reachable {
+        SubsystemHealth::ok("Publicly reachable, no relay needed")
+    } else if status.active_relay_reservations > 0 {
+        SubsystemHealth::ok(format!("{} relay reservations", status.active_relay_reservations))
+    } else if status.relay_connections > 0 {
+        SubsystemHealth::degraded("Connected to a relay, waiting for a reservation")
+    } else {
+        SubsystemHealth::failed(ErrorCode::RelayUnreachable, "No relay: peers on other networks can't reach us")
+    }
+}
+
+/// Whether we're in a room and, as a listener, connected to its host
+pub fn room(room: &Room, status: Option<&NetworkStatus>) -> SubsystemHealth {
+    match room {
+        Room::None => SubsystemHealth::skipped("Not in a room"),
+        Room::Creating { .. } => SubsystemHealth::degraded("Creating a room"),
+        Room::Joining { room_code, .. } => SubsystemHealth::degraded(format!("Looking for the host of {}", room_code)),
+        Room::Active(state) if state.is_host() => SubsystemHealth::ok(format!("Hosting {}", state.room_code)),
+        Room::Active(state) => {
+            let connected = status.is_some_and(|s| s.peer_transports.contains_key(&state.host_peer_id));
+            if connected {
+                SubsystemHealth::ok(format!("In {}, connected to the host", state.room_code))
+            } else {
+                SubsystemHealth::failed(ErrorCode::Network, format!("In {}, but not connected to the host", state.room_code))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::PeerTransport;
+    use crate::sync::{Capabilities, ClientInfo, Profile, RoomState};
+    use super::super::types::HealthStatus;
+
+    #[test]
+    fn test_cider_token() {
+        let reachable = Ok(());
+        assert_eq!(cider(&reachable, "localhost:10767").status, HealthStatus::Ok);
+        assert_eq!(cider_token(&reachable, &Ok(()), true).status, HealthStatus::Ok);
+
+        let rejected = cider_token(&reachable, &Err(CiderError::Unauthorized), true);
+        assert_eq!(rejected.error_code, Some(ErrorCode::CiderUnauthorized));
+
+        // The token can't be judged without Cider
+        let unreachable = Err(CiderError::NotReachable);
+        assert_eq!(cider(&unreachable, "localhost:10767").error_code, Some(ErrorCode::CiderNotReachable));
+        assert_eq!(cider_token(&unreachable, &Err(CiderError::NotReachable), true).status, HealthStatus::Skipped);
+    }
+
+    #[test]
+    fn test_network_and_relay() {
+        assert_eq!(network(None, false).status, HealthStatus::Skipped);
+        assert_eq!(relay(None, false).status, HealthStatus::Skipped);
+
+        let mut status = NetworkStatus {
+            connected_peers: 2,
+            relay_connections: 1,
+            ..NetworkStatus::default()
+        };
+        assert_eq!(network(Some(&status), false).status, HealthStatus::Degraded);
+        assert_eq!(network(Some(&status), true).status, HealthStatus::Ok);
+        assert_eq!(relay(Some(&status), false).status, HealthStatus::Degraded);
+        assert_eq!(relay(Some(&status), true).status, HealthStatus::Skipped);
+
+        status.dht_ready = true;
+        status.active_relay_reservations = 1;
+        assert_eq!(network(Some(&status), false).status, HealthStatus::Ok);
+        assert_eq!(relay(Some(&status), false).status, HealthStatus::Ok);
+    }
+
+    #[test]
+    fn test_room_needs_host_connection() {
+        assert_eq!(room(&Room::None, None).status, HealthStatus::Skipped);
+
+        let mut state = RoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        assert_eq!(room(&Room::Active(state.clone()), None).status, HealthStatus::Ok);
+
+        state.host_peer_id = "host".to_string();
+        let mut status = NetworkStatus::default();
+        assert_eq!(room(&Room::Active(state.clone()), Some(&status)).status, HealthStatus::Failed);
+
+        status.peer_transports.insert("host".to_string(), PeerTransport::Relayed);
+        assert_eq!(room(&Room::Active(state), Some(&status)).status, HealthStatus::Ok);
+    }
+}
@@ -0,0 +1,232 @@
+//! Forwarding core logs to the apps
+//!
+//! Logs normally only reach stderr, which users can't see without Xcode or
+//! adb attached. A `LogCallback` registered with `set_log_callback` gets
+//! formatted lines too, so the apps can show and share them in a debug
+//! console. Bursts (e.g. a reconnect storm) are capped so they can't flood
+//! the UI thread.
+
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use super::types::{current_time_ms, LogCallback, LogLevel, LogLine};
+
+/// Most lines forwarded per second; the rest are counted and reported as dropped
+const MAX_LINES_PER_SECOND: u32 = 50;
+
+/// Where log lines go, shared by the tracing layer and `set_log_callback`
+pub struct LogSink {
+    callback: RwLock<Option<(Arc<dyn LogCallback>, Level)>>,
+    limiter: Mutex<LineLimiter>,
+}
+
+/// The sink behind the process-wide tracing subscriber
+static LOG_SINK: LogSink = LogSink::new();
+
+impl LogSink {
+    pub const fn new() -> Self {
+        Self {
+            callback: RwLock::new(None),
+            limiter: Mutex::new(LineLimiter::new(MAX_LINES_PER_SECOND)),
+        }
+    }
+
+    /// Send lines at `min_level` or more severe to `callback`
+    pub fn set(&self, callback: Arc<dyn LogCallback>, min_level: Level) {
+        *self.callback.write().unwrap() = Some((callback, min_level));
+    }
+
+    pub fn clear(&self) {
+        *self.callback.write().unwrap() = None;
+    }
+
+    fn forward(&self, level: &Level, target: &str, message: String) {
+        let Some((callback, min_level)) = self.callback.read().unwrap().clone() else {
+            return;
+        };
+        if *level > min_level {
+            return;
+        }
+
+        // Not called with the lock held: the callback may log itself
+        let (allowed, dropped) = self.limiter.lock().unwrap().admit(Instant::now());
+        if dropped > 0 {
+            callback.on_log(LogLine {
+                timestamp_ms: current_time_ms(),
+                level: LogLevel::Warn,
+                target: module_path!().to_string(),
+                message: format!("{} log lines dropped", dropped),
+            });
+        }
+        if allowed {
+            callback.on_log(LogLine {
+                timestamp_ms: current_time_ms(),
+                level: LogLevel::from(level),
+                target: target.to_string(),
+                message,
+            });
+        }
+    }
+}
+
+/// Caps lines per one-second window
+struct LineLimiter {
+    max_per_window: u32,
+    window_start: Option<Instant>,
+    in_window: u32,
+    dropped: u32,
+}
+
+impl LineLimiter {
+    const fn new(max_per_window: u32) -> Self {
+        Self {
+            max_per_window,
+            window_start: None,
+            in_window: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Whether a line at `now` may go out, and how many were dropped before it (reported once)
+    fn admit(&mut self, now: Instant) -> (bool, u32) {
+        let mut dropped = 0;
+        if self.window_start.is_none_or(|start| now.duration_since(start) >= Duration::from_secs(1)) {
+            self.window_start = Some(now);
+            self.in_window = 0;
+            dropped = std::mem::take(&mut self.dropped);
+        }
+
+        if self.in_window < self.max_per_window {
+            self.in_window += 1;
+            (true, dropped)
+        } else {
+            self.dropped += 1;
+            (false, dropped)
+        }
+    }
+}
+
+/// Tracing layer feeding a sink
+pub struct CallbackLayer {
+    sink: &'static LogSink,
+}
+
+/// Layer for the session's subscriber, feeding the callback set with `set_log_callback`
+pub fn layer() -> CallbackLayer {
+    CallbackLayer { sink: &LOG_SINK }
+}
+
+impl<S: Subscriber> Layer<S> for CallbackLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // Skip formatting when nobody listens
+        if self.sink.callback.read().unwrap().is_none() {
+            return;
+        }
+        let mut message = MessageVisitor::default();
+        event.record(&mut message);
+        let metadata = event.metadata();
+        self.sink.forward(metadata.level(), metadata.target(), message.0);
+    }
+}
+
+/// Formats an event as its message followed by `name=value` fields
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let fields = std::mem::take(&mut self.0);
+            let _ = write!(self.0, "{:?}", value);
+            if !fields.is_empty() {
+                self.0.push_str(&fields);
+            }
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Send core log lines at `min_level` or more severe to `callback`
+///
+/// Applies to every session in the process. Lines are capped at 50 per
+/// second; a warning says how many were dropped.
+#[uniffi::export]
+pub fn set_log_callback(callback: Box<dyn LogCallback>, min_level: LogLevel) {
+    LOG_SINK.set(Arc::from(callback), Level::from(min_level));
+}
+
+/// Stop sending log lines to the callback set with `set_log_callback`
+#[uniffi::export]
+pub fn clear_log_callback() {
+    LOG_SINK.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Lines(Mutex<Vec<LogLine>>);
+
+    impl LogCallback for Lines {
+        fn on_log(&self, line: LogLine) {
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    #[test]
+    fn test_level_filter() {
+        let sink = LogSink::new();
+        let lines = Arc::new(Lines::default());
+        sink.set(lines.clone(), Level::INFO);
+
+        sink.forward(&Level::WARN, "cider_core", "kept".to_string());
+        sink.forward(&Level::DEBUG, "cider_core", "too verbose".to_string());
+
+        let lines = lines.0.lock().unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].message, "kept");
+        assert_eq!(lines[0].level, LogLevel::Warn);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut limiter = LineLimiter::new(2);
+        let start = Instant::now();
+        assert_eq!(limiter.admit(start), (true, 0));
+        assert_eq!(limiter.admit(start), (true, 0));
+        assert_eq!(limiter.admit(start), (false, 0));
+        assert_eq!(limiter.admit(start + Duration::from_millis(500)), (false, 0));
+
+        // The next window reports what was dropped
+        assert_eq!(limiter.admit(start + Duration::from_secs(1)), (true, 2));
+        assert_eq!(limiter.admit(start + Duration::from_secs(1)), (true, 0));
+    }
+
+    #[test]
+    fn test_layer_forwards_events() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let sink: &'static LogSink = Box::leak(Box::new(LogSink::new()));
+        let lines = Arc::new(Lines::default());
+        sink.set(lines.clone(), Level::DEBUG);
+
+        let subscriber = tracing_subscriber::registry().with(CallbackLayer { sink });
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(peer = "abc", "Connected");
+            tracing::trace!("Too verbose");
+        });
+
+        let lines = lines.0.lock().unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].message, "Connected peer=\"abc\"");
+        assert_eq!(lines[0].level, LogLevel::Info);
+        assert_eq!(lines[0].target, module_path!());
+    }
+}
@@ -2,9 +2,39 @@
 //!
 //! This module provides the interface exposed via uniffi to Swift/Kotlin.
 
+#[cfg(feature = "bridge")]
+mod bridge;
+mod deep_link;
+mod diagnostics;
+mod events;
 mod handlers;
+#[cfg(test)]
+mod harness;
+mod health;
+mod logging;
+#[cfg(feature = "bridge")]
+mod overlay;
+mod persistence;
+mod player;
+mod playlist;
+mod room_actor;
+mod room_broadcast;
+mod runtime;
 mod session;
+#[cfg(test)]
+mod simulator;
 mod types;
 
+pub use logging::{clear_log_callback, set_log_callback};
+
 pub use session::*;
 pub use types::*;
+
+/// Sync handlers, for the benchmarks (not a stable API)
+#[cfg(feature = "bench-internals")]
+#[doc(hidden)]
+pub mod bench {
+    pub use super::handlers::{handle_network_event, room_state_message, SyncSettings};
+    pub use super::player::Player;
+    pub use super::room_broadcast::RoomBroadcaster;
+}
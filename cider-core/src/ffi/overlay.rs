@@ -0,0 +1,171 @@
+//! Stream overlay served by the bridge
+//!
+//! Streamers add `http://localhost:<bridge port>/overlay?token=<bridge token>`
+//! as a browser source in OBS (or anything that shows a web page) to put the
+//! room on stream: the current track with its artwork and progress, and who's
+//! listening. The page polls `/api/overlay` (with the same token) for the same
+//! as JSON, which leaves out peer IDs since it ends up in front of an audience.
+
+use serde::Serialize;
+
+use crate::artwork;
+
+use super::types::{current_time_ms, RoomState};
+
+/// The overlay page (transparent background, text drawn with a shadow)
+pub const PAGE: &str = include_str!("overlay.html");
+
+/// Artwork size for the overlay (px)
+const ARTWORK_SIZE: u32 = 300;
+
+/// What the overlay shows
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Overlay {
+    /// None when not in a room
+    pub room_code: Option<String>,
+    pub track: Option<OverlayTrack>,
+    pub is_playing: bool,
+    /// Where the host's playback is now
+    pub position_ms: u64,
+    /// Host first
+    pub participants: Vec<OverlayParticipant>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OverlayTrack {
+    pub name: String,
+    pub artist: String,
+    pub album: String,
+    /// Sized for the overlay (empty if the track has none)
+    pub artwork_url: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OverlayParticipant {
+    pub display_name: String,
+    pub is_host: bool,
+    pub avatar: Option<String>,
+    pub color: Option<String>,
+}
+
+impl Overlay {
+    /// The overlay for `room` (None when not in a room), as of `now_ms`
+    pub fn new(room: Option<&RoomState>, now_ms: u64) -> Self {
+        let Some(room) = room else {
+            return Self {
+                room_code: None,
+                track: None,
+                is_playing: false,
+                position_ms: 0,
+                participants: Vec::new(),
+            };
+        };
+
+        let track = room.current_track.as_ref().map(|t| OverlayTrack {
+            name: t.name.clone(),
+            artist: t.artist.clone(),
+            album: t.album.clone(),
+            artwork_url: artwork::sized_url(&t.artwork_url, ARTWORK_SIZE).unwrap_or_default(),
+            duration_ms: t.duration_ms,
+        });
+        let playback = &room.playback;
+        let mut position_ms = playback.position_ms;
+        if playback.is_playing {
+            position_ms += now_ms.saturating_sub(playback.timestamp_ms);
+        }
+        if let Some(track) = &track {
+            position_ms = position_ms.min(track.duration_ms);
+        }
+
+        let mut participants: Vec<OverlayParticipant> = room
+            .participants
+            .iter()
+            .map(|p| OverlayParticipant {
+                display_name: p.display_name.clone(),
+                is_host: p.is_host,
+                avatar: p.avatar.clone(),
+                color: p.color.clone(),
+            })
+            .collect();
+        participants.sort_by_key(|p| !p.is_host);
+
+        Self {
+            room_code: Some(room.room_code.clone()),
+            track,
+            is_playing: playback.is_playing,
+            position_ms,
+            participants,
+        }
+    }
+
+    /// The overlay for `room` now
+    pub fn now(room: Option<&RoomState>) -> Self {
+        Self::new(room, current_time_ms())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::types::{Participant, ParticipantStatus, Platform, PlaybackState, RoomSettings, TrackInfo};
+
+    fn participant(name: &str, is_host: bool) -> Participant {
+        Participant {
+            peer_id: format!("12D3KooW{}", name),
+            display_name: name.to_string(),
+            is_host,
+            storefront: None,
+            explicit_restricted: None,
+            avatar: None,
+            color: Some("#FF2D55".to_string()),
+            platform: Platform::MacOs,
+            app_version: None,
+            core_version: None,
+            status: ParticipantStatus::Active,
+        }
+    }
+
+    #[test]
+    fn test_overlay() {
+        assert_eq!(Overlay::new(None, 0).room_code, None);
+
+        let room = RoomState {
+            room_code: "ABCD4679".to_string(),
+            local_peer_id: "12D3KooWAlex".to_string(),
+            host_peer_id: "12D3KooWSam".to_string(),
+            participants: vec![participant("Alex", false), participant("Sam", true)],
+            current_track: Some(TrackInfo {
+                song_id: "1440818839".to_string(),
+                name: "Song".to_string(),
+                artist: "Artist".to_string(),
+                album: "Album".to_string(),
+                artwork_url: "https://is1-ssl.mzstatic.com/image/thumb/a/b/{w}x{h}bb.jpg".to_string(),
+                duration_ms: 200_000,
+                position_ms: 0,
+                isrc: None,
+                explicit: false,
+            }),
+            playback: PlaybackState {
+                is_playing: true,
+                position_ms: 60_000,
+                timestamp_ms: 1_000_000,
+            },
+            locked: false,
+            suggestions_muted: Vec::new(),
+            settings: RoomSettings::from(&crate::sync::RoomSettings::default()),
+        };
+
+        let overlay = Overlay::new(Some(&room), 1_002_500);
+        assert_eq!(overlay.position_ms, 62_500);
+        let track = overlay.track.as_ref().unwrap();
+        assert_eq!(track.artwork_url, "https://is1-ssl.mzstatic.com/image/thumb/a/b/300x300bb.jpg");
+        let names: Vec<_> = overlay.participants.iter().map(|p| p.display_name.as_str()).collect();
+        assert_eq!(names, vec!["Sam", "Alex"]);
+        // No peer IDs on stream
+        assert!(!serde_json::to_string(&overlay).unwrap().contains("12D3KooW"));
+
+        // Held at the end of the track
+        assert_eq!(Overlay::new(Some(&room), 2_000_000).position_ms, 200_000);
+    }
+}
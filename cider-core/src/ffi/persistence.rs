@@ -0,0 +1,301 @@
+//! State kept across app launches
+//!
+//! Mobile apps get killed by the OS mid-party. The session keeps what it needs
+//! to pick up again in the app's `SecureStorage` (Keychain, encrypted
+//! preferences) rather than on the filesystem: our identity keypair, so peers
+//! and bans recognize us; the seek calibration; which room we're in, with its
+//! blocklist, so `Session::restore_last_session` can get back into it; and
+//! our friends.
+
+use std::collections::HashSet;
+
+use libp2p::identity::Keypair;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::seek_calibrator::SeekKind;
+
+use super::types::{Friend, SavedSession, SecureStorage};
+
+/// Storage key of the last room
+const LAST_SESSION_KEY: &str = "last_session";
+
+/// Storage key of the last room's banned peers
+const BLOCKLIST_KEY: &str = "blocklist";
+
+/// Storage key of the seek calibration for seeks within a track
+const CALIBRATION_KEY: &str = "seek_calibration";
+
+/// Storage key of the seek calibration for seeks right after loading a track
+const TRACK_START_CALIBRATION_KEY: &str = "seek_calibration_track_start";
+
+/// Storage key of our identity keypair (hex-encoded protobuf)
+const KEYPAIR_KEY: &str = "identity_keypair";
+
+/// Storage key of our friends
+const FRIENDS_KEY: &str = "friends";
+
+/// Storage key of the bridge's token (hex)
+#[cfg(feature = "bridge")]
+const BRIDGE_TOKEN_KEY: &str = "bridge_token";
+
+/// A converged seek calibration, so the next party doesn't relearn it from the default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedCalibration {
+    pub offset_ms: u64,
+    pub sample_count: u32,
+}
+
+/// Peers banned from a room, saved for when the host restores it
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedBlocklist {
+    room_code: String,
+    banned: Vec<String>,
+}
+
+/// Save the room we're in
+pub fn save_last_session(storage: &dyn SecureStorage, session: &SavedSession) {
+    match serde_json::to_string(session) {
+        Ok(json) => storage.set(LAST_SESSION_KEY.to_string(), json),
+        Err(e) => warn!("Couldn't save the last session: {}", e),
+    }
+}
+
+/// The room we were last in, if saved
+pub fn load_last_session(storage: &dyn SecureStorage) -> Option<SavedSession> {
+    let json = storage.get(LAST_SESSION_KEY.to_string())?;
+    match serde_json::from_str(&json) {
+        Ok(session) => Some(session),
+        Err(e) => {
+            warn!("Ignoring unreadable saved session: {}", e);
+            clear_last_session(storage);
+            None
+        }
+    }
+}
+
+/// Forget the last room and its blocklist (left on purpose, or it's gone)
+pub fn clear_last_session(storage: &dyn SecureStorage) {
+    storage.delete(LAST_SESSION_KEY.to_string());
+    storage.delete(BLOCKLIST_KEY.to_string());
+}
+
+/// Save the peers banned from the room we're hosting
+pub fn save_blocklist(storage: &dyn SecureStorage, room_code: &str, banned: &HashSet<String>) {
+    let blocklist = SavedBlocklist {
+        room_code: room_code.to_string(),
+        banned: banned.iter().cloned().collect(),
+    };
+    match serde_json::to_string(&blocklist) {
+        Ok(json) => storage.set(BLOCKLIST_KEY.to_string(), json),
+        Err(e) => warn!("Couldn't save the blocklist: {}", e),
+    }
+}
+
+/// Peers banned from `room_code`, if it's the room whose blocklist was saved
+pub fn load_blocklist(storage: &dyn SecureStorage, room_code: &str) -> HashSet<String> {
+    storage
+        .get(BLOCKLIST_KEY.to_string())
+        .and_then(|json| serde_json::from_str::<SavedBlocklist>(&json).ok())
+        .filter(|blocklist| blocklist.room_code == room_code)
+        .map(|blocklist| blocklist.banned.into_iter().collect())
+        .unwrap_or_default()
+}
+
+fn calibration_key(kind: SeekKind) -> String {
+    match kind {
+        SeekKind::InTrack => CALIBRATION_KEY.to_string(),
+        SeekKind::TrackStart => TRACK_START_CALIBRATION_KEY.to_string(),
+    }
+}
+
+/// Save the seek calibration for `kind`
+pub fn save_calibration(storage: &dyn SecureStorage, kind: SeekKind, calibration: SavedCalibration) {
+    match serde_json::to_string(&calibration) {
+        Ok(json) => storage.set(calibration_key(kind), json),
+        Err(e) => warn!("Couldn't save the seek calibration: {}", e),
+    }
+}
+
+/// The seek calibration for `kind` learned in an earlier run, if any
+pub fn load_calibration(storage: &dyn SecureStorage, kind: SeekKind) -> Option<SavedCalibration> {
+    serde_json::from_str(&storage.get(calibration_key(kind))?).ok()
+}
+
+/// Our saved identity keypair, or a new one (saved for next time)
+pub fn load_or_create_keypair(storage: &dyn SecureStorage) -> Keypair {
+    let saved = storage
+        .get(KEYPAIR_KEY.to_string())
+        .and_then(|hex| decode_hex(&hex))
+        .and_then(|bytes| Keypair::from_protobuf_encoding(&bytes).ok());
+    if let Some(keypair) = saved {
+        return keypair;
+    }
+
+    let keypair = Keypair::generate_ed25519();
+    match keypair.to_protobuf_encoding() {
+        Ok(bytes) => storage.set(KEYPAIR_KEY.to_string(), encode_hex(&bytes)),
+        Err(e) => warn!("Couldn't save the identity keypair: {}", e),
+    }
+    keypair
+}
+
+/// The bridge's saved token, or a new one (saved for next time), so
+/// integrations set up with it keep working across launches
+#[cfg(feature = "bridge")]
+pub fn load_or_create_bridge_token(storage: &dyn SecureStorage) -> String {
+    if let Some(token) = storage.get(BRIDGE_TOKEN_KEY.to_string()).filter(|t| t.len() == 32) {
+        return token;
+    }
+    let token = new_bridge_token();
+    storage.set(BRIDGE_TOKEN_KEY.to_string(), token.clone());
+    token
+}
+
+/// A new random bridge token
+#[cfg(feature = "bridge")]
+pub fn new_bridge_token() -> String {
+    encode_hex(&rand::random::<[u8; 16]>())
+}
+
+/// Save our friends
+pub fn save_friends(storage: &dyn SecureStorage, friends: &[Friend]) {
+    match serde_json::to_string(friends) {
+        Ok(json) => storage.set(FRIENDS_KEY.to_string(), json),
+        Err(e) => warn!("Couldn't save friends: {}", e),
+    }
+}
+
+/// Our saved friends (none if unreadable)
+pub fn load_friends(storage: &dyn SecureStorage) -> Vec<Friend> {
+    let Some(json) = storage.get(FRIENDS_KEY.to_string()) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&json).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable friends list: {}", e);
+        Vec::new()
+    })
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok().filter(|p| p.len() == 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryStorage(Mutex<HashMap<String, String>>);
+
+    impl SecureStorage for MemoryStorage {
+        fn get(&self, key: String) -> Option<String> {
+            self.0.lock().unwrap().get(&key).cloned()
+        }
+
+        fn set(&self, key: String, value: String) {
+            self.0.lock().unwrap().insert(key, value);
+        }
+
+        fn delete(&self, key: String) {
+            self.0.lock().unwrap().remove(&key);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let storage = MemoryStorage::default();
+        assert!(load_last_session(&storage).is_none());
+
+        let session = SavedSession {
+            room_code: "ABC123".to_string(),
+            display_name: "Me".to_string(),
+            was_host: true,
+        };
+        save_last_session(&storage, &session);
+        assert_eq!(load_last_session(&storage), Some(session));
+
+        clear_last_session(&storage);
+        assert!(load_last_session(&storage).is_none());
+    }
+
+    #[test]
+    fn test_unreadable_is_dropped() {
+        let storage = MemoryStorage::default();
+        storage.set(LAST_SESSION_KEY.to_string(), "{not json".to_string());
+
+        assert!(load_last_session(&storage).is_none());
+        assert!(storage.get(LAST_SESSION_KEY.to_string()).is_none());
+    }
+
+    #[test]
+    fn test_blocklist_belongs_to_room() {
+        let storage = MemoryStorage::default();
+        let banned = HashSet::from(["troll".to_string()]);
+        save_blocklist(&storage, "ABC123", &banned);
+
+        assert_eq!(load_blocklist(&storage, "ABC123"), banned);
+        assert!(load_blocklist(&storage, "XYZ789").is_empty());
+
+        // Goes with the room
+        clear_last_session(&storage);
+        assert!(load_blocklist(&storage, "ABC123").is_empty());
+    }
+
+    #[test]
+    fn test_identity_and_calibration_kept() {
+        let storage = MemoryStorage::default();
+        let keypair = load_or_create_keypair(&storage);
+        assert_eq!(load_or_create_keypair(&storage).public(), keypair.public());
+
+        // A corrupt keypair is replaced
+        storage.set(KEYPAIR_KEY.to_string(), "zz".to_string());
+        assert_ne!(load_or_create_keypair(&storage).public(), keypair.public());
+
+        assert_eq!(load_calibration(&storage, SeekKind::InTrack), None);
+        let calibration = SavedCalibration {
+            offset_ms: 650,
+            sample_count: 8,
+        };
+        save_calibration(&storage, SeekKind::InTrack, calibration);
+        assert_eq!(load_calibration(&storage, SeekKind::InTrack), Some(calibration));
+        assert_eq!(load_calibration(&storage, SeekKind::TrackStart), None);
+    }
+
+    #[cfg(feature = "bridge")]
+    #[test]
+    fn test_bridge_token_kept() {
+        let storage = MemoryStorage::default();
+        let token = load_or_create_bridge_token(&storage);
+        assert_eq!(token.len(), 32);
+        assert_eq!(load_or_create_bridge_token(&storage), token);
+        assert_ne!(new_bridge_token(), token);
+    }
+
+    #[test]
+    fn test_friends_kept() {
+        let storage = MemoryStorage::default();
+        assert!(load_friends(&storage).is_empty());
+
+        let friend = Friend {
+            peer_id: "12D3KooWfriend".to_string(),
+            display_name: "Sam".to_string(),
+            hosting_room: Some("ABCD4679".to_string()),
+        };
+        save_friends(&storage, std::slice::from_ref(&friend));
+        // Whether they're hosting is only known while it lasts
+        let loaded = load_friends(&storage);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].peer_id, friend.peer_id);
+        assert_eq!(loaded[0].hosting_room, None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Listener playback, run off the room actor
+//!
+//! Following the host means Cider calls that can take seconds: a track that
+//! won't play is given 5s before looking for it in our storefront. Awaited on
+//! the room actor, they held up every change queued behind them, and with it
+//! any FFI call waiting on one (leaving the room included). The actor now
+//! updates the room itself and hands the Cider side to this task, which runs
+//! it in the order the host's messages arrived.
+//!
+//! Heartbeats come every second or so, and one the player couldn't get to in
+//! time is outdated by the next: only the latest queued runs. Nothing queued
+//! in a room we've left runs either.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+
+use super::runtime::TaskSet;
+
+type Job = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A job, and what it was queued for
+struct Queued {
+    job: Job,
+    /// Room it was queued in (see `Player::next_room`)
+    room: u64,
+    /// Heartbeats are numbered: only the latest one queued runs
+    heartbeat: Option<u64>,
+}
+
+/// Queues Cider work for the task running it
+#[derive(Clone)]
+pub struct Player {
+    jobs: mpsc::UnboundedSender<Queued>,
+    /// Bumped when we leave a room: what was queued in it is dropped
+    room: Arc<AtomicU64>,
+    /// Number of the latest heartbeat queued
+    heartbeats: Arc<AtomicU64>,
+}
+
+impl Player {
+    /// Start the player on `runtime` (stopped with the session's other tasks)
+    pub fn spawn(runtime: &Handle, tasks: &TaskSet) -> Self {
+        let (player, job_rx) = Self::unstarted();
+        tasks.spawn(runtime, player.clone().serve(job_rx));
+        player
+    }
+
+    /// Start the player on the current runtime (and clock), until it's dropped
+    #[cfg(any(test, feature = "bench-internals"))]
+    pub fn start() -> Self {
+        let (player, job_rx) = Self::unstarted();
+        tokio::spawn(player.clone().serve(job_rx));
+        player
+    }
+
+    fn unstarted() -> (Self, mpsc::UnboundedReceiver<Queued>) {
+        let (jobs, job_rx) = mpsc::unbounded_channel();
+        let player = Self {
+            jobs,
+            room: Arc::new(AtomicU64::new(0)),
+            heartbeats: Arc::new(AtomicU64::new(0)),
+        };
+        (player, job_rx)
+    }
+
+    /// Run `job` after those already queued
+    pub fn run(&self, job: impl Future<Output = ()> + Send + 'static) {
+        self.queue(Box::pin(job), None);
+    }
+
+    /// Run a heartbeat's `job` after those already queued, unless another
+    /// heartbeat is queued before it starts: only the latest host playback
+    /// is worth following
+    pub fn run_heartbeat(&self, job: impl Future<Output = ()> + Send + 'static) {
+        let number = self.heartbeats.fetch_add(1, Ordering::SeqCst) + 1;
+        self.queue(Box::pin(job), Some(number));
+    }
+
+    /// We left the room: drop the jobs queued in it that haven't started
+    pub fn next_room(&self) {
+        self.room.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn queue(&self, job: Job, heartbeat: Option<u64>) {
+        let room = self.room.load(Ordering::SeqCst);
+        let _ = self.jobs.send(Queued { job, room, heartbeat });
+    }
+
+    /// Wait for the jobs queued so far to finish
+    #[cfg(any(test, feature = "bench-internals"))]
+    pub async fn flush(&self) {
+        let (done, finished) = tokio::sync::oneshot::channel();
+        self.run(async move {
+            let _ = done.send(());
+        });
+        let _ = finished.await;
+    }
+
+    async fn serve(self, mut jobs: mpsc::UnboundedReceiver<Queued>) {
+        // Holding only what it reads, so the player stops once every handle is dropped
+        let Self { room, heartbeats, .. } = self;
+        while let Some(queued) = jobs.recv().await {
+            if queued.room != room.load(Ordering::SeqCst) {
+                continue;
+            }
+            if queued.heartbeat.is_some_and(|number| number != heartbeats.load(Ordering::SeqCst)) {
+                continue;
+            }
+            queued.job.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_jobs_run_in_order() {
+        let player = Player::start();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for (i, delay_ms) in [(1, 30), (2, 0), (3, 10)] {
+            let order = Arc::clone(&order);
+            player.run(async move {
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                order.lock().unwrap().push(i);
+            });
+        }
+        player.flush().await;
+        assert_eq!(*order.lock().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_outdated_jobs_dropped() {
+        let player = Player::start();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let push = |i| {
+            let order = Arc::clone(&order);
+            async move { order.lock().unwrap().push(i) }
+        };
+
+        // Held up behind a slow job, only the last of the heartbeats runs
+        let (open, gate) = tokio::sync::oneshot::channel::<()>();
+        player.run(async move {
+            let _ = gate.await;
+        });
+        player.run_heartbeat(push(1));
+        player.run(push(2));
+        player.run_heartbeat(push(3));
+        let _ = open.send(());
+        player.flush().await;
+        assert_eq!(*order.lock().unwrap(), vec![2, 3]);
+
+        // Nothing from a room we left
+        let (open, gate) = tokio::sync::oneshot::channel::<()>();
+        player.run(async move {
+            let _ = gate.await;
+        });
+        player.run(push(4));
+        player.next_room();
+        player.run(push(5));
+        let _ = open.send(());
+        player.flush().await;
+        assert_eq!(*order.lock().unwrap(), vec![2, 3, 5]);
+    }
+}
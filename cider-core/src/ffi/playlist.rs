@@ -0,0 +1,102 @@
+//! Song IDs behind `Session::save_history_as_playlist`
+//!
+//! The room's recap has the host's song IDs, which are only good in the
+//! host's storefront: a listener elsewhere needs the same recordings as its
+//! own catalog has them. Each track is looked up by ISRC (or by name and
+//! artist if the host didn't send one), keeping the host's ID when our
+//! catalog has it too.
+
+use tracing::debug;
+
+use crate::cider::{CiderApi, SearchResult};
+use crate::sync::RecapTrack;
+
+/// Our catalog's IDs for `tracks`, each once in the order first played, and
+/// how many of them our catalog doesn't have
+pub async fn song_ids<C: CiderApi>(cider: &C, tracks: &[RecapTrack]) -> (Vec<String>, u32) {
+    let mut ids: Vec<String> = Vec::new();
+    let mut missing = 0;
+    let mut seen = std::collections::HashSet::new();
+    for track in tracks.iter().filter(|t| seen.insert(t.song_id.as_str())) {
+        match song_id(cider, track).await {
+            Some(id) if !ids.contains(&id) => ids.push(id),
+            Some(_) => {}
+            None => missing += 1,
+        }
+    }
+    (ids, missing)
+}
+
+/// Our catalog's ID for `track`, None if it isn't there
+async fn song_id<C: CiderApi>(cider: &C, track: &RecapTrack) -> Option<String> {
+    let candidates = match track.isrc.as_deref() {
+        Some(isrc) => cider.songs_by_isrc(isrc).await,
+        None => {
+            let term = format!("{} {}", track.name, track.artist);
+            let results = cider.search(&term, &["songs"]).await;
+            results.map(|results| results.into_iter().filter(|r| is_same_song(track, r)).collect())
+        }
+    };
+    match candidates {
+        Ok(candidates) if candidates.iter().any(|c| c.id == track.song_id) => Some(track.song_id.clone()),
+        Ok(candidates) => candidates.into_iter().next().map(|c| c.id),
+        Err(e) => {
+            // Can't tell, so try the host's: at worst the playlist leaves it out
+            debug!("Couldn't look up {} in our catalog: {}", track.song_id, e);
+            Some(track.song_id.clone())
+        }
+    }
+}
+
+fn is_same_song(track: &RecapTrack, result: &SearchResult) -> bool {
+    result.id == track.song_id
+        || (result.name.to_lowercase() == track.name.to_lowercase()
+            && result.artist.to_lowercase() == track.artist.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cider::mock::MockCider;
+
+    fn played(song_id: &str, name: &str, isrc: Option<&str>) -> RecapTrack {
+        RecapTrack {
+            song_id: song_id.to_string(),
+            name: name.to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            duration_ms: 200_000,
+            isrc: isrc.map(str::to_string),
+            started_at_ms: 0,
+            played_ms: 0,
+        }
+    }
+
+    fn catalog_track(song_id: &str, name: &str, isrc: Option<&str>) -> crate::cider::NowPlaying {
+        let mut np = MockCider::track(song_id, 200_000);
+        np.name = name.to_string();
+        np.isrc = isrc.map(str::to_string);
+        np
+    }
+
+    #[tokio::test]
+    async fn test_song_ids_in_our_catalog() {
+        // Our storefront has "1" as the host does, "2" under another ID, and not "3"
+        let cider = MockCider::new().with_catalog([
+            catalog_track("1", "One", Some("ISRC1")),
+            catalog_track("20", "Two", Some("ISRC2")),
+            catalog_track("40", "Four", None),
+        ]);
+        let tracks = vec![
+            played("1", "One", Some("ISRC1")),
+            played("2", "Two", Some("ISRC2")),
+            played("3", "Three", Some("ISRC3")),
+            played("1", "One", Some("ISRC1")),
+            played("4", "Four", None),
+        ];
+
+        let (ids, missing) = song_ids(&cider, &tracks).await;
+        assert_eq!(ids, vec!["1", "20", "40"]);
+        assert_eq!(missing, 1);
+    }
+}
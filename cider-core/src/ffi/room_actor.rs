@@ -0,0 +1,413 @@
+//! Room state owned by a single task
+//!
+//! Session methods, the network event loop and the host and listener loops
+//! all changed the room, each taking its lock from whatever thread it ran on,
+//! some while blocking on Cider. The room now belongs to one actor task:
+//! changes are sent to it as commands and applied in order, and network events
+//! are handled there too, between commands (the Cider calls they lead to run
+//! on the `Player`). The room stays behind a lock only so methods like
+//! `get_room_state` can read it; the actor is its only writer.
+
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+use crate::cider::CiderClient;
+use crate::latency::SharedLatencyTracker;
+use crate::network::{DiscoveryState, NetworkEvent, NetworkHandle, SignalingClient};
+use crate::seek_calibrator::SharedSeekCalibrator;
+use crate::sync::{Room, RoomHashCheck, SharedRecapRecorder, SyncMessage};
+
+use super::diagnostics::ErrorLog;
+use super::handlers::{check_room_hash, handle_network_event, SyncSettings};
+use super::persistence::{clear_last_session, load_last_session, save_last_session};
+use super::player::Player;
+use super::room_broadcast::RoomBroadcaster;
+use super::runtime::TaskSet;
+use super::session::HostLoop;
+use super::types::{current_time_ms, CoreError, ErrorCode, SecureStorage, SessionCallback};
+
+/// A change to the room, run on the actor task; what it returns sends its
+/// result, once the change is recorded
+type RoomChange = Box<dyn FnOnce(&mut Room) -> Reply + Send>;
+
+type Reply = Box<dyn FnOnce() + Send>;
+
+/// What the actor needs to handle the network's events
+pub struct NetworkContext {
+    pub events: mpsc::UnboundedReceiver<NetworkEvent>,
+    pub local_peer_id: String,
+    pub callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    pub cider: Arc<RwLock<CiderClient>>,
+    pub network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+    pub latency_tracker: SharedLatencyTracker,
+    pub seek_calibrator: SharedSeekCalibrator,
+    pub errors: Arc<ErrorLog>,
+    pub storage: Arc<RwLock<Option<Arc<dyn SecureStorage>>>>,
+    pub signaling: SignalingClient,
+    pub lan_only: bool,
+    /// Announce rooms we host on our presence channel, signed with this key
+    /// (our identity's), unless None
+    pub presence_identity: Option<libp2p::identity::Keypair>,
+    pub sync_settings: Arc<SyncSettings>,
+    /// Runs the Cider side of following the host
+    pub player: Player,
+    /// Sends the room state to those joining, when we host
+    pub room_broadcaster: RoomBroadcaster,
+    /// Follows Cider and broadcasts to listeners, once we're handed control
+    pub host_loop: HostLoop,
+    /// Whether our copy of the room keeps missing the host's (as a listener)
+    pub room_hash_check: RoomHashCheck,
+}
+
+enum Command {
+    Change(RoomChange),
+    /// Handle events from the network, once it's started
+    AttachNetwork(Box<NetworkContext>),
+}
+
+/// Sends commands to the task owning the room
+#[derive(Clone)]
+pub struct RoomActor {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl RoomActor {
+    /// Start the actor owning `room` (stopped with the session's other tasks);
+    /// it records the room's changes in `recap`
+    pub fn spawn(room: Arc<RwLock<Room>>, recap: SharedRecapRecorder, runtime: &Handle, tasks: &TaskSet) -> Self {
+        let (commands, command_rx) = mpsc::unbounded_channel();
+        tasks.spawn(runtime, run(room, recap, command_rx));
+        Self { commands }
+    }
+
+    /// Hand the network's events to the actor
+    pub fn attach_network(&self, network: NetworkContext) {
+        let _ = self.commands.send(Command::AttachNetwork(Box::new(network)));
+    }
+
+    /// Apply `change` to the room and wait for its result
+    ///
+    /// Blocks the calling thread, so not for use inside the runtime (see
+    /// `call_async`). Fails with `ShutDown` once the session's tasks stopped.
+    pub fn call<T: Send + 'static>(
+        &self,
+        change: impl FnOnce(&mut Room) -> Result<T, CoreError> + Send + 'static,
+    ) -> Result<T, CoreError> {
+        self.request(change)?.blocking_recv().unwrap_or(Err(CoreError::ShutDown))
+    }
+
+    /// Apply `change` to the room and wait for its result, from a task
+    pub async fn call_async<T: Send + 'static>(
+        &self,
+        change: impl FnOnce(&mut Room) -> Result<T, CoreError> + Send + 'static,
+    ) -> Result<T, CoreError> {
+        self.request(change)?.await.unwrap_or(Err(CoreError::ShutDown))
+    }
+
+    /// Apply `change` to the room without waiting
+    pub fn send(&self, change: impl FnOnce(&mut Room) + Send + 'static) {
+        let change: RoomChange = Box::new(move |room| {
+            change(room);
+            Box::new(|| {})
+        });
+        let _ = self.commands.send(Command::Change(change));
+    }
+
+    fn request<T: Send + 'static>(
+        &self,
+        change: impl FnOnce(&mut Room) -> Result<T, CoreError> + Send + 'static,
+    ) -> Result<oneshot::Receiver<Result<T, CoreError>>, CoreError> {
+        let (tx, rx) = oneshot::channel();
+        let change: RoomChange = Box::new(move |room| {
+            let result = change(room);
+            Box::new(move || {
+                let _ = tx.send(result);
+            })
+        });
+        self.commands.send(Command::Change(change)).map_err(|_| CoreError::ShutDown)?;
+        Ok(rx)
+    }
+}
+
+/// The actor: apply commands and network events to the room, one at a time
+async fn run(
+    room: Arc<RwLock<Room>>,
+    recap: SharedRecapRecorder,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+) {
+    let mut network: Option<Box<NetworkContext>> = None;
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(Command::Change(change)) => {
+                    let was_in_room = room.read().unwrap().is_busy();
+                    let reply = {
+                        let mut room = room.write().unwrap();
+                        let reply = change(&mut room);
+                        recap.lock().unwrap().observe(&room, current_time_ms());
+                        reply
+                    };
+                    left_room_check(was_in_room, &room, network.as_deref());
+                    reply();
+                }
+                Some(Command::AttachNetwork(context)) => network = Some(context),
+                None => break,
+            },
+            event = next_network_event(&mut network) => match event {
+                Some(event) => {
+                    if let Some(context) = &mut network {
+                        let was_in_room = room.read().unwrap().is_busy();
+                        context.handle(event, &room, &recap).await;
+                        left_room_check(was_in_room, &room, Some(context));
+                        recap.lock().unwrap().observe(&room.read().unwrap(), current_time_ms());
+                        context.report_sync(&recap);
+                    }
+                }
+                None => {
+                    debug!("Network events ended");
+                    network = None;
+                }
+            },
+        }
+    }
+    debug!("Room actor stopped");
+}
+
+/// Drop the player's jobs from a room we were in (or joining) and no longer are
+fn left_room_check(was_in_room: bool, room: &RwLock<Room>, network: Option<&NetworkContext>) {
+    if was_in_room && !room.read().unwrap().is_busy() {
+        if let Some(context) = network {
+            context.player.next_room();
+        }
+    }
+}
+
+/// Whether we're in a room, as its host
+fn is_host(room: &RwLock<Room>) -> bool {
+    room.read().unwrap().state().is_some_and(|state| state.is_host())
+}
+
+/// Peer ID of the room's host, once it's known
+fn host_of(room: &RwLock<Room>) -> Option<String> {
+    room.read().unwrap().state().map(|state| state.host_peer_id.clone())
+}
+
+/// Next event from the network (never resolves before it's attached)
+async fn next_network_event(network: &mut Option<Box<NetworkContext>>) -> Option<NetworkEvent> {
+    match network {
+        Some(context) => context.events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+impl NetworkContext {
+    async fn handle(&mut self, event: NetworkEvent, room: &Arc<RwLock<Room>>, recap: &SharedRecapRecorder) {
+        if let NetworkEvent::Error(e) = &event {
+            self.errors.record(format!("Network error: {}", e));
+        }
+
+        // Listeners' sync stats only go into the recap
+        if let NetworkEvent::Message { from, message: SyncMessage::SyncReport(stats) } = &event {
+            recap.lock().unwrap().reported(from, *stats);
+            return;
+        }
+
+        // Handle ListeningAddresses for signaling (internet discovery)
+        if let NetworkEvent::ListeningAddresses { addresses } = &event {
+            // Get room code if we're in a room (or joining one)
+            let (room_code, hosting) = match &*room.read().unwrap() {
+                Room::Active(state) => (Some((state.room_code.clone(), false)), state.is_host()),
+                Room::Joining { room_code, .. } => (Some((room_code.clone(), true)), false),
+                _ => (None, false),
+            };
+
+            if let Some((code, joining)) = room_code.filter(|_| !self.lan_only) {
+                let addresses = addresses.clone();
+                let signaling = self.signaling.clone();
+                let peer_id = self.local_peer_id.clone();
+
+                info!("Publishing {} addresses to signaling for room {}", addresses.len(), code);
+                for addr in &addresses {
+                    info!("  -> {}", addr);
+                }
+
+                // Publish to signaling in a separate task
+                let presence_identity = self.presence_identity.clone().filter(|_| hosting);
+                tokio::spawn(async move {
+                    if let Err(e) = signaling.publish_room(&code, &peer_id, addresses, joining).await {
+                        warn!("Failed to publish to signaling: {}", e);
+                    } else {
+                        info!("Successfully published to signaling");
+                    }
+                    if let Some(identity) = presence_identity {
+                        if let Err(e) = signaling.publish_presence(&identity, &code).await {
+                            warn!("Failed to announce presence: {}", e);
+                        }
+                    }
+                });
+            }
+            return;
+        }
+
+        // A join that discovery couldn't find a way into fails now, with its reason
+        if let NetworkEvent::DiscoveryProgress {
+            state: DiscoveryState::Failed { reason },
+            ..
+        } = &event
+        {
+            self.fail_join(room, reason);
+            return;
+        }
+
+        if let NetworkEvent::Message { from, message } = &event {
+            if host_of(room).as_deref() == Some(from.as_str()) {
+                match message {
+                    SyncMessage::Heartbeat { room_hash: Some(room_hash), .. } => check_room_hash(
+                        *room_hash,
+                        room,
+                        &mut self.room_hash_check,
+                        &self.network_handle,
+                        Instant::now(),
+                    ),
+                    SyncMessage::RoomState { .. } => self.room_hash_check.room_state_received(),
+                    _ => {}
+                }
+            }
+        }
+
+        let code_change = matches!(&event, NetworkEvent::Message { message: SyncMessage::RoomCodeChanged { .. }, .. });
+        let (was_host, host_before) = (is_host(room), host_of(room));
+        let was_in_room = !matches!(&*room.read().unwrap(), Room::None);
+        handle_network_event(
+            event,
+            room,
+            &self.callback,
+            &self.cider,
+            &self.network_handle,
+            &self.latency_tracker,
+            &self.seek_calibrator,
+            &self.local_peer_id,
+            &self.sync_settings,
+            &self.player,
+            &self.room_broadcaster,
+        )
+        .await;
+
+        if code_change {
+            self.remember_room_code(room);
+        }
+        // The room ended or we were removed from it: nothing to rejoin on the next launch
+        if was_in_room && matches!(&*room.read().unwrap(), Room::None) {
+            if let Some(storage) = self.storage.read().unwrap().as_deref() {
+                clear_last_session(storage);
+            }
+        }
+        let host = host_of(room);
+        if host != host_before {
+            if let (Some(host), Some(handle)) = (host, self.network_handle.read().unwrap().as_ref()) {
+                let _ = handle.set_room_host(&host);
+            }
+        }
+        if !was_host && is_host(room) {
+            let track_id = room.read().unwrap().state().and_then(|s| s.current_track.as_ref()).map(|t| t.song_id.clone());
+            info!("Handed control of the room, broadcasting as host");
+            self.host_loop.take_over(track_id);
+        }
+    }
+
+    /// Add our sync measurements to the recap, and send them to the room when due
+    fn report_sync(&self, recap: &SharedRecapRecorder) {
+        let report = {
+            let mut recap = recap.lock().unwrap();
+            recap.add_local_sync(self.sync_settings.take_stats());
+            recap.report_due(current_time_ms())
+        };
+        if let Some(stats) = report {
+            if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
+                let _ = handle.broadcast(SyncMessage::SyncReport(stats));
+            }
+        }
+    }
+
+    /// Save the room's new code, so restoring the session goes to the right room
+    fn remember_room_code(&self, room: &Arc<RwLock<Room>>) {
+        let Some(storage) = self.storage.read().unwrap().clone() else {
+            return;
+        };
+        let Some(room_code) = room.read().unwrap().state().map(|state| state.room_code.clone()) else {
+            return;
+        };
+        if let Some(mut saved) = load_last_session(storage.as_ref()) {
+            saved.room_code = room_code;
+            save_last_session(storage.as_ref(), &saved);
+        }
+    }
+
+    /// Stop joining the room (if we still are) and tell the user why
+    fn fail_join(&self, room: &Arc<RwLock<Room>>, reason: &str) {
+        let room_code = {
+            let mut room = room.write().unwrap();
+            let Room::Joining { room_code, .. } = &*room else {
+                return;
+            };
+            let room_code = room_code.clone();
+            *room = Room::None;
+            room_code
+        };
+
+        warn!("Giving up joining room {}: {}", room_code, reason);
+        if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
+            let _ = handle.leave_room();
+        }
+        if let Some(storage) = self.storage.read().unwrap().as_deref() {
+            clear_last_session(storage);
+        }
+
+        let message = format!("Room {} not found: {}", room_code, reason);
+        self.errors.record(message.clone());
+        if let Some(cb) = self.callback.read().unwrap().as_ref() {
+            let code = ErrorCode::RoomNotFound;
+            cb.on_error(code, code.message_key().to_string(), message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::sync::new_shared_recorder;
+
+    #[tokio::test]
+    async fn test_changes_applied_in_order() {
+        let room = Arc::new(RwLock::new(Room::None));
+        let tasks = TaskSet::new();
+        let recap = new_shared_recorder();
+        let actor = RoomActor::spawn(Arc::clone(&room), Arc::clone(&recap), &Handle::current(), &tasks);
+
+        actor.send(|room| *room = Room::Creating { display_name: "first".to_string() });
+        actor
+            .call_async(|room| {
+                let Room::Creating { display_name } = room else {
+                    return Err(CoreError::NotInRoom);
+                };
+                display_name.push_str(", second");
+                Ok(())
+            })
+            .await
+            .unwrap();
+        let busy = actor.call_async(|room| Ok(room.is_busy())).await.unwrap();
+        assert!(busy);
+        assert!(matches!(&*room.read().unwrap(), Room::Creating { display_name } if display_name == "first, second"));
+
+        // Stopped with the session's tasks, dropping what was still queued
+        tasks.abort_all();
+        let stopped = tokio::time::timeout(Duration::from_secs(1), actor.call_async(|_| Ok(()))).await;
+        assert!(matches!(stopped, Ok(Err(CoreError::ShutDown))));
+    }
+}
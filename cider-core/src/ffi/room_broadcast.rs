@@ -0,0 +1,160 @@
+//! The host's room state broadcasts, coalesced
+//!
+//! The host sends the whole room state whenever someone subscribes or asks to
+//! join, so that they can join. Five people arriving together meant ten of
+//! these, each larger than the last and all but one out of date on arrival.
+//! Asking for a broadcast now sends the room state right away if none went
+//! out in the last `ROOM_STATE_INTERVAL`, and otherwise once that's up, with
+//! the room as it is then.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+
+use crate::network::NetworkHandle;
+use crate::sync::Room;
+
+use super::handlers::room_state_message;
+use super::runtime::TaskSet;
+
+/// Least time between two room state broadcasts
+pub const ROOM_STATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Asks the task sending them for a room state broadcast
+#[derive(Clone)]
+pub struct RoomBroadcaster {
+    requests: mpsc::UnboundedSender<()>,
+}
+
+impl RoomBroadcaster {
+    /// Start the broadcaster on `runtime` (stopped with the session's other
+    /// tasks), sending playback `host_delay_ms` ahead of ours (see
+    /// `SessionConfig::host_sync_delay_ms`)
+    pub fn spawn(
+        room: Arc<RwLock<Room>>,
+        network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+        host_delay_ms: u64,
+        runtime: &Handle,
+        tasks: &TaskSet,
+    ) -> Self {
+        let (requests, request_rx) = mpsc::unbounded_channel();
+        tasks.spawn(runtime, run(request_rx, room, network_handle, host_delay_ms));
+        Self { requests }
+    }
+
+    /// Start the broadcaster on the current runtime, until it's dropped
+    #[cfg(any(test, feature = "bench-internals"))]
+    pub fn new(room: Arc<RwLock<Room>>, network_handle: Arc<RwLock<Option<NetworkHandle>>>) -> Self {
+        let (requests, request_rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(request_rx, room, network_handle, 0));
+        Self { requests }
+    }
+
+    /// Broadcast the room state, now or once `ROOM_STATE_INTERVAL` is up
+    pub fn request(&self) {
+        let _ = self.requests.send(());
+    }
+}
+
+async fn run(
+    mut requests: mpsc::UnboundedReceiver<()>,
+    room: Arc<RwLock<Room>>,
+    network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+    host_delay_ms: u64,
+) {
+    while requests.recv().await.is_some() {
+        loop {
+            broadcast(&room, &network_handle, host_delay_ms);
+            tokio::time::sleep(ROOM_STATE_INTERVAL).await;
+
+            // Everything asked for meanwhile goes out as one broadcast
+            let mut requested = false;
+            while requests.try_recv().is_ok() {
+                requested = true;
+            }
+            if !requested {
+                break;
+            }
+        }
+    }
+}
+
+/// Send the room state as it is now, if we're still its host
+fn broadcast(room: &RwLock<Room>, network_handle: &RwLock<Option<NetworkHandle>>, host_delay_ms: u64) {
+    let message = match room.read().unwrap().state() {
+        Some(state) if state.is_host() => room_state_message(state, host_delay_ms),
+        _ => return,
+    };
+    if let Some(handle) = network_handle.read().unwrap().as_ref() {
+        let _ = handle.broadcast(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::NetworkCommand;
+    use crate::sync::{Capabilities, ClientInfo, Participant, ParticipantStatus, Profile, RoomState, SyncMessage};
+
+    /// Participant counts in the room states broadcast so far
+    fn broadcasts(commands: &mut mpsc::UnboundedReceiver<NetworkCommand>) -> Vec<usize> {
+        let mut sent = Vec::new();
+        while let Ok(command) = commands.try_recv() {
+            if let NetworkCommand::Broadcast { message } = command {
+                if let SyncMessage::RoomState { participants, .. } = *message {
+                    sent.push(participants.len());
+                }
+            }
+        }
+        sent
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_broadcasts_are_coalesced() {
+        let state = RoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "host".to_string(),
+            "Host".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        let room = Arc::new(RwLock::new(Room::Active(state)));
+        let (handle, mut commands) = NetworkHandle::detached("host");
+        let broadcaster = RoomBroadcaster::new(Arc::clone(&room), Arc::new(RwLock::new(Some(handle))));
+        let join = |i: usize| {
+            if let Some(state) = room.write().unwrap().state_mut() {
+                state.add_participant(Participant {
+                    peer_id: format!("listener-{}", i),
+                    display_name: format!("Listener {}", i),
+                    is_host: false,
+                    storefront: None,
+                    capabilities: Capabilities::default(),
+                    profile: Profile::default(),
+                    client: ClientInfo::default(),
+                    status: ParticipantStatus::default(),
+                });
+            }
+            broadcaster.request();
+        };
+
+        // The first goes out right away, the next four together with the last of them
+        for i in 1..=5 {
+            join(i);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(broadcasts(&mut commands), vec![2]);
+        tokio::time::sleep(ROOM_STATE_INTERVAL).await;
+        assert_eq!(broadcasts(&mut commands), vec![6]);
+
+        // Nothing more unless asked, and right away once it's been a while
+        tokio::time::sleep(ROOM_STATE_INTERVAL * 2).await;
+        assert!(broadcasts(&mut commands).is_empty());
+        join(6);
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(broadcasts(&mut commands), vec![7]);
+    }
+}
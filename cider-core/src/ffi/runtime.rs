@@ -0,0 +1,98 @@
+//! Process-wide tokio runtime shared by all sessions
+//!
+//! Apps sometimes drop and recreate their Session (e.g. after a settings
+//! change). Each session used to start its own multi-thread runtime; sharing
+//! one keeps that cheap. A session's own tasks are tracked so shutting it down
+//! stops them without touching other sessions.
+
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+
+use tokio::runtime::{Builder, Handle, Runtime};
+use tokio::task::AbortHandle;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Handle of the shared runtime, started on first use
+pub fn handle() -> Handle {
+    RUNTIME
+        .get_or_init(|| {
+            Builder::new_multi_thread()
+                .enable_all()
+                .thread_name("cider-core")
+                .build()
+                .expect("Failed to create tokio runtime")
+        })
+        .handle()
+        .clone()
+}
+
+/// Tasks a session spawned on the shared runtime
+#[derive(Debug)]
+pub struct TaskSet {
+    /// None once aborted: later tasks aren't started
+    tasks: Mutex<Option<Vec<AbortHandle>>>,
+}
+
+impl TaskSet {
+    pub fn new() -> Self {
+        Self {
+            tasks: Mutex::new(Some(Vec::new())),
+        }
+    }
+
+    /// Spawn a task on `runtime`, to be aborted with the others (dropped if already aborted)
+    pub fn spawn<F>(&self, runtime: &Handle, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut tasks = self.tasks.lock().unwrap();
+        let Some(tasks) = tasks.as_mut() else {
+            return;
+        };
+        tasks.retain(|t| !t.is_finished());
+        tasks.push(runtime.spawn(task).abort_handle());
+    }
+
+    /// Abort all tasks, and any spawned from now on
+    pub fn abort_all(&self) {
+        for task in self.tasks.lock().unwrap().take().unwrap_or_default() {
+            task.abort();
+        }
+    }
+}
+
+impl Default for TaskSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, RecvTimeoutError};
+    use std::time::Duration;
+
+    #[test]
+    fn test_abort_all() {
+        let runtime = handle();
+        let tasks = TaskSet::new();
+        let (tx, rx) = channel::<()>();
+        tasks.spawn(&runtime, async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            let _ = tx.send(());
+        });
+
+        // The aborted task drops its sender without sending
+        tasks.abort_all();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Err(RecvTimeoutError::Disconnected));
+
+        // Too late to run anything
+        let (tx, rx) = channel();
+        tasks.spawn(&runtime, async move {
+            let _ = tx.send(());
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Err(RecvTimeoutError::Disconnected));
+    }
+}
@@ -1,28 +1,127 @@
 //! Session implementation for FFI
 
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Once, RwLock};
-use std::time::Duration;
-use tokio::runtime::Runtime;
+use std::time::{Duration, Instant};
+use libp2p::identity::Keypair;
+use tokio::runtime::Handle;
 use tracing::{debug, info, warn};
 
-use crate::cider::{CiderClient, CiderError as CiderApiError};
+use crate::artwork::ArtworkCache;
+use crate::cider::{
+    CiderApi, CiderClient, CiderError as CiderApiError, CiderEventStream, NowPlaying, PlaybackEvent, CANDIDATE_PORTS,
+    DEFAULT_HOST as DEFAULT_CIDER_HOST,
+};
 use crate::latency::{self, SharedLatencyTracker};
 use crate::network::{NetworkConfig, NetworkHandle, NetworkManager, RoomCode};
-use crate::seek_calibrator::{self, SharedSeekCalibrator};
-use crate::sync::{PlaybackInfo, Room, RoomState as InternalRoomState, SyncMessage};
-
-use super::handlers::handle_network_event;
+use crate::seek_calibrator::{self, SeekCalibrator, SeekKind, SharedSeekCalibrator};
+use crate::sync::{
+    display_name_within_limits, new_shared_recorder, Capabilities, ClientInfo, ParticipantStatus, PlaybackInfo,
+    Profile, RemovalReason, Room, RoomHashCheck, RoomState as InternalRoomState, SharedRecapRecorder, SyncMessage,
+};
+
+use super::deep_link;
+use super::diagnostics::ErrorLog;
+use super::events::{EventQueue, EVENT_QUEUE_CAPACITY};
+use super::handlers::{
+    ahead_of_host, estimated_position_ms, playback_as_sent, room_state_message, sync_to_host, warm_up_calibration,
+    SyncSettings,
+};
+use super::health;
+use super::logging;
+use super::persistence::{
+    clear_last_session, load_blocklist, load_calibration, load_friends, load_last_session,
+    load_or_create_keypair, save_blocklist, save_calibration, save_friends, save_last_session, SavedCalibration,
+};
+use super::player::Player;
+use super::room_broadcast::RoomBroadcaster;
+use super::playlist;
+use super::room_actor::{NetworkContext, RoomActor};
+use super::runtime::{self, TaskSet};
 use super::types::*;
 
 static TRACING_INIT: Once = Once::new();
 
+/// How long to wait before retrying Cider's event stream
+const EVENT_STREAM_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often peers are pinged to measure latency
+pub(super) const PING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Slowest the host sends heartbeats while in the background (listeners time out after 15s)
+const BACKGROUND_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest the host goes without a heartbeat while its playback is unchanged
+/// (listeners time out after 15s, and re-sync on each heartbeat)
+const HOST_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How far the host's position may stray from where its last heartbeat put it
+/// before listeners are sent the new one, i.e. what counts as a seek
+const HOST_POSITION_JUMP_MS: u64 = 1000;
+
+/// How long a new listener waits for the host to play before skipping the calibration warm-up
+const WARM_UP_WAIT: Duration = Duration::from_secs(60);
+
+/// Time after joining before the warm-up, for the first seek and ping to settle
+const WARM_UP_DELAY: Duration = Duration::from_secs(3);
+
+/// How long after a warm-up seek its result is measured
+const WARM_UP_SETTLE: Duration = Duration::from_millis(1500);
+
+/// Longest creating or joining a room waits on Cider for our account's storefront and restrictions
+const ACCOUNT_LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long `shutdown` waits for the network to stop
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long a join may take before it's given up on (discovery included)
+const JOIN_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// How often an accepted invite checks whether we've joined its room yet
+const INVITE_JOIN_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often signaling watches check we're still joining (or hosting) the room
+const SIGNALING_ROOM_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for signaling to tell whether a room code is in use
+const ROOM_IN_USE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long to wait for signaling to tell whether a friend is hosting
+const PRESENCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often `watch_friends` checks whether friends started a room
+const FRIENDS_WATCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time without input on the device before we show as idle
+const IDLE_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// Random room codes checked before settling for one (collisions are rare)
+const MAX_ROOM_CODE_ATTEMPTS: usize = 5;
+
 /// Main session interface
 #[derive(uniffi::Object)]
 pub struct Session {
-    runtime: Runtime,
+    /// Handle of the runtime shared by all sessions, for spawning and blocking on
+    runtime: Handle,
+    /// Tasks this session spawned, stopped by `shutdown`
+    tasks: Arc<TaskSet>,
+    /// Whether `shutdown` was called
+    shut_down: AtomicBool,
     cider: Arc<RwLock<CiderClient>>,
+    /// Cider API port set by the user (None = discover it)
+    cider_port_override: Arc<RwLock<Option<u16>>>,
+    /// The room, for reading (see `room_actor`)
     room: Arc<RwLock<Room>>,
+    /// Task owning the room: all changes to it go through here
+    room_actor: RoomActor,
     callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    /// Events for `next_event`, when used instead of a callback
+    events: Arc<EventQueue>,
+    /// Recent errors, for diagnostics
+    errors: Arc<ErrorLog>,
+    /// App storage for our identity, calibration and last room (see `set_storage`)
+    storage: Arc<RwLock<Option<Arc<dyn SecureStorage>>>>,
     network_handle: Arc<RwLock<Option<NetworkHandle>>>,
     local_peer_id: Arc<RwLock<Option<String>>>,
     /// Handle for cancelling the host broadcast loop
@@ -39,19 +138,73 @@ pub struct Session {
     signaling: Arc<RwLock<crate::network::SignalingClient>>,
     /// Custom bootstrap/relay nodes (if empty, uses defaults)
     bootstrap_nodes: Arc<RwLock<Vec<String>>>,
+    /// Preferred relay nodes for AutoRelay reservations
+    relay_nodes: Arc<RwLock<Vec<String>>>,
+    /// Access token for private relays
+    relay_access_token: Arc<RwLock<Option<String>>>,
+    /// Region to prefer relays from
+    preferred_relay_region: Arc<RwLock<Option<String>>>,
+    /// Downloaded track artwork
+    artwork: ArtworkCache,
+    /// How often the host sends heartbeats (and polls Cider without its event stream)
+    heartbeat_interval: Duration,
+    /// How far ahead of its own playback the host broadcasts positions
+    host_sync_delay_ms: u64,
+    /// How a listener follows the host and reports sync status
+    sync_settings: Arc<SyncSettings>,
+    /// Tracks, participants and sync quality of the room we're in (or were last in)
+    recap: SharedRecapRecorder,
+    /// Display name for rooms created or joined with an empty one
+    default_display_name: String,
+    /// Our avatar and color
+    profile: RwLock<Profile>,
+    /// The app's version, shown to others in the room
+    app_version: Option<String>,
+    /// We said we're away (see `set_away`)
+    away: AtomicBool,
+    /// No input on the device for `IDLE_AFTER` (see `report_idle_time`)
+    idle: AtomicBool,
+    /// Only connect to peers on the local network
+    lan_only: bool,
+    /// As host, announce the room on our presence channel
+    share_presence: bool,
+    /// Saved friends, with the rooms they were last seen hosting
+    friends: Arc<RwLock<Vec<Friend>>>,
+    /// Handle for stopping `watch_friends`
+    friends_watch_cancel: RwLock<Option<tokio::sync::oneshot::Sender<()>>>,
+    /// Whether the app is in the background (see `on_app_background`)
+    app_background: tokio::sync::watch::Sender<bool>,
+    /// The local bridge server, while it runs (see `start_bridge`)
+    #[cfg(feature = "bridge")]
+    bridge: RwLock<Option<super::bridge::RunningBridge>>,
 }
 
 #[uniffi::export]
 impl Session {
-    /// Create a new session
+    /// Create a new session with default settings
     #[uniffi::constructor]
     pub fn new() -> Self {
+        Self::new_with_config(SessionConfig::default())
+    }
+
+    /// Create a new session with the given settings
+    #[uniffi::constructor]
+    pub fn new_with_config(config: SessionConfig) -> Self {
         // Initialize tracing once
         TRACING_INIT.call_once(|| {
-            tracing_subscriber::fmt()
-                .with_ansi(false)  // Disable colors for Xcode console
-                .with_target(false)  // Cleaner output
-                .with_env_filter(
+            use tracing_subscriber::layer::SubscriberExt;
+            use tracing_subscriber::util::SubscriberInitExt;
+
+            tracing_subscriber::registry()
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .with_ansi(false)  // Disable colors for Xcode console
+                        .with_target(false)  // Cleaner output
+                        .with_writer(std::io::stderr),
+                )
+                // Also to the app's log callback, if one is set
+                .with(logging::layer())
+                .with(
                     tracing_subscriber::EnvFilter::from_default_env()
                         .add_directive("cider_core=debug".parse().unwrap())
                         .add_directive("libp2p_mdns=info".parse().unwrap())
@@ -60,19 +213,28 @@ impl Session {
                         .add_directive("reqwest=off".parse().unwrap())
                         .add_directive("hyper=off".parse().unwrap()),
                 )
-                .with_writer(std::io::stderr)
                 .init();
         });
 
         info!("Initializing cider-core session");
 
-        let runtime = Runtime::new().expect("Failed to create tokio runtime");
-
-        Self {
+        let runtime = runtime::handle();
+        let tasks = Arc::new(TaskSet::new());
+        let room = Arc::new(RwLock::new(Room::None));
+        let recap = new_shared_recorder();
+        let room_actor = RoomActor::spawn(Arc::clone(&room), Arc::clone(&recap), &runtime, &tasks);
+        let session = Self {
             runtime,
+            tasks,
+            shut_down: AtomicBool::new(false),
             cider: Arc::new(RwLock::new(CiderClient::new())),
-            room: Arc::new(RwLock::new(Room::None)),
+            cider_port_override: Arc::new(RwLock::new(None)),
+            room,
+            room_actor,
             callback: Arc::new(RwLock::new(None)),
+            events: Arc::new(EventQueue::new(EVENT_QUEUE_CAPACITY)),
+            errors: Arc::new(ErrorLog::new()),
+            storage: Arc::new(RwLock::new(None)),
             network_handle: Arc::new(RwLock::new(None)),
             local_peer_id: Arc::new(RwLock::new(None)),
             host_broadcast_cancel: Arc::new(RwLock::new(None)),
@@ -82,23 +244,332 @@ impl Session {
             seek_calibrator: seek_calibrator::new_shared_calibrator(),
             signaling: Arc::new(RwLock::new(crate::network::SignalingClient::new())),
             bootstrap_nodes: Arc::new(RwLock::new(Vec::new())),
+            relay_nodes: Arc::new(RwLock::new(Vec::new())),
+            relay_access_token: Arc::new(RwLock::new(None)),
+            preferred_relay_region: Arc::new(RwLock::new(None)),
+            artwork: ArtworkCache::new(),
+            heartbeat_interval: Duration::from_millis(config.heartbeat_interval_ms.max(1)),
+            host_sync_delay_ms: config.host_sync_delay_ms,
+            sync_settings: Arc::new(
+                SyncSettings::new(config.drift_threshold_ms.max(MIN_DRIFT_THRESHOLD_MS), config.sync_status_level)
+                    .with_catch_up_threshold(config.catch_up_threshold_ms)
+                    .with_calibration_warm_up(config.calibration_warm_up),
+            ),
+            recap,
+            default_display_name: config.default_display_name,
+            profile: RwLock::new(Profile::default()),
+            app_version: config.app_version,
+            away: AtomicBool::new(false),
+            idle: AtomicBool::new(false),
+            lan_only: config.lan_only,
+            share_presence: config.share_presence,
+            friends: Arc::new(RwLock::new(Vec::new())),
+            friends_watch_cancel: RwLock::new(None),
+            app_background: tokio::sync::watch::Sender::new(false),
+            #[cfg(feature = "bridge")]
+            bridge: RwLock::new(None),
+        };
+        session.set_cider_port(config.cider_port);
+        session.set_cider_token(config.cider_token);
+        session.set_relay_nodes(config.relay_nodes);
+        if let Some(server) = config.signaling_server {
+            session.set_signaling_server(server);
         }
+        session
     }
 
     /// Set the Cider API token
     pub fn set_cider_token(&self, token: Option<String>) {
         let mut cider = self.cider.write().unwrap();
+        // Keep the host and the port we found Cider on
+        let client = CiderClient::with_host(cider.host(), cider.port());
         // Trim whitespace from token (common copy/paste issue)
         *cider = match token.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()) {
-            Some(t) => CiderClient::new().with_token(t),
-            None => CiderClient::new(),
+            Some(t) => client.with_token(t),
+            None => client,
         };
     }
 
+    /// Set the machine running Cider (None = this one)
+    /// For companion apps on another device, e.g. "192.168.1.20", "desktop.local:10767"
+    /// or "http://desktop.local:10767"; a port given here is set as with `set_cider_port`
+    /// Cider's API must be reachable from the network (not just localhost) for this to work
+    pub fn set_cider_host(&self, host: Option<String>) {
+        let (host, port) = host.as_deref().and_then(parse_cider_address).unzip();
+        info!("Setting Cider host: {}", host.as_deref().unwrap_or(DEFAULT_CIDER_HOST));
+        {
+            let mut cider = self.cider.write().unwrap();
+            *cider = cider.at_host(host.as_deref().unwrap_or(DEFAULT_CIDER_HOST));
+        }
+        if let Some(port) = port.flatten() {
+            self.set_cider_port(Some(port));
+        }
+    }
+
+    /// Set the Cider API port (None = find it automatically)
+    /// Only needed if Cider's API runs on a port discovery doesn't try
+    pub fn set_cider_port(&self, port: Option<u16>) {
+        *self.cider_port_override.write().unwrap() = port;
+        let mut cider = self.cider.write().unwrap();
+        *cider = cider.at_port(port.unwrap_or(CANDIDATE_PORTS[0]));
+    }
+
     /// Set the event callback
     pub fn set_callback(&self, callback: Box<dyn SessionCallback>) {
-        let mut cb = self.callback.write().unwrap();
-        *cb = Some(Arc::from(callback));
+        self.install_callback(Arc::from(callback));
+    }
+
+    /// Set the secure storage used to keep state across launches
+    ///
+    /// Holds our identity keypair, the seek calibration, the current room and
+    /// its blocklist, and our friends. Set it before creating or joining a
+    /// room so the network starts with the saved identity.
+    pub fn set_storage(&self, storage: Box<dyn SecureStorage>) {
+        let storage: Arc<dyn SecureStorage> = Arc::from(storage);
+        restore_calibration(storage.as_ref(), &mut self.seek_calibrator.write().unwrap());
+        *self.friends.write().unwrap() = load_friends(storage.as_ref());
+        *self.storage.write().unwrap() = Some(storage);
+    }
+
+    /// Get back into the room we were in when the app was closed without leaving it
+    ///
+    /// Listeners rejoin the room; hosts re-create it under the same code so
+    /// their listeners can find it again. Returns the room, or None if there's
+    /// nothing to restore (no storage set, or the room was left, ended, or we
+    /// were removed from it).
+    pub fn restore_last_session(&self) -> Result<Option<SavedSession>, CoreError> {
+        let storage = self.storage.read().unwrap().clone();
+        let Some(saved) = storage.as_deref().and_then(load_last_session) else {
+            return Ok(None);
+        };
+
+        info!("Restoring last session in room {} (host: {})", saved.room_code, saved.was_host);
+        if saved.was_host {
+            let Some(code) = RoomCode::parse(&saved.room_code) else {
+                if let Some(storage) = storage.as_deref() {
+                    clear_last_session(storage);
+                }
+                return Err(CoreError::network(ErrorCode::InvalidRoomCode, "Invalid room code"));
+            };
+            self.create_room_with_code(saved.display_name.clone(), code)?;
+        } else {
+            self.join_room(saved.room_code.clone(), saved.display_name.clone())?;
+        }
+        Ok(Some(saved))
+    }
+
+    /// Save someone from the room we're in, or else the last one, as a friend
+    ///
+    /// Friends are kept in the app's storage (see `set_storage`), and
+    /// `refresh_friends` tells which of them are hosting a room. Adding a
+    /// friend again updates their display name. Fails with `PeerNotFound` if
+    /// they weren't in the room.
+    pub fn add_friend(&self, peer_id: String) -> Result<Friend, CoreError> {
+        let recap = self.recap.lock().unwrap().recap();
+        let display_name = recap
+            .iter()
+            .flat_map(|r| r.participants.iter().filter(move |p| p.peer_id != r.local_peer_id))
+            .find(|p| p.peer_id == peer_id)
+            .map(|p| p.display_name.clone())
+            .ok_or_else(|| CoreError::network(ErrorCode::PeerNotFound, format!("{} wasn't in the room", peer_id)))?;
+        Ok(remember_friend(&self.friends, &self.storage, peer_id, display_name))
+    }
+
+    /// Forget a friend
+    pub fn remove_friend(&self, peer_id: String) {
+        let mut friends = self.friends.write().unwrap();
+        friends.retain(|f| f.peer_id != peer_id);
+        self.persist_friends(&friends);
+    }
+
+    /// Our friends, in the order they were added, with the rooms they were
+    /// hosting as of the last `refresh_friends`
+    pub fn get_friends(&self) -> Vec<Friend> {
+        self.friends.read().unwrap().clone()
+    }
+
+    /// Check which friends are hosting a room, from the presence they announce
+    /// on signaling (hosts do if `SessionConfig::share_presence` is on)
+    ///
+    /// Returns the friends, as `get_friends` does after. Without signaling
+    /// (`lan_only`), nobody's presence can be seen.
+    pub async fn refresh_friends(self: Arc<Self>) -> Result<Vec<Friend>, CoreError> {
+        self.ensure_running()?;
+        if !self.lan_only {
+            // On our runtime, whatever executor the app awaits this on
+            let signaling = self.signaling.read().unwrap().clone();
+            let friends = Arc::clone(&self.friends);
+            let checked = self.runtime.spawn(async move {
+                check_presence(&signaling, &friends).await;
+            });
+            let _ = checked.await;
+        }
+        Ok(self.get_friends())
+    }
+
+    /// Watch for friends starting a room, calling `on_friend_started_room`
+    /// for each (unless it's the room we're in)
+    ///
+    /// Checks their presence every minute, as `refresh_friends` does, until
+    /// turned off; friends already hosting when the watch starts are reported
+    /// too, unless `refresh_friends` saw them first. Does nothing without
+    /// signaling (`lan_only`).
+    pub fn watch_friends(&self, enabled: bool) -> Result<(), CoreError> {
+        self.ensure_running()?;
+        if let Some(cancel) = self.friends_watch_cancel.write().unwrap().take() {
+            let _ = cancel.send(());
+        }
+        if !enabled || self.lan_only {
+            return Ok(());
+        }
+
+        let (cancel_tx, mut cancel_rx) = tokio::sync::oneshot::channel();
+        *self.friends_watch_cancel.write().unwrap() = Some(cancel_tx);
+        let signaling = Arc::clone(&self.signaling);
+        let friends = Arc::clone(&self.friends);
+        let room = Arc::clone(&self.room);
+        let callback = Arc::clone(&self.callback);
+
+        info!("Watching for friends starting a room");
+        self.spawn(async move {
+            loop {
+                let client = signaling.read().unwrap().clone();
+                for friend in check_presence(&client, &friends).await {
+                    let Some(room_code) = friend.hosting_room.clone() else {
+                        continue;
+                    };
+                    if room.read().unwrap().state().is_some_and(|s| s.room_code == room_code) {
+                        continue;
+                    }
+                    info!("{} started room {}", friend.display_name, room_code);
+                    if let Some(cb) = callback.read().unwrap().as_ref() {
+                        cb.on_friend_started_room(friend, room_code);
+                    }
+                }
+
+                tokio::select! {
+                    _ = &mut cancel_rx => break,
+                    _ = tokio::time::sleep(FRIENDS_WATCH_INTERVAL) => {}
+                }
+            }
+            debug!("Stopped watching friends");
+        });
+        Ok(())
+    }
+
+    /// Join the room a friend is hosting
+    ///
+    /// Checks their presence first, so the room is the one they're hosting
+    /// now. Fails with `RoomNotFound` if they aren't hosting one, and
+    /// `PeerNotFound` if they aren't a friend.
+    pub async fn join_friend(self: Arc<Self>, peer_id: String, display_name: String) -> Result<(), CoreError> {
+        let friend = Arc::clone(&self)
+            .refresh_friends()
+            .await?
+            .into_iter()
+            .find(|f| f.peer_id == peer_id)
+            .ok_or_else(|| CoreError::network(ErrorCode::PeerNotFound, format!("{} isn't a friend", peer_id)))?;
+        let Some(room_code) = friend.hosting_room else {
+            return Err(CoreError::network(
+                ErrorCode::RoomNotFound,
+                format!("{} isn't hosting a room", friend.display_name),
+            ));
+        };
+        info!("Joining {}'s room {}", friend.display_name, room_code);
+        self.off_thread(move |s| s.join_room(room_code, display_name)).await
+    }
+
+    /// Act on a `ciderlt://` link the app was opened with (see `ffi/deep_link.rs`)
+    ///
+    /// Join and invite links join the room under the default display name,
+    /// unless we're in it already; accepting an invite also saves whoever sent
+    /// it as a friend, once we're in. Suggest links add the song to the end of
+    /// the room's queue, which only the host can do for now. Returns the link
+    /// handled.
+    pub fn handle_uri(&self, uri: String) -> Result<DeepLink, CoreError> {
+        let link = deep_link::parse(&uri)?;
+        info!("Handling link {:?}", link);
+        let join = |room_code: &String| {
+            let in_room = self.room.read().unwrap().state().is_some_and(|s| s.room_code == *room_code);
+            if in_room {
+                return Ok(());
+            }
+            self.join_room(room_code.clone(), String::new())
+        };
+        match &link {
+            DeepLink::JoinRoom { room_code } => join(room_code)?,
+            DeepLink::Invite { room_code, from_peer_id } => {
+                join(room_code)?;
+                if let Some(peer_id) = from_peer_id {
+                    self.befriend_once_joined(room_code.clone(), peer_id.clone());
+                }
+            }
+            DeepLink::Suggest { song_id } => {
+                self.ensure_running()?;
+                self.hosted_room()?;
+                let cider = self.cider.read().unwrap().clone();
+                self.runtime.block_on(cider.play_later("songs", song_id))?;
+            }
+        }
+        Ok(link)
+    }
+
+    /// Save `peer_id` as a friend once we're in `room_code` and can see their
+    /// name (a join goes on after `join_room` returns); gives up if we end up
+    /// elsewhere, they aren't in the room, or the join times out
+    fn befriend_once_joined(&self, room_code: String, peer_id: String) {
+        let room = Arc::clone(&self.room);
+        let friends = Arc::clone(&self.friends);
+        let storage = Arc::clone(&self.storage);
+        self.spawn(async move {
+            let deadline = tokio::time::Instant::now() + JOIN_TIMEOUT;
+            let display_name = loop {
+                let joined = match &*room.read().unwrap() {
+                    Room::Joining { room_code: code, .. } if *code == room_code => None,
+                    Room::Active(state) if state.room_code == room_code => Some(
+                        state.participants.get(&peer_id).filter(|p| p.peer_id != state.local_peer_id).map(|p| p.display_name.clone()),
+                    ),
+                    _ => Some(None),
+                };
+                match joined {
+                    Some(display_name) => break display_name,
+                    None if tokio::time::Instant::now() >= deadline => break None,
+                    None => tokio::time::sleep(INVITE_JOIN_CHECK_INTERVAL).await,
+                }
+            };
+            match display_name {
+                Some(display_name) => {
+                    remember_friend(&friends, &storage, peer_id, display_name);
+                }
+                None => debug!("Not saving {} from their invite: they aren't in room {}", peer_id, room_code),
+            }
+        });
+    }
+
+    /// Link inviting others to the room we're in, from us (see `handle_uri`)
+    pub fn invite_link(&self) -> Result<String, CoreError> {
+        let room = self.room.read().unwrap();
+        let state = room.state().ok_or(CoreError::NotInRoom)?;
+        Ok(deep_link::invite(&state.room_code, &state.local_peer_id))
+    }
+
+    fn persist_friends(&self, friends: &[Friend]) {
+        if let Some(storage) = self.storage.read().unwrap().as_deref() {
+            save_friends(storage, friends);
+        }
+    }
+
+    /// Deliver events through `next_event` instead of a callback
+    /// Replaces the callback set with `set_callback` (and is replaced by it)
+    pub fn use_event_queue(&self) {
+        self.events.clear();
+        self.install_callback(self.events.clone());
+    }
+
+    /// Take the next queued event, waiting up to `timeout_ms` for one (see `use_event_queue`)
+    /// Returns None if none arrived in time; blocks the calling thread meanwhile
+    pub fn next_event(&self, timeout_ms: u64) -> Option<SessionEvent> {
+        self.events.pop(Duration::from_millis(timeout_ms))
     }
 
     /// Set the signaling server URL (e.g., "https://ntfy.sh" or your own server)
@@ -108,6 +579,17 @@ impl Session {
         *signaling = crate::network::SignalingClient::with_url(url);
     }
 
+    /// Set the signaling server: an ntfy server, or a relay server's
+    /// signaling endpoint, for groups that don't want to depend on ntfy.sh
+    /// Must be called before creating/joining a room
+    pub fn set_signaling_server(&self, server: SignalingServer) {
+        let mut signaling = self.signaling.write().unwrap();
+        *signaling = match server {
+            SignalingServer::Ntfy { url } => crate::network::SignalingClient::with_url(url),
+            SignalingServer::Relay { url } => crate::network::SignalingClient::relay(url),
+        };
+    }
+
     /// Set custom bootstrap/relay nodes
     /// Must be called before creating/joining a room
     /// Format: "/ip4/127.0.0.1/tcp/4001/p2p/PEER_ID" or "/ip4/YOUR_IP/tcp/4001/p2p/PEER_ID"
@@ -118,63 +600,117 @@ impl Session {
         *bootstrap = nodes;
     }
 
+    /// Set preferred relay nodes for AutoRelay
+    /// Must be called before creating/joining a room
+    /// Format: "/ip4/YOUR_IP/tcp/4001/p2p/PEER_ID"
+    /// Relays discovered via identify are still used as fallback candidates
+    pub fn set_relay_nodes(&self, nodes: Vec<String>) {
+        info!("Setting preferred relay nodes: {:?}", nodes);
+        let mut relays = self.relay_nodes.write().unwrap();
+        *relays = nodes;
+    }
+
+    /// Set the access token for private relays (None for public relays)
+    /// Must be called before creating/joining a room
+    /// The token itself is never sent; relays verify a proof derived from it
+    pub fn set_relay_access_token(&self, token: Option<String>) {
+        let mut access_token = self.relay_access_token.write().unwrap();
+        // Trim whitespace from token (common copy/paste issue)
+        *access_token = token.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+    }
+
+    /// Set the region to prefer relays from (e.g. "eu-west", None = lowest RTT only)
+    /// Must be called before creating/joining a room
+    /// Matched against the region tag relays advertise
+    pub fn set_preferred_relay_region(&self, region: Option<String>) {
+        let mut preferred = self.preferred_relay_region.write().unwrap();
+        *preferred = region.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+    }
+
     /// Check if Cider is reachable
     pub fn check_cider_connection(&self) -> Result<(), CoreError> {
+        self.ensure_running()?;
         debug!("Checking Cider connection...");
-        let cider = self.cider.read().unwrap();
+        let cider = self.cider.read().unwrap().clone();
+        let port_override = *self.cider_port_override.read().unwrap();
         let result = self.runtime.block_on(async {
-            cider.is_active().await.map_err(|e| match e {
-                CiderApiError::Unauthorized => CoreError::CiderApiError("Invalid API token".to_string()),
-                CiderApiError::Api(msg) => CoreError::CiderApiError(msg),
-                CiderApiError::Http(e) => CoreError::NetworkError(e.to_string()),
-                _ => CoreError::CiderApiError(e.to_string()),
-            })
+            let mut result = cider.is_active().await;
+
+            // Cider may run its API on another port; the one that answers is kept
+            let unreachable = result.as_ref().is_err_and(|e| !matches!(e, CiderApiError::Unauthorized));
+            if unreachable && port_override.is_none() {
+                let Some(found) = cider.discover(CANDIDATE_PORTS).await else {
+                    let ports: Vec<String> = CANDIDATE_PORTS.iter().map(|p| p.to_string()).collect();
+                    return Err(CoreError::cider(
+                        ErrorCode::CiderNotFound,
+                        format!(
+                            "Cider's API isn't answering on ports {} - set the port if you changed it in Cider",
+                            ports.join(", ")
+                        ),
+                    ));
+                };
+                info!("Found Cider on port {}", found.port());
+                result = found.is_active().await;
+                *self.cider.write().unwrap() = found;
+            }
+
+            result.map_err(CoreError::from)
         });
         match &result {
             Ok(()) => info!("Cider connection OK"),
-            Err(e) => warn!("Cider connection failed: {:?}", e),
+            Err(e) => {
+                warn!("Cider connection failed: {:?}", e);
+                self.errors.record(format!("Cider connection failed: {}", e));
+            }
         }
         result
     }
 
     /// Get the currently playing track from Cider
     pub fn get_now_playing(&self) -> Result<Option<TrackInfo>, CoreError> {
+        self.ensure_running()?;
         let cider = self.cider.read().unwrap();
         let result = self.runtime.block_on(async {
             match cider.now_playing().await {
                 Ok(Some(np)) => Ok(Some(TrackInfo::from(&np))),
                 Ok(None) => Ok(None),
-                Err(CiderApiError::NotReachable) => Err(CoreError::CiderNotReachable),
-                Err(e) => Err(CoreError::CiderApiError(e.to_string())),
+                Err(e) => Err(CoreError::from(e)),
             }
         });
         match &result {
             Ok(Some(track)) => debug!("Now playing: {} - {} ({}ms)", track.name, track.artist, track.position_ms),
             Ok(None) => debug!("Nothing playing"),
-            Err(e) => warn!("get_now_playing failed: {:?}", e),
+            Err(e) => {
+                warn!("get_now_playing failed: {:?}", e);
+                self.errors.record(format!("get_now_playing failed: {}", e));
+            }
         }
         result
     }
 
     /// Check if Cider is currently playing
     pub fn get_is_playing(&self) -> Result<bool, CoreError> {
+        self.ensure_running()?;
         let cider = self.cider.read().unwrap();
         let result = self.runtime.block_on(async {
             match cider.is_playing().await {
                 Ok(playing) => Ok(playing),
-                Err(CiderApiError::NotReachable) => Err(CoreError::CiderNotReachable),
-                Err(e) => Err(CoreError::CiderApiError(e.to_string())),
+                Err(e) => Err(CoreError::from(e)),
             }
         });
         match &result {
             Ok(playing) => debug!("is_playing: {}", playing),
-            Err(e) => warn!("get_is_playing failed: {:?}", e),
+            Err(e) => {
+                warn!("get_is_playing failed: {:?}", e);
+                self.errors.record(format!("get_is_playing failed: {}", e));
+            }
         }
         result
     }
 
     /// Get playback state (track info + is_playing) in a single call
     pub fn get_playback_state(&self) -> Result<CurrentPlayback, CoreError> {
+        self.ensure_running()?;
         let cider = self.cider.read().unwrap();
         let result = self.runtime.block_on(async {
             // Run both requests concurrently
@@ -186,14 +722,12 @@ impl Session {
             let track = match track_result {
                 Ok(Some(np)) => Some(TrackInfo::from(&np)),
                 Ok(None) => None,
-                Err(CiderApiError::NotReachable) => return Err(CoreError::CiderNotReachable),
-                Err(e) => return Err(CoreError::CiderApiError(e.to_string())),
+                Err(e) => return Err(CoreError::from(e)),
             };
 
             let is_playing = match playing_result {
                 Ok(playing) => playing,
-                Err(CiderApiError::NotReachable) => return Err(CoreError::CiderNotReachable),
-                Err(e) => return Err(CoreError::CiderApiError(e.to_string())),
+                Err(e) => return Err(CoreError::from(e)),
             };
 
             Ok(CurrentPlayback { track, is_playing })
@@ -202,61 +736,56 @@ impl Session {
         match &result {
             Ok(CurrentPlayback { track: Some(t), is_playing }) => debug!("Playback: {} - {} ({}ms), playing={}", t.name, t.artist, t.position_ms, is_playing),
             Ok(CurrentPlayback { track: None, is_playing }) => debug!("Playback: nothing playing, playing={}", is_playing),
-            Err(e) => warn!("get_playback_state failed: {:?}", e),
+            Err(e) => {
+                warn!("get_playback_state failed: {:?}", e);
+                self.errors.record(format!("get_playback_state failed: {}", e));
+            }
         }
         result
     }
 
-    /// Create a new room (become host)
-    pub fn create_room(&self, display_name: String) -> Result<String, CoreError> {
-        {
-            let room = self.room.read().unwrap();
-            if room.is_busy() {
-                return Err(CoreError::AlreadyInRoom);
-            }
-        }
-
-        // Start the network if not already running
-        let (handle, peer_id) = self.ensure_network_running()?;
-
-        // Generate room code
-        let room_code = RoomCode::random();
-        let room_code_str = room_code.as_str().to_string();
-
-        // Tell network to create the room
-        handle
-            .create_room(&room_code_str)
-            .map_err(|e| CoreError::NetworkError(e.to_string()))?;
-
-        // Create local room state
-        let state = InternalRoomState::new_as_host(
-            room_code_str.clone(),
-            peer_id.clone(),
-            display_name,
-        );
-
-        {
-            let mut room = self.room.write().unwrap();
-            *room = Room::Active(state);
-        }
+    /// Set where downloaded artwork is cached (None = the system temp directory)
+    pub fn set_artwork_cache_dir(&self, path: Option<String>) {
+        self.artwork.set_dir(path.filter(|p| !p.is_empty()).map(std::path::PathBuf::from));
+    }
 
-        // Notify callback
-        if let Some(cb) = self.callback.read().unwrap().as_ref() {
-            let room = self.room.read().unwrap();
-            if let Some(state) = room.state() {
-                cb.on_room_state_changed(RoomState::from(state));
-            }
-        }
+    /// Get track artwork at `size`x`size` pixels as image bytes
+    /// Accepts templated or sized artwork URLs; each size is downloaded once
+    pub fn get_artwork(&self, url: String, size: u32) -> Result<Vec<u8>, CoreError> {
+        self.ensure_running()?;
+        self.runtime
+            .block_on(self.artwork.fetch(&url, size))
+            .map(|image| image.to_vec())
+            .map_err(CoreError::from)
+    }
 
-        // Start host broadcast loop
-        self.start_host_broadcast_loop();
+    /// Get the path of a cached file with track artwork at `size`x`size` pixels
+    /// Downloads the artwork if it isn't cached yet
+    pub fn get_artwork_path(&self, url: String, size: u32) -> Result<String, CoreError> {
+        self.ensure_running()?;
+        self.runtime
+            .block_on(self.artwork.path(&url, size))
+            .map(|path| path.to_string_lossy().into_owned())
+            .map_err(CoreError::from)
+    }
 
-        info!("Created room: {}", room_code);
-        Ok(room_code.to_string())
+    /// Create a new room (become host)
+    ///
+    /// The code is checked on signaling in the background once the room is
+    /// up; if another room has it, the room moves to a new one (see
+    /// `RoomCodes::claim`).
+    pub fn create_room(&self, display_name: String) -> Result<String, CoreError> {
+        self.ensure_running()?;
+        let code = RoomCode::random();
+        self.create_room_with_code(display_name, code.clone())?;
+        self.room_codes().claim(code.clone());
+        Ok(code.to_string())
     }
 
     /// Join an existing room
     pub fn join_room(&self, room_code: String, display_name: String) -> Result<(), CoreError> {
+        self.ensure_running()?;
+        let display_name = self.display_name_or_default(display_name);
         {
             let room = self.room.read().unwrap();
             if room.is_busy() {
@@ -266,109 +795,84 @@ impl Session {
 
         // Validate room code
         let code = RoomCode::parse(&room_code)
-            .ok_or_else(|| CoreError::NetworkError("Invalid room code".to_string()))?;
+            .ok_or_else(|| CoreError::network(ErrorCode::InvalidRoomCode, "Invalid room code"))?;
         let room_code_str = code.as_str().to_string();
 
         // Start the network if not already running
         let (handle, _) = self.ensure_network_running()?;
+        let (storefront, capabilities) = self.runtime.block_on(self.local_account());
+        let client = ClientInfo::local(self.app_version.clone());
 
         // Set room to joining state
-        {
-            let mut room = self.room.write().unwrap();
-            *room = Room::Joining {
-                room_code: room_code_str.clone(),
-                display_name: display_name.clone(),
-            };
-        }
+        let joining = Room::Joining {
+            room_code: room_code_str.clone(),
+            display_name,
+            storefront: storefront.clone(),
+            capabilities: capabilities.clone(),
+            profile: self.profile.read().unwrap().clone(),
+            client: client.clone(),
+            status: self.local_status(),
+            refusal: None,
+        };
+        self.room_actor.call(move |room| {
+            *room = joining;
+            Ok(())
+        })?;
 
         // Tell network to join the room
         handle
             .join_room(&room_code_str)
-            .map_err(|e| CoreError::NetworkError(e.to_string()))?;
-
-        // Poll signaling for host addresses (internet discovery)
-        let signaling_clone = self.signaling.read().unwrap().clone();
-        let handle_for_signaling = handle.clone();
-        let room_for_signaling = Arc::clone(&self.room);
-        let room_code_for_signaling = room_code_str.clone();
-        let local_peer_id = self.local_peer_id.read().unwrap().clone().unwrap_or_default();
-
-        self.runtime.spawn(async move {
-            // Poll signaling a few times for host addresses
-            for poll_attempt in 1..=6 {
-                // Check if we're still joining
-                let still_joining = {
-                    let room = room_for_signaling.read().unwrap();
-                    matches!(&*room, Room::Joining { room_code, .. } if room_code == &room_code_for_signaling)
-                };
-
-                if !still_joining {
-                    debug!("No longer joining, stopping signaling poll");
-                    break;
-                }
-
-                info!("Signaling poll attempt {}/6 for room {}", poll_attempt, room_code_for_signaling);
-
-                match signaling_clone.poll_room(&room_code_for_signaling).await {
-                    Ok(messages) => {
-                        if messages.is_empty() {
-                            info!("Signaling: No messages found for room {}", room_code_for_signaling);
-                        }
-                        for msg in messages {
-                            // Skip our own messages
-                            if msg.peer_id == local_peer_id {
-                                info!("Signaling: Skipping own message");
-                                continue;
-                            }
-
-                            info!("Found host {} with {} addresses via signaling", msg.peer_id, msg.addresses.len());
-
-                            // Dial each address
-                            for addr in &msg.addresses {
-                                info!("Dialing host address from signaling: {}", addr);
-                                if let Err(e) = handle_for_signaling.dial_peer(addr) {
-                                    warn!("Failed to dial {}: {}", addr, e);
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Signaling poll failed: {}", e);
-                    }
-                }
+            .map_err(CoreError::from)?;
+
+        // Watch signaling for host addresses (internet discovery)
+        if !self.lan_only {
+            let room_code = room_code_str.clone();
+            self.watch_signaling(handle.clone(), room_code_str.clone(), false, move |room| {
+                matches!(room, Room::Joining { room_code: code, .. } if code == &room_code)
+            });
+        }
 
-                // Wait before next poll (5 seconds between polls)
-                tokio::time::sleep(Duration::from_secs(5)).await;
-            }
-        });
+        // Signaling can tell there's no such room before discovery runs its course
+        if !self.lan_only {
+            self.check_room_announced(handle.clone(), room_code_str.clone());
+        }
 
         // Send join request with retry - the gossipsub mesh takes time to form
         // so the first few broadcasts might not reach the host
         let handle_clone = handle.clone();
-        let display_name_clone = display_name.clone();
         let room_clone = Arc::clone(&self.room);
         let room_code_for_retry = room_code_str.clone();
 
-        self.runtime.spawn(async move {
+        self.spawn(async move {
             // Wait a bit for mesh to form before first attempt
             tokio::time::sleep(Duration::from_millis(500)).await;
 
             // Retry JoinRequest a few times until we're in the room
             for attempt in 1..=5 {
-                // Check if we're still trying to join (not yet Active)
-                let still_joining = {
+                // Check if we're still trying to join (not yet Active); the name may have changed meanwhile
+                let joining_as = {
                     let room = room_clone.read().unwrap();
-                    matches!(&*room, Room::Joining { room_code, .. } if room_code == &room_code_for_retry)
+                    match &*room {
+                        Room::Joining { room_code, display_name, profile, status, .. } if room_code == &room_code_for_retry => {
+                            Some((display_name.clone(), profile.clone(), *status))
+                        }
+                        _ => None,
+                    }
                 };
 
-                if !still_joining {
+                let Some((display_name, profile, status)) = joining_as else {
                     debug!("No longer joining, stopping JoinRequest retries");
                     break;
-                }
+                };
 
                 debug!("Sending JoinRequest attempt {}/5", attempt);
                 let join_msg = SyncMessage::JoinRequest {
-                    display_name: display_name_clone.clone(),
+                    display_name,
+                    storefront: storefront.clone(),
+                    capabilities: capabilities.clone(),
+                    profile,
+                    client: client.clone(),
+                    status,
                 };
                 let _ = handle_clone.broadcast(join_msg);
 
@@ -377,43 +881,50 @@ impl Session {
             }
         });
 
-        // Start a timeout task - if no host responds, notify the user
-        let room_clone = Arc::clone(&self.room);
+        // Discovery fails the join as soon as it runs out of ways to find the
+        // room; this catches the rest (a peer was found, but no host answered)
+        let room_actor = self.room_actor.clone();
         let callback_clone = Arc::clone(&self.callback);
+        let errors_clone = Arc::clone(&self.errors);
+        let storage_clone = Arc::clone(&self.storage);
         let room_code_for_timeout = room_code_str.clone();
 
-        self.runtime.spawn(async move {
-            // 30 seconds to allow DHT discovery over internet (can take 10-30s)
-            tokio::time::sleep(Duration::from_secs(30)).await;
-
-            // Check if we're still in joining state for this room
-            let should_clear = {
-                let room = room_clone.read().unwrap();
-                if let Room::Joining { room_code: rc, .. } = &*room {
-                    rc == &room_code_for_timeout
-                } else {
-                    false
-                }
-            };
+        self.spawn(async move {
+            tokio::time::sleep(JOIN_TIMEOUT).await;
 
-            if should_clear {
-                // No host found - notify the UI and clear state
-                warn!("No host found for room {} after timeout", room_code_for_timeout);
+            // If we're still joining this room, clear room state so the user can try again
+            let code = room_code_for_timeout.clone();
+            let timed_out = room_actor
+                .call_async(move |room| {
+                    let joining = matches!(room, Room::Joining { room_code, .. } if *room_code == code);
+                    if joining {
+                        *room = Room::None;
+                    }
+                    Ok(joining)
+                })
+                .await
+                .unwrap_or(false);
 
-                // Clear room state first so user can try again
-                *room_clone.write().unwrap() = Room::None;
+            if timed_out {
+                // No host found - notify the UI
+                warn!("No host found for room {} after timeout", room_code_for_timeout);
+                if let Some(storage) = storage_clone.read().unwrap().as_deref() {
+                    clear_last_session(storage);
+                }
 
+                let message = format!("Room {} not found", room_code_for_timeout);
+                errors_clone.record(message.clone());
                 if let Some(cb) = callback_clone.read().unwrap().as_ref() {
-                    cb.on_error(format!(
-                        "Room {} not found",
-                        room_code_for_timeout
-                    ));
+                    let code = ErrorCode::RoomNotFound;
+                    cb.on_error(code, code.message_key().to_string(), message);
                 }
             }
         });
 
         // Start ping loop to measure latency (host will be set when RoomState arrives)
         self.start_listener_ping_loop();
+        self.start_calibration_warm_up();
+        self.remember_room();
 
         info!("Joining room: {}", code);
         Ok(())
@@ -439,10 +950,10 @@ impl Session {
             let _ = handle.leave_room();
         }
 
-        {
-            let mut room = self.room.write().unwrap();
+        self.room_actor.call(|room| {
             *room = Room::None;
-        }
+            Ok(())
+        })?;
 
         // Clear last broadcast track
         {
@@ -450,6 +961,11 @@ impl Session {
             *last_track = None;
         }
 
+        // Left on purpose: don't rejoin on the next launch
+        if let Some(storage) = self.storage.read().unwrap().as_deref() {
+            clear_last_session(storage);
+        }
+
         // Notify callback
         if let Some(cb) = self.callback.read().unwrap().as_ref() {
             cb.on_disconnected();
@@ -459,150 +975,528 @@ impl Session {
         Ok(())
     }
 
-    /// Transfer host to another peer
-    pub fn transfer_host(&self, peer_id: String) -> Result<(), CoreError> {
-        let mut room = self.room.write().unwrap();
-        let state = room.state_mut().ok_or(CoreError::NotInRoom)?;
-
-        if !state.is_host() {
-            return Err(CoreError::NotHost);
+    /// The app went to the background
+    ///
+    /// Hosts send heartbeats less often and stop measuring latency, listeners
+    /// stop pinging, and the network slows down its upkeep, to save battery.
+    /// Playback stays in sync. Call `on_app_foreground` when the app is back.
+    pub fn on_app_background(&self) {
+        if self.app_background.send_replace(true) {
+            return;
         }
-
-        if !state.transfer_host(&peer_id) {
-            return Err(CoreError::NetworkError("Peer not found".to_string()));
+        info!("App in background");
+        if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
+            handle.set_background(true);
         }
+    }
 
-        // Broadcast transfer message
+    /// The app is back in the foreground, see `on_app_background`
+    pub fn on_app_foreground(&self) {
+        if !self.app_background.send_replace(false) {
+            return;
+        }
+        info!("App in foreground");
         if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
-            let msg = SyncMessage::TransferHost {
-                new_host_peer_id: peer_id,
-            };
-            let _ = handle.broadcast(msg);
+            handle.set_background(false);
         }
+    }
 
-        // Notify callback
-        if let Some(cb) = self.callback.read().unwrap().as_ref() {
-            cb.on_room_state_changed(RoomState::from(&*state));
+    /// Shut the session down: leave the room and stop the network and all tasks
+    ///
+    /// Waits a few seconds at most for the network to stop, so a session
+    /// created next starts afresh. Other sessions keep running. The saved room
+    /// is kept, so `restore_last_session` rejoins it on the next launch.
+    /// Afterwards calls that need Cider or the network fail with `ShutDown`.
+    /// Dropping the session shuts it down too.
+    pub fn shutdown(&self) {
+        if self.shut_down.swap(true, Ordering::SeqCst) {
+            return;
         }
+        info!("Shutting down session");
 
-        Ok(())
+        self.stop_host_broadcast_loop();
+        self.stop_listener_ping_loop();
+        self.tasks.abort_all();
+        #[cfg(feature = "bridge")]
+        self.stop_bridge();
+        let network = self.network_handle.write().unwrap().take();
+        if let Some(handle) = &network {
+            if self.room.read().unwrap().is_busy() {
+                let _ = handle.leave_room();
+            }
+            handle.shutdown();
+        }
+        // The actor stopped with the other tasks, nothing else writes the room now
+        *self.room.write().unwrap() = Room::None;
+        *self.local_peer_id.write().unwrap() = None;
+        *self.last_broadcast_track_id.write().unwrap() = None;
+
+        // Blocking isn't allowed inside the runtime (e.g. dropped from a task)
+        if let Some(handle) = network.filter(|_| Handle::try_current().is_err()) {
+            let stopped = self
+                .runtime
+                .block_on(async { tokio::time::timeout(SHUTDOWN_TIMEOUT, handle.closed()).await });
+            if stopped.is_err() {
+                warn!("Network didn't stop within {:?}", SHUTDOWN_TIMEOUT);
+            }
+        }
+        info!("Session shut down");
     }
 
-    /// Sync play command (host only)
-    pub fn sync_play(&self) -> Result<(), CoreError> {
-        let room = self.room.read().unwrap();
-        let state = room.state().ok_or(CoreError::NotInRoom)?;
+    /// Transfer host to another peer
+    pub fn transfer_host(&self, peer_id: String) -> Result<(), CoreError> {
+        let network_handle = Arc::clone(&self.network_handle);
+        let callback = Arc::clone(&self.callback);
+        let host_delay_ms = self.host_sync_delay_ms;
+        self.room_actor.call(move |room| {
+            let state = room.state_mut().ok_or(CoreError::NotInRoom)?;
 
-        if !state.is_host() {
-            return Err(CoreError::NotHost);
-        }
+            if !state.is_host() {
+                return Err(CoreError::NotHost);
+            }
 
-        let cider = self.cider.read().unwrap();
-        self.runtime.block_on(async {
-            cider.play().await.map_err(|e| CoreError::CiderApiError(e.to_string()))
-        })?;
+            if !state.transfer_host(&peer_id) {
+                return Err(CoreError::network(ErrorCode::PeerNotFound, "Peer not found"));
+            }
 
-        // Broadcast play command
-        if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
-            if let Some(track) = &state.current_track {
-                let msg = SyncMessage::Play {
-                    track: track.clone(),
-                    position_ms: state.playback.position_ms,
-                    timestamp_ms: current_time_ms(),
+            // Broadcast transfer message, with our playback for the new host to go on from
+            if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                let msg = SyncMessage::TransferHost {
+                    new_host_peer_id: peer_id,
+                    track: state.current_track.clone(),
+                    playback: Some(playback_as_sent(&state.playback, host_delay_ms)),
                 };
                 let _ = handle.broadcast(msg);
             }
-        }
 
+            // Notify callback
+            if let Some(cb) = callback.read().unwrap().as_ref() {
+                cb.on_room_state_changed(RoomState::from(&*state));
+            }
+            Ok(())
+        })?;
+
+        self.remember_room();
         Ok(())
     }
 
-    /// Sync pause command (host only)
-    pub fn sync_pause(&self) -> Result<(), CoreError> {
+    /// Give the room a new code (host only), e.g. when the old one got around
+    ///
+    /// Participants follow without leaving; the old code stops letting anyone
+    /// in right away, and stops working altogether shortly after. Returns the
+    /// new code.
+    pub fn rotate_room_code(&self) -> Result<String, CoreError> {
+        self.ensure_running()?;
+        let code = RoomCode::random();
+        let codes = self.room_codes();
+        self.room_actor.call(codes.change(None, code.clone()))?;
+        codes.changed(&code);
+        codes.claim(code.clone());
+        Ok(code.to_string())
+    }
+
+    /// Ask the host to hand over control (listeners only)
+    ///
+    /// The host sees `on_control_requested` and grants it with `transfer_host`.
+    /// Does nothing if we're already the host.
+    pub fn request_host_control(&self) -> Result<(), CoreError> {
         let room = self.room.read().unwrap();
         let state = room.state().ok_or(CoreError::NotInRoom)?;
-
-        if !state.is_host() {
-            return Err(CoreError::NotHost);
+        if state.is_host() {
+            return Ok(());
         }
+        drop(room);
 
-        let cider = self.cider.read().unwrap();
-        self.runtime.block_on(async {
-            cider.pause().await.map_err(|e| CoreError::CiderApiError(e.to_string()))
-        })?;
-
-        // Broadcast pause command
         if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
-            let msg = SyncMessage::Pause {
-                position_ms: state.playback.position_ms,
-                timestamp_ms: current_time_ms(),
-            };
-            let _ = handle.broadcast(msg);
+            handle.broadcast(SyncMessage::ControlRequest)?;
         }
-
         Ok(())
     }
 
-    /// Sync seek command (host only)
-    pub fn sync_seek(&self, position_ms: u64) -> Result<(), CoreError> {
+    /// Stop receiving a class of the room's messages, or take it again (listeners only)
+    ///
+    /// A listener paused on its own end has no use for heartbeats; the host
+    /// answering our pings keeps the room alive meanwhile. Lasts until we
+    /// leave the room or are made its host; does nothing while we're host.
+    pub fn set_receives(&self, class: MessageClass, receive: bool) -> Result<(), CoreError> {
         let room = self.room.read().unwrap();
         let state = room.state().ok_or(CoreError::NotInRoom)?;
+        if state.is_host() {
+            return Ok(());
+        }
+        drop(room);
 
-        if !state.is_host() {
-            return Err(CoreError::NotHost);
+        if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
+            handle.set_receives(class.into(), receive)?;
         }
+        Ok(())
+    }
 
-        let cider = self.cider.read().unwrap();
-        self.runtime.block_on(async {
-            cider.seek_ms(position_ms).await.map_err(|e| CoreError::CiderApiError(e.to_string()))
+    /// Remove a participant from the room (host only); they may join again
+    pub fn kick_participant(&self, peer_id: String) -> Result<(), CoreError> {
+        self.remove_participant(peer_id, RemovalReason::Kicked)
+    }
+
+    /// Remove a participant and keep them out for as long as the room lasts (host only)
+    pub fn ban_participant(&self, peer_id: String) -> Result<(), CoreError> {
+        self.remove_participant(peer_id, RemovalReason::Banned)
+    }
+
+    /// Ignore suggestions from a participant, or take them again (host only)
+    pub fn mute_suggestions_from(&self, peer_id: String, muted: bool) -> Result<(), CoreError> {
+        self.update_room(move |state| {
+            if !state.participants.contains_key(&peer_id) {
+                return Err(CoreError::network(ErrorCode::PeerNotFound, "Peer not found"));
+            }
+            if muted {
+                state.moderation.suggestions_muted.insert(peer_id);
+            } else {
+                state.moderation.suggestions_muted.remove(&peer_id);
+            }
+            Ok(())
+        })
+    }
+
+    /// Lock the room so no one new can join, or unlock it (host only)
+    pub fn set_room_locked(&self, locked: bool) -> Result<(), CoreError> {
+        self.update_room(move |state| {
+            state.moderation.locked = locked;
+            Ok(())
+        })
+    }
+
+    /// Change the room's rules (host only)
+    ///
+    /// Listeners, and anyone joining later, get them with the room state.
+    pub fn update_room_settings(&self, settings: RoomSettings) -> Result<(), CoreError> {
+        self.update_room(move |state| {
+            state.settings = settings.into();
+            Ok(())
+        })
+    }
+
+    /// Change our display name in the current room
+    ///
+    /// Everyone in the room sees the new name right away; no need to rejoin.
+    pub fn set_display_name(&self, display_name: String) -> Result<(), CoreError> {
+        let display_name = self.display_name_or_default(display_name);
+        let profile = self.profile.read().unwrap().clone();
+        let network_handle = Arc::clone(&self.network_handle);
+        let callback = Arc::clone(&self.callback);
+        self.room_actor.call(move |room| match room {
+            Room::Joining { display_name: joining_name, .. } => {
+                // Not announced yet: the host gets it with our next JoinRequest
+                *joining_name = display_name;
+                Ok(())
+            }
+            Room::Active(state) => {
+                announce_participant_update(state, display_name, profile, &network_handle, &callback)
+            }
+            _ => Err(CoreError::NotInRoom),
         })?;
 
-        // Broadcast seek command
-        if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
-            let msg = SyncMessage::Seek {
-                position_ms,
-                timestamp_ms: current_time_ms(),
-            };
-            let _ = handle.broadcast(msg);
+        self.remember_room();
+        Ok(())
+    }
+
+    /// Set our avatar (an emoji, or an image URL or hash) and color (`#RRGGBB`)
+    ///
+    /// Shown to everyone in the current room right away, and used for rooms
+    /// created or joined later. Values the apps couldn't show are dropped.
+    pub fn set_profile(&self, avatar: Option<String>, color: Option<String>) -> Result<(), CoreError> {
+        let profile = Profile { avatar, color }.sanitized();
+        *self.profile.write().unwrap() = profile.clone();
+
+        let default_display_name = self.default_display_name.clone();
+        let network_handle = Arc::clone(&self.network_handle);
+        let callback = Arc::clone(&self.callback);
+        self.room_actor.call(move |room| match room {
+            Room::Joining { profile: joining_profile, .. } => {
+                *joining_profile = profile;
+                Ok(())
+            }
+            Room::Active(state) => {
+                let display_name = state
+                    .participants
+                    .get(&state.local_peer_id)
+                    .map(|p| p.display_name.clone())
+                    .unwrap_or(default_display_name);
+                announce_participant_update(state, display_name, profile, &network_handle, &callback)
+            }
+            _ => Ok(()),
+        })
+    }
+
+    /// Show as away to the room, or back
+    ///
+    /// Lasts across rooms until called again; being away trumps being idle.
+    pub fn set_away(&self, away: bool) -> Result<(), CoreError> {
+        self.away.store(away, Ordering::Relaxed);
+        self.announce_status()
+    }
+
+    /// Tell the core how long the device has had no input
+    ///
+    /// Apps pass the system's idle time every minute or so, and 0 on input;
+    /// we show as idle to the room after `IDLE_AFTER` without any.
+    pub fn report_idle_time(&self, idle_secs: u64) -> Result<(), CoreError> {
+        let idle = Duration::from_secs(idle_secs) >= IDLE_AFTER;
+        if self.idle.swap(idle, Ordering::Relaxed) == idle {
+            return Ok(());
         }
+        self.announce_status()
+    }
 
-        Ok(())
+    /// Sync play command (host only)
+    pub fn sync_play(&self) -> Result<(), CoreError> {
+        self.runtime.block_on(self.play_as_host())
+    }
+
+    /// Sync pause command (host only)
+    pub fn sync_pause(&self) -> Result<(), CoreError> {
+        self.runtime.block_on(self.pause_as_host())
+    }
+
+    /// Sync seek command (host only)
+    pub fn sync_seek(&self, position_ms: u64) -> Result<(), CoreError> {
+        self.runtime.block_on(self.seek_as_host(position_ms))
     }
 
     /// Sync next command (host only)
     pub fn sync_next(&self) -> Result<(), CoreError> {
+        self.runtime.block_on(self.next_as_host())
+    }
+
+    /// Sync previous command (host only)
+    pub fn sync_previous(&self) -> Result<(), CoreError> {
+        self.runtime.block_on(self.previous_as_host())
+    }
+
+    /// Snap back in sync with the host right away (listeners only)
+    ///
+    /// Seeks to the host's last known position even if we're within the drift
+    /// threshold, e.g. after the audio output's latency changed. Does nothing
+    /// for the host.
+    pub fn force_resync(&self) -> Result<(), CoreError> {
+        self.ensure_running()?;
+        let playback = {
+            let room = self.room.read().unwrap();
+            let state = room.state().ok_or(CoreError::NotInRoom)?;
+            if state.is_host() {
+                return Ok(());
+            }
+            state.playback.clone()
+        };
+
+        info!("Resyncing to host at {}ms", playback.position_ms);
+        // Any drift is corrected, and the result reported right away
+        let settings = SyncSettings::new(0, self.sync_settings.status_level);
+        self.runtime.block_on(sync_to_host(
+            &playback,
+            &self.callback,
+            &self.cider,
+            &self.latency_tracker,
+            &self.seek_calibrator,
+            &settings,
+        ));
+        Ok(())
+    }
+
+    /// Get current room state
+    pub fn get_room_state(&self) -> Option<RoomState> {
         let room = self.room.read().unwrap();
-        let state = room.state().ok_or(CoreError::NotInRoom)?;
+        room.state().map(RoomState::from)
+    }
 
-        if !state.is_host() {
-            return Err(CoreError::NotHost);
-        }
+    /// Where the room's playback is now (ms), extrapolated from the last
+    /// heartbeat with the time since, our latency to the host, and whether
+    /// it's playing
+    ///
+    /// Doesn't ask Cider, so apps can call it every frame to draw a progress
+    /// bar. None outside a room.
+    pub fn get_estimated_position_ms(&self) -> Option<u64> {
+        let room = self.room.read().unwrap();
+        let state = room.state()?;
+        Some(estimated_position_ms(state, &self.latency_tracker, current_time_ms()))
+    }
 
-        let cider = self.cider.read().unwrap();
-        self.runtime.block_on(async {
-            cider.next().await.map_err(|e| CoreError::CiderApiError(e.to_string()))
-        })
+    /// Get recent network events (oldest first) for the debug connection timeline
+    /// Returns an empty list if the network hasn't been started yet
+    pub fn get_recent_network_events(&self) -> Vec<NetworkLogEntry> {
+        self.network_handle
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|h| h.recent_events().into_iter().map(NetworkLogEntry::from).collect())
+            .unwrap_or_default()
     }
 
-    /// Sync previous command (host only)
-    pub fn sync_previous(&self) -> Result<(), CoreError> {
+    /// Measured latency to each other participant in the room
+    ///
+    /// Participants without measurements yet are left out.
+    pub fn get_peer_latencies(&self) -> Vec<PeerLatency> {
         let room = self.room.read().unwrap();
-        let state = room.state().ok_or(CoreError::NotInRoom)?;
+        let Some(state) = room.state() else {
+            return Vec::new();
+        };
+        self.latency_tracker
+            .read()
+            .unwrap()
+            .peer_latencies()
+            .into_iter()
+            .filter(|l| l.peer_id != state.local_peer_id && state.participants.contains_key(&l.peer_id))
+            .map(PeerLatency::from)
+            .collect()
+    }
 
-        if !state.is_host() {
-            return Err(CoreError::NotHost);
+    /// Recent round-trip times to each other participant in the room, with a
+    /// histogram of them, for plotting latency over time
+    ///
+    /// Participants without measurements yet are left out.
+    pub fn get_latency_history(&self) -> Vec<PeerLatencyHistory> {
+        let room = self.room.read().unwrap();
+        let Some(state) = room.state() else {
+            return Vec::new();
+        };
+        self.latency_tracker
+            .read()
+            .unwrap()
+            .rtt_histories()
+            .into_iter()
+            .filter(|h| h.peer_id != state.local_peer_id && state.participants.contains_key(&h.peer_id))
+            .map(PeerLatencyHistory::from)
+            .collect()
+    }
+
+    /// Snapshot of the session for bug reports
+    ///
+    /// Collects the room, network and relay status, latency and seek
+    /// calibration figures and recent errors into one record.
+    pub fn get_diagnostics(&self) -> Diagnostics {
+        let network_handle = self.network_handle.read().unwrap().clone();
+        let (host_latency_ms, peer_latencies) = {
+            let tracker = self.latency_tracker.read().unwrap();
+            let peers = tracker.peer_latencies().into_iter().map(PeerLatency::from).collect();
+            (tracker.host_latency_ms(), peers)
+        };
+        let (seek_offset_ms, calibration_history) = {
+            let calibrator = self.seek_calibrator.read().unwrap();
+            let history = calibrator.sample_history().iter().map(CalibrationSample::from).collect();
+            (calibrator.offset_ms(SeekKind::InTrack), history)
+        };
+
+        Diagnostics {
+            generated_at_ms: current_time_ms(),
+            core_version: env!("CARGO_PKG_VERSION").to_string(),
+            cider_port: self.cider.read().unwrap().port(),
+            lan_only: self.lan_only,
+            room: self.get_room_state(),
+            network: network_handle
+                .as_ref()
+                .map(|h| NetworkDiagnostics::new(h.local_peer_id.clone(), h.status())),
+            host_latency_ms,
+            peer_latencies,
+            seek_offset_ms,
+            calibration_history,
+            recent_errors: self.errors.entries(),
+            recent_network_events: network_handle
+                .map(|h| h.recent_events().into_iter().map(NetworkLogEntry::from).collect())
+                .unwrap_or_default(),
         }
+    }
 
-        let cider = self.cider.read().unwrap();
-        self.runtime.block_on(async {
-            cider.previous().await.map_err(|e| CoreError::CiderApiError(e.to_string()))
+    /// Recap of the room we're in so far, or else of the last one, to share or
+    /// attach to a sync complaint: tracks played, who was there and how well
+    /// each listener kept in sync
+    ///
+    /// Fails with `NotInRoom` if we haven't been in one this session.
+    pub fn export_room_recap(&self, format: RecapFormat) -> Result<Vec<u8>, CoreError> {
+        let recap = self.recap.lock().unwrap().recap().ok_or(CoreError::NotInRoom)?;
+        Ok(match format {
+            RecapFormat::Json => recap.to_json(),
+            RecapFormat::Csv => recap.to_csv(),
         })
     }
 
-    /// Get current room state
-    pub fn get_room_state(&self) -> Option<RoomState> {
+    /// Save the tracks played in the room we're in so far, or else in the last
+    /// one, as a playlist named `name` in our Apple Music library
+    ///
+    /// Works for listeners as well as the host: tracks are added as our own
+    /// storefront's catalog has them, each once, and those it doesn't have are
+    /// left out (counted in `missing_count`). Fails with `NotInRoom` if we
+    /// haven't been in one this session, and `NothingPlaying` if nothing was
+    /// played in it.
+    pub fn save_history_as_playlist(&self, name: String) -> Result<SavedPlaylist, CoreError> {
+        self.ensure_running()?;
+        let recap = self.recap.lock().unwrap().recap().ok_or(CoreError::NotInRoom)?;
+        if recap.tracks.is_empty() {
+            return Err(CoreError::cider(ErrorCode::NothingPlaying, "No tracks were played in the room"));
+        }
+        let cider = self.cider.read().unwrap().clone();
+        let description = format!("Played in Cider Listen Together room {}", recap.room_code);
+        let result = self.runtime.block_on(async {
+            let (song_ids, missing_count) = playlist::song_ids(&cider, &recap.tracks).await;
+            if song_ids.is_empty() {
+                return Err(CoreError::cider(
+                    ErrorCode::CiderApi,
+                    "None of the room's tracks are in this storefront's catalog",
+                ));
+            }
+            let playlist_id = cider.create_library_playlist(&name, &description, &song_ids).await?;
+            Ok(SavedPlaylist {
+                playlist_id,
+                track_count: song_ids.len() as u32,
+                missing_count,
+            })
+        });
+        match &result {
+            Ok(saved) => info!(
+                "Saved {} tracks from room {} as playlist {} ({} missing)",
+                saved.track_count, recap.room_code, saved.playlist_id, saved.missing_count
+            ),
+            Err(e) => {
+                warn!("save_history_as_playlist failed: {:?}", e);
+                self.errors.record(format!("save_history_as_playlist failed: {}", e));
+            }
+        }
+        result
+    }
+
+    /// Current transport details: addresses, relays, how the host is reached and mesh size
+    ///
+    /// None when the network isn't running.
+    pub fn get_network_info(&self) -> Option<NetworkInfo> {
+        let handle = self.network_handle.read().unwrap().clone()?;
+        let host_peer_id = self.room.read().unwrap().state().map(|s| s.host_peer_id.clone());
+        Some(NetworkInfo::new(handle.local_peer_id.clone(), handle.status(), host_peer_id.as_deref()))
+    }
+
+    /// Check every subsystem at once, for a single status screen
+    ///
+    /// Probes Cider's reachability and our API token concurrently, and checks
+    /// the network, the relay reservation and room membership. Each gets its
+    /// own status, so one failing doesn't hide the others.
+    pub fn health_check(&self) -> Result<HealthReport, CoreError> {
+        self.ensure_running()?;
+        let cider = self.cider.read().unwrap().clone();
+        let location = format!("{}:{}", cider.host(), cider.port());
+        // Without the token, so a rejected token doesn't read as Cider being down
+        let anonymous = CiderClient::with_host(cider.host(), cider.port());
+        let (reachable, authorized) = self
+            .runtime
+            .block_on(async { futures::join!(anonymous.is_active(), cider.is_active()) });
+
+        let status = self.network_handle.read().unwrap().as_ref().map(|h| h.status());
         let room = self.room.read().unwrap();
-        room.state().map(RoomState::from)
+        let report = HealthReport::new(
+            health::cider(&reachable, &location),
+            health::cider_token(&reachable, &authorized, cider.has_token()),
+            health::network(status.as_ref(), self.lan_only),
+            health::relay(status.as_ref(), self.lan_only),
+            health::room(&room, status.as_ref()),
+        );
+        debug!("Health check: {:?}", report.overall);
+        Ok(report)
     }
 
     /// Check if we are the host
@@ -611,6 +1505,18 @@ impl Session {
         room.state().map(|s| s.is_host()).unwrap_or(false)
     }
 
+    /// Listeners whose accounts can't play `track` (e.g. an explicit track with explicit content restricted)
+    ///
+    /// Lets the host warn before playing a track some listeners would silently miss.
+    pub fn get_listeners_unable_to_play(&self, track: TrackInfo) -> Vec<Participant> {
+        let room = self.room.read().unwrap();
+        let Some(state) = room.state() else {
+            return Vec::new();
+        };
+        let track = crate::sync::TrackInfo::from(&track);
+        state.listeners_unable_to_play(&track).into_iter().map(Participant::from).collect()
+    }
+
     /// Check if we are in a room
     pub fn is_in_room(&self) -> bool {
         let room = self.room.read().unwrap();
@@ -631,11 +1537,12 @@ impl Session {
                 track_id: track.as_ref().map(|t| t.song_id.clone()),
                 playback: PlaybackInfo {
                     is_playing,
-                    position_ms,
+                    position_ms: ahead_of_host(position_ms, is_playing, self.host_sync_delay_ms),
                     timestamp_ms: current_time_ms(),
                 },
+                room_hash: Some(state.room_hash()),
             };
-            handle.broadcast(msg).map_err(|e| CoreError::NetworkError(e.to_string()))?;
+            handle.broadcast(msg).map_err(CoreError::from)?;
         }
 
         Ok(())
@@ -643,13 +1550,6 @@ impl Session {
 
     /// Broadcast track change to room (for host when track changes)
     pub fn broadcast_track_change(&self, track: TrackInfo, position_ms: u64) -> Result<(), CoreError> {
-        let mut room = self.room.write().unwrap();
-        let state = room.state_mut().ok_or(CoreError::NotInRoom)?;
-
-        if !state.is_host() {
-            return Err(CoreError::NotHost);
-        }
-
         // Update our local state with the new track
         let internal_track = crate::sync::TrackInfo {
             song_id: track.song_id.clone(),
@@ -658,292 +1558,694 @@ impl Session {
             album: track.album.clone(),
             artwork_url: track.artwork_url.clone(),
             duration_ms: track.duration_ms,
-        };
-        state.update_track(Some(internal_track.clone()));
-
-        // Broadcast the track change
-        if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
-            let msg = SyncMessage::TrackChange {
-                track: internal_track,
-                position_ms,
-                timestamp_ms: current_time_ms(),
-            };
-            handle.broadcast(msg).map_err(|e| CoreError::NetworkError(e.to_string()))?;
+            isrc: track.isrc.clone(),
+            explicit: track.explicit,
         }
+        .within_limits();
+        let network_handle = Arc::clone(&self.network_handle);
+        let position_ms = ahead_of_host(position_ms, true, self.host_sync_delay_ms);
+        self.room_actor.call(move |room| {
+            let state = room.state_mut().ok_or(CoreError::NotInRoom)?;
 
-        Ok(())
+            if !state.is_host() {
+                return Err(CoreError::NotHost);
+            }
+            state.update_track(Some(internal_track.clone()));
+
+            // Broadcast the track change
+            if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                let msg = SyncMessage::TrackChange {
+                    track: internal_track,
+                    position_ms,
+                    timestamp_ms: current_time_ms(),
+                };
+                handle.broadcast(msg).map_err(CoreError::from)?;
+            }
+            Ok(())
+        })
     }
 }
 
+/// Async variants of the blocking methods
+///
+/// The blocking methods wait on Cider and the network for up to their full
+/// timeouts, stalling the calling thread (e.g. Swift's main actor). These run
+/// the same call on a thread of its own and resolve once it's done.
+#[uniffi::export]
 impl Session {
-    /// Ensure the network is running, start it if not
-    fn ensure_network_running(&self) -> Result<(NetworkHandle, String), CoreError> {
-        // Check if already running
-        {
-            let handle = self.network_handle.read().unwrap();
-            if let Some(h) = handle.as_ref() {
-                let peer_id = self.local_peer_id.read().unwrap().clone().unwrap();
-                return Ok((h.clone(), peer_id));
-            }
-        }
+    /// Create a new room (become host), see `create_room`
+    pub async fn create_room_async(self: Arc<Self>, display_name: String) -> Result<String, CoreError> {
+        self.off_thread(move |s| s.create_room(display_name)).await
+    }
 
-        // Start the network with custom config if bootstrap nodes are set
-        let bootstrap_nodes = self.bootstrap_nodes.read().unwrap().clone();
-        let config = if bootstrap_nodes.is_empty() {
-            NetworkConfig::default()
-        } else {
-            NetworkConfig {
-                bootstrap_nodes,
-                ..NetworkConfig::default()
-            }
-        };
+    /// Join an existing room, see `join_room`
+    pub async fn join_room_async(self: Arc<Self>, room_code: String, display_name: String) -> Result<(), CoreError> {
+        self.off_thread(move |s| s.join_room(room_code, display_name)).await
+    }
 
-        let network_manager = NetworkManager::with_config(config)
-            .map_err(|e| CoreError::NetworkError(e.to_string()))?;
+    /// Get playback state (track info + is_playing), see `get_playback_state`
+    pub async fn get_playback_state_async(self: Arc<Self>) -> Result<CurrentPlayback, CoreError> {
+        self.off_thread(|s| s.get_playback_state()).await
+    }
 
-        let (handle, mut event_rx) = self.runtime.block_on(async {
-            network_manager.start()
-        }).map_err(|e| CoreError::NetworkError(e.to_string()))?;
+    /// Sync play command (host only)
+    pub async fn sync_play_async(self: Arc<Self>) -> Result<(), CoreError> {
+        self.on_runtime(|s| async move { s.play_as_host().await }).await
+    }
 
-        let peer_id = handle.local_peer_id.clone();
+    /// Sync pause command (host only)
+    pub async fn sync_pause_async(self: Arc<Self>) -> Result<(), CoreError> {
+        self.on_runtime(|s| async move { s.pause_as_host().await }).await
+    }
 
-        // Store the handle and peer ID
-        {
-            let mut h = self.network_handle.write().unwrap();
-            *h = Some(handle.clone());
-        }
-        {
-            let mut p = self.local_peer_id.write().unwrap();
-            *p = Some(peer_id.clone());
-        }
+    /// Sync seek command (host only)
+    pub async fn sync_seek_async(self: Arc<Self>, position_ms: u64) -> Result<(), CoreError> {
+        self.on_runtime(move |s| async move { s.seek_as_host(position_ms).await }).await
+    }
 
-        // Spawn event handler task
-        let room_clone = Arc::clone(&self.room);
-        let callback_clone = Arc::clone(&self.callback);
-        let cider_clone = Arc::clone(&self.cider);
-        let network_handle_clone = Arc::clone(&self.network_handle);
-        let latency_tracker_clone = Arc::clone(&self.latency_tracker);
-        let seek_calibrator_clone = Arc::clone(&self.seek_calibrator);
-        let signaling_clone = self.signaling.read().unwrap().clone();
-        let local_peer_id = peer_id.clone();
-
-        self.runtime.spawn(async move {
-            use crate::network::NetworkEvent;
-
-            while let Some(event) = event_rx.recv().await {
-                // Handle ListeningAddresses for signaling (internet discovery)
-                if let NetworkEvent::ListeningAddresses { addresses } = &event {
-                    // Get room code if we're in a room
-                    let room_code = {
-                        let room = room_clone.read().unwrap();
-                        match &*room {
-                            Room::Active(state) => Some(state.room_code.clone()),
-                            Room::Joining { room_code, .. } => Some(room_code.clone()),
-                            _ => None,
-                        }
-                    };
+    /// Sync next command (host only)
+    pub async fn sync_next_async(self: Arc<Self>) -> Result<(), CoreError> {
+        self.on_runtime(|s| async move { s.next_as_host().await }).await
+    }
 
-                    if let Some(code) = room_code {
-                        let addresses = addresses.clone();
-                        let signaling = signaling_clone.clone();
-                        let peer_id = local_peer_id.clone();
+    /// Sync previous command (host only)
+    pub async fn sync_previous_async(self: Arc<Self>) -> Result<(), CoreError> {
+        self.on_runtime(|s| async move { s.previous_as_host().await }).await
+    }
 
-                        info!("Publishing {} addresses to signaling for room {}", addresses.len(), code);
-                        for addr in &addresses {
-                            info!("  -> {}", addr);
-                        }
+    /// Get back into the last room, see `restore_last_session`
+    pub async fn restore_last_session_async(self: Arc<Self>) -> Result<Option<SavedSession>, CoreError> {
+        self.off_thread(|s| s.restore_last_session()).await
+    }
 
-                        // Publish to signaling in a separate task
-                        tokio::spawn(async move {
-                            if let Err(e) = signaling.publish_room(&code, &peer_id, addresses).await {
-                                warn!("Failed to publish to signaling: {}", e);
-                            } else {
-                                info!("Successfully published to signaling");
-                            }
-                        });
-                    }
-                    continue;
-                }
+    /// Save the room's tracks as a library playlist, see `save_history_as_playlist`
+    pub async fn save_history_as_playlist_async(self: Arc<Self>, name: String) -> Result<SavedPlaylist, CoreError> {
+        self.off_thread(move |s| s.save_history_as_playlist(name)).await
+    }
 
-                handle_network_event(
-                    event,
-                    &room_clone,
-                    &callback_clone,
-                    &cider_clone,
-                    &network_handle_clone,
-                    &latency_tracker_clone,
-                    &seek_calibrator_clone,
-                    &local_peer_id,
-                ).await;
-            }
-        });
+    /// Act on a `ciderlt://` link, see `handle_uri`
+    pub async fn handle_uri_async(self: Arc<Self>, uri: String) -> Result<DeepLink, CoreError> {
+        self.off_thread(move |s| s.handle_uri(uri)).await
+    }
 
-        Ok((handle, peer_id))
+    /// Check every subsystem at once, see `health_check`
+    pub async fn health_check_async(self: Arc<Self>) -> Result<HealthReport, CoreError> {
+        self.off_thread(|s| s.health_check()).await
     }
 
-    /// Start the host broadcast loop (polls Cider and broadcasts to listeners)
-    fn start_host_broadcast_loop(&self) {
-        // Stop any existing loop first
-        self.stop_host_broadcast_loop();
+    /// Snap back in sync with the host, see `force_resync`
+    pub async fn force_resync_async(self: Arc<Self>) -> Result<(), CoreError> {
+        self.off_thread(|s| s.force_resync()).await
+    }
 
-        let (cancel_tx, mut cancel_rx) = tokio::sync::oneshot::channel();
+    /// Shut the session down, see `shutdown`
+    pub async fn shutdown_async(self: Arc<Self>) {
+        self.off_thread(|s| s.shutdown()).await
+    }
+}
 
-        // Store cancel sender
-        {
-            let mut cancel = self.host_broadcast_cancel.write().unwrap();
-            *cancel = Some(cancel_tx);
+/// Local bridge server, for integrations without uniffi bindings
+#[cfg(feature = "bridge")]
+#[uniffi::export]
+impl Session {
+    /// Serve the session on localhost: its API as REST, and its events over a
+    /// WebSocket (see `ffi/bridge.rs` for the routes), to clients with its
+    /// `bridge_token`. `port` 0 picks a free one. Returns the port; if the
+    /// bridge already runs, the one it's on.
+    pub fn start_bridge(self: Arc<Self>, port: u16) -> Result<u16, CoreError> {
+        self.ensure_running()?;
+        let mut bridge = self.bridge.write().unwrap();
+        if let Some(running) = bridge.as_ref() {
+            return Ok(running.port);
         }
 
-        let cider = Arc::clone(&self.cider);
-        let room = Arc::clone(&self.room);
-        let network_handle = Arc::clone(&self.network_handle);
-        let callback = Arc::clone(&self.callback);
-        let last_track_id = Arc::clone(&self.last_broadcast_track_id);
-
-        self.runtime.spawn(async move {
-            info!("Host broadcast loop started");
+        let listener = self
+            .runtime
+            .block_on(async { tokio::net::TcpListener::bind(("127.0.0.1", port)).await })
+            .map_err(|e| CoreError::network(ErrorCode::Network, format!("Couldn't start the bridge: {}", e)))?;
+        let token = match self.storage.read().unwrap().as_deref() {
+            Some(storage) => super::persistence::load_or_create_bridge_token(storage),
+            None => super::persistence::new_bridge_token(),
+        };
+        let (mut running, server) = super::bridge::start(listener, Arc::downgrade(&self), token);
+        let app = self.callback.read().unwrap().clone();
+        *self.callback.write().unwrap() = Some(running.tap(app));
+        self.spawn(server);
+
+        info!("Bridge started on port {}", running.port);
+        let port = running.port;
+        *bridge = Some(running);
+        Ok(port)
+    }
 
-            loop {
-                // Check for cancellation
-                if cancel_rx.try_recv().is_ok() {
-                    info!("Host broadcast loop cancelled");
-                    break;
-                }
+    /// Stop the bridge server, if it runs
+    pub fn stop_bridge(&self) {
+        if let Some(running) = self.bridge.write().unwrap().take() {
+            *self.callback.write().unwrap() = running.app_callback();
+            info!("Bridge on port {} stopped", running.port);
+        }
+    }
 
-                // Check if we're still the host
-                let is_host = {
-                    let r = room.read().unwrap();
-                    r.state().map(|s| s.is_host()).unwrap_or(false)
-                };
+    /// Port the bridge server is on (None if it isn't running)
+    pub fn bridge_port(&self) -> Option<u16> {
+        self.bridge.read().unwrap().as_ref().map(|running| running.port)
+    }
 
-                if !is_host {
-                    debug!("No longer host, stopping broadcast loop");
-                    break;
-                }
+    /// Token every request to the bridge must carry (None if it isn't
+    /// running); kept in secure storage, if set, so it survives restarts
+    pub fn bridge_token(&self) -> Option<String> {
+        self.bridge.read().unwrap().as_ref().map(|running| running.token.clone())
+    }
+}
 
-                // Poll Cider for current playback
-                let cider_client = cider.read().unwrap().clone();
-                let playback_result = tokio::join!(
-                    cider_client.now_playing(),
-                    cider_client.is_playing()
-                );
-
-                // Extract playback info - use defaults if no track
-                let (current_track_id, position_ms, is_playing, track_info) = match playback_result {
-                    (Ok(Some(np)), Ok(playing)) => {
-                        let track = crate::sync::TrackInfo {
-                            song_id: np.song_id().map(|s| s.to_string()).unwrap_or_default(),
-                            name: np.name.clone(),
-                            artist: np.artist_name.clone(),
-                            album: np.album_name.clone(),
-                            artwork_url: np.artwork_url(600),
-                            duration_ms: np.duration_in_millis,
-                        };
-                        (np.song_id().map(|s| s.to_string()), np.current_position_ms(), playing, Some(track))
-                    }
-                    (Ok(None), Ok(playing)) => {
-                        // No track loaded - still send heartbeat with idle state
-                        (None, 0, playing, None)
-                    }
-                    _ => {
-                        // Cider error - skip this cycle but don't stop heartbeats
-                        debug!("Failed to poll Cider playback, skipping heartbeat");
-                        tokio::time::sleep(Duration::from_millis(1500)).await;
-                        continue;
-                    }
-                };
+impl Session {
+    /// Run a blocking method on the runtime's blocking pool, resolving with its result
+    ///
+    /// The methods `block_on` the runtime, which its blocking threads may do
+    /// (unlike its worker threads).
+    pub(super) async fn off_thread<T: Send + 'static>(
+        self: Arc<Self>,
+        call: impl FnOnce(&Session) -> T + Send + 'static,
+    ) -> T {
+        let runtime = self.runtime.clone();
+        runtime.spawn_blocking(move || call(&self)).await.expect("Session call panicked")
+    }
+
+    /// Run a future of the session's on its runtime, resolving with its result
+    ///
+    /// For the async methods: the app may await them on an executor of its
+    /// own, without the runtime Cider's and the network's clients need.
+    async fn on_runtime<T, F>(self: Arc<Self>, call: impl FnOnce(Arc<Session>) -> F) -> T
+    where
+        T: Send + 'static,
+        F: Future<Output = T> + Send + 'static,
+    {
+        let task = call(Arc::clone(&self));
+        self.runtime.spawn(task).await.expect("Session call panicked")
+    }
+
+    /// Play and tell the room, see `sync_play`
+    pub(super) async fn play_as_host(&self) -> Result<(), CoreError> {
+        self.ensure_running()?;
+        let state = self.hosted_room()?;
+        let cider = self.cider.read().unwrap().clone();
+        cider.play().await?;
 
-                // Check if track changed
-                let track_changed = {
-                    let last = last_track_id.read().unwrap();
-                    last.as_ref() != current_track_id.as_ref()
+        // Broadcast play command
+        if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
+            if let Some(track) = &state.current_track {
+                let msg = SyncMessage::Play {
+                    track: track.clone(),
+                    position_ms: ahead_of_host(state.playback.position_ms, true, self.host_sync_delay_ms),
+                    timestamp_ms: current_time_ms(),
                 };
+                let _ = handle.broadcast(msg);
+            }
+        }
 
-                if track_changed {
-                    // Update last track ID
-                    {
-                        let mut last = last_track_id.write().unwrap();
-                        *last = current_track_id.clone();
-                    }
+        Ok(())
+    }
 
-                    // Update room state
-                    {
-                        let mut r = room.write().unwrap();
-                        if let Some(state) = r.state_mut() {
-                            state.update_track(track_info.clone());
-                            state.update_playback(PlaybackInfo {
-                                is_playing,
-                                position_ms,
-                                timestamp_ms: current_time_ms(),
-                            });
-                        }
-                    }
+    /// Pause and tell the room, see `sync_pause`
+    pub(super) async fn pause_as_host(&self) -> Result<(), CoreError> {
+        self.ensure_running()?;
+        let state = self.hosted_room()?;
+        let cider = self.cider.read().unwrap().clone();
+        cider.pause().await?;
 
-                    // Broadcast track change (only if there's a track)
-                    if let Some(track) = &track_info {
-                        if let Some(handle) = network_handle.read().unwrap().as_ref() {
-                            let msg = SyncMessage::TrackChange {
-                                track: track.clone(),
-                                position_ms,
-                                timestamp_ms: current_time_ms(),
-                            };
-                            let _ = handle.broadcast(msg);
-                        }
+        // Broadcast pause command
+        if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
+            let msg = SyncMessage::Pause {
+                position_ms: state.playback.position_ms,
+                timestamp_ms: current_time_ms(),
+            };
+            let _ = handle.broadcast(msg);
+        }
 
-                        // Notify callback
-                        if let Some(cb) = callback.read().unwrap().as_ref() {
-                            cb.on_track_changed(Some(TrackInfo::from(track.clone())));
-                        }
+        Ok(())
+    }
 
-                        debug!("Broadcasted track change: {}", track.name);
-                    } else {
-                        // Track cleared - notify callback
-                        if let Some(cb) = callback.read().unwrap().as_ref() {
-                            cb.on_track_changed(None);
-                        }
-                        debug!("Track cleared");
-                    }
+    /// Seek and tell the room, see `sync_seek`
+    pub(super) async fn seek_as_host(&self, position_ms: u64) -> Result<(), CoreError> {
+        self.ensure_running()?;
+        let state = self.hosted_room()?;
+        let cider = self.cider.read().unwrap().clone();
+        cider.seek_ms(position_ms).await?;
+
+        // Broadcast seek command
+        if let Some(handle) = self.network_handle.read().unwrap().as_ref() {
+            let msg = SyncMessage::Seek {
+                position_ms: ahead_of_host(position_ms, state.playback.is_playing, self.host_sync_delay_ms),
+                timestamp_ms: current_time_ms(),
+            };
+            let _ = handle.broadcast(msg);
+        }
+
+        Ok(())
+    }
+
+    /// Skip to the next track, see `sync_next`
+    pub(super) async fn next_as_host(&self) -> Result<(), CoreError> {
+        self.ensure_running()?;
+        self.hosted_room()?;
+        let cider = self.cider.read().unwrap().clone();
+        Ok(cider.next().await?)
+    }
+
+    /// Go back to the previous track, see `sync_previous`
+    pub(super) async fn previous_as_host(&self) -> Result<(), CoreError> {
+        self.ensure_running()?;
+        self.hosted_room()?;
+        let cider = self.cider.read().unwrap().clone();
+        Ok(cider.previous().await?)
+    }
+
+    /// Set where events go, behind the bridge if it runs
+    fn install_callback(&self, callback: Arc<dyn SessionCallback>) {
+        #[cfg(feature = "bridge")]
+        let callback = match self.bridge.write().unwrap().as_mut() {
+            Some(running) => running.tap(Some(callback)),
+            None => callback,
+        };
+        *self.callback.write().unwrap() = Some(callback);
+    }
+
+    /// Fail if the session was shut down
+    fn ensure_running(&self) -> Result<(), CoreError> {
+        if self.shut_down.load(Ordering::SeqCst) {
+            return Err(CoreError::ShutDown);
+        }
+        Ok(())
+    }
+
+    /// Spawn one of the session's tasks on the runtime (stopped by `shutdown`)
+    fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.tasks.spawn(&self.runtime, task);
+    }
+
+    /// Apple Music storefront and restrictions of Cider's account, asked for
+    /// together (unknown if Cider can't tell us within `ACCOUNT_LOOKUP_TIMEOUT`)
+    async fn local_account(&self) -> (Option<String>, Capabilities) {
+        let cider = self.cider.read().unwrap().clone();
+        let lookup = async { tokio::join!(cider.storefront(), cider.explicit_content_restricted()) };
+        let Ok((storefront, explicit_restricted)) = tokio::time::timeout(ACCOUNT_LOOKUP_TIMEOUT, lookup).await else {
+            warn!("Cider didn't tell us about its account within {:?}", ACCOUNT_LOOKUP_TIMEOUT);
+            return (None, Capabilities::default());
+        };
+        let storefront = storefront
+            .map_err(|e| warn!("Couldn't get the Apple Music storefront: {}", e))
+            .ok()
+            .map(str::to_string);
+        let explicit_restricted = explicit_restricted
+            .map_err(|e| warn!("Couldn't get the explicit content setting: {}", e))
+            .ok();
+        (storefront, Capabilities { explicit_restricted })
+    }
+
+    /// Create a room under `room_code` (a new one, or ours from before a relaunch)
+    fn create_room_with_code(&self, display_name: String, room_code: RoomCode) -> Result<String, CoreError> {
+        self.ensure_running()?;
+        let display_name = self.display_name_or_default(display_name);
+        {
+            let room = self.room.read().unwrap();
+            if room.is_busy() {
+                return Err(CoreError::AlreadyInRoom);
+            }
+        }
+
+        // Start the network if not already running
+        let (handle, peer_id) = self.ensure_network_running()?;
+        let (storefront, capabilities) = self.runtime.block_on(self.local_account());
+        let room_code_str = room_code.as_str().to_string();
+
+        // Tell network to create the room
+        handle
+            .create_room(&room_code_str)
+            .map_err(CoreError::from)?;
+
+        // Create local room state
+        let mut state = InternalRoomState::new_as_host(
+            room_code_str.clone(),
+            peer_id.clone(),
+            display_name,
+            storefront,
+            capabilities,
+            self.profile.read().unwrap().clone(),
+            ClientInfo::local(self.app_version.clone()),
+        );
+        state.set_participant_status(&peer_id, self.local_status());
+        // Bans outlive a relaunch
+        if let Some(storage) = self.storage.read().unwrap().as_deref() {
+            state.banned = load_blocklist(storage, &room_code_str);
+        }
+
+        self.room_actor.call(move |room| {
+            *room = Room::Active(state);
+            Ok(())
+        })?;
+
+        // Notify callback
+        if let Some(cb) = self.callback.read().unwrap().as_ref() {
+            let room = self.room.read().unwrap();
+            if let Some(state) = room.state() {
+                cb.on_room_state_changed(RoomState::from(state));
+            }
+        }
+
+        // Start host broadcast loop
+        self.start_host_broadcast_loop();
+        self.remember_room();
+        // Now that the room is ours, so the announcement isn't dropped
+        let _ = handle.announce_room();
+
+        self.watch_for_joiners(handle, room_code_str);
+
+        info!("Created room: {}", room_code);
+        Ok(room_code.to_string())
+    }
+
+    /// Tell discovery if nobody in the room has announced themselves on
+    /// signaling lately, so it doesn't wait on signaling for them
+    fn check_room_announced(&self, handle: NetworkHandle, room_code: String) {
+        let signaling = self.signaling.read().unwrap().clone();
+        let local_peer_id = self.local_peer_id.read().unwrap().clone().unwrap_or_default();
+        self.spawn(async move {
+            match tokio::time::timeout(ROOM_IN_USE_TIMEOUT, signaling.room_active(&room_code, &local_peer_id)).await {
+                Ok(Ok(true)) => debug!("Room {} is announced on signaling", room_code),
+                Ok(Ok(false)) => {
+                    info!("Room {} isn't announced on signaling", room_code);
+                    let _ = handle.room_not_announced();
                 }
+                Ok(Err(e)) => debug!("Couldn't check room {} on signaling: {}", room_code, e),
+                Err(_) => debug!("Timed out checking room {} on signaling", room_code),
+            }
+        });
+    }
 
-                // Always send heartbeat (keeps clients alive even when idle)
-                if let Some(handle) = network_handle.read().unwrap().as_ref() {
-                    let msg = SyncMessage::Heartbeat {
-                        track_id: current_track_id,
-                        playback: PlaybackInfo {
-                            is_playing,
-                            position_ms,
-                            timestamp_ms: current_time_ms(),
-                        },
-                    };
-                    let _ = handle.broadcast(msg);
+    /// Reach out to joiners as they announce themselves on signaling, while
+    /// we're hosting the room under `room_code`
+    fn watch_for_joiners(&self, handle: NetworkHandle, room_code: String) {
+        self.room_codes().watch_for_joiners(handle, room_code);
+    }
+
+    /// Dial the peers announced on the room's signaling channel (see
+    /// `RoomCodes::watch_signaling`)
+    fn watch_signaling(
+        &self,
+        handle: NetworkHandle,
+        room_code: String,
+        joiners: bool,
+        watching: impl Fn(&Room) -> bool + Send + 'static,
+    ) {
+        self.room_codes().watch_signaling(handle, room_code, joiners, watching);
+    }
+
+    /// The given display name, or the configured default if it's blank, cut
+    /// to what other peers accept
+    fn display_name_or_default(&self, display_name: String) -> String {
+        let trimmed = display_name.trim();
+        let display_name = if trimmed.is_empty() { &self.default_display_name } else { trimmed };
+        display_name_within_limits(display_name).trim().to_string()
+    }
+
+    /// Save the room we're in, our name and role, for `restore_last_session`
+    fn remember_room(&self) {
+        self.room_codes().remember_room();
+    }
+
+    /// Snapshot of the room we're hosting
+    ///
+    /// A copy, so no lock is held while waiting on Cider.
+    fn hosted_room(&self) -> Result<InternalRoomState, CoreError> {
+        let room = self.room.read().unwrap();
+        let state = room.state().ok_or(CoreError::NotInRoom)?;
+        if !state.is_host() {
+            return Err(CoreError::NotHost);
+        }
+        Ok(state.clone())
+    }
+
+    /// Remove a participant on the host's behalf and tell the room
+    fn remove_participant(&self, peer_id: String, reason: RemovalReason) -> Result<(), CoreError> {
+        let storage = self.storage.read().unwrap().clone();
+        let network_handle = Arc::clone(&self.network_handle);
+        let callback = Arc::clone(&self.callback);
+        self.room_actor.call(move |room| {
+            let state = room.state_mut().ok_or(CoreError::NotInRoom)?;
+            if !state.is_host() {
+                return Err(CoreError::NotHost);
+            }
+            if peer_id == state.local_peer_id || state.remove_participant(&peer_id).is_none() {
+                return Err(CoreError::network(ErrorCode::PeerNotFound, "Peer not found"));
+            }
+            if reason == RemovalReason::Banned {
+                state.banned.insert(peer_id.clone());
+                if let Some(storage) = storage.as_deref() {
+                    save_blocklist(storage, &state.room_code, &state.banned);
                 }
+            }
+            info!("Removed {} from the room: {:?}", peer_id, reason);
 
-                // Update room playback state
-                {
-                    let mut r = room.write().unwrap();
-                    if let Some(state) = r.state_mut() {
-                        state.update_playback(PlaybackInfo {
-                            is_playing,
-                            position_ms,
-                            timestamp_ms: current_time_ms(),
-                        });
-                    }
+            if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                let _ = handle.broadcast(SyncMessage::Removed { peer_id: peer_id.clone(), reason });
+            }
+            if let Some(cb) = callback.read().unwrap().as_ref() {
+                cb.on_participant_left(peer_id);
+                cb.on_room_state_changed(RoomState::from(&*state));
+            }
+            Ok(())
+        })
+    }
+
+    /// Change the room's settings or moderation (host only) and share them
+    fn update_room(
+        &self,
+        update: impl FnOnce(&mut InternalRoomState) -> Result<(), CoreError> + Send + 'static,
+    ) -> Result<(), CoreError> {
+        let network_handle = Arc::clone(&self.network_handle);
+        let callback = Arc::clone(&self.callback);
+        let host_delay_ms = self.host_sync_delay_ms;
+        self.room_actor.call(move |room| {
+            let state = room.state_mut().ok_or(CoreError::NotInRoom)?;
+            if !state.is_host() {
+                return Err(CoreError::NotHost);
+            }
+            update(state)?;
+
+            if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                let _ = handle.broadcast(room_state_message(state, host_delay_ms));
+            }
+            if let Some(cb) = callback.read().unwrap().as_ref() {
+                cb.on_room_state_changed(RoomState::from(&*state));
+            }
+            Ok(())
+        })
+    }
+
+    /// Whether we're at the device, as the app told us
+    fn local_status(&self) -> ParticipantStatus {
+        if self.away.load(Ordering::Relaxed) {
+            ParticipantStatus::Away
+        } else if self.idle.load(Ordering::Relaxed) {
+            ParticipantStatus::Idle
+        } else {
+            ParticipantStatus::Active
+        }
+    }
+
+    /// Show our status to the room, if it changed
+    fn announce_status(&self) -> Result<(), CoreError> {
+        let status = self.local_status();
+        let network_handle = Arc::clone(&self.network_handle);
+        let callback = Arc::clone(&self.callback);
+        self.room_actor.call(move |room| match room {
+            Room::Joining { status: joining_status, .. } => {
+                // Not announced yet: the host gets it with our next JoinRequest
+                *joining_status = status;
+                Ok(())
+            }
+            Room::Active(state) => {
+                let peer_id = state.local_peer_id.clone();
+                if state.participants.get(&peer_id).is_none_or(|p| p.status == status) {
+                    return Ok(());
+                }
+                state.set_participant_status(&peer_id, status);
+                if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                    handle.broadcast(SyncMessage::StatusUpdated { peer_id, status }).map_err(CoreError::from)?;
+                }
+                if let Some(cb) = callback.read().unwrap().as_ref() {
+                    cb.on_room_state_changed(RoomState::from(&*state));
                 }
+                Ok(())
+            }
+            _ => Ok(()),
+        })
+    }
 
-                // Wait before next poll (1.5 seconds)
-                tokio::time::sleep(Duration::from_millis(1500)).await;
+    /// Ensure the network is running, start it if not
+    fn ensure_network_running(&self) -> Result<(NetworkHandle, String), CoreError> {
+        // Check if already running
+        {
+            let handle = self.network_handle.read().unwrap();
+            if let Some(h) = handle.as_ref() {
+                let peer_id = self.local_peer_id.read().unwrap().clone().unwrap();
+                return Ok((h.clone(), peer_id));
             }
+        }
 
-            info!("Host broadcast loop ended");
+        // Start the network with custom bootstrap/relay nodes (empty = defaults)
+        let identity = match self.storage.read().unwrap().as_deref() {
+            Some(storage) => load_or_create_keypair(storage),
+            None => Keypair::generate_ed25519(),
+        };
+        let mut config = NetworkConfig {
+            bootstrap_nodes: self.bootstrap_nodes.read().unwrap().clone(),
+            relay_nodes: self.relay_nodes.read().unwrap().clone(),
+            relay_access_token: self.relay_access_token.read().unwrap().clone(),
+            preferred_relay_region: self.preferred_relay_region.read().unwrap().clone(),
+            identity: Some(identity.clone()),
+            ..NetworkConfig::default()
+        };
+        if self.lan_only {
+            info!("LAN-only session: not using the DHT or relays");
+            config.enable_dht = false;
+            config.relay_nodes.clear();
+        }
+
+        let network_manager = NetworkManager::with_config(config)
+            .map_err(|e| CoreError::network(ErrorCode::NetworkUnavailable, e.to_string()))?;
+
+        let (handle, event_rx) = self.runtime.block_on(async {
+            network_manager.start()
+        }).map_err(|e| CoreError::network(ErrorCode::NetworkUnavailable, e.to_string()))?;
+
+        let peer_id = handle.local_peer_id.clone();
+
+        // Store the handle and peer ID
+        {
+            let mut h = self.network_handle.write().unwrap();
+            *h = Some(handle.clone());
+        }
+        {
+            let mut p = self.local_peer_id.write().unwrap();
+            *p = Some(peer_id.clone());
+        }
+        if *self.app_background.borrow() {
+            handle.set_background(true);
+        }
+
+        // Network events are handled by the room's actor, in order with other changes
+        self.room_actor.attach_network(NetworkContext {
+            events: event_rx,
+            local_peer_id: peer_id.clone(),
+            callback: Arc::clone(&self.callback),
+            cider: Arc::clone(&self.cider),
+            network_handle: Arc::clone(&self.network_handle),
+            latency_tracker: Arc::clone(&self.latency_tracker),
+            seek_calibrator: Arc::clone(&self.seek_calibrator),
+            errors: Arc::clone(&self.errors),
+            storage: Arc::clone(&self.storage),
+            signaling: self.signaling.read().unwrap().clone(),
+            lan_only: self.lan_only,
+            presence_identity: self.share_presence.then_some(identity),
+            sync_settings: Arc::clone(&self.sync_settings),
+            player: Player::spawn(&self.runtime, &self.tasks),
+            room_broadcaster: RoomBroadcaster::spawn(
+                Arc::clone(&self.room),
+                Arc::clone(&self.network_handle),
+                self.host_sync_delay_ms,
+                &self.runtime,
+                &self.tasks,
+            ),
+            host_loop: self.host_loop(),
+            room_hash_check: RoomHashCheck::default(),
         });
+
+        Ok((handle, peer_id))
+    }
+
+    /// The host broadcast loop, for starting and stopping it
+    fn host_loop(&self) -> HostLoop {
+        HostLoop {
+            runtime: self.runtime.clone(),
+            tasks: Arc::clone(&self.tasks),
+            cancel: Arc::clone(&self.host_broadcast_cancel),
+            cider: Arc::clone(&self.cider),
+            room: Arc::clone(&self.room),
+            room_actor: self.room_actor.clone(),
+            network_handle: Arc::clone(&self.network_handle),
+            callback: Arc::clone(&self.callback),
+            last_track_id: Arc::clone(&self.last_broadcast_track_id),
+            latency_tracker: Arc::clone(&self.latency_tracker),
+            heartbeat_interval: self.heartbeat_interval,
+            sync_delay_ms: self.host_sync_delay_ms,
+            background: self.app_background.subscribe(),
+        }
+    }
+
+    fn room_codes(&self) -> RoomCodes {
+        RoomCodes {
+            runtime: self.runtime.clone(),
+            tasks: Arc::clone(&self.tasks),
+            room: Arc::clone(&self.room),
+            room_actor: self.room_actor.clone(),
+            network_handle: Arc::clone(&self.network_handle),
+            callback: Arc::clone(&self.callback),
+            storage: Arc::clone(&self.storage),
+            signaling: Arc::clone(&self.signaling),
+            local_peer_id: Arc::clone(&self.local_peer_id),
+            default_display_name: self.default_display_name.clone(),
+            lan_only: self.lan_only,
+        }
+    }
+
+    /// Start the host broadcast loop (see `HostLoop::start`)
+    fn start_host_broadcast_loop(&self) {
+        self.host_loop().start();
     }
 
     /// Stop the host broadcast loop
     fn stop_host_broadcast_loop(&self) {
-        let mut cancel = self.host_broadcast_cancel.write().unwrap();
-        if let Some(tx) = cancel.take() {
-            let _ = tx.send(());
+        self.host_loop().stop();
+    }
+
+    /// Calibrate the seek offset once we're listening to a playing host
+    ///
+    /// Skipped if disabled in the config, or with a calibration learned in an
+    /// earlier party.
+    fn start_calibration_warm_up(&self) {
+        let converged = self.seek_calibrator.read().unwrap().is_converged(SeekKind::InTrack);
+        if !self.sync_settings.calibration_warm_up || converged {
+            return;
         }
+
+        let room = Arc::clone(&self.room);
+        let cider = Arc::clone(&self.cider);
+        let latency_tracker = Arc::clone(&self.latency_tracker);
+        let seek_calibrator = Arc::clone(&self.seek_calibrator);
+
+        self.spawn(async move {
+            let deadline = Instant::now() + WARM_UP_WAIT;
+            loop {
+                let host_playing = match &*room.read().unwrap() {
+                    Room::Joining { .. } => false,
+                    Room::Active(state) if !state.is_host() => state.playback.is_playing,
+                    _ => return,
+                };
+                if host_playing {
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    debug!("Host didn't start playing, skipping calibration warm-up");
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+
+            tokio::time::sleep(WARM_UP_DELAY).await;
+            let cider_client = cider.read().unwrap().clone();
+            warm_up_calibration(&room, &cider_client, &latency_tracker, &seek_calibrator, WARM_UP_SETTLE).await;
+        });
     }
 
     /// Start the listener ping loop (measures latency to peers)
@@ -963,12 +2265,19 @@ impl Session {
         let latency_tracker = Arc::clone(&self.latency_tracker);
         let network_handle = Arc::clone(&self.network_handle);
         let room = Arc::clone(&self.room);
+        let room_actor = self.room_actor.clone();
         let callback = Arc::clone(&self.callback);
         let cider = Arc::clone(&self.cider);
+        let seek_calibrator = Arc::clone(&self.seek_calibrator);
+        let storage = Arc::clone(&self.storage);
+        let mut background = self.app_background.subscribe();
 
-        self.runtime.spawn(async move {
+        self.spawn(async move {
             debug!("Listener ping loop started");
 
+            // Samples of each kind in the last saved calibration
+            let mut saved_samples = SeekKind::ALL.map(|kind| seek_calibrator.read().unwrap().sample_count(kind));
+
             // Timeout for detecting host disconnect (15 seconds without heartbeat)
             let heartbeat_timeout = Duration::from_secs(15);
 
@@ -979,6 +2288,30 @@ impl Session {
                     break;
                 }
 
+                // Suspended while the app is in the background
+                if *background.borrow() {
+                    debug!("Listener ping loop suspended");
+                    tokio::select! {
+                        _ = &mut cancel_rx => {
+                            debug!("Listener ping loop cancelled");
+                            break;
+                        }
+                        resumed = background.wait_for(|in_background| !in_background) => {
+                            if resumed.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    // Heartbeats may not have reached us while the app was suspended;
+                    // give the host a full timeout to be heard from again
+                    room_actor.send(|room| {
+                        if let Some(state) = room.state_mut() {
+                            state.last_heartbeat = Instant::now();
+                        }
+                    });
+                    continue;
+                }
+
                 // Check room state: Joining (wait), Active listener (check), Active host (exit), None (exit)
                 enum LoopState {
                     WaitingToJoin,
@@ -1024,9 +2357,9 @@ impl Session {
                             }
 
                             // Clear room state
-                            {
-                                let mut r = room.write().unwrap();
-                                *r = Room::None;
+                            room_actor.send(|room| *room = Room::None);
+                            if let Some(storage) = storage.read().unwrap().as_deref() {
+                                clear_last_session(storage);
                             }
 
                             break;
@@ -1034,19 +2367,31 @@ impl Session {
                     }
                 }
 
-                // Create and send ping
+                // Save the calibration as it's refined, in case the app is killed mid-party
+                {
+                    let calibrator = seek_calibrator.read().unwrap();
+                    let samples = SeekKind::ALL.map(|kind| calibrator.sample_count(kind));
+                    if samples != saved_samples {
+                        if let Some(storage) = storage.read().unwrap().as_deref() {
+                            save_learned_calibration(storage, &calibrator);
+                        }
+                        saved_samples = samples;
+                    }
+                }
+
+                // Create and send ping, to the host only
                 let timestamp = {
                     let mut tracker = latency_tracker.write().unwrap();
                     tracker.create_ping()
                 };
 
-                if let Some(handle) = network_handle.read().unwrap().as_ref() {
-                    let ping = SyncMessage::Ping { sent_at_ms: timestamp };
-                    let _ = handle.broadcast(ping);
+                let host_peer_id = room.read().unwrap().state().map(|s| s.host_peer_id.clone());
+                if let (Some(handle), Some(host)) = (network_handle.read().unwrap().as_ref(), host_peer_id) {
+                    let _ = handle.ping(&host, timestamp);
                 }
 
-                // Wait before next ping (5 seconds)
-                tokio::time::sleep(Duration::from_secs(5)).await;
+                // Wait before next ping
+                tokio::time::sleep(PING_INTERVAL).await;
             }
 
             debug!("Listener ping loop ended");
@@ -1062,9 +2407,16 @@ impl Session {
         // Clear latency tracker
         let mut tracker = self.latency_tracker.write().unwrap();
         tracker.clear();
-        // Reset seek calibrator
+        // Reset seek calibrator, keeping what it learned for the next room
+        let storage = self.storage.read().unwrap().clone();
         let mut calibrator = self.seek_calibrator.write().unwrap();
+        if let Some(storage) = storage.as_deref() {
+            save_learned_calibration(storage, &calibrator);
+        }
         calibrator.reset();
+        if let Some(storage) = storage.as_deref() {
+            restore_calibration(storage, &mut calibrator);
+        }
     }
 }
 
@@ -1073,3 +2425,1609 @@ impl Default for Session {
         Self::new()
     }
 }
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Host and port (if any) of Cider's address as typed by the user: a URL
+/// ("http://desktop.local:10767/"), or else a host with an optional port
+/// ("192.168.1.20", "desktop.local:10767"). None if there's no host in it.
+fn parse_cider_address(address: &str) -> Option<(String, Option<u16>)> {
+    let address = address.trim();
+    let url = reqwest::Url::parse(address)
+        .ok()
+        .filter(|url| url.has_host())
+        .or_else(|| {
+            let host_and_port = (!address.contains("://")).then(|| format!("http://{}", address))?;
+            reqwest::Url::parse(&host_and_port).ok()
+        })?;
+    // IPv6 hosts come bracketed; the Cider client adds the brackets itself
+    let host = url.host_str()?.trim_start_matches('[').trim_end_matches(']');
+    (!host.is_empty()).then(|| (host.to_string(), url.port()))
+}
+
+/// Save the calibration of each kind of seek that has converged
+fn save_learned_calibration(storage: &dyn SecureStorage, calibrator: &SeekCalibrator) {
+    for kind in SeekKind::ALL.into_iter().filter(|&kind| calibrator.is_converged(kind)) {
+        let calibration = SavedCalibration {
+            offset_ms: calibrator.offset_ms(kind),
+            sample_count: calibrator.sample_count(kind),
+        };
+        save_calibration(storage, kind, calibration);
+    }
+}
+
+/// Start from the calibration saved for each kind of seek
+fn restore_calibration(storage: &dyn SecureStorage, calibrator: &mut SeekCalibrator) {
+    for kind in SeekKind::ALL {
+        if let Some(saved) = load_calibration(storage, kind) {
+            calibrator.restore(kind, saved.offset_ms, saved.sample_count);
+        }
+    }
+}
+
+/// Update our entry in the room and tell everyone in it
+fn announce_participant_update(
+    state: &mut InternalRoomState,
+    display_name: String,
+    profile: Profile,
+    network_handle: &RwLock<Option<NetworkHandle>>,
+    callback: &RwLock<Option<Arc<dyn SessionCallback>>>,
+) -> Result<(), CoreError> {
+    let peer_id = state.local_peer_id.clone();
+    state.update_participant(&peer_id, display_name.clone(), profile.clone());
+
+    if let Some(handle) = network_handle.read().unwrap().as_ref() {
+        let msg = SyncMessage::ParticipantUpdated { peer_id, display_name, profile };
+        handle.broadcast(msg).map_err(CoreError::from)?;
+    }
+
+    if let Some(cb) = callback.read().unwrap().as_ref() {
+        cb.on_room_state_changed(RoomState::from(&*state));
+    }
+    Ok(())
+}
+
+/// How often the host sends heartbeats, slowed down in the background
+fn host_heartbeat_interval(heartbeat_interval: Duration, in_background: bool) -> Duration {
+    if in_background {
+        heartbeat_interval.max(BACKGROUND_HEARTBEAT_INTERVAL)
+    } else {
+        heartbeat_interval
+    }
+}
+
+/// The code of the room we're in: making sure nobody else uses it while we
+/// host, changing it, and finding its peers on signaling
+#[derive(Clone)]
+pub(super) struct RoomCodes {
+    runtime: Handle,
+    tasks: Arc<TaskSet>,
+    room: Arc<RwLock<Room>>,
+    room_actor: RoomActor,
+    network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+    callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    storage: Arc<RwLock<Option<Arc<dyn SecureStorage>>>>,
+    signaling: Arc<RwLock<crate::network::SignalingClient>>,
+    local_peer_id: Arc<RwLock<Option<String>>>,
+    default_display_name: String,
+    lan_only: bool,
+}
+
+impl RoomCodes {
+    /// Move the room we host to `code` if another room on signaling has
+    /// `code` too, and again until one is free (as far as signaling can
+    /// tell: when it can't, any code will do)
+    ///
+    /// Runs in the background: a room can't wait on signaling to start, and
+    /// collisions are rare.
+    pub(super) fn claim(&self, mut code: RoomCode) {
+        if self.lan_only {
+            return;
+        }
+        let codes = self.clone();
+        self.tasks.spawn(&self.runtime, async move {
+            let signaling = codes.signaling.read().unwrap().clone();
+            let local_peer_id = codes.local_peer_id.read().unwrap().clone().unwrap_or_default();
+            for _ in 1..MAX_ROOM_CODE_ATTEMPTS {
+                let in_use = tokio::time::timeout(ROOM_IN_USE_TIMEOUT, signaling.room_active(code.as_str(), &local_peer_id));
+                match in_use.await {
+                    Ok(Ok(true)) => {}
+                    Ok(Ok(false)) => return,
+                    Ok(Err(e)) => {
+                        debug!("Couldn't check whether room code {} is in use: {}", code, e);
+                        return;
+                    }
+                    Err(_) => {
+                        debug!("Timed out checking whether room code {} is in use", code);
+                        return;
+                    }
+                }
+
+                let new_code = RoomCode::random();
+                info!("Room code {} is in use, moving to {}", code, new_code);
+                let change = codes.change(Some(code.as_str().to_string()), new_code.clone());
+                if let Err(e) = codes.room_actor.call_async(change).await {
+                    debug!("Not moving off room code {}: {}", code, e);
+                    return;
+                }
+                codes.changed(&new_code);
+                code = new_code;
+            }
+        });
+    }
+
+    /// Change for the room actor giving the room we host `new_code`
+    /// (only if it has `old_code`, when given)
+    pub(super) fn change(
+        &self,
+        old_code: Option<String>,
+        new_code: RoomCode,
+    ) -> impl FnOnce(&mut Room) -> Result<(), CoreError> + Send + 'static {
+        let new_code = new_code.as_str().to_string();
+        let storage = self.storage.read().unwrap().clone();
+        let network_handle = Arc::clone(&self.network_handle);
+        let callback = Arc::clone(&self.callback);
+        move |room| {
+            let state = room.state_mut().ok_or(CoreError::NotInRoom)?;
+            if !state.is_host() {
+                return Err(CoreError::NotHost);
+            }
+            if old_code.is_some_and(|code| code != state.room_code) {
+                return Err(CoreError::NotInRoom);
+            }
+
+            // Told on the old topic, before we move to the new one
+            if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                handle.broadcast(SyncMessage::RoomCodeChanged { room_code: new_code.clone() })?;
+                handle.change_room_code(&new_code)?;
+            }
+            info!("Room code changed from {} to {}", state.room_code, new_code);
+            state.room_code = new_code;
+            // Bans are kept by room code
+            if let Some(storage) = storage.as_deref() {
+                save_blocklist(storage, &state.room_code, &state.banned);
+            }
+
+            if let Some(cb) = callback.read().unwrap().as_ref() {
+                cb.on_room_state_changed(RoomState::from(&*state));
+            }
+            Ok(())
+        }
+    }
+
+    /// The room we host now has `code`
+    pub(super) fn changed(&self, code: &RoomCode) {
+        self.remember_room();
+        if let Some(handle) = self.network_handle.read().unwrap().clone() {
+            self.watch_for_joiners(handle, code.as_str().to_string());
+        }
+    }
+
+    /// Save the room we're in, our name and role, for `restore_last_session`
+    pub(super) fn remember_room(&self) {
+        let Some(storage) = self.storage.read().unwrap().clone() else {
+            return;
+        };
+        let saved = match &*self.room.read().unwrap() {
+            Room::Joining { room_code, display_name, .. } => SavedSession {
+                room_code: room_code.clone(),
+                display_name: display_name.clone(),
+                was_host: false,
+            },
+            Room::Active(state) => SavedSession {
+                room_code: state.room_code.clone(),
+                display_name: state
+                    .participants
+                    .get(&state.local_peer_id)
+                    .map(|p| p.display_name.clone())
+                    .unwrap_or_else(|| self.default_display_name.clone()),
+                was_host: state.is_host(),
+            },
+            _ => return,
+        };
+        save_last_session(storage.as_ref(), &saved);
+    }
+
+    /// Reach out to joiners as they announce themselves on signaling, while
+    /// we're hosting the room under `room_code`
+    pub(super) fn watch_for_joiners(&self, handle: NetworkHandle, room_code: String) {
+        if self.lan_only {
+            return;
+        }
+        let code = room_code.clone();
+        self.watch_signaling(handle, room_code, true, move |room| {
+            matches!(room, Room::Active(state) if state.room_code == code && state.is_host())
+        });
+    }
+
+    /// Dial the peers announced on the room's signaling channel while
+    /// `watching` holds: the host and others in the room if we're joining, or
+    /// peers still joining (`joiners`) if we're the host
+    pub(super) fn watch_signaling(
+        &self,
+        handle: NetworkHandle,
+        room_code: String,
+        joiners: bool,
+        watching: impl Fn(&Room) -> bool + Send + 'static,
+    ) {
+        let mut messages = self.signaling.read().unwrap().subscribe_room(&room_code);
+        let room = Arc::clone(&self.room);
+        let local_peer_id = self.local_peer_id.read().unwrap().clone().unwrap_or_default();
+
+        self.tasks.spawn(&self.runtime, async move {
+            loop {
+                if !watching(&room.read().unwrap()) {
+                    debug!("Stopping signaling watch for room {}", room_code);
+                    break;
+                }
+                let msg = match tokio::time::timeout(SIGNALING_ROOM_CHECK_INTERVAL, messages.recv()).await {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => break,
+                    Err(_) => continue,
+                };
+                // Skip our own messages, and the ones we aren't after
+                if msg.peer_id == local_peer_id || msg.joining != joiners {
+                    continue;
+                }
+
+                let role = if joiners { "joiner" } else { "host" };
+                info!("Found {} {} with {} addresses via signaling", role, msg.peer_id, msg.addresses.len());
+                for addr in &msg.addresses {
+                    info!("Dialing {} address from signaling: {}", role, addr);
+                    if let Err(e) = handle.dial_peer(addr) {
+                        warn!("Failed to dial {}: {}", addr, e);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// The host broadcast loop, started when creating a room or handed control
+/// of one, and stopped when leaving it (it also stops once we're no longer host)
+#[derive(Clone)]
+pub(super) struct HostLoop {
+    runtime: Handle,
+    tasks: Arc<TaskSet>,
+    /// Handle for cancelling the running loop
+    cancel: Arc<RwLock<Option<tokio::sync::oneshot::Sender<()>>>>,
+    cider: Arc<RwLock<CiderClient>>,
+    room: Arc<RwLock<Room>>,
+    room_actor: RoomActor,
+    network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+    callback: Arc<RwLock<Option<Arc<dyn SessionCallback>>>>,
+    last_track_id: Arc<RwLock<Option<String>>>,
+    latency_tracker: SharedLatencyTracker,
+    heartbeat_interval: Duration,
+    sync_delay_ms: u64,
+    background: tokio::sync::watch::Receiver<bool>,
+}
+
+impl HostLoop {
+    /// Start the host broadcast loop (follows Cider and broadcasts to listeners)
+    ///
+    /// Track and play/pause changes come from Cider's event stream and are
+    /// broadcast as soon as they happen. Without the stream (older Cider, or
+    /// while it reconnects) the loop polls the REST API instead. Seeks are
+    /// told from playback running on by comparing positions with what the
+    /// last heartbeat predicted; while nothing changes, heartbeats go out only
+    /// every `HOST_KEEPALIVE_INTERVAL`.
+    pub(super) fn start(&self) {
+        // Stop any existing loop first
+        self.stop();
+
+        let (cancel_tx, mut cancel_rx) = tokio::sync::oneshot::channel();
+
+        // Store cancel sender
+        {
+            let mut cancel = self.cancel.write().unwrap();
+            *cancel = Some(cancel_tx);
+        }
+
+        let HostLoop {
+            cider,
+            room,
+            room_actor,
+            network_handle,
+            callback,
+            last_track_id,
+            latency_tracker,
+            heartbeat_interval,
+            sync_delay_ms,
+            mut background,
+            ..
+        } = self.clone();
+
+        self.tasks.spawn(&self.runtime, async move {
+            info!("Host broadcast loop started");
+
+            let mut events: Option<CiderEventStream> = None;
+            let mut last_connect_attempt: Option<Instant> = None;
+            // None until read from Cider (events only carry changes)
+            let mut playback: Option<HostPlayback> = None;
+            // Last playback sent to listeners, and when
+            let mut last_sent: Option<(HostPlayback, tokio::time::Instant)> = None;
+            let mut in_background = *background.borrow_and_update();
+            let mut heartbeat = tokio::time::interval(host_heartbeat_interval(heartbeat_interval, in_background));
+            // Listeners' latency, for the host's view of who has a shaky connection
+            let mut ping = tokio::time::interval(PING_INTERVAL);
+
+            loop {
+                let cider_client = cider.read().unwrap().clone();
+
+                tokio::select! {
+                    _ = &mut cancel_rx => {
+                        info!("Host broadcast loop cancelled");
+                        break;
+                    }
+                    event = next_playback_event(&mut events) => {
+                        let Some(event) = event else {
+                            warn!("Lost Cider event stream, polling instead");
+                            events = None;
+                            playback = None;
+                            continue;
+                        };
+                        let Some(current) = playback.as_mut() else {
+                            continue;
+                        };
+                        match event {
+                            PlaybackEvent::ItemChanged(np) => {
+                                let np = match np {
+                                    Some(np) => Some(*np),
+                                    None => {
+                                        cider_client.invalidate_playback_cache();
+                                        cider_client.now_playing().await.ok().flatten()
+                                    }
+                                };
+                                *current = HostPlayback::new(np.as_ref(), current.is_playing);
+                            }
+                            PlaybackEvent::StateChanged { is_playing } => {
+                                current.set_position(current.position_ms(), is_playing);
+                            }
+                            PlaybackEvent::TimeChanged { position_ms, is_playing } => {
+                                // Sent on only if it's a seek, below
+                                current.set_position(position_ms, is_playing);
+                            }
+                        }
+                    }
+                    Ok(()) = background.changed() => {
+                        in_background = *background.borrow_and_update();
+                        heartbeat = tokio::time::interval(host_heartbeat_interval(heartbeat_interval, in_background));
+                        continue;
+                    }
+                    _ = ping.tick(), if !in_background => {
+                        let listeners: Vec<String> = room
+                            .read()
+                            .unwrap()
+                            .state()
+                            .map(|s| s.participants.keys().filter(|p| **p != s.local_peer_id).cloned().collect())
+                            .unwrap_or_default();
+                        let timestamp = latency_tracker.write().unwrap().create_ping();
+                        if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                            for peer_id in &listeners {
+                                let _ = handle.ping(peer_id, timestamp);
+                            }
+                        }
+                        continue;
+                    }
+                    _ = heartbeat.tick() => {
+                        if events.is_none()
+                            && last_connect_attempt.is_none_or(|t| t.elapsed() >= EVENT_STREAM_RETRY_INTERVAL)
+                        {
+                            last_connect_attempt = Some(Instant::now());
+                            match CiderEventStream::connect(&cider_client).await {
+                                Ok(stream) => {
+                                    info!("Following Cider playback events");
+                                    events = Some(stream);
+                                    playback = None;
+                                }
+                                Err(e) => debug!("Cider event stream unavailable, polling: {}", e),
+                            }
+                        }
+
+                        if events.is_none() || playback.is_none() {
+                            match poll_host_playback(&cider_client).await {
+                                Some(polled) => playback = Some(polled),
+                                None => {
+                                    // Cider error - skip this cycle but don't stop heartbeats
+                                    debug!("Failed to poll Cider playback, skipping heartbeat");
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Check if we're still the host
+                let is_host = {
+                    let r = room.read().unwrap();
+                    r.state().map(|s| s.is_host()).unwrap_or(false)
+                };
+
+                if !is_host {
+                    debug!("No longer host, stopping broadcast loop");
+                    break;
+                }
+
+                // Only changes go out right away; otherwise a keepalive now and then
+                let Some(current) = &playback else {
+                    continue;
+                };
+                let due = match &last_sent {
+                    Some((sent, sent_at)) => current.differs_from(sent) || sent_at.elapsed() >= HOST_KEEPALIVE_INTERVAL,
+                    None => true,
+                };
+                if due {
+                    broadcast_host_playback(
+                        current,
+                        &room_actor,
+                        &network_handle,
+                        &callback,
+                        &last_track_id,
+                        sync_delay_ms,
+                        current_time_ms(),
+                    );
+                    last_sent = Some((current.clone(), tokio::time::Instant::now()));
+                }
+            }
+
+            info!("Host broadcast loop ended");
+        });
+    }
+
+    /// Stop the loop, if running
+    pub(super) fn stop(&self) {
+        if let Some(tx) = self.cancel.write().unwrap().take() {
+            let _ = tx.send(());
+        }
+    }
+
+    /// Start broadcasting as the room's new host, from the playback we were
+    /// handed (see `handle_transfer_host`)
+    ///
+    /// Listeners are already playing its track, so it's taken as broadcast:
+    /// a TrackChange would have them load it again, from the start.
+    pub(super) fn take_over(&self, track_id: Option<String>) {
+        *self.last_track_id.write().unwrap() = track_id;
+        self.start();
+    }
+}
+
+/// Cider playback as last seen by the host broadcast loop
+#[derive(Clone)]
+pub(super) struct HostPlayback {
+    track_id: Option<String>,
+    track: Option<crate::sync::TrackInfo>,
+    is_playing: bool,
+    position_ms: u64,
+    /// When `position_ms` was read (on tokio's clock, which simulations pause)
+    read_at: tokio::time::Instant,
+}
+
+impl HostPlayback {
+    fn new(np: Option<&NowPlaying>, is_playing: bool) -> Self {
+        let track = np.map(|np| crate::sync::TrackInfo {
+            song_id: np.song_id().map(|s| s.to_string()).unwrap_or_default(),
+            name: np.name.clone(),
+            artist: np.artist_name.clone(),
+            album: np.album_name.clone(),
+            artwork_url: np.artwork_url(600),
+            duration_ms: np.duration_in_millis,
+            isrc: np.isrc.clone(),
+            explicit: np.is_explicit(),
+        }
+        .within_limits());
+        Self {
+            track_id: np.and_then(|np| np.song_id()).map(|s| s.to_string()),
+            track,
+            is_playing,
+            position_ms: np.map(|np| np.current_position_ms()).unwrap_or(0),
+            read_at: tokio::time::Instant::now(),
+        }
+    }
+
+    fn set_position(&mut self, position_ms: u64, is_playing: bool) {
+        self.position_ms = position_ms;
+        self.is_playing = is_playing;
+        self.read_at = tokio::time::Instant::now();
+    }
+
+    /// Current position, advanced by the time since it was read while playing
+    fn position_ms(&self) -> u64 {
+        if self.is_playing {
+            self.position_ms + self.read_at.elapsed().as_millis() as u64
+        } else {
+            self.position_ms
+        }
+    }
+
+    /// Whether listeners need to hear about this playback, having been sent
+    /// `sent`: another track, play/pause, or a position off from where `sent`
+    /// would be by now (a seek, or Cider stalling)
+    fn differs_from(&self, sent: &HostPlayback) -> bool {
+        self.track_id != sent.track_id
+            || self.is_playing != sent.is_playing
+            || self.position_ms().abs_diff(sent.position_ms()) > HOST_POSITION_JUMP_MS
+    }
+
+    /// Our playback as of our `now_ms`, for the room
+    fn room_playback(&self, now_ms: u64) -> PlaybackInfo {
+        PlaybackInfo {
+            is_playing: self.is_playing,
+            position_ms: self.position_ms(),
+            timestamp_ms: now_ms,
+        }
+    }
+}
+
+/// Read the host's playback from Cider's REST API
+pub(super) async fn poll_host_playback<C: CiderApi>(cider: &C) -> Option<HostPlayback> {
+    match tokio::join!(cider.now_playing(), cider.is_playing()) {
+        (Ok(np), Ok(is_playing)) => Some(HostPlayback::new(np.as_ref(), is_playing)),
+        _ => None,
+    }
+}
+
+/// Next event from Cider's event stream (never resolves without a stream)
+async fn next_playback_event(events: &mut Option<CiderEventStream>) -> Option<PlaybackEvent> {
+    match events {
+        Some(stream) => stream.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Broadcast the host's playback, stamped with our `now_ms`: a track change
+/// if the track differs from the last one, then a heartbeat
+///
+/// The room keeps our own position; listeners are sent one `delay_ms` ahead
+/// of it while playing (see `SessionConfig::host_sync_delay_ms`).
+pub(super) fn broadcast_host_playback(
+    current: &HostPlayback,
+    room: &RoomActor,
+    network_handle: &RwLock<Option<NetworkHandle>>,
+    callback: &RwLock<Option<Arc<dyn SessionCallback>>>,
+    last_track_id: &RwLock<Option<String>>,
+    delay_ms: u64,
+    now_ms: u64,
+) {
+    let playback = current.room_playback(now_ms);
+    let sent = playback_as_sent(&playback, delay_ms);
+
+    // Check if track changed
+    let track_changed = {
+        let last = last_track_id.read().unwrap();
+        *last != current.track_id
+    };
+
+    if track_changed {
+        // Update last track ID
+        {
+            let mut last = last_track_id.write().unwrap();
+            *last = current.track_id.clone();
+        }
+
+        // Update room state
+        let (track, room_playback) = (current.track.clone(), playback.clone());
+        room.send(move |room| {
+            if let Some(state) = room.state_mut() {
+                state.update_track(track);
+                state.update_playback(room_playback);
+            }
+        });
+
+        // Broadcast track change (only if there's a track)
+        if let Some(track) = &current.track {
+            if let Some(handle) = network_handle.read().unwrap().as_ref() {
+                let msg = SyncMessage::TrackChange {
+                    track: track.clone(),
+                    position_ms: sent.position_ms,
+                    timestamp_ms: sent.timestamp_ms,
+                };
+                let _ = handle.broadcast(msg);
+            }
+
+            // Notify callback
+            if let Some(cb) = callback.read().unwrap().as_ref() {
+                cb.on_track_changed(Some(TrackInfo::from(track.clone())));
+            }
+
+            debug!("Broadcasted track change: {}", track.name);
+        } else {
+            // Track cleared - notify callback
+            if let Some(cb) = callback.read().unwrap().as_ref() {
+                cb.on_track_changed(None);
+            }
+            debug!("Track cleared");
+        }
+    }
+
+    // Always send heartbeat (keeps clients alive even when idle), from the
+    // actor so it hashes the room with any change queued before it
+    let handle = network_handle.read().unwrap().clone();
+    let track_id = current.track_id.clone();
+    room.send(move |room| {
+        if let Some(state) = room.state_mut() {
+            if let Some(handle) = handle {
+                let msg = SyncMessage::Heartbeat {
+                    track_id,
+                    playback: sent,
+                    room_hash: Some(state.room_hash()),
+                };
+                let _ = handle.broadcast(msg);
+            }
+            state.update_playback(playback);
+        }
+    });
+}
+
+/// Save `peer_id` as a friend (updating their name if they're one already)
+fn remember_friend(
+    friends: &RwLock<Vec<Friend>>,
+    storage: &RwLock<Option<Arc<dyn SecureStorage>>>,
+    peer_id: String,
+    display_name: String,
+) -> Friend {
+    let mut friends = friends.write().unwrap();
+    let friend = match friends.iter_mut().find(|f| f.peer_id == peer_id) {
+        Some(friend) => {
+            friend.display_name = display_name;
+            friend.clone()
+        }
+        None => {
+            let friend = Friend {
+                peer_id,
+                display_name,
+                hosting_room: None,
+            };
+            friends.push(friend.clone());
+            friend
+        }
+    };
+    info!("Saved {} ({}) as a friend", friend.display_name, friend.peer_id);
+    if let Some(storage) = storage.read().unwrap().as_deref() {
+        save_friends(storage, &friends);
+    }
+    friend
+}
+
+/// Check our friends' presence on signaling, updating the rooms they're hosting
+///
+/// Returns the friends who started hosting a room since the last check.
+async fn check_presence(signaling: &crate::network::SignalingClient, friends: &RwLock<Vec<Friend>>) -> Vec<Friend> {
+    let peer_ids: Vec<String> = friends.read().unwrap().iter().map(|f| f.peer_id.clone()).collect();
+    let polls = peer_ids.iter().map(|peer_id| async move {
+        (peer_id, tokio::time::timeout(PRESENCE_TIMEOUT, signaling.poll_presence(peer_id)).await)
+    });
+    let results = futures::future::join_all(polls).await;
+
+    let mut friends = friends.write().unwrap();
+    let mut started = Vec::new();
+    for (peer_id, result) in results {
+        let Some(friend) = friends.iter_mut().find(|f| &f.peer_id == peer_id) else {
+            continue;
+        };
+        match result {
+            Ok(Ok(hosting_room)) => {
+                let new_room = hosting_room.is_some() && hosting_room != friend.hosting_room;
+                friend.hosting_room = hosting_room;
+                if new_room {
+                    started.push(friend.clone());
+                }
+            }
+            Ok(Err(e)) => debug!("Couldn't check whether {} is hosting: {}", friend.display_name, e),
+            Err(_) => debug!("Timed out checking whether {} is hosting", friend.display_name),
+        }
+    }
+    started
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::signaling::{SignalingBackend, SignalingMessage};
+    use crate::network::{NetworkCommand, NetworkEvent};
+    use super::super::handlers::handle_network_event;
+    use futures::future::BoxFuture;
+    use std::collections::HashSet;
+
+    /// Signaling where the first `taken` room codes polled are in use
+    struct TakenCodes {
+        taken: usize,
+        polled: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl TakenCodes {
+        fn install(session: &Session, taken: usize) -> Arc<Self> {
+            let backend = Arc::new(Self { taken, polled: Default::default() });
+            *session.signaling.write().unwrap() = crate::network::SignalingClient::with_backend(backend.clone());
+            backend
+        }
+    }
+
+    impl SignalingBackend for TakenCodes {
+        fn base_url(&self) -> &str {
+            "test"
+        }
+
+        fn publish<'a>(&'a self, _: &'a str, _: &'a SignalingMessage) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn poll<'a>(&'a self, topic: &'a str) -> BoxFuture<'a, Result<Vec<SignalingMessage>, String>> {
+            let mut polled = self.polled.lock().unwrap();
+            polled.push(topic.to_string());
+            let host = SignalingMessage {
+                peer_id: "host".to_string(),
+                addresses: Vec::new(),
+                room_code: topic.to_string(),
+                joining: false,
+                proof: None,
+            };
+            let messages = if polled.len() <= self.taken { vec![host] } else { Vec::new() };
+            Box::pin(async move { Ok(messages) })
+        }
+    }
+
+    #[test]
+    fn test_async_variants() {
+        // Nothing listens on port 1
+        let config = SessionConfig {
+            cider_port: Some(1),
+            ..SessionConfig::default()
+        };
+        let session = Arc::new(Session::new_with_config(config));
+
+        // Polled outside the session's runtime, as the foreign executor does
+        let playback = futures::executor::block_on(Arc::clone(&session).get_playback_state_async());
+        assert!(playback.is_err());
+        let play = futures::executor::block_on(Arc::clone(&session).sync_play_async());
+        assert!(matches!(play, Err(CoreError::NotInRoom)));
+    }
+
+    #[cfg(feature = "bridge")]
+    #[test]
+    fn test_bridge() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        /// Send `request` to the bridge at `port`; returns the raw response
+        async fn send(port: u16, request: &str) -> String {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        }
+
+        let session = Arc::new(Session::new());
+        session.use_event_queue();
+        let port = Arc::clone(&session).start_bridge(0).unwrap();
+        assert_eq!(session.bridge_port(), Some(port));
+        assert_eq!(Arc::clone(&session).start_bridge(0).unwrap(), port);
+        let token = session.bridge_token().unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+        runtime.block_on(async {
+            let auth = format!("Authorization: Bearer {}\r\n", token);
+            let room = send(port, &format!("GET /api/room HTTP/1.1\r\nHost: 127.0.0.1\r\n{}\r\n", auth)).await;
+            assert!(room.starts_with("HTTP/1.1 200 OK"), "{}", room);
+            assert!(room.ends_with("\r\n\r\nnull"), "{}", room);
+
+            let play = send(port, &format!("POST /api/playback/play HTTP/1.1\r\nHost: 127.0.0.1\r\n{}\r\n", auth)).await;
+            assert!(play.starts_with("HTTP/1.1 409 Conflict"), "{}", play);
+            assert!(play.contains(r#""error":"not_in_room""#), "{}", play);
+
+            let page = send(port, "GET /api/room HTTP/1.1\r\nHost: 127.0.0.1\r\nOrigin: https://example.com\r\n\r\n").await;
+            assert!(page.starts_with("HTTP/1.1 403 Forbidden"), "{}", page);
+            let no_token = send(port, "GET /api/room HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n").await;
+            assert!(no_token.starts_with("HTTP/1.1 401 Unauthorized"), "{}", no_token);
+
+            // The stream overlay, as OBS loads it
+            let overlay = send(port, &format!("GET /overlay?token={} HTTP/1.1\r\nHost: localhost\r\n\r\n", token)).await;
+            assert!(overlay.contains("Content-Type: text/html"), "{}", overlay);
+            assert!(overlay.contains("/api/overlay"), "{}", overlay);
+            let overlay = send(port, &format!("GET /api/overlay?token={} HTTP/1.1\r\nHost: localhost\r\n\r\n", token)).await;
+            assert!(overlay.contains(r#""room_code":null"#), "{}", overlay);
+
+            // Events come over the WebSocket as they happen
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            let handshake = format!("GET /api/events?token={} HTTP/1.1\r\nHost: 127.0.0.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n", token);
+            stream.write_all(handshake.as_bytes()).await.unwrap();
+            let mut response = Vec::new();
+            while !response.ends_with(b"\r\n\r\n") {
+                response.push(stream.read_u8().await.unwrap());
+            }
+            assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 101"));
+
+            session.callback.read().unwrap().as_ref().unwrap().on_connected();
+            let mut head = [0u8; 2];
+            stream.read_exact(&mut head).await.unwrap();
+            let mut payload = vec![0u8; head[1] as usize];
+            stream.read_exact(&mut payload).await.unwrap();
+            assert_eq!(head[0], 0x81);
+            assert_eq!(payload, br#"{"type":"connected"}"#);
+        });
+
+        // ...and to the app as well, which gets them alone once the bridge stops
+        assert!(matches!(session.next_event(0), Some(SessionEvent::Connected)));
+        session.stop_bridge();
+        assert_eq!(session.bridge_port(), None);
+        assert_eq!(session.bridge_token(), None);
+        session.callback.read().unwrap().as_ref().unwrap().on_disconnected();
+        assert!(matches!(session.next_event(0), Some(SessionEvent::Disconnected)));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let config = SessionConfig {
+            cider_port: Some(1),
+            ..SessionConfig::default()
+        };
+        let session = Session::new_with_config(config);
+        assert!(session.get_playback_state().is_err());
+
+        let diagnostics = session.get_diagnostics();
+        assert_eq!(diagnostics.cider_port, 1);
+        assert!(diagnostics.room.is_none());
+        assert!(diagnostics.network.is_none());
+        assert_eq!(diagnostics.recent_errors.len(), 1);
+        assert!(diagnostics.recent_errors[0].message.starts_with("get_playback_state failed"));
+    }
+
+    #[test]
+    fn test_background_heartbeat_interval() {
+        let interval = Duration::from_millis(1000);
+        assert_eq!(host_heartbeat_interval(interval, false), interval);
+        assert_eq!(host_heartbeat_interval(interval, true), BACKGROUND_HEARTBEAT_INTERVAL);
+        // Already slower than that
+        let slow = Duration::from_secs(10);
+        assert_eq!(host_heartbeat_interval(slow, true), slow);
+    }
+
+    #[test]
+    fn test_drift_threshold_floor() {
+        let session = |drift_threshold_ms| {
+            Session::new_with_config(SessionConfig { drift_threshold_ms, ..SessionConfig::default() })
+        };
+        assert_eq!(session(0).sync_settings.drift_threshold_ms, MIN_DRIFT_THRESHOLD_MS);
+        assert_eq!(session(1_000).sync_settings.drift_threshold_ms, 1_000);
+    }
+
+    #[test]
+    fn test_cider_address() {
+        let address = |host: &str, port: Option<u16>| Some((host.to_string(), port));
+        assert_eq!(parse_cider_address("192.168.1.20"), address("192.168.1.20", None));
+        assert_eq!(parse_cider_address(" desktop.local:10767 "), address("desktop.local", Some(10767)));
+        assert_eq!(parse_cider_address("http://desktop.local:10767/"), address("desktop.local", Some(10767)));
+        assert_eq!(parse_cider_address("https://cider.example.com"), address("cider.example.com", None));
+        assert_eq!(parse_cider_address("[::1]:10767"), address("::1", Some(10767)));
+        assert_eq!(parse_cider_address(""), None);
+        assert_eq!(parse_cider_address("http://"), None);
+
+        let session = Session::new();
+        session.set_cider_host(Some("http://192.168.1.20:10800".to_string()));
+        let cider = session.cider.read().unwrap().clone();
+        assert_eq!((cider.host(), cider.port()), ("192.168.1.20", 10800));
+        session.set_cider_host(Some("[::1]:10767".to_string()));
+        assert_eq!(session.cider.read().unwrap().host(), "::1");
+        session.set_cider_host(None);
+        assert_eq!(session.cider.read().unwrap().host(), DEFAULT_CIDER_HOST);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let session = Session::new_with_config(SessionConfig {
+            lan_only: true,
+            ..SessionConfig::default()
+        });
+        session.create_room("Host".to_string()).unwrap();
+        assert!(session.is_in_room());
+        // Hosting: there's no connection to a host to describe
+        let info = session.get_network_info().unwrap();
+        assert_eq!(info.host_connection, None);
+
+        session.shutdown();
+        assert!(!session.is_in_room());
+        assert!(session.get_diagnostics().network.is_none());
+        assert!(session.get_network_info().is_none());
+        assert!(matches!(session.create_room("Host".to_string()), Err(CoreError::ShutDown)));
+        assert!(matches!(session.get_playback_state(), Err(CoreError::ShutDown)));
+        assert!(matches!(session.rotate_room_code(), Err(CoreError::ShutDown)));
+
+        // Again (and on drop) it does nothing
+        session.shutdown();
+    }
+
+    #[test]
+    fn test_health_check() {
+        let session = Session::new_with_config(SessionConfig {
+            lan_only: true,
+            // Nothing listens there
+            cider_port: Some(1),
+            ..SessionConfig::default()
+        });
+        session.create_room("Host".to_string()).unwrap();
+
+        let health = session.health_check().unwrap();
+        assert_eq!(health.cider.error_code, Some(ErrorCode::CiderNotReachable));
+        assert_eq!(health.cider_token.status, HealthStatus::Skipped);
+        assert_eq!(health.network.status, HealthStatus::Ok);
+        assert_eq!(health.relay.status, HealthStatus::Skipped);
+        assert_eq!(health.room.status, HealthStatus::Ok);
+        assert_eq!(health.overall, HealthStatus::Failed);
+
+        session.shutdown();
+        assert!(matches!(session.health_check(), Err(CoreError::ShutDown)));
+    }
+
+    #[test]
+    fn test_replace_session() {
+        let config = SessionConfig {
+            lan_only: true,
+            ..SessionConfig::default()
+        };
+        let old = Session::new_with_config(config.clone());
+        old.create_room("Host".to_string()).unwrap();
+        let old_network = old.network_handle.read().unwrap().clone().unwrap();
+
+        // Dropping the old session stops its network, but not the shared runtime
+        drop(old);
+        assert!(old_network.broadcast(SyncMessage::TrackUnavailable { song_id: String::new() }).is_err());
+        let new = Session::new_with_config(config);
+        new.create_room("Host".to_string()).unwrap();
+        assert!(new.get_network_info().is_some());
+    }
+
+    #[test]
+    fn test_peer_latencies_of_participants() {
+        let session = Session::new();
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.add_participant(crate::sync::Participant {
+            peer_id: "listener".to_string(),
+            display_name: "Listener".to_string(),
+            is_host: false,
+            storefront: None,
+            capabilities: Capabilities::default(),
+            profile: Profile::default(),
+            client: ClientInfo::default(),
+            status: ParticipantStatus::default(),
+        });
+        *session.room.write().unwrap() = Room::Active(state);
+        {
+            let mut tracker = session.latency_tracker.write().unwrap();
+            let ts = tracker.create_ping();
+            tracker.handle_pong("listener", ts, ts);
+            // A peer that has since left
+            tracker.handle_pong("gone", ts, ts);
+        }
+
+        let peers: Vec<String> = session.get_peer_latencies().into_iter().map(|l| l.peer_id).collect();
+        assert_eq!(peers, vec!["listener"]);
+
+        let history = session.get_latency_history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].peer_id, "listener");
+        assert_eq!(history[0].samples.len(), 1);
+        let first = &history[0].histogram[0];
+        assert_eq!(*first, LatencyBucket { min_ms: 0, max_ms: Some(10), count: 1 });
+        assert_eq!(history[0].histogram.last().unwrap().max_ms, None);
+    }
+
+    #[test]
+    fn test_moderation_host_only() {
+        let session = Session::new();
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        for peer_id in ["kicked", "banned", "muted"] {
+            state.add_participant(crate::sync::Participant {
+                peer_id: peer_id.to_string(),
+                display_name: peer_id.to_string(),
+                is_host: false,
+                storefront: None,
+                capabilities: Capabilities::default(),
+                profile: Profile::default(),
+                client: ClientInfo::default(),
+                status: ParticipantStatus::default(),
+            });
+        }
+        *session.room.write().unwrap() = Room::Active(state);
+
+        session.kick_participant("kicked".to_string()).unwrap();
+        session.ban_participant("banned".to_string()).unwrap();
+        session.mute_suggestions_from("muted".to_string(), true).unwrap();
+        session.set_room_locked(true).unwrap();
+        assert!(matches!(
+            session.kick_participant("me".to_string()),
+            Err(CoreError::NetworkError { code: ErrorCode::PeerNotFound, .. })
+        ));
+
+        let room_state = session.get_room_state().unwrap();
+        assert_eq!(room_state.participants.len(), 2);
+        assert!(room_state.locked);
+        assert_eq!(room_state.suggestions_muted, vec!["muted"]);
+        {
+            let room = session.room.read().unwrap();
+            let state = room.state().unwrap();
+            assert_eq!(state.refusal_for("banned"), Some(RemovalReason::Banned));
+            assert_eq!(state.refusal_for("kicked"), Some(RemovalReason::RoomLocked));
+            assert!(!state.accepts_suggestions_from("muted"));
+        }
+
+        // Listeners can't moderate
+        session.room.write().unwrap().state_mut().unwrap().host_peer_id = "muted".to_string();
+        assert!(matches!(session.set_room_locked(false), Err(CoreError::NotHost)));
+    }
+
+    #[test]
+    fn test_away_and_idle() {
+        let session = Session::new();
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.host_peer_id = "host".to_string();
+        *session.room.write().unwrap() = Room::Active(state);
+        let status = || session.room.read().unwrap().state().unwrap().participants["me"].status;
+
+        session.report_idle_time(60).unwrap();
+        assert_eq!(status(), ParticipantStatus::Active);
+        session.report_idle_time(IDLE_AFTER.as_secs()).unwrap();
+        assert_eq!(status(), ParticipantStatus::Idle);
+
+        // Away until said otherwise, input or not
+        session.set_away(true).unwrap();
+        session.report_idle_time(0).unwrap();
+        assert_eq!(status(), ParticipantStatus::Away);
+        session.set_away(false).unwrap();
+        assert_eq!(status(), ParticipantStatus::Active);
+    }
+
+    #[test]
+    fn test_room_code_claimed() {
+        let session = Session::new();
+        let state = InternalRoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        session
+            .room_actor
+            .call(move |room| {
+                *room = Room::Active(state);
+                Ok(())
+            })
+            .unwrap();
+        let polled_until = |signaling: &TakenCodes, count: usize| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while signaling.polled.lock().unwrap().len() < count && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            signaling.polled.lock().unwrap().clone()
+        };
+
+        // The room moved off the two codes that were taken, onto the third
+        let signaling = TakenCodes::install(&session, 2);
+        session.room_codes().claim(RoomCode::parse("ABCD4679").unwrap());
+        let polled = polled_until(&signaling, 3);
+        assert_eq!(polled.len(), 3);
+        assert_eq!(polled.iter().collect::<HashSet<_>>().len(), 3);
+        std::thread::sleep(Duration::from_millis(100));
+        let code = session.get_room_state().unwrap().room_code;
+        assert!(polled[2].ends_with(&code.to_lowercase()));
+
+        // Nothing is free: the last one is as good as any
+        let signaling = TakenCodes::install(&session, usize::MAX);
+        session.room_codes().claim(RoomCode::parse(&code).unwrap());
+        let polled = polled_until(&signaling, MAX_ROOM_CODE_ATTEMPTS - 1);
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(polled.len(), MAX_ROOM_CODE_ATTEMPTS - 1);
+        assert_eq!(signaling.polled.lock().unwrap().len(), MAX_ROOM_CODE_ATTEMPTS - 1);
+    }
+
+    #[test]
+    fn test_rotate_room_code() {
+        let session = Session::new();
+        let state = InternalRoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        *session.room.write().unwrap() = Room::Active(state);
+        TakenCodes::install(&session, 0);
+
+        let code = session.rotate_room_code().unwrap();
+        let room_code = session.get_room_state().unwrap().room_code;
+        assert_ne!(room_code, "ABCD4679");
+        assert_eq!(RoomCode::parse(&code).unwrap().as_str(), room_code);
+
+        // Only the host picks the code
+        session.room.write().unwrap().state_mut().unwrap().host_peer_id = "host".to_string();
+        assert!(matches!(session.rotate_room_code(), Err(CoreError::NotHost)));
+        assert_eq!(session.get_room_state().unwrap().room_code, room_code);
+    }
+
+    #[test]
+    fn test_export_room_recap() {
+        let session = Session::new();
+        assert!(matches!(session.export_room_recap(RecapFormat::Json), Err(CoreError::NotInRoom)));
+
+        let state = InternalRoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        session
+            .room_actor
+            .call(move |room| {
+                *room = Room::Active(state);
+                Ok(())
+            })
+            .unwrap();
+        session
+            .room_actor
+            .call(|room| {
+                *room = Room::None;
+                Ok(())
+            })
+            .unwrap();
+
+        // Still there once the room is over
+        let json: serde_json::Value =
+            serde_json::from_slice(&session.export_room_recap(RecapFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["room_code"], "ABCD4679");
+        assert_eq!(json["participants"][0]["peer_id"], "me");
+        assert!(json["ended_at_ms"].is_u64());
+        let csv = session.export_room_recap(RecapFormat::Csv).unwrap();
+        assert!(csv.starts_with(b"room_code,local_peer_id,started_at_ms,ended_at_ms\r\nABCD4679,me,"));
+    }
+
+    /// Signaling where `host` announced hosting `room_code` on every channel,
+    /// and `impostor` claimed to (unsigned)
+    struct Hosting {
+        host: Keypair,
+        impostor: String,
+        room_code: String,
+    }
+
+    impl SignalingBackend for Hosting {
+        fn base_url(&self) -> &str {
+            "test"
+        }
+
+        fn publish<'a>(&'a self, _: &'a str, _: &'a SignalingMessage) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn poll<'a>(&'a self, _: &'a str) -> BoxFuture<'a, Result<Vec<SignalingMessage>, String>> {
+            let forged = SignalingMessage {
+                peer_id: self.impostor.clone(),
+                addresses: Vec::new(),
+                room_code: self.room_code.clone(),
+                joining: false,
+                proof: None,
+            };
+            let presence = SignalingMessage::presence(&self.host, &self.room_code);
+            Box::pin(async move { Ok(vec![presence, forged]) })
+        }
+    }
+
+    #[test]
+    fn test_friends() {
+        let session = Arc::new(Session::new());
+        let sam = Keypair::generate_ed25519();
+        let (sam_id, alex_id) = (sam.public().to_peer_id().to_string(), libp2p::PeerId::random().to_string());
+        let mut state = InternalRoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        for (peer_id, display_name) in [(&sam_id, "Sam"), (&alex_id, "Alex")] {
+            state.add_participant(crate::sync::Participant {
+                peer_id: peer_id.to_string(),
+                display_name: display_name.to_string(),
+                is_host: false,
+                storefront: None,
+                capabilities: Capabilities::default(),
+                profile: Profile::default(),
+                client: ClientInfo::default(),
+                status: ParticipantStatus::default(),
+            });
+        }
+        session
+            .room_actor
+            .call(move |room| {
+                *room = Room::Active(state);
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(session.add_friend(sam_id.clone()).unwrap().display_name, "Sam");
+        session.add_friend(alex_id.clone()).unwrap();
+        session.add_friend(sam_id.clone()).unwrap();
+        let missing = session.add_friend("stranger".to_string());
+        assert!(matches!(missing, Err(CoreError::NetworkError { code: ErrorCode::PeerNotFound, .. })));
+        let ours = session.add_friend("me".to_string());
+        assert!(matches!(ours, Err(CoreError::NetworkError { code: ErrorCode::PeerNotFound, .. })));
+
+        let backend = Arc::new(Hosting {
+            host: sam,
+            impostor: alex_id.clone(),
+            room_code: "WXYZ3467".to_string(),
+        });
+        *session.signaling.write().unwrap() = crate::network::SignalingClient::with_backend(backend);
+        session.use_event_queue();
+        session.watch_friends(true).unwrap();
+        assert!(matches!(
+            session.next_event(5000),
+            Some(SessionEvent::FriendStartedRoom { friend, room_code }) if friend.peer_id == sam_id && room_code == "WXYZ3467"
+        ));
+        session.watch_friends(false).unwrap();
+
+        // Only the friend who signed their announcement is hosting
+        let friends = session.runtime.block_on(Arc::clone(&session).refresh_friends()).unwrap();
+        let hosting: Vec<_> = friends.iter().map(|f| (f.peer_id.as_str(), f.hosting_room.as_deref())).collect();
+        assert_eq!(hosting, vec![(sam_id.as_str(), Some("WXYZ3467")), (alex_id.as_str(), None)]);
+        assert_eq!(session.get_friends(), friends);
+        // Only reported once per room
+        let signaling = session.signaling.read().unwrap().clone();
+        assert!(session.runtime.block_on(check_presence(&signaling, &session.friends)).is_empty());
+
+        let not_hosting = session.runtime.block_on(Arc::clone(&session).join_friend(alex_id.clone(), String::new()));
+        assert!(matches!(not_hosting, Err(CoreError::NetworkError { code: ErrorCode::RoomNotFound, .. })));
+        session.remove_friend(alex_id.clone());
+        let not_friend = session.runtime.block_on(Arc::clone(&session).join_friend(alex_id, String::new()));
+        assert!(matches!(not_friend, Err(CoreError::NetworkError { code: ErrorCode::PeerNotFound, .. })));
+    }
+
+    #[test]
+    fn test_handle_uri() {
+        let session = Session::new();
+        let invalid = session.handle_uri("ciderlt://dance/ABCD4679".to_string());
+        assert!(matches!(invalid, Err(CoreError::NetworkError { code: ErrorCode::InvalidLink, .. })));
+        assert!(matches!(session.invite_link(), Err(CoreError::NotInRoom)));
+        let suggest = session.handle_uri("ciderlt://suggest/1440818839".to_string());
+        assert!(matches!(suggest, Err(CoreError::NotInRoom)));
+
+        let state = InternalRoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        *session.room.write().unwrap() = Room::Active(state);
+        let link = session.invite_link().unwrap();
+        assert_eq!(link, "ciderlt://invite/ABCD4679?from=me");
+
+        // Already in the room: nothing to do
+        let handled = session.handle_uri("ciderlt://join/abcd-4679".to_string()).unwrap();
+        assert_eq!(handled, DeepLink::JoinRoom { room_code: "ABCD4679".to_string() });
+        assert!(matches!(session.handle_uri(link).unwrap(), DeepLink::Invite { .. }));
+        std::thread::sleep(INVITE_JOIN_CHECK_INTERVAL);
+        assert!(session.get_friends().is_empty());
+    }
+
+    #[test]
+    fn test_invite_saves_friend_once_joined() {
+        let session = Session::new_with_config(SessionConfig {
+            lan_only: true,
+            ..SessionConfig::default()
+        });
+        session.handle_uri("ciderlt://invite/ABCD4679?from=sam".to_string()).unwrap();
+        assert!(matches!(&*session.room.read().unwrap(), Room::Joining { .. }));
+
+        // The host lets us in, and the one who invited us is there
+        let mut state = InternalRoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "host".to_string(),
+            "Host".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.local_peer_id = "me".to_string();
+        state.add_participant(crate::sync::Participant {
+            peer_id: "sam".to_string(),
+            display_name: "Sam".to_string(),
+            is_host: false,
+            storefront: None,
+            capabilities: Capabilities::default(),
+            profile: Profile::default(),
+            client: ClientInfo::default(),
+            status: ParticipantStatus::default(),
+        });
+        session
+            .room_actor
+            .call(move |room| {
+                *room = Room::Active(state);
+                Ok(())
+            })
+            .unwrap();
+
+        std::thread::sleep(INVITE_JOIN_CHECK_INTERVAL * 3);
+        let friends = session.get_friends();
+        assert_eq!(friends.len(), 1);
+        assert_eq!((friends[0].peer_id.as_str(), friends[0].display_name.as_str()), ("sam", "Sam"));
+    }
+
+    #[test]
+    fn test_room_settings() {
+        let session = Session::new();
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.add_participant(crate::sync::Participant {
+            peer_id: "listener".to_string(),
+            display_name: "Listener".to_string(),
+            is_host: false,
+            storefront: None,
+            capabilities: Capabilities::default(),
+            profile: Profile::default(),
+            client: ClientInfo::default(),
+            status: ParticipantStatus::default(),
+        });
+        *session.room.write().unwrap() = Room::Active(state);
+
+        let settings = RoomSettings {
+            who_can_queue: Permission::Everyone,
+            who_can_skip: Permission::HostOnly,
+            max_participants: Some(2),
+            approval_mode: ApprovalMode::Open,
+            follow_volume: true,
+        };
+        session.update_room_settings(settings.clone()).unwrap();
+        assert_eq!(session.get_room_state().unwrap().settings, settings);
+
+        // Sent to joiners with the room state
+        let room = session.room.read().unwrap();
+        let state = room.state().unwrap();
+        let SyncMessage::RoomState { settings: sent, .. } = room_state_message(state, 0) else {
+            panic!("not a room state");
+        };
+        assert_eq!(sent.max_participants, Some(2));
+        assert_eq!(state.refusal_for("newcomer"), Some(RemovalReason::RoomFull));
+        assert_eq!(state.refusal_for("listener"), None);
+
+        // Never past what listeners take, whatever the settings
+        let mut crowded = state.clone();
+        crowded.settings.max_participants = None;
+        let listener = crowded.participants["listener"].clone();
+        for i in crowded.participants.len()..crate::sync::MAX_ROOM_PARTICIPANTS {
+            crowded.add_participant(crate::sync::Participant { peer_id: format!("listener-{}", i), ..listener.clone() });
+        }
+        assert_eq!(crowded.refusal_for("newcomer"), Some(RemovalReason::RoomFull));
+    }
+
+    #[test]
+    fn test_host_sync_delay() {
+        let session = Session::new();
+        let state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        *session.room.write().unwrap() = Room::Active(state);
+        let (handle, mut commands) = NetworkHandle::detached("me");
+        *session.network_handle.write().unwrap() = Some(handle);
+        let mut broadcast = |current: &HostPlayback| {
+            broadcast_host_playback(
+                current,
+                &session.room_actor,
+                &session.network_handle,
+                &session.callback,
+                &session.last_broadcast_track_id,
+                300,
+                current_time_ms(),
+            );
+            let ours = session.room_actor.call(|room| Ok(room.state().unwrap().playback.clone())).unwrap();
+            let mut sent = None;
+            while let Ok(command) = commands.try_recv() {
+                if let NetworkCommand::Broadcast { message } = command {
+                    if let SyncMessage::Heartbeat { playback, .. } = *message {
+                        sent = Some(playback);
+                    }
+                }
+            }
+            (ours, sent.unwrap())
+        };
+
+        // Listeners are sent a position ahead of ours while playing, which the room keeps...
+        let mut current = HostPlayback::new(None, true);
+        current.set_position(10_000, true);
+        let (ours, sent) = broadcast(&current);
+        assert!((10_000..10_100).contains(&ours.position_ms), "position {}", ours.position_ms);
+        assert_eq!(sent.position_ms, ours.position_ms + 300);
+        let room = session.room.read().unwrap();
+        let SyncMessage::RoomState { playback, .. } = room_state_message(room.state().unwrap(), 300) else {
+            unreachable!()
+        };
+        assert_eq!(playback.position_ms, ours.position_ms + 300);
+        drop(room);
+
+        // ...and exactly ours while paused
+        current.set_position(10_000, false);
+        let (ours, sent) = broadcast(&current);
+        assert_eq!((ours.position_ms, sent.position_ms), (10_000, 10_000));
+    }
+
+    #[tokio::test]
+    async fn test_room_lives_on_without_heartbeats() {
+        let session = Session::new();
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.host_peer_id = "host".to_string();
+        *session.room.write().unwrap() = Room::Active(state);
+        let (handle, mut commands) = NetworkHandle::detached("me");
+        *session.network_handle.write().unwrap() = Some(handle);
+        session.latency_tracker.write().unwrap().set_host("host".to_string());
+
+        session.set_receives(MessageClass::Heartbeat, false).unwrap();
+        assert!(matches!(
+            commands.try_recv().unwrap(),
+            NetworkCommand::SetReceives { class: crate::sync::MessageClass::Heartbeat, receive: false }
+        ));
+
+        // 20s without a heartbeat, the host answering our pings all along
+        let timeout = Duration::from_secs(15);
+        let now = std::time::Instant::now();
+        session.room.write().unwrap().state_mut().unwrap().last_heartbeat = now - Duration::from_secs(20);
+        assert!(session.room.read().unwrap().state().unwrap().is_heartbeat_stale(timeout));
+        for from in ["someone", "host"] {
+            let ping_sent_at_ms = session.latency_tracker.write().unwrap().create_ping();
+            let pong = NetworkEvent::Pong { from: from.to_string(), ping_sent_at_ms, received_at_ms: ping_sent_at_ms };
+            let settings = Arc::new(SyncSettings::new(DEFAULT_DRIFT_THRESHOLD_MS, SyncStatusLevel::Full));
+            handle_network_event(
+                pong,
+                &session.room,
+                &session.callback,
+                &session.cider,
+                &session.network_handle,
+                &session.latency_tracker,
+                &session.seek_calibrator,
+                "me",
+                &settings,
+                &Player::start(),
+                &RoomBroadcaster::new(Arc::clone(&session.room), Arc::clone(&session.network_handle)),
+            )
+            .await;
+
+            // Only the host's answers count
+            let stale = session.room.read().unwrap().state().unwrap().is_heartbeat_stale(timeout);
+            assert_eq!(stale, from != "host");
+        }
+    }
+
+    #[test]
+    fn test_new_host_goes_on_with_track() {
+        let session = Session::new();
+        let mut state = InternalRoomState::new_as_host(
+            "ABC123".to_string(),
+            "me".to_string(),
+            "Me".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        state.host_peer_id = "host".to_string();
+        *session.room.write().unwrap() = Room::Active(state);
+        let (handle, mut commands) = NetworkHandle::detached("me");
+        *session.network_handle.write().unwrap() = Some(handle);
+
+        // Handed control while listening to the old host's track
+        session
+            .room_actor
+            .call(|room| {
+                room.state_mut().unwrap().transfer_host("me");
+                Ok(())
+            })
+            .unwrap();
+        session.host_loop().take_over(Some("1440818839".to_string()));
+
+        // Our first broadcast of it goes on with it, rather than starting it over
+        let mut current = HostPlayback::new(None, true);
+        current.track_id = Some("1440818839".to_string());
+        current.set_position(42_000, true);
+        broadcast_host_playback(
+            &current,
+            &session.room_actor,
+            &session.network_handle,
+            &session.callback,
+            &session.last_broadcast_track_id,
+            0,
+            current_time_ms(),
+        );
+        session.room_actor.call(|_| Ok(())).unwrap();
+        session.host_loop().stop();
+        let mut sent = Vec::new();
+        while let Ok(command) = commands.try_recv() {
+            if let NetworkCommand::Broadcast { message } = command {
+                sent.push(*message);
+            }
+        }
+        assert!(matches!(
+            sent.as_slice(),
+            [SyncMessage::Heartbeat { playback, .. }] if playback.position_ms >= 42_000
+        ));
+    }
+
+    #[test]
+    fn test_host_playback_changes() {
+        let mut sent = HostPlayback::new(None, true);
+        sent.set_position(10_000, true);
+        let mut current = sent.clone();
+        assert!(!current.differs_from(&sent));
+
+        // Cider's position wobbles a little between reads
+        current.set_position(10_400, true);
+        assert!(!current.differs_from(&sent));
+        // Seeks, pauses and other tracks go out right away
+        current.set_position(45_000, true);
+        assert!(current.differs_from(&sent));
+        current.set_position(10_000, false);
+        assert!(current.differs_from(&sent));
+        current = sent.clone();
+        current.track_id = Some("1440818839".to_string());
+        assert!(current.differs_from(&sent));
+    }
+}
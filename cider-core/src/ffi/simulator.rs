@@ -0,0 +1,394 @@
+//! Deterministic network for sync tests
+//!
+//! The harness connects peers over loopback, so latency, loss and clock skew
+//! are whatever the machine gives it. Here the peers' network handles are
+//! detached and the simulator carries their messages itself, over links with
+//! scripted latency, jitter and loss, while each peer reads its own skewed
+//! wall clock. Time is tokio's paused clock and the randomness comes from a
+//! seed, so a scenario like "listener on a 300ms relay with its clock 2s off"
+//! plays out the same way on every run.
+//!
+//! Each listener has one link to the room; messages between two listeners
+//! cross both of theirs. Like the TCP streams gossipsub runs over, a link
+//! delivers in order: a message held up by jitter holds up the ones after it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::cider::mock::MockCider;
+use crate::clock::{Clock, SimulatedClock};
+use crate::latency::{LatencyTracker, SharedLatencyTracker};
+use crate::network::{NetworkCommand, NetworkEvent, NetworkHandle};
+use crate::sync::{new_shared_recorder, Capabilities, ClientInfo, ParticipantStatus, Profile, Room, RoomState};
+
+use super::harness::{handle_events, host_broadcast, run_script, wait_for, DriftSample, HostAction, ROOM_CODE};
+use super::room_actor::RoomActor;
+use super::runtime::TaskSet;
+use super::session::PING_INTERVAL;
+
+/// Where the simulated wall clocks start (before skew)
+const EPOCH_MS: u64 = 1_700_000_000_000;
+
+/// A peer's connection to the room
+#[derive(Debug, Clone, Copy)]
+pub struct Link {
+    pub latency: Duration,
+    /// Up to this much more latency, at random
+    pub jitter: Duration,
+    /// Fraction of messages lost (0.0-1.0)
+    pub loss: f64,
+}
+
+impl Link {
+    /// A link that delivers everything after `latency`
+    pub fn new(latency: Duration) -> Self {
+        Self {
+            latency,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+        }
+    }
+
+    pub fn with_jitter(self, jitter: Duration) -> Self {
+        Self { jitter, ..self }
+    }
+
+    pub fn with_loss(self, loss: f64) -> Self {
+        Self { loss, ..self }
+    }
+
+    /// How long a message takes, or None if it's lost
+    fn delay(&self, rng: &mut StdRng) -> Option<Duration> {
+        if rng.gen_bool(self.loss) {
+            return None;
+        }
+        Some(self.latency + self.jitter.mul_f64(rng.gen_range(0.0..=1.0)))
+    }
+}
+
+/// A listener to simulate
+pub struct SimListener {
+    pub cider: MockCider,
+    pub link: Link,
+    /// How far the listener's wall clock is ahead of the host's (negative if behind)
+    pub clock_skew_ms: i64,
+}
+
+/// A peer as the network sees it
+struct Endpoint {
+    peer_id: String,
+    link: Link,
+    clock: SimulatedClock,
+    events: mpsc::UnboundedSender<NetworkEvent>,
+}
+
+/// Carries messages between the peers
+struct Network {
+    /// The host first
+    endpoints: Vec<Endpoint>,
+    rng: Mutex<StdRng>,
+    /// When the last message from one peer to another arrives
+    last_arrival: Mutex<HashMap<(usize, usize), Instant>>,
+}
+
+impl Network {
+    /// How long a message from `from` takes to reach `to`, or None if it's lost
+    fn delay(&self, from: usize, to: usize) -> Option<Duration> {
+        let mut rng = self.rng.lock().unwrap();
+        let out = self.endpoints[from].link.delay(&mut rng);
+        let other = self.endpoints[to].link.delay(&mut rng);
+        Some(out? + other?)
+    }
+
+    /// Deliver `event` to `to` as if sent by `from` now
+    fn send(&self, from: usize, to: usize, event: NetworkEvent) {
+        let Some(delay) = self.delay(from, to) else {
+            return;
+        };
+        let arrival = {
+            let mut last_arrival = self.last_arrival.lock().unwrap();
+            let last = last_arrival.entry((from, to)).or_insert_with(Instant::now);
+            *last = (*last).max(Instant::now() + delay);
+            *last
+        };
+        let events = self.endpoints[to].events.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(arrival).await;
+            let _ = events.send(event);
+        });
+    }
+
+    /// Answer a latency probe from `from` to `to`, if neither way is lost
+    fn probe(self: &Arc<Self>, from: usize, to: usize, sent_at_ms: u64) {
+        let (Some(there), Some(back)) = (self.delay(from, to), self.delay(to, from)) else {
+            return;
+        };
+        let network = Arc::clone(self);
+        tokio::spawn(async move {
+            tokio::time::sleep(there).await;
+            let target = &network.endpoints[to];
+            let received_at_ms = target.clock.now_ms();
+            tokio::time::sleep(back).await;
+            let _ = network.endpoints[from].events.send(NetworkEvent::Pong {
+                from: target.peer_id.clone(),
+                ping_sent_at_ms: sent_at_ms,
+                received_at_ms,
+            });
+        });
+    }
+
+    /// Carry out the commands of the peer at `from`
+    async fn route(self: Arc<Self>, from: usize, mut commands: mpsc::UnboundedReceiver<NetworkCommand>) {
+        while let Some(command) = commands.recv().await {
+            match command {
+                NetworkCommand::Broadcast { message } => {
+                    let sender = self.endpoints[from].peer_id.clone();
+                    for to in (0..self.endpoints.len()).filter(|to| *to != from) {
+                        let event = NetworkEvent::Message {
+                            from: sender.clone(),
+                            message: (*message).clone(),
+                        };
+                        self.send(from, to, event);
+                    }
+                }
+                NetworkCommand::Ping { peer_id, sent_at_ms } => {
+                    if let Some(to) = self.endpoints.iter().position(|e| e.peer_id == peer_id) {
+                        self.probe(from, to, sent_at_ms);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A host and its listeners on a simulated network
+pub struct Simulation {
+    pub host: MockCider,
+    pub listeners: Vec<MockCider>,
+    rooms: Vec<Arc<RwLock<Room>>>,
+    tasks: TaskSet,
+}
+
+impl Simulation {
+    /// Start a room with `host` as the host's Cider and `listeners`, with
+    /// the network's randomness seeded by `seed`; returns once every
+    /// listener is in. Run in a test with tokio's clock paused.
+    pub async fn start(seed: u64, host: MockCider, listeners: Vec<SimListener>) -> Self {
+        let tasks = TaskSet::new();
+        let clock = SimulatedClock::new(EPOCH_MS, 0);
+
+        let mut peers = vec![("host".to_string(), host.clone(), Link::new(Duration::ZERO), clock)];
+        for (i, listener) in listeners.iter().enumerate() {
+            let clock = clock.skewed(listener.clock_skew_ms);
+            peers.push((format!("listener-{}", i + 1), listener.cider.clone(), listener.link, clock));
+        }
+
+        let mut endpoints = Vec::new();
+        let mut rooms = Vec::new();
+        let mut commands = Vec::new();
+        for (i, (peer_id, cider, link, clock)) in peers.into_iter().enumerate() {
+            let room = if i == 0 {
+                Room::Active(RoomState::new_as_host(
+                    ROOM_CODE.to_string(),
+                    peer_id.clone(),
+                    "Host".to_string(),
+                    None,
+                    Capabilities::default(),
+                    Profile::default(),
+                    ClientInfo::default(),
+                ))
+            } else {
+                Room::Joining {
+                    room_code: ROOM_CODE.to_string(),
+                    display_name: format!("Listener {}", i),
+                    storefront: None,
+                    capabilities: Capabilities::default(),
+                    profile: Profile::default(),
+                    client: ClientInfo::default(),
+                    status: ParticipantStatus::default(),
+                    refusal: None,
+                }
+            };
+            let room = Arc::new(RwLock::new(room));
+            let (handle, command_rx) = NetworkHandle::detached(&peer_id);
+            let network_handle = Arc::new(RwLock::new(Some(handle)));
+            let (event_tx, event_rx) = mpsc::unbounded_channel();
+            let latency_tracker = Arc::new(RwLock::new(LatencyTracker::with_clock(Clock::Simulated(clock))));
+
+            let handler = handle_events(
+                event_rx,
+                Arc::clone(&room),
+                cider.clone(),
+                Arc::clone(&network_handle),
+                Arc::clone(&latency_tracker),
+                peer_id.clone(),
+            );
+            tasks.spawn(&Handle::current(), handler);
+            if i == 0 {
+                let room_actor = RoomActor::spawn(Arc::clone(&room), new_shared_recorder(), &Handle::current(), &tasks);
+                let broadcast = host_broadcast(cider, room_actor, Arc::clone(&network_handle), Clock::Simulated(clock));
+                tasks.spawn(&Handle::current(), broadcast);
+            } else {
+                let pings = ping_host(Arc::clone(&room), Arc::clone(&network_handle), latency_tracker);
+                tasks.spawn(&Handle::current(), pings);
+            }
+
+            endpoints.push(Endpoint {
+                peer_id,
+                link,
+                clock,
+                events: event_tx,
+            });
+            rooms.push(room);
+            commands.push(command_rx);
+        }
+
+        let network = Arc::new(Network {
+            endpoints,
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+            last_arrival: Mutex::new(HashMap::new()),
+        });
+        for (from, command_rx) in commands.into_iter().enumerate() {
+            tasks.spawn(&Handle::current(), Arc::clone(&network).route(from, command_rx));
+        }
+
+        // Listeners subscribe to the room topic, which the host hears about
+        for listener in 1..network.endpoints.len() {
+            let peer_id = network.endpoints[listener].peer_id.clone();
+            network.send(listener, 0, NetworkEvent::PeerSubscribed { peer_id });
+        }
+        wait_for("the listeners to join", || {
+            rooms[1..].iter().all(|room| room.read().unwrap().is_active()).then_some(())
+        })
+        .await;
+
+        Self {
+            host,
+            listeners: listeners.into_iter().map(|l| l.cider).collect(),
+            rooms,
+            tasks,
+        }
+    }
+
+    /// Play `script` (host actions by time since the start) on the host's
+    /// Cider until `duration`, sampling the listeners' drift as it goes
+    pub async fn run(&self, script: Vec<(Duration, HostAction)>, duration: Duration) -> Vec<DriftSample> {
+        run_script(&self.host, &self.listeners, script, duration).await
+    }
+
+    /// Whether every listener is still in the room
+    pub fn all_in_room(&self) -> bool {
+        self.rooms[1..].iter().all(|room| room.read().unwrap().is_active())
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.tasks.abort_all();
+    }
+}
+
+/// Ping the host every `PING_INTERVAL`, as the session's listener loop does
+async fn ping_host(
+    room: Arc<RwLock<Room>>,
+    network_handle: Arc<RwLock<Option<NetworkHandle>>>,
+    latency_tracker: SharedLatencyTracker,
+) {
+    loop {
+        let Some(host) = room.read().unwrap().state().map(|s| s.host_peer_id.clone()) else {
+            // Still joining
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            continue;
+        };
+        if let Some(handle) = network_handle.read().unwrap().as_ref() {
+            let timestamp = latency_tracker.write().unwrap().create_ping();
+            let _ = handle.ping(&host, timestamp);
+        }
+        tokio::time::sleep(PING_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::harness::DRIFT_THRESHOLD_MS;
+
+    /// Samples taken long enough after the host's last action for listeners to have followed it
+    const SETTLE: Duration = Duration::from_secs(2);
+
+    fn catalog() -> Vec<crate::cider::NowPlaying> {
+        vec![MockCider::track("1", 240_000), MockCider::track("2", 200_000)]
+    }
+
+    /// A listener on a 300ms relay with its clock 2s ahead, and one on a
+    /// lossy link with its clock 1.5s behind
+    async fn slow_and_skewed(seed: u64) -> Vec<DriftSample> {
+        let host = MockCider::new()
+            .with_catalog(catalog())
+            .with_playing(MockCider::track("1", 240_000), 10_000);
+        let listener = |seek_latency_ms| {
+            MockCider::new()
+                .with_catalog(catalog())
+                .with_seek_latency(Duration::from_millis(seek_latency_ms))
+        };
+        let listeners = vec![
+            SimListener {
+                cider: listener(400),
+                link: Link::new(Duration::from_millis(300)).with_jitter(Duration::from_millis(60)),
+                clock_skew_ms: 2_000,
+            },
+            SimListener {
+                cider: listener(600),
+                link: Link::new(Duration::from_millis(40)).with_loss(0.05),
+                clock_skew_ms: -1_500,
+            },
+        ];
+        let sim = Simulation::start(seed, host, listeners).await;
+
+        let script = vec![
+            (Duration::from_secs(10), HostAction::Seek(90_000)),
+            (Duration::from_secs(20), HostAction::Pause),
+            (Duration::from_secs(25), HostAction::Play),
+            (Duration::from_secs(35), HostAction::PlayTrack("2".to_string())),
+        ];
+        let samples = sim.run(script, Duration::from_secs(45)).await;
+        assert!(sim.all_in_room());
+        samples
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_listeners_follow_over_slow_skewed_links() {
+        let samples = slow_and_skewed(1).await;
+
+        let settled: Vec<_> = samples.iter().filter(|s| s.since_action >= SETTLE).collect();
+        assert!(settled.len() > 100, "only {} settled samples", settled.len());
+        for sample in settled {
+            for (listener, drift) in sample.drifts.iter().enumerate() {
+                let Some(drift) = drift else {
+                    panic!("listener {} not on the host's track at {:?}", listener + 1, sample.at);
+                };
+                assert!(
+                    drift.unsigned_abs() <= DRIFT_THRESHOLD_MS,
+                    "listener {} drifted {}ms at {:?}",
+                    listener + 1,
+                    drift,
+                    sample.at
+                );
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_same_seed_same_run() {
+        let first = slow_and_skewed(7).await;
+        assert_eq!(first, slow_and_skewed(7).await);
+    }
+}
@@ -1,19 +1,46 @@
 //! FFI types exposed via uniffi
 
+use crate::artwork::ArtworkError;
+use crate::cider::CiderError as CiderApiError;
+use crate::latency::{LatencyEstimate, RttHistory, RttSample as InternalRttSample, RTT_BUCKET_BOUNDS_MS};
+use crate::network::{
+    NetworkError, NetworkLogEntry as InternalNetworkLogEntry, NetworkLogKind as InternalNetworkLogKind, NetworkStatus,
+    PeerTransport,
+};
 use crate::seek_calibrator::CalibrationSample as InternalCalibrationSample;
-use crate::sync::{Participant as InternalParticipant, PlaybackInfo, RoomState as InternalRoomState, TrackInfo as InternalTrackInfo};
+use crate::sync::{
+    ApprovalMode as InternalApprovalMode, MessageClass as InternalMessageClass, Participant as InternalParticipant,
+    ParticipantStatus as InternalParticipantStatus, Permission as InternalPermission, Platform as InternalPlatform,
+    PlaybackInfo, RoomSettings as InternalRoomSettings, RoomState as InternalRoomState, TrackInfo as InternalTrackInfo,
+};
 
 /// Error types exposed via FFI
+///
+/// Failures from Cider and the network carry an `ErrorCode`, whether trying
+/// again may help and the key of a user-facing message, so the apps can offer
+/// a retry and localize messages without matching on text.
 #[derive(Debug, thiserror::Error, uniffi::Error)]
 pub enum CoreError {
     #[error("Cider is not reachable")]
     CiderNotReachable,
 
-    #[error("Cider API error: {0}")]
-    CiderApiError(String),
+    #[error("Cider API error: {detail}")]
+    CiderApiError {
+        code: ErrorCode,
+        retryable: bool,
+        message_key: String,
+        /// Technical details, for logs
+        detail: String,
+    },
 
-    #[error("Network error: {0}")]
-    NetworkError(String),
+    #[error("Network error: {detail}")]
+    NetworkError {
+        code: ErrorCode,
+        retryable: bool,
+        message_key: String,
+        /// Technical details, for logs
+        detail: String,
+    },
 
     #[error("Not in a room")]
     NotInRoom,
@@ -26,10 +53,177 @@ pub enum CoreError {
 
     #[error("Join timeout - room not found or host not reachable")]
     JoinTimeout,
+
+    #[error("Session has been shut down")]
+    ShutDown,
+}
+
+impl CoreError {
+    /// A failure talking to Cider
+    pub(crate) fn cider(code: ErrorCode, detail: impl Into<String>) -> Self {
+        CoreError::CiderApiError {
+            code,
+            retryable: code.is_retryable(),
+            message_key: code.message_key().to_string(),
+            detail: detail.into(),
+        }
+    }
+
+    /// A failure of the network or of a download
+    pub(crate) fn network(code: ErrorCode, detail: impl Into<String>) -> Self {
+        CoreError::NetworkError {
+            code,
+            retryable: code.is_retryable(),
+            message_key: code.message_key().to_string(),
+            detail: detail.into(),
+        }
+    }
+
+    /// What kind of failure this is
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CoreError::CiderNotReachable => ErrorCode::CiderNotReachable,
+            CoreError::CiderApiError { code, .. } | CoreError::NetworkError { code, .. } => *code,
+            CoreError::NotInRoom => ErrorCode::NotInRoom,
+            CoreError::AlreadyInRoom => ErrorCode::AlreadyInRoom,
+            CoreError::NotHost => ErrorCode::NotHost,
+            CoreError::JoinTimeout => ErrorCode::JoinTimeout,
+            CoreError::ShutDown => ErrorCode::ShutDown,
+        }
+    }
+
+    /// Whether trying again later may work
+    pub fn is_retryable(&self) -> bool {
+        self.code().is_retryable()
+    }
+
+    /// Key of the user-facing message in the apps' string tables
+    pub fn message_key(&self) -> &'static str {
+        self.code().message_key()
+    }
+}
+
+/// Key of the user-facing message for `error` in the apps' string tables
+///
+/// Covers the variants that don't carry a `message_key` of their own.
+#[uniffi::export]
+pub fn error_message_key(error: CoreError) -> String {
+    error.message_key().to_string()
+}
+
+impl From<CiderApiError> for CoreError {
+    fn from(e: CiderApiError) -> Self {
+        match e {
+            CiderApiError::NotReachable => CoreError::CiderNotReachable,
+            CiderApiError::Unauthorized => CoreError::cider(ErrorCode::CiderUnauthorized, e.to_string()),
+            CiderApiError::NothingPlaying => CoreError::cider(ErrorCode::NothingPlaying, e.to_string()),
+            CiderApiError::UnexpectedSchema(_) => CoreError::cider(ErrorCode::CiderUnexpectedResponse, e.to_string()),
+            CiderApiError::Http(e) => CoreError::network(ErrorCode::Network, e.to_string()),
+            CiderApiError::Api(msg) => CoreError::cider(ErrorCode::CiderApi, msg),
+            CiderApiError::SeekNotConfirmed(_) => CoreError::cider(ErrorCode::CiderApi, e.to_string()),
+        }
+    }
+}
+
+impl From<NetworkError> for CoreError {
+    fn from(e: NetworkError) -> Self {
+        CoreError::network(ErrorCode::Network, e.to_string())
+    }
+}
+
+impl From<ArtworkError> for CoreError {
+    fn from(e: ArtworkError) -> Self {
+        match e {
+            ArtworkError::InvalidUrl(_) => CoreError::network(ErrorCode::InvalidArtworkUrl, e.to_string()),
+            _ => CoreError::network(ErrorCode::ArtworkUnavailable, e.to_string()),
+        }
+    }
+}
+
+/// Kinds of `CoreError`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, uniffi::Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Cider isn't running, or its API is off
+    CiderNotReachable,
+    /// Cider's API doesn't answer on any of the usual ports
+    CiderNotFound,
+    /// Cider rejected the API token
+    CiderUnauthorized,
+    /// Cider answered with an error
+    CiderApi,
+    /// Cider's response couldn't be read (e.g. an unsupported Cider version)
+    CiderUnexpectedResponse,
+    /// Nothing is playing in Cider
+    NothingPlaying,
+    /// The network couldn't be started
+    NetworkUnavailable,
+    /// Sending over the network failed
+    Network,
+    /// No relay could be reached, so peers on other networks can't reach us
+    RelayUnreachable,
+    InvalidRoomCode,
+    /// Not a `ciderlt://` link we know
+    InvalidLink,
+    /// No host answered for the room
+    RoomNotFound,
+    /// The peer isn't in the room
+    PeerNotFound,
+    /// Artwork couldn't be downloaded or cached
+    ArtworkUnavailable,
+    InvalidArtworkUrl,
+    NotInRoom,
+    AlreadyInRoom,
+    NotHost,
+    JoinTimeout,
+    ShutDown,
+}
+
+impl ErrorCode {
+    /// Whether trying again later may work (without the user changing anything)
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::CiderNotReachable
+                | ErrorCode::CiderNotFound
+                | ErrorCode::CiderApi
+                | ErrorCode::NetworkUnavailable
+                | ErrorCode::Network
+                | ErrorCode::RelayUnreachable
+                | ErrorCode::ArtworkUnavailable
+                | ErrorCode::JoinTimeout
+        )
+    }
+
+    /// Key of the user-facing message in the apps' string tables
+    pub fn message_key(self) -> &'static str {
+        match self {
+            ErrorCode::CiderNotReachable => "error.cider_not_reachable",
+            ErrorCode::CiderNotFound => "error.cider_not_found",
+            ErrorCode::CiderUnauthorized => "error.cider_unauthorized",
+            ErrorCode::CiderApi => "error.cider_api",
+            ErrorCode::CiderUnexpectedResponse => "error.cider_unexpected_response",
+            ErrorCode::NothingPlaying => "error.nothing_playing",
+            ErrorCode::NetworkUnavailable => "error.network_unavailable",
+            ErrorCode::Network => "error.network",
+            ErrorCode::RelayUnreachable => "error.relay_unreachable",
+            ErrorCode::InvalidRoomCode => "error.invalid_room_code",
+            ErrorCode::InvalidLink => "error.invalid_link",
+            ErrorCode::RoomNotFound => "error.room_not_found",
+            ErrorCode::PeerNotFound => "error.peer_not_found",
+            ErrorCode::ArtworkUnavailable => "error.artwork_unavailable",
+            ErrorCode::InvalidArtworkUrl => "error.invalid_artwork_url",
+            ErrorCode::NotInRoom => "error.not_in_room",
+            ErrorCode::AlreadyInRoom => "error.already_in_room",
+            ErrorCode::NotHost => "error.not_host",
+            ErrorCode::JoinTimeout => "error.join_timeout",
+            ErrorCode::ShutDown => "error.shut_down",
+        }
+    }
 }
 
 /// Track information exposed via FFI
-#[derive(Debug, Clone, uniffi::Record)]
+#[derive(Debug, Clone, serde::Serialize, uniffi::Record)]
 pub struct TrackInfo {
     pub song_id: String,
     pub name: String,
@@ -38,6 +232,9 @@ pub struct TrackInfo {
     pub artwork_url: String,
     pub duration_ms: u64,
     pub position_ms: u64,
+    pub isrc: Option<String>,
+    /// Marked explicit in the catalog
+    pub explicit: bool,
 }
 
 impl From<InternalTrackInfo> for TrackInfo {
@@ -50,6 +247,8 @@ impl From<InternalTrackInfo> for TrackInfo {
             artwork_url: t.artwork_url,
             duration_ms: t.duration_ms,
             position_ms: 0, // Will be updated by playback state
+            isrc: t.isrc,
+            explicit: t.explicit,
         }
     }
 }
@@ -64,6 +263,8 @@ impl From<&crate::cider::NowPlaying> for TrackInfo {
             artwork_url: np.artwork_url(600),
             duration_ms: np.duration_in_millis,
             position_ms: np.current_position_ms(),
+            isrc: np.isrc.clone(),
+            explicit: np.is_explicit(),
         }
     }
 }
@@ -77,16 +278,33 @@ impl From<&TrackInfo> for InternalTrackInfo {
             album: t.album.clone(),
             artwork_url: t.artwork_url.clone(),
             duration_ms: t.duration_ms,
+            isrc: t.isrc.clone(),
+            explicit: t.explicit,
         }
     }
 }
 
 /// Participant exposed via FFI
-#[derive(Debug, Clone, uniffi::Record)]
+#[derive(Debug, Clone, serde::Serialize, uniffi::Record)]
 pub struct Participant {
     pub peer_id: String,
     pub display_name: String,
     pub is_host: bool,
+    /// Apple Music storefront (country code) of their account, if known
+    pub storefront: Option<String>,
+    /// Explicit content is restricted on their account (None = unknown)
+    pub explicit_restricted: Option<bool>,
+    /// An emoji, or an image URL or hash
+    pub avatar: Option<String>,
+    /// Accent color as `#RRGGBB`
+    pub color: Option<String>,
+    pub platform: Platform,
+    /// Version of their app, if it gave one
+    pub app_version: Option<String>,
+    /// Version of their cider-core (None for older peers)
+    pub core_version: Option<String>,
+    /// Whether they're at their device
+    pub status: ParticipantStatus,
 }
 
 impl From<&InternalParticipant> for Participant {
@@ -95,12 +313,66 @@ impl From<&InternalParticipant> for Participant {
             peer_id: p.peer_id.clone(),
             display_name: p.display_name.clone(),
             is_host: p.is_host,
+            storefront: p.storefront.clone(),
+            explicit_restricted: p.capabilities.explicit_restricted,
+            avatar: p.profile.avatar.clone(),
+            color: p.profile.color.clone(),
+            platform: p.client.platform.into(),
+            app_version: p.client.app_version.clone(),
+            core_version: p.client.core_version.clone(),
+            status: p.status.into(),
+        }
+    }
+}
+
+/// Whether a participant is at their device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, uniffi::Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticipantStatus {
+    Active,
+    /// No input on their device for a while
+    Idle,
+    /// They said they're away
+    Away,
+}
+
+impl From<InternalParticipantStatus> for ParticipantStatus {
+    fn from(s: InternalParticipantStatus) -> Self {
+        match s {
+            InternalParticipantStatus::Active => ParticipantStatus::Active,
+            InternalParticipantStatus::Idle => ParticipantStatus::Idle,
+            InternalParticipantStatus::Away => ParticipantStatus::Away,
+        }
+    }
+}
+
+/// The OS a participant's app runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, uniffi::Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum Platform {
+    #[serde(rename = "macos")]
+    MacOs,
+    Windows,
+    Ios,
+    Linux,
+    /// Not given (older peers), or newer than us
+    Unknown,
+}
+
+impl From<InternalPlatform> for Platform {
+    fn from(p: InternalPlatform) -> Self {
+        match p {
+            InternalPlatform::MacOs => Platform::MacOs,
+            InternalPlatform::Windows => Platform::Windows,
+            InternalPlatform::Ios => Platform::Ios,
+            InternalPlatform::Linux => Platform::Linux,
+            InternalPlatform::Unknown => Platform::Unknown,
         }
     }
 }
 
 /// Playback state exposed via FFI
-#[derive(Debug, Clone, uniffi::Record)]
+#[derive(Debug, Clone, serde::Serialize, uniffi::Record)]
 pub struct PlaybackState {
     pub is_playing: bool,
     pub position_ms: u64,
@@ -118,14 +390,14 @@ impl From<&PlaybackInfo> for PlaybackState {
 }
 
 /// Current playback info (for polling) exposed via FFI
-#[derive(Debug, Clone, uniffi::Record)]
+#[derive(Debug, Clone, serde::Serialize, uniffi::Record)]
 pub struct CurrentPlayback {
     pub track: Option<TrackInfo>,
     pub is_playing: bool,
 }
 
 /// Room state exposed via FFI
-#[derive(Debug, Clone, uniffi::Record)]
+#[derive(Debug, Clone, serde::Serialize, uniffi::Record)]
 pub struct RoomState {
     pub room_code: String,
     pub local_peer_id: String,
@@ -133,6 +405,11 @@ pub struct RoomState {
     pub participants: Vec<Participant>,
     pub current_track: Option<TrackInfo>,
     pub playback: PlaybackState,
+    /// No one new may join
+    pub locked: bool,
+    /// Peer IDs of participants whose suggestions the host ignores
+    pub suggestions_muted: Vec<String>,
+    pub settings: RoomSettings,
 }
 
 impl From<&InternalRoomState> for RoomState {
@@ -144,12 +421,122 @@ impl From<&InternalRoomState> for RoomState {
             participants: r.participant_list().into_iter().map(Participant::from).collect(),
             current_track: r.current_track.as_ref().map(|t| TrackInfo::from(t.clone())),
             playback: PlaybackState::from(&r.playback),
+            locked: r.moderation.locked,
+            suggestions_muted: r.moderation.suggestions_muted.iter().cloned().collect(),
+            settings: RoomSettings::from(&r.settings),
+        }
+    }
+}
+
+/// Who may do something in the room
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, uniffi::Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    HostOnly,
+    Everyone,
+}
+
+impl From<InternalPermission> for Permission {
+    fn from(p: InternalPermission) -> Self {
+        match p {
+            InternalPermission::HostOnly => Permission::HostOnly,
+            InternalPermission::Everyone => Permission::Everyone,
+        }
+    }
+}
+
+impl From<Permission> for InternalPermission {
+    fn from(p: Permission) -> Self {
+        match p {
+            Permission::HostOnly => InternalPermission::HostOnly,
+            Permission::Everyone => InternalPermission::Everyone,
+        }
+    }
+}
+
+/// Room messages a listener can stop receiving (room changes and the host's
+/// commands always come through)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, uniffi::Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageClass {
+    /// The host's heartbeats
+    Heartbeat,
+}
+
+impl From<MessageClass> for InternalMessageClass {
+    fn from(c: MessageClass) -> Self {
+        match c {
+            MessageClass::Heartbeat => InternalMessageClass::Heartbeat,
+        }
+    }
+}
+
+/// How newcomers get into the room
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, uniffi::Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalMode {
+    /// Anyone with the code joins
+    Open,
+    /// The host lets each newcomer in
+    HostApproval,
+}
+
+impl From<InternalApprovalMode> for ApprovalMode {
+    fn from(m: InternalApprovalMode) -> Self {
+        match m {
+            InternalApprovalMode::Open => ApprovalMode::Open,
+            InternalApprovalMode::HostApproval => ApprovalMode::HostApproval,
+        }
+    }
+}
+
+impl From<ApprovalMode> for InternalApprovalMode {
+    fn from(m: ApprovalMode) -> Self {
+        match m {
+            ApprovalMode::Open => InternalApprovalMode::Open,
+            ApprovalMode::HostApproval => InternalApprovalMode::HostApproval,
+        }
+    }
+}
+
+/// Room rules set by the host (see `update_room_settings`)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, uniffi::Record)]
+pub struct RoomSettings {
+    pub who_can_queue: Permission,
+    pub who_can_skip: Permission,
+    /// Most participants, host included (None = no limit); newcomers are turned away beyond it
+    pub max_participants: Option<u32>,
+    pub approval_mode: ApprovalMode,
+    /// Whether listeners follow the host's volume unless they opt out
+    pub follow_volume: bool,
+}
+
+impl From<&InternalRoomSettings> for RoomSettings {
+    fn from(s: &InternalRoomSettings) -> Self {
+        Self {
+            who_can_queue: s.who_can_queue.into(),
+            who_can_skip: s.who_can_skip.into(),
+            max_participants: s.max_participants,
+            approval_mode: s.approval_mode.into(),
+            follow_volume: s.follow_volume,
+        }
+    }
+}
+
+impl From<RoomSettings> for InternalRoomSettings {
+    fn from(s: RoomSettings) -> Self {
+        Self {
+            who_can_queue: s.who_can_queue.into(),
+            who_can_skip: s.who_can_skip.into(),
+            max_participants: s.max_participants,
+            approval_mode: s.approval_mode.into(),
+            follow_volume: s.follow_volume,
         }
     }
 }
 
 /// A calibration sample for debug display
-#[derive(Debug, Clone, uniffi::Record)]
+#[derive(Debug, Clone, serde::Serialize, uniffi::Record)]
 pub struct CalibrationSample {
     /// Drift measured after seek (positive = ahead, negative = behind)
     pub drift_ms: i64,
@@ -172,13 +559,31 @@ impl From<&InternalCalibrationSample> for CalibrationSample {
     }
 }
 
+/// The listener's drift model, for debug display
+#[derive(Debug, Clone, serde::Serialize, uniffi::Record)]
+pub struct DriftEstimate {
+    /// Drift with measurement noise smoothed out (positive = ahead of host)
+    pub drift_ms: i64,
+    /// How fast the drift is changing, in ms per second (positive = pulling ahead)
+    pub rate_ms_per_sec: f64,
+    /// When the drift is expected to exceed the threshold, in ms from now
+    /// (None if it isn't heading there, or the rate isn't known yet)
+    pub exceeds_threshold_in_ms: Option<u64>,
+    /// Measurements since the model last started over (after a correction or jump)
+    pub sample_count: u32,
+}
+
 /// Sync status for debug display
-#[derive(Debug, Clone, uniffi::Record)]
+#[derive(Debug, Clone, serde::Serialize, uniffi::Record)]
 pub struct SyncStatus {
     /// Drift in milliseconds (positive = ahead of host, negative = behind)
     pub drift_ms: i64,
     /// One-way latency to host in milliseconds
     pub latency_ms: u64,
+    /// How much the round-trip time to the host varies, in milliseconds
+    pub jitter_ms: u64,
+    /// Estimated drift and where it's heading
+    pub estimate: DriftEstimate,
     /// Time elapsed since host's heartbeat timestamp
     pub elapsed_ms: u64,
     /// Calibrated seek offset for Cider buffer latency
@@ -192,6 +597,489 @@ pub struct SyncStatus {
     pub sample_history: Vec<CalibrationSample>,
 }
 
+/// How often listeners get `on_sync_status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, uniffi::Enum)]
+pub enum SyncStatusLevel {
+    /// Never
+    Off,
+    /// At most every 10 seconds, without the calibration history (for release builds)
+    Summary,
+    /// On every heartbeat, with everything
+    #[default]
+    Full,
+}
+
+/// File format of a room recap (see `Session::export_room_recap`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum RecapFormat {
+    Json,
+    /// The room, its tracks and its participants as three tables
+    Csv,
+}
+
+/// A playlist made of the tracks played in a room (see `Session::save_history_as_playlist`)
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct SavedPlaylist {
+    /// Library playlist ID
+    pub playlist_id: String,
+    /// Tracks added, each once
+    pub track_count: u32,
+    /// Tracks left out because they aren't in our storefront's catalog
+    pub missing_count: u32,
+}
+
+/// Category of a network debug event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum NetworkLogKind {
+    Connection,
+    Relay,
+    Discovery,
+    Room,
+    Error,
+}
+
+impl From<InternalNetworkLogKind> for NetworkLogKind {
+    fn from(k: InternalNetworkLogKind) -> Self {
+        match k {
+            InternalNetworkLogKind::Connection => NetworkLogKind::Connection,
+            InternalNetworkLogKind::Relay => NetworkLogKind::Relay,
+            InternalNetworkLogKind::Discovery => NetworkLogKind::Discovery,
+            InternalNetworkLogKind::Room => NetworkLogKind::Room,
+            InternalNetworkLogKind::Error => NetworkLogKind::Error,
+        }
+    }
+}
+
+/// A recent network event for the connection timeline
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct NetworkLogEntry {
+    /// When the event was recorded (ms since UNIX epoch)
+    pub timestamp_ms: u64,
+    pub kind: NetworkLogKind,
+    pub message: String,
+}
+
+impl From<InternalNetworkLogEntry> for NetworkLogEntry {
+    fn from(e: InternalNetworkLogEntry) -> Self {
+        Self {
+            timestamp_ms: e.timestamp_ms,
+            kind: NetworkLogKind::from(e.kind),
+            message: e.message,
+        }
+    }
+}
+
+/// An error reported by the session, for diagnostics
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct ErrorLogEntry {
+    /// When the error last occurred (ms since UNIX epoch)
+    pub timestamp_ms: u64,
+    pub message: String,
+    /// How many times in a row it occurred
+    pub count: u32,
+}
+
+/// Measured latency to a peer
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct PeerLatency {
+    pub peer_id: String,
+    /// Average round-trip time
+    pub rtt_ms: u64,
+    /// Estimated one-way latency
+    pub latency_ms: u64,
+}
+
+impl From<LatencyEstimate> for PeerLatency {
+    fn from(l: LatencyEstimate) -> Self {
+        Self {
+            peer_id: l.peer_id,
+            rtt_ms: l.rtt_ms,
+            latency_ms: l.one_way_ms,
+        }
+    }
+}
+
+/// A measured round-trip time, for plotting
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct RttSample {
+    /// When the measurement was taken (Unix ms)
+    pub timestamp_ms: u64,
+    pub rtt_ms: u64,
+    /// Left out of the latency estimate as a spike
+    pub rejected: bool,
+}
+
+impl From<&InternalRttSample> for RttSample {
+    fn from(s: &InternalRttSample) -> Self {
+        Self {
+            timestamp_ms: s.timestamp_ms,
+            rtt_ms: s.rtt_ms,
+            rejected: s.rejected,
+        }
+    }
+}
+
+/// Number of round-trip times within a range
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct LatencyBucket {
+    pub min_ms: u64,
+    /// Exclusive; None for the slowest bucket
+    pub max_ms: Option<u64>,
+    pub count: u32,
+}
+
+/// Recent round-trip times to a peer, for the debug overlay
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct PeerLatencyHistory {
+    pub peer_id: String,
+    /// Each measured RTT, spikes included (oldest first)
+    pub samples: Vec<RttSample>,
+    /// The same samples by range, fastest first
+    pub histogram: Vec<LatencyBucket>,
+}
+
+impl From<RttHistory> for PeerLatencyHistory {
+    fn from(h: RttHistory) -> Self {
+        let histogram = h
+            .histogram
+            .iter()
+            .enumerate()
+            .map(|(i, count)| LatencyBucket {
+                min_ms: i.checked_sub(1).map(|prev| RTT_BUCKET_BOUNDS_MS[prev]).unwrap_or(0),
+                max_ms: RTT_BUCKET_BOUNDS_MS.get(i).copied(),
+                count: *count,
+            })
+            .collect();
+        Self {
+            peer_id: h.peer_id,
+            samples: h.samples.iter().map(RttSample::from).collect(),
+            histogram,
+        }
+    }
+}
+
+/// Connectivity of the P2P network, for diagnostics
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct NetworkDiagnostics {
+    pub local_peer_id: String,
+    pub listening_addresses: Vec<String>,
+    /// Peers with an open connection
+    pub connected_peers: u32,
+    /// Peers subscribed to the room topic
+    pub room_peers: u32,
+    /// Connected relay servers
+    pub relay_connections: u32,
+    /// Relay reservations held
+    pub active_relay_reservations: u32,
+    /// Relay reservations wanted (0 when publicly reachable)
+    pub target_relay_reservations: u32,
+    /// Whether peers can reach us without a relay
+    pub publicly_reachable: bool,
+    pub connected_bootstrap_nodes: u32,
+    pub total_bootstrap_nodes: u32,
+    /// Whether DHT bootstrap completed
+    pub dht_ready: bool,
+}
+
+impl NetworkDiagnostics {
+    pub(crate) fn new(local_peer_id: String, status: NetworkStatus) -> Self {
+        Self {
+            local_peer_id,
+            listening_addresses: status.listening_addresses,
+            connected_peers: status.connected_peers as u32,
+            room_peers: status.room_peers as u32,
+            relay_connections: status.relay_connections as u32,
+            active_relay_reservations: status.active_relay_reservations as u32,
+            target_relay_reservations: status.target_relay_reservations as u32,
+            publicly_reachable: status.publicly_reachable,
+            connected_bootstrap_nodes: status.connected_bootstrap_nodes as u32,
+            total_bootstrap_nodes: status.total_bootstrap_nodes as u32,
+            dht_ready: status.dht_ready,
+        }
+    }
+}
+
+/// How a peer is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum ConnectionKind {
+    /// A direct connection (LAN, public address or hole punched)
+    Direct,
+    /// Only through a relay server
+    Relayed,
+}
+
+impl From<PeerTransport> for ConnectionKind {
+    fn from(t: PeerTransport) -> Self {
+        match t {
+            PeerTransport::Direct => ConnectionKind::Direct,
+            PeerTransport::Relayed => ConnectionKind::Relayed,
+        }
+    }
+}
+
+/// Current transport details, for a connection-quality indicator
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct NetworkInfo {
+    pub local_peer_id: String,
+    pub listening_addresses: Vec<String>,
+    /// Confirmed addresses peers can reach us on without a relay
+    pub external_addresses: Vec<String>,
+    /// Relay servers we're connected to, by peer ID
+    pub relays: Vec<String>,
+    /// How we reach the host (None when hosting, not in a room or not connected yet)
+    pub host_connection: Option<ConnectionKind>,
+    /// Peers in our gossipsub mesh for the room
+    pub mesh_size: u32,
+}
+
+impl NetworkInfo {
+    pub(crate) fn new(local_peer_id: String, status: NetworkStatus, host_peer_id: Option<&str>) -> Self {
+        let host_connection = host_peer_id
+            .filter(|host| *host != local_peer_id)
+            .and_then(|host| status.peer_transports.get(host))
+            .map(|&t| ConnectionKind::from(t));
+        Self {
+            local_peer_id,
+            listening_addresses: status.listening_addresses,
+            external_addresses: status.external_addresses,
+            relays: status.relays,
+            host_connection,
+            mesh_size: status.mesh_peers as u32,
+        }
+    }
+}
+
+/// Outcome of one subsystem's check in a `HealthReport`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, uniffi::Enum)]
+pub enum HealthStatus {
+    /// Doesn't apply right now (e.g. room membership outside a room)
+    Skipped,
+    Ok,
+    /// Working, but not fully (e.g. no relay reservation yet)
+    Degraded,
+    Failed,
+}
+
+/// Health of one subsystem
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct SubsystemHealth {
+    pub status: HealthStatus,
+    /// What was found, for display
+    pub detail: String,
+    /// Why it failed, for the apps' message tables (only when Failed)
+    pub error_code: Option<ErrorCode>,
+}
+
+impl SubsystemHealth {
+    pub(crate) fn ok(detail: impl Into<String>) -> Self {
+        Self {
+            status: HealthStatus::Ok,
+            detail: detail.into(),
+            error_code: None,
+        }
+    }
+
+    pub(crate) fn degraded(detail: impl Into<String>) -> Self {
+        Self {
+            status: HealthStatus::Degraded,
+            detail: detail.into(),
+            error_code: None,
+        }
+    }
+
+    pub(crate) fn failed(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            status: HealthStatus::Failed,
+            detail: detail.into(),
+            error_code: Some(code),
+        }
+    }
+
+    pub(crate) fn skipped(detail: impl Into<String>) -> Self {
+        Self {
+            status: HealthStatus::Skipped,
+            detail: detail.into(),
+            error_code: None,
+        }
+    }
+}
+
+/// Result of `Session::health_check`, one entry per subsystem
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct HealthReport {
+    /// When the check ran (ms since UNIX epoch)
+    pub checked_at_ms: u64,
+    /// The worst status among the subsystems
+    pub overall: HealthStatus,
+    /// Cider's API answers
+    pub cider: SubsystemHealth,
+    /// Cider accepts our API token
+    pub cider_token: SubsystemHealth,
+    /// The P2P network is running and connected
+    pub network: SubsystemHealth,
+    /// We hold a relay reservation (or don't need one)
+    pub relay: SubsystemHealth,
+    /// We're in a room and connected to its host
+    pub room: SubsystemHealth,
+}
+
+impl HealthReport {
+    pub(crate) fn new(
+        cider: SubsystemHealth,
+        cider_token: SubsystemHealth,
+        network: SubsystemHealth,
+        relay: SubsystemHealth,
+        room: SubsystemHealth,
+    ) -> Self {
+        // Skipped checks don't count: all skipped is still Ok
+        let overall = [&cider, &cider_token, &network, &relay, &room]
+            .iter()
+            .map(|s| s.status)
+            .fold(HealthStatus::Ok, Ord::max);
+        Self {
+            checked_at_ms: current_time_ms(),
+            overall,
+            cider,
+            cider_token,
+            network,
+            relay,
+            room,
+        }
+    }
+}
+
+/// Snapshot of a session's state, for attaching to bug reports
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct Diagnostics {
+    /// When the snapshot was taken (ms since UNIX epoch)
+    pub generated_at_ms: u64,
+    /// cider-core version
+    pub core_version: String,
+    /// Port Cider's API is reached on
+    pub cider_port: u16,
+    pub lan_only: bool,
+    /// Current room (None if not in one)
+    pub room: Option<RoomState>,
+    /// Network connectivity (None if the network hasn't been started)
+    pub network: Option<NetworkDiagnostics>,
+    /// One-way latency to the host (listeners only; a default before it's measured)
+    pub host_latency_ms: u64,
+    /// Measured latency to each peer
+    pub peer_latencies: Vec<PeerLatency>,
+    /// Calibrated seek offset for Cider buffer latency
+    pub seek_offset_ms: u64,
+    /// Recent calibration samples (newest last)
+    pub calibration_history: Vec<CalibrationSample>,
+    /// Recent errors (oldest first)
+    pub recent_errors: Vec<ErrorLogEntry>,
+    /// Recent network events (oldest first)
+    pub recent_network_events: Vec<NetworkLogEntry>,
+}
+
+/// Default interval between the host's playback checks
+pub const DEFAULT_HEARTBEAT_INTERVAL_MS: u64 = 1500;
+
+/// Default drift from the host before a listener is re-synced
+pub const DEFAULT_DRIFT_THRESHOLD_MS: u64 = 3000;
+
+/// Smallest drift threshold a session takes; below it, listeners would
+/// re-sync on every heartbeat over drift the seeks themselves can't avoid
+pub const MIN_DRIFT_THRESHOLD_MS: u64 = 250;
+
+/// How far ahead of the host a listener may be before it pauses to let the
+/// host catch up (off by default: small leads are left alone)
+pub const DEFAULT_CATCH_UP_THRESHOLD_MS: u64 = 0;
+
+/// Default display name, used when a room is created or joined without one
+pub const DEFAULT_DISPLAY_NAME: &str = "Listener";
+
+/// Where hosts publish their addresses for joiners to find
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum SignalingServer {
+    /// An ntfy server: ntfy.sh, or a self-hosted one
+    Ntfy { url: String },
+    /// The signaling endpoint of a Cider relay server (`[signaling]` in its relay.toml)
+    Relay { url: String },
+}
+
+/// Session settings, given all at once to `Session::new_with_config`
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct SessionConfig {
+    /// Cider API port (None = find it automatically)
+    pub cider_port: Option<u16>,
+    /// Cider API token
+    pub cider_token: Option<String>,
+    /// How often the host checks its playback for changes to send (polling
+    /// Cider without its event stream); heartbeats go out on changes, and every
+    /// 5s otherwise
+    pub heartbeat_interval_ms: u64,
+    /// How far a listener may drift from the host before it's re-synced
+    /// (raised to `MIN_DRIFT_THRESHOLD_MS` if below it)
+    pub drift_threshold_ms: u64,
+    /// How far ahead of the host (within `drift_threshold_ms`) a listener may
+    /// be before it pauses for just as long, rather than seeking back. 0 to
+    /// leave small leads alone.
+    pub catch_up_threshold_ms: u64,
+    /// Preferred relay nodes for AutoRelay (format as for `set_relay_nodes`)
+    pub relay_nodes: Vec<String>,
+    /// Signaling server for finding rooms over the internet (None = ntfy.sh)
+    pub signaling_server: Option<SignalingServer>,
+    /// Display name used when a room is created or joined with an empty name
+    pub default_display_name: String,
+    /// Only connect to peers on the local network (no DHT, relays or signaling)
+    pub lan_only: bool,
+    /// How often listeners get `on_sync_status`
+    pub sync_status_level: SyncStatusLevel,
+    /// Calibrate the seek offset with a few seeks right after joining, unless
+    /// a calibration was saved (see `Session::set_storage`). Off by default:
+    /// the seeks can be heard, as skips in the first seconds of listening.
+    pub calibration_warm_up: bool,
+    /// As host, broadcast positions this far ahead of our own playback while
+    /// playing, so listeners that trail by network and buffering delay end up
+    /// level with us (we effectively listen this late). 0 to broadcast as is.
+    pub host_sync_delay_ms: u64,
+    /// As host, announce the room on our presence channel, so friends can
+    /// see we're hosting and join (see `Session::refresh_friends`). Off by
+    /// default: anyone who knows our peer ID could see when we host.
+    pub share_presence: bool,
+    /// The app's version, shown to others in the room next to our platform
+    pub app_version: Option<String>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            cider_port: None,
+            cider_token: None,
+            heartbeat_interval_ms: DEFAULT_HEARTBEAT_INTERVAL_MS,
+            drift_threshold_ms: DEFAULT_DRIFT_THRESHOLD_MS,
+            catch_up_threshold_ms: DEFAULT_CATCH_UP_THRESHOLD_MS,
+            relay_nodes: Vec::new(),
+            signaling_server: None,
+            default_display_name: DEFAULT_DISPLAY_NAME.to_string(),
+            lan_only: false,
+            sync_status_level: SyncStatusLevel::default(),
+            calibration_warm_up: false,
+            host_sync_delay_ms: 0,
+            share_presence: false,
+            app_version: None,
+        }
+    }
+}
+
+/// Default session settings, for apps to adjust before `Session::new_with_config`
+#[uniffi::export]
+pub fn default_session_config() -> SessionConfig {
+    SessionConfig::default()
+}
+
+/// What a `ciderlt://` link stands for, without acting on it (e.g. to ask
+/// before joining); `Session::handle_uri` acts on it
+#[uniffi::export]
+pub fn parse_deep_link(uri: String) -> Result<DeepLink, CoreError> {
+    super::deep_link::parse(&uri)
+}
+
 /// Callback interface for session events
 #[uniffi::export(callback_interface)]
 pub trait SessionCallback: Send + Sync {
@@ -201,17 +1089,194 @@ pub trait SessionCallback: Send + Sync {
     fn on_participant_joined(&self, participant: Participant);
     fn on_participant_left(&self, peer_id: String);
     fn on_room_ended(&self, reason: String);
-    fn on_error(&self, message: String);
+    /// Called on errors outside a method call; `message_key` names the
+    /// user-facing message in the apps' string tables, `message` is the English text
+    fn on_error(&self, code: ErrorCode, message_key: String, message: String);
     fn on_connected(&self);
     fn on_disconnected(&self);
     /// Called periodically with sync status (listeners only)
     fn on_sync_status(&self, status: SyncStatus);
+    /// Called when the host's track (or a catalog match for it) can't be played here (listeners only)
+    fn on_track_unavailable(&self, track: TrackInfo);
+    /// Called when a listener can't play the current track, e.g. because it's region-locked (host only)
+    fn on_listener_track_unavailable(&self, participant: Participant, track: TrackInfo);
+    /// Called when a listener asks to become host; grant it with `Session::transfer_host` (host only)
+    fn on_control_requested(&self, participant: Participant);
+    /// Called when a friend starts hosting a room, while `Session::watch_friends` is on
+    fn on_friend_started_room(&self, friend: Friend, room_code: String);
+}
+
+/// Severity of a log line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<&tracing::Level> for LogLevel {
+    fn from(level: &tracing::Level) -> Self {
+        match *level {
+            tracing::Level::ERROR => LogLevel::Error,
+            tracing::Level::WARN => LogLevel::Warn,
+            tracing::Level::INFO => LogLevel::Info,
+            tracing::Level::DEBUG => LogLevel::Debug,
+            tracing::Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for tracing::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => tracing::Level::ERROR,
+            LogLevel::Warn => tracing::Level::WARN,
+            LogLevel::Info => tracing::Level::INFO,
+            LogLevel::Debug => tracing::Level::DEBUG,
+            LogLevel::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
+/// A core log line, for the apps' debug console
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct LogLine {
+    /// When it was logged (ms since UNIX epoch)
+    pub timestamp_ms: u64,
+    pub level: LogLevel,
+    /// Module that logged it
+    pub target: String,
+    pub message: String,
+}
+
+/// Receives core log lines (see `set_log_callback`)
+#[uniffi::export(callback_interface)]
+pub trait LogCallback: Send + Sync {
+    fn on_log(&self, line: LogLine);
+}
+
+/// Secure key-value storage provided by the app (Keychain, EncryptedSharedPreferences)
+///
+/// Holds our identity keypair, seek calibration, last room and its blocklist,
+/// and our friends; core never writes these to the filesystem itself.
+#[uniffi::export(callback_interface)]
+pub trait SecureStorage: Send + Sync {
+    /// Value stored under `key`, if any
+    fn get(&self, key: String) -> Option<String>;
+    /// Store `value` under `key`, replacing any previous value
+    fn set(&self, key: String, value: String);
+    /// Remove the value under `key`, if any
+    fn delete(&self, key: String);
+}
+
+/// The room we were last in, as saved for `Session::restore_last_session`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, uniffi::Record)]
+pub struct SavedSession {
+    pub room_code: String,
+    pub display_name: String,
+    /// Whether we were hosting the room
+    pub was_host: bool,
+}
+
+/// A `ciderlt://` link (see `Session::handle_uri`)
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum DeepLink {
+    /// Join a room
+    JoinRoom { room_code: String },
+    /// An invite to a room, from someone in it (see `Session::invite_link`)
+    Invite {
+        room_code: String,
+        from_peer_id: Option<String>,
+    },
+    /// Suggest a catalog song for the room's queue
+    Suggest { song_id: String },
+}
+
+/// Someone we've listened with and saved as a friend (see `Session::add_friend`)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, uniffi::Record)]
+pub struct Friend {
+    pub peer_id: String,
+    /// Their display name when we last saw them
+    pub display_name: String,
+    /// Room they're hosting, as of the last `Session::refresh_friends`
+    #[serde(skip)]
+    pub hosting_room: Option<String>,
+}
+
+/// A session event, as delivered by `Session::next_event` (mirrors `SessionCallback`)
+#[derive(Debug, Clone, serde::Serialize, uniffi::Enum)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    RoomStateChanged { state: RoomState },
+    TrackChanged { track: Option<TrackInfo> },
+    PlaybackChanged { playback: PlaybackState },
+    ParticipantJoined { participant: Participant },
+    ParticipantLeft { peer_id: String },
+    RoomEnded { reason: String },
+    Error { code: ErrorCode, message_key: String, message: String },
+    Connected,
+    Disconnected,
+    SyncStatus { status: SyncStatus },
+    TrackUnavailable { track: TrackInfo },
+    ListenerTrackUnavailable { participant: Participant, track: TrackInfo },
+    ControlRequested { participant: Participant },
+    FriendStartedRoom { friend: Friend, room_code: String },
 }
 
 /// Get current time in milliseconds since UNIX epoch
 pub fn current_time_ms() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64
+    crate::clock::now_ms()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_error_codes() {
+        let unreachable = CoreError::from(CiderApiError::NotReachable);
+        assert_eq!(unreachable.code(), ErrorCode::CiderNotReachable);
+        assert!(unreachable.is_retryable());
+
+        let unauthorized = CoreError::from(CiderApiError::Unauthorized);
+        assert!(!unauthorized.is_retryable());
+        assert!(matches!(
+            unauthorized,
+            CoreError::CiderApiError { code: ErrorCode::CiderUnauthorized, retryable: false, ref message_key, .. }
+                if message_key == "error.cider_unauthorized"
+        ));
+
+        let api = CoreError::from(CiderApiError::Api("busy".to_string()));
+        assert_eq!(api.to_string(), "Cider API error: busy");
+        assert!(api.is_retryable());
+
+        assert!(!CoreError::NotHost.is_retryable());
+        assert!(CoreError::JoinTimeout.is_retryable());
+        assert_eq!(error_message_key(CoreError::NotInRoom), "error.not_in_room");
+        assert_eq!(ErrorCode::RoomNotFound.message_key(), "error.room_not_found");
+    }
+
+    #[test]
+    fn test_network_info_host_connection() {
+        let status = NetworkStatus {
+            peer_transports: HashMap::from([
+                ("host".to_string(), PeerTransport::Relayed),
+                ("guest".to_string(), PeerTransport::Direct),
+            ]),
+            mesh_peers: 2,
+            ..Default::default()
+        };
+
+        let info = NetworkInfo::new("guest".to_string(), status.clone(), Some("host"));
+        assert_eq!(info.host_connection, Some(ConnectionKind::Relayed));
+        assert_eq!(info.mesh_size, 2);
+
+        // Hosting, not in a room, or the host isn't connected
+        assert_eq!(NetworkInfo::new("host".to_string(), status.clone(), Some("host")).host_connection, None);
+        assert_eq!(NetworkInfo::new("guest".to_string(), status.clone(), None).host_connection, None);
+        assert_eq!(NetworkInfo::new("guest".to_string(), status, Some("other")).host_connection, None);
+    }
 }
@@ -2,61 +2,187 @@
 //!
 //! Measures round-trip time (RTT) to peers using ping/pong messages
 //! and provides estimated one-way latency for position calculations.
+//! Pongs also carry the peer's clock, which gives the offset between its wall
+//! clock and ours: the host's timestamps only mean something once corrected
+//! for it.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, RwLock};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
-/// Number of RTT samples to keep for averaging
-const RTT_SAMPLE_COUNT: usize = 5;
+use tokio::time::Instant;
+
+use crate::clock::Clock;
+
+/// Number of RTT samples to keep
+const RTT_SAMPLE_COUNT: usize = 7;
+
+/// Samples needed before spikes are rejected
+const MIN_SAMPLES_FOR_REJECTION: usize = 3;
+
+/// An RTT this many times the median is a spike (e.g. a pong held up on a relay)
+const SPIKE_FACTOR: u64 = 3;
+
+/// Added to the spike limit so small jumps on a fast network still count
+const SPIKE_MARGIN_MS: u64 = 50;
+
+/// Spikes in a row after which they're taken as the new normal (e.g. moved to a relay)
+const MAX_SPIKES_IN_A_ROW: usize = 3;
 
 /// Default latency estimate when no measurements exist (conservative for local network)
 const DEFAULT_LATENCY_MS: u64 = 10;
 
+/// RTT samples kept per peer for plotting (about 5 minutes of pings)
+const RTT_HISTORY_COUNT: usize = 60;
+
+/// Upper bounds of the RTT histogram's buckets in milliseconds (exclusive);
+/// a last bucket holds anything slower
+pub const RTT_BUCKET_BOUNDS_MS: [u64; 7] = [10, 25, 50, 100, 200, 400, 800];
+
+/// A measured RTT, for plotting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttSample {
+    /// Our wall clock when the pong arrived
+    pub timestamp_ms: u64,
+    pub rtt_ms: u64,
+    /// Left out of the estimate as a spike
+    pub rejected: bool,
+}
+
+/// Recent RTTs to one peer, spikes included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RttHistory {
+    pub peer_id: String,
+    /// Oldest first
+    pub samples: Vec<RttSample>,
+    /// Samples in each bucket of `RTT_BUCKET_BOUNDS_MS`, then the slower ones
+    pub histogram: Vec<u32>,
+}
+
 /// A single pending ping awaiting response
 struct PendingPing {
     sent_at: Instant,
 }
 
 /// RTT history for a single peer
+///
+/// Smoothed with the median rather than the mean, so one slow pong doesn't
+/// move the estimate, and spikes far above it are left out altogether.
 struct PeerLatency {
     /// Recent RTT samples in milliseconds
     samples: Vec<u64>,
-    /// Cached average RTT
-    avg_rtt_ms: u64,
+    /// Cached median RTT
+    rtt_ms: u64,
+    /// Cached mean deviation of the samples from the median
+    jitter_ms: u64,
+    /// Spikes rejected since the last accepted sample
+    spikes: Vec<u64>,
+    /// Recent clock offset samples (peer's clock minus ours) in milliseconds
+    clock_offsets: Vec<i64>,
+    /// Every RTT measured recently, spikes included (the estimate only keeps a few)
+    history: VecDeque<RttSample>,
 }
 
 impl PeerLatency {
     fn new() -> Self {
         Self {
             samples: Vec::with_capacity(RTT_SAMPLE_COUNT),
-            avg_rtt_ms: DEFAULT_LATENCY_MS * 2, // RTT = 2 * one-way
+            rtt_ms: DEFAULT_LATENCY_MS * 2, // RTT = 2 * one-way
+            jitter_ms: 0,
+            spikes: Vec::new(),
+            clock_offsets: Vec::with_capacity(RTT_SAMPLE_COUNT),
+            history: VecDeque::with_capacity(RTT_HISTORY_COUNT),
         }
     }
 
-    fn add_sample(&mut self, rtt_ms: u64) {
-        if self.samples.len() >= RTT_SAMPLE_COUNT {
-            self.samples.remove(0);
+    /// Add an RTT sample. Returns false if it was rejected as a spike.
+    fn add_sample(&mut self, rtt_ms: u64) -> bool {
+        let spike_limit = self.rtt_ms * SPIKE_FACTOR + SPIKE_MARGIN_MS;
+        if self.samples.len() >= MIN_SAMPLES_FOR_REJECTION && rtt_ms > spike_limit {
+            self.spikes.push(rtt_ms);
+            if self.spikes.len() < MAX_SPIKES_IN_A_ROW {
+                return false;
+            }
+            // Consistently slower: start over from the spikes
+            self.samples = std::mem::take(&mut self.spikes);
+            self.clock_offsets.clear();
+        } else {
+            self.spikes.clear();
+            if self.samples.len() >= RTT_SAMPLE_COUNT {
+                self.samples.remove(0);
+            }
+            self.samples.push(rtt_ms);
         }
-        self.samples.push(rtt_ms);
-        self.recalculate_average();
+        self.recalculate();
+        true
     }
 
-    fn recalculate_average(&mut self) {
-        if self.samples.is_empty() {
-            self.avg_rtt_ms = DEFAULT_LATENCY_MS * 2;
+    fn recalculate(&mut self) {
+        let Some(median_ms) = median(&self.samples) else {
+            self.rtt_ms = DEFAULT_LATENCY_MS * 2;
+            self.jitter_ms = 0;
             return;
+        };
+        let deviation: u64 = self.samples.iter().map(|s| s.abs_diff(median_ms)).sum();
+        self.rtt_ms = median_ms;
+        self.jitter_ms = deviation / self.samples.len() as u64;
+    }
+
+    fn add_to_history(&mut self, sample: RttSample) {
+        if self.history.len() >= RTT_HISTORY_COUNT {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+    }
+
+    /// Number of recent samples in each histogram bucket
+    fn histogram(&self) -> Vec<u32> {
+        let mut counts = vec![0; RTT_BUCKET_BOUNDS_MS.len() + 1];
+        for sample in &self.history {
+            let bucket = RTT_BUCKET_BOUNDS_MS
+                .iter()
+                .position(|bound| sample.rtt_ms < *bound)
+                .unwrap_or(RTT_BUCKET_BOUNDS_MS.len());
+            counts[bucket] += 1;
         }
-        let sum: u64 = self.samples.iter().sum();
-        self.avg_rtt_ms = sum / self.samples.len() as u64;
+        counts
+    }
+
+    fn add_clock_offset(&mut self, offset_ms: i64) {
+        if self.clock_offsets.len() >= RTT_SAMPLE_COUNT {
+            self.clock_offsets.remove(0);
+        }
+        self.clock_offsets.push(offset_ms);
     }
 
     /// Get estimated one-way latency (RTT / 2)
     fn one_way_latency_ms(&self) -> u64 {
-        self.avg_rtt_ms / 2
+        self.rtt_ms / 2
+    }
+
+    /// Median clock offset, if a pong carried the peer's clock
+    fn clock_offset_ms(&self) -> Option<i64> {
+        median(&self.clock_offsets)
     }
 }
 
+/// Middle value (the upper one of an even count), or None if empty
+fn median<T: Copy + Ord>(values: &[T]) -> Option<T> {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    sorted.get(sorted.len() / 2).copied()
+}
+
+/// Latency estimate for one peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyEstimate {
+    pub peer_id: String,
+    /// Median round-trip time
+    pub rtt_ms: u64,
+    /// Estimated one-way latency (RTT / 2)
+    pub one_way_ms: u64,
+}
+
 /// Tracks latency to peers in a room
 #[derive(Default)]
 pub struct LatencyTracker {
@@ -66,6 +192,8 @@ pub struct LatencyTracker {
     peer_latencies: HashMap<String, PeerLatency>,
     /// Host peer ID (we only care about latency to host)
     host_peer_id: Option<String>,
+    /// Our wall clock, stamped on pings
+    clock: Clock,
 }
 
 impl LatencyTracker {
@@ -73,6 +201,16 @@ impl LatencyTracker {
         Self::default()
     }
 
+    /// A tracker reading our wall clock from `clock`
+    pub fn with_clock(clock: Clock) -> Self {
+        Self { clock, ..Self::default() }
+    }
+
+    /// Our wall clock, in milliseconds since the UNIX epoch
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
     /// Set the host peer ID (latency to host is what matters for sync)
     pub fn set_host(&mut self, peer_id: String) {
         self.host_peer_id = Some(peer_id);
@@ -88,10 +226,7 @@ impl LatencyTracker {
     /// Create a ping to send. Returns the timestamp to include in the Ping message.
     pub fn create_ping(&mut self) -> u64 {
         let now = Instant::now();
-        let timestamp_ms = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
+        let timestamp_ms = self.clock.now_ms();
 
         self.pending_pings.insert(
             timestamp_ms,
@@ -105,24 +240,47 @@ impl LatencyTracker {
         timestamp_ms
     }
 
-    /// Handle a pong response. Returns the measured RTT if valid.
-    pub fn handle_pong(&mut self, from_peer: &str, original_timestamp_ms: u64) -> Option<u64> {
-        let pending = self.pending_pings.remove(&original_timestamp_ms)?;
+    /// Handle a pong response. Returns the measured RTT if valid (even if
+    /// it was left out of the estimate as a spike).
+    ///
+    /// `received_at_ms` is the peer's wall clock when it got the ping. Taking
+    /// that to be halfway through the round trip gives its clock offset.
+    ///
+    /// The host sends the same ping to every listener, and a ping on the
+    /// room topic is answered by every peer, so it stays pending until it
+    /// expires.
+    pub fn handle_pong(&mut self, from_peer: &str, original_timestamp_ms: u64, received_at_ms: u64) -> Option<u64> {
+        let pending = self.pending_pings.get(&original_timestamp_ms)?;
         let rtt_ms = pending.sent_at.elapsed().as_millis() as u64;
+        let midpoint_ms = original_timestamp_ms + rtt_ms / 2;
+        let clock_offset_ms = received_at_ms as i64 - midpoint_ms as i64;
 
-        // Record the RTT for this peer
+        // Record the RTT and clock offset for this peer
         let peer_latency = self
             .peer_latencies
             .entry(from_peer.to_string())
             .or_insert_with(PeerLatency::new);
-        peer_latency.add_sample(rtt_ms);
+        let accepted = peer_latency.add_sample(rtt_ms);
+        peer_latency.add_to_history(RttSample {
+            timestamp_ms: self.clock.now_ms(),
+            rtt_ms,
+            rejected: !accepted,
+        });
+        if !accepted {
+            // A delayed pong's clock reading is off by as much as its RTT
+            tracing::debug!("Latency to {}: ignoring RTT spike of {}ms", from_peer, rtt_ms);
+            return Some(rtt_ms);
+        }
+        peer_latency.add_clock_offset(clock_offset_ms);
 
         tracing::debug!(
-            "Latency to {}: RTT={}ms, avg={}ms, one-way={}ms",
+            "Latency to {}: RTT={}ms, median={}ms, jitter={}ms, one-way={}ms, clock offset={:+}ms",
             from_peer,
             rtt_ms,
-            peer_latency.avg_rtt_ms,
-            peer_latency.one_way_latency_ms()
+            peer_latency.rtt_ms,
+            peer_latency.jitter_ms,
+            peer_latency.one_way_latency_ms(),
+            clock_offset_ms
         );
 
         Some(rtt_ms)
@@ -139,6 +297,22 @@ impl LatencyTracker {
         DEFAULT_LATENCY_MS
     }
 
+    /// How much the RTT to the host varies (0 until measured)
+    pub fn host_jitter_ms(&self) -> u64 {
+        self.host_peer_id
+            .as_ref()
+            .and_then(|host_id| self.peer_latencies.get(host_id))
+            .map(|p| p.jitter_ms)
+            .unwrap_or(0)
+    }
+
+    /// How far the host's wall clock is ahead of ours (negative if behind),
+    /// or None until a pong from the host has been measured
+    pub fn host_clock_offset_ms(&self) -> Option<i64> {
+        let host_id = self.host_peer_id.as_ref()?;
+        self.peer_latencies.get(host_id)?.clock_offset_ms()
+    }
+
     /// Get estimated one-way latency to a specific peer
     pub fn peer_latency_ms(&self, peer_id: &str) -> u64 {
         self.peer_latencies
@@ -146,6 +320,36 @@ impl LatencyTracker {
             .map(|p| p.one_way_latency_ms())
             .unwrap_or(DEFAULT_LATENCY_MS)
     }
+
+    /// Latency to every measured peer, sorted by peer ID
+    pub fn peer_latencies(&self) -> Vec<LatencyEstimate> {
+        let mut latencies: Vec<LatencyEstimate> = self
+            .peer_latencies
+            .iter()
+            .map(|(peer_id, p)| LatencyEstimate {
+                peer_id: peer_id.clone(),
+                rtt_ms: p.rtt_ms,
+                one_way_ms: p.one_way_latency_ms(),
+            })
+            .collect();
+        latencies.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        latencies
+    }
+
+    /// Recent RTTs to every measured peer, sorted by peer ID
+    pub fn rtt_histories(&self) -> Vec<RttHistory> {
+        let mut histories: Vec<RttHistory> = self
+            .peer_latencies
+            .iter()
+            .map(|(peer_id, p)| RttHistory {
+                peer_id: peer_id.clone(),
+                samples: p.history.iter().copied().collect(),
+                histogram: p.histogram(),
+            })
+            .collect();
+        histories.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        histories
+    }
 }
 
 /// Thread-safe wrapper for LatencyTracker
@@ -171,7 +375,7 @@ mod tests {
         // Simulate a ping/pong with 50ms RTT
         let ts = tracker.create_ping();
         std::thread::sleep(Duration::from_millis(50));
-        let rtt = tracker.handle_pong("host123", ts);
+        let rtt = tracker.handle_pong("host123", ts, ts + 25);
 
         assert!(rtt.is_some());
         let measured_rtt = rtt.unwrap();
@@ -196,8 +400,97 @@ mod tests {
         peer_latency.add_sample(200);
         peer_latency.add_sample(150);
 
-        // Average should be (100+200+150)/3 = 150, one-way = 75
-        assert_eq!(peer_latency.avg_rtt_ms, 150);
+        // Median should be 150, one-way = 75
+        assert_eq!(peer_latency.rtt_ms, 150);
         assert_eq!(peer_latency.one_way_latency_ms(), 75);
+        let estimate = LatencyEstimate {
+            peer_id: "peer1".to_string(),
+            rtt_ms: 150,
+            one_way_ms: 75,
+        };
+        assert_eq!(tracker.peer_latencies(), vec![estimate]);
+    }
+
+    #[test]
+    fn test_every_peer_answers_a_ping() {
+        let mut tracker = LatencyTracker::new();
+        let ts = tracker.create_ping();
+
+        assert!(tracker.handle_pong("listener1", ts, ts).is_some());
+        assert!(tracker.handle_pong("listener2", ts, ts).is_some());
+        assert!(tracker.handle_pong("listener3", ts + 1, ts).is_none());
+
+        let peers: Vec<String> = tracker.peer_latencies().into_iter().map(|l| l.peer_id).collect();
+        assert_eq!(peers, vec!["listener1", "listener2"]);
+    }
+
+    #[test]
+    fn test_host_clock_offset() {
+        let mut tracker = LatencyTracker::new();
+        tracker.set_host("host".to_string());
+        assert_eq!(tracker.host_clock_offset_ms(), None);
+
+        // The host's clock reads 2s ahead of ours when the ping reaches it
+        let ts = tracker.create_ping();
+        tracker.handle_pong("host", ts, ts + 2_000);
+        let offset = tracker.host_clock_offset_ms().unwrap();
+        assert!((1_900..=2_000).contains(&offset), "offset {}", offset);
+
+        // Other peers' clocks don't count
+        tracker.handle_pong("listener", ts, ts - 5_000);
+        assert_eq!(tracker.host_clock_offset_ms(), Some(offset));
+    }
+
+    #[test]
+    fn test_spikes_rejected() {
+        let mut peer = PeerLatency::new();
+        for rtt in [40, 44, 38, 42] {
+            assert!(peer.add_sample(rtt));
+        }
+        assert_eq!(peer.rtt_ms, 42);
+        assert_eq!(peer.jitter_ms, 2);
+
+        // One pong held up on a relay doesn't move the estimate
+        assert!(!peer.add_sample(900));
+        assert_eq!(peer.rtt_ms, 42);
+        assert!(peer.add_sample(41));
+
+        // A lasting slowdown does
+        assert!(!peer.add_sample(300));
+        assert!(!peer.add_sample(310));
+        assert!(peer.add_sample(305));
+        assert_eq!(peer.rtt_ms, 305);
+    }
+
+    #[test]
+    fn test_rtt_history_and_histogram() {
+        let mut tracker = LatencyTracker::new();
+        let ts = tracker.create_ping();
+        tracker.handle_pong("peer1", ts, ts);
+        let histories = tracker.rtt_histories();
+        assert_eq!(histories.len(), 1);
+        assert_eq!(histories[0].samples.len(), 1);
+        assert!(!histories[0].samples[0].rejected);
+        assert_eq!(histories[0].histogram.len(), RTT_BUCKET_BOUNDS_MS.len() + 1);
+        assert_eq!(histories[0].histogram[0], 1);
+
+        // Spikes are kept for plotting, and only the most recent samples
+        let mut peer = PeerLatency::new();
+        for i in 0..RTT_HISTORY_COUNT as u64 {
+            let rtt_ms = if i.is_multiple_of(10) { 900 } else { 40 };
+            peer.add_to_history(RttSample {
+                timestamp_ms: i,
+                rtt_ms,
+                rejected: rtt_ms == 900,
+            });
+        }
+        peer.add_to_history(RttSample {
+            timestamp_ms: RTT_HISTORY_COUNT as u64,
+            rtt_ms: 10,
+            rejected: false,
+        });
+        assert_eq!(peer.history.len(), RTT_HISTORY_COUNT);
+        assert_eq!(peer.history.front().unwrap().timestamp_ms, 1);
+        assert_eq!(peer.histogram(), vec![0, 1, 54, 0, 0, 0, 0, 5]);
     }
 }
@@ -3,7 +3,10 @@
 //! This library provides the core functionality for syncing music playback
 //! across multiple Cider instances via P2P networking.
 
+pub mod artwork;
 pub mod cider;
+pub mod clock;
+pub mod drift_model;
 pub mod ffi;
 pub mod latency;
 pub mod network;
@@ -0,0 +1,508 @@
+//! Automatic relay selection (AutoRelay)
+//!
+//! Keeps track of peers that can act as a circuit relay and decides which
+//! of them we should hold reservations on. Candidates come from the
+//! configured relay nodes and from identify hints (peers advertising the
+//! relay hop protocol). While we're not publicly reachable we keep
+//! reservations on the best N candidates; once an external address is
+//! confirmed we scale down to a single reservation, since signaling only
+//! publishes relay addresses.
+//!
+//! Cider relays can advertise a region tag in their identify agent string
+//! (`region/<tag>`). With a preferred region set, relays in that region are
+//! ranked ahead of others of the same source, and a held reservation is
+//! moved once a better-placed relay becomes available.
+//!
+//! Relays that keep failing us (denied reservations, unreachable) are given up
+//! on, for a while: failures are forgotten after `FAILURE_MEMORY`, so a relay
+//! that was down comes back into consideration.
+
+use libp2p::core::transport::ListenerId;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::relay_access::agent_field;
+
+/// Default number of relay reservations to hold while behind NAT
+pub const DEFAULT_MAX_RELAY_RESERVATIONS: usize = 2;
+
+/// Candidates that failed this many times within `FAILURE_MEMORY` are no longer tried
+const MAX_CANDIDATE_FAILURES: usize = 3;
+
+/// How long a failure counts against a candidate
+const FAILURE_MEMORY: Duration = Duration::from_secs(10 * 60);
+
+/// Agent string field carrying a relay's region
+const REGION_PREFIX: &str = "region/";
+
+/// Where we learned about a relay candidate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CandidateSource {
+    /// Explicitly configured relay node (preferred)
+    Config,
+    /// Peer advertised the relay hop protocol via identify
+    Identify,
+}
+
+/// Reservation state for a single candidate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReservationState {
+    /// No reservation requested
+    Idle,
+    /// Listening on the relay, waiting for the reservation to be accepted
+    Pending(ListenerId),
+    /// Reservation accepted by the relay
+    Active(ListenerId),
+}
+
+/// A peer that can relay for us
+#[derive(Debug, Clone)]
+struct RelayCandidate {
+    /// Dialable addresses of the relay (without /p2p-circuit)
+    addrs: Vec<Multiaddr>,
+    /// How we discovered it
+    source: CandidateSource,
+    /// Last measured ping RTT
+    rtt: Option<Duration>,
+    /// Region advertised by the relay
+    region: Option<String>,
+    /// When recent reservation attempts failed
+    failures: Vec<Instant>,
+    /// Current reservation state
+    state: ReservationState,
+}
+
+/// Actions the swarm should take to converge on the desired reservations
+#[derive(Debug, Default)]
+pub struct RelayPlan {
+    /// Relay circuit addresses to listen on (one per relay)
+    pub reserve: Vec<(PeerId, Multiaddr)>,
+    /// Relay listeners to close
+    pub release: Vec<ListenerId>,
+}
+
+impl RelayPlan {
+    pub fn is_empty(&self) -> bool {
+        self.reserve.is_empty() && self.release.is_empty()
+    }
+}
+
+/// Tracks relay candidates and picks which ones to reserve
+#[derive(Debug)]
+pub struct AutoRelay {
+    /// Maximum reservations while not publicly reachable
+    max_reservations: usize,
+    /// Whether we have a confirmed external (non-relay) address
+    publicly_reachable: bool,
+    /// Region to prefer relays from
+    preferred_region: Option<String>,
+    /// Known relay candidates
+    candidates: HashMap<PeerId, RelayCandidate>,
+}
+
+impl AutoRelay {
+    pub fn new(max_reservations: usize) -> Self {
+        Self {
+            max_reservations,
+            publicly_reachable: false,
+            preferred_region: None,
+            candidates: HashMap::new(),
+        }
+    }
+
+    /// Prefer relays advertising this region (None = rank by RTT only)
+    pub fn set_preferred_region(&mut self, region: Option<String>) {
+        self.preferred_region = region;
+    }
+
+    /// Add or refresh a relay candidate
+    ///
+    /// Loopback addresses a relay reports are dropped since other peers can't
+    /// reach it there; a relay configured on this machine keeps them.
+    pub fn add_candidate(&mut self, peer_id: PeerId, addrs: Vec<Multiaddr>, source: CandidateSource) {
+        let addrs: Vec<Multiaddr> = addrs
+            .into_iter()
+            .filter(|a| source == CandidateSource::Config || !is_loopback(a))
+            .collect();
+        if addrs.is_empty() {
+            return;
+        }
+
+        let candidate = self.candidates.entry(peer_id).or_insert_with(|| RelayCandidate {
+            addrs: Vec::new(),
+            source,
+            rtt: None,
+            region: None,
+            failures: Vec::new(),
+            state: ReservationState::Idle,
+        });
+        // A configured relay keeps its priority even if identify reports it later
+        candidate.source = candidate.source.min(source);
+        for addr in addrs {
+            if !candidate.addrs.contains(&addr) {
+                candidate.addrs.push(addr);
+            }
+        }
+    }
+
+    /// Record a ping RTT for ranking
+    pub fn record_rtt(&mut self, peer_id: &PeerId, rtt: Duration) {
+        if let Some(candidate) = self.candidates.get_mut(peer_id) {
+            candidate.rtt = Some(rtt);
+        }
+    }
+
+    /// Record the region a relay advertised via identify
+    pub fn record_region(&mut self, peer_id: &PeerId, region: &str) {
+        if let Some(candidate) = self.candidates.get_mut(peer_id) {
+            candidate.region = Some(region.to_string());
+        }
+    }
+
+    /// Update public reachability (from confirmed/expired external addresses)
+    pub fn set_publicly_reachable(&mut self, reachable: bool) {
+        self.publicly_reachable = reachable;
+    }
+
+    pub fn is_publicly_reachable(&self) -> bool {
+        self.publicly_reachable
+    }
+
+    /// Number of reservations we currently want
+    pub fn target_reservations(&self) -> usize {
+        if self.publicly_reachable {
+            self.max_reservations.min(1)
+        } else {
+            self.max_reservations
+        }
+    }
+
+    /// Number of accepted reservations
+    pub fn active_reservations(&self) -> usize {
+        self.candidates
+            .values()
+            .filter(|c| matches!(c.state, ReservationState::Active(_)))
+            .count()
+    }
+
+    /// Note that we started listening on a relay
+    pub fn mark_pending(&mut self, peer_id: &PeerId, listener_id: ListenerId) {
+        if let Some(candidate) = self.candidates.get_mut(peer_id) {
+            candidate.state = ReservationState::Pending(listener_id);
+        }
+    }
+
+    /// Relay accepted our reservation
+    pub fn on_reservation_accepted(&mut self, peer_id: &PeerId) {
+        if let Some(candidate) = self.candidates.get_mut(peer_id) {
+            if let ReservationState::Pending(id) | ReservationState::Active(id) = candidate.state {
+                candidate.state = ReservationState::Active(id);
+                candidate.failures.clear();
+            }
+        }
+    }
+
+    /// A relay listener closed; returns the relay it belonged to, if any
+    ///
+    /// Only a reservation that `failed` (e.g. the relay denied it) counts
+    /// against the relay: one we released, or that was still pending when
+    /// the connection dropped, doesn't.
+    pub fn on_listener_closed(&mut self, listener_id: ListenerId, failed: bool) -> Option<PeerId> {
+        let (peer_id, candidate) = self.candidates.iter_mut().find(|(_, c)| {
+            matches!(c.state, ReservationState::Pending(id) | ReservationState::Active(id) if id == listener_id)
+        })?;
+
+        if failed && matches!(candidate.state, ReservationState::Pending(_)) {
+            candidate.failures.push(Instant::now());
+        }
+        candidate.state = ReservationState::Idle;
+        Some(*peer_id)
+    }
+
+    /// Connection to a relay closed - its reservation is gone
+    pub fn on_relay_disconnected(&mut self, peer_id: &PeerId) {
+        if let Some(candidate) = self.candidates.get_mut(peer_id) {
+            candidate.state = ReservationState::Idle;
+        }
+    }
+
+    /// Private relay accepted our token handshake
+    ///
+    /// Reservations denied before the handshake completed don't count against it.
+    pub fn on_authenticated(&mut self, peer_id: &PeerId) {
+        if let Some(candidate) = self.candidates.get_mut(peer_id) {
+            candidate.failures.clear();
+        }
+    }
+
+    /// Stop considering a relay (e.g. it rejected our access token)
+    pub fn remove_candidate(&mut self, peer_id: &PeerId) {
+        self.candidates.remove(peer_id);
+    }
+
+    /// Reservation request could not be started, or the relay couldn't be reached
+    pub fn record_failure(&mut self, peer_id: &PeerId) {
+        if let Some(candidate) = self.candidates.get_mut(peer_id) {
+            candidate.failures.retain(|at| at.elapsed() < FAILURE_MEMORY);
+            candidate.failures.push(Instant::now());
+            candidate.state = ReservationState::Idle;
+        }
+    }
+
+    /// Compute which reservations to open or close
+    pub fn plan(&self) -> RelayPlan {
+        self.plan_at(Instant::now())
+    }
+
+    /// Compute which reservations to open or close, as of `now`
+    fn plan_at(&self, now: Instant) -> RelayPlan {
+        let target = self.target_reservations();
+        let mut plan = RelayPlan::default();
+
+        let mut held: Vec<(&PeerId, &RelayCandidate)> = self
+            .candidates
+            .iter()
+            .filter(|(_, c)| c.state != ReservationState::Idle)
+            .collect();
+
+        // Release the worst-ranked reservations first
+        held.sort_by_key(|(peer_id, c)| self.rank(peer_id, c, now));
+        if held.len() > target {
+            for (_, candidate) in held.drain(target..) {
+                if let ReservationState::Pending(id) | ReservationState::Active(id) = candidate.state {
+                    plan.release.push(id);
+                }
+            }
+            return plan;
+        }
+
+        let mut idle: Vec<(&PeerId, &RelayCandidate)> = self
+            .candidates
+            .iter()
+            .filter(|(_, c)| c.state == ReservationState::Idle && recent_failures(c, now) < MAX_CANDIDATE_FAILURES)
+            .collect();
+        idle.sort_by_key(|(peer_id, c)| self.rank(peer_id, c, now));
+
+        // Move a full set of reservations to a better-placed relay (source/region,
+        // not RTT, so small latency changes don't cause churn). The freed slot is
+        // filled on the next plan.
+        if held.len() == target {
+            if let (Some((_, worst)), Some((_, best))) = (held.last(), idle.first()) {
+                if self.placement(best) < self.placement(worst) {
+                    if let ReservationState::Pending(id) | ReservationState::Active(id) = worst.state {
+                        plan.release.push(id);
+                    }
+                }
+            }
+            return plan;
+        }
+
+        for (peer_id, candidate) in idle.into_iter().take(target - held.len()) {
+            let addr = candidate.addrs[0]
+                .clone()
+                .with(Protocol::P2p(*peer_id))
+                .with(Protocol::P2pCircuit);
+            plan.reserve.push((*peer_id, addr));
+        }
+
+        plan
+    }
+}
+
+impl AutoRelay {
+    /// Ranking key: fewer recent failures, configured first, preferred
+    /// region, then lowest RTT (peer ID breaks ties)
+    fn rank(&self, peer_id: &PeerId, c: &RelayCandidate, now: Instant) -> (usize, (CandidateSource, bool), Duration, PeerId) {
+        (recent_failures(c, now), self.placement(c), c.rtt.unwrap_or(Duration::MAX), *peer_id)
+    }
+
+    /// Source and region part of the ranking (false = in the preferred region)
+    fn placement(&self, c: &RelayCandidate) -> (CandidateSource, bool) {
+        let other_region = self
+            .preferred_region
+            .as_deref()
+            .is_some_and(|preferred| c.region.as_deref() != Some(preferred));
+        (c.source, other_region)
+    }
+}
+
+/// Failures within `FAILURE_MEMORY` of `now`
+fn recent_failures(c: &RelayCandidate, now: Instant) -> usize {
+    c.failures
+        .iter()
+        .filter(|at| now.saturating_duration_since(**at) < FAILURE_MEMORY)
+        .count()
+}
+
+/// Region tag from a relay's identify agent version (`... region/<tag>`)
+pub fn region_from_agent(agent_version: &str) -> Option<&str> {
+    agent_field(agent_version, REGION_PREFIX)
+}
+
+/// Check whether an address points at the local machine
+fn is_loopback(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| match p {
+        Protocol::Ip4(ip) => ip.is_loopback(),
+        Protocol::Ip6(ip) => ip.is_loopback(),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_reserves_best_candidates() {
+        let mut auto_relay = AutoRelay::new(2);
+        let slow = PeerId::random();
+        let fast = PeerId::random();
+        let configured = PeerId::random();
+
+        auto_relay.add_candidate(slow, vec![addr("/ip4/1.1.1.1/tcp/4001")], CandidateSource::Identify);
+        auto_relay.add_candidate(fast, vec![addr("/ip4/2.2.2.2/tcp/4001")], CandidateSource::Identify);
+        auto_relay.add_candidate(configured, vec![addr("/ip4/3.3.3.3/tcp/4001")], CandidateSource::Config);
+        auto_relay.record_rtt(&slow, Duration::from_millis(300));
+        auto_relay.record_rtt(&fast, Duration::from_millis(20));
+
+        let plan = auto_relay.plan();
+        let reserved: Vec<PeerId> = plan.reserve.iter().map(|(p, _)| *p).collect();
+        assert_eq!(reserved, vec![configured, fast]);
+        assert!(plan.reserve[0].1.to_string().ends_with("/p2p-circuit"));
+    }
+
+    #[test]
+    fn test_ignores_loopback_addresses() {
+        let mut auto_relay = AutoRelay::new(2);
+        let peer = PeerId::random();
+        auto_relay.add_candidate(peer, vec![addr("/ip4/127.0.0.1/tcp/4001")], CandidateSource::Identify);
+        assert!(auto_relay.plan().is_empty());
+
+        // Unless it's a relay we were told to use
+        auto_relay.add_candidate(peer, vec![addr("/ip4/127.0.0.1/tcp/4001")], CandidateSource::Config);
+        assert_eq!(auto_relay.plan().reserve.len(), 1);
+    }
+
+    #[test]
+    fn test_scales_down_when_reachable() {
+        let mut auto_relay = AutoRelay::new(2);
+        let a = PeerId::random();
+        let b = PeerId::random();
+        auto_relay.add_candidate(a, vec![addr("/ip4/1.1.1.1/tcp/4001")], CandidateSource::Config);
+        auto_relay.add_candidate(b, vec![addr("/ip4/2.2.2.2/tcp/4001")], CandidateSource::Identify);
+
+        let (id_a, id_b) = (ListenerId::next(), ListenerId::next());
+        auto_relay.mark_pending(&a, id_a);
+        auto_relay.mark_pending(&b, id_b);
+        auto_relay.on_reservation_accepted(&a);
+        auto_relay.on_reservation_accepted(&b);
+        assert_eq!(auto_relay.active_reservations(), 2);
+
+        auto_relay.set_publicly_reachable(true);
+        let plan = auto_relay.plan();
+        assert!(plan.reserve.is_empty());
+        assert_eq!(plan.release, vec![id_b]);
+    }
+
+    #[test]
+    fn test_prefers_same_region() {
+        let mut auto_relay = AutoRelay::new(1);
+        auto_relay.set_preferred_region(Some("eu-west".to_string()));
+        let far = PeerId::random();
+        let near = PeerId::random();
+
+        auto_relay.add_candidate(far, vec![addr("/ip4/1.1.1.1/tcp/4001")], CandidateSource::Config);
+        auto_relay.add_candidate(near, vec![addr("/ip4/2.2.2.2/tcp/4001")], CandidateSource::Config);
+        auto_relay.record_rtt(&far, Duration::from_millis(20));
+        auto_relay.record_rtt(&near, Duration::from_millis(80));
+        auto_relay.record_region(&far, "us-east");
+        auto_relay.record_region(&near, "eu-west");
+
+        let plan = auto_relay.plan();
+        assert_eq!(plan.reserve.len(), 1);
+        assert_eq!(plan.reserve[0].0, near);
+    }
+
+    #[test]
+    fn test_moves_to_better_region() {
+        let mut auto_relay = AutoRelay::new(1);
+        auto_relay.set_preferred_region(Some("eu-west".to_string()));
+        let far = PeerId::random();
+        let near = PeerId::random();
+
+        // Reserved before the region of the other relay was known
+        auto_relay.add_candidate(far, vec![addr("/ip4/1.1.1.1/tcp/4001")], CandidateSource::Config);
+        let id = ListenerId::next();
+        auto_relay.mark_pending(&far, id);
+        auto_relay.on_reservation_accepted(&far);
+
+        auto_relay.add_candidate(near, vec![addr("/ip4/2.2.2.2/tcp/4001")], CandidateSource::Config);
+        assert!(auto_relay.plan().is_empty());
+
+        auto_relay.record_region(&near, "eu-west");
+        let plan = auto_relay.plan();
+        assert_eq!(plan.release, vec![id]);
+        assert!(plan.reserve.is_empty());
+
+        auto_relay.on_listener_closed(id, false);
+        assert_eq!(auto_relay.plan().reserve[0].0, near);
+    }
+
+    #[test]
+    fn test_region_from_agent() {
+        assert_eq!(region_from_agent("cider-relay/0.1.0 region/eu-west"), Some("eu-west"));
+        assert_eq!(region_from_agent("cider-relay/0.1.0"), None);
+        assert_eq!(region_from_agent("cider-relay/0.1.0 region/"), None);
+    }
+
+    #[test]
+    fn test_failed_candidates_are_dropped() {
+        let mut auto_relay = AutoRelay::new(1);
+        let peer = PeerId::random();
+        auto_relay.add_candidate(peer, vec![addr("/ip4/1.1.1.1/tcp/4001")], CandidateSource::Identify);
+
+        // Released or cut off before the relay answered: not the relay's fault
+        let id = ListenerId::next();
+        auto_relay.mark_pending(&peer, id);
+        auto_relay.on_listener_closed(id, false);
+        auto_relay.mark_pending(&peer, ListenerId::next());
+        auto_relay.on_relay_disconnected(&peer);
+
+        for _ in 0..MAX_CANDIDATE_FAILURES {
+            let id = ListenerId::next();
+            auto_relay.mark_pending(&peer, id);
+            assert_eq!(auto_relay.on_listener_closed(id, true), Some(peer));
+        }
+        assert!(auto_relay.plan().is_empty());
+
+        // Tried again once the failures are old
+        assert_eq!(auto_relay.plan_at(Instant::now() + FAILURE_MEMORY).reserve.len(), 1);
+    }
+
+    #[test]
+    fn test_authentication_clears_early_denials() {
+        let mut auto_relay = AutoRelay::new(1);
+        let peer = PeerId::random();
+        auto_relay.add_candidate(peer, vec![addr("/ip4/1.1.1.1/tcp/4001")], CandidateSource::Config);
+
+        // Reservations raced ahead of the handshake and were denied
+        for _ in 0..MAX_CANDIDATE_FAILURES {
+            let id = ListenerId::next();
+            auto_relay.mark_pending(&peer, id);
+            auto_relay.on_listener_closed(id, true);
+        }
+        assert!(auto_relay.plan().is_empty());
+
+        auto_relay.on_authenticated(&peer);
+        assert_eq!(auto_relay.plan().reserve.len(), 1);
+
+        auto_relay.remove_candidate(&peer);
+        assert!(auto_relay.plan().is_empty());
+    }
+}
@@ -7,17 +7,29 @@
 //! - DCUtR for hole punching (direct connections through NAT)
 
 use futures::StreamExt;
+use libp2p::request_response::{self, ProtocolSupport};
 use libp2p::{
-    dcutr, gossipsub, identify, identity, kad, mdns, noise, ping, relay, swarm::NetworkBehaviour,
-    swarm::SwarmEvent, tcp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm,
+    dcutr, gossipsub, identify, identity, kad, mdns, noise, ping, relay, rendezvous, swarm::dial_opts::DialOpts,
+    swarm::ConnectionId, swarm::NetworkBehaviour, swarm::SwarmEvent, tcp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm,
 };
-use std::collections::HashSet;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::mpsc;
 use tracing::{debug, info, warn};
 
-use crate::sync::SyncMessage;
+use crate::sync::{InvalidMessage, MessageClass, SyncMessage};
+
+use super::autorelay::{self, AutoRelay, CandidateSource, DEFAULT_MAX_RELAY_RESERVATIONS};
+use super::discovery::{DiscoveryChain, DiscoveryStage, DiscoveryState};
+use super::signaling;
+use super::event_log::{self, NetworkLogEntry, NetworkLogKind, SharedNetworkEventLog};
+use super::latency_probe::{self, ProbeRequest, ProbeResponse};
+use super::offenders::{self, Offenders};
+use super::relay_access::{self, AuthRequest, AuthResponse};
+use super::relay_lookup::{self, LookupRequest, LookupResponse};
+use super::room_topics::{RoomPeers, RoomTopics};
 
 /// Default IPFS bootstrap nodes with direct TCP/QUIC addresses
 /// Using direct IP addresses to avoid DNS resolution issues with /dnsaddr
@@ -39,6 +51,22 @@ const DEFAULT_BOOTSTRAP_NODES: &[&str] = &[
 /// Default signaling server URL (ntfy.sh)
 const DEFAULT_SIGNALING_URL: &str = "https://ntfy.sh";
 
+/// How often lost or failed relay reservations are retried
+const RELAY_MAINTENANCE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The same while the app is in the background, to save battery
+const BACKGROUND_RELAY_MAINTENANCE_INTERVAL: Duration = Duration::from_secs(120);
+
+/// How long a ping waits for its answer (pings are sent every few seconds)
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the discovery chain checks whether its stage has timed out
+const DISCOVERY_TICK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long the room's old topic is kept after its code changes, for peers
+/// that haven't switched to the new one yet
+const ROOM_CODE_OVERLAP: Duration = Duration::from_secs(30);
+
 /// Network configuration
 #[derive(Debug, Clone)]
 pub struct NetworkConfig {
@@ -51,6 +79,17 @@ pub struct NetworkConfig {
     pub enable_mdns: bool,
     /// Whether to enable DHT for internet discovery
     pub enable_dht: bool,
+    /// Relay nodes to prefer for reservations (in addition to relays found via identify)
+    /// Format: "/ip4/1.2.3.4/tcp/4001/p2p/PEER_ID"
+    pub relay_nodes: Vec<String>,
+    /// Maximum number of relay reservations to hold while not publicly reachable
+    pub max_relay_reservations: usize,
+    /// Shared access token for private relays (a proof is sent in the relay auth handshake)
+    pub relay_access_token: Option<String>,
+    /// Region to prefer relays from (matched against the relays' `region/` tag)
+    pub preferred_relay_region: Option<String>,
+    /// Identity to use, e.g. one saved from an earlier run (None = a new one)
+    pub identity: Option<identity::Keypair>,
 }
 
 impl Default for NetworkConfig {
@@ -60,6 +99,11 @@ impl Default for NetworkConfig {
             signaling_url: DEFAULT_SIGNALING_URL.to_string(),
             enable_mdns: true,
             enable_dht: true,
+            relay_nodes: Vec::new(),
+            max_relay_reservations: DEFAULT_MAX_RELAY_RESERVATIONS,
+            relay_access_token: None,
+            preferred_relay_region: None,
+            identity: None,
         }
     }
 }
@@ -117,6 +161,14 @@ pub struct CiderBehaviour {
     gossipsub: gossipsub::Behaviour,
     /// Kademlia DHT for peer discovery over internet
     kademlia: kad::Behaviour<kad::store::MemoryStore>,
+    /// Token handshake with private relays
+    relay_auth: request_response::json::Behaviour<AuthRequest, AuthResponse>,
+    /// Latency probes sent straight to one peer
+    latency_probe: request_response::json::Behaviour<ProbeRequest, ProbeResponse>,
+    /// Registration in the room with the relays we reserve on
+    rendezvous: rendezvous::client::Behaviour,
+    /// Asking our relays where a room's peers are
+    relay_lookup: request_response::json::Behaviour<LookupRequest, LookupResponse>,
 }
 
 /// Events emitted by the network manager
@@ -126,6 +178,12 @@ pub enum NetworkEvent {
     Ready { peer_id: String },
     /// Received a sync message from a peer
     Message { from: String, message: SyncMessage },
+    /// A peer answered our ping
+    Pong {
+        from: String,
+        ping_sent_at_ms: u64,
+        received_at_ms: u64,
+    },
     /// A peer subscribed to our room topic
     PeerSubscribed { peer_id: String },
     /// A peer unsubscribed from our room topic
@@ -143,10 +201,57 @@ pub enum NetworkEvent {
         /// Whether DHT bootstrap completed
         dht_ready: bool,
     },
+    /// The discovery chain for the room being joined moved on
+    DiscoveryProgress {
+        stage: DiscoveryStage,
+        state: DiscoveryState,
+    },
     /// Error occurred
     Error(String),
 }
 
+/// How we're connected to a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerTransport {
+    /// At least one connection that doesn't go through a relay
+    Direct,
+    /// Only relayed circuits
+    Relayed,
+}
+
+/// Snapshot of the network's connectivity, for diagnostics
+#[derive(Debug, Clone, Default)]
+pub struct NetworkStatus {
+    /// Our listening addresses (relayed ones included)
+    pub listening_addresses: Vec<String>,
+    /// Confirmed addresses other peers can reach us on
+    pub external_addresses: Vec<String>,
+    /// Relay servers we hold a connection to, by peer ID
+    pub relays: Vec<String>,
+    /// How each connected peer is reached, by peer ID
+    pub peer_transports: HashMap<String, PeerTransport>,
+    /// Peers in our gossipsub mesh for the room topic
+    pub mesh_peers: usize,
+    /// Peers with an open connection
+    pub connected_peers: usize,
+    /// Peers subscribed to our room topic
+    pub room_peers: usize,
+    /// Connected relay servers
+    pub relay_connections: usize,
+    /// Relay reservations held
+    pub active_relay_reservations: usize,
+    /// Relay reservations AutoRelay aims for (0 when publicly reachable)
+    pub target_relay_reservations: usize,
+    /// Whether we have a confirmed external address (reachable without a relay)
+    pub publicly_reachable: bool,
+    /// Bootstrap nodes we're connected to
+    pub connected_bootstrap_nodes: usize,
+    /// Bootstrap nodes configured
+    pub total_bootstrap_nodes: usize,
+    /// Whether DHT bootstrap completed
+    pub dht_ready: bool,
+}
+
 /// Commands sent to the network manager
 #[derive(Debug)]
 pub enum NetworkCommand {
@@ -154,12 +259,26 @@ pub enum NetworkCommand {
     CreateRoom { room_code: String },
     /// Join a room with the given code
     JoinRoom { room_code: String },
+    /// Move the current room to a new code
+    ChangeRoomCode { room_code: String },
+    /// Publish our addresses to signaling under the current room's code
+    AnnounceRoom,
+    /// Signaling has no recent announcement for the room being joined
+    RoomNotAnnounced,
     /// Leave the current room
     LeaveRoom,
     /// Broadcast a message to the room
-    Broadcast { message: SyncMessage },
+    Broadcast { message: Box<SyncMessage> },
+    /// Take a class of the room's messages, or stop taking it
+    SetReceives { class: MessageClass, receive: bool },
+    /// The room's host changed
+    SetRoomHost { peer_id: String },
+    /// Ping one peer to measure the latency to it
+    Ping { peer_id: String, sent_at_ms: u64 },
     /// Dial a peer directly by multiaddr (for manual connection)
     DialPeer { multiaddr: String },
+    /// The app went to the background (true) or came back (false)
+    SetBackground { background: bool },
     /// Shutdown the network
     Shutdown,
 }
@@ -169,9 +288,27 @@ pub enum NetworkCommand {
 pub struct NetworkHandle {
     command_tx: mpsc::UnboundedSender<NetworkCommand>,
     pub local_peer_id: String,
+    /// Recent network events for debugging
+    event_log: SharedNetworkEventLog,
+    /// Latest connectivity snapshot
+    status: Arc<RwLock<NetworkStatus>>,
 }
 
 impl NetworkHandle {
+    /// A handle with no network behind it: its commands go to the returned
+    /// receiver, for tests that stand in for the network
+    #[cfg(test)]
+    pub(crate) fn detached(local_peer_id: &str) -> (Self, mpsc::UnboundedReceiver<NetworkCommand>) {
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let handle = Self {
+            command_tx,
+            local_peer_id: local_peer_id.to_string(),
+            event_log: event_log::new_shared_event_log(),
+            status: Arc::new(RwLock::new(NetworkStatus::default())),
+        };
+        (handle, command_rx)
+    }
+
     pub fn create_room(&self, room_code: &str) -> Result<(), NetworkError> {
         self.command_tx
             .send(NetworkCommand::CreateRoom {
@@ -188,6 +325,28 @@ impl NetworkHandle {
             .map_err(|_| NetworkError::Libp2p("Network task closed".to_string()))
     }
 
+    /// Move the room to `room_code`, keeping the old topic for a while for
+    /// peers that haven't switched yet
+    pub fn change_room_code(&self, room_code: &str) -> Result<(), NetworkError> {
+        self.command_tx
+            .send(NetworkCommand::ChangeRoomCode {
+                room_code: room_code.to_string(),
+            })
+            .map_err(|_| NetworkError::Libp2p("Network task closed".to_string()))
+    }
+
+    pub fn announce_room(&self) -> Result<(), NetworkError> {
+        self.command_tx
+            .send(NetworkCommand::AnnounceRoom)
+            .map_err(|_| NetworkError::Libp2p("Network task closed".to_string()))
+    }
+
+    pub fn room_not_announced(&self) -> Result<(), NetworkError> {
+        self.command_tx
+            .send(NetworkCommand::RoomNotAnnounced)
+            .map_err(|_| NetworkError::Libp2p("Network task closed".to_string()))
+    }
+
     pub fn leave_room(&self) -> Result<(), NetworkError> {
         self.command_tx
             .send(NetworkCommand::LeaveRoom)
@@ -196,7 +355,34 @@ impl NetworkHandle {
 
     pub fn broadcast(&self, message: SyncMessage) -> Result<(), NetworkError> {
         self.command_tx
-            .send(NetworkCommand::Broadcast { message })
+            .send(NetworkCommand::Broadcast { message: Box::new(message) })
+            .map_err(|_| NetworkError::Libp2p("Network task closed".to_string()))
+    }
+
+    /// Take the room's messages of `class` (the default), or stop taking them
+    /// until the next room
+    pub fn set_receives(&self, class: MessageClass, receive: bool) -> Result<(), NetworkError> {
+        self.command_tx
+            .send(NetworkCommand::SetReceives { class, receive })
+            .map_err(|_| NetworkError::Libp2p("Network task closed".to_string()))
+    }
+
+    /// The room is now hosted by `peer_id`, whose messages never count against it
+    pub fn set_room_host(&self, peer_id: &str) -> Result<(), NetworkError> {
+        self.command_tx
+            .send(NetworkCommand::SetRoomHost {
+                peer_id: peer_id.to_string(),
+            })
+            .map_err(|_| NetworkError::Libp2p("Network task closed".to_string()))
+    }
+
+    /// Ping `peer_id` directly (answered with a `Pong` event)
+    pub fn ping(&self, peer_id: &str, sent_at_ms: u64) -> Result<(), NetworkError> {
+        self.command_tx
+            .send(NetworkCommand::Ping {
+                peer_id: peer_id.to_string(),
+                sent_at_ms,
+            })
             .map_err(|_| NetworkError::Libp2p("Network task closed".to_string()))
     }
 
@@ -204,6 +390,16 @@ impl NetworkHandle {
         let _ = self.command_tx.send(NetworkCommand::Shutdown);
     }
 
+    /// Wait until the network task has stopped (and its swarm is gone)
+    pub async fn closed(&self) {
+        self.command_tx.closed().await
+    }
+
+    /// Slow down upkeep while the app is in the background, or resume it
+    pub fn set_background(&self, background: bool) {
+        let _ = self.command_tx.send(NetworkCommand::SetBackground { background });
+    }
+
     pub fn dial_peer(&self, multiaddr: &str) -> Result<(), NetworkError> {
         self.command_tx
             .send(NetworkCommand::DialPeer {
@@ -211,6 +407,29 @@ impl NetworkHandle {
             })
             .map_err(|_| NetworkError::Libp2p("Network task closed".to_string()))
     }
+
+    /// Get recent network events (oldest first)
+    pub fn recent_events(&self) -> Vec<NetworkLogEntry> {
+        self.event_log.read().unwrap().entries()
+    }
+
+    /// Get the current connectivity snapshot
+    pub fn status(&self) -> NetworkStatus {
+        self.status.read().unwrap().clone()
+    }
+}
+
+/// A room topic being left after the room's code changed
+///
+/// Peers that haven't switched to the new topic yet still hear us on it, and
+/// we them. Only the room's peers from before the change are heard there, so
+/// the old code doesn't get anyone new in meanwhile.
+struct PreviousRoomTopic {
+    topics: RoomTopics,
+    /// Room peers not seen on the new topic yet
+    pending: HashSet<PeerId>,
+    /// When the topic is dropped, switched or not
+    until: Instant,
 }
 
 /// Manages P2P networking - runs in a background task
@@ -223,14 +442,26 @@ pub struct NetworkManager {
     config: NetworkConfig,
     /// Discovered peers (via mDNS or relay)
     discovered_peers: HashSet<PeerId>,
-    /// Current room topic (if in a room)
-    room_topic: Option<gossipsub::IdentTopic>,
+    /// Current room topics (if in a room)
+    room_topics: Option<RoomTopics>,
+    /// Classes of room messages we don't take, until the next room
+    skipped_classes: HashSet<MessageClass>,
     /// Current room code (for DHT cleanup)
     room_code: Option<String>,
-    /// Peers subscribed to our room topic
-    room_peers: HashSet<PeerId>,
+    /// The room's topic before its code last changed, while peers switch over
+    previous_room_topic: Option<PreviousRoomTopic>,
+    /// Discovery for the room being joined, until a peer in it is found
+    discovery: Option<DiscoveryChain>,
+    /// Peers subscribed to our room's topics
+    room_peers: RoomPeers,
+    /// Peers whose messages were past the protocol's limits
+    offenders: Offenders,
+    /// Our direct pings awaiting an answer, by when they were sent
+    pending_probes: HashMap<request_response::OutboundRequestId, u64>,
     /// Connected relay servers
     connected_relays: HashSet<PeerId>,
+    /// Open connections per peer, and whether each is relayed
+    peer_connections: HashMap<PeerId, HashMap<ConnectionId, bool>>,
     /// Our listening addresses (for signaling)
     listening_addresses: Vec<String>,
     /// Connected bootstrap node peer IDs
@@ -239,6 +470,14 @@ pub struct NetworkManager {
     expected_bootstrap_peers: HashSet<PeerId>,
     /// Whether DHT bootstrap has completed
     dht_bootstrapped: bool,
+    /// Relay candidates and reservation management
+    auto_relay: AutoRelay,
+    /// Relays that accepted our access token on the current connection
+    authenticated_relays: HashSet<PeerId>,
+    /// Ring buffer of recent network events
+    event_log: SharedNetworkEventLog,
+    /// Connectivity snapshot shared with handles
+    status: Arc<RwLock<NetworkStatus>>,
 }
 
 impl NetworkManager {
@@ -249,7 +488,7 @@ impl NetworkManager {
 
     /// Create a new network manager with custom config
     pub fn with_config(config: NetworkConfig) -> Result<Self, NetworkError> {
-        let keypair = identity::Keypair::generate_ed25519();
+        let keypair = config.identity.clone().unwrap_or_else(identity::Keypair::generate_ed25519);
         let local_peer_id = PeerId::from(keypair.public());
 
         info!("Local peer ID: {}", local_peer_id);
@@ -275,19 +514,46 @@ impl NetworkManager {
             config.signaling_url
         );
 
+        // Seed AutoRelay with configured relay nodes
+        let mut auto_relay = AutoRelay::new(config.max_relay_reservations);
+        auto_relay.set_preferred_region(config.preferred_relay_region.clone());
+        for addr_str in &config.relay_nodes {
+            match addr_str.parse::<Multiaddr>() {
+                Ok(addr) => match addr.iter().last() {
+                    Some(libp2p::multiaddr::Protocol::P2p(peer_id)) => {
+                        let mut relay_addr = addr.clone();
+                        relay_addr.pop();
+                        auto_relay.add_candidate(peer_id, vec![relay_addr], CandidateSource::Config);
+                    }
+                    _ => warn!("Relay node address is missing /p2p/ peer ID: {}", addr),
+                },
+                Err(e) => warn!("Invalid relay node address {}: {}", addr_str, e),
+            }
+        }
+
         Ok(Self {
             local_peer_id,
             keypair,
             config,
             discovered_peers: HashSet::new(),
-            room_topic: None,
+            room_topics: None,
+            skipped_classes: HashSet::new(),
             room_code: None,
-            room_peers: HashSet::new(),
+            previous_room_topic: None,
+            discovery: None,
+            room_peers: RoomPeers::default(),
+            offenders: Offenders::default(),
+            pending_probes: HashMap::new(),
             connected_relays: HashSet::new(),
+            peer_connections: HashMap::new(),
             listening_addresses: Vec::new(),
             connected_bootstrap_peers: HashSet::new(),
             expected_bootstrap_peers,
             dht_bootstrapped: false,
+            auto_relay,
+            authenticated_relays: HashSet::new(),
+            event_log: event_log::new_shared_event_log(),
+            status: Arc::new(RwLock::new(NetworkStatus::default())),
         })
     }
 
@@ -318,6 +584,8 @@ impl NetworkManager {
         let handle = NetworkHandle {
             command_tx,
             local_peer_id: local_peer_id.clone(),
+            event_log: self.event_log.clone(),
+            status: self.status.clone(),
         };
 
         // Spawn the network task
@@ -341,6 +609,7 @@ impl NetworkManager {
             .iter()
             .map(|s| s.to_string())
             .collect();
+        let agent_version = relay_access::agent_version();
 
         let swarm = libp2p::SwarmBuilder::with_existing_identity(self.keypair.clone())
             .with_tokio()
@@ -397,10 +666,10 @@ impl NetworkManager {
                 .map_err(|e| e.to_string())?;
 
                 // Identify config
-                let identify = identify::Behaviour::new(identify::Config::new(
-                    "/cider-together/1.0.0".into(),
-                    keypair.public(),
-                ));
+                let identify = identify::Behaviour::new(
+                    identify::Config::new("/cider-together/1.0.0".into(), keypair.public())
+                        .with_agent_version(agent_version),
+                );
 
                 // Kademlia DHT for peer discovery
                 // Use IPFS protocol to leverage the public IPFS DHT network
@@ -425,6 +694,24 @@ impl NetworkManager {
                     }
                 }
 
+                // Token handshake for private relays (we only ever ask)
+                let relay_auth = request_response::json::Behaviour::new(
+                    [(relay_access::AUTH_PROTOCOL, ProtocolSupport::Outbound)],
+                    request_response::Config::default(),
+                );
+
+                // Latency probes: we ping and answer pings
+                let latency_probe = request_response::json::Behaviour::new(
+                    [(latency_probe::PROBE_PROTOCOL, ProtocolSupport::Full)],
+                    request_response::Config::default().with_request_timeout(PROBE_TIMEOUT),
+                );
+
+                // Room lookups on relays (we only ever ask)
+                let relay_lookup = request_response::json::Behaviour::new(
+                    [(relay_lookup::LOOKUP_PROTOCOL, ProtocolSupport::Outbound)],
+                    request_response::Config::default(),
+                );
+
                 Ok(CiderBehaviour {
                     ping,
                     relay_client,
@@ -433,6 +720,10 @@ impl NetworkManager {
                     identify,
                     gossipsub,
                     kademlia,
+                    relay_auth,
+                    latency_probe,
+                    rendezvous: rendezvous::client::Behaviour::new(keypair.clone()),
+                    relay_lookup,
                 })
             })
             .map_err(|e| NetworkError::Transport(e.to_string()))?
@@ -455,6 +746,162 @@ impl NetworkManager {
         }
     }
 
+    /// Record an event in the debug ring buffer
+    fn log_event(&self, kind: NetworkLogKind, message: impl Into<String>) {
+        self.event_log.write().unwrap().record(kind, message);
+    }
+
+    /// Connect to configured relay nodes so AutoRelay can reserve on them
+    fn connect_to_relay_nodes(&self, swarm: &mut Swarm<CiderBehaviour>) {
+        for addr_str in &self.config.relay_nodes {
+            if let Ok(addr) = addr_str.parse::<Multiaddr>() {
+                info!("Connecting to relay node: {}", addr);
+                if let Err(e) = swarm.dial(addr.clone()) {
+                    debug!("Failed to dial relay node {}: {}", addr, e);
+                }
+            }
+        }
+    }
+
+    /// Open or close relay reservations to match AutoRelay's plan
+    fn maintain_relay_reservations(&mut self, swarm: &mut Swarm<CiderBehaviour>) {
+        let plan = self.auto_relay.plan();
+        if plan.is_empty() {
+            return;
+        }
+
+        for listener_id in plan.release {
+            info!("AutoRelay: releasing relay listener {:?}", listener_id);
+            self.auto_relay.on_listener_closed(listener_id, false);
+            swarm.remove_listener(listener_id);
+        }
+
+        for (relay_peer_id, relay_addr) in plan.reserve {
+            // The relay client would dial the relay itself, but that dial is refused
+            // while ours is still in flight (e.g. at startup) and the reservation
+            // closes as a failure. Connect first; identify plans again once we're in.
+            if !swarm.is_connected(&relay_peer_id) {
+                let mut addr = relay_addr.clone();
+                addr.pop(); // /p2p-circuit
+                let opts = DialOpts::peer_id(relay_peer_id).addresses(vec![addr]).build();
+                if let Err(e) = swarm.dial(opts) {
+                    debug!("Not dialing relay {}: {}", relay_peer_id, e);
+                }
+                continue;
+            }
+            info!("AutoRelay: requesting reservation on {}", relay_addr);
+            match swarm.listen_on(relay_addr.clone()) {
+                Ok(id) => self.auto_relay.mark_pending(&relay_peer_id, id),
+                Err(e) => {
+                    warn!("Failed to listen on relay {}: {}", relay_addr, e);
+                    self.auto_relay.record_failure(&relay_peer_id);
+                }
+            }
+        }
+    }
+
+    /// Register in the room with `relay`, where we hold a reservation, so
+    /// joiners asking it (or its federation) can find us
+    fn register_with_relay(&self, swarm: &mut Swarm<CiderBehaviour>, relay: PeerId) {
+        let Some(code) = &self.room_code else {
+            return;
+        };
+        let Ok(namespace) = rendezvous::Namespace::new(relay_lookup::namespace(code)) else {
+            return;
+        };
+        // Fails without an external address; the reservation's circuit address brings one
+        if let Err(e) = swarm.behaviour_mut().rendezvous.register(namespace, relay, None) {
+            debug!("Not registering with relay {} yet: {}", relay, e);
+        }
+    }
+
+    /// Drop our registrations in room `code` with our relays
+    fn unregister_from_relays(&self, swarm: &mut Swarm<CiderBehaviour>, code: &str) {
+        let Ok(namespace) = rendezvous::Namespace::new(relay_lookup::namespace(code)) else {
+            return;
+        };
+        for relay in &self.connected_relays {
+            swarm.behaviour_mut().rendezvous.unregister(namespace.clone(), *relay);
+        }
+    }
+
+    /// Ask our relays where the room's peers are (answered with circuit addresses to dial)
+    fn look_up_room(&self, swarm: &mut Swarm<CiderBehaviour>) {
+        let Some(code) = &self.room_code else {
+            return;
+        };
+        for relay in &self.connected_relays {
+            let request = LookupRequest { namespace: relay_lookup::namespace(code) };
+            swarm.behaviour_mut().relay_lookup.send_request(relay, request);
+        }
+    }
+
+    /// Hand our relay addresses to signaling (local ones are of no use there),
+    /// even if there are none yet: it still shows the room is active
+    fn announce_room(&self, event_tx: &mpsc::UnboundedSender<NetworkEvent>) {
+        let relay_addresses: Vec<String> = self.listening_addresses
+            .iter()
+            .filter(|a| a.contains("p2p-circuit"))
+            .cloned()
+            .collect();
+        let _ = event_tx.send(NetworkEvent::ListeningAddresses {
+            addresses: relay_addresses,
+        });
+    }
+
+    /// Start the discovery chain for the room we just joined
+    fn start_discovery(&mut self, event_tx: &mpsc::UnboundedSender<NetworkEvent>) {
+        self.discovery = DiscoveryChain::start(self.config.enable_mdns, self.config.enable_dht, Instant::now());
+        if let Some(chain) = &self.discovery {
+            self.send_discovery_progress(event_tx, chain.stage(), DiscoveryState::Trying);
+        }
+    }
+
+    /// Move the discovery chain on if its stage timed out
+    fn poll_discovery(&mut self, swarm: &mut Swarm<CiderBehaviour>, event_tx: &mpsc::UnboundedSender<NetworkEvent>) {
+        let Some(chain) = self.discovery.as_mut() else {
+            return;
+        };
+        match chain.poll(Instant::now(), |peer| swarm.is_connected(peer)) {
+            None => {}
+            Some(Ok(stage)) => {
+                if stage == DiscoveryStage::Relays {
+                    self.look_up_room(swarm);
+                }
+                // The DHT may not have been ready when the room was joined: search again
+                if stage == DiscoveryStage::Dht {
+                    if let Some(code) = &self.room_code {
+                        let room_key = kad::RecordKey::new(&format!("cider-room-{}", code));
+                        swarm.behaviour_mut().kademlia.get_providers(room_key);
+                    }
+                }
+                self.send_discovery_progress(event_tx, stage, DiscoveryState::Trying);
+            }
+            Some(Err(reason)) => {
+                let stage = chain.stage();
+                self.discovery = None;
+                self.send_discovery_progress(event_tx, stage, DiscoveryState::Failed { reason });
+            }
+        }
+    }
+
+    /// Report discovery progress (and log it)
+    fn send_discovery_progress(
+        &self,
+        event_tx: &mpsc::UnboundedSender<NetworkEvent>,
+        stage: DiscoveryStage,
+        state: DiscoveryState,
+    ) {
+        let message = match &state {
+            DiscoveryState::Trying => format!("Discovery: trying {:?}", stage),
+            DiscoveryState::Found { peer_id } => format!("Discovery: found {} via {:?}", peer_id, stage),
+            DiscoveryState::Failed { reason } => format!("Discovery failed: {}", reason),
+        };
+        info!("{}", message);
+        self.log_event(NetworkLogKind::Discovery, message);
+        let _ = event_tx.send(NetworkEvent::DiscoveryProgress { stage, state });
+    }
+
     /// Send bootstrap status event
     fn send_bootstrap_status(&self, event_tx: &mpsc::UnboundedSender<NetworkEvent>) {
         let _ = event_tx.send(NetworkEvent::BootstrapStatus {
@@ -465,6 +912,43 @@ impl NetworkManager {
         });
     }
 
+    /// Refresh the connectivity snapshot handles read
+    fn publish_status(&self, swarm: &Swarm<CiderBehaviour>) {
+        let peer_transports = self
+            .peer_connections
+            .iter()
+            .map(|(peer_id, connections)| {
+                let transport = if connections.values().all(|&relayed| relayed) {
+                    PeerTransport::Relayed
+                } else {
+                    PeerTransport::Direct
+                };
+                (peer_id.to_string(), transport)
+            })
+            .collect();
+        let mesh_peers = self.room_topics.as_ref().map_or(0, |topics| {
+            let topic = topics.get(MessageClass::Control);
+            swarm.behaviour().gossipsub.mesh_peers(&topic.hash()).count()
+        });
+
+        *self.status.write().unwrap() = NetworkStatus {
+            listening_addresses: self.listening_addresses.clone(),
+            external_addresses: swarm.external_addresses().map(|a| a.to_string()).collect(),
+            relays: self.connected_relays.iter().map(|p| p.to_string()).collect(),
+            peer_transports,
+            mesh_peers,
+            connected_peers: swarm.connected_peers().count(),
+            room_peers: self.room_peers.len(),
+            relay_connections: self.connected_relays.len(),
+            active_relay_reservations: self.auto_relay.active_reservations(),
+            target_relay_reservations: self.auto_relay.target_reservations(),
+            publicly_reachable: self.auto_relay.is_publicly_reachable(),
+            connected_bootstrap_nodes: self.connected_bootstrap_peers.len(),
+            total_bootstrap_nodes: self.expected_bootstrap_peers.len(),
+            dht_ready: self.dht_bootstrapped,
+        };
+    }
+
     /// Run the network event loop
     async fn run(
         mut self,
@@ -486,10 +970,15 @@ impl NetworkManager {
         }
 
         // Connect to bootstrap nodes for internet connectivity
-        self.connect_to_bootstrap_nodes(&mut swarm);
+        if self.config.enable_dht {
+            self.connect_to_bootstrap_nodes(&mut swarm);
+        }
+        self.connect_to_relay_nodes(&mut swarm);
 
         // Bootstrap the Kademlia DHT
-        if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
+        if !self.config.enable_dht {
+            info!("DHT disabled, finding peers on the local network only");
+        } else if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
             warn!("Failed to bootstrap Kademlia DHT: {:?}", e);
         } else {
             info!("Kademlia DHT bootstrap started");
@@ -500,12 +989,31 @@ impl NetworkManager {
             peer_id: self.local_peer_id.to_string(),
         });
 
+        // Periodically retry relay reservations that were lost or failed
+        let mut relay_maintenance = tokio::time::interval(RELAY_MAINTENANCE_INTERVAL);
+        let mut discovery_tick = tokio::time::interval(DISCOVERY_TICK_INTERVAL);
+        // Announcements on signaling only count for a few minutes
+        let mut room_announcement = tokio::time::interval(signaling::ANNOUNCE_INTERVAL);
+
         loop {
+            let previous_topic_until = self.previous_room_topic.as_ref().map(|previous| previous.until);
             tokio::select! {
                 // Handle swarm events
                 event = swarm.select_next_some() => {
                     self.handle_swarm_event(&mut swarm, event, &event_tx);
                 }
+                _ = relay_maintenance.tick() => {
+                    self.maintain_relay_reservations(&mut swarm);
+                }
+                _ = discovery_tick.tick(), if self.discovery.is_some() => {
+                    self.poll_discovery(&mut swarm, &event_tx);
+                }
+                _ = room_announcement.tick(), if self.room_topics.is_some() => {
+                    self.announce_room(&event_tx);
+                }
+                _ = sleep_until(previous_topic_until) => {
+                    self.drop_previous_room_topic(&mut swarm, &event_tx);
+                }
                 // Handle commands
                 Some(cmd) = command_rx.recv() => {
                     match cmd {
@@ -513,6 +1021,7 @@ impl NetworkManager {
                             if let Err(e) = self.create_room(&mut swarm, &room_code) {
                                 let _ = event_tx.send(NetworkEvent::Error(e.to_string()));
                             } else {
+                                self.room_peers_already_subscribed(&swarm, &event_tx);
                                 // Send relay addresses for signaling (local addresses filtered out)
                                 // Note: Relay addresses may not be available yet - they'll be sent
                                 // via NewListenAddr event when the relay reservation is accepted
@@ -533,6 +1042,8 @@ impl NetworkManager {
                             if let Err(e) = self.join_room(&mut swarm, &room_code) {
                                 let _ = event_tx.send(NetworkEvent::Error(e.to_string()));
                             } else {
+                                self.start_discovery(&event_tx);
+                                self.room_peers_already_subscribed(&swarm, &event_tx);
                                 // Send relay addresses for signaling (local addresses filtered out)
                                 let relay_addresses: Vec<String> = self.listening_addresses
                                     .iter()
@@ -547,6 +1058,25 @@ impl NetworkManager {
                                 }
                             }
                         }
+                        NetworkCommand::ChangeRoomCode { room_code } => {
+                            if let Err(e) = self.change_room_code(&mut swarm, &room_code, &event_tx) {
+                                let _ = event_tx.send(NetworkEvent::Error(e.to_string()));
+                            } else {
+                                // Signaling is by room code: publish our addresses under the new one
+                                self.announce_room(&event_tx);
+                            }
+                        }
+                        NetworkCommand::AnnounceRoom => {
+                            if self.room_topics.is_some() {
+                                self.announce_room(&event_tx);
+                            }
+                        }
+                        NetworkCommand::RoomNotAnnounced => {
+                            if let Some(chain) = self.discovery.as_mut() {
+                                chain.room_not_announced();
+                                self.log_event(NetworkLogKind::Discovery, "Room isn't announced on signaling");
+                            }
+                        }
                         NetworkCommand::LeaveRoom => {
                             let _ = self.leave_room(&mut swarm);
                         }
@@ -555,10 +1085,33 @@ impl NetworkManager {
                                 debug!("Broadcast error (may be no peers yet): {}", e);
                             }
                         }
+                        NetworkCommand::SetReceives { class, receive } => {
+                            self.set_receives(&mut swarm, class, receive);
+                        }
+                        NetworkCommand::SetRoomHost { peer_id } => match peer_id.parse::<PeerId>() {
+                            Ok(peer) => self.offenders.set_host(peer),
+                            Err(e) => debug!("Ignoring invalid host peer ID {}: {}", peer_id, e),
+                        },
+                        NetworkCommand::Ping { peer_id, sent_at_ms } => match peer_id.parse::<PeerId>() {
+                            Ok(peer) if swarm.is_connected(&peer) => {
+                                let request = ProbeRequest { sent_at_ms };
+                                let request_id = swarm.behaviour_mut().latency_probe.send_request(&peer, request);
+                                self.pending_probes.insert(request_id, sent_at_ms);
+                            }
+                            // Only reached through the mesh
+                            Ok(_) => self.ping_on_topic(&mut swarm, sent_at_ms),
+                            Err(e) => debug!("Not pinging invalid peer ID {}: {}", peer_id, e),
+                        },
                         NetworkCommand::DialPeer { multiaddr } => {
                             match multiaddr.parse::<Multiaddr>() {
                                 Ok(addr) => {
                                     info!("Dialing peer at {}", addr);
+                                    // Signaling hands the host's addresses over this way
+                                    if let Some(libp2p::multiaddr::Protocol::P2p(peer_id)) = addr.iter().last() {
+                                        if let Some(chain) = self.discovery.as_mut() {
+                                            chain.add_candidate(peer_id, DiscoveryStage::Signaling);
+                                        }
+                                    }
                                     if let Err(e) = swarm.dial(addr) {
                                         warn!("Failed to dial peer: {}", e);
                                     }
@@ -568,6 +1121,19 @@ impl NetworkManager {
                                 }
                             }
                         }
+                        NetworkCommand::SetBackground { background } => {
+                            let period = if background {
+                                info!("App in background, slowing down relay upkeep");
+                                BACKGROUND_RELAY_MAINTENANCE_INTERVAL
+                            } else {
+                                // Connections may have died while the app was suspended
+                                info!("App in foreground, reconnecting to relays");
+                                self.connect_to_relay_nodes(&mut swarm);
+                                RELAY_MAINTENANCE_INTERVAL
+                            };
+                            // Ticks right away, so reservations are checked now too
+                            relay_maintenance = tokio::time::interval(period);
+                        }
                         NetworkCommand::Shutdown => {
                             info!("Network shutting down");
                             break;
@@ -575,6 +1141,12 @@ impl NetworkManager {
                     }
                 }
             }
+
+            // Everyone switched to the new room code: no need to wait it out
+            if self.previous_room_topic.as_ref().is_some_and(|previous| previous.pending.is_empty()) {
+                self.drop_previous_room_topic(&mut swarm, &event_tx);
+            }
+            self.publish_status(&swarm);
         }
 
         Ok(())
@@ -600,11 +1172,12 @@ impl NetworkManager {
                 let is_relay = full_addr.contains("p2p-circuit");
 
                 info!("Listening on {} (relay: {})", full_addr, is_relay);
+                self.log_event(NetworkLogKind::Connection, format!("Listening on {}", full_addr));
                 self.listening_addresses.push(full_addr.clone());
 
                 // If we're in a room, notify about new address for signaling
                 // This is important for relay addresses which are discovered after room creation
-                if self.room_topic.is_some() {
+                if self.room_topics.is_some() {
                     // Only send relay addresses for internet signaling (filter out local IPs)
                     let relay_addresses: Vec<String> = self.listening_addresses
                         .iter()
@@ -626,7 +1199,11 @@ impl NetworkManager {
                 for (peer_id, addr) in peers {
                     if peer_id != self.local_peer_id {
                         info!("mDNS discovered peer: {} at {}", peer_id, addr);
+                        self.log_event(NetworkLogKind::Discovery, format!("mDNS discovered {} at {}", peer_id, addr));
                         self.discovered_peers.insert(peer_id);
+                        if let Some(chain) = self.discovery.as_mut() {
+                            chain.add_candidate(peer_id, DiscoveryStage::Mdns);
+                        }
 
                         // Add the peer and dial them
                         swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
@@ -658,7 +1235,18 @@ impl NetworkManager {
                     relay_peer_id,
                     limit
                 );
+                self.log_event(
+                    NetworkLogKind::Relay,
+                    format!("Reservation {} by {}", if renewal { "renewed" } else { "accepted" }, relay_peer_id),
+                );
                 self.connected_relays.insert(relay_peer_id);
+                self.auto_relay.on_reservation_accepted(&relay_peer_id);
+                self.register_with_relay(swarm, relay_peer_id);
+                debug!(
+                    "AutoRelay: {}/{} reservations active",
+                    self.auto_relay.active_reservations(),
+                    self.auto_relay.target_reservations()
+                );
             }
 
             SwarmEvent::Behaviour(CiderBehaviourEvent::RelayClient(
@@ -671,6 +1259,7 @@ impl NetworkManager {
                     "Outbound circuit established through relay {} (limit: {:?})",
                     relay_peer_id, limit
                 );
+                self.log_event(NetworkLogKind::Relay, format!("Outbound circuit via {}", relay_peer_id));
             }
 
             SwarmEvent::Behaviour(CiderBehaviourEvent::RelayClient(
@@ -680,6 +1269,7 @@ impl NetworkManager {
                     "Inbound circuit established from {} (limit: {:?})",
                     src_peer_id, limit
                 );
+                self.log_event(NetworkLogKind::Relay, format!("Inbound circuit from {}", src_peer_id));
             }
 
             // DCUtR events (hole punching)
@@ -688,8 +1278,14 @@ impl NetworkManager {
                 result,
             })) => {
                 match result {
-                    Ok(_) => info!("DCUtR hole punch succeeded with {}", remote_peer_id),
-                    Err(e) => debug!("DCUtR hole punch failed with {}: {:?}", remote_peer_id, e),
+                    Ok(_) => {
+                        info!("DCUtR hole punch succeeded with {}", remote_peer_id);
+                        self.log_event(NetworkLogKind::Connection, format!("Hole punch succeeded with {}", remote_peer_id));
+                    }
+                    Err(e) => {
+                        debug!("DCUtR hole punch failed with {}: {:?}", remote_peer_id, e);
+                        self.log_event(NetworkLogKind::Connection, format!("Hole punch failed with {}: {}", remote_peer_id, e));
+                    }
                 }
             }
 
@@ -701,10 +1297,31 @@ impl NetworkManager {
                     ..
                 },
             )) => {
+                if !self.accepts_room_message(&message.topic, message.source) {
+                    debug!("Ignoring message on the room's previous topic from {:?}", message.source);
+                    return;
+                }
+                // Attribute messages to their (signed) author, not the peer that
+                // forwarded them, or the host's messages relayed by another
+                // listener would be taken as a listener's
+                let from = message.source.unwrap_or(propagation_source);
+                if self.offenders.is_ignored(&from, Instant::now()) {
+                    return;
+                }
                 if let Ok(sync_msg) = serde_json::from_slice::<SyncMessage>(&message.data) {
-                    debug!("Received message from {}: {:?}", propagation_source, sync_msg);
+                    let sync_msg = match sync_msg.within_limits() {
+                        Ok(sync_msg) => sync_msg,
+                        Err(e) => {
+                            self.reject_message(from, e);
+                            return;
+                        }
+                    };
+                    if !self.wants_room_message(&message.topic, &from, &sync_msg) {
+                        return;
+                    }
+                    debug!("Received message from {}: {:?}", from, sync_msg);
                     let _ = event_tx.send(NetworkEvent::Message {
-                        from: propagation_source.to_string(),
+                        from: from.to_string(),
                         message: sync_msg,
                     });
                 }
@@ -714,30 +1331,38 @@ impl NetworkManager {
             SwarmEvent::Behaviour(CiderBehaviourEvent::Gossipsub(
                 gossipsub::Event::Subscribed { peer_id, topic },
             )) => {
-                if let Some(our_topic) = &self.room_topic {
-                    if topic == our_topic.hash() {
-                        info!("Peer {} subscribed to room", peer_id);
-                        self.room_peers.insert(peer_id);
-                        let _ = event_tx.send(NetworkEvent::PeerSubscribed {
-                            peer_id: peer_id.to_string(),
-                        });
-                    }
-                }
+                self.peer_subscribed(peer_id, &topic, event_tx);
             }
 
             // Peer unsubscribed from topic
             SwarmEvent::Behaviour(CiderBehaviourEvent::Gossipsub(
                 gossipsub::Event::Unsubscribed { peer_id, topic },
             )) => {
-                if let Some(our_topic) = &self.room_topic {
-                    if topic == our_topic.hash() {
+                let class = self.room_topics.as_ref().and_then(|topics| topics.class_of(&topic));
+                if let Some(class) = class {
+                    // Gone once off all of them
+                    if self.room_peers.unsubscribed(&peer_id, class) {
                         info!("Peer {} unsubscribed from room", peer_id);
-                        self.room_peers.remove(&peer_id);
+                        self.log_event(NetworkLogKind::Room, format!("{} unsubscribed from room", peer_id));
                         let _ = event_tx.send(NetworkEvent::PeerUnsubscribed {
                             peer_id: peer_id.to_string(),
                         });
                     }
                 }
+
+                // Left from the old topic without switching: gone all the same
+                let left_previous = self
+                    .previous_room_topic
+                    .as_mut()
+                    .is_some_and(|previous| {
+                        previous.topics.class_of(&topic).is_some() && previous.pending.remove(&peer_id)
+                    });
+                if left_previous && self.room_peers.remove(&peer_id) {
+                    info!("Peer {} left the room from its previous topic", peer_id);
+                    let _ = event_tx.send(NetworkEvent::PeerUnsubscribed {
+                        peer_id: peer_id.to_string(),
+                    });
+                }
             }
 
             SwarmEvent::Behaviour(CiderBehaviourEvent::Identify(identify::Event::Received {
@@ -761,34 +1386,38 @@ impl NetworkManager {
                     proto.contains("circuit") && proto.contains("relay")
                 });
 
-                if supports_relay {
+                // Relays disconnect clients older than their minimum; tell the user instead of retrying
+                let required_version = relay_access::min_client_version(&info.agent_version)
+                    .filter(|minimum| !relay_access::meets_minimum(minimum));
+
+                if let Some(minimum) = required_version.filter(|_| supports_relay) {
+                    warn!("Relay {} requires Cider Together {} or newer", peer_id, minimum);
+                    self.log_event(
+                        NetworkLogKind::Error,
+                        format!("Relay {} requires Cider Together {} or newer - please update", peer_id, minimum),
+                    );
+                    self.auto_relay.remove_candidate(&peer_id);
+                } else if supports_relay {
                     info!(
-                        "Peer {} supports relay protocol, requesting reservation via {} addresses",
+                        "Peer {} supports relay protocol, adding as relay candidate ({} addresses)",
                         peer_id,
                         info.listen_addrs.len()
                     );
 
-                    // Request relay reservation through each non-localhost address
                     // The server should advertise its public IP via add_external_address()
-                    for addr in &info.listen_addrs {
-                        let addr_str = addr.to_string();
-
-                        // Skip localhost - can't be used for relay
-                        if addr_str.contains("127.0.0.1") || addr_str.contains("/ip6/::1/") {
-                            continue;
-                        }
-
-                        // Build relay address: /ip4/.../tcp/.../p2p/RELAY_ID/p2p-circuit
-                        let relay_addr = addr
-                            .clone()
-                            .with(libp2p::multiaddr::Protocol::P2p(peer_id))
-                            .with(libp2p::multiaddr::Protocol::P2pCircuit);
+                    self.auto_relay.add_candidate(peer_id, info.listen_addrs.clone(), CandidateSource::Identify);
+                    if let Some(region) = autorelay::region_from_agent(&info.agent_version) {
+                        debug!("Relay {} is in region {}", peer_id, region);
+                        self.auto_relay.record_region(&peer_id, region);
+                    }
 
-                        info!("Requesting relay listen on: {}", relay_addr);
-                        match swarm.listen_on(relay_addr.clone()) {
-                            Ok(id) => info!("Relay listen request accepted, listener id: {:?}", id),
-                            Err(e) => warn!("Failed to listen on relay {}: {}", relay_addr, e),
+                    // Private relays refuse reservations until we've presented the token
+                    match &self.config.relay_access_token {
+                        Some(token) if !self.authenticated_relays.contains(&peer_id) => {
+                            let proof = relay_access::handshake_proof(token, &self.local_peer_id, &peer_id);
+                            swarm.behaviour_mut().relay_auth.send_request(&peer_id, AuthRequest { proof });
                         }
+                        _ => self.maintain_relay_reservations(swarm),
                     }
                 } else {
                     debug!(
@@ -799,8 +1428,16 @@ impl NetworkManager {
                 }
             }
 
-            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+            SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
                 info!("Connection established with {} via {:?}", peer_id, endpoint);
+                self.log_event(
+                    NetworkLogKind::Connection,
+                    format!("Connected to {} via {}", peer_id, endpoint.get_remote_address()),
+                );
+                self.peer_connections
+                    .entry(peer_id)
+                    .or_default()
+                    .insert(connection_id, endpoint.is_relayed());
                 // Add to gossipsub for mesh
                 swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
 
@@ -812,10 +1449,27 @@ impl NetworkManager {
                 }
             }
 
-            SwarmEvent::ConnectionClosed { peer_id, .. } => {
+            SwarmEvent::ConnectionClosed { peer_id, connection_id, cause, num_established, .. } => {
                 debug!("Connection closed with {}", peer_id);
+                if let Some(connections) = self.peer_connections.get_mut(&peer_id) {
+                    connections.remove(&connection_id);
+                    if connections.is_empty() {
+                        self.peer_connections.remove(&peer_id);
+                    }
+                }
+                match cause {
+                    Some(e) => self.log_event(NetworkLogKind::Connection, format!("Disconnected from {}: {}", peer_id, e)),
+                    None => self.log_event(NetworkLogKind::Connection, format!("Disconnected from {}", peer_id)),
+                }
                 self.room_peers.remove(&peer_id);
-                self.connected_relays.remove(&peer_id);
+                // Relays forget the handshake once we're fully disconnected
+                if num_established == 0 {
+                    self.authenticated_relays.remove(&peer_id);
+                }
+                if self.connected_relays.remove(&peer_id) {
+                    self.auto_relay.on_relay_disconnected(&peer_id);
+                    self.maintain_relay_reservations(swarm);
+                }
 
                 // Track bootstrap node disconnections
                 if self.connected_bootstrap_peers.remove(&peer_id) {
@@ -827,22 +1481,196 @@ impl NetworkManager {
             SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
                 if let Some(peer) = peer_id {
                     warn!("Failed to connect to {}: {}", peer, error);
+                    self.log_event(NetworkLogKind::Error, format!("Failed to connect to {}: {}", peer, error));
+                    // Unreachable relay candidates are given up on like failed reservations
+                    if !swarm.is_connected(&peer) {
+                        self.auto_relay.record_failure(&peer);
+                    }
                 } else {
                     warn!("Outgoing connection error: {}", error);
+                    self.log_event(NetworkLogKind::Error, format!("Outgoing connection error: {}", error));
                 }
             }
 
             SwarmEvent::ListenerError { listener_id, error } => {
                 warn!("Listener {} error: {}", listener_id, error);
+                self.log_event(NetworkLogKind::Error, format!("Listener error: {}", error));
                 // This can happen when relay reservation fails
                 // The swarm will automatically retry with other listeners
             }
 
+            // Registrations carry our external addresses: the first one may only
+            // be the circuit address confirmed with the reservation
+            SwarmEvent::ExternalAddrConfirmed { address } if address.to_string().contains("p2p-circuit") => {
+                if let Some(relay_peer_id) = relay_lookup::circuit_relay(&address) {
+                    self.register_with_relay(swarm, relay_peer_id);
+                }
+            }
+
+            SwarmEvent::ExternalAddrConfirmed { address } => {
+                info!("External address confirmed: {} - publicly reachable", address);
+                self.log_event(NetworkLogKind::Connection, format!("External address confirmed: {}", address));
+                self.auto_relay.set_publicly_reachable(true);
+                self.maintain_relay_reservations(swarm);
+            }
+
+            SwarmEvent::ExternalAddrExpired { address }
+                if !address.to_string().contains("p2p-circuit") && self.auto_relay.is_publicly_reachable() =>
+            {
+                info!("External address expired: {} - assuming not reachable", address);
+                self.auto_relay.set_publicly_reachable(false);
+                self.maintain_relay_reservations(swarm);
+            }
+
+            SwarmEvent::Behaviour(CiderBehaviourEvent::RelayAuth(request_response::Event::Message {
+                peer,
+                message: request_response::Message::Response { response, .. },
+                ..
+            })) => {
+                if response.accepted {
+                    info!("Relay {} accepted our access token", peer);
+                    self.log_event(NetworkLogKind::Relay, format!("Authenticated with relay {}", peer));
+                    self.authenticated_relays.insert(peer);
+                    self.auto_relay.on_authenticated(&peer);
+                } else if response.token_required {
+                    warn!("Relay {} rejected our access token", peer);
+                    self.log_event(NetworkLogKind::Error, format!("Relay {} rejected the access token", peer));
+                    self.auto_relay.remove_candidate(&peer);
+                } else {
+                    // Our token is for another relay; this one is open (or refuses us by
+                    // peer ID, which its reservations will tell)
+                    debug!("Relay {} doesn't take access tokens", peer);
+                    self.authenticated_relays.insert(peer);
+                }
+                self.maintain_relay_reservations(swarm);
+            }
+
+            SwarmEvent::Behaviour(CiderBehaviourEvent::RelayAuth(request_response::Event::OutboundFailure {
+                peer,
+                error,
+                ..
+            })) => {
+                // Public relays don't speak the auth protocol and need no token
+                if matches!(error, request_response::OutboundFailure::UnsupportedProtocols) {
+                    debug!("Relay {} doesn't require authentication", peer);
+                    self.authenticated_relays.insert(peer);
+                } else {
+                    warn!("Access token handshake with relay {} failed: {}", peer, error);
+                }
+                self.maintain_relay_reservations(swarm);
+            }
+
+            SwarmEvent::Behaviour(CiderBehaviourEvent::LatencyProbe(request_response::Event::Message {
+                peer,
+                message: request_response::Message::Request { request, channel, .. },
+                ..
+            })) => {
+                // Answered right away, so the timestamp is taken as close to arrival as we can
+                let response = ProbeResponse::answer(&request);
+                if swarm.behaviour_mut().latency_probe.send_response(channel, response).is_err() {
+                    debug!("Couldn't answer ping from {}: connection closed", peer);
+                }
+            }
+
+            SwarmEvent::Behaviour(CiderBehaviourEvent::LatencyProbe(request_response::Event::Message {
+                peer,
+                message: request_response::Message::Response { request_id, response },
+                ..
+            })) => {
+                self.pending_probes.remove(&request_id);
+                let _ = event_tx.send(NetworkEvent::Pong {
+                    from: peer.to_string(),
+                    ping_sent_at_ms: response.ping_sent_at_ms,
+                    received_at_ms: response.received_at_ms,
+                });
+            }
+
+            SwarmEvent::Behaviour(CiderBehaviourEvent::LatencyProbe(request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+                ..
+            })) => {
+                let sent_at_ms = self.pending_probes.remove(&request_id);
+                match (error, sent_at_ms) {
+                    // Older versions only answer pings on the room topic
+                    (request_response::OutboundFailure::UnsupportedProtocols, Some(sent_at_ms)) => {
+                        self.ping_on_topic(swarm, sent_at_ms);
+                    }
+                    // The ping expires on its own; the next one may get through
+                    (error, _) => debug!("Ping to {} failed: {}", peer, error),
+                }
+            }
+
+            SwarmEvent::Behaviour(CiderBehaviourEvent::Rendezvous(rendezvous::client::Event::Registered {
+                rendezvous_node,
+                namespace,
+                ..
+            })) => {
+                debug!("Registered in {} with relay {}", namespace, rendezvous_node);
+            }
+
+            SwarmEvent::Behaviour(CiderBehaviourEvent::Rendezvous(rendezvous::client::Event::RegisterFailed {
+                rendezvous_node,
+                namespace,
+                error,
+            })) => {
+                // Relays without a registry can't help find the room, nothing more
+                debug!("Relay {} didn't register us in {}: {:?}", rendezvous_node, namespace, error);
+            }
+
+            SwarmEvent::Behaviour(CiderBehaviourEvent::RelayLookup(request_response::Event::Message {
+                peer,
+                message: request_response::Message::Response { response, .. },
+                ..
+            })) => {
+                if self.room_code.is_none() {
+                    return;
+                }
+                let found = response.dialable(&self.local_peer_id);
+                info!("Relay {} knows {} peers in the room", peer, found.len());
+                self.log_event(
+                    NetworkLogKind::Discovery,
+                    format!("Relay {} knows {} peers in the room", peer, found.len()),
+                );
+                for (peer_id, addrs) in found {
+                    if let Some(chain) = self.discovery.as_mut() {
+                        chain.add_candidate(peer_id, DiscoveryStage::Relays);
+                    }
+                    if swarm.is_connected(&peer_id) {
+                        continue;
+                    }
+                    let opts = DialOpts::peer_id(peer_id).addresses(addrs).build();
+                    if let Err(e) = swarm.dial(opts) {
+                        debug!("Failed to dial {} from relay lookup: {}", peer_id, e);
+                    }
+                }
+            }
+
+            SwarmEvent::Behaviour(CiderBehaviourEvent::RelayLookup(request_response::Event::OutboundFailure {
+                peer,
+                error,
+                ..
+            })) => {
+                // Older relays don't answer lookups
+                debug!("Room lookup on relay {} failed: {}", peer, error);
+            }
+
+            // Ping RTTs rank relay candidates
+            SwarmEvent::Behaviour(CiderBehaviourEvent::Ping(ping::Event { peer, result: Ok(rtt), .. })) => {
+                self.auto_relay.record_rtt(&peer, rtt);
+            }
+
             SwarmEvent::ListenerClosed {
                 listener_id,
                 reason,
                 addresses,
             } => {
+                if let Some(relay_peer_id) = self.auto_relay.on_listener_closed(listener_id, reason.is_err()) {
+                    debug!("AutoRelay: reservation on {} ended", relay_peer_id);
+                    self.maintain_relay_reservations(swarm);
+                }
+
                 let addr_str = addresses
                     .iter()
                     .map(|a| a.to_string())
@@ -870,19 +1698,25 @@ impl NetworkManager {
                                 info!("Kademlia bootstrap progress: peer={}, remaining={}", peer, num_remaining);
                                 if num_remaining == 0 {
                                     info!("Kademlia bootstrap complete!");
+                                    self.log_event(NetworkLogKind::Discovery, "DHT bootstrap complete");
                                     self.dht_bootstrapped = true;
                                     self.send_bootstrap_status(event_tx);
                                 }
                             }
                             kad::QueryResult::Bootstrap(Err(e)) => {
                                 warn!("Kademlia bootstrap error: {:?}", e);
+                                self.log_event(NetworkLogKind::Error, format!("DHT bootstrap error: {:?}", e));
                                 // Don't set dht_bootstrapped on failure - will retry
                             }
                             kad::QueryResult::GetProviders(Ok(kad::GetProvidersOk::FoundProviders { providers, .. })) => {
                                 info!("DHT found {} providers for room", providers.len());
+                                self.log_event(NetworkLogKind::Discovery, format!("DHT found {} room providers", providers.len()));
                                 for provider in providers {
                                     if provider != self.local_peer_id {
                                         debug!("Found room provider: {}", provider);
+                                        if let Some(chain) = self.discovery.as_mut() {
+                                            chain.add_candidate(provider, DiscoveryStage::Dht);
+                                        }
                                         // Add to gossipsub and try to connect
                                         swarm.behaviour_mut().gossipsub.add_explicit_peer(&provider);
                                         // Dial the peer through known addresses
@@ -940,30 +1774,31 @@ impl NetworkManager {
         swarm: &mut Swarm<CiderBehaviour>,
         room_code: &str,
     ) -> Result<(), NetworkError> {
-        if self.room_topic.is_some() {
+        if self.room_topics.is_some() {
             return Err(NetworkError::AlreadyInRoom);
         }
 
-        let topic = gossipsub::IdentTopic::new(format!("cider-room-{}", room_code));
-
-        swarm
-            .behaviour_mut()
-            .gossipsub
-            .subscribe(&topic)
-            .map_err(|e| NetworkError::Libp2p(e.to_string()))?;
+        let topics = RoomTopics::new(room_code);
+        self.subscribe_room(swarm, &topics)?;
 
         // Advertise this room in the DHT so others can find us
         let room_key = kad::RecordKey::new(&format!("cider-room-{}", room_code));
-        if let Err(e) = swarm.behaviour_mut().kademlia.start_providing(room_key.clone()) {
+        if !self.config.enable_dht {
+            debug!("DHT disabled, not advertising room {}", room_code);
+        } else if let Err(e) = swarm.behaviour_mut().kademlia.start_providing(room_key.clone()) {
             warn!("Failed to start providing room in DHT: {:?}", e);
         } else {
             info!("DHT: Advertising room {} to the network", room_code);
         }
 
         info!("Created and subscribed to room: {}", room_code);
-        self.room_topic = Some(topic);
+        self.log_event(NetworkLogKind::Room, format!("Created room {}", room_code));
+        self.room_topics = Some(topics);
         self.room_code = Some(room_code.to_string());
         self.room_peers.clear();
+        for relay in self.connected_relays.clone() {
+            self.register_with_relay(swarm, relay);
+        }
 
         Ok(())
     }
@@ -974,61 +1809,169 @@ impl NetworkManager {
         swarm: &mut Swarm<CiderBehaviour>,
         room_code: &str,
     ) -> Result<(), NetworkError> {
-        if self.room_topic.is_some() {
+        if self.room_topics.is_some() {
             return Err(NetworkError::AlreadyInRoom);
         }
 
-        let topic = gossipsub::IdentTopic::new(format!("cider-room-{}", room_code));
-
-        swarm
-            .behaviour_mut()
-            .gossipsub
-            .subscribe(&topic)
-            .map_err(|e| NetworkError::Libp2p(e.to_string()))?;
+        let topics = RoomTopics::new(room_code);
+        self.subscribe_room(swarm, &topics)?;
 
         // Search DHT for peers in this room
-        let room_key = kad::RecordKey::new(&format!("cider-room-{}", room_code));
-        swarm.behaviour_mut().kademlia.get_providers(room_key.clone());
-        info!("DHT: Searching for peers in room {}", room_code);
-
-        // Also advertise ourselves so others can find us
-        if let Err(e) = swarm.behaviour_mut().kademlia.start_providing(room_key) {
-            warn!("Failed to start providing room in DHT: {:?}", e);
+        if self.config.enable_dht {
+            let room_key = kad::RecordKey::new(&format!("cider-room-{}", room_code));
+            swarm.behaviour_mut().kademlia.get_providers(room_key.clone());
+            info!("DHT: Searching for peers in room {}", room_code);
+
+            // Also advertise ourselves so others can find us
+            if let Err(e) = swarm.behaviour_mut().kademlia.start_providing(room_key) {
+                warn!("Failed to start providing room in DHT: {:?}", e);
+            }
         }
 
         info!("Joined room: {}", room_code);
-        self.room_topic = Some(topic);
+        self.log_event(NetworkLogKind::Room, format!("Joined room {}", room_code));
+        self.room_topics = Some(topics);
         self.room_code = Some(room_code.to_string());
         self.room_peers.clear();
+        for relay in self.connected_relays.clone() {
+            self.register_with_relay(swarm, relay);
+        }
+        // Asked again when discovery gets to the relays, in case we had none yet
+        self.look_up_room(swarm);
 
         Ok(())
     }
 
+    /// Move the room to a new code: subscribe to its topic and advertise it,
+    /// keeping the old topic for the peers still on it
+    fn change_room_code(
+        &mut self,
+        swarm: &mut Swarm<CiderBehaviour>,
+        room_code: &str,
+        event_tx: &mpsc::UnboundedSender<NetworkEvent>,
+    ) -> Result<(), NetworkError> {
+        let old_topics = self.room_topics.clone().ok_or(NetworkError::NotInRoom)?;
+        let topics = RoomTopics::new(room_code);
+        let control = MessageClass::Control;
+        if topics.get(control).hash() == old_topics.get(control).hash() {
+            return Ok(());
+        }
+
+        self.subscribe_room(swarm, &topics)?;
+
+        // Changed again before everyone switched: those still two codes behind are lost
+        self.drop_previous_room_topic(swarm, event_tx);
+
+        if let Some(old_code) = self.room_code.replace(room_code.to_string()) {
+            let old_key = kad::RecordKey::new(&format!("cider-room-{}", old_code));
+            swarm.behaviour_mut().kademlia.stop_providing(&old_key);
+            self.unregister_from_relays(swarm, &old_code);
+        }
+        for relay in self.connected_relays.clone() {
+            self.register_with_relay(swarm, relay);
+        }
+        let room_key = kad::RecordKey::new(&format!("cider-room-{}", room_code));
+        if self.config.enable_dht {
+            if let Err(e) = swarm.behaviour_mut().kademlia.start_providing(room_key) {
+                warn!("Failed to start providing room in DHT: {:?}", e);
+            }
+        }
+
+        info!("Room code changed to {}", room_code);
+        self.log_event(NetworkLogKind::Room, format!("Room code changed to {}", room_code));
+        self.room_topics = Some(topics);
+        self.previous_room_topic = Some(PreviousRoomTopic {
+            topics: old_topics,
+            pending: self.room_peers.ids(),
+            until: Instant::now() + ROOM_CODE_OVERLAP,
+        });
+        self.room_peers.moved();
+        Ok(())
+    }
+
+    /// Leave the room's previous topic; peers that never switched from it are gone
+    fn drop_previous_room_topic(
+        &mut self,
+        swarm: &mut Swarm<CiderBehaviour>,
+        event_tx: &mpsc::UnboundedSender<NetworkEvent>,
+    ) {
+        let Some(previous) = self.previous_room_topic.take() else {
+            return;
+        };
+        for topic in previous.topics.all() {
+            let _ = swarm.behaviour_mut().gossipsub.unsubscribe(topic);
+        }
+        debug!("Left the room's previous topic ({} peers didn't switch)", previous.pending.len());
+        for peer_id in previous.pending {
+            if self.room_peers.remove(&peer_id) {
+                let _ = event_tx.send(NetworkEvent::PeerUnsubscribed {
+                    peer_id: peer_id.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Whether a room message is for us: anything on the room's topic, but on
+    /// its previous topic only messages from peers that haven't switched yet
+    fn accepts_room_message(&mut self, topic: &gossipsub::TopicHash, source: Option<PeerId>) -> bool {
+        let Some(previous) = self.previous_room_topic.as_mut() else {
+            return true;
+        };
+        if previous.topics.class_of(topic).is_some() {
+            return source.is_some_and(|peer_id| previous.pending.contains(&peer_id));
+        }
+        // Heard on the new topic: switched
+        if let Some(peer_id) = source {
+            previous.pending.remove(&peer_id);
+        }
+        true
+    }
+
     /// Leave the current room
     fn leave_room(&mut self, swarm: &mut Swarm<CiderBehaviour>) -> Result<(), NetworkError> {
-        if let Some(topic) = self.room_topic.take() {
-            let _ = swarm.behaviour_mut().gossipsub.unsubscribe(&topic);
+        if let Some(topics) = self.room_topics.take() {
+            for topic in topics.all() {
+                let _ = swarm.behaviour_mut().gossipsub.unsubscribe(topic);
+            }
             info!("Left room");
+            self.log_event(NetworkLogKind::Room, "Left room");
+        }
+        if let Some(previous) = self.previous_room_topic.take() {
+            for topic in previous.topics.all() {
+                let _ = swarm.behaviour_mut().gossipsub.unsubscribe(topic);
+            }
         }
+        self.skipped_classes.clear();
+        self.offenders.clear();
 
         // Stop providing in DHT
         if let Some(code) = self.room_code.take() {
+            self.unregister_from_relays(swarm, &code);
             let room_key = kad::RecordKey::new(&format!("cider-room-{}", code));
             swarm.behaviour_mut().kademlia.stop_providing(&room_key);
             info!("DHT: Stopped advertising room {}", code);
         }
 
+        self.discovery = None;
         self.room_peers.clear();
         Ok(())
     }
 
-    /// Broadcast a message to the room
+    /// Ping the room on its topic, the way older versions do (the host answers)
+    fn ping_on_topic(&self, swarm: &mut Swarm<CiderBehaviour>, sent_at_ms: u64) {
+        if let Err(e) = self.broadcast(swarm, &SyncMessage::Ping { sent_at_ms }) {
+            debug!("Couldn't ping on the room topic: {}", e);
+        }
+    }
+
+    /// Broadcast a message to the room, on its class's topic
     fn broadcast(
         &self,
         swarm: &mut Swarm<CiderBehaviour>,
         message: &SyncMessage,
     ) -> Result<(), NetworkError> {
-        let topic = self.room_topic.as_ref().ok_or(NetworkError::NotInRoom)?;
+        let topics = self.room_topics.as_ref().ok_or(NetworkError::NotInRoom)?;
+        let class = message.class();
 
         let data =
             serde_json::to_vec(message).map_err(|e| NetworkError::Libp2p(e.to_string()))?;
@@ -1036,11 +1979,152 @@ impl NetworkManager {
         swarm
             .behaviour_mut()
             .gossipsub
-            .publish(topic.clone(), data)
+            .publish(topics.get(class).clone(), data.clone())
             .map_err(|e| NetworkError::Libp2p(e.to_string()))?;
 
+        // Older peers only take the control topic
+        let control_copy = self.room_peers.needs_control_copy(class);
+        if control_copy {
+            let control = topics.get(MessageClass::Control).clone();
+            if let Err(e) = swarm.behaviour_mut().gossipsub.publish(control, data.clone()) {
+                debug!("Broadcast on the control topic for older peers failed: {}", e);
+            }
+        }
+
+        // Peers still on the room's previous topics need it too
+        if let Some(previous) = self.previous_room_topic.as_ref().filter(|p| !p.pending.is_empty()) {
+            let mut classes = vec![class];
+            if control_copy {
+                classes.push(MessageClass::Control);
+            }
+            for class in classes {
+                let topic = previous.topics.get(class).clone();
+                if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, data.clone()) {
+                    debug!("Broadcast on the room's previous topic failed: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Subscribe to the room's topics, but those of classes we skip
+    fn subscribe_room(&self, swarm: &mut Swarm<CiderBehaviour>, topics: &RoomTopics) -> Result<(), NetworkError> {
+        for class in MessageClass::ALL.into_iter().filter(|class| !self.skipped_classes.contains(class)) {
+            swarm
+                .behaviour_mut()
+                .gossipsub
+                .subscribe(topics.get(class))
+                .map_err(|e| NetworkError::Libp2p(e.to_string()))?;
+        }
         Ok(())
     }
+
+    /// `peer_id` is on `topic`, maybe one of the room's
+    fn peer_subscribed(
+        &mut self,
+        peer_id: PeerId,
+        topic: &gossipsub::TopicHash,
+        event_tx: &mpsc::UnboundedSender<NetworkEvent>,
+    ) {
+        let Some(class) = self.room_topics.as_ref().and_then(|topics| topics.class_of(topic)) else {
+            return;
+        };
+        // Counted once, on the first of the room's topics they're on
+        if !self.room_peers.subscribed(peer_id, class) {
+            return;
+        }
+        info!("Peer {} subscribed to room", peer_id);
+        self.log_event(NetworkLogKind::Room, format!("{} subscribed to room", peer_id));
+        if let Some(previous) = self.previous_room_topic.as_mut() {
+            previous.pending.remove(&peer_id);
+        }
+        if let Some(chain) = self.discovery.take() {
+            let stage = chain.room_peer_found(&peer_id);
+            let state = DiscoveryState::Found { peer_id: peer_id.to_string() };
+            self.send_discovery_progress(event_tx, stage, state);
+        }
+        let _ = event_tx.send(NetworkEvent::PeerSubscribed {
+            peer_id: peer_id.to_string(),
+        });
+    }
+
+    /// Count the peers that were on the room's topics before we were: we
+    /// only hear of a peer's subscriptions when it makes them or connects,
+    /// and relay clients may have connected to each other beforehand
+    fn room_peers_already_subscribed(
+        &mut self,
+        swarm: &Swarm<CiderBehaviour>,
+        event_tx: &mpsc::UnboundedSender<NetworkEvent>,
+    ) {
+        let subscribed: Vec<(PeerId, gossipsub::TopicHash)> = swarm
+            .behaviour()
+            .gossipsub
+            .all_peers()
+            .flat_map(|(peer_id, topics)| topics.into_iter().map(|topic| (*peer_id, topic.clone())))
+            .collect();
+        for (peer_id, topic) in subscribed {
+            self.peer_subscribed(peer_id, &topic, event_tx);
+        }
+    }
+
+    /// Take a class of the room's messages again, or stop taking it
+    fn set_receives(&mut self, swarm: &mut Swarm<CiderBehaviour>, class: MessageClass, receive: bool) {
+        if class == MessageClass::Control && !receive {
+            warn!("Not skipping control messages: they say who's in the room");
+            return;
+        }
+        let changed = if receive {
+            self.skipped_classes.remove(&class)
+        } else {
+            self.skipped_classes.insert(class)
+        };
+        let Some(topic) = self.room_topics.as_ref().map(|topics| topics.get(class).clone()) else {
+            return;
+        };
+        if !changed {
+            return;
+        }
+        if receive {
+            if let Err(e) = swarm.behaviour_mut().gossipsub.subscribe(&topic) {
+                warn!("Failed to subscribe to {}: {}", topic, e);
+            }
+        } else {
+            let _ = swarm.behaviour_mut().gossipsub.unsubscribe(&topic);
+        }
+        info!("{} the room's {:?} messages", if receive { "Taking" } else { "Skipping" }, class);
+    }
+
+    /// Whether to pass on a room message heard on `topic`: not of a class we
+    /// skip, nor a copy sent on the control topic for older peers by someone
+    /// who sends it on its own topic as well
+    fn wants_room_message(&self, topic: &gossipsub::TopicHash, from: &PeerId, message: &SyncMessage) -> bool {
+        let class = message.class();
+        if self.skipped_classes.contains(&class) {
+            return false;
+        }
+        match self.room_topics.as_ref().and_then(|topics| topics.class_of(topic)) {
+            Some(heard_as) if heard_as != class => !self.room_peers.splits_classes(from),
+            _ => true,
+        }
+    }
+
+    /// Drop a message from `from` past the protocol's limits, and count it against them
+    fn reject_message(&mut self, from: PeerId, reason: InvalidMessage) {
+        warn!("Rejected message from {}: {}", from, reason);
+        if self.offenders.strike(from, Instant::now()) {
+            warn!("Ignoring {} for {:?} after repeated invalid messages", from, offenders::IGNORE_FOR);
+            self.log_event(NetworkLogKind::Room, format!("Ignoring {}: invalid messages", from));
+        }
+    }
+}
+
+/// Sleep until `deadline` (forever if there's none)
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
 }
 
 impl Default for NetworkManager {
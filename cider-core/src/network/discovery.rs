@@ -0,0 +1,253 @@
+//! Layered discovery when joining a room
+//!
+//! A joiner can find the room's peers several ways: mDNS on the local
+//! network, the addresses the host publishes to signaling, the room's
+//! registrations with our relays, and the room's providers in the DHT. They
+//! used to all run at once against a single 30s timeout, so a join that
+//! couldn't work took the full 30s to fail and said nothing about why.
+//!
+//! The chain tries them in order, cheapest first, each for a limited time.
+//! Earlier mechanisms keep running when the chain moves on (a late mDNS
+//! answer still counts); a stage ending only means the next one starts. Once
+//! they're all exhausted, the chain waits a little for the gossipsub mesh if
+//! it reached any candidate peer, and otherwise gives up right away.
+//!
+//! If signaling has no announcement for the room, its stage is skipped. That
+//! alone doesn't mean there's no room: announcements can be late or lost, and
+//! the host's relays or the DHT may still know of it.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+/// How long to wait for peers on the local network
+const MDNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the host's signaling addresses to connect
+const SIGNALING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the peers our relays know of to connect
+const RELAYS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the room's providers in the DHT
+const DHT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long to wait for a connected peer to show up in the room's topic
+const MESH_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A step in the discovery chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiscoveryStage {
+    /// mDNS on the local network
+    Mdns,
+    /// Addresses published to the signaling server
+    Signaling,
+    /// Peers registered in the room with our relays (or their federation)
+    Relays,
+    /// Room providers in the DHT
+    Dht,
+    /// Connected to candidates, waiting for one to join the room topic
+    MeshWait,
+}
+
+impl DiscoveryStage {
+    fn timeout(self) -> Duration {
+        match self {
+            DiscoveryStage::Mdns => MDNS_TIMEOUT,
+            DiscoveryStage::Signaling => SIGNALING_TIMEOUT,
+            DiscoveryStage::Relays => RELAYS_TIMEOUT,
+            DiscoveryStage::Dht => DHT_TIMEOUT,
+            DiscoveryStage::MeshWait => MESH_WAIT_TIMEOUT,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            DiscoveryStage::Mdns => "the local network",
+            DiscoveryStage::Signaling => "signaling",
+            DiscoveryStage::Relays => "our relays",
+            DiscoveryStage::Dht => "the DHT",
+            DiscoveryStage::MeshWait => "the room's peers",
+        }
+    }
+}
+
+/// Where the chain is at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscoveryState {
+    /// The stage started
+    Trying,
+    /// A peer in the room was found (the stage is how it was found)
+    Found { peer_id: String },
+    /// Nothing worked; the join should fail
+    Failed { reason: String },
+}
+
+/// Progress of the discovery chain for the room being joined
+#[derive(Debug)]
+pub struct DiscoveryChain {
+    /// Stages still to go, in order (discovery stages only)
+    remaining: Vec<DiscoveryStage>,
+    /// Stages tried so far, in order
+    tried: Vec<DiscoveryStage>,
+    /// The stage running, and when it started
+    current: DiscoveryStage,
+    started_at: Instant,
+    /// Candidate peers, by the stage that found them first
+    candidates: HashMap<PeerId, DiscoveryStage>,
+    /// Signaling has no recent announcement for the room
+    not_announced: bool,
+}
+
+impl DiscoveryChain {
+    /// Start discovery with the mechanisms enabled (`internet` covers
+    /// signaling, relays and the DHT); None if there's nothing to try
+    pub fn start(mdns: bool, internet: bool, now: Instant) -> Option<Self> {
+        let mut stages = Vec::new();
+        if mdns {
+            stages.push(DiscoveryStage::Mdns);
+        }
+        if internet {
+            stages.extend([DiscoveryStage::Signaling, DiscoveryStage::Relays, DiscoveryStage::Dht]);
+        }
+        if stages.is_empty() {
+            return None;
+        }
+        let current = stages.remove(0);
+        Some(Self {
+            remaining: stages,
+            tried: vec![current],
+            current,
+            started_at: now,
+            candidates: HashMap::new(),
+            not_announced: false,
+        })
+    }
+
+    /// The stage running
+    pub fn stage(&self) -> DiscoveryStage {
+        self.current
+    }
+
+    /// `peer_id` may be in the room; it was found by `via`
+    pub fn add_candidate(&mut self, peer_id: PeerId, via: DiscoveryStage) {
+        self.candidates.entry(peer_id).or_insert(via);
+    }
+
+    /// `peer_id` joined the room topic: discovery is done. Returns the stage
+    /// that found it (the current one if it wasn't a candidate)
+    pub fn room_peer_found(&self, peer_id: &PeerId) -> DiscoveryStage {
+        self.candidates.get(peer_id).copied().unwrap_or(self.current)
+    }
+
+    /// Signaling has no recent announcement for the room: there's nothing to
+    /// wait for there
+    pub fn room_not_announced(&mut self) {
+        self.not_announced = true;
+        self.remaining.retain(|stage| *stage != DiscoveryStage::Signaling);
+    }
+
+    /// Move on if the current stage has timed out: the stage that started,
+    /// or the reason to give up. `connected` tells whether we're connected
+    /// to a peer.
+    pub fn poll(
+        &mut self,
+        now: Instant,
+        connected: impl Fn(&PeerId) -> bool,
+    ) -> Option<Result<DiscoveryStage, String>> {
+        let skipped = self.not_announced && self.current == DiscoveryStage::Signaling;
+        let timed_out = skipped || now.saturating_duration_since(self.started_at) >= self.current.timeout();
+        if !timed_out {
+            return None;
+        }
+
+        let next = if !self.remaining.is_empty() {
+            self.remaining.remove(0)
+        } else if self.current == DiscoveryStage::MeshWait {
+            return Some(Err("Connected to peers, but none of them are in the room".to_string()));
+        } else if self.candidates.keys().any(connected) {
+            DiscoveryStage::MeshWait
+        } else {
+            let tried: Vec<_> = self.tried.iter().map(|stage| stage.description()).collect();
+            if self.not_announced {
+                return Some(Err(format!("No room with this code is active (nothing found via {})", tried.join(", "))));
+            }
+            return Some(Err(format!("No peers found for the room via {}", tried.join(", "))));
+        };
+
+        self.current = next;
+        self.started_at = now;
+        self.tried.push(next);
+        Some(Ok(next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages_in_order_then_fail() {
+        let start = Instant::now();
+        let mut chain = DiscoveryChain::start(true, true, start).unwrap();
+        assert_eq!(chain.stage(), DiscoveryStage::Mdns);
+        assert_eq!(chain.poll(start + Duration::from_secs(1), |_| false), None);
+
+        let at = start + MDNS_TIMEOUT;
+        assert_eq!(chain.poll(at, |_| false), Some(Ok(DiscoveryStage::Signaling)));
+        let at = at + SIGNALING_TIMEOUT;
+        assert_eq!(chain.poll(at, |_| false), Some(Ok(DiscoveryStage::Relays)));
+        let at = at + RELAYS_TIMEOUT;
+        assert_eq!(chain.poll(at, |_| false), Some(Ok(DiscoveryStage::Dht)));
+
+        // Nothing to wait for in the mesh: give up
+        let Some(Err(reason)) = chain.poll(at + DHT_TIMEOUT, |_| false) else {
+            panic!("expected failure");
+        };
+        assert!(reason.contains("the local network, signaling, our relays, the DHT"), "{}", reason);
+
+        // Nothing enabled, nothing to try
+        assert!(DiscoveryChain::start(false, false, start).is_none());
+    }
+
+    #[test]
+    fn test_waits_for_mesh_when_connected() {
+        let start = Instant::now();
+        let mut chain = DiscoveryChain::start(true, false, start).unwrap();
+        let peer = PeerId::random();
+        chain.add_candidate(peer, DiscoveryStage::Mdns);
+        chain.add_candidate(peer, DiscoveryStage::MeshWait);
+
+        let at = start + MDNS_TIMEOUT;
+        assert_eq!(chain.poll(at, |p| *p == peer), Some(Ok(DiscoveryStage::MeshWait)));
+        assert_eq!(chain.room_peer_found(&peer), DiscoveryStage::Mdns);
+        assert_eq!(chain.room_peer_found(&PeerId::random()), DiscoveryStage::MeshWait);
+
+        assert!(matches!(chain.poll(at + MESH_WAIT_TIMEOUT, |_| true), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_room_not_announced() {
+        let start = Instant::now();
+        let mut chain = DiscoveryChain::start(true, true, start).unwrap();
+        chain.room_not_announced();
+
+        // Signaling is skipped, everything else still gets its chance
+        assert_eq!(chain.poll(start + Duration::from_secs(1), |_| false), None);
+        let at = start + MDNS_TIMEOUT;
+        assert_eq!(chain.poll(at, |_| false), Some(Ok(DiscoveryStage::Relays)));
+        let at = at + RELAYS_TIMEOUT;
+        assert_eq!(chain.poll(at, |_| false), Some(Ok(DiscoveryStage::Dht)));
+        let Some(Err(reason)) = chain.poll(at + DHT_TIMEOUT, |_| false) else {
+            panic!("expected failure");
+        };
+        assert!(reason.starts_with("No room with this code is active"), "{}", reason);
+
+        // Heard of once signaling is already running: it ends right away
+        let mut chain = DiscoveryChain::start(false, true, start).unwrap();
+        assert_eq!(chain.stage(), DiscoveryStage::Signaling);
+        chain.room_not_announced();
+        assert_eq!(chain.poll(start, |_| false), Some(Ok(DiscoveryStage::Relays)));
+    }
+}
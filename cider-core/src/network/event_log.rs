@@ -0,0 +1,98 @@
+//! Ring buffer of recent network events
+//!
+//! Keeps a bounded, timestamped history of notable swarm events
+//! (connections, relay reservations, discovery, errors) so the apps can
+//! show a connection timeline when users report sync problems.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+/// Maximum number of events to keep
+const MAX_EVENT_LOG_ENTRIES: usize = 200;
+
+/// Category of a logged network event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkLogKind {
+    /// Connection opened/closed or dial failure
+    Connection,
+    /// Relay reservations and circuits
+    Relay,
+    /// mDNS, DHT and signaling discovery
+    Discovery,
+    /// Room topic subscriptions
+    Room,
+    /// Listener and transport errors
+    Error,
+}
+
+/// A single logged network event
+#[derive(Debug, Clone)]
+pub struct NetworkLogEntry {
+    /// Wall-clock time the event was recorded (ms since UNIX epoch)
+    pub timestamp_ms: u64,
+    /// Event category
+    pub kind: NetworkLogKind,
+    /// Human-readable description
+    pub message: String,
+}
+
+/// Bounded log of recent network events
+#[derive(Debug, Default)]
+pub struct NetworkEventLog {
+    entries: VecDeque<NetworkLogEntry>,
+}
+
+impl NetworkEventLog {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::with_capacity(MAX_EVENT_LOG_ENTRIES),
+        }
+    }
+
+    /// Record an event, evicting the oldest if full
+    pub fn record(&mut self, kind: NetworkLogKind, message: impl Into<String>) {
+        if self.entries.len() >= MAX_EVENT_LOG_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(NetworkLogEntry {
+            timestamp_ms: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            kind,
+            message: message.into(),
+        });
+    }
+
+    /// Get recent events (oldest first)
+    pub fn entries(&self) -> Vec<NetworkLogEntry> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+/// Thread-safe wrapper for NetworkEventLog
+pub type SharedNetworkEventLog = Arc<RwLock<NetworkEventLog>>;
+
+/// Create a new shared network event log
+pub fn new_shared_event_log() -> SharedNetworkEventLog {
+    Arc::new(RwLock::new(NetworkEventLog::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log_is_bounded() {
+        let mut log = NetworkEventLog::new();
+        for i in 0..(MAX_EVENT_LOG_ENTRIES + 10) {
+            log.record(NetworkLogKind::Connection, format!("event {}", i));
+        }
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), MAX_EVENT_LOG_ENTRIES);
+        // Oldest entries were evicted
+        assert_eq!(entries[0].message, "event 10");
+        assert_eq!(entries.last().unwrap().message, format!("event {}", MAX_EVENT_LOG_ENTRIES + 9));
+    }
+}
@@ -0,0 +1,38 @@
+//! Point-to-point latency probes
+//!
+//! Pings used to be published to the room topic, so every peer answered every
+//! other peer's pings and room traffic grew with the square of its size. A
+//! probe now goes straight to the peer we want the latency to (the host, for
+//! listeners) over request-response, and only that peer answers.
+//!
+//! Peers we have no direct connection to, or that predate the protocol, are
+//! still pinged on the room topic (`SyncMessage::Ping`).
+
+use libp2p::StreamProtocol;
+use serde::{Deserialize, Serialize};
+
+/// Latency probe protocol
+pub const PROBE_PROTOCOL: StreamProtocol = StreamProtocol::new("/cider-together/ping/1.0.0");
+
+/// A ping, stamped with our wall clock when sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeRequest {
+    pub sent_at_ms: u64,
+}
+
+/// The answer to a ping: its timestamp, and the peer's wall clock when it got it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResponse {
+    pub ping_sent_at_ms: u64,
+    pub received_at_ms: u64,
+}
+
+impl ProbeResponse {
+    /// Answer `request`, received now
+    pub fn answer(request: &ProbeRequest) -> Self {
+        Self {
+            ping_sent_at_ms: request.sent_at_ms,
+            received_at_ms: crate::clock::now_ms(),
+        }
+    }
+}
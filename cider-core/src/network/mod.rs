@@ -2,10 +2,22 @@
 //!
 //! Uses libp2p for decentralized peer-to-peer connectivity.
 
+mod autorelay;
 mod behaviour;
+mod discovery;
+mod event_log;
+mod latency_probe;
+mod offenders;
+mod relay_access;
+mod relay_lookup;
 mod room_code;
+mod room_topics;
 pub mod signaling;
 
-pub use behaviour::{NetworkConfig, NetworkError, NetworkEvent, NetworkHandle, NetworkManager};
+#[cfg(test)]
+pub(crate) use behaviour::NetworkCommand;
+pub use behaviour::{NetworkConfig, NetworkError, NetworkEvent, NetworkHandle, NetworkManager, NetworkStatus, PeerTransport};
+pub use discovery::{DiscoveryStage, DiscoveryState};
+pub use event_log::{NetworkLogEntry, NetworkLogKind};
 pub use room_code::RoomCode;
-pub use signaling::SignalingClient;
+pub use signaling::{SignalingBackend, SignalingClient};
@@ -0,0 +1,126 @@
+//! Peers sending messages past the protocol's limits
+//!
+//! A message `sync::validation` can't bring within its limits is dropped and
+//! counts against its author (gossipsub messages are signed, so a peer
+//! forwarding one isn't blamed). One could be a bug in another version; a few
+//! in a short while are taken as abuse, and everything from that peer is
+//! ignored for a while. The room's host is never ignored: the room can't go on
+//! without it.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+/// Invalid messages within `STRIKES_WINDOW` before a peer is ignored
+pub const MAX_STRIKES: usize = 3;
+
+/// How long a strike counts against a peer
+pub const STRIKES_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// How long a peer is ignored for
+pub const IGNORE_FOR: Duration = Duration::from_secs(10 * 60);
+
+/// Strikes against peers, and those ignored for them
+#[derive(Default)]
+pub struct Offenders {
+    /// When each of a peer's recent strikes was
+    strikes: HashMap<PeerId, VecDeque<Instant>>,
+    /// When each ignored peer is heard again
+    ignored: HashMap<PeerId, Instant>,
+    /// The room's host, never struck
+    host: Option<PeerId>,
+}
+
+impl Offenders {
+    /// Count an invalid message from `peer_id`; true if that gets them ignored
+    pub fn strike(&mut self, peer_id: PeerId, now: Instant) -> bool {
+        if self.host == Some(peer_id) {
+            return false;
+        }
+        let strikes = self.strikes.entry(peer_id).or_default();
+        while strikes.front().is_some_and(|at| now.saturating_duration_since(*at) >= STRIKES_WINDOW) {
+            strikes.pop_front();
+        }
+        strikes.push_back(now);
+        if strikes.len() < MAX_STRIKES {
+            return false;
+        }
+        self.strikes.remove(&peer_id);
+        self.ignored.insert(peer_id, now + IGNORE_FOR);
+        true
+    }
+
+    /// Whether messages from `peer_id` are ignored as of `now`
+    pub fn is_ignored(&mut self, peer_id: &PeerId, now: Instant) -> bool {
+        match self.ignored.get(peer_id) {
+            Some(until) if now < *until => true,
+            Some(_) => {
+                self.ignored.remove(peer_id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// The room is now hosted by `peer_id`, who's heard again if ignored
+    pub fn set_host(&mut self, peer_id: PeerId) {
+        self.strikes.remove(&peer_id);
+        self.ignored.remove(&peer_id);
+        self.host = Some(peer_id);
+    }
+
+    /// Forget everything, on leaving the room
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignored_after_strikes() {
+        let (offender, other) = (PeerId::random(), PeerId::random());
+        let mut offenders = Offenders::default();
+        let start = Instant::now();
+
+        for _ in 1..MAX_STRIKES {
+            assert!(!offenders.strike(offender, start));
+        }
+        assert!(!offenders.is_ignored(&offender, start));
+        assert!(offenders.strike(offender, start));
+        assert!(offenders.is_ignored(&offender, start + IGNORE_FOR / 2));
+        assert!(!offenders.is_ignored(&other, start));
+
+        // Heard again once it's up, starting over with no strikes
+        assert!(!offenders.is_ignored(&offender, start + IGNORE_FOR));
+        assert!(!offenders.strike(offender, start + IGNORE_FOR));
+    }
+
+    #[test]
+    fn test_strikes_wear_off() {
+        let (peer, host) = (PeerId::random(), PeerId::random());
+        let mut offenders = Offenders::default();
+        let start = Instant::now();
+
+        // Spread out, strikes never add up
+        for i in 0..MAX_STRIKES as u32 * 2 {
+            assert!(!offenders.strike(peer, start + STRIKES_WINDOW * i));
+        }
+
+        offenders.set_host(host);
+        for _ in 0..MAX_STRIKES * 2 {
+            assert!(!offenders.strike(host, start));
+        }
+        assert!(!offenders.is_ignored(&host, start));
+
+        // Nothing carries over to the next room
+        for _ in 1..MAX_STRIKES {
+            offenders.strike(peer, start);
+        }
+        offenders.clear();
+        assert!(!offenders.strike(peer, start));
+    }
+}
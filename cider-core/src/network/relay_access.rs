@@ -0,0 +1,128 @@
+//! Token handshake for private relays
+//!
+//! Private relays can require a shared access token. Before reserving, we
+//! send `sha256(token || our peer ID || relay peer ID)` over the relay's
+//! auth protocol instead of the token itself. The proof is bound to both
+//! peer IDs (which noise authenticates), so the relay can't replay it
+//! elsewhere and other peers can't reuse it. Must stay in sync with
+//! `relay-server/src/access.rs`.
+
+use libp2p::{PeerId, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Token handshake protocol
+pub const AUTH_PROTOCOL: StreamProtocol = StreamProtocol::new("/cider-relay/auth/1.0.0");
+
+/// Domain separator so the proof can't be confused with other hashes
+const PROOF_CONTEXT: &[u8] = b"cider-relay-auth:";
+
+/// Relay agent string field carrying the oldest client version it accepts
+const MIN_CLIENT_PREFIX: &str = "min-client/";
+
+/// Handshake request: proof of the shared token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthRequest {
+    pub proof: String,
+}
+
+/// Relay response to a handshake
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthResponse {
+    pub accepted: bool,
+    /// Whether the relay takes tokens at all; older relays reject every
+    /// handshake when they don't, so only one that does has really refused us
+    #[serde(default)]
+    pub token_required: bool,
+}
+
+/// Compute the handshake proof for a token, our peer ID and the relay's (hex-encoded)
+pub fn handshake_proof(token: &str, local: &PeerId, relay: &PeerId) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(PROOF_CONTEXT);
+    hasher.update(token.as_bytes());
+    hasher.update(local.to_bytes());
+    hasher.update(relay.to_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Our identify agent version
+pub fn agent_version() -> String {
+    format!("cider-together/{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Value of the `<prefix><value>` field of an identify agent version, if set
+pub fn agent_field<'a>(agent_version: &'a str, prefix: &str) -> Option<&'a str> {
+    agent_version
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix(prefix))
+        .filter(|value| !value.is_empty())
+}
+
+/// Minimum client version from a relay's identify agent version (`... min-client/<version>`)
+pub fn min_client_version(agent_version: &str) -> Option<&str> {
+    agent_field(agent_version, MIN_CLIENT_PREFIX)
+}
+
+/// Whether our version is at least `minimum`
+///
+/// Unparseable minimums are treated as met; the relay decides in the end.
+pub fn meets_minimum(minimum: &str) -> bool {
+    match (parse_version(env!("CARGO_PKG_VERSION")), parse_version(minimum)) {
+        (Some(ours), Some(minimum)) => ours >= minimum,
+        _ => true,
+    }
+}
+
+/// Parse `major[.minor[.patch]]`, ignoring pre-release/build suffixes
+fn parse_version(version: &str) -> Option<[u64; 3]> {
+    let core = version.split(['-', '+']).next()?;
+    let mut parsed = [0; 3];
+    for (i, part) in core.split('.').enumerate() {
+        *parsed.get_mut(i)? = part.parse().ok()?;
+    }
+    Some(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proof_is_bound_to_both_peers() {
+        let a = PeerId::random();
+        let b = PeerId::random();
+        let relay = PeerId::random();
+        assert_eq!(handshake_proof("secret", &a, &relay), handshake_proof("secret", &a, &relay));
+        assert_ne!(handshake_proof("secret", &a, &relay), handshake_proof("secret", &b, &relay));
+        assert_ne!(handshake_proof("secret", &a, &relay), handshake_proof("secret", &a, &b));
+        assert_ne!(handshake_proof("secret", &a, &relay), handshake_proof("other", &a, &relay));
+        assert_eq!(handshake_proof("secret", &a, &relay).len(), 64);
+    }
+
+    #[test]
+    fn test_agent_version() {
+        assert!(agent_version().starts_with("cider-together/"));
+        assert!(!agent_version().contains(' '));
+    }
+
+    #[test]
+    fn test_min_client_version() {
+        assert_eq!(min_client_version("cider-relay/0.1.0 region/eu min-client/0.4.0"), Some("0.4.0"));
+        assert_eq!(min_client_version("cider-relay/0.1.0"), None);
+        assert_eq!(min_client_version("cider-relay/0.1.0 min-client/"), None);
+
+        assert!(meets_minimum("0.0.1"));
+        assert!(meets_minimum(env!("CARGO_PKG_VERSION")));
+        assert!(!meets_minimum("999.0.0"));
+        assert!(meets_minimum("not-a-version"));
+        assert_eq!(parse_version("0.10"), Some([0, 10, 0]));
+        assert_eq!(parse_version("1.2.3-beta"), Some([1, 2, 3]));
+        assert!(parse_version("0.10.0") > parse_version("0.9.9"));
+        assert_eq!(parse_version("1.2.3.4"), None);
+    }
+}
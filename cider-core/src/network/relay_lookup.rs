@@ -0,0 +1,93 @@
+//! Finding a room's peers through our relays
+//!
+//! Relays keep a rendezvous registry. While in a room, we register under its
+//! namespace with every relay we hold a reservation on, and a joiner asks its
+//! relays where the namespace's members can be reached. Federated relays
+//! answer for each other, so a host reserved on relay A and a listener on
+//! relay B still find each other. Answers are circuit addresses through the
+//! relay holding the member's reservation, which work behind any NAT.
+//!
+//! Must stay in sync with `relay-server/src/federation.rs`.
+
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
+use serde::{Deserialize, Serialize};
+
+/// Namespace lookup protocol
+pub const LOOKUP_PROTOCOL: StreamProtocol = StreamProtocol::new("/cider-relay/lookup/1.0.0");
+
+/// Where are the members of a namespace reachable?
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LookupRequest {
+    pub namespace: String,
+}
+
+/// Relay answer to a lookup
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LookupResponse {
+    pub peers: Vec<PeerLocation>,
+}
+
+/// A namespace member and the relay circuit addresses it can be dialed on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerLocation {
+    pub peer_id: String,
+    pub addrs: Vec<String>,
+}
+
+/// Rendezvous namespace of a room (the same key it's advertised under in the DHT)
+pub fn namespace(room_code: &str) -> String {
+    format!("cider-room-{}", room_code)
+}
+
+/// Relay a circuit address goes through (`.../p2p/<relay>/p2p-circuit/...`)
+pub fn circuit_relay(addr: &Multiaddr) -> Option<PeerId> {
+    let mut relay = None;
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::P2p(peer_id) => relay = Some(peer_id),
+            Protocol::P2pCircuit => return relay,
+            _ => {}
+        }
+    }
+    None
+}
+
+impl LookupResponse {
+    /// Peers to dial from the answer, and their addresses (never ourselves)
+    pub fn dialable(&self, local_peer_id: &PeerId) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        self.peers
+            .iter()
+            .filter_map(|location| {
+                let peer_id: PeerId = location.peer_id.parse().ok()?;
+                let addrs: Vec<Multiaddr> = location.addrs.iter().filter_map(|a| a.parse().ok()).collect();
+                (peer_id != *local_peer_id && !addrs.is_empty()).then_some((peer_id, addrs))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dialable_locations() {
+        let (relay, host, me) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let circuit = format!("/ip4/203.0.113.10/tcp/4001/p2p/{}/p2p-circuit/p2p/{}", relay, host);
+        let response = LookupResponse {
+            peers: vec![
+                PeerLocation { peer_id: host.to_string(), addrs: vec![circuit.clone(), "garbage".to_string()] },
+                PeerLocation { peer_id: me.to_string(), addrs: vec![circuit.clone()] },
+                PeerLocation { peer_id: "not a peer".to_string(), addrs: vec![circuit.clone()] },
+            ],
+        };
+
+        let dialable = response.dialable(&me);
+        assert_eq!(dialable.len(), 1);
+        assert_eq!(dialable[0].0, host);
+        assert_eq!(dialable[0].1, vec![circuit.parse::<Multiaddr>().unwrap()]);
+        assert_eq!(circuit_relay(&dialable[0].1[0]), Some(relay));
+        assert_eq!(circuit_relay(&"/ip4/203.0.113.10/tcp/4001".parse().unwrap()), None);
+    }
+}
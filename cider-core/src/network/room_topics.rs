@@ -0,0 +1,170 @@
+//! A room's gossipsub topics, one per class of message
+//!
+//! The host's heartbeats make up most of a room's traffic, and gossipsub
+//! fans each one out across the mesh. They go out on a topic of their own, so
+//! a listener that doesn't need them (paused on its own end, say) can leave
+//! it, and one that only follows the playback can leave the control topic.
+//!
+//! Control messages stay on the topic rooms have always used, the only one
+//! older peers subscribe to. While any of them are in the room, the host sends
+//! its heartbeats there too, and peers that got them on their own topic leave
+//! out that copy.
+
+use std::collections::{HashMap, HashSet};
+
+use libp2p::gossipsub::{IdentTopic, TopicHash};
+use libp2p::PeerId;
+
+use crate::sync::MessageClass;
+
+/// The topics of a room
+#[derive(Clone)]
+pub struct RoomTopics {
+    control: IdentTopic,
+    heartbeat: IdentTopic,
+}
+
+impl RoomTopics {
+    pub fn new(room_code: &str) -> Self {
+        Self {
+            control: IdentTopic::new(format!("cider-room-{}", room_code)),
+            heartbeat: IdentTopic::new(format!("cider-room-{}-heartbeat", room_code)),
+        }
+    }
+
+    /// The topic `class` goes out on
+    pub fn get(&self, class: MessageClass) -> &IdentTopic {
+        match class {
+            MessageClass::Control => &self.control,
+            MessageClass::Heartbeat => &self.heartbeat,
+        }
+    }
+
+    /// The class `topic` is for, if it's one of ours
+    pub fn class_of(&self, topic: &TopicHash) -> Option<MessageClass> {
+        MessageClass::ALL.into_iter().find(|class| self.get(*class).hash() == *topic)
+    }
+
+    pub fn all(&self) -> [&IdentTopic; 2] {
+        [&self.control, &self.heartbeat]
+    }
+}
+
+/// Peers subscribed to the room's topics, and the classes each takes
+#[derive(Default)]
+pub struct RoomPeers {
+    classes: HashMap<PeerId, HashSet<MessageClass>>,
+    /// Peers seen on a topic other than control, so running a version with
+    /// class topics (they may have left it since)
+    split: HashSet<PeerId>,
+}
+
+impl RoomPeers {
+    /// Record `peer_id` taking `class`; true if they weren't in the room
+    pub fn subscribed(&mut self, peer_id: PeerId, class: MessageClass) -> bool {
+        if class != MessageClass::Control {
+            self.split.insert(peer_id);
+        }
+        let classes = self.classes.entry(peer_id).or_default();
+        let joined = classes.is_empty();
+        classes.insert(class);
+        joined
+    }
+
+    /// Record `peer_id` no longer taking `class`; true if that was their
+    /// last, so they left the room
+    pub fn unsubscribed(&mut self, peer_id: &PeerId, class: MessageClass) -> bool {
+        let Some(classes) = self.classes.get_mut(peer_id) else {
+            return false;
+        };
+        classes.remove(&class);
+        if !classes.is_empty() {
+            return false;
+        }
+        self.classes.remove(peer_id);
+        true
+    }
+
+    /// Take `peer_id` out of the room; true if they were in it
+    pub fn remove(&mut self, peer_id: &PeerId) -> bool {
+        self.classes.remove(peer_id).is_some()
+    }
+
+    /// Keep everyone in the room, but as if on none of its topics: the room
+    /// moved to new ones, and they count as joined again once on one of those
+    pub fn moved(&mut self) {
+        for classes in self.classes.values_mut() {
+            classes.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.classes.clear();
+        self.split.clear();
+    }
+
+    pub fn ids(&self) -> HashSet<PeerId> {
+        self.classes.keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+
+    /// Whether `peer_id` sends each class on its own topic
+    pub fn splits_classes(&self, peer_id: &PeerId) -> bool {
+        self.split.contains(peer_id)
+    }
+
+    /// Whether messages of `class` need to go on the control topic as well,
+    /// for older peers in the room
+    pub fn needs_control_copy(&self, class: MessageClass) -> bool {
+        class != MessageClass::Control && self.classes.keys().any(|peer_id| !self.split.contains(peer_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_topics() {
+        let topics = RoomTopics::new("ABCD4679");
+        // Older peers only know this one
+        assert_eq!(topics.get(MessageClass::Control).to_string(), "cider-room-ABCD4679");
+        assert_eq!(
+            topics.class_of(&topics.get(MessageClass::Heartbeat).hash()),
+            Some(MessageClass::Heartbeat)
+        );
+        let other = RoomTopics::new("WXYK4679");
+        assert_eq!(topics.class_of(&other.get(MessageClass::Control).hash()), None);
+    }
+
+    #[test]
+    fn test_room_peers() {
+        let (listener, spectator, older) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let mut peers = RoomPeers::default();
+        assert!(peers.subscribed(listener, MessageClass::Control));
+        assert!(!peers.subscribed(listener, MessageClass::Heartbeat));
+        assert!(peers.subscribed(spectator, MessageClass::Heartbeat));
+        assert!(!peers.needs_control_copy(MessageClass::Heartbeat));
+
+        // Paused, the listener skips heartbeats; the spectator never took control
+        assert!(!peers.unsubscribed(&listener, MessageClass::Heartbeat));
+        assert!(peers.splits_classes(&listener));
+        assert!(peers.unsubscribed(&spectator, MessageClass::Heartbeat));
+        assert_eq!(peers.len(), 1);
+
+        // Heartbeats go on the control topic too while an older peer is in
+        assert!(peers.subscribed(older, MessageClass::Control));
+        assert!(peers.needs_control_copy(MessageClass::Heartbeat));
+        assert!(!peers.needs_control_copy(MessageClass::Control));
+        assert!(peers.remove(&older));
+        assert!(!peers.needs_control_copy(MessageClass::Heartbeat));
+
+        // Moved to new topics: still in the room, joined again on them
+        peers.moved();
+        assert_eq!(peers.len(), 1);
+        assert!(peers.subscribed(listener, MessageClass::Control));
+    }
+}
@@ -1,30 +1,376 @@
-//! Simple signaling via ntfy.sh for room discovery
+//! Simple signaling for room discovery
 //!
-//! Uses the free ntfy.sh pub/sub service to exchange peer addresses.
-//! No signup required, works immediately over the internet.
-//! Can be configured to use a custom ntfy.sh-compatible server.
+//! Hosts publish their addresses under the room code and joiners poll for
+//! them. By default this goes through the free ntfy.sh pub/sub service: no
+//! signup required, works immediately over the internet. Groups that don't
+//! want to depend on ntfy.sh (its availability, its rate limits) can point
+//! the client at their own ntfy server, or at the signaling endpoint of a
+//! Cider relay server (`[signaling]` in its relay.toml).
+//!
+//! Rather than polling, clients can subscribe to a room's channel: ntfy
+//! streams new messages as they're published (server-sent events), so a
+//! joiner learns the host's addresses as soon as they're out, and the host
+//! sees joiners announce themselves.
+//!
+//! Peers in a room re-announce themselves every `ANNOUNCE_INTERVAL`, well
+//! within the 5 minutes polls look back over, so a room code nobody has
+//! announced lately isn't in use: hosts pick another code if theirs is, and
+//! joiners learn early that there's no such room.
+//!
+//! Hosts can also announce the room on a presence channel of their own, named
+//! after their peer ID, so friends who know the ID can tell they're hosting
+//! and join without being sent the code. Anyone can publish on a channel, so
+//! announcements are signed with the key the peer ID is derived from, and
+//! dated so an old one can't be passed off as current.
 
-use reqwest::Client;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::PeerId;
+use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
 
 /// Default signaling server URL
 const DEFAULT_SIGNALING_URL: &str = "https://ntfy.sh";
 
+/// How often subscriptions poll backends that can't push messages
+const SUBSCRIBE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Wait before resubscribing after a subscription drops
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(3);
+
+/// How often peers in a room re-announce themselves on signaling
+pub(crate) const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2 * 60);
+
+/// Characters of a peer ID its presence channel is named after
+const PRESENCE_ID_CHARS: usize = 32;
+
+/// How old a presence announcement can be and still count (hosts repeat
+/// theirs every `ANNOUNCE_INTERVAL`)
+const PRESENCE_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// Domain separator so presence signatures can't be confused with other signed data
+const PRESENCE_CONTEXT: &[u8] = b"cider-presence:";
+
 /// Message published to signaling channel
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct SignalingMessage {
     pub peer_id: String,
     pub addresses: Vec<String>,
     pub room_code: String,
+    /// Published by a peer still joining the room, rather than one in it
+    #[serde(default)]
+    pub joining: bool,
+    /// Presence announcements only: proof they're from `peer_id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<PresenceProof>,
+}
+
+/// A presence announcement's date and its announcer's signature
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PresenceProof {
+    /// When it was announced (ms since the epoch)
+    pub signed_at_ms: u64,
+    /// Signature over `presence_payload` (hex-encoded)
+    pub signature: String,
+}
+
+impl SignalingMessage {
+    /// Our announcement that we're hosting `room_code`, signed with our identity key
+    pub fn presence(identity: &Keypair, room_code: &str) -> Self {
+        let peer_id = identity.public().to_peer_id().to_string();
+        let signed_at_ms = crate::clock::now_ms();
+        let signature = identity
+            .sign(&presence_payload(&peer_id, room_code, signed_at_ms))
+            .map(|signature| signature.iter().map(|b| format!("{:02x}", b)).collect())
+            .unwrap_or_default();
+        Self {
+            peer_id,
+            addresses: Vec::new(),
+            room_code: room_code.to_string(),
+            joining: false,
+            proof: Some(PresenceProof { signed_at_ms, signature }),
+        }
+    }
+
+    /// Whether this is a presence announcement `peer_id` signed in the last
+    /// `PRESENCE_MAX_AGE`
+    fn is_presence_of(&self, peer_id: &str, now_ms: u64) -> bool {
+        let Some(proof) = self.proof.as_ref().filter(|_| self.peer_id == peer_id) else {
+            return false;
+        };
+        // Either way, for clocks that are a bit off
+        let fresh = now_ms.abs_diff(proof.signed_at_ms) <= PRESENCE_MAX_AGE.as_millis() as u64;
+        let (Some(key), Some(signature)) = (public_key_of(peer_id), decode_hex(&proof.signature)) else {
+            return false;
+        };
+        fresh && key.verify(&presence_payload(peer_id, &self.room_code, proof.signed_at_ms), &signature)
+    }
+}
+
+/// What a presence announcement's signature covers
+fn presence_payload(peer_id: &str, room_code: &str, signed_at_ms: u64) -> Vec<u8> {
+    let mut payload = PRESENCE_CONTEXT.to_vec();
+    payload.extend_from_slice(format!("{}:{}:{}", peer_id, room_code, signed_at_ms).as_bytes());
+    payload
+}
+
+/// The public key a peer ID is derived from, if it's inlined in it (as for
+/// the Ed25519 keys peers use)
+fn public_key_of(peer_id: &str) -> Option<PublicKey> {
+    /// Multihash code of keys inlined as is
+    const IDENTITY_HASH: u64 = 0x00;
+    let peer_id: PeerId = peer_id.parse().ok()?;
+    let multihash: &libp2p::multihash::Multihash<64> = peer_id.as_ref();
+    if multihash.code() != IDENTITY_HASH {
+        return None;
+    }
+    PublicKey::try_decode_protobuf(multihash.digest())
+        .ok()
+        .filter(|key| key.to_peer_id() == peer_id)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok().filter(|p| p.len() == 2)?, 16).ok())
+        .collect()
+}
+
+/// A server that passes signaling messages around by topic
+pub trait SignalingBackend: Send + Sync {
+    /// The server's URL (for logging)
+    fn base_url(&self) -> &str;
+
+    /// Publish `message` on `topic`
+    fn publish<'a>(&'a self, topic: &'a str, message: &'a SignalingMessage) -> BoxFuture<'a, Result<(), String>>;
+
+    /// Messages published on `topic` in the last few minutes
+    fn poll<'a>(&'a self, topic: &'a str) -> BoxFuture<'a, Result<Vec<SignalingMessage>, String>>;
+
+    /// Messages published on `topic` in the last few minutes, then new ones as
+    /// they're published, until the connection drops. Polls by default.
+    fn subscribe<'a>(&'a self, topic: &'a str) -> BoxStream<'a, Result<SignalingMessage, String>> {
+        poll_repeatedly(self, topic)
+    }
+}
+
+/// `backend.poll(topic)` every `SUBSCRIBE_POLL_INTERVAL`, as a stream (repeats included)
+fn poll_repeatedly<'a, B: SignalingBackend + ?Sized>(
+    backend: &'a B,
+    topic: &'a str,
+) -> BoxStream<'a, Result<SignalingMessage, String>> {
+    stream::unfold(true, move |first| async move {
+        if !first {
+            tokio::time::sleep(SUBSCRIBE_POLL_INTERVAL).await;
+        }
+        Some((backend.poll(topic).await, false))
+    })
+    .flat_map(|polled| {
+        let items: Vec<_> = match polled {
+            Ok(messages) => messages.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        };
+        stream::iter(items)
+    })
+    .boxed()
+}
+
+/// An ntfy server (ntfy.sh, or self-hosted)
+pub struct NtfyBackend {
+    client: Client,
+    base_url: String,
+}
+
+impl NtfyBackend {
+    pub fn new(base_url: String) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl SignalingBackend for NtfyBackend {
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn publish<'a>(&'a self, topic: &'a str, message: &'a SignalingMessage) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let url = format!("{}/{}", self.base_url, topic);
+            let body = serde_json::to_string(message).map_err(|e| e.to_string())?;
+            self.client
+                .post(&url)
+                .header("Title", format!("Room {}", message.room_code))
+                .header("Tags", "musical_note")
+                .body(body)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("Failed to publish to signaling: {}", e))?;
+            Ok(())
+        })
+    }
+
+    fn poll<'a>(&'a self, topic: &'a str) -> BoxFuture<'a, Result<Vec<SignalingMessage>, String>> {
+        Box::pin(async move {
+            // Use the JSON endpoint with poll=1 to get cached messages
+            let url = format!("{}/{}/json?poll=1&since=5m", self.base_url, topic);
+            let text = self
+                .client
+                .get(&url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("Failed to poll signaling: {}", e))?
+                .text()
+                .await
+                .map_err(|e| format!("Failed to read response: {}", e))?;
+            Ok(parse_ntfy_messages(&text))
+        })
+    }
+
+    fn subscribe<'a>(&'a self, topic: &'a str) -> BoxStream<'a, Result<SignalingMessage, String>> {
+        // Server-sent events, starting with the cached messages
+        let url = format!("{}/{}/sse?since=5m", self.base_url, topic);
+        stream::once(async move {
+            self.client
+                .get(&url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("Failed to subscribe to signaling: {}", e))
+        })
+        .map_ok(sse_messages)
+        .try_flatten()
+        .boxed()
+    }
+}
+
+/// Signaling messages from ntfy's newline-delimited JSON (others are skipped)
+fn parse_ntfy_messages(text: &str) -> Vec<SignalingMessage> {
+    text.lines().filter_map(parse_ntfy_message).collect()
+}
+
+/// The signaling message in one of ntfy's JSON messages, if it holds one
+fn parse_ntfy_message(line: &str) -> Option<SignalingMessage> {
+    // Parse ntfy message wrapper; the actual message is in the "message" field
+    let ntfy_msg = serde_json::from_str::<serde_json::Value>(line.trim()).ok()?;
+    let message_str = ntfy_msg.get("message")?.as_str()?;
+    serde_json::from_str::<SignalingMessage>(message_str).ok()
+}
+
+/// Signaling messages in an ntfy server-sent event stream, as they arrive
+fn sse_messages(response: Response) -> BoxStream<'static, Result<SignalingMessage, String>> {
+    stream::unfold(Some((response, SseLines::default())), |state| async move {
+        let (mut response, mut lines) = state?;
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                let messages: Vec<_> = lines.push(&chunk).into_iter().map(Ok).collect();
+                Some((stream::iter(messages), Some((response, lines))))
+            }
+            Ok(None) => None,
+            Err(e) => Some((stream::iter(vec![Err(format!("Signaling subscription failed: {}", e))]), None)),
+        }
+    })
+    .flatten()
+    .boxed()
+}
+
+/// Splits a server-sent event stream into lines, picking out signaling messages
+#[derive(Default)]
+struct SseLines {
+    /// Start of a line whose end hasn't arrived yet
+    partial: Vec<u8>,
+}
+
+impl SseLines {
+    /// Add the next chunk of the stream; returns the messages it completed
+    fn push(&mut self, chunk: &[u8]) -> Vec<SignalingMessage> {
+        self.partial.extend_from_slice(chunk);
+        let Some(end) = self.partial.iter().rposition(|b| *b == b'\n') else {
+            return Vec::new();
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+
+        // Keepalives and the like are events too; only "message" events have data we parse
+        String::from_utf8_lossy(&complete)
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .filter_map(parse_ntfy_message)
+            .collect()
+    }
+}
+
+/// The signaling endpoint of a Cider relay server
+///
+/// `POST {base_url}/signaling/{topic}` publishes a message and
+/// `GET {base_url}/signaling/{topic}` returns the recent ones as a JSON array.
+pub struct RelayBackend {
+    client: Client,
+    base_url: String,
+}
+
+impl RelayBackend {
+    pub fn new(base_url: String) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn topic_url(&self, topic: &str) -> String {
+        format!("{}/signaling/{}", self.base_url, topic)
+    }
+}
+
+impl SignalingBackend for RelayBackend {
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn publish<'a>(&'a self, topic: &'a str, message: &'a SignalingMessage) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            self.client
+                .post(self.topic_url(topic))
+                .json(message)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("Failed to publish to signaling: {}", e))?;
+            Ok(())
+        })
+    }
+
+    fn poll<'a>(&'a self, topic: &'a str) -> BoxFuture<'a, Result<Vec<SignalingMessage>, String>> {
+        Box::pin(async move {
+            let messages: Vec<serde_json::Value> = self
+                .client
+                .get(self.topic_url(topic))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("Failed to poll signaling: {}", e))?
+                .json()
+                .await
+                .map_err(|e| format!("Failed to read response: {}", e))?;
+            // Anyone can publish on a topic; skip what isn't ours
+            Ok(messages.into_iter().filter_map(|m| serde_json::from_value(m).ok()).collect())
+        })
+    }
 }
 
 /// Signaling client for room discovery
 #[derive(Clone)]
 pub struct SignalingClient {
-    client: Client,
-    /// Base URL for the signaling server (e.g., "https://ntfy.sh")
-    base_url: String,
+    backend: Arc<dyn SignalingBackend>,
 }
 
 impl SignalingClient {
@@ -33,18 +379,25 @@ impl SignalingClient {
         Self::with_url(DEFAULT_SIGNALING_URL.to_string())
     }
 
-    /// Create a new signaling client with custom server URL
+    /// Create a new signaling client with a custom ntfy server URL
     pub fn with_url(base_url: String) -> Self {
-        info!("Signaling client using server: {}", base_url);
-        Self {
-            client: Client::new(),
-            base_url,
-        }
+        Self::with_backend(Arc::new(NtfyBackend::new(base_url)))
+    }
+
+    /// Create a new signaling client using a relay server's signaling endpoint
+    pub fn relay(base_url: String) -> Self {
+        Self::with_backend(Arc::new(RelayBackend::new(base_url)))
+    }
+
+    /// Create a new signaling client on any backend
+    pub fn with_backend(backend: Arc<dyn SignalingBackend>) -> Self {
+        info!("Signaling client using server: {}", backend.base_url());
+        Self { backend }
     }
 
     /// Get the signaling server URL
     pub fn base_url(&self) -> &str {
-        &self.base_url
+        self.backend.base_url()
     }
 
     /// Normalize room code for topic naming - strips hyphens and lowercases
@@ -56,85 +409,127 @@ impl SignalingClient {
             .to_lowercase()
     }
 
+    fn topic(room_code: &str) -> String {
+        format!("cider-together-{}", Self::normalize_room_code(room_code))
+    }
+
+    /// Our presence channel: the peer ID's last 32 characters, lowercased to
+    /// fit topic names (peer IDs all start alike)
+    fn presence_topic(peer_id: &str) -> String {
+        let start = peer_id.len().saturating_sub(PRESENCE_ID_CHARS);
+        let id = peer_id.get(start..).unwrap_or(peer_id);
+        format!("cider-presence-{}", id.to_lowercase())
+    }
+
     /// Publish our addresses to the room's signaling channel
+    /// (`joining` if we're not in the room yet, so its host can reach out to us)
     pub async fn publish_room(
         &self,
         room_code: &str,
         peer_id: &str,
         addresses: Vec<String>,
+        joining: bool,
     ) -> Result<(), String> {
-        let normalized = Self::normalize_room_code(room_code);
-        let topic = format!("cider-together-{}", normalized);
-        let url = format!("{}/{}", self.base_url, topic);
-
+        let topic = Self::topic(room_code);
         let msg = SignalingMessage {
             peer_id: peer_id.to_string(),
             addresses,
             room_code: room_code.to_string(),
+            joining,
+            proof: None,
         };
 
-        let body = serde_json::to_string(&msg).map_err(|e| e.to_string())?;
-
-        info!("Signaling: Publishing room {} (topic: {}) to ntfy.sh", room_code, topic);
-
-        self.client
-            .post(&url)
-            .header("Title", format!("Room {}", room_code))
-            .header("Tags", "musical_note")
-            .body(body)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to publish to signaling: {}", e))?;
-
+        info!("Signaling: Publishing room {} (topic: {}) to {}", room_code, topic, self.base_url());
+        self.backend.publish(&topic, &msg).await?;
         info!("Signaling: Room {} published successfully", room_code);
         Ok(())
     }
 
     /// Poll for peers in a room (gets recent messages)
     pub async fn poll_room(&self, room_code: &str) -> Result<Vec<SignalingMessage>, String> {
-        let normalized = Self::normalize_room_code(room_code);
-        let topic = format!("cider-together-{}", normalized);
-        // Use the JSON endpoint with poll=1 to get cached messages
-        let url = format!("{}/{}/json?poll=1&since=5m", self.base_url, topic);
-
+        let topic = Self::topic(room_code);
         debug!("Signaling: Polling room {} (topic: {})", room_code, topic);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to poll signaling: {}", e))?;
-
-        let text = response
-            .text()
-            .await
-            .map_err(|e| format!("Failed to read response: {}", e))?;
-
-        // ntfy returns newline-delimited JSON
-        let mut messages = Vec::new();
-        for line in text.lines() {
-            if line.trim().is_empty() {
-                continue;
-            }
-
-            // Parse ntfy message wrapper
-            if let Ok(ntfy_msg) = serde_json::from_str::<serde_json::Value>(line) {
-                // The actual message is in the "message" field
-                if let Some(message_str) = ntfy_msg.get("message").and_then(|m| m.as_str()) {
-                    if let Ok(sig_msg) = serde_json::from_str::<SignalingMessage>(message_str) {
-                        messages.push(sig_msg);
-                    }
-                }
-            }
-        }
-
+        let messages = self.backend.poll(&topic).await?;
         if !messages.is_empty() {
             info!("Signaling: Found {} peers in room {}", messages.len(), room_code);
         }
 
         Ok(messages)
     }
+
+    /// Announce on our presence channel that we're hosting `room_code`
+    pub async fn publish_presence(&self, identity: &Keypair, room_code: &str) -> Result<(), String> {
+        let msg = SignalingMessage::presence(identity, room_code);
+        debug!("Signaling: Announcing presence in room {}", room_code);
+        self.backend.publish(&Self::presence_topic(&msg.peer_id), &msg).await
+    }
+
+    /// The room `peer_id` last announced hosting on its presence channel, if
+    /// it did in the last few minutes (announcements it didn't sign don't count)
+    pub async fn poll_presence(&self, peer_id: &str) -> Result<Option<String>, String> {
+        let messages = self.backend.poll(&Self::presence_topic(peer_id)).await?;
+        let now_ms = crate::clock::now_ms();
+        Ok(messages
+            .into_iter()
+            .rev()
+            .find(|m| m.is_presence_of(peer_id, now_ms))
+            .map(|m| m.room_code))
+    }
+
+    /// Whether anyone but `local_peer_id` in the room announced themselves
+    /// recently (joiners don't count): if not, there's no active room with
+    /// this code
+    pub async fn room_active(&self, room_code: &str, local_peer_id: &str) -> Result<bool, String> {
+        let messages = self.poll_room(room_code).await?;
+        Ok(messages.iter().any(|m| !m.joining && m.peer_id != local_peer_id))
+    }
+
+    /// Watch the room's signaling channel: messages from the last few minutes,
+    /// then new ones as they're published, each once. Resubscribes if the
+    /// connection drops, until the receiver is dropped.
+    pub fn subscribe_room(&self, room_code: &str) -> mpsc::UnboundedReceiver<SignalingMessage> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let backend = Arc::clone(&self.backend);
+        let topic = Self::topic(room_code);
+        info!("Signaling: Subscribing to room {} (topic: {})", room_code, topic);
+
+        tokio::spawn(async move {
+            let mut seen = HashSet::new();
+            loop {
+                let mut messages = backend.subscribe(&topic);
+                loop {
+                    let next = tokio::select! {
+                        _ = tx.closed() => return,
+                        next = messages.next() => next,
+                    };
+                    match next {
+                        Some(Ok(message)) => {
+                            if seen.insert(message.clone()) && tx.send(message).is_err() {
+                                return;
+                            }
+                        }
+                        Some(Err(e)) => {
+                            warn!("{}", e);
+                            break;
+                        }
+                        None => {
+                            debug!("Signaling: Subscription to {} closed", topic);
+                            break;
+                        }
+                    }
+                }
+                drop(messages);
+
+                tokio::select! {
+                    _ = tx.closed() => return,
+                    _ = tokio::time::sleep(RESUBSCRIBE_DELAY) => {}
+                }
+            }
+        });
+
+        rx
+    }
 }
 
 impl Default for SignalingClient {
@@ -142,3 +537,88 @@ impl Default for SignalingClient {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ntfy_messages() {
+        let ours = SignalingMessage {
+            peer_id: "host".to_string(),
+            addresses: vec!["/ip4/1.2.3.4/tcp/4001/p2p/relay/p2p-circuit".to_string()],
+            room_code: "ABCD-1234".to_string(),
+            joining: false,
+            proof: None,
+        };
+        let wrap = |message: &str| serde_json::json!({ "event": "message", "message": message }).to_string();
+        let text = [
+            serde_json::json!({ "event": "open" }).to_string(),
+            wrap(&serde_json::to_string(&ours).unwrap()),
+            String::new(),
+            wrap("someone else's note"),
+        ]
+        .join("\n");
+
+        let messages = parse_ntfy_messages(&text);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].peer_id, "host");
+        assert_eq!(SignalingClient::topic("ABCD-1234"), "cider-together-abcd1234");
+        assert_eq!(
+            SignalingClient::presence_topic("12D3KooWQYhTNQdmr3ArTeUHRYzFg94BKyTkoWBDWez9kSCVe2Xo"),
+            "cider-presence-teuhryzfg94bkytkowbdwez9kscve2xo"
+        );
+    }
+
+    #[test]
+    fn test_presence_signed() {
+        let host = Keypair::generate_ed25519();
+        let presence = SignalingMessage::presence(&host, "ABCD4679");
+        let peer_id = presence.peer_id.clone();
+        let now_ms = presence.proof.as_ref().unwrap().signed_at_ms;
+        assert!(presence.is_presence_of(&peer_id, now_ms));
+        assert!(!presence.is_presence_of(&PeerId::random().to_string(), now_ms));
+
+        // Stale, moved to another room, or claimed by someone else: none count
+        let stale = now_ms + PRESENCE_MAX_AGE.as_millis() as u64 + 1;
+        assert!(!presence.is_presence_of(&peer_id, stale));
+        let moved = SignalingMessage { room_code: "WXYZ3467".to_string(), ..presence.clone() };
+        assert!(!moved.is_presence_of(&peer_id, now_ms));
+        let other = Keypair::generate_ed25519().public().to_peer_id().to_string();
+        let claimed = SignalingMessage { peer_id: other.clone(), ..presence.clone() };
+        assert!(!claimed.is_presence_of(&other, now_ms));
+        let unsigned = SignalingMessage { proof: None, ..presence };
+        assert!(!unsigned.is_presence_of(&peer_id, now_ms));
+    }
+
+    #[test]
+    fn test_sse_lines_across_chunks() {
+        let ours = SignalingMessage {
+            peer_id: "joiner".to_string(),
+            addresses: vec!["/ip4/5.6.7.8/udp/4001/quic-v1/p2p/relay/p2p-circuit".to_string()],
+            room_code: "ABCD-1234".to_string(),
+            joining: true,
+            proof: None,
+        };
+        let data = serde_json::json!({
+            "event": "message",
+            "message": serde_json::to_string(&ours).unwrap(),
+        });
+        let stream = format!(
+            "event: open\ndata: {{\"event\":\"open\"}}\n\nevent: message\ndata: {}\n\nevent: keepalive\ndata: {{\"event\":\"keepalive\"}}\n\n",
+            data
+        );
+
+        // However the stream is split, each message comes out once, when its line is complete
+        for split in [1, 7, 40, stream.len() - 1] {
+            let mut lines = SseLines::default();
+            let mut messages = lines.push(&stream.as_bytes()[..split]);
+            messages.extend(lines.push(&stream.as_bytes()[split..]));
+            assert_eq!(messages, vec![ours.clone()], "split at {}", split);
+        }
+
+        // Messages from clients that predate join intents are from peers in the room
+        let old = r#"{"peer_id":"host","addresses":[],"room_code":"ABCD-1234"}"#;
+        assert!(!serde_json::from_str::<SignalingMessage>(old).unwrap().joining);
+    }
+}
@@ -3,6 +3,9 @@
 //! Cider's seek operation has inherent latency due to buffering.
 //! This module adaptively calibrates the seek offset based on observed drift
 //! to minimize sync error between host and listeners.
+//!
+//! Seeking right after loading a track takes longer than seeking within one
+//! that's already buffered, so each kind of seek has its own offset.
 
 use std::sync::{Arc, RwLock};
 
@@ -28,6 +31,9 @@ const MAX_CALIBRATION_DRIFT_MS: i64 = 1500;
 /// We still learn from outliers, just much more slowly
 const OUTLIER_ALPHA: f64 = 0.05;
 
+/// Samples taken with the faster initial alpha, after which the offset has converged
+const CONVERGED_SAMPLE_COUNT: u32 = 5;
+
 /// A recorded calibration sample
 #[derive(Debug, Clone)]
 pub struct CalibrationSample {
@@ -44,15 +50,46 @@ pub struct CalibrationSample {
 /// Maximum number of samples to keep in history
 const MAX_SAMPLE_HISTORY: usize = 10;
 
-/// Calibrates seek offset based on observed drift
-#[derive(Debug)]
-pub struct SeekCalibrator {
+/// Where a seek lands, which decides how long Cider buffers before playing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekKind {
+    /// Right after loading a track (track changes, joining)
+    TrackStart,
+    /// Within the track already playing (drift corrections, the host seeking)
+    InTrack,
+}
+
+impl SeekKind {
+    pub const ALL: [SeekKind; 2] = [SeekKind::TrackStart, SeekKind::InTrack];
+}
+
+/// Calibrated offset for one kind of seek
+#[derive(Debug, Clone, Copy)]
+struct OffsetProfile {
     /// Current calibrated seek offset in milliseconds
     offset_ms: f64,
     /// Number of samples received (for initial calibration)
     sample_count: u32,
-    /// Whether we're waiting to measure the result of a seek operation
-    awaiting_measurement: bool,
+}
+
+impl Default for OffsetProfile {
+    fn default() -> Self {
+        Self {
+            offset_ms: DEFAULT_SEEK_OFFSET_MS as f64,
+            sample_count: 0,
+        }
+    }
+}
+
+/// Calibrates seek offset based on observed drift
+#[derive(Debug)]
+pub struct SeekCalibrator {
+    /// Offset for seeks right after loading a track
+    track_start: OffsetProfile,
+    /// Offset for seeks within the playing track
+    in_track: OffsetProfile,
+    /// The kind of seek we're waiting to measure the result of, if any
+    awaiting_measurement: Option<SeekKind>,
     /// Recent sample history for debug display
     sample_history: Vec<CalibrationSample>,
 }
@@ -60,38 +97,75 @@ pub struct SeekCalibrator {
 impl SeekCalibrator {
     pub fn new() -> Self {
         Self {
-            offset_ms: DEFAULT_SEEK_OFFSET_MS as f64,
-            sample_count: 0,
-            awaiting_measurement: false,
+            track_start: OffsetProfile::default(),
+            in_track: OffsetProfile::default(),
+            awaiting_measurement: None,
             sample_history: Vec::new(),
         }
     }
 
-    /// Get the current calibrated seek offset in milliseconds
-    pub fn offset_ms(&self) -> u64 {
-        self.offset_ms.round() as u64
+    fn profile(&self, kind: SeekKind) -> &OffsetProfile {
+        match kind {
+            SeekKind::TrackStart => &self.track_start,
+            SeekKind::InTrack => &self.in_track,
+        }
+    }
+
+    fn profile_mut(&mut self, kind: SeekKind) -> &mut OffsetProfile {
+        match kind {
+            SeekKind::TrackStart => &mut self.track_start,
+            SeekKind::InTrack => &mut self.in_track,
+        }
+    }
+
+    /// Get the current calibrated seek offset for `kind` in milliseconds
+    pub fn offset_ms(&self, kind: SeekKind) -> u64 {
+        self.profile(kind).offset_ms.round() as u64
+    }
+
+    /// Number of seeks of `kind` measured so far
+    pub fn sample_count(&self, kind: SeekKind) -> u32 {
+        self.profile(kind).sample_count
+    }
+
+    /// Whether enough seeks of `kind` were measured for its offset to be worth keeping
+    pub fn is_converged(&self, kind: SeekKind) -> bool {
+        self.sample_count(kind) >= CONVERGED_SAMPLE_COUNT
+    }
+
+    /// Start from a calibration of `kind` learned earlier (e.g. in a previous run)
+    ///
+    /// With its sample count, a converged offset is only fine-tuned rather
+    /// than relearned with the faster initial alpha.
+    pub fn restore(&mut self, kind: SeekKind, offset_ms: u64, sample_count: u32) {
+        *self.profile_mut(kind) = OffsetProfile {
+            offset_ms: offset_ms.clamp(MIN_SEEK_OFFSET_MS, MAX_SEEK_OFFSET_MS) as f64,
+            sample_count,
+        };
     }
 
     /// Check if we're waiting to measure after a seek
     pub fn is_awaiting_measurement(&self) -> bool {
-        self.awaiting_measurement
+        self.awaiting_measurement.is_some()
     }
 
-    /// Preview what ideal offset would result from a given drift measurement.
+    /// Preview what ideal offset would result from a given drift measurement
+    /// (of the pending seek, or an in-track one).
     /// Returns None if the drift would be rejected as an outlier.
     pub fn preview_calibration(&self, drift_ms: i64) -> Option<i64> {
         if drift_ms.abs() > MAX_CALIBRATION_DRIFT_MS {
             return None; // Would be rejected as outlier
         }
         // ideal_offset = current_offset - drift
-        let ideal = self.offset_ms - drift_ms as f64;
+        let kind = self.awaiting_measurement.unwrap_or(SeekKind::InTrack);
+        let ideal = self.profile(kind).offset_ms - drift_ms as f64;
         Some(ideal.round() as i64)
     }
 
-    /// Mark that a seek was just performed and we should measure on next heartbeat
-    pub fn mark_seek_performed(&mut self) {
-        self.awaiting_measurement = true;
-        tracing::debug!("Seek calibrator: marked awaiting measurement");
+    /// Mark that a seek of `kind` was just performed and we should measure on next heartbeat
+    pub fn mark_seek_performed(&mut self, kind: SeekKind) {
+        self.awaiting_measurement = Some(kind);
+        tracing::debug!("Seek calibrator: marked awaiting measurement ({:?})", kind);
     }
 
     /// Called on each heartbeat. If we were awaiting a measurement (just seeked),
@@ -100,21 +174,20 @@ impl SeekCalibrator {
     /// - Negative drift = we're behind host → need MORE offset
     /// - Positive drift = we're ahead of host → need LESS offset
     pub fn measure_if_pending(&mut self, drift_ms: i64) -> bool {
-        if !self.awaiting_measurement {
-            return false;
-        }
-
         // Clear the flag - we only measure once per seek
-        self.awaiting_measurement = false;
+        let Some(kind) = self.awaiting_measurement.take() else {
+            return false;
+        };
+        let profile = self.profile_mut(kind);
 
         // Calculate ideal offset for this measurement
-        let ideal_offset = self.offset_ms - drift_ms as f64;
+        let ideal_offset = profile.offset_ms - drift_ms as f64;
 
         // Determine alpha based on drift magnitude
         // Large drifts (outliers) get much smaller weight - we learn slowly from them
         let is_outlier = drift_ms.abs() > MAX_CALIBRATION_DRIFT_MS;
 
-        self.sample_count = self.sample_count.saturating_add(1);
+        profile.sample_count = profile.sample_count.saturating_add(1);
 
         let alpha = if is_outlier {
             // Outlier: learn very slowly (but still learn!)
@@ -124,32 +197,34 @@ impl SeekCalibrator {
                 OUTLIER_ALPHA
             );
             OUTLIER_ALPHA
-        } else if self.sample_count <= 5 {
+        } else if profile.sample_count <= CONVERGED_SAMPLE_COUNT {
             0.4 // Faster initial calibration
         } else {
             EMA_ALPHA
         };
 
         // EMA update
-        self.offset_ms = alpha * ideal_offset + (1.0 - alpha) * self.offset_ms;
+        profile.offset_ms = alpha * ideal_offset + (1.0 - alpha) * profile.offset_ms;
 
         // Clamp to bounds
-        self.offset_ms = self.offset_ms.clamp(MIN_SEEK_OFFSET_MS as f64, MAX_SEEK_OFFSET_MS as f64);
+        profile.offset_ms = profile.offset_ms.clamp(MIN_SEEK_OFFSET_MS as f64, MAX_SEEK_OFFSET_MS as f64);
+        let profile = *profile;
 
         // Record sample (mark outliers as "rejected" meaning damped weight)
         self.record_sample(CalibrationSample {
             drift_ms,
             ideal_offset_ms: ideal_offset.round() as i64,
-            new_offset_ms: self.offset_ms.round() as u64,
+            new_offset_ms: profile.offset_ms.round() as u64,
             rejected: is_outlier,
         });
 
         tracing::debug!(
-            "Seek calibrator: measured drift={:+}ms, ideal={}ms, new_offset={}ms (samples={}, outlier={})",
+            "Seek calibrator: measured {:?} drift={:+}ms, ideal={}ms, new_offset={}ms (samples={}, outlier={})",
+            kind,
             drift_ms,
             ideal_offset.round(),
-            self.offset_ms.round(),
-            self.sample_count,
+            profile.offset_ms.round(),
+            profile.sample_count,
             is_outlier
         );
 
@@ -171,9 +246,9 @@ impl SeekCalibrator {
 
     /// Reset calibration (e.g., when joining a new room)
     pub fn reset(&mut self) {
-        self.offset_ms = DEFAULT_SEEK_OFFSET_MS as f64;
-        self.sample_count = 0;
-        self.awaiting_measurement = false;
+        self.track_start = OffsetProfile::default();
+        self.in_track = OffsetProfile::default();
+        self.awaiting_measurement = None;
         self.sample_history.clear();
     }
 }
@@ -199,31 +274,31 @@ mod tests {
     #[test]
     fn test_initial_offset() {
         let calibrator = SeekCalibrator::new();
-        assert_eq!(calibrator.offset_ms(), DEFAULT_SEEK_OFFSET_MS);
+        assert_eq!(calibrator.offset_ms(SeekKind::InTrack), DEFAULT_SEEK_OFFSET_MS);
     }
 
     #[test]
     fn test_no_update_without_pending() {
         let mut calibrator = SeekCalibrator::new();
-        let initial = calibrator.offset_ms();
+        let initial = calibrator.offset_ms(SeekKind::InTrack);
 
         // Without marking seek performed, measure_if_pending should do nothing
         let updated = calibrator.measure_if_pending(-200);
         assert!(!updated);
-        assert_eq!(calibrator.offset_ms(), initial);
+        assert_eq!(calibrator.offset_ms(SeekKind::InTrack), initial);
     }
 
     #[test]
     fn test_behind_increases_offset() {
         let mut calibrator = SeekCalibrator::new();
-        let initial = calibrator.offset_ms();
+        let initial = calibrator.offset_ms(SeekKind::InTrack);
 
         // Mark seek performed, then measure
-        calibrator.mark_seek_performed();
+        calibrator.mark_seek_performed(SeekKind::InTrack);
         let updated = calibrator.measure_if_pending(-200); // We're behind by 200ms
 
         assert!(updated);
-        assert!(calibrator.offset_ms() > initial);
+        assert!(calibrator.offset_ms(SeekKind::InTrack) > initial);
     }
 
     #[test]
@@ -231,17 +306,17 @@ mod tests {
         let mut calibrator = SeekCalibrator::new();
 
         // Mark seek performed
-        calibrator.mark_seek_performed();
+        calibrator.mark_seek_performed(SeekKind::InTrack);
 
         // First measurement should update
         let updated1 = calibrator.measure_if_pending(-200);
         assert!(updated1);
-        let after_first = calibrator.offset_ms();
+        let after_first = calibrator.offset_ms(SeekKind::InTrack);
 
         // Second measurement without new seek should NOT update
         let updated2 = calibrator.measure_if_pending(-200);
         assert!(!updated2);
-        assert_eq!(calibrator.offset_ms(), after_first);
+        assert_eq!(calibrator.offset_ms(SeekKind::InTrack), after_first);
     }
 
     #[test]
@@ -250,17 +325,17 @@ mod tests {
 
         // Prime with some samples
         for _ in 0..10 {
-            calibrator.mark_seek_performed();
+            calibrator.mark_seek_performed(SeekKind::InTrack);
             calibrator.measure_if_pending(0);
         }
-        let initial = calibrator.offset_ms();
+        let initial = calibrator.offset_ms(SeekKind::InTrack);
 
         // We're ahead by 200ms
-        calibrator.mark_seek_performed();
+        calibrator.mark_seek_performed(SeekKind::InTrack);
         calibrator.measure_if_pending(200);
 
         // Offset should decrease
-        assert!(calibrator.offset_ms() < initial);
+        assert!(calibrator.offset_ms(SeekKind::InTrack) < initial);
     }
 
     #[test]
@@ -269,18 +344,35 @@ mod tests {
 
         // Try to push way below minimum
         for _ in 0..100 {
-            calibrator.mark_seek_performed();
+            calibrator.mark_seek_performed(SeekKind::InTrack);
             calibrator.measure_if_pending(1000); // Way ahead
         }
-        assert!(calibrator.offset_ms() >= MIN_SEEK_OFFSET_MS);
+        assert!(calibrator.offset_ms(SeekKind::InTrack) >= MIN_SEEK_OFFSET_MS);
 
         // Try to push way above maximum
         calibrator.reset();
         for _ in 0..100 {
-            calibrator.mark_seek_performed();
+            calibrator.mark_seek_performed(SeekKind::InTrack);
             calibrator.measure_if_pending(-5000); // Way behind
         }
-        assert!(calibrator.offset_ms() <= MAX_SEEK_OFFSET_MS);
+        assert!(calibrator.offset_ms(SeekKind::InTrack) <= MAX_SEEK_OFFSET_MS);
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut calibrator = SeekCalibrator::new();
+        calibrator.restore(SeekKind::InTrack, 800, 12);
+        assert_eq!(calibrator.offset_ms(SeekKind::InTrack), 800);
+        assert!(calibrator.is_converged(SeekKind::InTrack));
+
+        // A converged offset is only nudged by a bad measurement
+        calibrator.mark_seek_performed(SeekKind::InTrack);
+        calibrator.measure_if_pending(-400);
+        assert_eq!(calibrator.offset_ms(SeekKind::InTrack), 860);
+
+        calibrator.restore(SeekKind::InTrack, 10_000, 0);
+        assert_eq!(calibrator.offset_ms(SeekKind::InTrack), MAX_SEEK_OFFSET_MS);
+        assert!(!calibrator.is_converged(SeekKind::InTrack));
     }
 
     #[test]
@@ -292,16 +384,37 @@ mod tests {
         let true_latency: i64 = 700;
 
         for _ in 0..50 {
-            let current_offset = calibrator.offset_ms() as i64;
+            let current_offset = calibrator.offset_ms(SeekKind::InTrack) as i64;
             // Simulate drift based on how close we are to true latency
             let simulated_drift = current_offset - true_latency;
 
-            calibrator.mark_seek_performed();
+            calibrator.mark_seek_performed(SeekKind::InTrack);
             calibrator.measure_if_pending(simulated_drift);
         }
 
         // Should converge close to 700ms
-        let offset = calibrator.offset_ms();
+        let offset = calibrator.offset_ms(SeekKind::InTrack);
         assert!(offset >= 650 && offset <= 750, "Expected ~700ms, got {}ms", offset);
     }
+
+    #[test]
+    fn test_kinds_calibrated_separately() {
+        let mut calibrator = SeekCalibrator::new();
+
+        // Loading a track buffers longer than seeking within it
+        for _ in 0..10 {
+            calibrator.mark_seek_performed(SeekKind::TrackStart);
+            let drift = calibrator.offset_ms(SeekKind::TrackStart) as i64 - 900;
+            calibrator.measure_if_pending(drift);
+
+            calibrator.mark_seek_performed(SeekKind::InTrack);
+            let drift = calibrator.offset_ms(SeekKind::InTrack) as i64 - 300;
+            calibrator.measure_if_pending(drift);
+        }
+
+        assert!(calibrator.offset_ms(SeekKind::TrackStart) > 800);
+        assert!(calibrator.offset_ms(SeekKind::InTrack) < 400);
+        assert_eq!(calibrator.sample_count(SeekKind::TrackStart), 10);
+        assert_eq!(calibrator.sample_count(SeekKind::InTrack), 10);
+    }
 }
@@ -3,7 +3,11 @@
 //! Handles synchronization of playback state between peers.
 
 mod protocol;
+mod recap;
 mod state;
+mod validation;
 
 pub use protocol::*;
+pub use recap::*;
 pub use state::*;
+pub use validation::*;
@@ -1,5 +1,7 @@
 //! Sync Protocol Messages
 
+use std::collections::HashSet;
+
 use serde::{Deserialize, Serialize};
 
 /// Information about a track for sync purposes
@@ -17,6 +19,12 @@ pub struct TrackInfo {
     pub artwork_url: String,
     /// Duration in milliseconds
     pub duration_ms: u64,
+    /// ISRC, for finding the recording in other storefronts (not sent by older peers)
+    #[serde(default)]
+    pub isrc: Option<String>,
+    /// Marked explicit in the catalog
+    #[serde(default)]
+    pub explicit: bool,
 }
 
 /// Participant in a listening room
@@ -28,6 +36,214 @@ pub struct Participant {
     pub display_name: String,
     /// Whether this participant is the current host
     pub is_host: bool,
+    /// Apple Music storefront (country) of their account, if known
+    #[serde(default)]
+    pub storefront: Option<String>,
+    /// What their Apple Music account can play
+    #[serde(default)]
+    pub capabilities: Capabilities,
+    /// Avatar and color shown next to their name
+    #[serde(default)]
+    pub profile: Profile,
+    /// Their platform and app version
+    #[serde(default)]
+    pub client: ClientInfo,
+    /// Whether they're at their device
+    #[serde(default)]
+    pub status: ParticipantStatus,
+}
+
+/// Whether a participant is at their device, for the host to tell who's
+/// actually listening
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParticipantStatus {
+    /// No input on their device for a while
+    Idle,
+    /// They said they're away
+    Away,
+    /// At their device (also older peers, and statuses we don't know of)
+    #[default]
+    #[serde(other)]
+    Active,
+}
+
+/// Longest avatar accepted (room for a small image URL)
+const MAX_AVATAR_LEN: usize = 256;
+
+/// How a participant appears in the room list, besides their name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    /// An emoji, or an image URL or hash
+    #[serde(default)]
+    pub avatar: Option<String>,
+    /// Accent color as `#RRGGBB`
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+impl Profile {
+    /// The profile without values the apps couldn't show (they come from other peers)
+    pub fn sanitized(self) -> Self {
+        let avatar = self
+            .avatar
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty() && a.len() <= MAX_AVATAR_LEN);
+        let color = self.color.filter(|c| {
+            c.len() == 7 && c.starts_with('#') && c[1..].chars().all(|ch| ch.is_ascii_hexdigit())
+        });
+        Self { avatar, color }
+    }
+}
+
+/// Longest app or core version accepted
+const MAX_VERSION_LEN: usize = 32;
+
+/// The OS a participant's app runs on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    #[serde(rename = "macos")]
+    MacOs,
+    Windows,
+    Ios,
+    Linux,
+    /// Not sent (older peers), or one we don't know of
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+impl Platform {
+    /// The platform we're built for
+    pub fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else if cfg!(target_os = "ios") {
+            Platform::Ios
+        } else if cfg!(target_os = "linux") {
+            Platform::Linux
+        } else {
+            Platform::Unknown
+        }
+    }
+}
+
+/// The app a participant runs, so the host can tell why one listener behaves
+/// differently, and support can spot outdated clients
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientInfo {
+    #[serde(default)]
+    pub platform: Platform,
+    /// Version of the app, if it gave one
+    #[serde(default)]
+    pub app_version: Option<String>,
+    /// Version of cider-core
+    #[serde(default)]
+    pub core_version: Option<String>,
+}
+
+impl ClientInfo {
+    /// Ours, running `app_version` of the app
+    pub fn local(app_version: Option<String>) -> Self {
+        Self {
+            platform: Platform::current(),
+            app_version,
+            core_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }
+    }
+
+    /// The info without versions too long or odd to show (they come from other peers)
+    pub fn sanitized(self) -> Self {
+        let version = |v: Option<String>| {
+            v.map(|v| v.trim().to_string()).filter(|v| {
+                !v.is_empty() && v.len() <= MAX_VERSION_LEN && v.chars().all(|c| c.is_ascii_graphic())
+            })
+        };
+        Self {
+            platform: self.platform,
+            app_version: version(self.app_version),
+            core_version: version(self.core_version),
+        }
+    }
+}
+
+/// Restrictions of a participant's Apple Music account
+///
+/// Tracks their account can't play fail silently on their end, so the host
+/// needs to know about these up front.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Explicit content is restricted (None = unknown, e.g. older peers)
+    #[serde(default)]
+    pub explicit_restricted: Option<bool>,
+}
+
+impl Capabilities {
+    /// Whether they can play a track marked explicit or not
+    pub fn can_play(&self, track: &TrackInfo) -> bool {
+        !track.explicit || self.explicit_restricted != Some(true)
+    }
+}
+
+/// The host's moderation settings, shared with the room
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Moderation {
+    /// No one new may join
+    #[serde(default)]
+    pub locked: bool,
+    /// Peers whose suggestions are ignored
+    #[serde(default)]
+    pub suggestions_muted: HashSet<String>,
+}
+
+/// Who may do something in the room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Permission {
+    #[default]
+    HostOnly,
+    Everyone,
+}
+
+/// How newcomers get into the room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApprovalMode {
+    /// Anyone with the code joins
+    #[default]
+    Open,
+    /// The host lets each newcomer in
+    HostApproval,
+}
+
+/// Room rules set by the host, shared with the room
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomSettings {
+    #[serde(default)]
+    pub who_can_queue: Permission,
+    #[serde(default)]
+    pub who_can_skip: Permission,
+    /// Most participants, host included (None = no limit)
+    #[serde(default)]
+    pub max_participants: Option<u32>,
+    #[serde(default)]
+    pub approval_mode: ApprovalMode,
+    /// Whether listeners follow the host's volume unless they opt out
+    #[serde(default)]
+    pub follow_volume: bool,
+}
+
+/// Why the host removed someone from the room (or turned them away)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RemovalReason {
+    Kicked,
+    /// Banned for as long as the room lasts
+    Banned,
+    /// Tried to join a locked room
+    RoomLocked,
+    /// Tried to join a room at its participant limit
+    RoomFull,
 }
 
 /// Current playback state
@@ -41,6 +257,51 @@ pub struct PlaybackInfo {
     pub timestamp_ms: u64,
 }
 
+/// Version of what `RoomState::room_hash` covers and how
+///
+/// Bump it whenever a hashed type changes shape (a participant field, a
+/// setting...): peers on another version hash the same room differently.
+pub const ROOM_HASH_VERSION: u32 = 1;
+
+/// Hash of the room the host shares, in its heartbeats (see `RoomState::room_hash`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomHash {
+    /// `ROOM_HASH_VERSION` of whoever hashed it: only the same one compares
+    pub version: u32,
+    pub hash: u64,
+}
+
+/// How well a listener kept in sync with the host during a room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncStats {
+    /// Sum of the drift measured on heartbeats, either way (ms)
+    pub drift_total_ms: u64,
+    /// Heartbeats measured while the host was playing
+    pub samples: u32,
+    /// Seeks and catch-up pauses to get back in sync
+    pub corrections: u32,
+}
+
+impl SyncStats {
+    /// Add a drift measurement
+    pub fn record_drift(&mut self, drift_ms: i64) {
+        self.drift_total_ms += drift_ms.unsigned_abs();
+        self.samples += 1;
+    }
+
+    /// Add the stats in `other`
+    pub fn add(&mut self, other: SyncStats) {
+        self.drift_total_ms += other.drift_total_ms;
+        self.samples += other.samples;
+        self.corrections += other.corrections;
+    }
+
+    /// Average drift either way (ms), if any was measured
+    pub fn average_drift_ms(&self) -> Option<u64> {
+        (self.samples > 0).then(|| self.drift_total_ms / self.samples as u64)
+    }
+}
+
 /// Messages exchanged between peers for synchronization
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SyncMessage {
@@ -52,10 +313,31 @@ pub enum SyncMessage {
         participants: Vec<Participant>,
         current_track: Option<TrackInfo>,
         playback: PlaybackInfo,
+        #[serde(default)]
+        moderation: Moderation,
+        #[serde(default)]
+        settings: RoomSettings,
     },
 
     /// Request to join a room
-    JoinRequest { display_name: String },
+    JoinRequest {
+        display_name: String,
+        /// Our Apple Music storefront, so the host can tell region-locked tracks apart
+        #[serde(default)]
+        storefront: Option<String>,
+        /// Our account's restrictions
+        #[serde(default)]
+        capabilities: Capabilities,
+        /// Our avatar and color
+        #[serde(default)]
+        profile: Profile,
+        /// Our platform and app version
+        #[serde(default)]
+        client: ClientInfo,
+        /// Whether we're at our device
+        #[serde(default)]
+        status: ParticipantStatus,
+    },
 
     /// Response to join request
     JoinResponse {
@@ -70,8 +352,40 @@ pub enum SyncMessage {
     /// Notification that someone left
     ParticipantLeft { peer_id: String },
 
+    /// A participant changed their display name or profile (sent by that participant)
+    ParticipantUpdated {
+        peer_id: String,
+        display_name: String,
+        #[serde(default)]
+        profile: Profile,
+    },
+
+    /// A participant went idle or away, or came back (sent by that participant)
+    StatusUpdated { peer_id: String, status: ParticipantStatus },
+
     /// Host is transferring control to another peer
-    TransferHost { new_host_peer_id: String },
+    TransferHost {
+        new_host_peer_id: String,
+        /// The old host's track and playback, for the new one to carry on
+        /// from (not sent by older peers)
+        #[serde(default)]
+        track: Option<TrackInfo>,
+        #[serde(default)]
+        playback: Option<PlaybackInfo>,
+    },
+
+    /// The host gave the room a new code; everyone moves to its topic
+    RoomCodeChanged { room_code: String },
+
+    /// A listener asks the host to hand over control (sent by that listener)
+    ControlRequest,
+
+    /// The host removed a participant or turned them away; they leave the room
+    Removed { peer_id: String, reason: RemovalReason },
+
+    /// A listener's copy of the room no longer matches the host's (by the
+    /// hash in its heartbeats); the host sends the room state again
+    RoomStateRequest,
 
     // === Playback Commands (from host) ===
     /// Start or resume playback
@@ -94,8 +408,17 @@ pub enum SyncMessage {
         timestamp_ms: u64,
     },
 
+    /// A listener couldn't play the host's track (nor a match from its own storefront)
+    TrackUnavailable { song_id: String },
+
+    /// A listener's sync so far this room, for the recap (sent by that listener)
+    SyncReport(SyncStats),
+
     // === Clock Synchronization ===
-    /// Ping for measuring round-trip time
+    /// Ping for measuring round-trip time, on the room topic
+    ///
+    /// Older versions only ping this way, so it's still answered; we only
+    /// send it to peers a direct ping can't reach (see `network::latency_probe`).
     Ping { sent_at_ms: u64 },
 
     /// Pong response for RTT calculation
@@ -109,10 +432,35 @@ pub enum SyncMessage {
     Heartbeat {
         track_id: Option<String>,
         playback: PlaybackInfo,
+        /// The host's `RoomState::room_hash` (not sent by older hosts)
+        #[serde(default)]
+        room_hash: Option<RoomHash>,
     },
 }
 
+/// What a message is for; each class goes out on a topic of its own, so
+/// peers can skip those they don't need
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageClass {
+    /// Everything but heartbeats: room changes, the host's playback commands
+    Control,
+    /// The host's heartbeats, several a minute
+    Heartbeat,
+}
+
+impl MessageClass {
+    pub const ALL: [MessageClass; 2] = [MessageClass::Control, MessageClass::Heartbeat];
+}
+
 impl SyncMessage {
+    /// The class of this message, for the topic it goes out on
+    pub fn class(&self) -> MessageClass {
+        match self {
+            SyncMessage::Heartbeat { .. } => MessageClass::Heartbeat,
+            _ => MessageClass::Control,
+        }
+    }
+
     /// Check if this is a playback command that requires host privileges
     pub fn requires_host(&self) -> bool {
         matches!(
@@ -122,6 +470,8 @@ impl SyncMessage {
                 | SyncMessage::Seek { .. }
                 | SyncMessage::TrackChange { .. }
                 | SyncMessage::TransferHost { .. }
+                | SyncMessage::RoomCodeChanged { .. }
+                | SyncMessage::Removed { .. }
         )
     }
 }
@@ -0,0 +1,490 @@
+//! Recap of a room
+//!
+//! When a room ends, people like to share what they listened to, and when
+//! someone says sync was bad, we need more to go on than "it was off". The
+//! recorder follows the room through its changes and keeps the tracks played,
+//! who was there and how well each listener kept in sync, for the app to
+//! export as JSON or CSV.
+//!
+//! Listeners only know their own drift, so each one sends the room what it
+//! measured every `SYNC_REPORT_INTERVAL_MS`: everyone's recap covers everyone.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use super::protocol::SyncStats;
+use super::state::{Room, RoomState};
+
+/// How often listeners send the room their sync stats (ms)
+pub const SYNC_REPORT_INTERVAL_MS: u64 = 30_000;
+
+/// A track played in the room
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecapTrack {
+    pub song_id: String,
+    pub name: String,
+    pub artist: String,
+    pub album: String,
+    pub duration_ms: u64,
+    /// ISRC, for finding the recording in other storefronts
+    pub isrc: Option<String>,
+    /// When it came on (ms since the epoch)
+    pub started_at_ms: u64,
+    /// How long it played, pauses excluded
+    pub played_ms: u64,
+}
+
+/// Someone who was in the room
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecapParticipant {
+    pub peer_id: String,
+    pub display_name: String,
+    /// Whether they were the host at some point
+    pub was_host: bool,
+    /// When they first joined (ms since the epoch)
+    pub joined_at_ms: u64,
+    /// When they last left, if before the room ended
+    pub left_at_ms: Option<u64>,
+    /// Average drift from the host either way, if they reported any (ms)
+    pub average_drift_ms: Option<u64>,
+    /// Drift measurements behind the average
+    pub drift_samples: u32,
+    /// Seeks and catch-up pauses to get back in sync
+    pub corrections: u32,
+}
+
+/// What happened in a room, as seen from here
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RoomRecap {
+    /// The room's code when it ended (it may have been rotated)
+    pub room_code: String,
+    /// Whose recap it is
+    pub local_peer_id: String,
+    /// When we joined or created the room (ms since the epoch)
+    pub started_at_ms: u64,
+    /// When we left it (None while still in it)
+    pub ended_at_ms: Option<u64>,
+    /// In the order they played
+    pub tracks: Vec<RecapTrack>,
+    /// In the order they joined
+    pub participants: Vec<RecapParticipant>,
+}
+
+impl RoomRecap {
+    /// The recap as pretty-printed JSON
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).unwrap_or_default()
+    }
+
+    /// The recap as CSV: the room, its tracks and its participants, as three
+    /// tables (each with a header) separated by blank lines
+    pub fn to_csv(&self) -> Vec<u8> {
+        let mut csv = String::new();
+        push_row(&mut csv, &["room_code", "local_peer_id", "started_at_ms", "ended_at_ms"]);
+        push_row(
+            &mut csv,
+            &[
+                &self.room_code,
+                &self.local_peer_id,
+                &self.started_at_ms.to_string(),
+                &optional(self.ended_at_ms),
+            ],
+        );
+
+        csv.push_str("\r\n");
+        push_row(
+            &mut csv,
+            &["song_id", "name", "artist", "album", "duration_ms", "started_at_ms", "played_ms"],
+        );
+        for track in &self.tracks {
+            push_row(
+                &mut csv,
+                &[
+                    &track.song_id,
+                    &track.name,
+                    &track.artist,
+                    &track.album,
+                    &track.duration_ms.to_string(),
+                    &track.started_at_ms.to_string(),
+                    &track.played_ms.to_string(),
+                ],
+            );
+        }
+
+        csv.push_str("\r\n");
+        push_row(
+            &mut csv,
+            &[
+                "peer_id",
+                "display_name",
+                "was_host",
+                "joined_at_ms",
+                "left_at_ms",
+                "average_drift_ms",
+                "drift_samples",
+                "corrections",
+            ],
+        );
+        for participant in &self.participants {
+            push_row(
+                &mut csv,
+                &[
+                    &participant.peer_id,
+                    &participant.display_name,
+                    &participant.was_host.to_string(),
+                    &participant.joined_at_ms.to_string(),
+                    &optional(participant.left_at_ms),
+                    &optional(participant.average_drift_ms),
+                    &participant.drift_samples.to_string(),
+                    &participant.corrections.to_string(),
+                ],
+            );
+        }
+        csv.into_bytes()
+    }
+}
+
+fn optional(value: Option<u64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Add a CSV row, quoting fields that need it (RFC 4180)
+///
+/// Spreadsheets take a cell starting with `=`, `+`, `-` or `@` as a formula,
+/// and names and titles come from other peers: those get a `'` in front.
+fn push_row(csv: &mut String, fields: &[&str]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        let escaped;
+        let field = if field.starts_with(['=', '+', '-', '@']) {
+            escaped = format!("'{}", field);
+            escaped.as_str()
+        } else {
+            field
+        };
+        if field.contains([',', '"', '\n', '\r']) {
+            csv.push('"');
+            csv.push_str(&field.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(field);
+        }
+    }
+    csv.push_str("\r\n");
+}
+
+/// The room being recorded
+#[derive(Debug)]
+struct Recording {
+    recap: RoomRecap,
+    host_peer_id: String,
+    /// Sync stats by participant
+    sync: HashMap<String, SyncStats>,
+    /// When the room was last observed, and whether it was playing then
+    observed_at_ms: u64,
+    playing: bool,
+    /// When we last sent the room our sync stats
+    reported_at_ms: u64,
+}
+
+impl Recording {
+    fn start(state: &RoomState, now_ms: u64) -> Self {
+        Self {
+            recap: RoomRecap {
+                room_code: state.room_code.clone(),
+                local_peer_id: state.local_peer_id.clone(),
+                started_at_ms: now_ms,
+                ended_at_ms: None,
+                tracks: Vec::new(),
+                participants: Vec::new(),
+            },
+            host_peer_id: state.host_peer_id.clone(),
+            sync: HashMap::new(),
+            observed_at_ms: now_ms,
+            playing: false,
+            reported_at_ms: now_ms,
+        }
+    }
+
+    fn observe(&mut self, state: &RoomState, now_ms: u64) {
+        self.count_playing_time(now_ms);
+        self.playing = state.playback.is_playing;
+        self.recap.room_code = state.room_code.clone();
+        self.host_peer_id = state.host_peer_id.clone();
+
+        if let Some(track) = &state.current_track {
+            let is_new = self.recap.tracks.last().is_none_or(|last| last.song_id != track.song_id);
+            if is_new {
+                self.recap.tracks.push(RecapTrack {
+                    song_id: track.song_id.clone(),
+                    name: track.name.clone(),
+                    artist: track.artist.clone(),
+                    album: track.album.clone(),
+                    duration_ms: track.duration_ms,
+                    isrc: track.isrc.clone(),
+                    started_at_ms: now_ms,
+                    played_ms: 0,
+                });
+            }
+        }
+
+        for participant in state.participants.values() {
+            let recorded = self.recap.participants.iter_mut().find(|p| p.peer_id == participant.peer_id);
+            match recorded {
+                Some(recorded) => {
+                    recorded.display_name = participant.display_name.clone();
+                    recorded.was_host |= participant.is_host;
+                    recorded.left_at_ms = None;
+                }
+                None => self.recap.participants.push(RecapParticipant {
+                    peer_id: participant.peer_id.clone(),
+                    display_name: participant.display_name.clone(),
+                    was_host: participant.is_host,
+                    joined_at_ms: now_ms,
+                    left_at_ms: None,
+                    average_drift_ms: None,
+                    drift_samples: 0,
+                    corrections: 0,
+                }),
+            }
+        }
+        for recorded in &mut self.recap.participants {
+            if recorded.left_at_ms.is_none() && !state.participants.contains_key(&recorded.peer_id) {
+                recorded.left_at_ms = Some(now_ms);
+            }
+        }
+    }
+
+    /// Count the time since the last observation to the track, if it was playing
+    fn count_playing_time(&mut self, now_ms: u64) {
+        if self.playing {
+            if let Some(track) = self.recap.tracks.last_mut() {
+                track.played_ms += now_ms.saturating_sub(self.observed_at_ms);
+            }
+        }
+        self.observed_at_ms = now_ms;
+    }
+
+    /// The recap so far, ended at `ended_at_ms` if given
+    fn snapshot(&self, ended_at_ms: Option<u64>) -> RoomRecap {
+        let mut recap = self.recap.clone();
+        recap.ended_at_ms = ended_at_ms;
+        for participant in &mut recap.participants {
+            if let Some(stats) = self.sync.get(&participant.peer_id) {
+                participant.average_drift_ms = stats.average_drift_ms();
+                participant.drift_samples = stats.samples;
+                participant.corrections = stats.corrections;
+            }
+        }
+        recap
+    }
+}
+
+/// Records the room we're in, and keeps the recap of the last one
+#[derive(Debug, Default)]
+pub struct RecapRecorder {
+    current: Option<Recording>,
+    last: Option<RoomRecap>,
+}
+
+pub type SharedRecapRecorder = Arc<Mutex<RecapRecorder>>;
+
+/// Create a new shared recap recorder
+pub fn new_shared_recorder() -> SharedRecapRecorder {
+    Arc::new(Mutex::new(RecapRecorder::new()))
+}
+
+impl RecapRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follow a change to the room; the recording ends once we're out of it
+    pub fn observe(&mut self, room: &Room, now_ms: u64) {
+        let Some(state) = room.state() else {
+            if let Some(mut recording) = self.current.take() {
+                recording.count_playing_time(now_ms);
+                self.last = Some(recording.snapshot(Some(now_ms)));
+            }
+            return;
+        };
+        self.current
+            .get_or_insert_with(|| Recording::start(state, now_ms))
+            .observe(state, now_ms);
+    }
+
+    /// Add our own sync measurements (as a listener)
+    pub fn add_local_sync(&mut self, stats: SyncStats) {
+        if let Some(recording) = &mut self.current {
+            let local_peer_id = recording.recap.local_peer_id.clone();
+            recording.sync.entry(local_peer_id).or_default().add(stats);
+        }
+    }
+
+    /// `peer_id` sent their sync stats so far (they replace what they sent before)
+    pub fn reported(&mut self, peer_id: &str, stats: SyncStats) {
+        let Some(recording) = &mut self.current else {
+            return;
+        };
+        if peer_id != recording.recap.local_peer_id
+            && recording.recap.participants.iter().any(|p| p.peer_id == peer_id)
+        {
+            recording.sync.insert(peer_id.to_string(), stats);
+        }
+    }
+
+    /// Our sync stats, if we're a listener with some and it's time to send
+    /// them to the room again
+    pub fn report_due(&mut self, now_ms: u64) -> Option<SyncStats> {
+        let recording = self.current.as_mut()?;
+        if recording.host_peer_id == recording.recap.local_peer_id
+            || now_ms.saturating_sub(recording.reported_at_ms) < SYNC_REPORT_INTERVAL_MS
+        {
+            return None;
+        }
+        let stats = recording.sync.get(&recording.recap.local_peer_id).copied()?;
+        recording.reported_at_ms = now_ms;
+        Some(stats)
+    }
+
+    /// The recap of the room we're in so far, or else of the last one
+    pub fn recap(&self) -> Option<RoomRecap> {
+        match &self.current {
+            Some(recording) => Some(recording.snapshot(None)),
+            None => self.last.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{Capabilities, ClientInfo, Participant, ParticipantStatus, PlaybackInfo, Profile, TrackInfo};
+
+    fn room_with(participants: &[&str], track: Option<&str>, is_playing: bool) -> Room {
+        let mut state = RoomState::new_as_host(
+            "ABCD4679".to_string(),
+            "host".to_string(),
+            "Host".to_string(),
+            None,
+            Capabilities::default(),
+            Profile::default(),
+            ClientInfo::default(),
+        );
+        for peer_id in participants {
+            state.add_participant(Participant {
+                peer_id: peer_id.to_string(),
+                display_name: format!("{}, listening", peer_id),
+                is_host: false,
+                storefront: None,
+                capabilities: Capabilities::default(),
+                profile: Profile::default(),
+                client: ClientInfo::default(),
+                status: ParticipantStatus::default(),
+            });
+        }
+        state.update_track(track.map(|song_id| TrackInfo {
+            song_id: song_id.to_string(),
+            name: format!("Song \"{}\"", song_id),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            artwork_url: String::new(),
+            duration_ms: 180_000,
+            isrc: None,
+            explicit: false,
+        }));
+        state.update_playback(PlaybackInfo { is_playing, position_ms: 0, timestamp_ms: 0 });
+        Room::Active(state)
+    }
+
+    #[test]
+    fn test_records_tracks_and_participants() {
+        let mut recorder = RecapRecorder::new();
+        assert!(recorder.recap().is_none());
+
+        recorder.observe(&room_with(&[], Some("1"), true), 1_000);
+        recorder.observe(&room_with(&["ana"], Some("1"), true), 11_000);
+        // Paused for 20s: doesn't count as played
+        recorder.observe(&room_with(&["ana"], Some("1"), false), 31_000);
+        recorder.observe(&room_with(&["ana"], Some("2"), true), 51_000);
+        recorder.observe(&room_with(&[], Some("2"), true), 56_000);
+        recorder.observe(&Room::None, 61_000);
+
+        let recap = recorder.recap().unwrap();
+        assert_eq!(recap.room_code, "ABCD4679");
+        assert_eq!((recap.started_at_ms, recap.ended_at_ms), (1_000, Some(61_000)));
+        let played: Vec<_> = recap.tracks.iter().map(|t| (t.song_id.as_str(), t.started_at_ms, t.played_ms)).collect();
+        assert_eq!(played, vec![("1", 1_000, 30_000), ("2", 51_000, 10_000)]);
+
+        let ana = &recap.participants[1];
+        assert_eq!((ana.peer_id.as_str(), ana.was_host), ("ana", false));
+        assert_eq!((ana.joined_at_ms, ana.left_at_ms), (11_000, Some(56_000)));
+        assert!(recap.participants[0].was_host && recap.participants[0].left_at_ms.is_none());
+    }
+
+    #[test]
+    fn test_sync_reports() {
+        let mut recorder = RecapRecorder::new();
+        let mut room = room_with(&["ana", "bo"], None, true);
+        if let Room::Active(state) = &mut room {
+            // We're a listener this time
+            state.local_peer_id = "ana".to_string();
+        }
+        recorder.observe(&room, 0);
+
+        let mut stats = SyncStats::default();
+        stats.record_drift(-40);
+        stats.record_drift(60);
+        stats.corrections = 1;
+        recorder.add_local_sync(stats);
+        assert_eq!(recorder.report_due(1_000), None);
+        assert_eq!(recorder.report_due(SYNC_REPORT_INTERVAL_MS), Some(stats));
+        assert_eq!(recorder.report_due(SYNC_REPORT_INTERVAL_MS + 1_000), None);
+
+        // Others' reports replace what they sent before; strangers' are ignored
+        recorder.reported("bo", SyncStats { drift_total_ms: 10, samples: 1, corrections: 0 });
+        recorder.reported("bo", SyncStats { drift_total_ms: 300, samples: 3, corrections: 2 });
+        recorder.reported("eve", SyncStats { drift_total_ms: 300, samples: 3, corrections: 2 });
+
+        let recap = recorder.recap().unwrap();
+        assert_eq!(recap.ended_at_ms, None);
+        let sync: Vec<_> = recap
+            .participants
+            .iter()
+            .map(|p| (p.peer_id.as_str(), p.average_drift_ms, p.corrections))
+            .collect();
+        assert_eq!(sync.len(), 3);
+        assert!(sync.contains(&("host", None, 0)));
+        assert!(sync.contains(&("ana", Some(50), 1)));
+        assert!(sync.contains(&("bo", Some(100), 2)));
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let mut recorder = RecapRecorder::new();
+        recorder.observe(&room_with(&["ana"], Some("1"), true), 0);
+        recorder.observe(&Room::None, 5_000);
+        let recap = recorder.recap().unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&recap.to_json()).unwrap();
+        assert_eq!(json["tracks"][0]["played_ms"], 5_000);
+        assert_eq!(json["participants"].as_array().unwrap().len(), 2);
+
+        let csv = String::from_utf8(recap.to_csv()).unwrap();
+        let tables: Vec<_> = csv.split("\r\n\r\n").collect();
+        assert_eq!(tables.len(), 3);
+        assert!(tables[0].ends_with("ABCD4679,host,0,5000"));
+        // Quotes and commas are escaped
+        assert!(tables[1].contains(r#"1,"Song ""1""",Artist,Album,180000,0,5000"#), "{}", tables[1]);
+        assert!(tables[2].contains(r#"ana,"ana, listening",false,0,,,0,0"#), "{}", tables[2]);
+
+        // As are formulas, so a spreadsheet shows them as text
+        let mut row = String::new();
+        push_row(&mut row, &[r#"=HYPERLINK("x")"#, "+1", "-2", "@SUM(A1)", "a=b"]);
+        assert_eq!(row, "\"'=HYPERLINK(\"\"x\"\")\",'+1,'-2,'@SUM(A1),a=b\r\n");
+    }
+}
@@ -1,9 +1,19 @@
 //! Room State Management
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, Instant};
 
-use super::protocol::{Participant, PlaybackInfo, TrackInfo};
+use super::protocol::{
+    Capabilities, ClientInfo, Moderation, Participant, ParticipantStatus, PlaybackInfo, Profile, RemovalReason,
+    RoomHash, RoomSettings, TrackInfo, ROOM_HASH_VERSION,
+};
+use super::validation::MAX_ROOM_PARTICIPANTS;
+
+/// Least time between a listener's requests for the room state
+pub const ROOM_STATE_REQUEST_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Least time between a listener's requests for control that reach the host
+pub const CONTROL_REQUEST_INTERVAL: Duration = Duration::from_secs(30);
 
 /// Current state of the room
 #[derive(Debug, Clone)]
@@ -22,11 +32,27 @@ pub struct RoomState {
     pub playback: PlaybackInfo,
     /// When we last received a heartbeat from host
     pub last_heartbeat: Instant,
+    /// Lock and muted suggestions, set by the host
+    pub moderation: Moderation,
+    /// Room rules, set by the host
+    pub settings: RoomSettings,
+    /// Peers who may not join again (host only, not shared)
+    pub banned: HashSet<String>,
+    /// When each listener last asked for control (host only, not shared)
+    pub control_requested: HashMap<String, Instant>,
 }
 
 impl RoomState {
     /// Create a new room state for a host
-    pub fn new_as_host(room_code: String, local_peer_id: String, display_name: String) -> Self {
+    pub fn new_as_host(
+        room_code: String,
+        local_peer_id: String,
+        display_name: String,
+        storefront: Option<String>,
+        capabilities: Capabilities,
+        profile: Profile,
+        client: ClientInfo,
+    ) -> Self {
         let mut participants = HashMap::new();
         participants.insert(
             local_peer_id.clone(),
@@ -34,6 +60,11 @@ impl RoomState {
                 peer_id: local_peer_id.clone(),
                 display_name,
                 is_host: true,
+                storefront,
+                capabilities,
+                profile,
+                client,
+                status: ParticipantStatus::default(),
             },
         );
 
@@ -49,6 +80,10 @@ impl RoomState {
                 timestamp_ms: 0,
             },
             last_heartbeat: Instant::now(),
+            moderation: Moderation::default(),
+            settings: RoomSettings::default(),
+            banned: HashSet::new(),
+            control_requested: HashMap::new(),
         }
     }
 
@@ -72,6 +107,14 @@ impl RoomState {
         list
     }
 
+    /// Listeners whose accounts can't play `track`
+    pub fn listeners_unable_to_play(&self, track: &TrackInfo) -> Vec<&Participant> {
+        self.participant_list()
+            .into_iter()
+            .filter(|p| !p.is_host && !p.capabilities.can_play(track))
+            .collect()
+    }
+
     /// Add a participant
     pub fn add_participant(&mut self, participant: Participant) {
         self.participants
@@ -80,9 +123,70 @@ impl RoomState {
 
     /// Remove a participant
     pub fn remove_participant(&mut self, peer_id: &str) -> Option<Participant> {
+        self.control_requested.remove(peer_id);
         self.participants.remove(peer_id)
     }
 
+    /// Set whether a participant is at their device
+    pub fn set_participant_status(&mut self, peer_id: &str, status: ParticipantStatus) -> bool {
+        match self.participants.get_mut(peer_id) {
+            Some(participant) => {
+                participant.status = status;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Change a participant's display name and profile
+    pub fn update_participant(&mut self, peer_id: &str, display_name: String, profile: Profile) -> bool {
+        match self.participants.get_mut(peer_id) {
+            Some(participant) => {
+                participant.display_name = display_name;
+                participant.profile = profile;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Why a peer may not join, if they may not (host only)
+    pub fn refusal_for(&self, peer_id: &str) -> Option<RemovalReason> {
+        let is_new = !self.participants.contains_key(peer_id);
+        // Past the protocol's cap, listeners would drop the rest of the list
+        let max = self.settings.max_participants.map_or(MAX_ROOM_PARTICIPANTS, |max| {
+            (max as usize).min(MAX_ROOM_PARTICIPANTS)
+        });
+        let is_full = self.participants.len() >= max;
+        if self.banned.contains(peer_id) {
+            Some(RemovalReason::Banned)
+        } else if is_new && self.moderation.locked {
+            Some(RemovalReason::RoomLocked)
+        } else if is_new && is_full {
+            Some(RemovalReason::RoomFull)
+        } else {
+            None
+        }
+    }
+
+    /// Whether suggestions from a peer are taken
+    pub fn accepts_suggestions_from(&self, peer_id: &str) -> bool {
+        !self.moderation.suggestions_muted.contains(peer_id)
+    }
+
+    /// Whether a listener's request for control at `now` is passed on, at most
+    /// one per `CONTROL_REQUEST_INTERVAL` (host only)
+    pub fn takes_control_request(&mut self, peer_id: &str, now: Instant) -> bool {
+        let due = self
+            .control_requested
+            .get(peer_id)
+            .is_none_or(|at| now.saturating_duration_since(*at) >= CONTROL_REQUEST_INTERVAL);
+        if due {
+            self.control_requested.insert(peer_id.to_string(), now);
+        }
+        due
+    }
+
     /// Transfer host to another peer
     pub fn transfer_host(&mut self, new_host_peer_id: &str) -> bool {
         // Check if new host exists
@@ -117,12 +221,72 @@ impl RoomState {
         self.current_track = track;
     }
 
+    /// Hash of what the host shares with the room besides playback and its
+    /// track (those come with every heartbeat anyway)
+    ///
+    /// Heartbeats carry the host's, so listeners can tell their copy missed a
+    /// change without the host sending the whole room each time.
+    pub fn room_hash(&self) -> RoomHash {
+        let mut participants: Vec<&Participant> = self.participants.values().collect();
+        participants.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        let mut muted: Vec<&String> = self.moderation.suggestions_muted.iter().collect();
+        muted.sort();
+        let shared = (&self.host_peer_id, participants, self.moderation.locked, muted, &self.settings);
+
+        // FNV-1a: the same on every peer, which std's hashers don't promise across releases
+        let hash = serde_json::to_vec(&shared)
+            .unwrap_or_default()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3));
+        RoomHash { version: ROOM_HASH_VERSION, hash }
+    }
+
     /// Check if heartbeat is stale (host might be disconnected)
     pub fn is_heartbeat_stale(&self, timeout: Duration) -> bool {
         self.last_heartbeat.elapsed() > timeout
     }
 }
 
+/// A listener's side of the room hashes in the host's heartbeats
+#[derive(Debug, Default)]
+pub struct RoomHashCheck {
+    /// Heartbeats in a row whose room hash didn't match ours
+    mismatched_heartbeats: u32,
+    /// When we last asked the host for the room state
+    requested: Option<Instant>,
+}
+
+impl RoomHashCheck {
+    /// Whether to ask the host for the room state, given the room hash in its
+    /// latest heartbeat and ours
+    ///
+    /// A change can cross a heartbeat on the way, so it takes two mismatches
+    /// in a row, and asking again waits `ROOM_STATE_REQUEST_INTERVAL` in case
+    /// the room state didn't settle it. Hashes of another version can't be
+    /// compared, and never count.
+    pub fn needs_room_state(&mut self, host_hash: RoomHash, our_hash: RoomHash, now: Instant) -> bool {
+        if host_hash.version != our_hash.version || host_hash == our_hash {
+            self.mismatched_heartbeats = 0;
+            return false;
+        }
+        self.mismatched_heartbeats += 1;
+        let due = self
+            .requested
+            .is_none_or(|at| now.saturating_duration_since(at) >= ROOM_STATE_REQUEST_INTERVAL);
+        if self.mismatched_heartbeats < 2 || !due {
+            return false;
+        }
+        self.mismatched_heartbeats = 0;
+        self.requested = Some(now);
+        true
+    }
+
+    /// The host sent the room state: our copy is up to date as of now
+    pub fn room_state_received(&mut self) {
+        self.mismatched_heartbeats = 0;
+    }
+}
+
 /// Represents the room we're in (or not)
 #[derive(Debug)]
 pub enum Room {
@@ -134,6 +298,14 @@ pub enum Room {
     Joining {
         room_code: String,
         display_name: String,
+        storefront: Option<String>,
+        capabilities: Capabilities,
+        profile: Profile,
+        client: ClientInfo,
+        status: ParticipantStatus,
+        /// A peer turned us away (who, and why): believed once its room
+        /// state shows it's the host
+        refusal: Option<(String, RemovalReason)>,
     },
     /// In an active room
     Active(RoomState),
@@ -158,6 +330,12 @@ impl Room {
         }
     }
 
+    /// Storefront of the host's account, if known
+    pub fn host_storefront(&self) -> Option<&str> {
+        let state = self.state()?;
+        state.participants.get(&state.host_peer_id)?.storefront.as_deref()
+    }
+
     /// Get mutable reference to active room state
     pub fn state_mut(&mut self) -> Option<&mut RoomState> {
         match self {
@@ -0,0 +1,317 @@
+//! Limits on what other peers send
+//!
+//! Anyone with a room's code can send to it, and what they send ends up in the
+//! room state and on screen: names in the participant list, track details and
+//! artwork in the now-playing card. Messages are brought within these limits
+//! when they arrive (see `SyncMessage::within_limits`), before anything reads
+//! them: names and track details are cut short, artwork from elsewhere than
+//! Apple Music is left out, and so on. Older peers send such things in good
+//! faith (a track uploaded to their library has its artwork on Cider's local
+//! server), so that's all that happens to them.
+//!
+//! IDs can't be cut short without pointing at something else, so a message
+//! with one past its limit is rejected whole, and counts against its sender.
+//! Our own names and tracks are cut to fit before they go out as well.
+
+use thiserror::Error;
+
+use super::protocol::{Participant, SyncMessage, TrackInfo};
+
+/// Longest display name, in characters
+pub const MAX_DISPLAY_NAME_LEN: usize = 64;
+
+/// Longest track name, artist, album or other free text, in characters
+pub const MAX_TEXT_LEN: usize = 512;
+
+/// Longest peer ID, song ID, storefront or room code
+pub const MAX_ID_LEN: usize = 128;
+
+/// Longest artwork URL
+pub const MAX_URL_LEN: usize = 1024;
+
+/// Most participants (and muted peers) in a room; also keeps its room state
+/// well within gossipsub's largest message
+pub const MAX_ROOM_PARTICIPANTS: usize = 100;
+
+/// Hosts artwork is loaded from (and their subdomains): Apple Music's CDN
+const ARTWORK_HOSTS: &[&str] = &["mzstatic.com", "apple.com"];
+
+/// Why a message from another peer was rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InvalidMessage {
+    #[error("{0} too long")]
+    TooLong(&'static str),
+}
+
+impl SyncMessage {
+    /// The message brought within the limits above, or why it can't be (an
+    /// ID past its limit)
+    pub fn within_limits(self) -> Result<Self, InvalidMessage> {
+        let message = match self {
+            SyncMessage::RoomState {
+                room_code,
+                host_peer_id,
+                mut participants,
+                current_track,
+                playback,
+                mut moderation,
+                settings,
+            } => {
+                id(&room_code, "room code")?;
+                id(&host_peer_id, "peer ID")?;
+                // The host is kept, wherever it is in the list
+                participants.retain(|p| p.peer_id.len() <= MAX_ID_LEN);
+                participants.sort_by_key(|p| p.peer_id != host_peer_id);
+                participants.truncate(MAX_ROOM_PARTICIPANTS);
+                moderation.suggestions_muted.retain(|peer_id| peer_id.len() <= MAX_ID_LEN);
+                if moderation.suggestions_muted.len() > MAX_ROOM_PARTICIPANTS {
+                    moderation.suggestions_muted =
+                        moderation.suggestions_muted.into_iter().take(MAX_ROOM_PARTICIPANTS).collect();
+                }
+                SyncMessage::RoomState {
+                    room_code,
+                    host_peer_id,
+                    participants: participants.into_iter().map(Participant::within_limits).collect(),
+                    current_track: current_track.map(TrackInfo::checked).transpose()?,
+                    playback,
+                    moderation,
+                    settings,
+                }
+            }
+            SyncMessage::JoinRequest { display_name, storefront, capabilities, profile, client, status } => {
+                SyncMessage::JoinRequest {
+                    display_name: display_name_within_limits(&display_name),
+                    storefront: storefront.filter(|s| s.len() <= MAX_ID_LEN),
+                    capabilities,
+                    profile,
+                    client,
+                    status,
+                }
+            }
+            SyncMessage::JoinResponse { accepted, room_code, reason } => {
+                if let Some(room_code) = &room_code {
+                    id(room_code, "room code")?;
+                }
+                SyncMessage::JoinResponse { accepted, room_code, reason: reason.map(|r| text_within_limits(&r)) }
+            }
+            SyncMessage::ParticipantJoined(p) => {
+                id(&p.peer_id, "peer ID")?;
+                SyncMessage::ParticipantJoined(p.within_limits())
+            }
+            SyncMessage::ParticipantUpdated { peer_id, display_name, profile } => {
+                id(&peer_id, "peer ID")?;
+                SyncMessage::ParticipantUpdated { peer_id, display_name: display_name_within_limits(&display_name), profile }
+            }
+            SyncMessage::TransferHost { new_host_peer_id, track, playback } => {
+                id(&new_host_peer_id, "peer ID")?;
+                SyncMessage::TransferHost { new_host_peer_id, track: track.map(TrackInfo::checked).transpose()?, playback }
+            }
+            SyncMessage::Play { track, position_ms, timestamp_ms } => {
+                SyncMessage::Play { track: track.checked()?, position_ms, timestamp_ms }
+            }
+            SyncMessage::TrackChange { track, position_ms, timestamp_ms } => {
+                SyncMessage::TrackChange { track: track.checked()?, position_ms, timestamp_ms }
+            }
+            message => {
+                match &message {
+                    SyncMessage::ParticipantLeft { peer_id }
+                    | SyncMessage::StatusUpdated { peer_id, .. }
+                    | SyncMessage::Removed { peer_id, .. } => id(peer_id, "peer ID")?,
+                    SyncMessage::RoomCodeChanged { room_code } => id(room_code, "room code")?,
+                    SyncMessage::TrackUnavailable { song_id } => id(song_id, "song ID")?,
+                    SyncMessage::Heartbeat { track_id: Some(track_id), .. } => id(track_id, "song ID")?,
+                    _ => {}
+                }
+                message
+            }
+        };
+        Ok(message)
+    }
+}
+
+impl TrackInfo {
+    /// The track cut to the limits above: text cut short, and artwork left
+    /// out unless it's from Apple Music (for ours, before they go out)
+    pub fn within_limits(mut self) -> Self {
+        for text in [&mut self.name, &mut self.artist, &mut self.album] {
+            *text = text_within_limits(text);
+        }
+        if self.artwork_url.len() > MAX_URL_LEN || !artwork_url_allowed(&self.artwork_url) {
+            self.artwork_url.clear();
+        }
+        self.isrc = self.isrc.filter(|isrc| isrc.len() <= MAX_ID_LEN);
+        self
+    }
+
+    /// The track within the limits, or why it can't be (its song ID)
+    fn checked(self) -> Result<Self, InvalidMessage> {
+        id(&self.song_id, "song ID")?;
+        Ok(self.within_limits())
+    }
+}
+
+impl Participant {
+    /// The participant within the limits (their peer ID is checked apart)
+    fn within_limits(mut self) -> Self {
+        self.display_name = display_name_within_limits(&self.display_name);
+        self.storefront = self.storefront.filter(|s| s.len() <= MAX_ID_LEN);
+        self.profile = self.profile.sanitized();
+        self.client = self.client.sanitized();
+        self
+    }
+}
+
+/// `name` cut to `MAX_DISPLAY_NAME_LEN` characters, without control characters
+pub fn display_name_within_limits(name: &str) -> String {
+    name.chars().filter(|c| !c.is_control()).take(MAX_DISPLAY_NAME_LEN).collect()
+}
+
+/// `text` cut to `MAX_TEXT_LEN` characters
+fn text_within_limits(text: &str) -> String {
+    text.chars().take(MAX_TEXT_LEN).collect()
+}
+
+/// Whether artwork at `url` may be loaded: https, from one of `ARTWORK_HOSTS`
+pub fn artwork_url_allowed(url: &str) -> bool {
+    let Some(rest) = url.strip_prefix("https://") else {
+        return false;
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    // No user info or port to hide another host behind
+    if host.contains(['@', ':']) {
+        return false;
+    }
+    let host = host.to_ascii_lowercase();
+    ARTWORK_HOSTS
+        .iter()
+        .any(|allowed| host == *allowed || host.strip_suffix(allowed).is_some_and(|sub| sub.ends_with('.')))
+}
+
+fn id(value: &str, field: &'static str) -> Result<(), InvalidMessage> {
+    if value.len() > MAX_ID_LEN {
+        return Err(InvalidMessage::TooLong(field));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{Capabilities, ClientInfo, ParticipantStatus, Profile};
+
+    fn track(artwork_url: &str) -> TrackInfo {
+        TrackInfo {
+            song_id: "1440818839".to_string(),
+            name: "Song".to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            artwork_url: artwork_url.to_string(),
+            duration_ms: 200_000,
+            isrc: None,
+            explicit: false,
+        }
+    }
+
+    fn participant(display_name: &str) -> Participant {
+        Participant {
+            peer_id: "peer".to_string(),
+            display_name: display_name.to_string(),
+            is_host: false,
+            storefront: None,
+            capabilities: Capabilities::default(),
+            profile: Profile::default(),
+            client: ClientInfo::default(),
+            status: ParticipantStatus::default(),
+        }
+    }
+
+    #[test]
+    fn test_message_limits() {
+        let update = |display_name: &str| SyncMessage::ParticipantUpdated {
+            peer_id: "peer".to_string(),
+            display_name: display_name.to_string(),
+            profile: Profile::default(),
+        };
+        let name_of = |message: Result<SyncMessage, InvalidMessage>| match message {
+            Ok(SyncMessage::ParticipantUpdated { display_name, .. }) => display_name,
+            other => panic!("unexpected {:?}", other),
+        };
+        // Counted in characters, not bytes
+        let name = "é".repeat(MAX_DISPLAY_NAME_LEN);
+        assert_eq!(name_of(update(&name).within_limits()), name);
+        assert_eq!(name_of(update(&"a".repeat(MAX_DISPLAY_NAME_LEN + 1)).within_limits()).len(), MAX_DISPLAY_NAME_LEN);
+        assert_eq!(name_of(update("Line\nbreak").within_limits()), "Linebreak");
+
+        let mut long_name = track("");
+        long_name.name = "a".repeat(MAX_TEXT_LEN + 1);
+        let change = SyncMessage::TrackChange { track: long_name, position_ms: 0, timestamp_ms: 0 };
+        match change.within_limits() {
+            Ok(SyncMessage::TrackChange { track, .. }) => assert_eq!(track.name.len(), MAX_TEXT_LEN),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // IDs are the only thing a message is rejected for
+        let mut long_id = track("");
+        long_id.song_id = "1".repeat(MAX_ID_LEN + 1);
+        let change = SyncMessage::TrackChange { track: long_id, position_ms: 0, timestamp_ms: 0 };
+        assert_eq!(change.within_limits().err(), Some(InvalidMessage::TooLong("song ID")));
+
+        let room_state = |participants: Vec<Participant>| SyncMessage::RoomState {
+            room_code: "ABCD4679".to_string(),
+            host_peer_id: "host".to_string(),
+            participants,
+            current_track: Some(track("https://is1-ssl.mzstatic.com/image/thumb/a/b/{w}x{h}bb.jpg")),
+            playback: crate::sync::PlaybackInfo { is_playing: true, position_ms: 0, timestamp_ms: 0 },
+            moderation: Default::default(),
+            settings: Default::default(),
+        };
+        let participants_of = |message: Result<SyncMessage, InvalidMessage>| match message {
+            Ok(SyncMessage::RoomState { participants, .. }) => participants,
+            other => panic!("unexpected {:?}", other),
+        };
+        let mut crowd = vec![participant("Listener"); MAX_ROOM_PARTICIPANTS + 1];
+        crowd.push(Participant { peer_id: "host".to_string(), is_host: true, ..participant("Host") });
+        crowd.push(participant(&"a".repeat(MAX_DISPLAY_NAME_LEN + 1)));
+        let participants = participants_of(room_state(crowd).within_limits());
+        assert_eq!(participants.len(), MAX_ROOM_PARTICIPANTS);
+        assert_eq!(participants[0].peer_id, "host");
+        let sneaky = vec![participant(&"a".repeat(MAX_DISPLAY_NAME_LEN + 1))];
+        assert_eq!(participants_of(room_state(sneaky).within_limits())[0].display_name.len(), MAX_DISPLAY_NAME_LEN);
+    }
+
+    #[test]
+    fn test_artwork_hosts() {
+        assert!(artwork_url_allowed("https://is1-ssl.mzstatic.com/image/thumb/a/b/600x600bb.jpg"));
+        assert!(artwork_url_allowed("https://IS5-SSL.MZSTATIC.COM/image.jpg"));
+        for url in [
+            "http://is1-ssl.mzstatic.com/image.jpg",
+            "https://evilmzstatic.com/image.jpg",
+            "https://mzstatic.com.evil.example/image.jpg",
+            "https://is1-ssl.mzstatic.com@evil.example/image.jpg",
+            "https://is1-ssl.mzstatic.com:8080/image.jpg",
+            "file:///etc/passwd",
+        ] {
+            assert!(!artwork_url_allowed(url), "{} allowed", url);
+        }
+        // Left out, the rest of the track still plays
+        let play = SyncMessage::Play { track: track("https://evil.example/a.jpg"), position_ms: 0, timestamp_ms: 0 };
+        match play.within_limits() {
+            Ok(SyncMessage::Play { track, .. }) => assert!(track.artwork_url.is_empty()),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ours_within_limits() {
+        let mut ours = track("http://localhost:10767/artwork.jpg");
+        ours.album = "é".repeat(MAX_TEXT_LEN + 10);
+        let ours = ours.within_limits();
+        assert!(ours.artwork_url.is_empty());
+        assert_eq!(ours.album.chars().count(), MAX_TEXT_LEN);
+        assert_eq!(ours.clone().within_limits().album, ours.album);
+
+        let name = display_name_within_limits(&format!("Tab\there{}", "a".repeat(100)));
+        assert_eq!(name.chars().count(), MAX_DISPLAY_NAME_LEN);
+        assert!(name.starts_with("Tabhere"));
+    }
+}
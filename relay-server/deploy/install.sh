@@ -66,6 +66,18 @@ echo "  Found binary at: $BINARY_PATH"
 cp "$BINARY_PATH" "$INSTALL_DIR/"
 
 chmod +x "$INSTALL_DIR/$BINARY_NAME"
+
+# Install default config (keep existing one on upgrade)
+if [ ! -f "$INSTALL_DIR/relay.toml" ]; then
+    if [ -f "./relay.example.toml" ]; then
+        cp ./relay.example.toml "$INSTALL_DIR/relay.toml"
+    elif [ -f "../relay.example.toml" ]; then
+        cp ../relay.example.toml "$INSTALL_DIR/relay.toml"
+    else
+        touch "$INSTALL_DIR/relay.toml"
+    fi
+    echo "  Installed default config at $INSTALL_DIR/relay.toml"
+fi
 chown -R "$SERVICE_USER:$SERVICE_USER" "$INSTALL_DIR"
 
 # Install systemd service
@@ -90,8 +102,8 @@ echo "    systemctl restart cider-relay   # Restart"
 echo "    systemctl stop cider-relay      # Stop"
 echo ""
 echo "  Configuration:"
-echo "    Edit /etc/systemd/system/cider-relay.service"
-echo "    Then: systemctl daemon-reload && systemctl restart cider-relay"
+echo "    Edit $INSTALL_DIR/relay.toml"
+echo "    Then: systemctl restart cider-relay"
 echo ""
 
 # Show initial status
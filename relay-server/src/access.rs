@@ -0,0 +1,201 @@
+//! Access control for private relays
+//!
+//! When enabled, only allow-listed peer IDs or clients that completed the
+//! token handshake may make reservations or open circuits. Clients don't
+//! send the shared token itself but `sha256(token || client || relay)` over
+//! the auth protocol; the proof is bound to both peer IDs (which noise
+//! authenticates), so it can't be replayed by other peers or against other
+//! relays. Must stay in sync with `cider-core/src/network/relay_access.rs`.
+//!
+//! Protocol (JSON request-response):
+//!   /cider-relay/auth/1.0.0 - client -> relay token handshake
+
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Token handshake protocol
+pub const AUTH_PROTOCOL: StreamProtocol = StreamProtocol::new("/cider-relay/auth/1.0.0");
+
+/// Domain separator so the proof can't be confused with other hashes
+const PROOF_CONTEXT: &[u8] = b"cider-relay-auth:";
+
+/// Client request: proof of the shared token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthRequest {
+    pub proof: String,
+}
+
+/// Relay response to a handshake
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthResponse {
+    pub accepted: bool,
+    /// Whether this relay takes tokens at all (clients only give up on a
+    /// relay that rejected their token if it does)
+    #[serde(default)]
+    pub token_required: bool,
+}
+
+/// Allow-list and/or shared token check
+///
+/// Clones share the set of authenticated peers, so the copy handed to the
+/// relay behaviour sees handshakes completed by the network loop.
+#[derive(Debug, Clone, Default)]
+pub struct AccessControl {
+    allowed_peers: Arc<HashSet<PeerId>>,
+    token: Option<String>,
+    authenticated: Arc<Mutex<HashSet<PeerId>>>,
+}
+
+impl AccessControl {
+    pub fn new(allowed_peers: HashSet<PeerId>, token: Option<String>) -> Self {
+        Self {
+            allowed_peers: Arc::new(allowed_peers),
+            token,
+            authenticated: Arc::default(),
+        }
+    }
+
+    /// Whether the relay is restricted at all
+    pub fn is_enabled(&self) -> bool {
+        !self.allowed_peers.is_empty() || self.token.is_some()
+    }
+
+    /// Whether peers can still prove access after connecting (via token)
+    pub fn accepts_tokens(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Check a peer ID against the allow-list
+    pub fn is_allowed_peer(&self, peer_id: &PeerId) -> bool {
+        !self.is_enabled() || self.allowed_peers.contains(peer_id)
+    }
+
+    /// Whether a peer may make reservations and open circuits
+    pub fn is_authorized(&self, peer_id: &PeerId) -> bool {
+        self.is_allowed_peer(peer_id) || self.authenticated.lock().contains(peer_id)
+    }
+
+    /// Verify a handshake proof sent to `relay`; remembers the peer on success
+    pub fn authenticate(&self, peer_id: PeerId, relay: &PeerId, proof: &str) -> bool {
+        let Some(token) = &self.token else {
+            return false;
+        };
+        if !proof.eq_ignore_ascii_case(&handshake_proof(token, &peer_id, relay)) {
+            return false;
+        }
+        self.authenticated.lock().insert(peer_id);
+        true
+    }
+
+    /// Answer a handshake sent to `relay`: peers that need no token (the
+    /// relay is open, or they're allow-listed) are accepted whatever they send
+    pub fn handshake(&self, peer_id: PeerId, relay: &PeerId, proof: &str) -> AuthResponse {
+        AuthResponse {
+            accepted: self.is_authorized(&peer_id) || self.authenticate(peer_id, relay, proof),
+            token_required: self.accepts_tokens(),
+        }
+    }
+
+    /// Forget a peer's handshake once it fully disconnects
+    pub fn forget(&self, peer_id: &PeerId) {
+        self.authenticated.lock().remove(peer_id);
+    }
+
+    /// Relay rate limiter that refuses reservations/circuits from unauthorized peers
+    pub fn relay_gate(&self) -> impl FnMut(PeerId, &Multiaddr, Instant) -> bool + Send + 'static {
+        let access = self.clone();
+        move |peer_id: PeerId, _addr: &Multiaddr, _now: Instant| access.is_authorized(&peer_id)
+    }
+}
+
+/// Compute the handshake proof for a token, client and relay (hex-encoded)
+fn handshake_proof(token: &str, client: &PeerId, relay: &PeerId) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(PROOF_CONTEXT);
+    hasher.update(token.as_bytes());
+    hasher.update(client.to_bytes());
+    hasher.update(relay.to_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_allows_everyone() {
+        let access = AccessControl::default();
+        assert!(!access.is_enabled());
+        assert!(access.is_authorized(&PeerId::random()));
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let allowed = PeerId::random();
+        let access = AccessControl::new(HashSet::from([allowed]), None);
+
+        assert!(access.is_allowed_peer(&allowed));
+        assert!(!access.is_allowed_peer(&PeerId::random()));
+        assert!(!access.accepts_tokens());
+    }
+
+    #[test]
+    fn test_token_handshake() {
+        let access = AccessControl::new(HashSet::new(), Some("secret".to_string()));
+        let relay = PeerId::random();
+        let peer = PeerId::random();
+        let proof = handshake_proof("secret", &peer, &relay);
+
+        // Proof is bound to the peer that computed it and to this relay
+        assert!(!access.authenticate(PeerId::random(), &relay, &proof));
+        assert!(!access.authenticate(peer, &PeerId::random(), &proof));
+        // Wrong token
+        assert!(!access.authenticate(peer, &relay, &handshake_proof("guess", &peer, &relay)));
+        assert!(!access.is_authorized(&peer));
+
+        assert!(access.authenticate(peer, &relay, &proof));
+        assert!(access.is_authorized(&peer));
+        access.forget(&peer);
+        assert!(!access.is_authorized(&peer));
+    }
+
+    #[test]
+    fn test_handshake_without_token() {
+        let relay = PeerId::random();
+        let peer = PeerId::random();
+
+        // Clients with a token for another relay still get in on open ones
+        let response = AccessControl::default().handshake(peer, &relay, "proof for another relay");
+        assert!(response.accepted);
+        assert!(!response.token_required);
+
+        let allowed = PeerId::random();
+        let access = AccessControl::new(HashSet::from([allowed]), Some("secret".to_string()));
+        assert!(access.handshake(allowed, &relay, "").accepted);
+        let response = access.handshake(peer, &relay, "");
+        assert!(!response.accepted);
+        assert!(response.token_required);
+    }
+
+    #[test]
+    fn test_relay_gate_shares_state() {
+        let access = AccessControl::new(HashSet::new(), Some("secret".to_string()));
+        let mut gate = access.relay_gate();
+        let relay = PeerId::random();
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/203.0.113.1/tcp/4001".parse().unwrap();
+
+        assert!(!gate(peer, &addr, Instant::now()));
+        access.authenticate(peer, &relay, &handshake_proof("secret", &peer, &relay));
+        assert!(gate(peer, &addr, Instant::now()));
+    }
+}
@@ -0,0 +1,187 @@
+//! Persistent ban list
+//!
+//! A plain text file with one peer ID or IP address per line; `#` starts a
+//! comment. Banned peers are disconnected as soon as their connection is
+//! established. The file is re-read when it changes on disk, and bans made
+//! from the dashboard are appended to it, so operator comments survive.
+
+use chrono::Local;
+use libp2p::PeerId;
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Banned peer IDs and IPs, backed by a file
+#[derive(Debug)]
+pub struct BanList {
+    path: PathBuf,
+    peers: HashSet<PeerId>,
+    ips: HashSet<IpAddr>,
+    /// Modification time of the file when last read
+    modified: Option<SystemTime>,
+}
+
+impl BanList {
+    /// Load the ban list (a missing file is an empty list)
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let mut bans = Self {
+            path,
+            peers: HashSet::new(),
+            ips: HashSet::new(),
+            modified: None,
+        };
+        bans.reload()?;
+        Ok(bans)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of banned peers and IPs
+    pub fn len(&self) -> usize {
+        self.peers.len() + self.ips.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty() && self.ips.is_empty()
+    }
+
+    /// Check a peer and (for inbound connections) its IP
+    pub fn is_banned(&self, peer_id: &PeerId, ip: Option<IpAddr>) -> bool {
+        self.peers.contains(peer_id) || ip.is_some_and(|ip| self.ips.contains(&ip))
+    }
+
+    /// Ban a peer (and the IP it connected from) and append it to the file
+    ///
+    /// Loopback IPs are never banned so local clients keep working.
+    pub fn ban(&mut self, peer_id: PeerId, ip: Option<IpAddr>) -> io::Result<()> {
+        let ip = ip.filter(|ip| !ip.is_loopback());
+        let date = Local::now().format("%Y-%m-%d %H:%M");
+        let mut lines = String::new();
+        if self.peers.insert(peer_id) {
+            lines.push_str(&format!("{}  # banned {}\n", peer_id, date));
+        }
+        if let Some(ip) = ip.filter(|ip| self.ips.insert(*ip)) {
+            lines.push_str(&format!("{}  # banned {} ({})\n", ip, date, peer_id));
+        }
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(lines.as_bytes())?;
+        self.modified = modified(&self.path);
+        Ok(())
+    }
+
+    /// Re-read the file if it changed on disk; returns whether it was reloaded
+    ///
+    /// On a parse error the current list is kept (and the error reported once).
+    pub fn reload_if_changed(&mut self) -> io::Result<bool> {
+        let current = modified(&self.path);
+        if current == self.modified {
+            return Ok(false);
+        }
+        self.modified = current;
+        self.reload()?;
+        Ok(true)
+    }
+
+    fn reload(&mut self) -> io::Result<()> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let (peers, ips) = parse(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.peers = peers;
+        self.ips = ips;
+        self.modified = modified(&self.path);
+        Ok(())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Parse ban list entries
+fn parse(contents: &str) -> Result<(HashSet<PeerId>, HashSet<IpAddr>), String> {
+    let mut peers = HashSet::new();
+    let mut ips = HashSet::new();
+    for (number, line) in contents.lines().enumerate() {
+        let entry = line.split('#').next().unwrap_or_default().trim();
+        if entry.is_empty() {
+            continue;
+        }
+        if let Ok(ip) = entry.parse::<IpAddr>() {
+            ips.insert(ip);
+        } else if let Ok(peer_id) = entry.parse::<PeerId>() {
+            peers.insert(peer_id);
+        } else {
+            return Err(format!("line {}: '{}' is neither a peer ID nor an IP address", number + 1, entry));
+        }
+    }
+    Ok((peers, ips))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let peer = PeerId::random();
+        let contents = format!("# Repeat abusers\n{}  # spam\n\n203.0.113.7\n2001:db8::1 # scanner\n", peer);
+        let (peers, ips) = parse(&contents).unwrap();
+        assert!(peers.contains(&peer));
+        assert_eq!(ips, HashSet::from([ip("203.0.113.7"), ip("2001:db8::1")]));
+
+        assert!(parse("not-a-peer\n").unwrap_err().starts_with("line 1"));
+    }
+
+    #[test]
+    fn test_ban_appends_and_reloads() {
+        let path = std::env::temp_dir().join(format!("cider-relay-bans-{}.txt", std::process::id()));
+        fs::write(&path, "# Keep this comment\n").unwrap();
+
+        let mut bans = BanList::load(path.clone()).unwrap();
+        let peer = PeerId::random();
+        assert!(!bans.is_banned(&peer, Some(ip("198.51.100.4"))));
+
+        bans.ban(peer, Some(ip("198.51.100.4"))).unwrap();
+        assert!(bans.is_banned(&peer, None));
+        assert!(bans.is_banned(&PeerId::random(), Some(ip("198.51.100.4"))));
+
+        // Loopback is never banned
+        bans.ban(PeerId::random(), Some(ip("127.0.0.1"))).unwrap();
+        assert!(!bans.is_banned(&PeerId::random(), Some(ip("127.0.0.1"))));
+
+        // A restarted relay reads the appended entries, keeping the comment
+        let restarted = BanList::load(path.clone()).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(restarted.len(), 3);
+        assert!(restarted.is_banned(&peer, None));
+        assert!(contents.starts_with("# Keep this comment\n"));
+    }
+
+    #[test]
+    fn test_missing_file_is_empty() {
+        let path = std::env::temp_dir().join(format!("cider-relay-no-bans-{}.txt", std::process::id()));
+        let mut bans = BanList::load(path).unwrap();
+        assert_eq!(bans.len(), 0);
+        assert!(!bans.reload_if_changed().unwrap());
+    }
+}
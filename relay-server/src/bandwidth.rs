@@ -0,0 +1,193 @@
+//! Per-peer traffic accounting
+//!
+//! The relay behaviour doesn't report how much data flows through it, so
+//! every connection's stream muxer is wrapped with a counter keyed by the
+//! remote peer. Since relayed traffic passes through the relay's own
+//! connections to both ends of a circuit, these counters reflect what each
+//! peer costs the relay in bandwidth.
+//!
+//! Relayed bytes are counted separately on circuit "stop" streams: every
+//! circuit has exactly one, opened by the relay towards the destination
+//! peer, and after the handshake it carries the circuit's data in both
+//! directions. They're recognised by the protocol name the relay writes
+//! during negotiation.
+
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::core::muxing::{StreamMuxer, StreamMuxerBox, StreamMuxerEvent, SubstreamBox};
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Protocol of the relay -> destination stream of a circuit
+const CIRCUIT_STOP_PROTOCOL: &[u8] = b"/libp2p/circuit/relay/0.2.0/stop";
+
+/// Byte counters for one peer (shared by all of its connections)
+#[derive(Debug, Default)]
+struct PeerCounters {
+    /// Bytes read + written on all connections
+    total: AtomicU64,
+    /// Circuit data relayed to/from this peer as circuit destination
+    relayed: AtomicU64,
+}
+
+/// Traffic for one peer since the last drain
+#[derive(Debug, Clone, Copy)]
+pub struct PeerTraffic {
+    pub peer_id: PeerId,
+    /// All bytes on the peer's connections
+    pub bytes: u64,
+    /// Circuit data where this peer is the destination
+    pub relayed_bytes: u64,
+}
+
+/// Shared per-peer byte counters
+#[derive(Clone, Default)]
+pub struct PeerBandwidth {
+    peers: Arc<Mutex<HashMap<PeerId, Arc<PeerCounters>>>>,
+}
+
+impl PeerBandwidth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap a connection's muxer so its traffic is counted for `peer_id`
+    pub fn wrap(&self, peer_id: PeerId, muxer: StreamMuxerBox) -> CountingMuxer {
+        let counters = Arc::clone(self.peers.lock().entry(peer_id).or_default());
+        CountingMuxer { inner: muxer, counters }
+    }
+
+    /// Take the traffic counted since the last call, per peer
+    ///
+    /// Peers without open connections are forgotten once drained.
+    pub fn drain(&self) -> Vec<PeerTraffic> {
+        let mut peers = self.peers.lock();
+        let drained = peers
+            .iter()
+            .map(|(peer_id, counters)| PeerTraffic {
+                peer_id: *peer_id,
+                bytes: counters.total.swap(0, Ordering::Relaxed),
+                relayed_bytes: counters.relayed.swap(0, Ordering::Relaxed),
+            })
+            .filter(|traffic| traffic.bytes > 0)
+            .collect();
+        // Only the map holds the counter -> all connections are gone
+        peers.retain(|_, counters| Arc::strong_count(counters) > 1);
+        drained
+    }
+}
+
+/// Stream muxer that counts bytes on all of its substreams
+pub struct CountingMuxer {
+    inner: StreamMuxerBox,
+    counters: Arc<PeerCounters>,
+}
+
+impl CountingMuxer {
+    fn counted(&self, inner: SubstreamBox) -> CountingStream {
+        CountingStream {
+            inner,
+            counters: Arc::clone(&self.counters),
+            is_circuit: false,
+        }
+    }
+}
+
+impl StreamMuxer for CountingMuxer {
+    type Substream = CountingStream;
+    type Error = io::Error;
+
+    fn poll_inbound(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Self::Substream, Self::Error>> {
+        let stream = futures::ready!(Pin::new(&mut self.inner).poll_inbound(cx))?;
+        Poll::Ready(Ok(self.counted(stream)))
+    }
+
+    fn poll_outbound(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Self::Substream, Self::Error>> {
+        let stream = futures::ready!(Pin::new(&mut self.inner).poll_outbound(cx))?;
+        Poll::Ready(Ok(self.counted(stream)))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        Pin::new(&mut self.inner).poll(cx)
+    }
+}
+
+/// Substream that adds every byte read or written to its peer's counters
+pub struct CountingStream {
+    inner: SubstreamBox,
+    counters: Arc<PeerCounters>,
+    /// Negotiated as a circuit stop stream (carries relayed data)
+    is_circuit: bool,
+}
+
+impl CountingStream {
+    fn count(&self, result: &io::Result<usize>) {
+        if let Ok(n) = result {
+            self.counters.total.fetch_add(*n as u64, Ordering::Relaxed);
+            if self.is_circuit {
+                self.counters.relayed.fetch_add(*n as u64, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let result = futures::ready!(Pin::new(&mut self.inner).poll_read(cx, buf));
+        self.count(&result);
+        Poll::Ready(result)
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let result = futures::ready!(Pin::new(&mut self.inner).poll_write(cx, buf));
+        // Handshake bytes are counted before the stream is marked as a circuit
+        self.count(&result);
+        if !self.is_circuit && is_circuit_negotiation(buf) {
+            self.is_circuit = true;
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Check whether written bytes propose the circuit stop protocol
+fn is_circuit_negotiation(buf: &[u8]) -> bool {
+    buf.windows(CIRCUIT_STOP_PROTOCOL.len())
+        .any(|window| window == CIRCUIT_STOP_PROTOCOL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_circuit_negotiation() {
+        // multistream-select: length-prefixed, newline-terminated protocol names
+        let mut buf = vec![19u8];
+        buf.extend_from_slice(b"/multistream/1.0.0\n");
+        buf.push(33);
+        buf.extend_from_slice(b"/libp2p/circuit/relay/0.2.0/stop\n");
+        assert!(is_circuit_negotiation(&buf));
+
+        assert!(!is_circuit_negotiation(b"\x20/libp2p/circuit/relay/0.2.0/hop\n"));
+        assert!(!is_circuit_negotiation(b"/ipfs/id/1.0.0\n"));
+    }
+}
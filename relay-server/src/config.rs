@@ -0,0 +1,947 @@
+//! Relay server configuration
+//!
+//! Settings are loaded from a TOML file and can be overridden from the
+//! command line. Lookup order for the file:
+//!   1. `--config <path>`
+//!   2. `CIDER_RELAY_CONFIG` environment variable
+//!   3. `relay.toml` next to the executable (if present)
+//!
+//! Missing files/sections fall back to defaults, so a bare binary still
+//! runs with the same behaviour as before.
+
+use crate::access::AccessControl;
+use crate::federation::federation_peer_id;
+use crate::version::Version;
+use libp2p::{Multiaddr, PeerId};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Default config file name (looked up next to the executable)
+const CONFIG_FILE: &str = "relay.toml";
+
+/// Default keypair file name
+const KEYPAIR_FILE: &str = "keypair.bin";
+
+/// Default TCP/QUIC port
+const DEFAULT_PORT: u16 = 4001;
+
+/// Default time a peer has to identify as a Cider client
+const DEFAULT_IDENTIFY_TIMEOUT_SECS: u64 = 30;
+
+/// Default per-IP connection limits (generous enough for shared NATs)
+const DEFAULT_MAX_PEERS_PER_IP: usize = 16;
+const DEFAULT_CONNECTIONS_PER_IP_PER_MINUTE: u32 = 60;
+
+/// Default relay protocol limits (same as libp2p's)
+const DEFAULT_MAX_RESERVATIONS: usize = 128;
+const DEFAULT_MAX_RESERVATIONS_PER_PEER: usize = 4;
+const DEFAULT_RESERVATION_DURATION_SECS: u64 = 60 * 60;
+const DEFAULT_MAX_CIRCUITS: usize = 16;
+const DEFAULT_MAX_CIRCUITS_PER_PEER: usize = 4;
+const DEFAULT_MAX_CIRCUIT_DURATION_SECS: u64 = 2 * 60;
+const DEFAULT_MAX_CIRCUIT_KB: u64 = 128;
+
+/// Default interval between federation state exchanges
+const DEFAULT_FEDERATION_SYNC_SECS: u64 = 60;
+
+/// Default time active circuits get to finish on shutdown
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+/// Default metrics file name (next to the executable)
+const METRICS_FILE: &str = "metrics.json";
+
+/// Default ban list file name (next to the executable)
+const BAN_FILE: &str = "bans.txt";
+
+/// Default number of rotated log files to keep
+const DEFAULT_MAX_LOG_FILES: usize = 7;
+
+/// Quotas are configured in MB, circuit data limits in KB
+const BYTES_PER_MB: u64 = 1024 * 1024;
+const BYTES_PER_KB: u64 = 1024;
+
+/// Usage text for `--help`
+pub const USAGE: &str = "\
+Usage: cider-relay [OPTIONS]
+
+Options:
+  --config <PATH>            Config file (default: relay.toml next to the binary)
+  --tcp-port <PORT>          TCP listen port
+  --quic-port <PORT>         QUIC (UDP) listen port
+  --keypair <PATH>           Keypair file (created if missing)
+  --external-address <ADDR>  Public IP or domain to advertise (skips detection)
+  --web-dashboard <ADDR>     Serve the web dashboard on ADDR (e.g. 127.0.0.1:8080)
+  --signaling <ADDR>         Serve the signaling endpoint on ADDR (e.g. 0.0.0.0:8090)
+  --log-file <PATH>          Also write logs to PATH (rotated daily by default)
+  --no-dashboard             Plain logging instead of the terminal dashboard
+  --help                     Show this help
+";
+
+/// Errors while loading or validating configuration
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path}: {source}")]
+    Read { path: PathBuf, source: std::io::Error },
+
+    #[error("Invalid config file {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+
+    #[error("Invalid command line: {0}")]
+    Cli(String),
+
+    #[error("Invalid value for {field}: {message}")]
+    Invalid { field: &'static str, message: String },
+}
+
+/// Top-level relay configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: NetworkConfig,
+    pub limits: LimitsConfig,
+    pub dashboard: DashboardConfig,
+    pub access: AccessConfig,
+    pub rendezvous: RendezvousConfig,
+    pub signaling: SignalingConfig,
+    pub federation: FederationConfig,
+    pub logging: LoggingConfig,
+    pub shutdown: ShutdownConfig,
+}
+
+/// Listening and addressing options
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// TCP listen port
+    pub tcp_port: u16,
+    /// QUIC (UDP) listen port
+    pub quic_port: u16,
+    /// Also listen on IPv6
+    pub listen_ipv6: bool,
+    /// Public IP or domain to advertise (skips public IP detection)
+    pub external_address: Option<String>,
+    /// Keypair file (default: keypair.bin next to the executable)
+    pub keypair_path: Option<PathBuf>,
+    /// Region tag advertised to clients via identify (e.g. "eu-west")
+    pub region: Option<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            tcp_port: DEFAULT_PORT,
+            quic_port: DEFAULT_PORT,
+            listen_ipv6: true,
+            external_address: None,
+            keypair_path: None,
+            region: None,
+        }
+    }
+}
+
+/// Abuse protection limits
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Seconds a peer has to identify as a Cider client before being disconnected
+    pub identify_timeout_secs: u64,
+    /// Traffic a single peer may use per hour, in MB (unlimited if unset)
+    pub hourly_quota_mb: Option<u64>,
+    /// Traffic a single peer may use per day, in MB (unlimited if unset)
+    pub daily_quota_mb: Option<u64>,
+    /// Maximum distinct peers connected from one IP
+    pub max_peers_per_ip: usize,
+    /// Maximum new connections from one IP per minute
+    pub connections_per_ip_per_minute: u32,
+    /// Relay reservation requests allowed per IP
+    pub reservation_rate_per_ip: RateLimitConfig,
+    /// Relay circuit requests allowed per source IP
+    pub circuit_rate_per_ip: RateLimitConfig,
+    /// Maximum reservations held on the relay
+    pub max_reservations: usize,
+    /// Maximum reservations held by one peer
+    pub max_reservations_per_peer: usize,
+    /// Seconds a reservation lasts before the client must renew it
+    pub reservation_duration_secs: u64,
+    /// Maximum circuits open at once
+    pub max_circuits: usize,
+    /// Maximum circuits per source or destination peer
+    pub max_circuits_per_peer: usize,
+    /// Seconds after which a circuit is closed
+    pub max_circuit_duration_secs: u64,
+    /// Data after which a circuit is closed, in KB (0 = unlimited)
+    pub max_circuit_kb: u64,
+}
+
+/// Token bucket rate limit: `burst` requests at once, one more every `interval_secs`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub burst: u32,
+    pub interval_secs: u64,
+}
+
+impl RateLimitConfig {
+    fn validate(&self, field: &'static str) -> Result<(), ConfigError> {
+        if self.burst == 0 || self.interval_secs == 0 {
+            return Err(ConfigError::Invalid {
+                field,
+                message: "burst and interval_secs must be greater than 0".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl LimitsConfig {
+    /// Hourly per-peer quota in bytes
+    pub fn hourly_quota_bytes(&self) -> Option<u64> {
+        self.hourly_quota_mb.map(|mb| mb.saturating_mul(BYTES_PER_MB))
+    }
+
+    /// Daily per-peer quota in bytes
+    pub fn daily_quota_bytes(&self) -> Option<u64> {
+        self.daily_quota_mb.map(|mb| mb.saturating_mul(BYTES_PER_MB))
+    }
+
+    /// Per-circuit data limit in bytes (0 = unlimited)
+    pub fn max_circuit_bytes(&self) -> u64 {
+        self.max_circuit_kb.saturating_mul(BYTES_PER_KB)
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            identify_timeout_secs: DEFAULT_IDENTIFY_TIMEOUT_SECS,
+            hourly_quota_mb: None,
+            daily_quota_mb: None,
+            max_peers_per_ip: DEFAULT_MAX_PEERS_PER_IP,
+            connections_per_ip_per_minute: DEFAULT_CONNECTIONS_PER_IP_PER_MINUTE,
+            reservation_rate_per_ip: RateLimitConfig {
+                burst: 20,
+                interval_secs: 60,
+            },
+            circuit_rate_per_ip: RateLimitConfig {
+                burst: 60,
+                interval_secs: 30,
+            },
+            max_reservations: DEFAULT_MAX_RESERVATIONS,
+            max_reservations_per_peer: DEFAULT_MAX_RESERVATIONS_PER_PEER,
+            reservation_duration_secs: DEFAULT_RESERVATION_DURATION_SECS,
+            max_circuits: DEFAULT_MAX_CIRCUITS,
+            max_circuits_per_peer: DEFAULT_MAX_CIRCUITS_PER_PEER,
+            max_circuit_duration_secs: DEFAULT_MAX_CIRCUIT_DURATION_SECS,
+            max_circuit_kb: DEFAULT_MAX_CIRCUIT_KB,
+        }
+    }
+}
+
+/// Private relay options (relay is public if allow-list and token are unset), bans
+/// and the minimum client version
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessConfig {
+    /// Peer IDs that may always use the relay
+    pub allowed_peers: Vec<String>,
+    /// Shared token; clients configured with it may use the relay
+    pub token: Option<String>,
+    /// Ban list file (default: bans.txt next to the executable)
+    pub ban_file: Option<PathBuf>,
+    /// Oldest Cider Together version allowed to connect (unset = any)
+    pub min_client_version: Option<String>,
+}
+
+/// Rendezvous server options
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RendezvousConfig {
+    /// Serve the rendezvous protocol so clients can register/discover rooms via the relay
+    pub enabled: bool,
+}
+
+impl Default for RendezvousConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Signaling endpoint options
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignalingConfig {
+    /// Address to serve the HTTP signaling endpoint on, for clients that
+    /// don't use ntfy.sh to find rooms (disabled if unset)
+    pub address: Option<SocketAddr>,
+}
+
+/// Log file options
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Log file path; rotated files get the date inserted before the extension
+    pub file: Option<PathBuf>,
+    /// How often to start a new log file
+    pub rotation: LogRotation,
+    /// Size at which to start a new log file, in MB (no limit if unset)
+    pub max_file_mb: Option<u64>,
+    /// Rotated log files to keep (oldest are deleted)
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            rotation: LogRotation::Daily,
+            max_file_mb: None,
+            max_files: DEFAULT_MAX_LOG_FILES,
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Size at which to start a new log file, in bytes
+    pub fn max_file_bytes(&self) -> Option<u64> {
+        self.max_file_mb.map(|mb| mb.saturating_mul(BYTES_PER_MB))
+    }
+}
+
+/// Log file rotation interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+/// Graceful shutdown options
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// Seconds to let active circuits finish after SIGTERM/SIGINT
+    pub drain_timeout_secs: u64,
+    /// Lifetime counters file (default: metrics.json next to the executable)
+    pub metrics_file: Option<PathBuf>,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout_secs: DEFAULT_DRAIN_TIMEOUT_SECS,
+            metrics_file: None,
+        }
+    }
+}
+
+/// Relay federation options
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FederationConfig {
+    /// Other relays to peer with (full multiaddrs ending in /p2p/PEER_ID)
+    pub peers: Vec<String>,
+    /// Seconds between state exchanges with federation peers
+    pub sync_interval_secs: u64,
+}
+
+impl Default for FederationConfig {
+    fn default() -> Self {
+        Self {
+            peers: Vec::new(),
+            sync_interval_secs: DEFAULT_FEDERATION_SYNC_SECS,
+        }
+    }
+}
+
+/// Dashboard options
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DashboardConfig {
+    /// Show the terminal dashboard (false = plain logging)
+    pub tui: bool,
+    /// Address to serve the read-only web dashboard on (disabled if unset)
+    pub web_address: Option<SocketAddr>,
+}
+
+impl Default for DashboardConfig {
+    fn default() -> Self {
+        Self {
+            tui: true,
+            web_address: None,
+        }
+    }
+}
+
+/// A publicly advertised address
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternalAddress {
+    Ip(IpAddr),
+    Domain(String),
+}
+
+/// Outcome of parsing the command line
+pub enum Startup {
+    /// Run the relay with this config
+    Run(Box<Config>),
+    /// `--help` was requested
+    Help,
+}
+
+impl Config {
+    /// Load config from file (if any) and apply command line overrides
+    pub fn load(args: &[String]) -> Result<Startup, ConfigError> {
+        let overrides = CliOverrides::parse(args)?;
+        if overrides.help {
+            return Ok(Startup::Help);
+        }
+
+        let path = overrides
+            .config_path
+            .clone()
+            .or_else(|| std::env::var("CIDER_RELAY_CONFIG").ok().map(PathBuf::from));
+
+        let mut config = match path {
+            // Explicitly requested file must exist
+            Some(path) => Self::from_file(&path)?,
+            None => {
+                let default_path = exe_dir().join(CONFIG_FILE);
+                if default_path.exists() {
+                    Self::from_file(&default_path)?
+                } else {
+                    Self::default()
+                }
+            }
+        };
+
+        overrides.apply(&mut config);
+        config.validate()?;
+        Ok(Startup::Run(Box::new(config)))
+    }
+
+    /// Parse a config file
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_toml(&contents).map_err(|message| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    /// Parse config from a TOML string
+    pub fn from_toml(contents: &str) -> Result<Self, String> {
+        toml::from_str(contents).map_err(|e| e.to_string())
+    }
+
+    /// Check values that TOML types alone can't express
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.network.tcp_port == 0 {
+            return Err(ConfigError::Invalid {
+                field: "network.tcp_port",
+                message: "must be a fixed port (not 0) so clients can reach it".to_string(),
+            });
+        }
+        if self.network.quic_port == 0 {
+            return Err(ConfigError::Invalid {
+                field: "network.quic_port",
+                message: "must be a fixed port (not 0) so clients can reach it".to_string(),
+            });
+        }
+        if let Some(region) = &self.network.region {
+            if region.is_empty() || !region.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(ConfigError::Invalid {
+                    field: "network.region",
+                    message: format!("'{}' must only contain letters, digits, '-' or '_'", region),
+                });
+            }
+        }
+        if self.limits.identify_timeout_secs == 0 {
+            return Err(ConfigError::Invalid {
+                field: "limits.identify_timeout_secs",
+                message: "must be greater than 0".to_string(),
+            });
+        }
+        if self.limits.hourly_quota_mb == Some(0) {
+            return Err(ConfigError::Invalid {
+                field: "limits.hourly_quota_mb",
+                message: "must be greater than 0 (remove it to disable the quota)".to_string(),
+            });
+        }
+        if self.limits.daily_quota_mb == Some(0) {
+            return Err(ConfigError::Invalid {
+                field: "limits.daily_quota_mb",
+                message: "must be greater than 0 (remove it to disable the quota)".to_string(),
+            });
+        }
+        if self.limits.max_peers_per_ip == 0 {
+            return Err(ConfigError::Invalid {
+                field: "limits.max_peers_per_ip",
+                message: "must be greater than 0".to_string(),
+            });
+        }
+        if self.limits.connections_per_ip_per_minute == 0 {
+            return Err(ConfigError::Invalid {
+                field: "limits.connections_per_ip_per_minute",
+                message: "must be greater than 0".to_string(),
+            });
+        }
+        self.limits.reservation_rate_per_ip.validate("limits.reservation_rate_per_ip")?;
+        self.limits.circuit_rate_per_ip.validate("limits.circuit_rate_per_ip")?;
+        for (field, value) in [
+            ("limits.max_reservations", self.limits.max_reservations),
+            ("limits.max_reservations_per_peer", self.limits.max_reservations_per_peer),
+            ("limits.max_circuits", self.limits.max_circuits),
+            ("limits.max_circuits_per_peer", self.limits.max_circuits_per_peer),
+        ] {
+            if value == 0 {
+                return Err(ConfigError::Invalid {
+                    field,
+                    message: "must be greater than 0".to_string(),
+                });
+            }
+        }
+        // Durations are sent to clients as 32-bit seconds
+        for (field, secs) in [
+            ("limits.reservation_duration_secs", self.limits.reservation_duration_secs),
+            ("limits.max_circuit_duration_secs", self.limits.max_circuit_duration_secs),
+        ] {
+            if secs == 0 || secs > u64::from(u32::MAX) {
+                return Err(ConfigError::Invalid {
+                    field,
+                    message: format!("must be between 1 and {}", u32::MAX),
+                });
+            }
+        }
+        self.external_address()?;
+        self.access_control()?;
+        self.min_client_version()?;
+        self.federation_peers()?;
+        if let Some(file) = &self.logging.file {
+            if file.file_name().is_none() {
+                return Err(ConfigError::Invalid {
+                    field: "logging.file",
+                    message: format!("'{}' is not a file path", file.display()),
+                });
+            }
+        }
+        if self.logging.max_file_mb == Some(0) {
+            return Err(ConfigError::Invalid {
+                field: "logging.max_file_mb",
+                message: "must be greater than 0 (remove it to rotate by time only)".to_string(),
+            });
+        }
+        if self.logging.max_files == 0 {
+            return Err(ConfigError::Invalid {
+                field: "logging.max_files",
+                message: "must be greater than 0".to_string(),
+            });
+        }
+        if self.federation.sync_interval_secs == 0 {
+            return Err(ConfigError::Invalid {
+                field: "federation.sync_interval_secs",
+                message: "must be greater than 0".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Parse the configured external address, if any
+    pub fn external_address(&self) -> Result<Option<ExternalAddress>, ConfigError> {
+        let Some(addr) = self.network.external_address.as_deref() else {
+            return Ok(None);
+        };
+        let addr = addr.trim();
+
+        if let Ok(ip) = addr.parse::<IpAddr>() {
+            return Ok(Some(ExternalAddress::Ip(ip)));
+        }
+
+        let is_domain = !addr.is_empty()
+            && addr.contains('.')
+            && addr
+                .split('.')
+                .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+        if is_domain {
+            Ok(Some(ExternalAddress::Domain(addr.to_lowercase())))
+        } else {
+            Err(ConfigError::Invalid {
+                field: "network.external_address",
+                message: format!("'{}' is neither an IP address nor a domain name", addr),
+            })
+        }
+    }
+
+    /// Build the access control from the configured allow-list and token
+    pub fn access_control(&self) -> Result<AccessControl, ConfigError> {
+        let allowed_peers = self
+            .access
+            .allowed_peers
+            .iter()
+            .map(|id| {
+                id.trim().parse::<PeerId>().map_err(|e| ConfigError::Invalid {
+                    field: "access.allowed_peers",
+                    message: format!("'{}' is not a valid peer ID: {}", id, e),
+                })
+            })
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        let token = match self.access.token.as_deref().map(str::trim) {
+            Some("") => {
+                return Err(ConfigError::Invalid {
+                    field: "access.token",
+                    message: "must not be empty (remove it to disable token access)".to_string(),
+                })
+            }
+            token => token.map(String::from),
+        };
+
+        Ok(AccessControl::new(allowed_peers, token))
+    }
+
+    /// Parse the configured minimum client version, if any
+    pub fn min_client_version(&self) -> Result<Option<Version>, ConfigError> {
+        self.access
+            .min_client_version
+            .as_deref()
+            .map(|version| {
+                version.parse().map_err(|message| ConfigError::Invalid {
+                    field: "access.min_client_version",
+                    message,
+                })
+            })
+            .transpose()
+    }
+
+    /// Parse the configured federation peers
+    pub fn federation_peers(&self) -> Result<Vec<(PeerId, Multiaddr)>, ConfigError> {
+        self.federation
+            .peers
+            .iter()
+            .map(|addr| {
+                let invalid = |message: String| ConfigError::Invalid {
+                    field: "federation.peers",
+                    message,
+                };
+                let multiaddr: Multiaddr = addr
+                    .trim()
+                    .parse()
+                    .map_err(|e| invalid(format!("'{}' is not a valid multiaddr: {}", addr, e)))?;
+                let peer_id = federation_peer_id(&multiaddr)
+                    .ok_or_else(|| invalid(format!("'{}' must end with /p2p/<relay peer ID>", addr)))?;
+                Ok((peer_id, multiaddr))
+            })
+            .collect()
+    }
+
+    /// Effective keypair path
+    pub fn keypair_path(&self) -> PathBuf {
+        self.network
+            .keypair_path
+            .clone()
+            .unwrap_or_else(|| exe_dir().join(KEYPAIR_FILE))
+    }
+
+    /// Effective metrics file path
+    pub fn metrics_path(&self) -> PathBuf {
+        self.shutdown
+            .metrics_file
+            .clone()
+            .unwrap_or_else(|| exe_dir().join(METRICS_FILE))
+    }
+
+    /// Effective ban list path
+    pub fn ban_path(&self) -> PathBuf {
+        self.access
+            .ban_file
+            .clone()
+            .unwrap_or_else(|| exe_dir().join(BAN_FILE))
+    }
+}
+
+/// Directory containing the executable (or current dir)
+fn exe_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Values given on the command line (take precedence over the file)
+#[derive(Debug, Default)]
+struct CliOverrides {
+    help: bool,
+    config_path: Option<PathBuf>,
+    tcp_port: Option<u16>,
+    quic_port: Option<u16>,
+    keypair_path: Option<PathBuf>,
+    external_address: Option<String>,
+    web_address: Option<SocketAddr>,
+    signaling_address: Option<SocketAddr>,
+    log_file: Option<PathBuf>,
+    no_dashboard: bool,
+}
+
+impl CliOverrides {
+    fn parse(args: &[String]) -> Result<Self, ConfigError> {
+        let mut overrides = Self::default();
+        // Skip the program name
+        let mut iter = args.iter().skip(1);
+
+        while let Some(arg) = iter.next() {
+            let mut value = |flag: &str| {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| ConfigError::Cli(format!("{} requires a value", flag)))
+            };
+
+            match arg.as_str() {
+                "--help" | "-h" => overrides.help = true,
+                "--no-dashboard" => overrides.no_dashboard = true,
+                "--config" => overrides.config_path = Some(PathBuf::from(value(arg)?)),
+                "--keypair" => overrides.keypair_path = Some(PathBuf::from(value(arg)?)),
+                "--external-address" => overrides.external_address = Some(value(arg)?),
+                "--tcp-port" => overrides.tcp_port = Some(parse_value(arg, &value(arg)?)?),
+                "--quic-port" => overrides.quic_port = Some(parse_value(arg, &value(arg)?)?),
+                "--web-dashboard" => overrides.web_address = Some(parse_value(arg, &value(arg)?)?),
+                "--signaling" => overrides.signaling_address = Some(parse_value(arg, &value(arg)?)?),
+                "--log-file" => overrides.log_file = Some(PathBuf::from(value(arg)?)),
+                other => return Err(ConfigError::Cli(format!("unknown option '{}' (see --help)", other))),
+            }
+        }
+
+        Ok(overrides)
+    }
+
+    fn apply(&self, config: &mut Config) {
+        if let Some(port) = self.tcp_port {
+            config.network.tcp_port = port;
+        }
+        if let Some(port) = self.quic_port {
+            config.network.quic_port = port;
+        }
+        if let Some(path) = &self.keypair_path {
+            config.network.keypair_path = Some(path.clone());
+        }
+        if let Some(addr) = &self.external_address {
+            config.network.external_address = Some(addr.clone());
+        }
+        if let Some(addr) = self.web_address {
+            config.dashboard.web_address = Some(addr);
+        }
+        if let Some(addr) = self.signaling_address {
+            config.signaling.address = Some(addr);
+        }
+        if let Some(path) = &self.log_file {
+            config.logging.file = Some(path.clone());
+        }
+        if self.no_dashboard {
+            config.dashboard.tui = false;
+        }
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| ConfigError::Cli(format!("invalid value '{}' for {}: {}", value, flag, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("cider-relay")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_partial_file() {
+        let config = Config::from_toml(
+            r#"
+            [network]
+            tcp_port = 5001
+            external_address = "relay.example.com"
+
+            [dashboard]
+            web_address = "127.0.0.1:8080"
+
+            [signaling]
+            address = "0.0.0.0:8090"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.network.tcp_port, 5001);
+        assert_eq!(config.network.quic_port, DEFAULT_PORT);
+        assert!(config.dashboard.tui);
+        assert_eq!(config.dashboard.web_address, Some("127.0.0.1:8080".parse().unwrap()));
+        assert_eq!(config.signaling.address, Some("0.0.0.0:8090".parse().unwrap()));
+        assert_eq!(
+            config.external_address().unwrap(),
+            Some(ExternalAddress::Domain("relay.example.com".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_rate_limits() {
+        let config = Config::from_toml(
+            r#"
+            [limits]
+            max_peers_per_ip = 4
+            reservation_rate_per_ip = { burst = 5, interval_secs = 120 }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.limits.max_peers_per_ip, 4);
+        assert_eq!(config.limits.reservation_rate_per_ip.burst, 5);
+        assert_eq!(config.limits.circuit_rate_per_ip.burst, 60);
+
+        let config = Config::from_toml("[limits]\ncircuit_rate_per_ip = { burst = 0, interval_secs = 1 }\n").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_relay_limits() {
+        let config = Config::from_toml(
+            r#"
+            [limits]
+            max_circuits = 64
+            max_circuit_duration_secs = 3600
+            max_circuit_kb = 0
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.limits.max_circuits, 64);
+        assert_eq!(config.limits.max_circuit_bytes(), 0);
+        assert_eq!(config.limits.max_reservations, 128);
+        assert_eq!(Config::default().limits.max_circuit_bytes(), 128 * 1024);
+
+        let config = Config::from_toml("[limits]\nmax_reservations_per_peer = 0\n").unwrap();
+        assert!(config.validate().is_err());
+        let config = Config::from_toml("[limits]\nreservation_duration_secs = 5000000000\n").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_access_config() {
+        let config = Config::default();
+        assert!(!config.access_control().unwrap().is_enabled());
+
+        let peer = PeerId::random();
+        let config = Config::from_toml(&format!("[access]\nallowed_peers = [\"{}\"]\n", peer)).unwrap();
+        assert!(config.access_control().unwrap().is_allowed_peer(&peer));
+
+        let config = Config::from_toml("[access]\nallowed_peers = [\"not-a-peer\"]\n").unwrap();
+        assert!(config.validate().is_err());
+
+        let config = Config::from_toml("[access]\ntoken = \"  \"\n").unwrap();
+        assert!(config.validate().is_err());
+
+        let config = Config::from_toml("[access]\nmin_client_version = \"0.4\"\n").unwrap();
+        assert_eq!(config.min_client_version().unwrap().map(|v| v.to_string()).as_deref(), Some("0.4.0"));
+        let config = Config::from_toml("[access]\nmin_client_version = \"latest\"\n").unwrap();
+        assert!(config.validate().is_err());
+
+        // A ban list alone doesn't make the relay private
+        let config = Config::from_toml("[access]\nban_file = \"/var/lib/cider-relay/bans.txt\"\n").unwrap();
+        assert!(!config.access_control().unwrap().is_enabled());
+        assert_eq!(config.ban_path(), PathBuf::from("/var/lib/cider-relay/bans.txt"));
+    }
+
+    #[test]
+    fn test_logging_config() {
+        let config = Config::default();
+        assert!(config.logging.file.is_none());
+        assert_eq!(config.logging.rotation, LogRotation::Daily);
+        assert_eq!(config.logging.max_file_bytes(), None);
+
+        let config = Config::from_toml(
+            "[logging]\nfile = \"/var/log/cider-relay/relay.log\"\nrotation = \"hourly\"\nmax_files = 48\n",
+        )
+        .unwrap();
+        assert_eq!(config.logging.rotation, LogRotation::Hourly);
+        assert_eq!(config.logging.max_files, 48);
+
+        let config = Config::from_toml("[logging]\nmax_file_mb = 100\n").unwrap();
+        assert_eq!(config.logging.max_file_bytes(), Some(100 * 1024 * 1024));
+        let config = Config::from_toml("[logging]\nmax_file_mb = 0\n").unwrap();
+        assert!(config.validate().is_err());
+
+        assert!(Config::from_toml("[logging]\nrotation = \"weekly\"\n").is_err());
+    }
+
+    #[test]
+    fn test_region_validation() {
+        let config = Config::from_toml("[network]\nregion = \"eu-west\"\n").unwrap();
+        assert_eq!(config.network.region.as_deref(), Some("eu-west"));
+
+        let config = Config::from_toml("[network]\nregion = \"eu west\"\n").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_federation_config() {
+        let relay = PeerId::random();
+        let config = Config::from_toml(&format!(
+            "[federation]\npeers = [\"/ip4/203.0.113.10/tcp/4001/p2p/{}\"]\n",
+            relay
+        ))
+        .unwrap();
+        assert_eq!(config.federation_peers().unwrap()[0].0, relay);
+
+        let config = Config::from_toml("[federation]\npeers = [\"/ip4/203.0.113.10/tcp/4001\"]\n").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_rejected() {
+        let err = Config::from_toml("[network]\ntcp_prot = 4001\n").unwrap_err();
+        assert!(err.contains("tcp_prot"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_cli_overrides_file() {
+        let mut config = Config::from_toml("[network]\ntcp_port = 5001\n").unwrap();
+        let overrides = CliOverrides::parse(&args(&["--tcp-port", "6001", "--no-dashboard"])).unwrap();
+        overrides.apply(&mut config);
+
+        assert_eq!(config.network.tcp_port, 6001);
+        assert!(!config.dashboard.tui);
+    }
+
+    #[test]
+    fn test_cli_errors() {
+        assert!(CliOverrides::parse(&args(&["--tcp-port"])).is_err());
+        assert!(CliOverrides::parse(&args(&["--tcp-port", "abc"])).is_err());
+        assert!(CliOverrides::parse(&args(&["--bogus"])).is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+
+        config.network.external_address = Some("not an address".to_string());
+        assert!(config.validate().is_err());
+
+        config.network.external_address = Some("203.0.113.7".to_string());
+        assert!(config.validate().is_ok());
+
+        config.network.tcp_port = 0;
+        assert!(config.validate().is_err());
+    }
+}
@@ -1,7 +1,9 @@
 //! Terminal dashboard for the relay server
 
-use crate::metrics::{LogLevel, Metrics, ServerStatus};
-use crate::network::{self, NetworkEvent};
+use crate::config::Config;
+use crate::metrics::{truncate_peer_id, LogLevel, Metrics, ServerStatus};
+use crate::network::{self, NetworkCommand, NetworkEvent};
+use chrono::{DateTime, Local};
 use crossterm::{
     event::{self, Event, KeyCode, KeyEventKind},
     execute,
@@ -12,7 +14,10 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState},
+    widgets::{
+        Block, Borders, Cell, List, ListItem, Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState,
+        Sparkline, Table, TableState,
+    },
     Frame, Terminal,
 };
 use std::io::stdout;
@@ -20,16 +25,42 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
 
+/// Main panel content (switched with Tab)
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+    Logs,
+    Peers,
+    Circuits,
+    Rooms,
+}
+
+impl View {
+    fn next(self) -> Self {
+        match self {
+            View::Logs => View::Peers,
+            View::Peers => View::Circuits,
+            View::Circuits => View::Rooms,
+            View::Rooms => View::Logs,
+        }
+    }
+}
+
 /// Dashboard state for scrolling etc.
 struct DashboardState {
+    /// Panel shown below the stats
+    view: View,
+    /// Selected row in the peers panel
+    selected_peer: usize,
     /// Log scroll position (0 = most recent at bottom)
     log_scroll: usize,
     /// Whether auto-scroll is enabled (follows new logs)
     auto_scroll: bool,
+    /// Quit was pressed; the network is draining
+    shutdown_requested: bool,
 }
 
 /// Run the dashboard
-pub async fn run(metrics: Arc<RwLock<Metrics>>) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn run(config: Config, metrics: Arc<RwLock<Metrics>>) -> Result<(), Box<dyn std::error::Error>> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = stdout();
@@ -37,21 +68,25 @@ pub async fn run(metrics: Arc<RwLock<Metrics>>) -> Result<(), Box<dyn std::error
     let backend = ratatui::backend::CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Channel for network events
+    // Channels for network events and commands
     let (event_tx, mut event_rx) = mpsc::unbounded_channel::<NetworkEvent>();
+    let (command_tx, command_rx) = mpsc::unbounded_channel::<NetworkCommand>();
 
     // Start network in background
     let metrics_for_network = Arc::clone(&metrics);
     tokio::spawn(async move {
-        if let Err(e) = network::run_with_dashboard(metrics_for_network, event_tx).await {
+        if let Err(e) = network::run_with_dashboard(config, metrics_for_network, event_tx, command_rx).await {
             eprintln!("Network error: {}", e);
         }
     });
 
     // Dashboard state
     let mut state = DashboardState {
+        view: View::Logs,
+        selected_peer: 0,
         log_scroll: 0,
         auto_scroll: true,
+        shutdown_requested: false,
     };
 
     // Main loop
@@ -65,6 +100,7 @@ pub async fn run(metrics: Arc<RwLock<Metrics>>) -> Result<(), Box<dyn std::error
                 NetworkEvent::Ready { .. } => {}
                 NetworkEvent::PublicIp(_) => {}
                 NetworkEvent::PortCheck(_) => {}
+                NetworkEvent::Stopped => should_quit = true,
             }
             // New events came in, scroll to bottom if auto-scroll enabled
             if state.auto_scroll {
@@ -81,10 +117,45 @@ pub async fn run(metrics: Arc<RwLock<Metrics>>) -> Result<(), Box<dyn std::error
                 if key.kind == KeyEventKind::Press {
                     let log_count = metrics.read().logs.len();
 
+                    let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                        || (key.code == KeyCode::Char('c') && key.modifiers.contains(event::KeyModifiers::CONTROL));
+
                     match key.code {
-                        KeyCode::Char('q') | KeyCode::Esc => should_quit = true,
-                        KeyCode::Char('c') if key.modifiers.contains(event::KeyModifiers::CONTROL) => {
-                            should_quit = true
+                        // First quit drains active circuits, the second one exits immediately
+                        _ if quit => {
+                            if state.shutdown_requested || command_tx.send(NetworkCommand::Shutdown).is_err() {
+                                should_quit = true;
+                            }
+                            state.shutdown_requested = true;
+                        }
+                        KeyCode::Tab => state.view = state.view.next(),
+                        // Peers panel: select, disconnect and ban
+                        _ if state.view == View::Peers => {
+                            let peers: Vec<String> =
+                                metrics.read().peer_list.iter().map(|p| p.peer_id.clone()).collect();
+                            let last = peers.len().saturating_sub(1);
+
+                            match key.code {
+                                KeyCode::Up | KeyCode::Char('k') => {
+                                    state.selected_peer = state.selected_peer.saturating_sub(1);
+                                }
+                                KeyCode::Down | KeyCode::Char('j') => {
+                                    state.selected_peer = (state.selected_peer + 1).min(last);
+                                }
+                                KeyCode::Home => state.selected_peer = 0,
+                                KeyCode::End => state.selected_peer = last,
+                                KeyCode::Char('d') | KeyCode::Delete => {
+                                    if let Some(peer_id) = peers.get(state.selected_peer).and_then(|p| p.parse().ok()) {
+                                        let _ = command_tx.send(NetworkCommand::Disconnect(peer_id));
+                                    }
+                                }
+                                KeyCode::Char('b') => {
+                                    if let Some(peer_id) = peers.get(state.selected_peer).and_then(|p| p.parse().ok()) {
+                                        let _ = command_tx.send(NetworkCommand::Ban(peer_id));
+                                    }
+                                }
+                                _ => {}
+                            }
                         }
                         // Scroll up (older logs)
                         KeyCode::Up | KeyCode::Char('k') => {
@@ -161,7 +232,8 @@ fn draw(f: &mut Frame, metrics: &Arc<RwLock<Metrics>>, state: &DashboardState) {
         .constraints([
             Constraint::Length(3),  // Header
             Constraint::Length(9),  // Stats
-            Constraint::Min(10),    // Logs
+            Constraint::Length(5),  // Traffic
+            Constraint::Min(10),    // Logs / peers / circuits
             Constraint::Length(1),  // Footer
         ])
         .split(f.area());
@@ -172,23 +244,33 @@ fn draw(f: &mut Frame, metrics: &Arc<RwLock<Metrics>>, state: &DashboardState) {
     // Stats
     draw_stats(f, chunks[1], &m);
 
-    // Logs
-    draw_logs(f, chunks[2], &m, state);
+    // Traffic history
+    draw_traffic(f, chunks[2], &m);
+
+    // Logs, peers or circuits
+    match state.view {
+        View::Logs => draw_logs(f, chunks[3], &m, state),
+        View::Peers => draw_peers(f, chunks[3], &m, state),
+        View::Circuits => draw_circuits(f, chunks[3], &m),
+        View::Rooms => draw_rooms(f, chunks[3], &m),
+    }
 
     // Footer
-    draw_footer(f, chunks[3], state);
+    draw_footer(f, chunks[4], state);
 }
 
 fn draw_header(f: &mut Frame, area: Rect, m: &Metrics) {
     let status_style = match m.status {
         ServerStatus::Starting => Style::default().fg(Color::Yellow),
         ServerStatus::Running => Style::default().fg(Color::Green),
+        ServerStatus::Draining => Style::default().fg(Color::Yellow),
         ServerStatus::Error => Style::default().fg(Color::Red),
     };
 
     let status_text = match m.status {
         ServerStatus::Starting => "STARTING",
         ServerStatus::Running => "RUNNING",
+        ServerStatus::Draining => "DRAINING",
         ServerStatus::Error => "ERROR",
     };
 
@@ -234,7 +316,7 @@ fn draw_stats(f: &mut Frame, area: Rect, m: &Metrics) {
         })
         .unwrap_or_else(|| "detecting...".to_string());
 
-    let server_info = vec![
+    let mut server_info = vec![
         Line::from(vec![
             Span::raw("Peer ID: "),
             Span::styled(&peer_id_short, Style::default().fg(Color::Yellow)),
@@ -243,11 +325,25 @@ fn draw_stats(f: &mut Frame, area: Rect, m: &Metrics) {
             Span::raw("Public IP: "),
             Span::styled(&ip_display, Style::default().fg(Color::Cyan)),
         ]),
-        Line::from(vec![
-            Span::raw("Ports: "),
-            Span::styled(format!("TCP:{} QUIC:{}", m.tcp_port, m.quic_port), Style::default().fg(Color::Cyan)),
-        ]),
     ];
+    if let Some(ipv6) = &m.public_ipv6 {
+        server_info.push(Line::from(vec![
+            Span::raw("IPv6: "),
+            Span::styled(ipv6, Style::default().fg(Color::Cyan)),
+        ]));
+    }
+    let quic_reachable = match m.quic_reachable {
+        Some(true) => " ✓",
+        Some(false) => " ✗",
+        None => "",
+    };
+    server_info.push(Line::from(vec![
+        Span::raw("Ports: "),
+        Span::styled(
+            format!("TCP:{} QUIC:{}{}", m.tcp_port, m.quic_port, quic_reachable),
+            Style::default().fg(Color::Cyan),
+        ),
+    ]));
 
     let server_block = Paragraph::new(server_info)
         .block(Block::default().borders(Borders::ALL).title(" Server "));
@@ -303,6 +399,41 @@ fn draw_stats(f: &mut Frame, area: Rect, m: &Metrics) {
     f.render_widget(relay_block, chunks[2]);
 }
 
+fn draw_traffic(f: &mut Frame, area: Rect, m: &Metrics) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    // Completed minutes plus the current one, newest on the right
+    let samples: Vec<_> = m.traffic_history.iter().chain(std::iter::once(&m.current_traffic)).collect();
+    let visible = chunks[0].width.saturating_sub(2) as usize;
+    let recent = &samples[samples.len().saturating_sub(visible)..];
+
+    let connections: Vec<u64> = recent.iter().map(|s| s.connections).collect();
+    let peak_connections = connections.iter().copied().max().unwrap_or(0);
+    let connections_chart = Sparkline::default()
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            " Connections/min (now {}, peak {}) ",
+            m.current_traffic.connections, peak_connections
+        )))
+        .data(&connections)
+        .style(Style::default().fg(Color::Green));
+    f.render_widget(connections_chart, chunks[0]);
+
+    let bytes: Vec<u64> = recent.iter().map(|s| s.bytes).collect();
+    let peak_bytes = bytes.iter().copied().max().unwrap_or(0);
+    let bytes_chart = Sparkline::default()
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            " Relayed/min (now {}, peak {}) ",
+            format_bytes(m.current_traffic.bytes),
+            format_bytes(peak_bytes)
+        )))
+        .data(&bytes)
+        .style(Style::default().fg(Color::Cyan));
+    f.render_widget(bytes_chart, chunks[1]);
+}
+
 fn draw_logs(f: &mut Frame, area: Rect, m: &Metrics, state: &DashboardState) {
     let visible_height = area.height.saturating_sub(2) as usize;
     let total_logs = m.logs.len();
@@ -363,23 +494,180 @@ fn draw_logs(f: &mut Frame, area: Rect, m: &Metrics, state: &DashboardState) {
     }
 }
 
+fn draw_peers(f: &mut Frame, area: Rect, m: &Metrics, state: &DashboardState) {
+    let header = Row::new(["Peer ID", "Protocol", "Connected", "Reservation", "Circuits"])
+        .style(Style::default().fg(Color::DarkGray).add_modifier(Modifier::BOLD));
+
+    let rows: Vec<Row> = m
+        .peer_list
+        .iter()
+        .map(|peer| {
+            let circuits = m
+                .circuit_list
+                .iter()
+                .filter(|c| c.src_peer_id == peer.peer_id || c.dst_peer_id == peer.peer_id)
+                .count();
+            let reservation = if peer.has_reservation {
+                Cell::from("yes").style(Style::default().fg(Color::Yellow))
+            } else {
+                Cell::from("-")
+            };
+
+            Row::new(vec![
+                Cell::from(truncate_peer_id(&peer.peer_id)),
+                Cell::from(peer.protocol.clone().unwrap_or_else(|| "unverified".to_string())),
+                Cell::from(format!("{} ({})", peer.connected_at.format("%H:%M:%S"), format_age(peer.connected_at))),
+                reservation,
+                Cell::from(circuits.to_string()).style(Style::default().fg(Color::Cyan)),
+            ])
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Length(18),
+        Constraint::Min(20),
+        Constraint::Length(20),
+        Constraint::Length(12),
+        Constraint::Length(8),
+    ];
+
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(format!(" Peers ({}) ", m.peer_list.len())))
+        .row_highlight_style(Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD))
+        .highlight_symbol("▶ ");
+
+    // Peers may have left since the selection was made
+    let selected = (!m.peer_list.is_empty()).then(|| state.selected_peer.min(m.peer_list.len() - 1));
+    let mut table_state = TableState::default().with_selected(selected);
+    f.render_stateful_widget(table, area, &mut table_state);
+}
+
+fn draw_circuits(f: &mut Frame, area: Rect, m: &Metrics) {
+    let header = Row::new(["Source", "", "Destination", "Age", "Relayed", "Throughput"])
+        .style(Style::default().fg(Color::DarkGray).add_modifier(Modifier::BOLD));
+
+    // Busiest circuits first
+    let mut circuits: Vec<_> = m.circuit_list.iter().collect();
+    circuits.sort_by(|a, b| b.bytes_per_sec.cmp(&a.bytes_per_sec).then(b.bytes.cmp(&a.bytes)));
+
+    let rows: Vec<Row> = circuits
+        .into_iter()
+        .map(|c| {
+            let throughput_style = if c.bytes_per_sec > 0 {
+                Style::default().fg(Color::Green)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            Row::new(vec![
+                Cell::from(truncate_peer_id(&c.src_peer_id)),
+                Cell::from("→"),
+                Cell::from(truncate_peer_id(&c.dst_peer_id)),
+                Cell::from(format_age(c.established_at)),
+                Cell::from(format_bytes(c.bytes)),
+                Cell::from(format!("{}/s", format_bytes(c.bytes_per_sec))).style(throughput_style),
+            ])
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Length(18),
+        Constraint::Length(2),
+        Constraint::Length(18),
+        Constraint::Length(10),
+        Constraint::Length(12),
+        Constraint::Min(12),
+    ];
+
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(format!(" Circuits ({}) ", m.circuit_list.len())));
+    f.render_widget(table, area);
+}
+
+fn draw_rooms(f: &mut Frame, area: Rect, m: &Metrics) {
+    let header = Row::new(["Room", "Participants", "Circuits", "Relayed"])
+        .style(Style::default().fg(Color::DarkGray).add_modifier(Modifier::BOLD));
+
+    // Rooms driving the most traffic first
+    let mut rooms: Vec<_> = m.rooms.iter().collect();
+    rooms.sort_by(|a, b| b.1.bytes_relayed.cmp(&a.1.bytes_relayed).then(a.0.cmp(b.0)));
+
+    let rows: Vec<Row> = rooms
+        .into_iter()
+        .map(|(room, stats)| {
+            Row::new(vec![
+                Cell::from(room.clone()).style(Style::default().fg(Color::Cyan)),
+                Cell::from(stats.participants.to_string()),
+                Cell::from(stats.active_circuits.to_string()),
+                Cell::from(format_bytes(stats.bytes_relayed)),
+            ])
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Min(20),
+        Constraint::Length(14),
+        Constraint::Length(10),
+        Constraint::Length(12),
+    ];
+
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(format!(" Rooms ({}) ", m.rooms.len())));
+    f.render_widget(table, area);
+}
+
 fn draw_footer(f: &mut Frame, area: Rect, state: &DashboardState) {
-    let auto_text = if state.auto_scroll { "ON " } else { "OFF" };
-    let auto_color = if state.auto_scroll { Color::Green } else { Color::Yellow };
-
-    let footer = Paragraph::new(Line::from(vec![
-        Span::styled(" Q ", Style::default().fg(Color::Black).bg(Color::White)),
-        Span::raw(" Quit  "),
-        Span::styled(" ↑↓ ", Style::default().fg(Color::Black).bg(Color::White)),
-        Span::raw(" Scroll  "),
-        Span::styled(" PgUp/Dn ", Style::default().fg(Color::Black).bg(Color::White)),
-        Span::raw(" Page  "),
-        Span::styled(" A ", Style::default().fg(Color::Black).bg(Color::White)),
-        Span::raw(" Auto-scroll: "),
-        Span::styled(auto_text, Style::default().fg(auto_color)),
-    ]));
+    let key = |k: &'static str| Span::styled(k, Style::default().fg(Color::Black).bg(Color::White));
+
+    let mut spans = vec![
+        key(" Q "),
+        Span::raw(if state.shutdown_requested { " Force quit  " } else { " Quit  " }),
+        key(" Tab "),
+        Span::raw(" View  "),
+    ];
 
-    f.render_widget(footer, area);
+    match state.view {
+        View::Logs => {
+            let auto_text = if state.auto_scroll { "ON " } else { "OFF" };
+            let auto_color = if state.auto_scroll { Color::Green } else { Color::Yellow };
+            spans.extend([
+                key(" ↑↓ "),
+                Span::raw(" Scroll  "),
+                key(" PgUp/Dn "),
+                Span::raw(" Page  "),
+                key(" A "),
+                Span::raw(" Auto-scroll: "),
+                Span::styled(auto_text, Style::default().fg(auto_color)),
+            ]);
+        }
+        View::Peers => {
+            spans.extend([
+                key(" ↑↓ "),
+                Span::raw(" Select  "),
+                key(" D "),
+                Span::raw(" Disconnect  "),
+                key(" B "),
+                Span::raw(" Ban"),
+            ]);
+        }
+        View::Circuits | View::Rooms => {}
+    }
+
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+/// Time elapsed since `since` (e.g. "42s", "5m 12s", "3h 20m")
+fn format_age(since: DateTime<Local>) -> String {
+    let secs = Local::now().signed_duration_since(since).num_seconds().max(0);
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+    }
 }
 
 fn format_bytes(bytes: u64) -> String {
@@ -0,0 +1,231 @@
+//! Relay federation
+//!
+//! Relays configured as federation peers periodically exchange which
+//! clients hold reservations on them and which rendezvous registrations
+//! they serve. Clients can then ask any relay of the federation where the
+//! members of a namespace (room) are reachable, so a host reserved on
+//! relay A and a listener on relay B still find each other.
+//!
+//! Federation peers also check each other's QUIC reachability: a relay asks
+//! a peer to open a fresh QUIC connection to its public address, which tells
+//! the operator whether the UDP port is actually open.
+//!
+//! Protocols (JSON request-response):
+//!   /cider-relay/federation/1.0.0 - relay <-> relay state exchange
+//!   /cider-relay/lookup/1.0.0     - client -> relay namespace lookup
+//!   /cider-relay/probe/1.0.0      - relay <-> relay dial-back check
+
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Relay <-> relay state exchange protocol
+pub const FEDERATION_PROTOCOL: StreamProtocol = StreamProtocol::new("/cider-relay/federation/1.0.0");
+
+/// Client lookup protocol
+pub const LOOKUP_PROTOCOL: StreamProtocol = StreamProtocol::new("/cider-relay/lookup/1.0.0");
+
+/// Relay <-> relay dial-back protocol
+pub const PROBE_PROTOCOL: StreamProtocol = StreamProtocol::new("/cider-relay/probe/1.0.0");
+
+/// Announced state is dropped after this many missed sync intervals
+const STATE_EXPIRY_INTERVALS: u32 = 3;
+
+/// What a relay announces to its federation peers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RelayState {
+    /// Public addresses of the announcing relay
+    pub addrs: Vec<String>,
+    /// Peers holding a reservation on the announcing relay
+    pub reservations: Vec<String>,
+    /// Rendezvous registrations on the announcing relay
+    pub registrations: Vec<RegistrationInfo>,
+}
+
+/// A rendezvous registration (namespace + registered peer)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RegistrationInfo {
+    pub namespace: String,
+    pub peer_id: String,
+}
+
+/// Client request: where are the members of a namespace reachable?
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LookupRequest {
+    pub namespace: String,
+}
+
+/// Relay response to a lookup
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LookupResponse {
+    pub peers: Vec<PeerLocation>,
+}
+
+/// A namespace member and the relay circuit addresses it can be dialed on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerLocation {
+    pub peer_id: String,
+    pub addrs: Vec<String>,
+}
+
+/// Request to dial the requesting relay back on one of its addresses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeRequest {
+    pub addr: String,
+}
+
+/// Dial-back result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResponse {
+    pub reachable: bool,
+    /// Why the dial failed (if it did)
+    pub error: Option<String>,
+}
+
+impl RelayState {
+    /// Members of `namespace` reachable through this relay (`relay` = its peer ID)
+    ///
+    /// Only registered peers that also hold a reservation can be reached
+    /// via a circuit address.
+    pub fn locations(&self, relay: &PeerId, namespace: &str) -> Vec<PeerLocation> {
+        let reserved: HashSet<&str> = self.reservations.iter().map(String::as_str).collect();
+        self.registrations
+            .iter()
+            .filter(|r| r.namespace == namespace && reserved.contains(r.peer_id.as_str()))
+            .filter_map(|r| {
+                let peer_id: PeerId = r.peer_id.parse().ok()?;
+                let addrs: Vec<String> = self
+                    .addrs
+                    .iter()
+                    .filter_map(|a| a.parse::<Multiaddr>().ok())
+                    .map(|a| {
+                        a.with(Protocol::P2p(*relay))
+                            .with(Protocol::P2pCircuit)
+                            .with(Protocol::P2p(peer_id))
+                            .to_string()
+                    })
+                    .collect();
+                (!addrs.is_empty()).then(|| PeerLocation {
+                    peer_id: r.peer_id.clone(),
+                    addrs,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Latest state announced by each federated relay
+#[derive(Debug)]
+pub struct FederationRegistry {
+    expiry: Duration,
+    relays: HashMap<PeerId, (RelayState, Instant)>,
+}
+
+impl FederationRegistry {
+    pub fn new(sync_interval: Duration) -> Self {
+        Self {
+            expiry: sync_interval * STATE_EXPIRY_INTERVALS,
+            relays: HashMap::new(),
+        }
+    }
+
+    /// Store a relay's announced state
+    pub fn update(&mut self, relay: PeerId, state: RelayState, now: Instant) {
+        self.relays.insert(relay, (state, now));
+    }
+
+    /// Drop state from relays that stopped announcing
+    pub fn prune(&mut self, now: Instant) {
+        let expiry = self.expiry;
+        self.relays
+            .retain(|_, (_, updated)| now.duration_since(*updated) < expiry);
+    }
+
+    /// Number of relays with current state
+    pub fn len(&self) -> usize {
+        self.relays.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.relays.is_empty()
+    }
+
+    /// Members of `namespace` reachable through federated relays
+    pub fn lookup(&self, namespace: &str) -> Vec<PeerLocation> {
+        self.relays
+            .iter()
+            .flat_map(|(relay, (state, _))| state.locations(relay, namespace))
+            .collect()
+    }
+}
+
+/// Peer ID from a federation peer address (must end in /p2p/<id>)
+pub fn federation_peer_id(addr: &Multiaddr) -> Option<PeerId> {
+    match addr.iter().last() {
+        Some(Protocol::P2p(peer_id)) => Some(peer_id),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(reservations: &[PeerId], registrations: &[(&str, PeerId)]) -> RelayState {
+        RelayState {
+            addrs: vec!["/ip4/203.0.113.10/tcp/4001".to_string()],
+            reservations: reservations.iter().map(|p| p.to_string()).collect(),
+            registrations: registrations
+                .iter()
+                .map(|(ns, p)| RegistrationInfo {
+                    namespace: ns.to_string(),
+                    peer_id: p.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_locations_require_reservation() {
+        let relay = PeerId::random();
+        let host = PeerId::random();
+        let unreachable = PeerId::random();
+        let state = state(&[host], &[("room-ABC123", host), ("room-ABC123", unreachable)]);
+
+        let locations = state.locations(&relay, "room-ABC123");
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].peer_id, host.to_string());
+        assert_eq!(
+            locations[0].addrs[0],
+            format!("/ip4/203.0.113.10/tcp/4001/p2p/{}/p2p-circuit/p2p/{}", relay, host)
+        );
+        assert!(state.locations(&relay, "room-OTHER1").is_empty());
+    }
+
+    #[test]
+    fn test_registry_expires_state() {
+        let mut registry = FederationRegistry::new(Duration::from_secs(60));
+        let relay = PeerId::random();
+        let host = PeerId::random();
+        let start = Instant::now();
+
+        registry.update(relay, state(&[host], &[("room-ABC123", host)]), start);
+        assert_eq!(registry.lookup("room-ABC123").len(), 1);
+
+        registry.prune(start + Duration::from_secs(120));
+        assert_eq!(registry.len(), 1);
+        registry.prune(start + Duration::from_secs(180));
+        assert_eq!(registry.len(), 0);
+    }
+
+    #[test]
+    fn test_federation_peer_id() {
+        let peer = PeerId::random();
+        let addr: Multiaddr = format!("/dns/relay.example.com/tcp/4001/p2p/{}", peer).parse().unwrap();
+        assert_eq!(federation_peer_id(&addr), Some(peer));
+        let addr: Multiaddr = "/ip4/203.0.113.10/tcp/4001".parse().unwrap();
+        assert_eq!(federation_peer_id(&addr), None);
+    }
+}
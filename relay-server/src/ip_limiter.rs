@@ -0,0 +1,190 @@
+//! Per-IP connection limits
+//!
+//! Peer IDs are free to generate, so the Cider-identify check alone doesn't
+//! stop a single host from flooding the relay. This caps the number of
+//! distinct peers and the rate of new connections coming from one address.
+//! Loopback addresses are exempt so local testing isn't throttled.
+
+use libp2p::core::ConnectedPoint;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Window for the connection rate limit
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Why a connection was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpLimitExceeded {
+    /// Too many distinct peers connected from the same IP
+    TooManyPeers(usize),
+    /// Too many new connections from the same IP within a minute
+    TooManyConnections(u32),
+}
+
+impl fmt::Display for IpLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpLimitExceeded::TooManyPeers(max) => write!(f, "more than {} peers from one IP", max),
+            IpLimitExceeded::TooManyConnections(max) => write!(f, "more than {} connections/min from one IP", max),
+        }
+    }
+}
+
+/// Tracks connections per remote IP
+#[derive(Debug)]
+pub struct IpLimiter {
+    max_peers_per_ip: usize,
+    max_connections_per_minute: u32,
+    /// Open connection count per peer, grouped by IP
+    peers: HashMap<IpAddr, HashMap<PeerId, usize>>,
+    /// Recent connection times per IP
+    recent: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl IpLimiter {
+    pub fn new(max_peers_per_ip: usize, max_connections_per_minute: u32) -> Self {
+        Self {
+            max_peers_per_ip,
+            max_connections_per_minute,
+            peers: HashMap::new(),
+            recent: HashMap::new(),
+        }
+    }
+
+    /// Register a new connection; returns an error if it should be closed
+    ///
+    /// Refused connections are not tracked: `on_connection_closed` must
+    /// only be called for connections this admitted.
+    pub fn on_connection(&mut self, ip: IpAddr, peer_id: PeerId, now: Instant) -> Result<(), IpLimitExceeded> {
+        if ip.is_loopback() {
+            return Ok(());
+        }
+
+        let recent = self.recent.entry(ip).or_default();
+        while recent.front().is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW) {
+            recent.pop_front();
+        }
+        if recent.len() >= self.max_connections_per_minute as usize {
+            return Err(IpLimitExceeded::TooManyConnections(self.max_connections_per_minute));
+        }
+        recent.push_back(now);
+
+        let peers = self.peers.entry(ip).or_default();
+        if !peers.contains_key(&peer_id) && peers.len() >= self.max_peers_per_ip {
+            return Err(IpLimitExceeded::TooManyPeers(self.max_peers_per_ip));
+        }
+        *peers.entry(peer_id).or_insert(0) += 1;
+        Ok(())
+    }
+
+    /// Unregister a closed connection
+    pub fn on_connection_closed(&mut self, ip: IpAddr, peer_id: &PeerId) {
+        let Some(peers) = self.peers.get_mut(&ip) else {
+            return;
+        };
+        if let Some(count) = peers.get_mut(peer_id) {
+            *count -= 1;
+            if *count == 0 {
+                peers.remove(peer_id);
+            }
+        }
+        if peers.is_empty() {
+            self.peers.remove(&ip);
+        }
+    }
+
+    /// Drop rate-limit history older than the window
+    pub fn prune(&mut self, now: Instant) {
+        self.recent.retain(|_, times| {
+            times.back().is_some_and(|t| now.duration_since(*t) < RATE_WINDOW)
+        });
+    }
+}
+
+/// Remote IP of an inbound connection (outbound ones aren't limited)
+pub fn inbound_ip(endpoint: &ConnectedPoint) -> Option<IpAddr> {
+    match endpoint {
+        ConnectedPoint::Listener { send_back_addr, .. } => multiaddr_ip(send_back_addr),
+        ConnectedPoint::Dialer { .. } => None,
+    }
+}
+
+/// Extract the IP address from a multiaddr
+pub fn multiaddr_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_limits_peers_per_ip() {
+        let mut limiter = IpLimiter::new(2, 100);
+        let now = Instant::now();
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+
+        assert!(limiter.on_connection(ip("203.0.113.1"), a, now).is_ok());
+        assert!(limiter.on_connection(ip("203.0.113.1"), b, now).is_ok());
+        // Additional transport for a known peer is fine
+        assert!(limiter.on_connection(ip("203.0.113.1"), a, now).is_ok());
+        assert_eq!(
+            limiter.on_connection(ip("203.0.113.1"), c, now),
+            Err(IpLimitExceeded::TooManyPeers(2))
+        );
+        // Other IPs are unaffected
+        assert!(limiter.on_connection(ip("203.0.113.2"), c, now).is_ok());
+
+        // Slot frees up once all of b's connections are gone
+        limiter.on_connection_closed(ip("203.0.113.1"), &b);
+        assert!(limiter.on_connection(ip("203.0.113.1"), c, now).is_ok());
+    }
+
+    #[test]
+    fn test_limits_connection_rate() {
+        let mut limiter = IpLimiter::new(100, 3);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            let peer = PeerId::random();
+            assert!(limiter.on_connection(ip("198.51.100.7"), peer, start).is_ok());
+            limiter.on_connection_closed(ip("198.51.100.7"), &peer);
+        }
+        assert_eq!(
+            limiter.on_connection(ip("198.51.100.7"), PeerId::random(), start),
+            Err(IpLimitExceeded::TooManyConnections(3))
+        );
+        assert!(limiter
+            .on_connection(ip("198.51.100.7"), PeerId::random(), start + RATE_WINDOW)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_loopback_is_exempt() {
+        let mut limiter = IpLimiter::new(1, 1);
+        let now = Instant::now();
+        for _ in 0..5 {
+            assert!(limiter.on_connection(ip("127.0.0.1"), PeerId::random(), now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_multiaddr_ip() {
+        let addr: Multiaddr = "/ip4/192.0.2.5/udp/4001/quic-v1".parse().unwrap();
+        assert_eq!(multiaddr_ip(&addr), Some(ip("192.0.2.5")));
+        let addr: Multiaddr = "/dns/example.com/tcp/4001".parse().unwrap();
+        assert_eq!(multiaddr_ip(&addr), None);
+    }
+}
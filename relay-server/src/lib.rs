@@ -0,0 +1,21 @@
+//! Cider Listen Together - Dedicated Relay Server
+//!
+//! The relay is built as a library so the `cider-relay` binary and the
+//! integration tests in `tests/` run the same network code.
+
+pub mod access;
+pub mod ban;
+pub mod bandwidth;
+pub mod config;
+pub mod dashboard;
+pub mod federation;
+pub mod ip_limiter;
+pub mod logging;
+pub mod metrics;
+pub mod network;
+pub mod quota;
+pub mod shutdown;
+pub mod signaling;
+pub mod stun;
+pub mod version;
+pub mod web;
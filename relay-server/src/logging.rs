@@ -0,0 +1,257 @@
+//! Tracing setup (console and/or rotating log file)
+//!
+//! The terminal dashboard only keeps the last 100 log entries in memory, so
+//! operators who want to audit rejections or crashes later can enable a log
+//! file. With the dashboard, tracing goes to the file only; in plain logging
+//! mode it goes to both stdout and the file.
+//!
+//! The log file starts over every hour or day, and once it reaches the
+//! configured size. Finished files get their period inserted before the
+//! extension (relay.2025-01-31.log, then relay.2025-01-31.1.log if the day
+//! filled more than one), and only the newest few are kept.
+
+use crate::config::{LogRotation, LoggingConfig};
+use chrono::{DateTime, Local};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::error;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+/// Initialize tracing
+///
+/// Does nothing when neither console output nor a log file is wanted, since
+/// tracing output would garble the TUI. The returned guard keeps the file
+/// writer alive; drop it only on exit so buffered lines are flushed.
+pub fn init(config: &LoggingConfig, console: bool) -> Result<Option<WorkerGuard>, Box<dyn Error>> {
+    if !console && config.file.is_none() {
+        return Ok(None);
+    }
+
+    let filter = EnvFilter::from_default_env()
+        .add_directive("cider_relay=info".parse()?)
+        .add_directive("libp2p_relay=info".parse()?)
+        .add_directive("libp2p_kad=warn".parse()?)
+        .add_directive("libp2p_identify=warn".parse()?);
+
+    let (file_layer, guard) = match &config.file {
+        Some(path) => {
+            let (writer, guard) = tracing_appender::non_blocking(RotatingFile::open(path, config)?);
+            (Some(fmt::layer().with_ansi(false).with_writer(writer)), Some(guard))
+        }
+        None => (None, None),
+    };
+    let console_layer = console.then(fmt::layer);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(console_layer)
+        .with(file_layer)
+        .init();
+
+    if config.file.is_some() {
+        log_panics();
+    }
+
+    Ok(guard)
+}
+
+/// Log file that's moved aside each period and once it's full
+struct RotatingFile {
+    dir: PathBuf,
+    stem: String,
+    extension: Option<String>,
+    rotation: LogRotation,
+    max_bytes: Option<u64>,
+    max_files: usize,
+    file: File,
+    /// Bytes in the current file
+    size: u64,
+    /// Period the current file covers (see `period`)
+    period: String,
+}
+
+impl RotatingFile {
+    /// Open `path` for appending, creating it and its directory if needed
+    fn open(path: &Path, config: &LoggingConfig) -> Result<Self, Box<dyn Error>> {
+        let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let stem = path
+            .file_stem()
+            .ok_or_else(|| format!("Invalid log file path {}", path.display()))?
+            .to_string_lossy()
+            .into_owned();
+        fs::create_dir_all(dir)?;
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        // A file left by the last run covers the period it was last written in
+        let modified = metadata.modified().map_or_else(|_| Local::now(), DateTime::from);
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            stem,
+            extension: path.extension().map(|e| e.to_string_lossy().into_owned()),
+            rotation: config.rotation,
+            max_bytes: config.max_file_bytes(),
+            max_files: config.max_files,
+            file,
+            size: metadata.len(),
+            period: period(config.rotation, modified),
+        })
+    }
+
+    /// Write `buf` as of `now`, moving the current file aside first if it doesn't belong there
+    fn write_at(&mut self, buf: &[u8], now: DateTime<Local>) -> io::Result<usize> {
+        // If the file can't be moved aside, keep writing to it and try again next time
+        let _ = self.rotate_if_due(buf.len(), now);
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    /// Move the current file aside if it's from an earlier period or `incoming` bytes won't fit
+    fn rotate_if_due(&mut self, incoming: usize, now: DateTime<Local>) -> io::Result<()> {
+        let now_period = period(self.rotation, now);
+        if self.size == 0 {
+            self.period = now_period;
+            return Ok(());
+        }
+        let new_period = self.rotation != LogRotation::Never && now_period != self.period;
+        let full = self.max_bytes.is_some_and(|max| self.size + incoming as u64 > max);
+        if !new_period && !full {
+            return Ok(());
+        }
+
+        self.file.flush()?;
+        let mut n = 0;
+        let rotated = loop {
+            let tag = if n == 0 { self.period.clone() } else { format!("{}.{}", self.period, n) };
+            let rotated = self.dir.join(self.file_name(Some(&tag)));
+            if !rotated.exists() {
+                break rotated;
+            }
+            n += 1;
+        };
+        let path = self.dir.join(self.file_name(None));
+        fs::rename(&path, rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.size = 0;
+        self.period = now_period;
+        self.remove_old();
+        Ok(())
+    }
+
+    /// `<stem>[.<tag>][.<extension>]`
+    fn file_name(&self, tag: Option<&str>) -> String {
+        let mut name = self.stem.clone();
+        for part in [tag, self.extension.as_deref()].into_iter().flatten() {
+            name.push('.');
+            name.push_str(part);
+        }
+        name
+    }
+
+    /// Period and number of one of our finished files, which sort oldest first
+    fn rotated_tag(&self, name: &str) -> Option<(String, u32)> {
+        let tag = name.strip_prefix(self.stem.as_str())?.strip_prefix('.')?;
+        let tag = match &self.extension {
+            Some(extension) => tag.strip_suffix(extension.as_str())?.strip_suffix('.')?,
+            None => tag,
+        };
+        let (period, n) = match tag.split_once('.') {
+            Some((period, n)) => (period, n.parse().ok()?),
+            None => (tag, 0),
+        };
+        period
+            .starts_with(|c: char| c.is_ascii_digit())
+            .then(|| (period.to_string(), n))
+    }
+
+    /// Delete finished files past the newest `max_files`
+    fn remove_old(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut rotated: Vec<((String, u32), PathBuf)> = entries
+            .flatten()
+            .filter_map(|entry| Some((self.rotated_tag(&entry.file_name().to_string_lossy())?, entry.path())))
+            .collect();
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.max_files);
+        for (_, path) in rotated.into_iter().take(excess) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, Local::now())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Label of the period `time` falls in, as used in finished file names
+fn period(rotation: LogRotation, time: DateTime<Local>) -> String {
+    match rotation {
+        LogRotation::Hourly => time.format("%Y-%m-%d-%H").to_string(),
+        LogRotation::Daily | LogRotation::Never => time.format("%Y-%m-%d").to_string(),
+    }
+}
+
+/// Record panics in the log file before the default hook prints them
+fn log_panics() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        error!("Panic: {}", info);
+        default_hook(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("cider-relay-logs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = LoggingConfig {
+            file: Some(dir.join("relay.log")),
+            max_files: 2,
+            ..LoggingConfig::default()
+        };
+        let mut file = RotatingFile::open(&dir.join("relay.log"), &config).unwrap();
+        file.max_bytes = Some(10);
+        let morning = Local.with_ymd_and_hms(2025, 1, 31, 9, 0, 0).unwrap();
+        let names = || {
+            let mut names: Vec<String> = fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+
+        // Filling up starts a new file, more than once a day if needed
+        for _ in 0..3 {
+            file.write_at(b"0123456789", morning).unwrap();
+        }
+        assert_eq!(names(), ["relay.2025-01-31.1.log", "relay.2025-01-31.log", "relay.log"]);
+
+        // So does a new day, and only the newest finished files are kept
+        file.write_at(b"next day", morning + chrono::Duration::days(1)).unwrap();
+        assert_eq!(names(), ["relay.2025-01-31.1.log", "relay.2025-01-31.2.log", "relay.log"]);
+        assert_eq!(fs::read_to_string(dir.join("relay.log")).unwrap(), "next day");
+        assert_eq!(file.rotated_tag("relay.2025-01-31-09.3.log"), Some(("2025-01-31-09".to_string(), 3)));
+        assert_eq!(file.rotated_tag("relay.example.log"), None);
+        assert_eq!(file.rotated_tag("relay.log"), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
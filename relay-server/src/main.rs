@@ -5,27 +5,45 @@
 //! Usage:
 //!   cargo run --release
 //!   cargo run --release -- --no-dashboard  # Plain logging mode
+//!   cargo run --release -- --config relay.toml  # Load settings from a config file
+//!   cargo run --release -- --web-dashboard 0.0.0.0:8080  # Also serve web dashboard
+//!   cargo run --release -- --log-file logs/relay.log  # Also log to a rotating file
+//!
+//! See `relay.example.toml` for all settings and `--help` for CLI overrides.
 
-mod dashboard;
-mod metrics;
-mod network;
-
+use cider_relay::config::{self, Config, Startup};
+use cider_relay::{dashboard, logging, metrics, network};
 use std::sync::Arc;
 use parking_lot::RwLock;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = std::env::args().collect();
-    let use_dashboard = !args.contains(&"--no-dashboard".to_string());
+
+    // Load config before touching the terminal so errors are readable
+    let config = match Config::load(&args) {
+        Ok(Startup::Run(config)) => *config,
+        Ok(Startup::Help) => {
+            print!("{}", config::USAGE);
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    // Tracing to stdout (plain mode) and/or the log file; flushed when dropped
+    let _log_guard = logging::init(&config.logging, !config.dashboard.tui)?;
 
     // Shared metrics state
     let metrics = Arc::new(RwLock::new(metrics::Metrics::new()));
 
-    if use_dashboard {
+    if config.dashboard.tui {
         // Run with TUI dashboard
-        dashboard::run(metrics).await
+        dashboard::run(config, metrics).await
     } else {
         // Run with plain logging
-        network::run_with_logging(metrics).await
+        network::run_with_logging(config, metrics).await
     }
 }
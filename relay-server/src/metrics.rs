@@ -1,11 +1,19 @@
 //! Metrics tracking for the relay server
 
 use chrono::{DateTime, Local};
-use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
 
 /// Maximum number of log entries to keep
 const MAX_LOG_ENTRIES: usize = 100;
 
+/// Minutes of traffic history to keep
+const HISTORY_MINUTES: usize = 60;
+
 /// A log entry for the dashboard
 #[derive(Clone)]
 pub struct LogEntry {
@@ -47,6 +55,9 @@ pub struct Metrics {
     /// Public IP address
     pub public_ip: Option<String>,
 
+    /// Public IPv6 address (when detected in addition to IPv4)
+    pub public_ipv6: Option<String>,
+
     /// TCP port
     pub tcp_port: u16,
 
@@ -56,33 +67,54 @@ pub struct Metrics {
     /// TCP port reachable from internet
     pub tcp_reachable: Option<bool>,
 
+    /// QUIC port reachable (dialed back by a federated relay)
+    pub quic_reachable: Option<bool>,
+
     /// Current number of connected peers
     pub connected_peers: usize,
 
-    /// Total connections since start
+    /// Total connections (lifetime, restored on startup)
     pub total_connections: u64,
 
-    /// Peak simultaneous connections
+    /// Peak simultaneous connections (lifetime)
     pub peak_connections: usize,
 
     /// Active relay reservations
     pub active_reservations: usize,
 
-    /// Total relay reservations since start
+    /// Total relay reservations (lifetime)
     pub total_reservations: u64,
 
     /// Active relay circuits
     pub active_circuits: usize,
 
-    /// Total relay circuits since start
+    /// Total relay circuits (lifetime)
     pub total_circuits: u64,
 
-    /// Bytes relayed (approximate)
+    /// Bytes relayed through circuits (lifetime)
     pub bytes_relayed: u64,
 
+    /// Active rendezvous registrations
+    pub rendezvous_registrations: usize,
+
+    /// Federated relays with current state
+    pub federated_relays: usize,
+
     /// Connected peer IDs (for display)
     pub peer_list: Vec<PeerInfo>,
 
+    /// Active relay circuits (for display)
+    pub circuit_list: Vec<CircuitInfo>,
+
+    /// Rooms (rendezvous namespaces) with registered peers or circuits, by name
+    pub rooms: BTreeMap<String, RoomStats>,
+
+    /// Per-minute traffic, oldest first (completed minutes only)
+    pub traffic_history: VecDeque<TrafficSample>,
+
+    /// Traffic in the current (incomplete) minute
+    pub current_traffic: TrafficSample,
+
     /// Log entries
     pub logs: VecDeque<LogEntry>,
 
@@ -91,7 +123,6 @@ pub struct Metrics {
 }
 
 #[derive(Clone)]
-#[allow(dead_code)]
 pub struct PeerInfo {
     pub peer_id: String,
     pub protocol: Option<String>,
@@ -99,23 +130,109 @@ pub struct PeerInfo {
     pub has_reservation: bool,
 }
 
+#[derive(Clone)]
+pub struct CircuitInfo {
+    pub src_peer_id: String,
+    pub dst_peer_id: String,
+    pub established_at: DateTime<Local>,
+    /// Room the circuit is attributed to (from the peers' rendezvous registrations)
+    pub room: Option<String>,
+    /// Bytes relayed through this circuit
+    pub bytes: u64,
+    /// Recent throughput (bytes/s over the last sample interval)
+    pub bytes_per_sec: u64,
+    /// `bytes` at the last throughput sample
+    sampled_bytes: u64,
+}
+
+/// Load caused by one room
+///
+/// Kept while the room has registered peers or active circuits, so the byte
+/// count covers the room's current session on this relay.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoomStats {
+    /// Peers registered in the room on this relay
+    pub participants: usize,
+    /// Active circuits attributed to the room
+    pub active_circuits: usize,
+    /// Bytes relayed through the room's circuits
+    pub bytes_relayed: u64,
+}
+
+impl RoomStats {
+    fn is_empty(&self) -> bool {
+        self.participants == 0 && self.active_circuits == 0
+    }
+}
+
+/// Traffic within one minute
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TrafficSample {
+    /// New peer connections
+    pub connections: u64,
+    /// Bytes relayed through circuits
+    pub bytes: u64,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 #[allow(dead_code)]
 pub enum ServerStatus {
     Starting,
     Running,
+    /// Shutting down, waiting for active circuits to finish
+    Draining,
     Error,
 }
 
+/// Lifetime counters saved on shutdown and restored on startup
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersistedMetrics {
+    pub total_connections: u64,
+    pub peak_connections: usize,
+    pub total_reservations: u64,
+    pub total_circuits: u64,
+    pub bytes_relayed: u64,
+}
+
+impl PersistedMetrics {
+    /// Load saved counters (None if nothing was saved yet)
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save counters (via a temp file so a crash can't leave a truncated file)
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Metrics {
     pub fn new() -> Self {
         Self {
             start_time: Local::now(),
             peer_id: None,
             public_ip: None,
+            public_ipv6: None,
             tcp_port: 4001,
             quic_port: 4001,
             tcp_reachable: None,
+            quic_reachable: None,
             connected_peers: 0,
             total_connections: 0,
             peak_connections: 0,
@@ -124,7 +241,13 @@ impl Metrics {
             active_circuits: 0,
             total_circuits: 0,
             bytes_relayed: 0,
+            rendezvous_registrations: 0,
+            federated_relays: 0,
             peer_list: Vec::new(),
+            circuit_list: Vec::new(),
+            rooms: BTreeMap::new(),
+            traffic_history: VecDeque::with_capacity(HISTORY_MINUTES),
+            current_traffic: TrafficSample::default(),
             logs: VecDeque::with_capacity(MAX_LOG_ENTRIES),
             status: ServerStatus::Starting,
         }
@@ -152,6 +275,7 @@ impl Metrics {
 
         self.connected_peers += 1;
         self.total_connections += 1;
+        self.current_traffic.connections += 1;
         if self.connected_peers > self.peak_connections {
             self.peak_connections = self.connected_peers;
         }
@@ -218,10 +342,22 @@ impl Metrics {
         self.log(LogLevel::Relay, format!("Reservation: {}", short_id));
     }
 
-    /// Record a relay circuit
-    pub fn circuit_established(&mut self, src: &str, dst: &str) {
+    /// Record a relay circuit, attributed to `room` if its peers are registered in one
+    pub fn circuit_established(&mut self, src: &str, dst: &str, room: Option<&str>) {
         self.active_circuits += 1;
         self.total_circuits += 1;
+        if let Some(room) = room {
+            self.rooms.entry(room.to_string()).or_default().active_circuits += 1;
+        }
+        self.circuit_list.push(CircuitInfo {
+            src_peer_id: src.to_string(),
+            dst_peer_id: dst.to_string(),
+            established_at: Local::now(),
+            room: room.map(String::from),
+            bytes: 0,
+            bytes_per_sec: 0,
+            sampled_bytes: 0,
+        });
 
         let src_short = truncate_peer_id(src);
         let dst_short = truncate_peer_id(dst);
@@ -229,8 +365,74 @@ impl Metrics {
     }
 
     /// Record circuit closed
-    pub fn circuit_closed(&mut self) {
+    pub fn circuit_closed(&mut self, src: &str, dst: &str) {
         self.active_circuits = self.active_circuits.saturating_sub(1);
+        if let Some(pos) = self
+            .circuit_list
+            .iter()
+            .position(|c| c.src_peer_id == src && c.dst_peer_id == dst)
+        {
+            let circuit = self.circuit_list.remove(pos);
+            if let Some(room) = circuit.room {
+                self.update_room(&room, |stats| stats.active_circuits = stats.active_circuits.saturating_sub(1));
+            }
+        }
+    }
+
+    /// Record the number of peers registered in a room
+    pub fn set_room_participants(&mut self, room: &str, participants: usize) {
+        self.update_room(room, |stats| stats.participants = participants);
+    }
+
+    /// Change a room's stats, dropping the room once it's empty
+    fn update_room(&mut self, room: &str, update: impl FnOnce(&mut RoomStats)) {
+        let stats = self.rooms.entry(room.to_string()).or_default();
+        update(stats);
+        if stats.is_empty() {
+            self.rooms.remove(room);
+        }
+    }
+
+    /// Record circuit data relayed to/from a destination peer
+    ///
+    /// Traffic is counted per destination, so it's split evenly if the
+    /// peer is the destination of several circuits at once.
+    pub fn bytes_relayed_to(&mut self, dst: &str, bytes: u64) {
+        self.bytes_relayed += bytes;
+        self.current_traffic.bytes += bytes;
+
+        let mut circuits: Vec<&mut CircuitInfo> =
+            self.circuit_list.iter_mut().filter(|c| c.dst_peer_id == dst).collect();
+        let count = circuits.len() as u64;
+        if count == 0 {
+            return;
+        }
+        let share = bytes / count;
+        let remainder = bytes % count;
+        for (i, circuit) in circuits.iter_mut().enumerate() {
+            let circuit_bytes = share + if i == 0 { remainder } else { 0 };
+            circuit.bytes += circuit_bytes;
+            if let Some(stats) = circuit.room.as_ref().and_then(|room| self.rooms.get_mut(room)) {
+                stats.bytes_relayed += circuit_bytes;
+            }
+        }
+    }
+
+    /// Recompute circuit throughput from the bytes relayed since the last sample
+    pub fn sample_circuit_throughput(&mut self, elapsed: Duration) {
+        let millis = elapsed.as_millis().max(1) as u64;
+        for circuit in &mut self.circuit_list {
+            circuit.bytes_per_sec = (circuit.bytes - circuit.sampled_bytes) * 1000 / millis;
+            circuit.sampled_bytes = circuit.bytes;
+        }
+    }
+
+    /// Close the current minute of traffic history (called once per minute)
+    pub fn roll_traffic_history(&mut self) {
+        if self.traffic_history.len() >= HISTORY_MINUTES {
+            self.traffic_history.pop_front();
+        }
+        self.traffic_history.push_back(std::mem::take(&mut self.current_traffic));
     }
 
     /// Update peer protocol info (logging is handled by caller)
@@ -240,6 +442,26 @@ impl Metrics {
         }
     }
 
+    /// Lifetime counters to persist
+    pub fn persisted(&self) -> PersistedMetrics {
+        PersistedMetrics {
+            total_connections: self.total_connections,
+            peak_connections: self.peak_connections,
+            total_reservations: self.total_reservations,
+            total_circuits: self.total_circuits,
+            bytes_relayed: self.bytes_relayed,
+        }
+    }
+
+    /// Continue counting from previously saved totals
+    pub fn restore(&mut self, saved: PersistedMetrics) {
+        self.total_connections += saved.total_connections;
+        self.peak_connections = self.peak_connections.max(saved.peak_connections);
+        self.total_reservations += saved.total_reservations;
+        self.total_circuits += saved.total_circuits;
+        self.bytes_relayed += saved.bytes_relayed;
+    }
+
     /// Get uptime as formatted string
     pub fn uptime(&self) -> String {
         let duration = Local::now().signed_duration_since(self.start_time);
@@ -265,3 +487,91 @@ pub fn truncate_peer_id(peer_id: &str) -> String {
         peer_id.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_persisted_metrics_roundtrip() {
+        let mut metrics = Metrics::new();
+        metrics.connection_established("peer-a".to_string(), None);
+        metrics.circuit_established("peer-a", "peer-b", None);
+        metrics.bytes_relayed_to("peer-b", 1234);
+
+        let path = std::env::temp_dir().join(format!("cider-relay-metrics-{}.json", std::process::id()));
+        metrics.persisted().save(&path).unwrap();
+        let saved = PersistedMetrics::load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // A restarted relay continues from the saved totals
+        let mut restarted = Metrics::new();
+        restarted.connection_established("peer-c".to_string(), None);
+        restarted.restore(saved);
+        assert_eq!(restarted.total_connections, 2);
+        assert_eq!(restarted.total_circuits, 1);
+        assert_eq!(restarted.bytes_relayed, 1234);
+        assert_eq!(restarted.peak_connections, 1);
+        assert_eq!(restarted.connected_peers, 1);
+    }
+
+    #[test]
+    fn test_circuit_throughput() {
+        let mut metrics = Metrics::new();
+        metrics.circuit_established("peer-a", "peer-b", None);
+
+        metrics.bytes_relayed_to("peer-b", 50_000);
+        metrics.sample_circuit_throughput(Duration::from_secs(5));
+        assert_eq!(metrics.circuit_list[0].bytes_per_sec, 10_000);
+
+        // Idle circuits drop back to zero
+        metrics.sample_circuit_throughput(Duration::from_secs(5));
+        assert_eq!(metrics.circuit_list[0].bytes_per_sec, 0);
+        assert_eq!(metrics.circuit_list[0].bytes, 50_000);
+    }
+
+    #[test]
+    fn test_room_stats() {
+        let mut metrics = Metrics::new();
+        metrics.set_room_participants("room-ABC123", 2);
+        metrics.circuit_established("peer-a", "peer-b", Some("room-ABC123"));
+        metrics.circuit_established("peer-c", "peer-b", None);
+
+        // Traffic to peer-b is split between its circuits; only one belongs to the room
+        metrics.bytes_relayed_to("peer-b", 1000);
+        let room = &metrics.rooms["room-ABC123"];
+        assert_eq!((room.participants, room.active_circuits, room.bytes_relayed), (2, 1, 500));
+
+        // The room stays while it has a circuit, and goes once it's empty
+        metrics.set_room_participants("room-ABC123", 0);
+        assert!(metrics.rooms.contains_key("room-ABC123"));
+        metrics.circuit_closed("peer-a", "peer-b");
+        assert!(metrics.rooms.is_empty());
+    }
+
+    #[test]
+    fn test_traffic_history() {
+        let mut metrics = Metrics::new();
+        metrics.connection_established("peer-a".to_string(), None);
+        metrics.bytes_relayed_to("peer-b", 100);
+        metrics.roll_traffic_history();
+
+        assert_eq!(
+            metrics.traffic_history.back(),
+            Some(&TrafficSample { connections: 1, bytes: 100 })
+        );
+        assert_eq!(metrics.current_traffic, TrafficSample::default());
+
+        for _ in 0..HISTORY_MINUTES {
+            metrics.roll_traffic_history();
+        }
+        assert_eq!(metrics.traffic_history.len(), HISTORY_MINUTES);
+        assert!(metrics.traffic_history.iter().all(|s| s.connections == 0));
+    }
+
+    #[test]
+    fn test_load_missing_metrics() {
+        let path = std::env::temp_dir().join("cider-relay-metrics-missing.json");
+        assert_eq!(PersistedMetrics::load(&path).unwrap(), None);
+    }
+}
@@ -1,30 +1,54 @@
 //! Network handling for the relay server
 
-use crate::metrics::{LogLevel, Metrics, ServerStatus, truncate_peer_id};
+use crate::access::{AccessControl, AuthRequest, AuthResponse, AUTH_PROTOCOL};
+use crate::ban::BanList;
+use crate::bandwidth::PeerBandwidth;
+use crate::config::{Config, ExternalAddress, LimitsConfig};
+use crate::federation::{
+    FederationRegistry, LookupRequest, LookupResponse, ProbeRequest, ProbeResponse, RegistrationInfo, RelayState,
+    FEDERATION_PROTOCOL, LOOKUP_PROTOCOL, PROBE_PROTOCOL,
+};
+use crate::ip_limiter::{inbound_ip, multiaddr_ip, IpLimiter};
+use crate::metrics::{LogLevel, Metrics, PersistedMetrics, ServerStatus, truncate_peer_id};
+use crate::quota::QuotaTracker;
+use crate::version::{self, Version, MIN_CLIENT_PREFIX};
+use crate::{shutdown, signaling, stun, web};
+use futures::future::Either;
 use futures::StreamExt;
+use libp2p::core::{muxing::StreamMuxerBox, transport::ListenerId, upgrade};
+use libp2p::multiaddr::Protocol;
+use libp2p::request_response::{self, ProtocolSupport};
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::swarm::ConnectionId;
 use libp2p::{
-    identify, identity, kad, noise, ping, relay, swarm::NetworkBehaviour, swarm::SwarmEvent, tcp,
-    yamux, Multiaddr, PeerId, StreamProtocol, Swarm,
+    identify, identity, kad, noise, ping, quic, relay, rendezvous, swarm::NetworkBehaviour, swarm::SwarmEvent,
+    tcp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm, Transport,
 };
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::num::NonZeroU32;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tracing::{info, warn};
 
-/// Default keypair file name
-const KEYPAIR_FILE: &str = "keypair.bin";
-
-/// How long to wait for a peer to identify before disconnecting
-const IDENTIFY_TIMEOUT_SECS: u64 = 30;
-
 /// Required protocol prefix for Cider clients
 const CIDER_PROTOCOL_PREFIX: &str = "cider";
 
+/// Agent string field carrying the relay's region
+const REGION_PREFIX: &str = "region/";
+
+/// Clients that must report the same observed IP before it's used as our public address
+const OBSERVED_IP_CONFIRMATIONS: usize = 3;
+
+/// How long a federated relay gets to dial us back on QUIC
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Combined behaviour for the relay server
 #[derive(NetworkBehaviour)]
 pub struct RelayServerBehaviour {
@@ -32,6 +56,16 @@ pub struct RelayServerBehaviour {
     pub relay: relay::Behaviour,
     pub identify: identify::Behaviour,
     pub kademlia: kad::Behaviour<kad::store::MemoryStore>,
+    /// Rendezvous point for room discovery (optional)
+    pub rendezvous: Toggle<rendezvous::server::Behaviour>,
+    /// State exchange with federated relays
+    pub federation: request_response::json::Behaviour<RelayState, RelayState>,
+    /// Namespace lookups from clients (local + federated)
+    pub lookup: request_response::json::Behaviour<LookupRequest, LookupResponse>,
+    /// Token handshake from clients of a private relay
+    pub auth: request_response::json::Behaviour<AuthRequest, AuthResponse>,
+    /// QUIC dial-back checks between federated relays
+    pub probe: request_response::json::Behaviour<ProbeRequest, ProbeResponse>,
 }
 
 /// Events sent from network to dashboard
@@ -41,30 +75,26 @@ pub enum NetworkEvent {
     Ready { peer_id: String },
     PublicIp(Option<String>),
     PortCheck(bool),
+    /// Drain finished, the network loop has exited
+    Stopped,
 }
 
-/// Get the path to the keypair file
-fn get_keypair_path() -> PathBuf {
-    // Check for custom path via env var
-    if let Ok(path) = std::env::var("KEYPAIR_PATH") {
-        return PathBuf::from(path);
-    }
-
-    // Default: same directory as executable, or current dir
-    std::env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(KEYPAIR_FILE)
+/// Commands sent from dashboard to network
+#[derive(Debug)]
+pub enum NetworkCommand {
+    /// Start a graceful shutdown (a second one exits immediately)
+    Shutdown,
+    /// Close all connections to a peer
+    Disconnect(PeerId),
+    /// Ban a peer and the IP it connected from, then disconnect it
+    Ban(PeerId),
 }
 
 /// Load existing keypair or generate a new one
-fn load_or_create_keypair() -> Result<identity::Keypair, Box<dyn Error>> {
-    let path = get_keypair_path();
-
+fn load_or_create_keypair(path: &Path) -> Result<identity::Keypair, Box<dyn Error>> {
     if path.exists() {
         // Load existing keypair
-        let bytes = fs::read(&path)?;
+        let bytes = fs::read(path)?;
         let keypair = identity::Keypair::from_protobuf_encoding(&bytes)?;
         info!("Loaded existing keypair from {}", path.display());
         Ok(keypair)
@@ -78,24 +108,45 @@ fn load_or_create_keypair() -> Result<identity::Keypair, Box<dyn Error>> {
             fs::create_dir_all(parent)?;
         }
 
-        fs::write(&path, bytes)?;
+        fs::write(path, bytes)?;
         info!("Generated new keypair, saved to {}", path.display());
         Ok(keypair)
     }
 }
 
 /// Create and configure the swarm
-pub fn create_swarm(keypair: &identity::Keypair) -> Result<Swarm<RelayServerBehaviour>, Box<dyn Error>> {
+///
+/// TCP and QUIC are assembled by hand so every connection can be wrapped
+/// with per-peer traffic accounting.
+pub fn create_swarm(
+    keypair: &identity::Keypair,
+    bandwidth: &PeerBandwidth,
+    limits: &LimitsConfig,
+    access: &AccessControl,
+    enable_rendezvous: bool,
+    region: Option<&str>,
+    min_client_version: Option<Version>,
+) -> Result<Swarm<RelayServerBehaviour>, Box<dyn Error>> {
     let local_peer_id = keypair.public().to_peer_id();
+    let bandwidth = bandwidth.clone();
 
     let swarm = libp2p::SwarmBuilder::with_existing_identity(keypair.clone())
         .with_tokio()
-        .with_tcp(
-            tcp::Config::default().nodelay(true),
-            noise::Config::new,
-            yamux::Config::default,
-        )?
-        .with_quic()
+        .with_other_transport(|keypair| {
+            let tcp = tcp::tokio::Transport::new(tcp::Config::default().nodelay(true))
+                .upgrade(upgrade::Version::V1Lazy)
+                .authenticate(noise::Config::new(keypair)?)
+                .multiplex(yamux::Config::default());
+            let quic = quic::tokio::Transport::new(quic::Config::new(keypair));
+
+            Ok::<_, Box<dyn Error + Send + Sync>>(tcp.or_transport(quic).map(move |output, _| {
+                let (peer_id, muxer) = match output {
+                    Either::Left((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+                    Either::Right((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+                };
+                (peer_id, StreamMuxerBox::new(bandwidth.wrap(peer_id, muxer)))
+            }))
+        })?
         .with_behaviour(|keypair| {
             // Ping for keep-alive (every 15 seconds)
             let ping = ping::Behaviour::new(
@@ -104,24 +155,50 @@ pub fn create_swarm(keypair: &identity::Keypair) -> Result<Swarm<RelayServerBeha
                     .with_timeout(Duration::from_secs(20)),
             );
 
-            let relay_config = relay::Config::default();
+            let relay_config = relay_config(limits, access);
             let relay = relay::Behaviour::new(keypair.public().to_peer_id(), relay_config);
 
-            let identify = identify::Behaviour::new(identify::Config::new(
-                "/cider-relay/1.0.0".into(),
-                keypair.public(),
-            ));
+            let identify = identify::Behaviour::new(
+                identify::Config::new("/cider-relay/1.0.0".into(), keypair.public())
+                    .with_agent_version(agent_version(region, min_client_version)),
+            );
 
             let store = kad::store::MemoryStore::new(local_peer_id);
             let mut kademlia_config = kad::Config::new(StreamProtocol::new("/ipfs/kad/1.0.0"));
             kademlia_config.set_query_timeout(Duration::from_secs(60));
             let kademlia = kad::Behaviour::with_config(local_peer_id, store, kademlia_config);
 
+            let rendezvous = Toggle::from(
+                enable_rendezvous.then(|| rendezvous::server::Behaviour::new(rendezvous::server::Config::default())),
+            );
+
+            let federation = request_response::json::Behaviour::new(
+                [(FEDERATION_PROTOCOL, ProtocolSupport::Full)],
+                request_response::Config::default(),
+            );
+            let lookup = request_response::json::Behaviour::new(
+                [(LOOKUP_PROTOCOL, ProtocolSupport::Inbound)],
+                request_response::Config::default(),
+            );
+            let auth = request_response::json::Behaviour::new(
+                [(AUTH_PROTOCOL, ProtocolSupport::Inbound)],
+                request_response::Config::default(),
+            );
+            let probe = request_response::json::Behaviour::new(
+                [(PROBE_PROTOCOL, ProtocolSupport::Full)],
+                request_response::Config::default().with_request_timeout(PROBE_TIMEOUT),
+            );
+
             Ok(RelayServerBehaviour {
                 ping,
                 relay,
                 identify,
                 kademlia,
+                rendezvous,
+                federation,
+                lookup,
+                auth,
+                probe,
             })
         })?
         // Longer timeout to keep client connections alive while waiting for peers
@@ -131,12 +208,190 @@ pub fn create_swarm(keypair: &identity::Keypair) -> Result<Swarm<RelayServerBeha
     Ok(swarm)
 }
 
+/// Identify agent version, including the region tag and minimum client version if configured
+///
+/// Clients parse the `region/<tag>` field to prefer relays in their region, and
+/// `min-client/<version>` to tell the user to update instead of retrying.
+fn agent_version(region: Option<&str>, min_client_version: Option<Version>) -> String {
+    let mut agent = format!("cider-relay/{}", env!("CARGO_PKG_VERSION"));
+    if let Some(region) = region {
+        agent.push_str(&format!(" {}{}", REGION_PREFIX, region));
+    }
+    if let Some(version) = min_client_version {
+        agent.push_str(&format!(" {}{}", MIN_CLIENT_PREFIX, version));
+    }
+    agent
+}
+
+/// Build the relay protocol config from the configured limits
+///
+/// On private relays, unauthorized peers are refused by an extra limiter
+/// that checks the allow-list and completed token handshakes.
+fn relay_config(limits: &LimitsConfig, access: &AccessControl) -> relay::Config {
+    let rate = |burst: u32| NonZeroU32::new(burst).expect("validated in config");
+    let reservations = limits.reservation_rate_per_ip;
+    let circuits = limits.circuit_rate_per_ip;
+
+    // Replace libp2p's default limiters; per-peer limits keep the libp2p defaults
+    let mut config = relay::Config {
+        max_reservations: limits.max_reservations,
+        max_reservations_per_peer: limits.max_reservations_per_peer,
+        reservation_duration: Duration::from_secs(limits.reservation_duration_secs),
+        reservation_rate_limiters: Vec::new(),
+        max_circuits: limits.max_circuits,
+        max_circuits_per_peer: limits.max_circuits_per_peer,
+        max_circuit_duration: Duration::from_secs(limits.max_circuit_duration_secs),
+        max_circuit_bytes: limits.max_circuit_bytes(),
+        circuit_src_rate_limiters: Vec::new(),
+    }
+    .reservation_rate_per_peer(rate(30), Duration::from_secs(2 * 60))
+    .circuit_src_per_peer(rate(30), Duration::from_secs(2 * 60))
+    .reservation_rate_per_ip(rate(reservations.burst), Duration::from_secs(reservations.interval_secs))
+    .circuit_src_per_ip(rate(circuits.burst), Duration::from_secs(circuits.interval_secs));
+
+    // Checked first so refused requests don't use up the rate limits
+    if access.is_enabled() {
+        config.reservation_rate_limiters.insert(0, Box::new(access.relay_gate()));
+        config.circuit_src_rate_limiters.insert(0, Box::new(access.relay_gate()));
+    }
+    config
+}
+
+/// Disconnect connected peers that are (now) banned
+fn disconnect_banned(
+    swarm: &mut Swarm<RelayServerBehaviour>,
+    bans: &BanList,
+    peer_ips: &HashMap<PeerId, IpAddr>,
+    metrics: &RwLock<Metrics>,
+) {
+    let banned: Vec<PeerId> = swarm
+        .connected_peers()
+        .filter(|peer_id| bans.is_banned(peer_id, peer_ips.get(peer_id).copied()))
+        .copied()
+        .collect();
+    for peer_id in banned {
+        let short_id = truncate_peer_id(&peer_id.to_string());
+        info!("Disconnecting banned peer {}", short_id);
+        let _ = swarm.disconnect_peer_id(peer_id);
+        metrics.write().log(LogLevel::Warning, format!("Disconnected: {} (banned)", short_id));
+    }
+}
+
+/// Our external QUIC address (IPv4 or DNS preferred over IPv6)
+fn quic_external_address(swarm: &Swarm<RelayServerBehaviour>) -> Option<Multiaddr> {
+    let quic: Vec<&Multiaddr> = swarm
+        .external_addresses()
+        .filter(|addr| addr.iter().any(|p| p == Protocol::QuicV1))
+        .collect();
+    quic.iter()
+        .find(|addr| !matches!(addr.iter().next(), Some(Protocol::Ip6(_))))
+        .or(quic.first())
+        .map(|addr| (*addr).clone())
+}
+
+/// Ask a federated relay to dial us back on QUIC; returns whether a probe was sent
+///
+/// Each relay is asked at most once, so one that doesn't support the check isn't retried.
+fn request_quic_probe(swarm: &mut Swarm<RelayServerBehaviour>, peer_id: &PeerId, probed: &mut HashSet<PeerId>) -> bool {
+    if probed.contains(peer_id) {
+        return false;
+    }
+    let Some(addr) = quic_external_address(swarm) else {
+        return false;
+    };
+    probed.insert(*peer_id);
+    info!("Asking {} to check QUIC reachability of {}", truncate_peer_id(&peer_id.to_string()), addr);
+    swarm.behaviour_mut().probe.send_request(peer_id, ProbeRequest { addr: addr.to_string() });
+    true
+}
+
+/// Our state as announced to federated relays
+fn local_relay_state(
+    swarm: &Swarm<RelayServerBehaviour>,
+    reserved_peers: &HashSet<PeerId>,
+    registrations: &HashSet<(PeerId, String)>,
+) -> RelayState {
+    RelayState {
+        addrs: swarm.external_addresses().map(|a| a.to_string()).collect(),
+        reservations: reserved_peers.iter().map(|p| p.to_string()).collect(),
+        registrations: registrations
+            .iter()
+            .map(|(peer_id, namespace)| RegistrationInfo {
+                namespace: namespace.clone(),
+                peer_id: peer_id.to_string(),
+            })
+            .collect(),
+    }
+}
+
+/// Stop taking new clients and disconnect peers that aren't part of a circuit
+///
+/// Returns the deadline after which remaining circuits are cut off.
+fn start_draining(
+    swarm: &mut Swarm<RelayServerBehaviour>,
+    listeners: &[ListenerId],
+    circuits: &[(PeerId, PeerId)],
+    metrics: &RwLock<Metrics>,
+    timeout: Duration,
+) -> Instant {
+    for listener in listeners {
+        swarm.remove_listener(*listener);
+    }
+    let idle: Vec<PeerId> = swarm
+        .connected_peers()
+        .filter(|peer_id| !in_circuit(circuits, peer_id))
+        .copied()
+        .collect();
+    for peer_id in idle {
+        let _ = swarm.disconnect_peer_id(peer_id);
+    }
+
+    let msg = format!(
+        "Shutting down: waiting up to {}s for {} active circuits",
+        timeout.as_secs(),
+        circuits.len()
+    );
+    info!("{}", msg);
+    shutdown::notify_stopping(&msg);
+
+    let mut m = metrics.write();
+    m.status = ServerStatus::Draining;
+    m.log(LogLevel::Warning, msg);
+
+    Instant::now() + timeout
+}
+
+/// Room a circuit belongs to: the destination's rendezvous namespace, else the source's
+fn circuit_room<'a>(registrations: &'a HashSet<(PeerId, String)>, src: &PeerId, dst: &PeerId) -> Option<&'a str> {
+    let room_of = |peer: &PeerId| {
+        registrations
+            .iter()
+            .find(|(registered, _)| registered == peer)
+            .map(|(_, namespace)| namespace.as_str())
+    };
+    room_of(dst).or_else(|| room_of(src))
+}
+
+/// Number of peers registered in a room
+fn room_participants(registrations: &HashSet<(PeerId, String)>, namespace: &str) -> usize {
+    registrations.iter().filter(|(_, registered)| registered == namespace).count()
+}
+
+/// Whether a peer is either end of an active circuit
+fn in_circuit(circuits: &[(PeerId, PeerId)], peer_id: &PeerId) -> bool {
+    circuits.iter().any(|(src, dst)| src == peer_id || dst == peer_id)
+}
+
 /// Run the network with dashboard integration
+///
+/// Returns once a shutdown (signal or `NetworkCommand::Shutdown`) has drained.
 pub async fn run_with_dashboard(
+    config: Config,
     metrics: Arc<RwLock<Metrics>>,
     event_tx: mpsc::UnboundedSender<NetworkEvent>,
+    mut commands: mpsc::UnboundedReceiver<NetworkCommand>,
 ) -> Result<(), Box<dyn Error>> {
-    let keypair = load_or_create_keypair()?;
+    let keypair = load_or_create_keypair(&config.keypair_path())?;
     let local_peer_id = PeerId::from(keypair.public());
 
     info!("Cider Relay Server starting...");
@@ -149,17 +404,47 @@ pub async fn run_with_dashboard(
         m.log(LogLevel::Info, format!("Peer ID: {}", local_peer_id));
     }
 
-    let mut swarm = create_swarm(&keypair)?;
+    // Continue lifetime counters from the last run
+    let metrics_path = config.metrics_path();
+    match PersistedMetrics::load(&metrics_path) {
+        Ok(Some(saved)) => metrics.write().restore(saved),
+        Ok(None) => {}
+        Err(e) => warn!("Could not load metrics from {}: {}", metrics_path.display(), e),
+    }
+
+    let bandwidth = PeerBandwidth::new();
+    let access = config.access_control()?;
+    let min_client_version = config.min_client_version()?;
+    let mut swarm = create_swarm(
+        &keypair,
+        &bandwidth,
+        &config.limits,
+        &access,
+        config.rendezvous.enabled,
+        config.network.region.as_deref(),
+        min_client_version,
+    )?;
+
+    if let Some(region) = &config.network.region {
+        info!("Region: {}", region);
+        metrics.write().log(LogLevel::Info, format!("Region: {}", region));
+    }
+    if let Some(version) = min_client_version {
+        let msg = format!("Minimum client version: {}", version);
+        info!("{}", msg);
+        metrics.write().log(LogLevel::Info, msg);
+    }
 
-    // Get ports from env
-    let tcp_port = std::env::var("TCP_PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(4001u16);
-    let quic_port = std::env::var("QUIC_PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(4001u16);
+    let tcp_port = config.network.tcp_port;
+    let quic_port = config.network.quic_port;
+    let identify_timeout_secs = config.limits.identify_timeout_secs;
+    let mut ip_limiter = IpLimiter::new(
+        config.limits.max_peers_per_ip,
+        config.limits.connections_per_ip_per_minute,
+    );
+    // Connections the IP limits admitted, by address (only these were counted)
+    let mut ip_admitted: HashMap<ConnectionId, IpAddr> = HashMap::new();
+    let mut quota = QuotaTracker::new(config.limits.hourly_quota_bytes(), config.limits.daily_quota_bytes());
 
     {
         let mut m = metrics.write();
@@ -167,17 +452,50 @@ pub async fn run_with_dashboard(
         m.quic_port = quic_port;
     }
 
+    // Ask STUN servers from the QUIC port before the swarm binds it, so the
+    // reply also shows whether a NAT remaps that port. IPv4 and IPv6 run one
+    // after the other since a dual-stack [::] socket would clash with 0.0.0.0.
+    let configured_external = config.external_address()?;
+    let (stun_ipv4, stun_ipv6) = if configured_external.is_none() {
+        info!("Detecting public IP address...");
+        let stun_ipv4 = stun::public_address((Ipv4Addr::UNSPECIFIED, quic_port).into()).await;
+        let stun_ipv6 = if config.network.listen_ipv6 {
+            stun::public_address((Ipv6Addr::UNSPECIFIED, quic_port).into()).await
+        } else {
+            None
+        };
+        for mapped in [stun_ipv4, stun_ipv6].into_iter().flatten() {
+            info!("STUN: public address {}", mapped);
+            if mapped.port() != quic_port {
+                warn!(
+                    "QUIC port {} appears as {} from outside - forward UDP {} for direct QUIC connections",
+                    quic_port,
+                    mapped.port(),
+                    quic_port
+                );
+                let mut m = metrics.write();
+                m.log(LogLevel::Warning, format!("QUIC port {} is remapped to {} by a NAT", quic_port, mapped.port()));
+            }
+        }
+        let public = |addr: &SocketAddr| is_public_ip(&addr.ip());
+        (stun_ipv4.filter(public), stun_ipv6.filter(public))
+    } else {
+        (None, None)
+    };
+
     // Listen on IPv4
     let tcp_addr: Multiaddr = format!("/ip4/0.0.0.0/tcp/{}", tcp_port).parse()?;
     let quic_addr: Multiaddr = format!("/ip4/0.0.0.0/udp/{}/quic-v1", quic_port).parse()?;
-    swarm.listen_on(tcp_addr)?;
-    swarm.listen_on(quic_addr)?;
-
-    // Listen on IPv6 (if available)
-    let tcp6_addr: Multiaddr = format!("/ip6/::/tcp/{}", tcp_port).parse()?;
-    let quic6_addr: Multiaddr = format!("/ip6/::/udp/{}/quic-v1", quic_port).parse()?;
-    let _ = swarm.listen_on(tcp6_addr); // Ignore error if IPv6 not available
-    let _ = swarm.listen_on(quic6_addr);
+    let mut listeners = vec![swarm.listen_on(tcp_addr)?, swarm.listen_on(quic_addr)?];
+
+    // Listen on IPv6 (if enabled and available)
+    if config.network.listen_ipv6 {
+        let tcp6_addr: Multiaddr = format!("/ip6/::/tcp/{}", tcp_port).parse()?;
+        let quic6_addr: Multiaddr = format!("/ip6/::/udp/{}/quic-v1", quic_port).parse()?;
+        // Ignore errors if IPv6 is not available
+        listeners.extend(swarm.listen_on(tcp6_addr).ok());
+        listeners.extend(swarm.listen_on(quic6_addr).ok());
+    }
 
     // Notify ready
     let _ = event_tx.send(NetworkEvent::Ready {
@@ -189,19 +507,66 @@ pub async fn run_with_dashboard(
         m.status = ServerStatus::Running;
         m.log(LogLevel::Info, format!("Listening on TCP:{} QUIC:{}", tcp_port, quic_port));
     }
+    shutdown::notify_ready(&format!("Listening on TCP:{} QUIC:{}", tcp_port, quic_port));
 
-    // Detect public IP and add external addresses BEFORE starting event loop
+    // Use the configured external address, or detect public IP, and add external
+    // addresses BEFORE starting the event loop.
     // This ensures clients get the correct addresses when they identify us
-    info!("Detecting public IP address...");
-    if let Some(public_ip) = detect_public_ip().await {
-        info!("Public IP detected: {}", public_ip);
+    // IPv6 is detected alongside IPv4 and advertised as a second address; on
+    // IPv6-only hosts it becomes the primary one.
+    let (external, external_ipv6) = match configured_external {
+        Some(addr) => {
+            info!("Using configured external address");
+            (Some(addr), None)
+        }
+        None => {
+            // Fall back to the HTTP services where STUN got no answer
+            let detect_ipv4 = async {
+                match stun_ipv4 {
+                    Some(addr) => Some(addr.ip()),
+                    None => detect_public_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED)).await,
+                }
+            };
+            let detect_ipv6 = async {
+                match stun_ipv6 {
+                    Some(addr) => Some(addr.ip()),
+                    None if config.network.listen_ipv6 => detect_public_ip(IpAddr::V6(Ipv6Addr::UNSPECIFIED)).await,
+                    None => None,
+                }
+            };
+            match tokio::join!(detect_ipv4, detect_ipv6) {
+                (Some(ipv4), ipv6) => (Some(ExternalAddress::Ip(ipv4)), ipv6),
+                (None, ipv6) => (ipv6.map(ExternalAddress::Ip), None),
+            }
+        }
+    };
 
-        // Add external addresses so clients can see our public IP via identify
-        let tcp_external: Multiaddr = format!("/ip4/{}/tcp/{}", public_ip, tcp_port).parse()
-            .expect("valid multiaddr");
-        let quic_external: Multiaddr = format!("/ip4/{}/udp/{}/quic-v1", public_ip, quic_port).parse()
-            .expect("valid multiaddr");
+    if let Some(ipv6) = external_ipv6 {
+        let (tcp_external, quic_external) = ip_multiaddrs(ipv6, tcp_port, quic_port);
+        info!("Public IPv6 address: {}", ipv6);
+        swarm.add_external_address(tcp_external);
+        swarm.add_external_address(quic_external);
+
+        let mut m = metrics.write();
+        m.public_ipv6 = Some(ipv6.to_string());
+        m.log(LogLevel::Info, format!("Public IPv6: {}", ipv6));
+    }
+
+    if let Some(external) = external {
+        let (public_ip, tcp_external, quic_external): (String, Multiaddr, Multiaddr) = match &external {
+            ExternalAddress::Ip(ip) => {
+                let (tcp_external, quic_external) = ip_multiaddrs(*ip, tcp_port, quic_port);
+                (ip.to_string(), tcp_external, quic_external)
+            }
+            ExternalAddress::Domain(domain) => (
+                domain.clone(),
+                format!("/dns/{}/tcp/{}", domain, tcp_port).parse()?,
+                format!("/dns/{}/udp/{}/quic-v1", domain, quic_port).parse()?,
+            ),
+        };
+        info!("Public address: {}", public_ip);
 
+        // Add external addresses so clients can see our public IP via identify
         info!("Adding external TCP address: {}", tcp_external);
         swarm.add_external_address(tcp_external);
         info!("Adding external QUIC address: {}", quic_external);
@@ -240,13 +605,87 @@ pub async fn run_with_dashboard(
         let _ = event_tx.send(NetworkEvent::PublicIp(None));
     }
 
+    // Optional read-only web dashboard (for headless deployments)
+    if let Some(addr) = config.dashboard.web_address {
+        let metrics_for_web = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            if let Err(e) = web::serve(addr, metrics_for_web).await {
+                warn!("Web dashboard error: {}", e);
+            }
+        });
+        let mut m = metrics.write();
+        m.log(LogLevel::Info, format!("Web dashboard on http://{}", addr));
+    }
+
+    // Optional signaling endpoint (an alternative to ntfy.sh for finding rooms)
+    if let Some(addr) = config.signaling.address {
+        let store = Arc::new(Mutex::new(signaling::SignalingStore::new()));
+        tokio::spawn(async move {
+            if let Err(e) = signaling::serve(addr, store).await {
+                warn!("Signaling endpoint error: {}", e);
+            }
+        });
+        let mut m = metrics.write();
+        m.log(LogLevel::Info, format!("Signaling endpoint on http://{}", addr));
+    }
+
     // Track peer verification status
     // Peers must identify as Cider clients within the timeout or get disconnected
     let mut verified_peers: HashSet<PeerId> = HashSet::new();
+
+    // Rendezvous registrations currently held (peer, namespace)
+    let mut registrations: HashSet<(PeerId, String)> = HashSet::new();
+    if config.rendezvous.enabled {
+        metrics.write().log(LogLevel::Info, "Rendezvous server enabled");
+    }
     let mut pending_peers: HashMap<PeerId, Instant> = HashMap::new();
 
+    // Peers that still have to complete the token handshake (private relays)
+    let mut unauthenticated: HashMap<PeerId, Instant> = HashMap::new();
+
+    // Banned peers/IPs (re-read when the file changes) and the IP each peer connected from
+    let ban_path = config.ban_path();
+    let mut bans = BanList::load(ban_path.clone())
+        .map_err(|e| format!("Failed to load ban list {}: {}", ban_path.display(), e))?;
+    let mut peer_ips: HashMap<PeerId, IpAddr> = HashMap::new();
+
+    // Our address as observed by verified clients (used if detection failed)
+    let mut observed_ips: HashMap<IpAddr, HashSet<PeerId>> = HashMap::new();
+    if !bans.is_empty() {
+        let msg = format!("Loaded {} ban list entries from {}", bans.len(), bans.path().display());
+        info!("{}", msg);
+        metrics.write().log(LogLevel::Info, msg);
+    }
+
+    // Peers currently holding a reservation (announced to federated relays)
+    let mut reserved_peers: HashSet<PeerId> = HashSet::new();
+
+    // Relay federation; peers are (re)dialed on every sync tick
+    let federation_peers: HashMap<PeerId, Multiaddr> = config.federation_peers()?.into_iter().collect();
+    let federation_interval = Duration::from_secs(config.federation.sync_interval_secs);
+    let mut federation_registry = FederationRegistry::new(federation_interval);
+    let mut federation_sync = tokio::time::interval(federation_interval);
+    if !federation_peers.is_empty() {
+        let msg = format!("Federation: peering with {} relays", federation_peers.len());
+        info!("{}", msg);
+        metrics.write().log(LogLevel::Info, msg);
+    } else {
+        info!("No federation peers - QUIC port reachability can't be checked");
+    }
+
+    // QUIC dial-back checks: ours (each relay is asked once), and dials made for other relays
+    let mut probe_pending = false;
+    let mut probed_relays: HashSet<PeerId> = HashSet::new();
+    let mut probe_dials: HashMap<ConnectionId, request_response::ResponseChannel<ProbeResponse>> = HashMap::new();
+    let mut closing_probes: HashSet<ConnectionId> = HashSet::new();
+
     // Create interval for checking pending peer timeouts
     let mut timeout_check = tokio::time::interval(Duration::from_secs(5));
+    let mut last_check = Instant::now();
+
+    // Per-minute traffic history for the dashboard
+    let minute = Duration::from_secs(60);
+    let mut history_tick = tokio::time::interval_at(tokio::time::Instant::now() + minute, minute);
 
     {
         let mut m = metrics.write();
@@ -254,27 +693,196 @@ pub async fn run_with_dashboard(
     }
     info!("Cider-only mode enabled: peers must identify as Cider clients");
 
+    if access.is_enabled() {
+        let msg = if access.accepts_tokens() {
+            "Private mode: only allow-listed peers or clients with the access token"
+        } else {
+            "Private mode: only allow-listed peers"
+        };
+        info!("{}", msg);
+        metrics.write().log(LogLevel::Info, msg);
+    }
+
+    if quota.is_enabled() {
+        let describe = |mb: Option<u64>| mb.map_or("unlimited".to_string(), |mb| format!("{} MB", mb));
+        let msg = format!(
+            "Per-peer quotas: {} per hour, {} per day",
+            describe(config.limits.hourly_quota_mb),
+            describe(config.limits.daily_quota_mb)
+        );
+        info!("{}", msg);
+        metrics.write().log(LogLevel::Info, msg);
+    }
+
+    let limits = &config.limits;
+    let circuit_data = match limits.max_circuit_kb {
+        0 => "unlimited".to_string(),
+        kb => format!("{} KB", kb),
+    };
+    info!(
+        "Relay limits: {} reservations ({}/peer, {}s), {} circuits ({}/peer, {}s, {})",
+        limits.max_reservations,
+        limits.max_reservations_per_peer,
+        limits.reservation_duration_secs,
+        limits.max_circuits,
+        limits.max_circuits_per_peer,
+        limits.max_circuit_duration_secs,
+        circuit_data
+    );
+
+    // Graceful shutdown state
+    let mut signals = shutdown::Signals::new()?;
+    let drain_timeout = Duration::from_secs(config.shutdown.drain_timeout_secs);
+    let mut drain_deadline: Option<Instant> = None;
+    let mut circuits: Vec<(PeerId, PeerId)> = Vec::new();
+
     // Event loop
     loop {
+        // Done draining once all circuits are gone or time is up
+        if let Some(deadline) = drain_deadline {
+            if circuits.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+        }
+
         tokio::select! {
+            // SIGTERM/SIGINT: drain first, exit right away on the second signal
+            signal = signals.recv() => {
+                if drain_deadline.is_some() {
+                    warn!("Received {} again, exiting without waiting for circuits", signal);
+                    break;
+                }
+                info!("Received {}", signal);
+                drain_deadline = Some(start_draining(&mut swarm, &listeners, &circuits, &metrics, drain_timeout));
+            }
+
+            Some(command) = commands.recv() => match command {
+                NetworkCommand::Shutdown => {
+                    if drain_deadline.is_some() {
+                        break;
+                    }
+                    drain_deadline = Some(start_draining(&mut swarm, &listeners, &circuits, &metrics, drain_timeout));
+                }
+                NetworkCommand::Disconnect(peer_id) => {
+                    let short_id = truncate_peer_id(&peer_id.to_string());
+                    info!("Disconnecting peer {} (requested from dashboard)", short_id);
+                    if swarm.disconnect_peer_id(peer_id).is_ok() {
+                        metrics.write().log(LogLevel::Warning, format!("Disconnected: {} (manual)", short_id));
+                    }
+                }
+                NetworkCommand::Ban(peer_id) => {
+                    let short_id = truncate_peer_id(&peer_id.to_string());
+                    let ip = peer_ips.get(&peer_id).copied();
+                    // Still banned in memory if the file can't be written
+                    if let Err(e) = bans.ban(peer_id, ip) {
+                        warn!("Could not save ban list {}: {}", bans.path().display(), e);
+                    }
+                    info!("Banned peer {} (requested from dashboard)", short_id);
+                    metrics.write().log(LogLevel::Warning, format!("Banned: {}", short_id));
+                    disconnect_banned(&mut swarm, &bans, &peer_ips, &metrics);
+                }
+            },
+
+            // Wake up when the drain times out
+            _ = tokio::time::sleep_until(drain_deadline.unwrap_or_else(Instant::now).into()), if drain_deadline.is_some() => {}
+
             // Check for timed-out pending peers
             _ = timeout_check.tick() => {
                 let now = Instant::now();
                 let timed_out: Vec<PeerId> = pending_peers
                     .iter()
-                    .filter(|(_, connected_at)| now.duration_since(**connected_at).as_secs() > IDENTIFY_TIMEOUT_SECS)
+                    .filter(|(_, connected_at)| now.duration_since(**connected_at).as_secs() > identify_timeout_secs)
                     .map(|(peer_id, _)| *peer_id)
                     .collect();
 
                 for peer_id in timed_out {
                     pending_peers.remove(&peer_id);
                     let short_id = truncate_peer_id(&peer_id.to_string());
-                    warn!("Disconnecting peer {} - failed to identify as Cider within {}s", short_id, IDENTIFY_TIMEOUT_SECS);
+                    warn!("Disconnecting peer {} - failed to identify as Cider within {}s", short_id, identify_timeout_secs);
                     let _ = swarm.disconnect_peer_id(peer_id);
 
                     let mut m = metrics.write();
                     m.log(LogLevel::Warning, format!("Rejected: {} (identify timeout)", short_id));
                 }
+
+                let timed_out: Vec<PeerId> = unauthenticated
+                    .iter()
+                    .filter(|(_, connected_at)| now.duration_since(**connected_at).as_secs() > identify_timeout_secs)
+                    .map(|(peer_id, _)| *peer_id)
+                    .collect();
+
+                for peer_id in timed_out {
+                    unauthenticated.remove(&peer_id);
+                    let short_id = truncate_peer_id(&peer_id.to_string());
+                    warn!("Disconnecting peer {} - no valid access token within {}s", short_id, identify_timeout_secs);
+                    let _ = swarm.disconnect_peer_id(peer_id);
+
+                    let mut m = metrics.write();
+                    m.log(LogLevel::Warning, format!("Rejected: {} (not authorized)", short_id));
+                }
+
+                ip_limiter.prune(now);
+
+                // Pick up edits to the ban list file
+                match bans.reload_if_changed() {
+                    Ok(true) => {
+                        let msg = format!("Reloaded ban list ({} entries)", bans.len());
+                        info!("{}", msg);
+                        metrics.write().log(LogLevel::Info, msg);
+                        disconnect_banned(&mut swarm, &bans, &peer_ips, &metrics);
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        warn!("Could not reload ban list {}: {}", bans.path().display(), e);
+                        metrics.write().log(LogLevel::Warning, format!("Ban list not reloaded: {}", e));
+                    }
+                }
+
+                // Account traffic since the last tick and charge it against quotas
+                for traffic in bandwidth.drain() {
+                    if traffic.relayed_bytes > 0 {
+                        metrics.write().bytes_relayed_to(&traffic.peer_id.to_string(), traffic.relayed_bytes);
+                    }
+
+                    let Some(window) = quota.record(traffic.peer_id, traffic.bytes, now) else {
+                        continue;
+                    };
+                    if swarm.is_connected(&traffic.peer_id) {
+                        let short_id = truncate_peer_id(&traffic.peer_id.to_string());
+                        warn!("Disconnecting peer {} - {} quota exceeded", short_id, window);
+                        let _ = swarm.disconnect_peer_id(traffic.peer_id);
+
+                        let mut m = metrics.write();
+                        m.log(LogLevel::Warning, format!("Disconnected: {} ({} quota exceeded)", short_id, window));
+                    }
+                }
+                quota.prune(now);
+
+                metrics.write().sample_circuit_throughput(now.duration_since(last_check));
+                last_check = now;
+            }
+
+            _ = history_tick.tick() => {
+                metrics.write().roll_traffic_history();
+            }
+
+            // Exchange state with federated relays
+            _ = federation_sync.tick(), if !federation_peers.is_empty() && drain_deadline.is_none() => {
+                federation_registry.prune(Instant::now());
+                metrics.write().federated_relays = federation_registry.len();
+
+                let state = local_relay_state(&swarm, &reserved_peers, &registrations);
+                for (peer_id, addr) in &federation_peers {
+                    if swarm.is_connected(peer_id) {
+                        swarm.behaviour_mut().federation.send_request(peer_id, state.clone());
+                        // Retry the QUIC check (e.g. once the public address is known)
+                        if !probe_pending && metrics.read().quic_reachable.is_none() {
+                            probe_pending = request_quic_probe(&mut swarm, peer_id, &mut probed_relays);
+                        }
+                    } else if let Err(e) = swarm.dial(addr.clone()) {
+                        warn!("Failed to dial federation peer {}: {}", addr, e);
+                    }
+                }
             }
 
             // Handle swarm events
@@ -286,9 +894,78 @@ pub async fn run_with_dashboard(
                         m.log(LogLevel::Info, format!("Listening: {}", address));
                     }
 
-                    SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                    SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, num_established, .. } => {
                         let short_id = truncate_peer_id(&peer_id.to_string());
 
+                        // Dial-back for a federated relay: report success and hang up
+                        if let Some(channel) = probe_dials.remove(&connection_id) {
+                            info!("QUIC dial-back to {} succeeded", short_id);
+                            let response = ProbeResponse { reachable: true, error: None };
+                            let _ = swarm.behaviour_mut().probe.send_response(channel, response);
+                            closing_probes.insert(connection_id);
+                            swarm.close_connection(connection_id);
+                            continue;
+                        }
+                        let is_federation_peer = federation_peers.contains_key(&peer_id);
+
+                        // Only circuit endpoints stay connected while draining
+                        if drain_deadline.is_some() && !in_circuit(&circuits, &peer_id) {
+                            let _ = swarm.disconnect_peer_id(peer_id);
+                            continue;
+                        }
+
+                        let ip = inbound_ip(&endpoint);
+                        if bans.is_banned(&peer_id, ip) {
+                            info!("Rejecting peer {} - banned", short_id);
+                            let _ = swarm.disconnect_peer_id(peer_id);
+
+                            let mut m = metrics.write();
+                            m.log(LogLevel::Warning, format!("Rejected: {} (banned)", short_id));
+                            continue;
+                        }
+                        if let Some(ip) = ip {
+                            peer_ips.insert(peer_id, ip);
+                        }
+
+                        // Without a token, unknown peers can never be authorized
+                        if !is_federation_peer && !access.accepts_tokens() && !access.is_allowed_peer(&peer_id) {
+                            info!("Rejecting peer {} - not on allow-list", short_id);
+                            let _ = swarm.disconnect_peer_id(peer_id);
+
+                            let mut m = metrics.write();
+                            m.log(LogLevel::Warning, format!("Rejected: {} (not on allow-list)", short_id));
+                            continue;
+                        }
+
+                        // Per-IP limits (only inbound connections count)
+                        if let Some(ip) = ip {
+                            if let Err(reason) = ip_limiter.on_connection(ip, peer_id, Instant::now()) {
+                                warn!("Rejecting peer {} from {} - {}", short_id, ip, reason);
+                                // Only this connection: the peer's others were within the limits
+                                swarm.close_connection(connection_id);
+
+                                let mut m = metrics.write();
+                                m.log(LogLevel::Warning, format!("Rejected: {} ({})", short_id, reason));
+                                continue;
+                            }
+                            ip_admitted.insert(connection_id, ip);
+                        }
+
+                        // Refuse peers that used up their quota until the window resets
+                        if let Some(window) = quota.exceeded(&peer_id, Instant::now()) {
+                            info!("Rejecting peer {} - {} quota exceeded", short_id, window);
+                            let _ = swarm.disconnect_peer_id(peer_id);
+
+                            let mut m = metrics.write();
+                            m.log(LogLevel::Warning, format!("Rejected: {} ({} quota exceeded)", short_id, window));
+                            continue;
+                        }
+
+                        // Token holders get until the identify timeout to complete the handshake
+                        if !is_federation_peer && !access.is_authorized(&peer_id) {
+                            unauthenticated.entry(peer_id).or_insert(Instant::now());
+                        }
+
                         // Skip if already verified (additional transport to same peer)
                         if verified_peers.contains(&peer_id) {
                             info!("Peer connected: {} (already verified, additional transport)", short_id);
@@ -298,17 +975,43 @@ pub async fn run_with_dashboard(
                             pending_peers.entry(peer_id).or_insert(Instant::now());
                         }
 
+                        // Announce our state right away instead of waiting for the next sync
+                        if is_federation_peer && num_established.get() == 1 {
+                            info!("Federation peer connected: {}", short_id);
+                            metrics.write().log(LogLevel::Info, format!("Federation: connected to {}", short_id));
+                            let state = local_relay_state(&swarm, &reserved_peers, &registrations);
+                            swarm.behaviour_mut().federation.send_request(&peer_id, state);
+
+                            if !probe_pending && metrics.read().quic_reachable.is_none() {
+                                probe_pending = request_quic_probe(&mut swarm, &peer_id, &mut probed_relays);
+                            }
+                        }
+
                         let mut m = metrics.write();
                         m.connection_established(peer_id.to_string(), None);
                     }
 
-                    SwarmEvent::ConnectionClosed { peer_id, .. } => {
+                    SwarmEvent::ConnectionClosed { peer_id, connection_id, num_established, .. } => {
+                        if closing_probes.remove(&connection_id) {
+                            continue;
+                        }
                         let short_id = truncate_peer_id(&peer_id.to_string());
                         info!("Peer disconnected: {}", short_id);
 
+                        // Connections rejected before the IP limits were never counted
+                        if let Some(ip) = ip_admitted.remove(&connection_id) {
+                            ip_limiter.on_connection_closed(ip, &peer_id);
+                        }
+
                         // Clean up tracking
                         verified_peers.remove(&peer_id);
                         pending_peers.remove(&peer_id);
+                        if num_established == 0 {
+                            reserved_peers.remove(&peer_id);
+                            unauthenticated.remove(&peer_id);
+                            access.forget(&peer_id);
+                            peer_ips.remove(&peer_id);
+                        }
 
                         let mut m = metrics.write();
                         m.connection_closed(&peer_id.to_string());
@@ -326,8 +1029,20 @@ pub async fn run_with_dashboard(
                         } else {
                             info!("Relay reservation accepted: {} (pending verification)", short_id);
                         }
+                        reserved_peers.insert(src_peer_id);
                         let mut m = metrics.write();
                         m.reservation_accepted(&src_peer_id.to_string());
+
+                        // No new reservations while draining
+                        if drain_deadline.is_some() && !in_circuit(&circuits, &src_peer_id) {
+                            let _ = swarm.disconnect_peer_id(src_peer_id);
+                        }
+                    }
+
+                    SwarmEvent::Behaviour(RelayServerBehaviourEvent::Relay(
+                        relay::Event::ReservationTimedOut { src_peer_id },
+                    )) => {
+                        reserved_peers.remove(&src_peer_id);
                     }
 
                     SwarmEvent::Behaviour(RelayServerBehaviourEvent::Relay(
@@ -340,16 +1055,228 @@ pub async fn run_with_dashboard(
                         let src_short = truncate_peer_id(&src_peer_id.to_string());
                         let dst_short = truncate_peer_id(&dst_peer_id.to_string());
                         info!("Relay circuit: {} -> {}", src_short, dst_short);
+                        circuits.push((src_peer_id, dst_peer_id));
+                        let room = circuit_room(&registrations, &src_peer_id, &dst_peer_id);
                         let mut m = metrics.write();
-                        m.circuit_established(&src_peer_id.to_string(), &dst_peer_id.to_string());
+                        m.circuit_established(&src_peer_id.to_string(), &dst_peer_id.to_string(), room);
                     }
 
                     SwarmEvent::Behaviour(RelayServerBehaviourEvent::Relay(
-                        relay::Event::CircuitClosed { .. },
+                        relay::Event::CircuitClosed {
+                            src_peer_id,
+                            dst_peer_id,
+                            ..
+                        },
                     )) => {
                         info!("Relay circuit closed");
+                        if let Some(pos) = circuits.iter().position(|c| *c == (src_peer_id, dst_peer_id)) {
+                            circuits.remove(pos);
+                        }
+                        let mut m = metrics.write();
+                        m.circuit_closed(&src_peer_id.to_string(), &dst_peer_id.to_string());
+                    }
+
+                    SwarmEvent::Behaviour(RelayServerBehaviourEvent::Relay(
+                        relay::Event::ReservationReqDenied { src_peer_id, .. },
+                    )) => {
+                        let short_id = truncate_peer_id(&src_peer_id.to_string());
+                        let reason = if access.is_authorized(&src_peer_id) { "limit reached" } else { "not authorized" };
+                        warn!("Relay reservation denied: {} ({})", short_id, reason);
+                        let mut m = metrics.write();
+                        m.log(LogLevel::Warning, format!("Reservation denied: {} ({})", short_id, reason));
+                    }
+
+                    SwarmEvent::Behaviour(RelayServerBehaviourEvent::Relay(
+                        relay::Event::CircuitReqDenied { src_peer_id, dst_peer_id, .. },
+                    )) => {
+                        let src_short = truncate_peer_id(&src_peer_id.to_string());
+                        let dst_short = truncate_peer_id(&dst_peer_id.to_string());
+                        let reason = if access.is_authorized(&src_peer_id) { "limit reached" } else { "not authorized" };
+                        warn!("Relay circuit denied: {} -> {} ({})", src_short, dst_short, reason);
                         let mut m = metrics.write();
-                        m.circuit_closed();
+                        m.log(LogLevel::Warning, format!("Circuit denied: {} -> {} ({})", src_short, dst_short, reason));
+                    }
+
+                    SwarmEvent::Behaviour(RelayServerBehaviourEvent::Rendezvous(event)) => {
+                        match event {
+                            rendezvous::server::Event::PeerRegistered { peer, registration } => {
+                                let short_id = truncate_peer_id(&peer.to_string());
+                                let namespace = registration.namespace.to_string();
+                                info!("Rendezvous registration: {} in '{}' (ttl {}s)", short_id, namespace, registration.ttl);
+                                if registrations.insert((peer, namespace.clone())) {
+                                    let mut m = metrics.write();
+                                    m.rendezvous_registrations = registrations.len();
+                                    m.set_room_participants(&namespace, room_participants(&registrations, &namespace));
+                                    m.log(LogLevel::Relay, format!("Registered: {} in '{}'", short_id, namespace));
+                                }
+                            }
+                            rendezvous::server::Event::PeerNotRegistered { peer, namespace, error } => {
+                                let short_id = truncate_peer_id(&peer.to_string());
+                                warn!("Rendezvous registration rejected: {} in '{}': {:?}", short_id, namespace, error);
+                            }
+                            rendezvous::server::Event::PeerUnregistered { peer, namespace } => {
+                                let namespace = namespace.to_string();
+                                registrations.remove(&(peer, namespace.clone()));
+                                let mut m = metrics.write();
+                                m.rendezvous_registrations = registrations.len();
+                                m.set_room_participants(&namespace, room_participants(&registrations, &namespace));
+                            }
+                            rendezvous::server::Event::RegistrationExpired(registration) => {
+                                let peer = registration.record.peer_id();
+                                let namespace = registration.namespace.to_string();
+                                registrations.remove(&(peer, namespace.clone()));
+                                let mut m = metrics.write();
+                                m.rendezvous_registrations = registrations.len();
+                                m.set_room_participants(&namespace, room_participants(&registrations, &namespace));
+                            }
+                            rendezvous::server::Event::DiscoverServed { enquirer, registrations: served } => {
+                                info!(
+                                    "Rendezvous discovery: {} got {} registrations",
+                                    truncate_peer_id(&enquirer.to_string()),
+                                    served.len()
+                                );
+                            }
+                            rendezvous::server::Event::DiscoverNotServed { enquirer, error } => {
+                                warn!("Rendezvous discovery failed for {}: {:?}", truncate_peer_id(&enquirer.to_string()), error);
+                            }
+                        }
+                    }
+
+                    SwarmEvent::Behaviour(RelayServerBehaviourEvent::Federation(event)) => {
+                        match event {
+                            request_response::Event::Message { peer, message, .. } => {
+                                // Only configured relays may announce state
+                                if !federation_peers.contains_key(&peer) {
+                                    warn!("Ignoring federation message from unknown peer {}", truncate_peer_id(&peer.to_string()));
+                                    continue;
+                                }
+                                match message {
+                                    request_response::Message::Request { request, channel, .. } => {
+                                        federation_registry.update(peer, request, Instant::now());
+                                        let state = local_relay_state(&swarm, &reserved_peers, &registrations);
+                                        let _ = swarm.behaviour_mut().federation.send_response(channel, state);
+                                    }
+                                    request_response::Message::Response { response, .. } => {
+                                        federation_registry.update(peer, response, Instant::now());
+                                    }
+                                }
+                                metrics.write().federated_relays = federation_registry.len();
+                            }
+                            request_response::Event::OutboundFailure { peer, error, .. } => {
+                                warn!("Federation sync with {} failed: {}", truncate_peer_id(&peer.to_string()), error);
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    SwarmEvent::OutgoingConnectionError { connection_id, peer_id, error } => {
+                        if let Some(channel) = probe_dials.remove(&connection_id) {
+                            let short_id = peer_id.map(|p| truncate_peer_id(&p.to_string())).unwrap_or_default();
+                            info!("QUIC dial-back to {} failed: {}", short_id, error);
+                            let response = ProbeResponse { reachable: false, error: Some(error.to_string()) };
+                            let _ = swarm.behaviour_mut().probe.send_response(channel, response);
+                        }
+                    }
+
+                    SwarmEvent::Behaviour(RelayServerBehaviourEvent::Probe(event)) => {
+                        match event {
+                            request_response::Event::Message {
+                                peer,
+                                message: request_response::Message::Request { request, channel, .. },
+                                ..
+                            } => {
+                                // Only configured relays may make us dial out; dropping the channel refuses
+                                if !federation_peers.contains_key(&peer) {
+                                    continue;
+                                }
+                                let addr = match request.addr.parse::<Multiaddr>() {
+                                    Ok(addr) if addr.iter().any(|p| p == Protocol::QuicV1) => addr,
+                                    _ => continue,
+                                };
+                                let opts = DialOpts::peer_id(peer)
+                                    .addresses(vec![addr])
+                                    .condition(PeerCondition::Always)
+                                    .build();
+                                let connection_id = opts.connection_id();
+                                match swarm.dial(opts) {
+                                    Ok(()) => {
+                                        probe_dials.insert(connection_id, channel);
+                                    }
+                                    Err(e) => {
+                                        let response = ProbeResponse { reachable: false, error: Some(e.to_string()) };
+                                        let _ = swarm.behaviour_mut().probe.send_response(channel, response);
+                                    }
+                                }
+                            }
+                            request_response::Event::Message {
+                                peer,
+                                message: request_response::Message::Response { response, .. },
+                                ..
+                            } => {
+                                probe_pending = false;
+                                let short_id = truncate_peer_id(&peer.to_string());
+                                let mut m = metrics.write();
+                                m.quic_reachable = Some(response.reachable);
+                                if response.reachable {
+                                    info!("QUIC port {} is reachable (checked by {})", quic_port, short_id);
+                                    m.log(LogLevel::Info, format!("QUIC port {} is reachable", quic_port));
+                                } else {
+                                    let reason = response.error.unwrap_or_else(|| "unknown error".to_string());
+                                    warn!(
+                                        "QUIC port {} is NOT reachable (checked by {}: {}) - check that UDP is open",
+                                        quic_port, short_id, reason
+                                    );
+                                    m.log(LogLevel::Warning, format!("QUIC port {} NOT reachable - check UDP firewall", quic_port));
+                                }
+                            }
+                            request_response::Event::OutboundFailure { peer, error, .. } => {
+                                probe_pending = false;
+                                warn!("QUIC check via {} failed: {}", truncate_peer_id(&peer.to_string()), error);
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    SwarmEvent::Behaviour(RelayServerBehaviourEvent::Lookup(request_response::Event::Message {
+                        peer,
+                        message: request_response::Message::Request { request, channel, .. },
+                        ..
+                    })) => {
+                        // Only verified clients may look up rooms; dropping the channel refuses the request
+                        if !verified_peers.contains(&peer) {
+                            continue;
+                        }
+                        let mut peers = local_relay_state(&swarm, &reserved_peers, &registrations)
+                            .locations(&local_peer_id, &request.namespace);
+                        peers.extend(federation_registry.lookup(&request.namespace));
+                        info!(
+                            "Lookup: {} asked for '{}' ({} peers)",
+                            truncate_peer_id(&peer.to_string()),
+                            request.namespace,
+                            peers.len()
+                        );
+                        let _ = swarm.behaviour_mut().lookup.send_response(channel, LookupResponse { peers });
+                    }
+
+                    SwarmEvent::Behaviour(RelayServerBehaviourEvent::Auth(request_response::Event::Message {
+                        peer,
+                        message: request_response::Message::Request { request, channel, .. },
+                        ..
+                    })) => {
+                        let short_id = truncate_peer_id(&peer.to_string());
+                        let response = access.handshake(peer, &local_peer_id, &request.proof);
+                        let accepted = response.accepted;
+                        let _ = swarm.behaviour_mut().auth.send_response(channel, response);
+
+                        // Rejected peers are left to the handshake timeout so the response gets delivered
+                        if accepted {
+                            unauthenticated.remove(&peer);
+                            info!("Authenticated peer: {}", short_id);
+                            metrics.write().log(LogLevel::Info, format!("Authenticated: {}", short_id));
+                        } else {
+                            warn!("Invalid access token from {}", short_id);
+                            metrics.write().log(LogLevel::Warning, format!("Invalid token: {}", short_id));
+                        }
                     }
 
                     SwarmEvent::Behaviour(RelayServerBehaviourEvent::Identify(
@@ -363,11 +1290,50 @@ pub async fn run_with_dashboard(
                             continue;
                         }
 
+                        // Refuse builds older than the configured minimum (federated relays aren't clients)
+                        if is_cider && !federation_peers.contains_key(&peer_id) {
+                            if let Some(min) = min_client_version {
+                                let client = version::client_version(&info.agent_version);
+                                if client.is_none_or(|v| v < min) {
+                                    pending_peers.remove(&peer_id);
+                                    let reported = client.map_or("unknown".to_string(), |v| v.to_string());
+                                    warn!(
+                                        "Rejecting peer {} - client version {} is older than the minimum {}",
+                                        short_id, reported, min
+                                    );
+                                    let _ = swarm.disconnect_peer_id(peer_id);
+
+                                    let mut m = metrics.write();
+                                    m.log(LogLevel::Warning, format!("Rejected: {} (client {} < {})", short_id, reported, min));
+                                    continue;
+                                }
+                            }
+                        }
+
                         if is_cider {
                             // Verified as Cider client
                             pending_peers.remove(&peer_id);
                             verified_peers.insert(peer_id);
 
+                            // Without a detected public IP, use what enough clients see us as
+                            if metrics.read().public_ip.is_none() {
+                                if let Some(ip) = multiaddr_ip(&info.observed_addr).filter(is_public_ip) {
+                                    let reporters = observed_ips.entry(ip).or_default();
+                                    reporters.insert(peer_id);
+                                    if reporters.len() >= OBSERVED_IP_CONFIRMATIONS {
+                                        info!("Public address from identify: {} (reported by {} clients)", ip, reporters.len());
+                                        let (tcp_external, quic_external) = ip_multiaddrs(ip, tcp_port, quic_port);
+                                        swarm.add_external_address(tcp_external);
+                                        swarm.add_external_address(quic_external);
+                                        observed_ips.clear();
+
+                                        let mut m = metrics.write();
+                                        m.public_ip = Some(ip.to_string());
+                                        m.log(LogLevel::Info, format!("Public IP: {} (observed by clients)", ip));
+                                    }
+                                }
+                            }
+
                             info!("Verified Cider peer: {} ({})", short_id, info.protocol_version);
                             let mut m = metrics.write();
                             m.peer_identified(&peer_id.to_string(), info.protocol_version.clone());
@@ -390,44 +1356,52 @@ pub async fn run_with_dashboard(
             }
         }
     }
+
+    if !circuits.is_empty() {
+        warn!("Closing {} circuits that didn't finish in time", circuits.len());
+    }
+
+    // Keep lifetime counters for the next run
+    match metrics.read().persisted().save(&metrics_path) {
+        Ok(()) => info!("Saved metrics to {}", metrics_path.display()),
+        Err(e) => warn!("Could not save metrics to {}: {}", metrics_path.display(), e),
+    }
+
+    info!("Relay stopped");
+    let _ = event_tx.send(NetworkEvent::Stopped);
+    Ok(())
 }
 
 /// Run with plain logging (no dashboard)
-pub async fn run_with_logging(metrics: Arc<RwLock<Metrics>>) -> Result<(), Box<dyn Error>> {
-    // Initialize tracing for logging mode
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("cider_relay=info".parse()?)
-                .add_directive("libp2p_relay=info".parse()?)
-                .add_directive("libp2p_kad=warn".parse()?)
-                .add_directive("libp2p_identify=warn".parse()?),
-        )
-        .init();
-
+pub async fn run_with_logging(config: Config, metrics: Arc<RwLock<Metrics>>) -> Result<(), Box<dyn Error>> {
     let (tx, _rx) = mpsc::unbounded_channel();
-    run_with_dashboard(metrics, tx).await
+    let (_command_tx, command_rx) = mpsc::unbounded_channel();
+    run_with_dashboard(config, metrics, tx, command_rx).await
 }
 
-/// Detect public IP address using external services
-async fn detect_public_ip() -> Option<String> {
+/// Detect the public address of one IP family using external services
+///
+/// The client is bound to `local` (0.0.0.0 or ::), which forces requests
+/// over that family, so the dual-stack services report the matching address.
+async fn detect_public_ip(local: IpAddr) -> Option<IpAddr> {
     let client = reqwest::Client::builder()
         .timeout(Duration::from_secs(10))
+        .local_address(local)
         .build()
         .ok()?;
 
     let services = [
-        "https://api.ipify.org",
+        "https://api64.ipify.org",
         "https://ifconfig.me/ip",
         "https://icanhazip.com",
     ];
 
     for service in services {
         if let Ok(resp) = client.get(service).send().await {
-            if let Ok(ip) = resp.text().await {
-                let ip = ip.trim().to_string();
-                if ip.contains('.') && ip.len() <= 15 && ip.chars().all(|c| c.is_ascii_digit() || c == '.') {
-                    return Some(ip);
+            if let Ok(text) = resp.text().await {
+                match text.trim().parse::<IpAddr>() {
+                    Ok(ip) if ip.is_ipv4() == local.is_ipv4() && is_public_ip(&ip) => return Some(ip),
+                    _ => {}
                 }
             }
         }
@@ -435,6 +1409,26 @@ async fn detect_public_ip() -> Option<String> {
     None
 }
 
+/// Whether an address can be reached from the internet
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified()),
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            // Excludes unique local (fc00::/7) and link-local (fe80::/10) addresses
+            !(ip.is_loopback() || ip.is_unspecified() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+/// External TCP and QUIC addresses for a public IP
+fn ip_multiaddrs(ip: IpAddr, tcp_port: u16, quic_port: u16) -> (Multiaddr, Multiaddr) {
+    (
+        Multiaddr::from(ip).with(Protocol::Tcp(tcp_port)),
+        Multiaddr::from(ip).with(Protocol::Udp(quic_port)).with(Protocol::QuicV1),
+    )
+}
+
 /// Check if a port is reachable from the internet
 async fn check_port_reachable(ip: &str, port: u16) -> bool {
     let client = match reqwest::Client::builder()
@@ -0,0 +1,171 @@
+//! Per-peer bandwidth quotas
+//!
+//! Usage is tracked in fixed hourly and daily windows that start with the
+//! peer's first counted byte. Peers over either limit are disconnected and
+//! refused until the exceeded window resets.
+
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Which quota a peer ran over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaWindow {
+    Hourly,
+    Daily,
+}
+
+impl fmt::Display for QuotaWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaWindow::Hourly => write!(f, "hourly"),
+            QuotaWindow::Daily => write!(f, "daily"),
+        }
+    }
+}
+
+/// Bytes used within one fixed window
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    started: Instant,
+    bytes: u64,
+}
+
+impl Window {
+    fn new(now: Instant) -> Self {
+        Self { started: now, bytes: 0 }
+    }
+
+    /// Bytes used in the current window (0 once it has expired)
+    fn used(&self, length: Duration, now: Instant) -> u64 {
+        if now.duration_since(self.started) >= length {
+            0
+        } else {
+            self.bytes
+        }
+    }
+
+    fn add(&mut self, bytes: u64, length: Duration, now: Instant) {
+        if now.duration_since(self.started) >= length {
+            *self = Self::new(now);
+        }
+        self.bytes = self.bytes.saturating_add(bytes);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PeerUsage {
+    hour: Window,
+    day: Window,
+}
+
+/// Tracks per-peer usage against the configured limits
+#[derive(Debug)]
+pub struct QuotaTracker {
+    hourly_limit: Option<u64>,
+    daily_limit: Option<u64>,
+    usage: HashMap<PeerId, PeerUsage>,
+}
+
+impl QuotaTracker {
+    /// Create a tracker (limits in bytes, None = unlimited)
+    pub fn new(hourly_limit: Option<u64>, daily_limit: Option<u64>) -> Self {
+        Self {
+            hourly_limit,
+            daily_limit,
+            usage: HashMap::new(),
+        }
+    }
+
+    /// Whether any quota is configured
+    pub fn is_enabled(&self) -> bool {
+        self.hourly_limit.is_some() || self.daily_limit.is_some()
+    }
+
+    /// Add traffic for a peer; returns the exceeded quota, if any
+    pub fn record(&mut self, peer_id: PeerId, bytes: u64, now: Instant) -> Option<QuotaWindow> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let usage = self.usage.entry(peer_id).or_insert(PeerUsage {
+            hour: Window::new(now),
+            day: Window::new(now),
+        });
+        usage.hour.add(bytes, HOUR, now);
+        usage.day.add(bytes, DAY, now);
+        self.exceeded(&peer_id, now)
+    }
+
+    /// Check whether a peer is currently over quota
+    pub fn exceeded(&self, peer_id: &PeerId, now: Instant) -> Option<QuotaWindow> {
+        let usage = self.usage.get(peer_id)?;
+        if self.daily_limit.is_some_and(|limit| usage.day.used(DAY, now) >= limit) {
+            return Some(QuotaWindow::Daily);
+        }
+        if self.hourly_limit.is_some_and(|limit| usage.hour.used(HOUR, now) >= limit) {
+            return Some(QuotaWindow::Hourly);
+        }
+        None
+    }
+
+    /// Forget peers whose windows have all expired
+    pub fn prune(&mut self, now: Instant) {
+        self.usage
+            .retain(|_, usage| usage.hour.used(HOUR, now) > 0 || usage.day.used(DAY, now) > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_never_exceeds() {
+        let mut quota = QuotaTracker::new(None, None);
+        assert!(!quota.is_enabled());
+        assert_eq!(quota.record(PeerId::random(), u64::MAX, Instant::now()), None);
+    }
+
+    #[test]
+    fn test_hourly_quota_resets() {
+        let mut quota = QuotaTracker::new(Some(1000), None);
+        let peer = PeerId::random();
+        let start = Instant::now();
+
+        assert_eq!(quota.record(peer, 600, start), None);
+        assert_eq!(quota.record(peer, 600, start + Duration::from_secs(60)), Some(QuotaWindow::Hourly));
+        assert_eq!(quota.exceeded(&peer, start + Duration::from_secs(120)), Some(QuotaWindow::Hourly));
+
+        // New hour, clean slate
+        assert_eq!(quota.exceeded(&peer, start + HOUR), None);
+        assert_eq!(quota.record(peer, 600, start + HOUR), None);
+    }
+
+    #[test]
+    fn test_daily_quota_outlasts_hourly() {
+        let mut quota = QuotaTracker::new(Some(1000), Some(1500));
+        let peer = PeerId::random();
+        let start = Instant::now();
+
+        assert_eq!(quota.record(peer, 900, start), None);
+        assert_eq!(quota.record(peer, 900, start + HOUR), Some(QuotaWindow::Daily));
+        assert_eq!(quota.exceeded(&peer, start + HOUR * 2), Some(QuotaWindow::Daily));
+        assert_eq!(quota.exceeded(&peer, start + DAY), None);
+    }
+
+    #[test]
+    fn test_prune_expired_peers() {
+        let mut quota = QuotaTracker::new(Some(1000), None);
+        let start = Instant::now();
+        quota.record(PeerId::random(), 10, start);
+
+        quota.prune(start + Duration::from_secs(60));
+        assert_eq!(quota.usage.len(), 1);
+        quota.prune(start + DAY);
+        assert!(quota.usage.is_empty());
+    }
+}
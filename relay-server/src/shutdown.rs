@@ -0,0 +1,64 @@
+//! Shutdown signals and systemd notifications
+//!
+//! SIGTERM/SIGINT start a graceful drain (see `network.rs`); a second signal
+//! exits right away. systemd is told when the relay is ready and when it
+//! starts stopping (`Type=notify`); outside systemd the notifications are
+//! no-ops.
+
+use std::io;
+#[cfg(unix)]
+use tracing::debug;
+
+/// Listens for termination signals
+pub struct Signals {
+    #[cfg(unix)]
+    sigterm: tokio::signal::unix::Signal,
+}
+
+impl Signals {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            sigterm: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?,
+        })
+    }
+
+    /// Wait for the next SIGTERM or SIGINT; returns the signal name
+    pub async fn recv(&mut self) -> &'static str {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.sigterm.recv() => "SIGTERM",
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = tokio::signal::ctrl_c().await;
+            "Ctrl+C"
+        }
+    }
+}
+
+/// Tell systemd we're up and serving
+pub fn notify_ready(status: &str) {
+    #[cfg(unix)]
+    notify(&[sd_notify::NotifyState::Ready, sd_notify::NotifyState::Status(status)]);
+    #[cfg(not(unix))]
+    let _ = status;
+}
+
+/// Tell systemd we're draining
+pub fn notify_stopping(status: &str) {
+    #[cfg(unix)]
+    notify(&[sd_notify::NotifyState::Stopping, sd_notify::NotifyState::Status(status)]);
+    #[cfg(not(unix))]
+    let _ = status;
+}
+
+#[cfg(unix)]
+fn notify(states: &[sd_notify::NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, states) {
+        debug!("sd_notify failed: {}", e);
+    }
+}
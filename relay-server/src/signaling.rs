@@ -0,0 +1,351 @@
+//! Signaling endpoint for room discovery
+//!
+//! Clients find a room's host by publishing and polling its addresses on a
+//! signaling server, ntfy.sh by default. Groups running their own relay can
+//! have it serve the same over plain HTTP instead, so discovery doesn't
+//! depend on ntfy.sh's availability or rate limits. Routes:
+//!   POST /signaling/<topic>  - publish a message (a JSON object)
+//!   GET  /signaling/<topic>  - messages published in the last few minutes, as a JSON array
+//!
+//! Messages are only kept in memory, for `MESSAGE_TTL`. Each address may make
+//! `MAX_REQUESTS_PER_IP_PER_MINUTE` requests (loopback isn't limited).
+
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+/// How long published messages are kept (clients poll for the last 5 minutes)
+const MESSAGE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Messages kept per topic (oldest are dropped)
+const MAX_MESSAGES_PER_TOPIC: usize = 16;
+
+/// Topics kept at once, so the relay can't be used as free storage
+const MAX_TOPICS: usize = 4096;
+
+/// Longest topic name accepted
+const MAX_TOPIC_LEN: usize = 64;
+
+/// Largest request accepted (headers and message)
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+
+/// Longest a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Requests accepted from one address a minute (a joiner polls a room every
+/// 2s, and may look up friends' presence besides)
+const MAX_REQUESTS_PER_IP_PER_MINUTE: usize = 120;
+
+/// Window for the request rate limit
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Why a message wasn't stored
+#[derive(Debug, PartialEq, Eq)]
+pub enum PublishError {
+    /// Not a JSON object
+    InvalidMessage,
+    /// Too many topics in use
+    Full,
+}
+
+/// Recent messages by topic
+#[derive(Default)]
+pub struct SignalingStore {
+    topics: HashMap<String, VecDeque<(Instant, String)>>,
+}
+
+pub type SharedSignalingStore = Arc<Mutex<SignalingStore>>;
+
+impl SignalingStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `message` on `topic`
+    pub fn publish(&mut self, topic: &str, message: &str, now: Instant) -> Result<(), PublishError> {
+        let is_object = serde_json::from_str::<serde_json::Value>(message).is_ok_and(|v| v.is_object());
+        if !is_object {
+            return Err(PublishError::InvalidMessage);
+        }
+        self.prune(now);
+        if !self.topics.contains_key(topic) && self.topics.len() >= MAX_TOPICS {
+            return Err(PublishError::Full);
+        }
+
+        let messages = self.topics.entry(topic.to_string()).or_default();
+        if messages.len() >= MAX_MESSAGES_PER_TOPIC {
+            messages.pop_front();
+        }
+        messages.push_back((now, message.to_string()));
+        Ok(())
+    }
+
+    /// Messages on `topic` that haven't expired, oldest first
+    pub fn messages(&mut self, topic: &str, now: Instant) -> Vec<String> {
+        self.prune(now);
+        self.topics
+            .get(topic)
+            .map(|messages| messages.iter().map(|(_, m)| m.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Topics with messages
+    pub fn topic_count(&self) -> usize {
+        self.topics.len()
+    }
+
+    fn prune(&mut self, now: Instant) {
+        self.topics.retain(|_, messages| {
+            messages.retain(|(at, _)| now.saturating_duration_since(*at) < MESSAGE_TTL);
+            !messages.is_empty()
+        });
+    }
+}
+
+/// Recent requests per remote address
+#[derive(Default)]
+struct RequestLimiter {
+    recent: HashMap<IpAddr, VecDeque<Instant>>,
+    last_pruned: Option<Instant>,
+}
+
+impl RequestLimiter {
+    /// Count a request from `ip`; false if it's past the rate limit
+    fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+        if ip.is_loopback() {
+            return true;
+        }
+        if self.last_pruned.is_none_or(|at| now.saturating_duration_since(at) >= RATE_WINDOW) {
+            self.recent
+                .retain(|_, times| times.back().is_some_and(|t| now.saturating_duration_since(*t) < RATE_WINDOW));
+            self.last_pruned = Some(now);
+        }
+
+        let recent = self.recent.entry(ip).or_default();
+        while recent.front().is_some_and(|t| now.saturating_duration_since(*t) >= RATE_WINDOW) {
+            recent.pop_front();
+        }
+        if recent.len() >= MAX_REQUESTS_PER_IP_PER_MINUTE {
+            return false;
+        }
+        recent.push_back(now);
+        true
+    }
+}
+
+/// Serve the signaling endpoint until the listener fails
+pub async fn serve(addr: SocketAddr, store: SharedSignalingStore) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Signaling endpoint listening on http://{}", addr);
+    serve_on(listener, store).await
+}
+
+async fn serve_on(listener: TcpListener, store: SharedSignalingStore) -> std::io::Result<()> {
+    let mut limiter = RequestLimiter::default();
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let allowed = limiter.allow(remote.ip(), Instant::now());
+        let store = Arc::clone(&store);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &store, allowed).await {
+                debug!("Signaling request from {} failed: {}", remote, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, store: &SharedSignalingStore, allowed: bool) -> std::io::Result<()> {
+    let (status, body) = if !allowed {
+        ("429 Too Many Requests", "Too many requests".to_string())
+    } else {
+        match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+            Ok(request) => match request? {
+                Some((method, path, body)) => route(&method, &path, &body, store),
+                None => ("413 Payload Too Large", "Request too large".to_string()),
+            },
+            Err(_) => ("408 Request Timeout", "Request timed out".to_string()),
+        }
+    };
+    let content_type = if status.starts_with("200") { "application/json" } else { "text/plain" };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Method, path and body of the request, or None if it's too large
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<(String, String, String)>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buf.len() > MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if header_end + content_length > MAX_REQUEST_BYTES {
+        return Ok(None);
+    }
+    while buf.len() < header_end + content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    // Request line: "POST /signaling/topic HTTP/1.1"
+    let mut parts = head.lines().next().unwrap_or("").split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("/").to_string();
+    let body = String::from_utf8_lossy(&buf[header_end..header_end + content_length]).to_string();
+    Ok(Some((method, path, body)))
+}
+
+fn route(method: &str, path: &str, body: &str, store: &SharedSignalingStore) -> (&'static str, String) {
+    // Ignore query strings (ntfy-style `?poll=1&since=5m` included)
+    let path = path.split('?').next().unwrap_or("/");
+    let Some(topic) = path.strip_prefix("/signaling/").filter(|t| valid_topic(t)) else {
+        return ("404 Not Found", "Not found".to_string());
+    };
+
+    let now = Instant::now();
+    match method {
+        "GET" => {
+            let messages = store.lock().messages(topic, now);
+            ("200 OK", format!("[{}]", messages.join(",")))
+        }
+        "POST" => match store.lock().publish(topic, body, now) {
+            Ok(()) => {
+                debug!("Signaling: message published on {}", topic);
+                ("200 OK", "{}".to_string())
+            }
+            Err(PublishError::InvalidMessage) => ("400 Bad Request", "Expected a JSON object".to_string()),
+            Err(PublishError::Full) => ("503 Service Unavailable", "Too many topics".to_string()),
+        },
+        _ => ("405 Method Not Allowed", "Method not allowed".to_string()),
+    }
+}
+
+/// Topic names: letters, digits and '-', like the clients' `cider-together-<room>`
+fn valid_topic(topic: &str) -> bool {
+    !topic.is_empty()
+        && topic.len() <= MAX_TOPIC_LEN
+        && topic.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_expire_and_are_capped() {
+        let mut store = SignalingStore::new();
+        let start = Instant::now();
+        assert_eq!(store.publish("room", "not json", start), Err(PublishError::InvalidMessage));
+        assert_eq!(store.publish("room", "[1, 2]", start), Err(PublishError::InvalidMessage));
+
+        for i in 0..MAX_MESSAGES_PER_TOPIC + 2 {
+            store.publish("room", &format!(r#"{{"n": {}}}"#, i), start).unwrap();
+        }
+        let messages = store.messages("room", start);
+        assert_eq!(messages.len(), MAX_MESSAGES_PER_TOPIC);
+        assert_eq!(messages[0], r#"{"n": 2}"#);
+        assert!(store.messages("other", start).is_empty());
+
+        // Gone once expired, and so is the topic
+        assert!(store.messages("room", start + MESSAGE_TTL).is_empty());
+        assert_eq!(store.topic_count(), 0);
+    }
+
+    #[test]
+    fn test_requests_rate_limited() {
+        let mut limiter = RequestLimiter::default();
+        let (client, other) = ("203.0.113.1".parse().unwrap(), "203.0.113.2".parse().unwrap());
+        let start = Instant::now();
+
+        for _ in 0..MAX_REQUESTS_PER_IP_PER_MINUTE {
+            assert!(limiter.allow(client, start));
+        }
+        assert!(!limiter.allow(client, start));
+        assert!(limiter.allow(other, start));
+        assert!(limiter.allow("127.0.0.1".parse().unwrap(), start));
+
+        // Allowed again as the window moves on, and forgotten once idle
+        assert!(limiter.allow(client, start + RATE_WINDOW));
+        limiter.allow(other, start + RATE_WINDOW * 3);
+        assert_eq!(limiter.recent.len(), 1);
+    }
+
+    #[test]
+    fn test_routes() {
+        let store: SharedSignalingStore = Arc::new(Mutex::new(SignalingStore::new()));
+        assert_eq!(route("POST", "/signaling/cider-together-abc", r#"{"a": 1}"#, &store).0, "200 OK");
+        assert_eq!(
+            route("GET", "/signaling/cider-together-abc?since=5m", "", &store),
+            ("200 OK", r#"[{"a": 1}]"#.to_string())
+        );
+        assert_eq!(route("GET", "/signaling/../etc", "", &store).0, "404 Not Found");
+        assert_eq!(route("GET", "/api/status", "", &store).0, "404 Not Found");
+        assert_eq!(route("DELETE", "/signaling/abc", "", &store).0, "405 Method Not Allowed");
+    }
+
+    #[tokio::test]
+    async fn test_cider_client_finds_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let store: SharedSignalingStore = Arc::new(Mutex::new(SignalingStore::new()));
+        tokio::spawn(serve_on(listener, Arc::clone(&store)));
+
+        let client = cider_core::network::SignalingClient::relay(url);
+        let addresses = vec!["/ip4/1.2.3.4/tcp/4001/p2p-circuit".to_string()];
+        client.publish_room("ABCD-1234", "host", addresses.clone(), false).await.unwrap();
+
+        let messages = client.poll_room("abcd1234").await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].peer_id, "host");
+        assert_eq!(messages[0].addresses, addresses);
+        assert!(client.poll_room("OTHER").await.unwrap().is_empty());
+        assert!(client.room_active("ABCD-1234", "joiner").await.unwrap());
+        assert!(!client.room_active("ABCD-1234", "host").await.unwrap());
+        assert!(!client.room_active("OTHER", "joiner").await.unwrap());
+
+        // Subscribers get what's there, then what's published later, each once
+        let mut subscription = client.subscribe_room("ABCD-1234");
+        let first = subscription.recv().await.unwrap();
+        assert_eq!(first.peer_id, "host");
+        client.publish_room("ABCD-1234", "joiner", Vec::new(), true).await.unwrap();
+        let next = tokio::time::timeout(std::time::Duration::from_secs(10), subscription.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.peer_id, "joiner");
+        assert!(next.joining);
+    }
+}
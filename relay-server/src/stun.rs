@@ -0,0 +1,215 @@
+//! Public address detection via STUN
+//!
+//! Sends RFC 5389 binding requests to public STUN servers and reads back the
+//! address they saw us on. Queried from the QUIC port, the reply also shows
+//! whether a NAT in front of the relay maps that UDP port to a different one.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::timeout;
+use tracing::debug;
+
+/// Public STUN servers (all dual-stack); queried in parallel, first answer wins
+const STUN_SERVERS: &[&str] = &["stun.l.google.com:19302", "stun.cloudflare.com:3478", "stun.nextcloud.com:443"];
+
+/// How long to wait for any server to answer
+const STUN_TIMEOUT: Duration = Duration::from_secs(3);
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const HEADER_LEN: usize = 20;
+
+/// Ask the STUN servers for our public address as seen from `local`
+///
+/// Only servers of the same IP family as `local` are used. Returns None if
+/// the socket can't be bound or no server answers in time.
+pub async fn public_address(local: SocketAddr) -> Option<SocketAddr> {
+    let socket = match UdpSocket::bind(local).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            debug!("STUN: could not bind {}: {}", local, e);
+            return None;
+        }
+    };
+
+    let transaction_id: [u8; 12] = rand::random();
+    let request = binding_request(&transaction_id);
+    let mut sent = false;
+    for server in STUN_SERVERS {
+        let Ok(addrs) = lookup_host(server).await else {
+            continue;
+        };
+        for addr in addrs.filter(|a| a.is_ipv4() == local.is_ipv4()) {
+            sent |= socket.send_to(&request, addr).await.is_ok();
+        }
+    }
+    if !sent {
+        return None;
+    }
+
+    let mut buf = [0u8; 512];
+    timeout(STUN_TIMEOUT, async {
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await.ok()?;
+            match parse_binding_response(&buf[..len], &transaction_id) {
+                Some(addr) => {
+                    debug!("STUN: {} reports our address as {}", from, addr);
+                    return Some(addr);
+                }
+                None => continue,
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Encode a binding request without attributes
+fn binding_request(transaction_id: &[u8; 12]) -> [u8; HEADER_LEN] {
+    let mut request = [0u8; HEADER_LEN];
+    request[0..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    // Message length (no attributes) stays 0
+    request[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request[8..20].copy_from_slice(transaction_id);
+    request
+}
+
+/// Extract the mapped address from a binding success response to our request
+fn parse_binding_response(message: &[u8], transaction_id: &[u8; 12]) -> Option<SocketAddr> {
+    if message.len() < HEADER_LEN
+        || u16::from_be_bytes([message[0], message[1]]) != BINDING_SUCCESS
+        || message[4..8] != MAGIC_COOKIE.to_be_bytes()
+        || &message[8..20] != transaction_id
+    {
+        return None;
+    }
+    let length = u16::from_be_bytes([message[2], message[3]]) as usize;
+    let attributes = message.get(HEADER_LEN..HEADER_LEN + length)?;
+
+    // Prefer XOR-MAPPED-ADDRESS; some old servers only send MAPPED-ADDRESS
+    let mut mapped = None;
+    let mut offset = 0;
+    while offset + 4 <= attributes.len() {
+        let kind = u16::from_be_bytes([attributes[offset], attributes[offset + 1]]);
+        let len = u16::from_be_bytes([attributes[offset + 2], attributes[offset + 3]]) as usize;
+        let value = attributes.get(offset + 4..offset + 4 + len)?;
+        match kind {
+            ATTR_XOR_MAPPED_ADDRESS => return decode_address(value, Some(transaction_id)),
+            ATTR_MAPPED_ADDRESS => mapped = decode_address(value, None),
+            _ => {}
+        }
+        // Attributes are padded to 4 bytes
+        offset += 4 + len.div_ceil(4) * 4;
+    }
+    mapped
+}
+
+/// Decode a (XOR-)MAPPED-ADDRESS value; `xor` holds the transaction ID for XOR-MAPPED-ADDRESS
+fn decode_address(value: &[u8], xor: Option<&[u8; 12]>) -> Option<SocketAddr> {
+    let family = *value.get(1)?;
+    let mut port = u16::from_be_bytes([*value.get(2)?, *value.get(3)?]);
+    let mut key = [0u8; 16];
+    key[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    if let Some(transaction_id) = xor {
+        key[4..].copy_from_slice(transaction_id);
+        port ^= (MAGIC_COOKIE >> 16) as u16;
+    }
+    let unmask = |bytes: &[u8]| -> Vec<u8> {
+        bytes
+            .iter()
+            .zip(key.iter())
+            .map(|(b, k)| if xor.is_some() { b ^ k } else { *b })
+            .collect()
+    };
+
+    match family {
+        0x01 => {
+            let octets: [u8; 4] = unmask(value.get(4..8)?).try_into().ok()?;
+            Some(SocketAddr::from((octets, port)))
+        }
+        0x02 => {
+            let octets: [u8; 16] = unmask(value.get(4..20)?).try_into().ok()?;
+            Some(SocketAddr::from((octets, port)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a binding success response carrying one address attribute
+    fn response(transaction_id: &[u8; 12], kind: u16, value: &[u8]) -> Vec<u8> {
+        let mut message = Vec::new();
+        message.extend_from_slice(&BINDING_SUCCESS.to_be_bytes());
+        message.extend_from_slice(&((4 + value.len()) as u16).to_be_bytes());
+        message.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        message.extend_from_slice(transaction_id);
+        message.extend_from_slice(&kind.to_be_bytes());
+        message.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        message.extend_from_slice(value);
+        message
+    }
+
+    #[test]
+    fn test_binding_request() {
+        let transaction_id = [7u8; 12];
+        let request = binding_request(&transaction_id);
+        assert_eq!(&request[..4], &[0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(&request[4..8], &[0x21, 0x12, 0xA4, 0x42]);
+        assert_eq!(&request[8..], &transaction_id);
+    }
+
+    #[test]
+    fn test_xor_mapped_ipv4() {
+        let transaction_id = [1u8; 12];
+        // 203.0.113.7:4001 XORed with the magic cookie
+        let port = 4001u16 ^ 0x2112;
+        let ip: Vec<u8> = [203u8, 0, 113, 7].iter().zip(MAGIC_COOKIE.to_be_bytes()).map(|(b, k)| b ^ k).collect();
+        let value = [&[0x00, 0x01][..], &port.to_be_bytes(), &ip].concat();
+
+        let message = response(&transaction_id, ATTR_XOR_MAPPED_ADDRESS, &value);
+        assert_eq!(
+            parse_binding_response(&message, &transaction_id),
+            Some("203.0.113.7:4001".parse().unwrap())
+        );
+        // Replies to other requests are ignored
+        assert_eq!(parse_binding_response(&message, &[2u8; 12]), None);
+    }
+
+    #[test]
+    fn test_xor_mapped_ipv6() {
+        let transaction_id = [9u8; 12];
+        let addr: SocketAddr = "[2001:db8::42]:4001".parse().unwrap();
+        let SocketAddr::V6(v6) = addr else { unreachable!() };
+
+        let mut key = MAGIC_COOKIE.to_be_bytes().to_vec();
+        key.extend_from_slice(&transaction_id);
+        let ip: Vec<u8> = v6.ip().octets().iter().zip(&key).map(|(b, k)| b ^ k).collect();
+        let port = 4001u16 ^ 0x2112;
+        let value = [&[0x00, 0x02][..], &port.to_be_bytes(), &ip].concat();
+
+        let message = response(&transaction_id, ATTR_XOR_MAPPED_ADDRESS, &value);
+        assert_eq!(parse_binding_response(&message, &transaction_id), Some(addr));
+    }
+
+    #[test]
+    fn test_plain_mapped_address() {
+        let transaction_id = [3u8; 12];
+        let value = [&[0x00, 0x01][..], &4001u16.to_be_bytes(), &[198, 51, 100, 1]].concat();
+        let message = response(&transaction_id, ATTR_MAPPED_ADDRESS, &value);
+        assert_eq!(
+            parse_binding_response(&message, &transaction_id),
+            Some("198.51.100.1:4001".parse().unwrap())
+        );
+
+        // Truncated messages don't panic
+        assert_eq!(parse_binding_response(&message[..message.len() - 3], &transaction_id), None);
+    }
+}
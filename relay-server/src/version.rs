@@ -0,0 +1,98 @@
+//! Client version checks
+//!
+//! Cider clients report `cider-together/<version>` in their identify agent
+//! version. Relays can refuse clients older than a configured minimum, e.g.
+//! after a protocol change that old builds handle badly. The minimum is
+//! advertised as `min-client/<version>` in the relay's own agent version so
+//! rejected clients can tell the user to update.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Agent string field carrying a client's version
+const CLIENT_PREFIX: &str = "cider-together/";
+
+/// Agent string field carrying the minimum client version
+pub const MIN_CLIENT_PREFIX: &str = "min-client/";
+
+/// `major.minor.patch` version (pre-release and build suffixes are ignored)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    /// Parse `1`, `1.2` or `1.2.3` (missing parts are 0), optionally followed by `-beta` or `+build`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let core = s.trim().split(['-', '+']).next().unwrap_or_default();
+        let mut parts = core.split('.').map(|part| part.parse::<u64>());
+        let mut next = |required: bool| match parts.next() {
+            Some(Ok(n)) => Ok(n),
+            None if !required => Ok(0),
+            _ => Err(format!("'{}' is not a version (expected e.g. 1.2.0)", s)),
+        };
+        let version = Self {
+            major: next(true)?,
+            minor: next(false)?,
+            patch: next(false)?,
+        };
+        match parts.next() {
+            Some(_) => Err(format!("'{}' is not a version (expected e.g. 1.2.0)", s)),
+            None => Ok(version),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Client version from an identify agent version (None for other/old agents)
+pub fn client_version(agent_version: &str) -> Option<Version> {
+    agent_version
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix(CLIENT_PREFIX))
+        .and_then(|version| version.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(v("1.2.3"), Version { major: 1, minor: 2, patch: 3 });
+        assert_eq!(v("0.4"), v("0.4.0"));
+        assert_eq!(v("2.0.0-beta.1"), v("2.0.0"));
+        assert_eq!(v("1.2.3").to_string(), "1.2.3");
+
+        assert!("".parse::<Version>().is_err());
+        assert!("1.x".parse::<Version>().is_err());
+        assert!("1.2.3.4".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert!(v("0.10.0") > v("0.9.9"));
+        assert!(v("1.0.0") > v("0.99.0"));
+        assert!(v("0.4.1") >= v("0.4.1"));
+    }
+
+    #[test]
+    fn test_client_version() {
+        assert_eq!(client_version("cider-together/0.4.2"), Some(v("0.4.2")));
+        assert_eq!(client_version("rust-libp2p/0.45.0"), None);
+        assert_eq!(client_version("cider-relay/0.1.0 region/eu-west"), None);
+        assert_eq!(client_version("cider-together/garbage"), None);
+    }
+}
@@ -0,0 +1,296 @@
+//! Read-only web dashboard for the relay server
+//!
+//! Serves the same data as the TUI dashboard over plain HTTP so the relay
+//! can run headless (e.g. in a container) and still be inspected from a
+//! browser. Routes:
+//!   GET /              - HTML dashboard (polls the JSON API)
+//!   GET /api/status    - server status and counters
+//!   GET /api/peers     - connected peers
+//!   GET /api/circuits  - active relay circuits
+//!   GET /api/rooms     - per-room participants and relayed traffic
+//!   GET /api/logs      - recent activity log
+
+use crate::metrics::{Metrics, ServerStatus};
+use chrono::Local;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+/// Maximum request size we bother reading (only the request line matters)
+const MAX_REQUEST_BYTES: usize = 8192;
+
+#[derive(Serialize)]
+struct StatusResponse {
+    status: &'static str,
+    uptime: String,
+    peer_id: Option<String>,
+    public_ip: Option<String>,
+    public_ipv6: Option<String>,
+    tcp_port: u16,
+    quic_port: u16,
+    tcp_reachable: Option<bool>,
+    quic_reachable: Option<bool>,
+    connected_peers: usize,
+    total_connections: u64,
+    peak_connections: usize,
+    active_reservations: usize,
+    total_reservations: u64,
+    active_circuits: usize,
+    total_circuits: u64,
+    bytes_relayed: u64,
+    rendezvous_registrations: usize,
+    federated_relays: usize,
+}
+
+#[derive(Serialize)]
+struct PeerResponse {
+    peer_id: String,
+    protocol: Option<String>,
+    connected_at: String,
+    connected_secs: i64,
+    has_reservation: bool,
+}
+
+#[derive(Serialize)]
+struct CircuitResponse {
+    src_peer_id: String,
+    dst_peer_id: String,
+    established_at: String,
+    duration_secs: i64,
+    room: Option<String>,
+    bytes: u64,
+    bytes_per_sec: u64,
+}
+
+#[derive(Serialize)]
+struct RoomResponse {
+    room: String,
+    participants: usize,
+    active_circuits: usize,
+    bytes_relayed: u64,
+}
+
+#[derive(Serialize)]
+struct LogResponse {
+    timestamp: String,
+    level: &'static str,
+    message: String,
+}
+
+/// Serve the web dashboard until the listener fails
+pub async fn serve(addr: SocketAddr, metrics: Arc<RwLock<Metrics>>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Web dashboard listening on http://{}", addr);
+
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &metrics).await {
+                debug!("Web dashboard request from {} failed: {}", remote, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, metrics: &Arc<RwLock<Metrics>>) -> std::io::Result<()> {
+    let mut buf = vec![0u8; MAX_REQUEST_BYTES];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+
+    // Request line: "GET /path HTTP/1.1"
+    let mut parts = request.lines().next().unwrap_or("").split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("/");
+    // Ignore query strings
+    let path = path.split('?').next().unwrap_or("/");
+
+    let (status, content_type, body) = if method != "GET" {
+        ("405 Method Not Allowed", "text/plain", "Method not allowed".to_string())
+    } else {
+        route(path, metrics)
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn route(path: &str, metrics: &Arc<RwLock<Metrics>>) -> (&'static str, &'static str, String) {
+    let m = metrics.read();
+    let json = match path {
+        "/" | "/index.html" => return ("200 OK", "text/html", INDEX_HTML.to_string()),
+        "/api/status" => serde_json::to_string(&status_response(&m)),
+        "/api/peers" => serde_json::to_string(&peers_response(&m)),
+        "/api/circuits" => serde_json::to_string(&circuits_response(&m)),
+        "/api/rooms" => serde_json::to_string(&rooms_response(&m)),
+        "/api/logs" => serde_json::to_string(&logs_response(&m)),
+        _ => return ("404 Not Found", "text/plain", "Not found".to_string()),
+    };
+
+    match json {
+        Ok(body) => ("200 OK", "application/json", body),
+        Err(e) => ("500 Internal Server Error", "text/plain", e.to_string()),
+    }
+}
+
+fn status_response(m: &Metrics) -> StatusResponse {
+    StatusResponse {
+        status: match m.status {
+            ServerStatus::Starting => "starting",
+            ServerStatus::Running => "running",
+            ServerStatus::Draining => "draining",
+            ServerStatus::Error => "error",
+        },
+        uptime: m.uptime(),
+        peer_id: m.peer_id.clone(),
+        public_ip: m.public_ip.clone(),
+        public_ipv6: m.public_ipv6.clone(),
+        tcp_port: m.tcp_port,
+        quic_port: m.quic_port,
+        tcp_reachable: m.tcp_reachable,
+        quic_reachable: m.quic_reachable,
+        connected_peers: m.connected_peers,
+        total_connections: m.total_connections,
+        peak_connections: m.peak_connections,
+        active_reservations: m.active_reservations,
+        total_reservations: m.total_reservations,
+        active_circuits: m.active_circuits,
+        total_circuits: m.total_circuits,
+        bytes_relayed: m.bytes_relayed,
+        rendezvous_registrations: m.rendezvous_registrations,
+        federated_relays: m.federated_relays,
+    }
+}
+
+fn peers_response(m: &Metrics) -> Vec<PeerResponse> {
+    let now = Local::now();
+    m.peer_list
+        .iter()
+        .map(|p| PeerResponse {
+            peer_id: p.peer_id.clone(),
+            protocol: p.protocol.clone(),
+            connected_at: p.connected_at.to_rfc3339(),
+            connected_secs: now.signed_duration_since(p.connected_at).num_seconds(),
+            has_reservation: p.has_reservation,
+        })
+        .collect()
+}
+
+fn circuits_response(m: &Metrics) -> Vec<CircuitResponse> {
+    let now = Local::now();
+    m.circuit_list
+        .iter()
+        .map(|c| CircuitResponse {
+            src_peer_id: c.src_peer_id.clone(),
+            dst_peer_id: c.dst_peer_id.clone(),
+            established_at: c.established_at.to_rfc3339(),
+            duration_secs: now.signed_duration_since(c.established_at).num_seconds(),
+            room: c.room.clone(),
+            bytes: c.bytes,
+            bytes_per_sec: c.bytes_per_sec,
+        })
+        .collect()
+}
+
+fn rooms_response(m: &Metrics) -> Vec<RoomResponse> {
+    m.rooms
+        .iter()
+        .map(|(room, stats)| RoomResponse {
+            room: room.clone(),
+            participants: stats.participants,
+            active_circuits: stats.active_circuits,
+            bytes_relayed: stats.bytes_relayed,
+        })
+        .collect()
+}
+
+fn logs_response(m: &Metrics) -> Vec<LogResponse> {
+    m.logs
+        .iter()
+        .rev()
+        .map(|entry| LogResponse {
+            timestamp: entry.timestamp.to_rfc3339(),
+            level: entry.level.as_str(),
+            message: entry.message.clone(),
+        })
+        .collect()
+}
+
+/// Single-page dashboard; polls the JSON API every 2 seconds
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Cider Relay Server</title>
+<style>
+  body { font-family: ui-monospace, Menlo, Consolas, monospace; background: #111; color: #ddd; margin: 2em; }
+  h1 { font-size: 1.2em; }
+  h2 { font-size: 1em; color: #aaa; margin-top: 2em; }
+  table { border-collapse: collapse; width: 100%; }
+  td, th { text-align: left; padding: 4px 12px 4px 0; border-bottom: 1px solid #222; }
+  .ok { color: #5c5; } .warn { color: #cc5; } .err { color: #c55; }
+  #stats td:first-child { color: #888; width: 14em; }
+</style>
+</head>
+<body>
+<h1>Cider Relay Server <span id="status"></span></h1>
+<table id="stats"></table>
+<h2>Peers</h2>
+<table><thead><tr><th>Peer ID</th><th>Protocol</th><th>Connected</th><th>Reservation</th></tr></thead><tbody id="peers"></tbody></table>
+<h2>Circuits</h2>
+<table><thead><tr><th>Source</th><th>Destination</th><th>Room</th><th>Duration</th><th>Relayed</th><th>Throughput</th></tr></thead><tbody id="circuits"></tbody></table>
+<h2>Rooms</h2>
+<table><thead><tr><th>Room</th><th>Participants</th><th>Circuits</th><th>Relayed</th></tr></thead><tbody id="rooms"></tbody></table>
+<h2>Activity Log</h2>
+<table><tbody id="logs"></tbody></table>
+<script>
+const esc = s => String(s ?? "").replace(/[&<>"]/g, c => ({"&":"&amp;","<":"&lt;",">":"&gt;",'"':"&quot;"}[c]));
+const dur = s => s < 60 ? s + "s" : s < 3600 ? Math.floor(s / 60) + "m " + (s % 60) + "s" : Math.floor(s / 3600) + "h " + Math.floor((s % 3600) / 60) + "m";
+const bytes = b => b < 1024 ? b + " B" : b < 1048576 ? (b / 1024).toFixed(1) + " KB" : b < 1073741824 ? (b / 1048576).toFixed(1) + " MB" : (b / 1073741824).toFixed(2) + " GB";
+async function refresh() {
+  try {
+    const [s, peers, circuits, rooms, logs] = await Promise.all(
+      ["status", "peers", "circuits", "rooms", "logs"].map(p => fetch("/api/" + p).then(r => r.json())));
+    const cls = s.status === "running" ? "ok" : s.status === "error" ? "err" : "warn";
+    document.getElementById("status").innerHTML = `<span class="${cls}">${s.status.toUpperCase()}</span>`;
+    const reach = s.tcp_reachable === null ? "?" : s.tcp_reachable ? "✓" : "✗";
+    const quicReach = s.quic_reachable === null ? "" : s.quic_reachable ? "✓" : "✗";
+    document.getElementById("stats").innerHTML = [
+      ["Uptime", s.uptime], ["Peer ID", s.peer_id], ["Public IP", (s.public_ip ?? "detecting...") + " " + reach],
+      ...(s.public_ipv6 ? [["Public IPv6", s.public_ipv6]] : []),
+      ["Ports", `TCP:${s.tcp_port} QUIC:${s.quic_port} ${quicReach}`],
+      ["Connections", `${s.connected_peers} active / ${s.total_connections} total / ${s.peak_connections} peak`],
+      ["Reservations", `${s.active_reservations} / ${s.total_reservations}`],
+      ["Circuits", `${s.active_circuits} / ${s.total_circuits}`], ["Relayed", bytes(s.bytes_relayed)],
+      ["Rendezvous", `${s.rendezvous_registrations} registrations`],
+      ["Federation", `${s.federated_relays} relays`],
+    ].map(([k, v]) => `<tr><td>${k}</td><td>${esc(v)}</td></tr>`).join("");
+    document.getElementById("peers").innerHTML = peers.map(p =>
+      `<tr><td>${esc(p.peer_id)}</td><td>${esc(p.protocol ?? "-")}</td><td>${dur(p.connected_secs)}</td><td>${p.has_reservation ? "yes" : ""}</td></tr>`).join("");
+    document.getElementById("circuits").innerHTML = circuits.map(c =>
+      `<tr><td>${esc(c.src_peer_id)}</td><td>${esc(c.dst_peer_id)}</td><td>${esc(c.room ?? "-")}</td><td>${dur(c.duration_secs)}</td><td>${bytes(c.bytes)}</td><td>${bytes(c.bytes_per_sec)}/s</td></tr>`).join("");
+    document.getElementById("rooms").innerHTML = rooms.map(r =>
+      `<tr><td>${esc(r.room)}</td><td>${r.participants}</td><td>${r.active_circuits}</td><td>${bytes(r.bytes_relayed)}</td></tr>`).join("");
+    document.getElementById("logs").innerHTML = logs.map(l =>
+      `<tr><td>${new Date(l.timestamp).toLocaleTimeString()}</td><td>[${l.level}]</td><td>${esc(l.message)}</td></tr>`).join("");
+  } catch (e) {
+    document.getElementById("status").innerHTML = `<span class="err">UNREACHABLE</span>`;
+  }
+}
+refresh();
+setInterval(refresh, 2000);
+</script>
+</body>
+</html>
+"#;
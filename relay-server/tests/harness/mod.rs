@@ -0,0 +1,177 @@
+//! In-process relay and clients for integration tests
+//!
+//! Runs the relay's network loop on a random port and real cider-core
+//! `NetworkManager`s against it. Tests drive the clients and assert on the
+//! relay's `Metrics`, the same state the dashboards show.
+//!
+//! The relay and its clients talk over loopback, so the tests run anywhere.
+
+#![allow(dead_code)]
+
+use cider_core::network::{NetworkConfig, NetworkEvent, NetworkHandle, NetworkManager};
+use cider_relay::config::Config;
+use cider_relay::metrics::Metrics;
+use cider_relay::network::{self, NetworkCommand};
+use parking_lot::RwLock;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, TcpListener, UdpSocket};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How long a test waits for the relay to reach an expected state
+pub const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A relay running in the test process
+pub struct TestRelay {
+    /// Relay address including `/p2p/<peer id>`
+    pub addr: String,
+    pub peer_id: String,
+    pub metrics: Arc<RwLock<Metrics>>,
+    commands: mpsc::UnboundedSender<NetworkCommand>,
+    task: JoinHandle<()>,
+    dir: PathBuf,
+}
+
+impl TestRelay {
+    /// Start a relay with the default test config
+    pub async fn start() -> io::Result<Self> {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Start a relay, adjusting the test config first
+    pub async fn start_with(configure: impl FnOnce(&mut Config)) -> io::Result<Self> {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let port = free_port(ip)?;
+        let dir = std::env::temp_dir().join(format!("cider-relay-test-{}-{}", std::process::id(), port));
+        std::fs::create_dir_all(&dir)?;
+
+        let mut config = Config::default();
+        config.network.tcp_port = port;
+        config.network.quic_port = port;
+        config.network.listen_ipv6 = false;
+        // Skips public address detection (no network access needed)
+        config.network.external_address = Some(ip.to_string());
+        config.network.keypair_path = Some(dir.join("keypair.bin"));
+        config.access.ban_file = Some(dir.join("bans.txt"));
+        config.shutdown.metrics_file = Some(dir.join("metrics.json"));
+        config.shutdown.drain_timeout_secs = 1;
+        config.dashboard.tui = false;
+        configure(&mut config);
+        config.validate().expect("invalid test relay config");
+
+        let metrics = Arc::new(RwLock::new(Metrics::new()));
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let (commands, command_rx) = mpsc::unbounded_channel();
+        let metrics_for_network = Arc::clone(&metrics);
+        let task = tokio::spawn(async move {
+            if let Err(e) = network::run_with_dashboard(config, metrics_for_network, event_tx, command_rx).await {
+                panic!("relay failed: {}", e);
+            }
+        });
+
+        let mut relay = Self {
+            addr: String::new(),
+            peer_id: String::new(),
+            metrics,
+            commands,
+            task,
+            dir,
+        };
+        relay.wait_for("relay to start", |m| m.peer_id.is_some()).await;
+        relay.peer_id = relay.metrics.read().peer_id.clone().unwrap();
+        relay.addr = format!("/ip4/{}/tcp/{}/p2p/{}", ip, port, relay.peer_id);
+        Ok(relay)
+    }
+
+    /// Wait until the relay's metrics satisfy `condition`, panicking after `WAIT_TIMEOUT`
+    pub async fn wait_for(&self, what: &str, condition: impl Fn(&Metrics) -> bool) {
+        let deadline = tokio::time::Instant::now() + WAIT_TIMEOUT;
+        while !condition(&self.metrics.read()) {
+            if tokio::time::Instant::now() >= deadline {
+                let logs: Vec<String> = self.metrics.read().logs.iter().map(|l| l.message.clone()).collect();
+                panic!("timed out waiting for {}; relay log:\n{}", what, logs.join("\n"));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Whether the relay logged a message containing `text`
+    pub fn logged(&self, text: &str) -> bool {
+        self.metrics.read().logs.iter().any(|l| l.message.contains(text))
+    }
+
+    /// Shut the relay down and remove its files
+    pub async fn stop(self) {
+        let _ = self.commands.send(NetworkCommand::Shutdown);
+        let _ = tokio::time::timeout(Duration::from_secs(5), self.task).await;
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A cider-core client that only knows about the test relay
+pub struct TestClient {
+    pub handle: NetworkHandle,
+    pub events: mpsc::UnboundedReceiver<NetworkEvent>,
+}
+
+impl TestClient {
+    pub fn start(relay: &TestRelay) -> Self {
+        let config = NetworkConfig {
+            bootstrap_nodes: vec![relay.addr.clone()],
+            relay_nodes: vec![relay.addr.clone()],
+            enable_mdns: false,
+            enable_dht: false,
+            ..NetworkConfig::default()
+        };
+        let (handle, events) = NetworkManager::with_config(config).unwrap().start().unwrap();
+        Self { handle, events }
+    }
+
+    pub fn peer_id(&self) -> &str {
+        &self.handle.local_peer_id
+    }
+
+    /// Wait for an event matching `condition`, panicking after `WAIT_TIMEOUT`
+    pub async fn wait_for_event(&mut self, what: &str, condition: impl Fn(&NetworkEvent) -> bool) {
+        let found = tokio::time::timeout(WAIT_TIMEOUT, async {
+            while let Some(event) = self.events.recv().await {
+                if condition(&event) {
+                    return true;
+                }
+            }
+            false
+        })
+        .await;
+        assert_eq!(found, Ok(true), "timed out waiting for {}", what);
+    }
+
+    /// Wait until the client's network log has a message containing `text`
+    pub async fn wait_for_log(&self, what: &str, text: &str) {
+        let deadline = tokio::time::Instant::now() + WAIT_TIMEOUT;
+        while !self.handle.recent_events().iter().any(|e| e.message.contains(text)) {
+            if tokio::time::Instant::now() >= deadline {
+                panic!("timed out waiting for {}", what);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+impl Drop for TestClient {
+    fn drop(&mut self) {
+        self.handle.shutdown();
+    }
+}
+
+/// A port that's free for both TCP and UDP (the relay uses the same one for both)
+fn free_port(ip: IpAddr) -> io::Result<u16> {
+    loop {
+        let port = TcpListener::bind((ip, 0))?.local_addr()?.port();
+        if UdpSocket::bind((ip, port)).is_ok() {
+            return Ok(port);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Relay behavior with real clients: reservations, circuits and peer filtering
+
+mod harness;
+
+use cider_core::network::NetworkEvent;
+use futures::StreamExt;
+use harness::{TestClient, TestRelay, WAIT_TIMEOUT};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{identify, noise, tcp, yamux, Multiaddr};
+use std::io;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_client_gets_reservation() -> io::Result<()> {
+    let relay = TestRelay::start().await?;
+    let client = TestClient::start(&relay);
+    let peer_id = client.peer_id().to_string();
+
+    relay
+        .wait_for("the client to reserve", |m| m.active_reservations == 1)
+        .await;
+
+    {
+        let m = relay.metrics.read();
+        let peer = m.peer_list.iter().find(|p| p.peer_id == peer_id).expect("client not listed");
+        assert!(peer.has_reservation);
+        // Verified as a Cider client via identify
+        assert!(peer.protocol.as_deref().is_some_and(|p| p.contains("cider")));
+    }
+
+    relay.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_circuit_between_clients() -> io::Result<()> {
+    let relay = TestRelay::start().await?;
+    let host = TestClient::start(&relay);
+    relay
+        .wait_for("the host to reserve", |m| m.active_reservations == 1)
+        .await;
+
+    // The guest only knows the host's relayed address, as when hole punching isn't possible
+    let guest = TestClient::start(&relay);
+    let circuit_addr = format!("{}/p2p-circuit/p2p/{}", relay.addr, host.peer_id());
+    guest.handle.dial_peer(&circuit_addr).unwrap();
+
+    relay.wait_for("a circuit", |m| m.total_circuits >= 1).await;
+    relay
+        .wait_for("traffic through the circuit", |m| m.bytes_relayed > 0)
+        .await;
+
+    relay.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_guest_finds_host_through_lookup() -> io::Result<()> {
+    let relay = TestRelay::start().await?;
+    let host = TestClient::start(&relay);
+    relay
+        .wait_for("the host to reserve", |m| m.active_reservations == 1)
+        .await;
+    host.handle.create_room("ABCD4679").unwrap();
+    relay
+        .wait_for("the host to register in the room", |m| m.rendezvous_registrations == 1)
+        .await;
+
+    // No mDNS, DHT or signaling: the guest only learns where the host is from the relay
+    let mut guest = TestClient::start(&relay);
+    // Once the guest has its reservation, it asks the relay on joining
+    guest
+        .wait_for_log("the guest to reserve", "Reservation accepted by")
+        .await;
+    guest.handle.join_room("ABCD4679").unwrap();
+    let host_id = host.peer_id().to_string();
+    guest
+        .wait_for_event("the host in the room", |event| {
+            matches!(event, NetworkEvent::PeerSubscribed { peer_id } if *peer_id == host_id)
+        })
+        .await;
+    // Clients on a LAN may also meet through the relay's DHT: check the lookup answered too
+    guest
+        .wait_for_log("the relay to know the host", "knows 1 peers in the room")
+        .await;
+    relay
+        .wait_for("the guest to register in the room", |m| m.rendezvous_registrations == 2)
+        .await;
+
+    relay.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_non_cider_peer_rejected() -> io::Result<()> {
+    let relay = TestRelay::start().await?;
+
+    // A plain libp2p node that identifies as something else
+    let mut swarm = libp2p::SwarmBuilder::with_new_identity()
+        .with_tokio()
+        .with_tcp(tcp::Config::default(), noise::Config::new, yamux::Config::default)
+        .unwrap()
+        .with_behaviour(|key| identify::Behaviour::new(identify::Config::new("/ipfs/0.1.0".into(), key.public())))
+        .unwrap()
+        .build();
+    let relay_addr: Multiaddr = relay.addr.parse().unwrap();
+    swarm.dial(relay_addr).unwrap();
+
+    let closed = tokio::time::timeout(WAIT_TIMEOUT, async {
+        loop {
+            if let SwarmEvent::ConnectionClosed { .. } = swarm.select_next_some().await {
+                break;
+            }
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "relay kept the non-Cider peer connected");
+    assert!(relay.logged("non-Cider"));
+    assert_eq!(relay.metrics.read().active_reservations, 0);
+
+    relay.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_outdated_client_rejected() -> io::Result<()> {
+    let relay = TestRelay::start_with(|config| {
+        config.access.min_client_version = Some("999.0.0".to_string());
+    })
+    .await?;
+    let _client = TestClient::start(&relay);
+
+    relay
+        .wait_for("the outdated client to be rejected", |m| {
+            m.logs.iter().any(|l| l.message.contains("< 999.0.0"))
+        })
+        .await;
+
+    relay.stop().await;
+    Ok(())
+}